@@ -3,17 +3,15 @@ extern crate unrust;
 #[macro_use]
 extern crate unrust_derive;
 
-use unrust::world::{Actor, Handle, World, WorldBuilder};
-use unrust::engine::{Camera, ClearOption, DirectionalLight, GameObject, Material, Mesh,
-                     RenderTexture, TextureAttachment};
+use unrust::world::{Actor, World, WorldBuilder};
+use unrust::engine::{Bloom, Camera, DirectionalLight, GameObject, Material, MaterialEffect,
+                     Mesh, PostProcess};
 use unrust::world::events::*;
 use unrust::math::*;
 
 // GUI
 use unrust::imgui;
 
-use std::rc::Rc;
-
 #[derive(Actor)]
 pub struct MainScene {
     eye: Vector3<f32>,
@@ -31,10 +29,15 @@ impl MainScene {
 
 impl Actor for MainScene {
     fn start(&mut self, _go: &mut GameObject, world: &mut World) {
-        // add main camera to scene
+        // add main camera to scene, with bloom and a crt effect on top of it
         {
+            let mut post_process = PostProcess::new();
+            post_process.add(Bloom::new(world.asset_system()));
+            post_process.add(MaterialEffect::new(world.asset_system().new_program("crt")));
+
             let go = world.new_game_object();
             go.borrow_mut().add_component(Camera::default());
+            go.borrow_mut().add_component(post_process);
         }
 
         // add direction light to scene.
@@ -42,9 +45,9 @@ impl Actor for MainScene {
         go.borrow_mut()
             .add_component(DirectionalLight::default());
 
-        // Added Crt
+        // Added a cube in the scene
         let go = world.new_game_object();
-        go.borrow_mut().add_component(Crt::new());
+        go.borrow_mut().add_component(Cube::new());
     }
 
     fn update(&mut self, _go: &mut GameObject, world: &mut World) {
@@ -111,73 +114,6 @@ impl Actor for MainScene {
     }
 }
 
-#[derive(Actor)]
-pub struct Crt {
-    rt: Rc<RenderTexture>,
-    cube: Handle<GameObject>,
-}
-
-impl Crt {
-    fn new() -> Crt {
-        Crt {
-            rt: Rc::new(RenderTexture::new(1024, 1024, TextureAttachment::Color0)),
-            cube: GameObject::empty(),
-        }
-    }
-}
-
-impl Actor for Crt {
-    fn start(&mut self, go: &mut GameObject, world: &mut World) {
-        // add main camera to scene
-        {
-            let go = world.new_game_object();
-            go.borrow_mut().add_component(Camera::default());
-        }
-
-        {
-            let db = &mut world.asset_system();
-
-            let material = Material::new(db.new_program("crt"));
-            material.set("uDiffuse", self.rt.as_texture());
-
-            let mut mesh = Mesh::new();
-            mesh.add_surface(db.new_mesh_buffer("screen_quad"), material);
-            go.add_component(mesh);
-        }
-
-        // Added a cube in the scene
-        self.cube = world.new_game_object();
-        self.cube.borrow_mut().add_component(Cube::new());
-    }
-
-    fn update(&mut self, go: &mut GameObject, world: &mut World) {
-        // Setup fb for camera
-        let cam_borrow = world.current_camera().unwrap();
-        let mut cam = cam_borrow.borrow_mut();
-
-        cam.render_texture = Some(self.rt.clone());
-
-        // Setup proper viewport to render to the whole texture
-        cam.rect = Some(((0, 0), (1024, 1024)));
-
-        // show only cube
-        // TODO it is a little bit hacky, we should support a PostProcessing Component
-        self.cube.borrow_mut().active = true;
-        go.active = false;
-
-        // Render current scene by camera using given frame buffer
-        world.engine_mut().render_pass(&cam, ClearOption::default());
-
-        // show only this crt
-        self.cube.borrow_mut().active = false;
-        go.active = true;
-
-        // Clean up stuffs in camera, as later we could render normally
-        cam.render_texture = None;
-        cam.rect = None;
-    }
-}
-
 #[derive(Actor)]
 pub struct Cube {}
 
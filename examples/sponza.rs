@@ -261,8 +261,7 @@ fn build_material(asys: &AssetSystem, obj_mat: ObjMaterial) -> Rc<Material> {
     );
 
     let diffuse_tex = asys.new_texture(&obj_mat.diffuse_map.unwrap_or("default_white".to_owned()));
-    diffuse_tex.wrap_u.set(TextureWrap::Repeat);
-    diffuse_tex.wrap_v.set(TextureWrap::Repeat);
+    diffuse_tex.set_wrap(TextureWrap::Repeat, TextureWrap::Repeat);
     material.set(
         "uMaterial.diffuse",
         obj_mat.diffuse.unwrap_or(Vector3::new(1.0, 1.0, 1.0)),
@@ -271,8 +270,7 @@ fn build_material(asys: &AssetSystem, obj_mat: ObjMaterial) -> Rc<Material> {
 
     let specular_tex =
         asys.new_texture(&obj_mat.specular_map.unwrap_or("default_black".to_owned()));
-    specular_tex.wrap_u.set(TextureWrap::Repeat);
-    specular_tex.wrap_v.set(TextureWrap::Repeat);
+    specular_tex.set_wrap(TextureWrap::Repeat, TextureWrap::Repeat);
     material.set(
         "uMaterial.specular",
         obj_mat.specular.unwrap_or(Vector3::new(1.0, 1.0, 1.0)),
@@ -284,8 +282,7 @@ fn build_material(asys: &AssetSystem, obj_mat: ObjMaterial) -> Rc<Material> {
 
     obj_mat.normal_map.as_ref().map(|nm| {
         let n_tex = asys.new_texture(nm);
-        n_tex.wrap_u.set(TextureWrap::Repeat);
-        n_tex.wrap_v.set(TextureWrap::Repeat);
+        n_tex.set_wrap(TextureWrap::Repeat, TextureWrap::Repeat);
 
         material.set("uMaterial.normal_map", n_tex);
     });
@@ -1,7 +1,7 @@
 use world::{Actor, Handle, World};
 use engine::{Asset, Camera, ClearOption, Component, CullMode, GameObject, Light,
              Material, MaterialParamMap, Mesh, MeshBuffer, MeshData, RenderQueue, RenderTexture,
-             TextureAttachment};
+             ShadowSettings, TextureAttachment};
 use engine::mesh_util::*;
 
 use world::Processor;
@@ -23,6 +23,7 @@ struct ShadowMap {
 #[derive(Component)]
 pub struct ShadowPass {
     rt: Rc<RenderTexture>,
+    texture_size: u32,
     shadow_maps: [ShadowMap; 4],
     material_params: MaterialParamMap,
 
@@ -415,14 +416,48 @@ impl ShadowMap {
     }
 }
 
+fn update_settings_params(settings: &ShadowSettings, params: &mut MaterialParamMap) {
+    params.insert("uShadowSettings.bias".into(), settings.bias.into());
+    params.insert("uShadowSettings.slope_bias".into(), settings.slope_bias.into());
+    params.insert("uShadowSettings.normal_bias".into(), settings.normal_bias.into());
+    params.insert(
+        "uShadowSettings.pcf_radius".into(),
+        (settings.pcf_radius as i32).into(),
+    );
+}
+
 impl ShadowPass {
     pub fn disable_cascaded(&mut self) {
         self.shadow_maps[0].partition_z = 1000.0;
-        self.shadow_maps[0].viewport = ((0, 0), (2048, 2048));
+        self.shadow_maps[0].viewport = ((0, 0), (self.texture_size, self.texture_size));
 
         self.use_scene_aabb = true;
     }
 
+    /// Recreate the shadow texture, each cascade gets a quarter of it
+    fn resize(&mut self, texture_size: u32) {
+        let rt = Rc::new(RenderTexture::new(
+            texture_size,
+            texture_size,
+            TextureAttachment::Depth,
+        ));
+
+        let half = texture_size / 2;
+        let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+
+        for (map, &(x, y)) in self.shadow_maps.iter_mut().zip(offsets.iter()) {
+            map.rt = rt.clone();
+            map.viewport = ((x as i32, y as i32), (half, half));
+        }
+
+        self.rt = rt;
+        self.texture_size = texture_size;
+
+        if self.use_scene_aabb {
+            self.disable_cascaded();
+        }
+    }
+
     pub fn set_partitions(&mut self, partitions: &[f32; 4]) {
         self.shadow_maps[0].partition_z = partitions[0];
         self.shadow_maps[1].partition_z = partitions[1];
@@ -481,6 +516,23 @@ impl Actor for ShadowPass {
             }
         };
 
+        let settings = {
+            let light = main_light.try_as::<Light>().unwrap().borrow();
+            light.directional().map(|l| l.shadow)
+        };
+
+        if let Some(settings) = settings {
+            let size = settings.resolution.max(2);
+            if size != self.texture_size {
+                self.resize(size);
+            }
+
+            update_settings_params(&settings, &mut self.material_params);
+        }
+
+        // The cascades replace the single map of the engine for this frame
+        world.engine_mut().external_directional_shadow = true;
+
         let ctx = LightMatrixContext::new(&self.light_camera, &main_light, world);
 
         if let Some(ctx) = ctx {
@@ -561,6 +613,7 @@ impl Processor for ShadowPass {
 
         ShadowPass {
             rt: rt.clone(),
+            texture_size: texture_size,
             material_params: MaterialParamMap::default(),
             use_scene_aabb: false,
             shadow_maps: [
@@ -1,22 +1,33 @@
+use std::any::Any;
 use std::rc::Rc;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::SystemTime;
 
 use engine::asset::{CubeMesh, PlaneMesh, QuadMesh, SkyboxMesh};
 use engine::asset::default_font_bitmap::DEFAULT_FONT_DATA;
 use engine::asset::fs;
+use engine::asset::import_settings::{ImportSettings, ImportSettingsFuture};
 use engine::asset::loader;
-use engine::asset::Resource;
-
-use engine::{Material, MeshBuffer, ShaderFs, ShaderProgram, ShaderVs, Texture, TextureFiltering,
-             TextureImage};
+use engine::asset::loader::Loadable;
+use engine::asset::progress::FileTracker;
+use engine::asset::vfs::{MountSource, Vfs, VfsSource};
+use engine::asset::worker;
+use engine::asset::{AssetBundle, AssetState, CustomAsset, LoadingProgress, Resource};
+
+use engine::{AudioClip, Capabilities, Material, MeshBuffer, MeshData, SdfFont, ShaderFs,
+             ShaderProgram, ShaderVs, Texture, TextureAtlas, TextureFiltering, TextureImage,
+             TtfFont};
 use std::fmt::Debug;
 use std::ops::Deref;
+use futures::future;
 use futures::{Async, Future};
 use std::boxed::FnBox;
 
 use image;
 use image::ImageBuffer;
+use uni_app;
 
 #[derive(Debug)]
 pub enum AssetError {
@@ -33,8 +44,12 @@ pub enum AssetError {
 pub type AssetResult<T> = Result<T, AssetError>;
 
 type PrefabHandler = Box<FnBox(AssetResult<loader::Prefab>)>;
+type GltfHandler = Box<FnBox(AssetResult<loader::GltfScene>)>;
+type ColladaHandler = Box<FnBox(AssetResult<loader::ColladaScene>)>;
 type MaterialHandler = Box<Fn(&AssetSystem, loader::ObjMaterial) -> Rc<Material>>;
 type AssetTask = Box<Future<Item = (), Error = AssetError>>;
+/// Parses the bytes of the named file into a user value, see `CustomAsset`
+pub type CustomLoader = Rc<Fn(&str, Vec<u8>) -> AssetResult<Box<Any>>>;
 
 pub trait AssetSystem {
     fn new() -> Self
@@ -43,20 +58,167 @@ pub trait AssetSystem {
 
     fn new_file(&self, name: &str) -> fs::FileFuture;
 
+    /// Read the files whose path starts with `point` from `source`, before
+    /// the mounts of lower priority and the file system. With equal
+    /// priorities the last mounted is searched first.
+    fn mount(&self, point: &str, source: MountSource, priority: i32);
+
+    /// Remove the mounts at `point`
+    fn unmount(&self, point: &str);
+
+    /// Read the files packed in the bundle `name` from it once it is loaded,
+    /// instead of one request per file. The files opened meanwhile wait for it,
+    /// the ones it does not have are read from the file system.
+    fn mount_bundle(&self, name: &str) {
+        self.mount("", MountSource::Bundle(name.to_string()), 0);
+    }
+
     fn new_program(&self, name: &str) -> Rc<ShaderProgram>;
 
+    /// Loaded with the import settings of `name`, see `import_settings`
     fn new_texture(&self, name: &str) -> Rc<Texture>;
 
+    /// Loaded with the import settings of `name`, see `import_settings`
     fn new_mesh_buffer(&self, name: &str) -> Rc<MeshBuffer>;
 
+    /// The settings of the sidecar file `<name>.meta`, the defaults when
+    /// there is none or it is invalid. It is not counted by the loading
+    /// progress.
+    fn import_settings(&self, name: &str) -> ImportSettingsFuture;
+
+    /// A texture of `img` instead of a file, returned by `new_texture(name)`
+    /// afterward. It replaces the texture of that name.
+    fn new_texture_from_image(&self, name: &str, img: TextureImage) -> Rc<Texture>;
+
+    /// A texture of the Rgba8 `pixels`, row by row from the top,
+    /// see `new_texture_from_image`
+    fn new_texture_from_rgba(
+        &self,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    ) -> Rc<Texture> {
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+
+        let img = ImageBuffer::from_raw(width, height, pixels).unwrap();
+        self.new_texture_from_image(name, TextureImage::Rgba(img))
+    }
+
+    /// A mesh of `data` instead of a file, returned by `new_mesh_buffer(name)`
+    /// afterward. It replaces the mesh of that name.
+    fn new_mesh_buffer_from_data(&self, name: &str, data: MeshData) -> Rc<MeshBuffer>;
+
+    /// A mesh of the (x, y, z) `vertices` with their (u, v) `uvs` and (x, y, z)
+    /// `normals`, and the triangles of `indices`, see `new_mesh_buffer_from_data`
+    fn new_mesh_buffer_from_slices(
+        &self,
+        name: &str,
+        vertices: &[f32],
+        uvs: Option<&[f32]>,
+        normals: Option<&[f32]>,
+        indices: &[u16],
+    ) -> Rc<MeshBuffer> {
+        let count = vertices.len() / 3;
+        assert_eq!(vertices.len(), count * 3);
+        assert!(uvs.map_or(true, |uvs| uvs.len() == count * 2));
+        assert!(normals.map_or(true, |normals| normals.len() == count * 3));
+        assert!(indices.iter().all(|&i| (i as usize) < count));
+
+        let data = MeshData {
+            vertices: vertices.to_vec(),
+            uvs: uvs.map(|uvs| uvs.to_vec()),
+            normals: normals.map(|normals| normals.to_vec()),
+            tangents: None,
+            bitangents: None,
+            indices: indices.to_vec(),
+        };
+
+        self.new_mesh_buffer_from_data(name, data)
+    }
+
+    /// A TexturePacker or Aseprite JSON file, see `TextureAtlas`
+    fn new_atlas(&self, name: &str) -> Rc<TextureAtlas>;
+
+    /// A TTF file, its distance field atlas is generated once loaded
+    fn new_font(&self, name: &str) -> Rc<SdfFont>;
+
+    /// A TTF or OTF file, its glyphs are rasterized at the requested sizes
+    fn new_ttf_font(&self, name: &str) -> Rc<TtfFont>;
+
+    /// A WAV or OGG Vorbis file, decoded once loaded
+    fn new_audio_clip(&self, name: &str) -> Rc<AudioClip>;
+
+    /// Load the files ending with `.extension`, e.g. "csv", through `loader`
+    /// with `new_custom_asset`. It replaces the loader of that extension.
+    fn register_loader(&self, extension: &str, loader: CustomLoader);
+
+    /// The loader registered for the extension of the file `name`
+    fn custom_loader(&self, name: &str) -> Option<CustomLoader>;
+
+    /// A file loaded by the loader registered for its extension
+    fn new_custom_asset(&self, name: &str) -> Rc<CustomAsset>;
+
     fn new_prefab(&self, name: &str, mh: MaterialHandler, f: PrefabHandler);
 
+    /// An .obj file and its .mtl files, one surface per material group with the
+    /// materials of `ObjMaterial::build_material`
+    fn new_obj(&self, name: &str, f: PrefabHandler) {
+        self.new_prefab(name, Box::new(loader::ObjMaterial::build_material), f);
+    }
+
+    /// A .gltf or .glb file, see `GltfScene::instantiate` to add it to the scene
+    fn new_gltf(&self, name: &str, f: GltfHandler);
+
+    /// A COLLADA .dae file, see `ColladaScene::instantiate` to add it to the scene
+    fn new_collada(&self, name: &str, f: ColladaHandler);
+
     fn reset(&mut self);
 
     fn step(&mut self);
 
     fn loading_files(&self) -> Vec<String>;
 
+    /// The state of the file `name`, None when it was never opened
+    fn asset_state(&self, name: &str) -> Option<AssetState>;
+
+    /// The files opened since the start or the last `reset`, e.g. for a loading screen
+    fn loading_progress(&self) -> LoadingProgress;
+
+    /// The files which finished loading or failed during the last `step`
+    fn finished_files(&self) -> Vec<(String, AssetState)>;
+
+    /// The features of the context of the engine, for the loaders choosing
+    /// the materials it can render. Set by the engine when it is created and
+    /// when its context is restored, the default ones before.
+    fn capabilities(&self) -> Capabilities;
+
+    fn set_capabilities(&self, caps: Capabilities);
+
+    /// The file reads and image or mesh decodes running at once on the worker
+    /// threads, 4 by default, the others wait for a free worker. Without
+    /// threads on wasm they run on the main thread.
+    fn set_max_decodes(&self, n: usize) {
+        worker::set_max_in_flight(n);
+    }
+
+    fn max_decodes(&self) -> usize {
+        worker::max_in_flight()
+    }
+
+    /// Remove the asset `name` from the asset system and free its GPU
+    /// textures or buffers. The handles still held keep working and upload it
+    /// again when used, `new_*` loads it again. False when it is not loaded.
+    fn unload(&self, name: &str) -> bool;
+
+    /// Unload the assets no handle is held to outside the asset system, e.g.
+    /// between levels, except the built-in ones. Returns how many were unloaded.
+    fn unload_unused(&self) -> usize;
+
+    /// The handles to the asset `name` held outside the asset system, None
+    /// when it is not loaded
+    fn asset_ref_count(&self, name: &str) -> Option<usize>;
+
     fn execute(&self, AssetTask);
 }
 
@@ -91,6 +253,16 @@ pub trait LoadableAsset: Asset {
 }
 
 type PrefabFuture = Box<Future<Item = loader::Prefab, Error = AssetError>>;
+type GltfFuture = Box<Future<Item = loader::GltfScene, Error = AssetError>>;
+type ColladaFuture = Box<Future<Item = loader::ColladaScene, Error = AssetError>>;
+
+/// A bundle being loaded, mounted at `point` once it is
+struct PendingBundle {
+    name: String,
+    point: String,
+    priority: i32,
+    file: fs::FileFuture,
+}
 
 pub struct AssetDatabaseContext<FS> {
     fs: FS,
@@ -98,9 +270,31 @@ pub struct AssetDatabaseContext<FS> {
     textures: RefCell<HashMap<String, Rc<Texture>>>,
     mesh_buffers: RefCell<HashMap<String, Rc<MeshBuffer>>>,
     programs: RefCell<HashMap<String, Rc<ShaderProgram>>>,
+    atlases: RefCell<HashMap<String, Rc<TextureAtlas>>>,
+    fonts: RefCell<HashMap<String, Rc<SdfFont>>>,
+    ttf_fonts: RefCell<HashMap<String, Rc<TtfFont>>>,
+    audio_clips: RefCell<HashMap<String, Rc<AudioClip>>>,
+    custom_assets: RefCell<HashMap<String, Rc<CustomAsset>>>,
+    /// By lowercase extension
+    custom_loaders: RefCell<HashMap<String, CustomLoader>>,
+    program_files: RefCell<HashMap<(String, String), SystemTime>>,
+    /// The modification times of the texture and mesh files
+    asset_files: RefCell<HashMap<String, SystemTime>>,
+    /// Steps until the files are checked for changes again
+    modified_check: Cell<u32>,
+    capabilities: Cell<Capabilities>,
+    file_tracker: FileTracker,
+    vfs: Vfs,
+    pending_bundles: RefCell<Vec<PendingBundle>>,
+    /// The assets added by `setup`, never unloaded
+    builtin_assets: RefCell<HashSet<String>>,
 
     pending_prefabs: RefCell<Vec<(PrefabHandler, PrefabFuture)>>,
+    pending_gltfs: RefCell<Vec<(GltfHandler, GltfFuture)>>,
+    pending_colladas: RefCell<Vec<(ColladaHandler, ColladaFuture)>>,
     pending_tasks: RefCell<Vec<AssetTask>>,
+    /// The textures and meshes loading again, by file name
+    pending_reloads: RefCell<Vec<(String, AssetTask)>>,
 }
 
 pub struct AssetDatabase<FS, F>
@@ -141,7 +335,33 @@ where
     F: fs::File + 'static,
 {
     fn new_file(&self, name: &str) -> fs::FileFuture {
-        self.fs.open(&self.get_filename(name))
+        let file = self.open_after_bundles(name);
+        self.file_tracker.track(name, file)
+    }
+
+    fn mount(&self, point: &str, source: MountSource, priority: i32) {
+        let name = match source {
+            MountSource::Bundle(name) => name,
+            source => {
+                if let Err(e) = self.vfs.mount(point, source, priority) {
+                    uni_app::App::print(format!("Failed to mount {}, reason {}\n", point, e));
+                }
+                return;
+            }
+        };
+
+        let file = self.open_file(&name);
+        let file = self.file_tracker.track(&name, file);
+        self.pending_bundles.borrow_mut().push(PendingBundle {
+            name,
+            point: point.to_string(),
+            priority,
+            file,
+        });
+    }
+
+    fn unmount(&self, point: &str) {
+        self.vfs.unmount(point);
     }
 
     fn new_program(&self, name: &str) -> Rc<ShaderProgram> {
@@ -150,12 +370,105 @@ where
     }
 
     fn new_texture(&self, name: &str) -> Rc<Texture> {
-        let mut a = self.textures.borrow_mut();
-        self.new_asset(&mut a, name)
+        if let Some(tex) = self.textures.borrow().get(name) {
+            return tex.clone();
+        }
+
+        let tex = {
+            let mut a = self.textures.borrow_mut();
+            self.new_asset(&mut a, name)
+        };
+
+        // Applied to the sampler and the uploads from then on
+        let weak = Rc::downgrade(&tex);
+        let apply = self.import_settings(name).map(move |settings| {
+            if let Some(tex) = weak.upgrade() {
+                settings.texture.apply(&tex);
+            }
+        });
+        self.execute(Box::new(apply));
+
+        tex
     }
 
     fn new_mesh_buffer(&self, name: &str) -> Rc<MeshBuffer> {
-        let mut a = self.mesh_buffers.borrow_mut();
+        if let Some(mesh) = self.mesh_buffers.borrow().get(name) {
+            return mesh.clone();
+        }
+
+        let mesh = MeshBuffer::new(Resource::new_future(self.load_mesh_data(name)));
+        self.mesh_buffers.borrow_mut().insert(name.into(), mesh.clone());
+        mesh
+    }
+
+    fn import_settings(&self, name: &str) -> ImportSettingsFuture {
+        let meta = format!("{}.meta", name);
+
+        Box::new(self.open_after_bundles(&meta).then(move |r| -> AssetResult<_> {
+            // Most files have none
+            let mut file = match r {
+                Ok(file) => file,
+                Err(_) => return Ok(ImportSettings::default()),
+            };
+
+            let settings = file.read_binary()
+                .map_err(|_| AssetError::ReadBufferFail(meta.clone()))
+                .and_then(|buf| ImportSettings::parse(&meta, &buf));
+
+            Ok(settings.unwrap_or_else(|e| {
+                uni_app::App::print(format!(
+                    "Ignoring the import settings {}, reason {:?}\n",
+                    meta, e
+                ));
+                ImportSettings::default()
+            }))
+        }))
+    }
+
+    fn new_texture_from_image(&self, name: &str, img: TextureImage) -> Rc<Texture> {
+        let tex = Texture::new(img);
+        self.textures.borrow_mut().insert(name.into(), tex.clone());
+        tex
+    }
+
+    fn new_mesh_buffer_from_data(&self, name: &str, data: MeshData) -> Rc<MeshBuffer> {
+        let mesh = MeshBuffer::new(data);
+        self.mesh_buffers.borrow_mut().insert(name.into(), mesh.clone());
+        mesh
+    }
+
+    fn new_atlas(&self, name: &str) -> Rc<TextureAtlas> {
+        let mut a = self.atlases.borrow_mut();
+        self.new_asset(&mut a, name)
+    }
+
+    fn new_font(&self, name: &str) -> Rc<SdfFont> {
+        let mut a = self.fonts.borrow_mut();
+        self.new_asset(&mut a, name)
+    }
+
+    fn new_ttf_font(&self, name: &str) -> Rc<TtfFont> {
+        let mut a = self.ttf_fonts.borrow_mut();
+        self.new_asset(&mut a, name)
+    }
+
+    fn new_audio_clip(&self, name: &str) -> Rc<AudioClip> {
+        let mut a = self.audio_clips.borrow_mut();
+        self.new_asset(&mut a, name)
+    }
+
+    fn register_loader(&self, extension: &str, loader: CustomLoader) {
+        let extension = extension.trim_left_matches('.').to_lowercase();
+        self.custom_loaders.borrow_mut().insert(extension, loader);
+    }
+
+    fn custom_loader(&self, name: &str) -> Option<CustomLoader> {
+        let extension = Path::new(name).extension()?.to_str()?.to_lowercase();
+        self.custom_loaders.borrow().get(&extension).cloned()
+    }
+
+    fn new_custom_asset(&self, name: &str) -> Rc<CustomAsset> {
+        let mut a = self.custom_assets.borrow_mut();
         self.new_asset(&mut a, name)
     }
 
@@ -163,6 +476,15 @@ where
         self.textures.borrow_mut().clear();
         self.mesh_buffers.borrow_mut().clear();
         self.programs.borrow_mut().clear();
+        self.atlases.borrow_mut().clear();
+        self.fonts.borrow_mut().clear();
+        self.ttf_fonts.borrow_mut().clear();
+        self.audio_clips.borrow_mut().clear();
+        self.custom_assets.borrow_mut().clear();
+        self.program_files.borrow_mut().clear();
+        self.asset_files.borrow_mut().clear();
+        self.pending_reloads.borrow_mut().clear();
+        self.file_tracker.clear();
 
         self.setup();
     }
@@ -172,10 +494,82 @@ where
         self.pending_prefabs.borrow_mut().push((f, prefab));
     }
 
+    fn new_gltf(&self, name: &str, f: GltfHandler) {
+        let scene = loader::GltfScene::load_future(self.clone(), self.new_file(name));
+        self.pending_gltfs.borrow_mut().push((f, scene));
+    }
+
+    fn new_collada(&self, name: &str, f: ColladaHandler) {
+        let scene = loader::ColladaScene::load_future(self.clone(), self.new_file(name));
+        self.pending_colladas.borrow_mut().push((f, scene));
+    }
+
     fn execute(&self, task: AssetTask) {
         self.pending_tasks.borrow_mut().push(task);
     }
 
+    fn unload(&self, name: &str) -> bool {
+        let mut found = false;
+
+        if let Some(tex) = self.textures.borrow_mut().remove(name) {
+            tex.release_gpu();
+            found = true;
+        }
+        if let Some(mesh) = self.mesh_buffers.borrow_mut().remove(name) {
+            mesh.release_gpu();
+            found = true;
+        }
+
+        found |= self.programs.borrow_mut().remove(name).is_some();
+        found |= self.atlases.borrow_mut().remove(name).is_some();
+        found |= self.fonts.borrow_mut().remove(name).is_some();
+        found |= self.ttf_fonts.borrow_mut().remove(name).is_some();
+        found |= self.audio_clips.borrow_mut().remove(name).is_some();
+        found |= self.custom_assets.borrow_mut().remove(name).is_some();
+
+        self.asset_files.borrow_mut().remove(name);
+        self.program_files
+            .borrow_mut()
+            .retain(|&(ref program, _), _| program != name);
+
+        found
+    }
+
+    fn unload_unused(&self) -> usize {
+        let mut total = 0;
+
+        // The atlases and fonts hold their textures, which are unused after them
+        loop {
+            let builtin = self.builtin_assets.borrow();
+            let unloaded = remove_unused(&self.custom_assets, &builtin)
+                + remove_unused(&self.atlases, &builtin)
+                + remove_unused(&self.fonts, &builtin)
+                + remove_unused(&self.ttf_fonts, &builtin)
+                + remove_unused(&self.audio_clips, &builtin)
+                + remove_unused(&self.programs, &builtin)
+                + remove_unused(&self.mesh_buffers, &builtin)
+                + remove_unused(&self.textures, &builtin);
+
+            if unloaded == 0 {
+                break;
+            }
+            total += unloaded;
+        }
+
+        total
+    }
+
+    fn asset_ref_count(&self, name: &str) -> Option<usize> {
+        ref_count(&self.textures, name)
+            .or_else(|| ref_count(&self.mesh_buffers, name))
+            .or_else(|| ref_count(&self.programs, name))
+            .or_else(|| ref_count(&self.atlases, name))
+            .or_else(|| ref_count(&self.fonts, name))
+            .or_else(|| ref_count(&self.ttf_fonts, name))
+            .or_else(|| ref_count(&self.audio_clips, name))
+            .or_else(|| ref_count(&self.custom_assets, name))
+    }
+
     fn new() -> AssetDatabase<FS, F> {
         let mut db = AssetDatabase {
             context: Rc::new(AssetDatabaseContext {
@@ -184,8 +578,25 @@ where
                 textures: RefCell::new(HashMap::new()),
                 mesh_buffers: RefCell::new(HashMap::new()),
                 programs: RefCell::new(HashMap::new()),
+                atlases: RefCell::new(HashMap::new()),
+                fonts: RefCell::new(HashMap::new()),
+                ttf_fonts: RefCell::new(HashMap::new()),
+                audio_clips: RefCell::new(HashMap::new()),
+                custom_assets: RefCell::new(HashMap::new()),
+                custom_loaders: RefCell::new(HashMap::new()),
+                program_files: RefCell::new(HashMap::new()),
+                asset_files: RefCell::new(HashMap::new()),
+                modified_check: Cell::new(0),
+                capabilities: Cell::new(Capabilities::default()),
+                file_tracker: FileTracker::default(),
+                vfs: Vfs::default(),
+                builtin_assets: RefCell::new(HashSet::new()),
+                pending_bundles: RefCell::new(Vec::new()),
                 pending_prefabs: RefCell::new(Vec::new()),
+                pending_gltfs: RefCell::new(Vec::new()),
+                pending_colladas: RefCell::new(Vec::new()),
                 pending_tasks: RefCell::new(Vec::new()),
+                pending_reloads: RefCell::new(Vec::new()),
             }),
         };
 
@@ -194,29 +605,12 @@ where
     }
 
     fn step(&mut self) {
-        {
-            let pending_prefabs = self.pending_prefabs
-                .borrow_mut()
-                .drain(0..)
-                .collect::<Vec<_>>();
+        self.poll_bundles();
+        self.file_tracker.poll();
 
-            let new_pending = pending_prefabs
-                .into_iter()
-                .filter_map(|(f, mut prefab)| match prefab.poll() {
-                    Err(e) => {
-                        f(Err(e));
-                        None
-                    }
-                    Ok(Async::NotReady) => Some((f, prefab)),
-                    Ok(Async::Ready(i)) => {
-                        f(Ok(i));
-                        None
-                    }
-                })
-                .collect();
-
-            *self.pending_prefabs.borrow_mut() = new_pending;
-        }
+        poll_pending(&self.pending_prefabs);
+        poll_pending(&self.pending_gltfs);
+        poll_pending(&self.pending_colladas);
 
         {
             let pending_tasks = self.pending_tasks
@@ -237,11 +631,82 @@ where
 
             *self.pending_tasks.borrow_mut() = new_pending;
         }
+
+        // Reading the modification time of every file is slow, check them
+        // a few times per second only
+        match self.modified_check.get() {
+            0 => {
+                self.modified_check.set(MODIFIED_CHECK_INTERVAL);
+                self.reload_modified_programs();
+                self.reload_modified_assets();
+            }
+            n => self.modified_check.set(n - 1),
+        }
+        self.poll_reloads();
     }
 
     fn loading_files(&self) -> Vec<String> {
         self.fs.loading_files()
     }
+
+    fn asset_state(&self, name: &str) -> Option<AssetState> {
+        self.file_tracker.state(name)
+    }
+
+    fn loading_progress(&self) -> LoadingProgress {
+        self.file_tracker.progress()
+    }
+
+    fn finished_files(&self) -> Vec<(String, AssetState)> {
+        self.file_tracker.finished()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.context.capabilities.get()
+    }
+
+    fn set_capabilities(&self, caps: Capabilities) {
+        self.context.capabilities.set(caps);
+    }
+}
+
+/// Drop the assets of `map` only it holds, returns how many
+fn remove_unused<T>(map: &RefCell<HashMap<String, Rc<T>>>, builtin: &HashSet<String>) -> usize {
+    let mut map = map.borrow_mut();
+    let len = map.len();
+    map.retain(|name, asset| Rc::strong_count(asset) > 1 || builtin.contains(name));
+
+    len - map.len()
+}
+
+fn ref_count<T>(map: &RefCell<HashMap<String, Rc<T>>>, name: &str) -> Option<usize> {
+    map.borrow().get(name).map(|asset| Rc::strong_count(asset) - 1)
+}
+
+type PendingLoads<T> =
+    RefCell<Vec<(Box<FnBox(AssetResult<T>)>, Box<Future<Item = T, Error = AssetError>>)>>;
+
+/// Poll the loading futures, calling the handler of the finished ones
+fn poll_pending<T>(pending: &PendingLoads<T>) {
+    let pending_futures = pending.borrow_mut().drain(0..).collect::<Vec<_>>();
+
+    let new_pending: Vec<_> = pending_futures
+        .into_iter()
+        .filter_map(|(f, mut future)| match future.poll() {
+            Err(e) => {
+                f(Err(e));
+                None
+            }
+            Ok(Async::NotReady) => Some((f, future)),
+            Ok(Async::Ready(i)) => {
+                f(Ok(i));
+                None
+            }
+        })
+        .collect();
+
+    // The handlers may have started new loads
+    pending.borrow_mut().extend(new_pending);
 }
 
 impl<FS, F> AssetDatabase<FS, F>
@@ -316,6 +781,11 @@ where
             hm.insert("default".into(), Self::new_default_program());
             hm.insert("default_ui".into(), Self::new_default_ui_program());
         }
+
+        let mut builtin = self.builtin_assets.borrow_mut();
+        builtin.extend(self.mesh_buffers.borrow().keys().cloned());
+        builtin.extend(self.textures.borrow().keys().cloned());
+        builtin.extend(self.programs.borrow().keys().cloned());
     }
 
     fn new_default_font_bitmap() -> Rc<Texture> {
@@ -334,7 +804,7 @@ where
             }
         })));
 
-        tex.filtering.set(TextureFiltering::Nearest);
+        tex.set_filtering(TextureFiltering::Nearest);
 
         tex
     }
@@ -344,7 +814,7 @@ where
             image::Rgba(color)
         })));
 
-        tex.filtering.set(TextureFiltering::Nearest);
+        tex.set_filtering(TextureFiltering::Nearest);
 
         tex
     }
@@ -364,7 +834,12 @@ where
 
     pub fn new_default_program() -> Rc<ShaderProgram> {
         let vs = ShaderVs::new("phong_vs.glsl", DEFAULT_VS);
-        let fs = ShaderFs::new("phong_fs.glsl", DEFAULT_FS);
+        let mut includes = HashMap::new();
+        includes.insert("unrust/phong_light.glsl".to_string(), PHONG_LIGHT.to_string());
+        includes.insert("unrust/normal_map.glsl".to_string(), NORMAL_MAP.to_string());
+        includes.insert("unrust/color_space.glsl".to_string(), COLOR_SPACE.to_string());
+        includes.insert("unrust/camera_block.glsl".to_string(), CAMERA_BLOCK.to_string());
+        let fs = ShaderFs::with_includes("phong_fs.glsl", DEFAULT_FS, &includes);
 
         ShaderProgram::new((Resource::new(vs), Resource::new(fs)))
     }
@@ -376,14 +851,240 @@ where
         ShaderProgram::new((Resource::new(vs), Resource::new(fs)))
     }
 
+    /// Reload the programs whose shader files (or included files) changed on disk,
+    /// only on file systems which can watch files
+    fn reload_modified_programs(&self) {
+        let programs = self.programs.borrow();
+        let mut times = self.program_files.borrow_mut();
+
+        for (name, prog) in programs.iter() {
+            let mut files = vec![format!("{}_vs.glsl", name), format!("{}_fs.glsl", name)];
+            files.extend(prog.included_files());
+
+            let mut changed = false;
+            for file in files.into_iter() {
+                if let Some(t) = self.modified_time(&file) {
+                    match times.insert((name.clone(), file), t) {
+                        Some(last) if last != t => changed = true,
+                        _ => (),
+                    }
+                }
+            }
+
+            if changed {
+                uni_app::App::print(format!("Reloading shader program : {}\n", name));
+                prog.reload(ShaderProgram::load(self, ShaderProgram::gather(self, name)));
+            }
+        }
+    }
+
+    /// The file `name` once the bundles being loaded are mounted, as it may be
+    /// in one of them
+    fn open_after_bundles(&self, name: &str) -> fs::FileFuture {
+        if self.pending_bundles.borrow().is_empty() {
+            return self.open_file(name);
+        }
+
+        let db = self.clone();
+        let name = name.to_string();
+        let mut opened: Option<fs::FileFuture> = None;
+
+        Box::new(future::poll_fn(move || {
+            if opened.is_none() {
+                db.poll_bundles();
+                if !db.pending_bundles.borrow().is_empty() {
+                    return Ok(Async::NotReady);
+                }
+
+                opened = Some(db.open_file(&name));
+            }
+
+            opened.as_mut().unwrap().poll()
+        }))
+    }
+
+    /// The mesh of the file `name`, with its import settings applied
+    fn load_mesh_data(&self, name: &str) -> Box<Future<Item = MeshData, Error = AssetError>> {
+        let data = MeshData::load_future(self.clone(), self.new_file(name));
+
+        Box::new(data.join(self.import_settings(name)).map(|(mut data, settings)| {
+            settings.mesh.apply(&mut data);
+            data
+        }))
+    }
+
+    /// From the mounts having it, in priority order, or from the file system.
+    /// The next place is tried when a directory or URL fails to open.
+    fn open_file(&self, name: &str) -> fs::FileFuture {
+        let mut sources = self.vfs.resolve(name).into_iter();
+        let mut opened: Option<fs::FileFuture> = None;
+        let db = self.clone();
+        let name = name.to_string();
+
+        Box::new(future::poll_fn(move || loop {
+            if opened.is_none() {
+                opened = Some(match sources.next() {
+                    Some(VfsSource::Data(Ok(data))) => {
+                        let file = fs::MemoryFile {
+                            name: name.clone(),
+                            data,
+                        };
+                        return Ok(Async::Ready(Box::new(file) as Box<fs::File>));
+                    }
+                    Some(VfsSource::Data(Err(e))) => return Err(fs::FileIoError::Unknown(e)),
+                    Some(VfsSource::Path(path)) => db.fs.open(&db.get_filename(&path)),
+                    Some(VfsSource::Url(url)) => db.fs.open(&url),
+                    None => return Err(fs::FileIoError::NoSuchFile(name.clone())),
+                });
+            }
+
+            match opened.as_mut().unwrap().poll() {
+                Err(_) if !sources.is_empty() => opened = None,
+                r => return r,
+            }
+        }))
+    }
+
+    /// Mount the bundles which finished loading
+    fn poll_bundles(&self) {
+        let pending = self.pending_bundles
+            .borrow_mut()
+            .drain(0..)
+            .collect::<Vec<_>>();
+
+        let pending = pending
+            .into_iter()
+            .filter_map(|mut pending| {
+                let bundle = match pending.file.poll() {
+                    Ok(Async::NotReady) => return Some(pending),
+                    Ok(Async::Ready(mut file)) => file.read_binary()
+                        .map_err(|e| format!("{:?}", e))
+                        .and_then(AssetBundle::parse),
+                    Err(e) => Err(format!("{:?}", e)),
+                };
+
+                match bundle {
+                    Ok(bundle) => self.vfs
+                        .mount_bundle(&pending.point, bundle, pending.priority),
+                    Err(e) => uni_app::App::print(format!(
+                        "Failed to mount bundle {}, reason {}\n",
+                        pending.name, e
+                    )),
+                }
+
+                None
+            })
+            .collect();
+
+        *self.pending_bundles.borrow_mut() = pending;
+    }
+
+    /// The modification time of the file `name` is opened from, `None` for
+    /// the files in memory and on file systems which can't watch files
+    fn modified_time(&self, name: &str) -> Option<SystemTime> {
+        for source in self.vfs.resolve(name) {
+            match source {
+                VfsSource::Data(_) => return None,
+                VfsSource::Path(path) => {
+                    if let Some(t) = self.fs.modified(&self.get_filename(&path)) {
+                        return Some(t);
+                    }
+                }
+                VfsSource::Url(_) => (),
+            }
+        }
+
+        None
+    }
+
+    /// Whether the file `name` changed on disk since the last call, only on
+    /// file systems which can watch files
+    fn file_changed(&self, name: &str) -> bool {
+        let t = match self.modified_time(name) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        match self.asset_files.borrow_mut().insert(name.to_string(), t) {
+            Some(last) => last != t,
+            None => false,
+        }
+    }
+
+    /// Load the textures and meshes whose files changed again, their handles
+    /// get the new data once it is loaded
+    fn reload_modified_assets(&self) {
+        for (name, tex) in self.textures.borrow().iter() {
+            if self.file_changed(name) {
+                uni_app::App::print(format!("Reloading texture : {}\n", name));
+
+                let tex = tex.clone();
+                let img = TextureImage::load_future(self.clone(), self.new_file(name));
+                let reload = img.join(self.import_settings(name))
+                    .map(move |(img, settings)| {
+                        settings.texture.apply(&tex);
+                        tex.replace_image(img);
+                    });
+
+                self.pending_reloads
+                    .borrow_mut()
+                    .push((name.clone(), Box::new(reload)));
+            }
+        }
+
+        for (name, mesh) in self.mesh_buffers.borrow().iter() {
+            if self.file_changed(name) {
+                uni_app::App::print(format!("Reloading mesh : {}\n", name));
+
+                let mesh = mesh.clone();
+                let reload = self.load_mesh_data(name)
+                    .map(move |data| mesh.update_mesh_data(data));
+
+                self.pending_reloads
+                    .borrow_mut()
+                    .push((name.clone(), Box::new(reload)));
+            }
+        }
+    }
+
+    /// Keep the previous data of the assets which fail to load again
+    fn poll_reloads(&self) {
+        let pending = self.pending_reloads
+            .borrow_mut()
+            .drain(0..)
+            .collect::<Vec<_>>();
+
+        let pending = pending
+            .into_iter()
+            .filter_map(|(name, mut reload)| match reload.poll() {
+                Ok(Async::NotReady) => Some((name, reload)),
+                Ok(Async::Ready(_)) => None,
+                Err(e) => {
+                    uni_app::App::print(format!("Failed to reload {}, reason {:?}\n", name, e));
+                    None
+                }
+            })
+            .collect();
+
+        *self.pending_reloads.borrow_mut() = pending;
+    }
+
     pub fn get_filename(&self, name: &str) -> String {
         format!("{}{}", self.path, name)
     }
 }
 
+/// Steps between the checks of the modification times of the asset files
+const MODIFIED_CHECK_INTERVAL: u32 = 15;
+
 // Default vertex shader source code
 const DEFAULT_VS: &'static str = include_str!("phong_vs.glsl");
 const DEFAULT_FS: &'static str = include_str!("phong_fs.glsl");
+// The lights shared with the phong shaders of the static folder
+const PHONG_LIGHT: &'static str = include_str!("../../../static/unrust/phong_light.glsl");
+const NORMAL_MAP: &'static str = include_str!("../../../static/unrust/normal_map.glsl");
+const COLOR_SPACE: &'static str = include_str!("../../../static/unrust/color_space.glsl");
+const CAMERA_BLOCK: &'static str = include_str!("../../../static/unrust/camera_block.glsl");
 
 const DEFAULT_UI_VS: &'static str = include_str!("ui_vs.glsl");
 const DEFAULT_UI_FS: &'static str = include_str!("ui_fs.glsl");
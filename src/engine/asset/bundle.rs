@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+/// Magic of the packed bundles, followed by the version
+const BUNDLE_MAGIC: &[u8; 4] = b"UPAK";
+const BUNDLE_VERSION: u32 = 1;
+
+/// Minimum length of an LZ4 match
+const MIN_MATCH: usize = 4;
+/// The last bytes of an LZ4 block are always literals
+const LAST_LITERALS: usize = 5;
+/// A match does not start in the last bytes of an LZ4 block
+const MF_LIMIT: usize = 12;
+const HASH_BITS: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BundleCompression {
+    None,
+    /// The LZ4 block format
+    Lz4,
+}
+
+#[derive(Debug)]
+struct BundleEntry {
+    compression: BundleCompression,
+    offset: usize,
+    size: usize,
+    /// The size once decompressed
+    original_size: usize,
+}
+
+/// Many asset files packed in one, mounted with `AssetSystem::mount_bundle` so
+/// they are read from it instead of one request per file. The layout is:
+///
+/// - "UPAK", the version and the file count, as little endian u32
+/// - per file, its u16 name length and name, u8 compression (0 for none, 1
+///   for LZ4), and its u32 offset, stored size and original size
+/// - the file data, at their offsets from the start of the bundle
+#[derive(Debug)]
+pub struct AssetBundle {
+    entries: HashMap<String, BundleEntry>,
+    data: Vec<u8>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.pos + n > self.data.len() {
+            return Err("The bundle is truncated".to_string());
+        }
+
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(b[0] as u16 | (b[1] as u16) << 8)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+}
+
+fn push_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+}
+
+impl AssetBundle {
+    pub fn parse(data: Vec<u8>) -> Result<AssetBundle, String> {
+        let mut entries = HashMap::new();
+
+        {
+            let mut r = Reader {
+                data: &data,
+                pos: 0,
+            };
+
+            if r.bytes(4)? != BUNDLE_MAGIC {
+                return Err("Not an asset bundle".to_string());
+            }
+
+            let version = r.u32()?;
+            if version != BUNDLE_VERSION {
+                return Err(format!("Unsupported bundle version {}", version));
+            }
+
+            for _ in 0..r.u32()? {
+                let len = r.u16()? as usize;
+                let name = String::from_utf8(r.bytes(len)?.to_vec())
+                    .map_err(|_| "A file name is not utf8".to_string())?;
+
+                let compression = match r.u8()? {
+                    0 => BundleCompression::None,
+                    1 => BundleCompression::Lz4,
+                    c => return Err(format!("Unknown compression {} of {}", c, name)),
+                };
+
+                let entry = BundleEntry {
+                    compression,
+                    offset: r.u32()? as usize,
+                    size: r.u32()? as usize,
+                    original_size: r.u32()? as usize,
+                };
+
+                if entry.offset + entry.size > data.len() {
+                    return Err(format!("The data of {} is out of the bundle", name));
+                }
+
+                entries.insert(name, entry);
+            }
+        }
+
+        Ok(AssetBundle { entries, data })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.keys().map(|s| s.as_str()).collect()
+    }
+
+    /// The content of the file `name`, decompressed
+    pub fn read(&self, name: &str) -> Option<Result<Vec<u8>, String>> {
+        let entry = self.entries.get(name)?;
+        let stored = &self.data[entry.offset..entry.offset + entry.size];
+
+        Some(match entry.compression {
+            BundleCompression::None => Ok(stored.to_vec()),
+            BundleCompression::Lz4 => lz4_decompress(stored, entry.original_size)
+                .map_err(|e| format!("{} of {}", e, name)),
+        })
+    }
+}
+
+/// Builds the bundles read by `AssetBundle`, e.g. in a build script
+#[derive(Default)]
+pub struct AssetBundleWriter {
+    files: Vec<(String, BundleCompression, Vec<u8>, usize)>,
+    /// The bytes of the file headers and data
+    size: u64,
+}
+
+impl AssetBundleWriter {
+    pub fn new() -> AssetBundleWriter {
+        Default::default()
+    }
+
+    /// Add the file `name`, as passed to `AssetSystem::new_file`. It is stored
+    /// as is when the compression does not make it smaller.
+    ///
+    /// Fails when the name is over 64 KiB, or the file would not fit in the
+    /// 4 GiB a bundle addresses.
+    pub fn add(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        compression: BundleCompression,
+    ) -> Result<(), String> {
+        if name.len() > ::std::u16::MAX as usize {
+            return Err("A file name is over 64 KiB".to_string());
+        }
+        if data.len() as u64 > ::std::u32::MAX as u64 {
+            return Err(format!("{} is over 4 GiB", name));
+        }
+
+        let (compression, stored) = match compression {
+            BundleCompression::Lz4 => {
+                let compressed = lz4_compress(data);
+                if compressed.len() < data.len() {
+                    (BundleCompression::Lz4, compressed)
+                } else {
+                    (BundleCompression::None, data.to_vec())
+                }
+            }
+            BundleCompression::None => (BundleCompression::None, data.to_vec()),
+        };
+
+        let size = self.size + (2 + name.len() + 1 + 12) as u64 + stored.len() as u64;
+        if 12 + size > ::std::u32::MAX as u64 {
+            return Err(format!("The bundle is over 4 GiB with {}", name));
+        }
+
+        self.size = size;
+        self.files
+            .push((name.to_string(), compression, stored, data.len()));
+        Ok(())
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let header_size = 12 + self.files
+            .iter()
+            .map(|f| 2 + f.0.len() + 1 + 12)
+            .sum::<usize>();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(BUNDLE_MAGIC);
+        push_u32(&mut out, BUNDLE_VERSION);
+        push_u32(&mut out, self.files.len() as u32);
+
+        let mut offset = header_size;
+        for &(ref name, compression, ref stored, original_size) in self.files.iter() {
+            push_u16(&mut out, name.len() as u16);
+            out.extend_from_slice(name.as_bytes());
+            out.push(match compression {
+                BundleCompression::None => 0,
+                BundleCompression::Lz4 => 1,
+            });
+            push_u32(&mut out, offset as u32);
+            push_u32(&mut out, stored.len() as u32);
+            push_u32(&mut out, original_size as u32);
+
+            offset += stored.len();
+        }
+
+        for &(_, _, ref stored, _) in self.files.iter() {
+            out.extend_from_slice(stored);
+        }
+
+        out
+    }
+}
+
+fn read_u32(data: &[u8], i: usize) -> u32 {
+    data[i] as u32 | (data[i + 1] as u32) << 8 | (data[i + 2] as u32) << 16
+        | (data[i + 3] as u32) << 24
+}
+
+/// The extra bytes of a length of 15 or more in a token
+fn push_length(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], offset: usize, match_len: usize) {
+    let lit = literals.len();
+    let ml = match_len - MIN_MATCH;
+
+    out.push((lit.min(15) << 4 | ml.min(15)) as u8);
+    if lit >= 15 {
+        push_length(out, lit - 15);
+    }
+    out.extend_from_slice(literals);
+
+    push_u16(out, offset as u16);
+    if ml >= 15 {
+        push_length(out, ml - 15);
+    }
+}
+
+/// A greedy LZ4 block compressor, with a hash table of the last positions of
+/// the 4 bytes sequences
+fn lz4_compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len() / 2 + 16);
+    let mut table = vec![None; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut i = 0;
+
+    if src.len() > MF_LIMIT {
+        let match_limit = src.len() - MF_LIMIT;
+        let end = src.len() - LAST_LITERALS;
+
+        while i < match_limit {
+            let seq = read_u32(src, i);
+            let h = (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+            let candidate = table[h];
+            table[h] = Some(i);
+
+            match candidate {
+                Some(c) if i - c <= 0xffff && read_u32(src, c) == seq => {
+                    let mut len = MIN_MATCH;
+                    while i + len < end && src[c + len] == src[i + len] {
+                        len += 1;
+                    }
+
+                    push_sequence(&mut out, &src[anchor..i], i - c, len);
+                    i += len;
+                    anchor = i;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+
+    let lit = src.len() - anchor;
+    out.push((lit.min(15) << 4) as u8);
+    if lit >= 15 {
+        push_length(&mut out, lit - 15);
+    }
+    out.extend_from_slice(&src[anchor..]);
+
+    out
+}
+
+/// Decompress an LZ4 block of `size` bytes once decompressed. `size` comes
+/// from the bundle, the output never grows past it, nor past the 255:1 ratio
+/// of LZ4 when reserved.
+fn lz4_decompress(src: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let corrupted = || "Corrupted LZ4 data".to_string();
+    let mut out = Vec::with_capacity(size.min(src.len().saturating_mul(255)));
+    let mut i = 0;
+
+    let read_length = |i: &mut usize, mut n: usize| -> Result<usize, String> {
+        if n == 15 {
+            loop {
+                let b = *src.get(*i).ok_or_else(corrupted)?;
+                *i += 1;
+                n += b as usize;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        Ok(n)
+    };
+
+    while i < src.len() {
+        let token = src[i];
+        i += 1;
+
+        let lit = read_length(&mut i, (token >> 4) as usize)?;
+        if i + lit > src.len() || out.len() + lit > size {
+            return Err(corrupted());
+        }
+        out.extend_from_slice(&src[i..i + lit]);
+        i += lit;
+
+        // The last sequence has no match
+        if i == src.len() {
+            break;
+        }
+
+        if i + 2 > src.len() {
+            return Err(corrupted());
+        }
+        let offset = src[i] as usize | (src[i + 1] as usize) << 8;
+        i += 2;
+
+        if offset == 0 || offset > out.len() {
+            return Err(corrupted());
+        }
+
+        let len = read_length(&mut i, (token & 15) as usize)? + MIN_MATCH;
+        if out.len() + len > size {
+            return Err(corrupted());
+        }
+
+        // The match can overlap the bytes it writes
+        let start = out.len() - offset;
+        for k in 0..len {
+            let b = out[start + k];
+            out.push(b);
+        }
+    }
+
+    if out.len() != size {
+        return Err(corrupted());
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = lz4_compress(data);
+        assert_eq!(lz4_decompress(&compressed, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn lz4_round_trip() {
+        round_trip(b"");
+        round_trip(b"short");
+        round_trip(b"abcdabcdabcdabcdabcdabcdabcdabcd");
+        round_trip(&[7; 5000]);
+
+        // Long literal runs and matches, with their extra length bytes
+        let text: Vec<u8> = (0..20000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .chain(b"uniform vec3 uViewPos;\n".iter().cycle().cloned().take(3000))
+            .collect();
+        round_trip(&text);
+
+        assert!(lz4_compress(&[7; 5000]).len() < 100);
+    }
+
+    #[test]
+    fn lz4_corrupted() {
+        let data = b"abcdabcdabcdabcdabcdabcdabcdabcd";
+        let compressed = lz4_compress(data);
+
+        // Truncated, or a wrong size
+        assert!(lz4_decompress(&compressed[..compressed.len() - 1], data.len()).is_err());
+        assert!(lz4_decompress(&compressed[..3], data.len()).is_err());
+        assert!(lz4_decompress(&compressed, data.len() - 1).is_err());
+        assert!(lz4_decompress(&compressed, data.len() + 1).is_err());
+
+        // A match before the start of the output, or at offset 0
+        assert!(lz4_decompress(&[0x10, b'a', 5, 0, 0x00], 6).is_err());
+        assert!(lz4_decompress(&[0x10, b'a', 0, 0, 0x00], 6).is_err());
+
+        // A literal length running past the input
+        assert!(lz4_decompress(&[0xf0, 255, 255], 1000).is_err());
+
+        // A huge size from the header is not reserved up front
+        let huge = lz4_decompress(&[0x1f, b'a', 1, 0, 255, 255, 255, 255, 10], 0xffff_ffff);
+        assert!(huge.is_err());
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let shader = b"void main() { gl_FragColor = vec4(1.0); }\n".repeat(50);
+
+        let mut writer = AssetBundleWriter::new();
+        writer
+            .add("shader.glsl", &shader, BundleCompression::Lz4)
+            .unwrap();
+        writer
+            .add("random.bin", &[3, 1, 4, 1, 5], BundleCompression::Lz4)
+            .unwrap();
+        writer.add("empty", &[], BundleCompression::None).unwrap();
+
+        let bundle = AssetBundle::parse(writer.finish()).unwrap();
+        let mut names = bundle.names();
+        names.sort();
+        assert_eq!(names, vec!["empty", "random.bin", "shader.glsl"]);
+
+        assert_eq!(bundle.entries["shader.glsl"].compression, BundleCompression::Lz4);
+        assert_eq!(bundle.entries["random.bin"].compression, BundleCompression::None);
+
+        assert_eq!(bundle.read("shader.glsl").unwrap().unwrap(), shader);
+        assert_eq!(bundle.read("random.bin").unwrap().unwrap(), vec![3, 1, 4, 1, 5]);
+        assert_eq!(bundle.read("empty").unwrap().unwrap(), Vec::<u8>::new());
+        assert!(bundle.read("missing").is_none());
+    }
+
+    #[test]
+    fn bundle_corrupted() {
+        let mut writer = AssetBundleWriter::new();
+        writer.add("a", b"aaaa", BundleCompression::None).unwrap();
+        let data = writer.finish();
+
+        assert!(AssetBundle::parse(b"UPAC".to_vec()).is_err());
+        assert!(AssetBundle::parse(data[..data.len() - 1].to_vec()).is_err());
+
+        let mut wrong_version = data.clone();
+        wrong_version[4] = 2;
+        assert!(AssetBundle::parse(wrong_version).is_err());
+    }
+
+    #[test]
+    fn bundle_oversized_name() {
+        let name: String = ::std::iter::repeat('a').take(0x10000).collect();
+
+        let mut writer = AssetBundleWriter::new();
+        assert!(writer.add(&name, b"", BundleCompression::None).is_err());
+        assert!(writer.files.is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use engine::asset::{Asset, AssetError, AssetResult, AssetSystem, FileFuture, LoadableAsset,
+                    Resource};
+use std::any::Any;
+use std::cell::Ref;
+use std::fmt;
+use std::rc::Rc;
+
+/// The value returned by a loader of `AssetSystem::register_loader`
+pub struct CustomData(pub Box<Any>);
+
+impl fmt::Debug for CustomData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CustomData")
+    }
+}
+
+/// A file loaded by `AssetSystem::new_custom_asset` with the loader registered
+/// for its extension
+pub struct CustomAsset {
+    data: Resource<CustomData>,
+}
+
+impl Asset for CustomAsset {
+    type Resource = Resource<CustomData>;
+
+    fn new_from_resource(r: Self::Resource) -> Rc<Self> {
+        Rc::new(CustomAsset { data: r })
+    }
+}
+
+impl LoadableAsset for CustomAsset {
+    fn load<T>(asys: &T, mut files: Vec<FileFuture>) -> Self::Resource
+    where
+        T: AssetSystem + Clone + 'static,
+    {
+        Self::load_resource::<CustomData, T>(asys.clone(), files.remove(0))
+    }
+
+    fn gather<T: AssetSystem>(asys: &T, fname: &str) -> Vec<FileFuture> {
+        vec![asys.new_file(fname)]
+    }
+}
+
+impl CustomAsset {
+    /// The value of the loader, `AssetError::NotReady` while loading and
+    /// `AssetError::InvalidFormat` when it is not a `T`
+    pub fn try_get<T: Any>(&self) -> AssetResult<Ref<T>> {
+        let data = self.data.try_borrow()?;
+
+        if !data.0.is::<T>() {
+            return Err(AssetError::InvalidFormat {
+                path: "custom asset".to_string(),
+                len: 0,
+                reason: "The loaded value is of another type".to_string(),
+            });
+        }
+
+        Ok(Ref::map(data, |data| data.0.downcast_ref::<T>().unwrap()))
+    }
+
+    /// None while loading, on failure or when the value is not a `T`
+    pub fn get<T: Any>(&self) -> Option<Ref<T>> {
+        self.try_get().ok()
+    }
+}
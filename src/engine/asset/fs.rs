@@ -1,6 +1,7 @@
 use std::default::Default;
 use futures::prelude::*;
 use std;
+use std::time::SystemTime;
 
 pub type FileFuture = Box<Future<Item = Box<File>, Error = FileIoError>>;
 
@@ -10,6 +11,11 @@ pub trait FileSystem: Default {
     fn open(&self, filename: &str) -> FileFuture;
 
     fn loading_files(&self) -> Vec<String>;
+
+    /// The last modification time of a file, if the file system can watch it
+    fn modified(&self, _filename: &str) -> Option<SystemTime> {
+        None
+    }
 }
 
 pub trait File {
@@ -18,6 +24,23 @@ pub trait File {
     fn read_binary(&mut self) -> Result<Vec<u8>, FileIoError>;
 }
 
+/// The content of a file already in memory, e.g. read from a bundle or embedded
+/// in another file. It is handed over by the first `read_binary`.
+pub struct MemoryFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl File for MemoryFile {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn read_binary(&mut self) -> Result<Vec<u8>, FileIoError> {
+        Ok(std::mem::replace(&mut self.data, Vec::new()))
+    }
+}
+
 #[derive(Debug)]
 pub enum FileIoError {
     NotReady,
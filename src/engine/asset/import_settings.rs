@@ -0,0 +1,201 @@
+use engine::asset::{AssetError, AssetResult};
+use engine::render::{MeshData, Texture, TextureFiltering, TextureWrap};
+
+use futures::Future;
+use serde_json;
+use serde_json::Value;
+
+pub type ImportSettingsFuture = Box<Future<Item = ImportSettings, Error = AssetError>>;
+
+/// How an asset file is imported, from its optional sidecar file `<name>.meta`,
+/// e.g. `foo.png.meta`, read by `AssetSystem::import_settings`. It is a JSON
+/// object whose missing keys keep the defaults:
+///
+/// * `filtering`: `"linear"` or `"nearest"`
+/// * `wrap`: `"clamp"`, `"repeat"` or `"mirror"`, or `[u, v]`
+/// * `srgb`: false for the textures of data, see `Texture::set_srgb`
+/// * `mipmaps`: false to upload the textures without mipmaps
+/// * `scale`: of the mesh positions
+/// * `flip_x`, `flip_y` and `flip_z`: mirror the meshes along the axes
+/// * `swap_yz`: turn the meshes of Z up tools to Y up
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ImportSettings {
+    pub texture: TextureImportSettings,
+    pub mesh: MeshImportSettings,
+}
+
+/// The None fields keep the values set on the texture
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextureImportSettings {
+    pub filtering: Option<TextureFiltering>,
+    pub wrap: Option<(TextureWrap, TextureWrap)>,
+    pub srgb: Option<bool>,
+    pub mipmaps: Option<bool>,
+}
+
+impl TextureImportSettings {
+    pub fn apply(&self, tex: &Texture) {
+        if let Some(filtering) = self.filtering {
+            tex.set_filtering(filtering);
+        }
+        if let Some((wrap_u, wrap_v)) = self.wrap {
+            tex.set_wrap(wrap_u, wrap_v);
+        }
+        if let Some(srgb) = self.srgb {
+            tex.set_srgb(srgb);
+        }
+        if let Some(mipmaps) = self.mipmaps {
+            tex.set_mipmaps(mipmaps);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshImportSettings {
+    pub scale: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub flip_z: bool,
+    /// Applied before the flips
+    pub swap_yz: bool,
+}
+
+impl Default for MeshImportSettings {
+    fn default() -> MeshImportSettings {
+        MeshImportSettings {
+            scale: 1.0,
+            flip_x: false,
+            flip_y: false,
+            flip_z: false,
+            swap_yz: false,
+        }
+    }
+}
+
+impl MeshImportSettings {
+    /// Swap the axes then flip and scale the vector `v`
+    fn transform(&self, v: &mut [f32], scale: f32) {
+        if self.swap_yz {
+            // A rotation around X, which keeps the handedness
+            let (y, z) = (v[1], v[2]);
+            v[1] = z;
+            v[2] = -y;
+        }
+
+        let flips = [self.flip_x, self.flip_y, self.flip_z];
+        for (c, flip) in v.iter_mut().zip(flips.iter()) {
+            if *flip {
+                *c = -*c;
+            }
+            *c *= scale;
+        }
+    }
+
+    pub fn apply(&self, data: &mut MeshData) {
+        if *self == MeshImportSettings::default() {
+            return;
+        }
+
+        for v in data.vertices.chunks_mut(3) {
+            self.transform(v, self.scale);
+        }
+
+        // The directions only take the sign of the scale
+        let sign = self.scale.signum();
+        let dirs = data.normals
+            .iter_mut()
+            .chain(data.tangents.iter_mut())
+            .chain(data.bitangents.iter_mut());
+
+        for dir in dirs {
+            for v in dir.chunks_mut(3) {
+                self.transform(v, sign);
+            }
+        }
+
+        // A mirror turns the triangles inside out
+        let flips = [self.flip_x, self.flip_y, self.flip_z, self.scale < 0.0];
+        if flips.iter().filter(|f| **f).count() % 2 == 1 {
+            for tri in data.indices.chunks_mut(3) {
+                if tri.len() == 3 {
+                    tri.swap(1, 2);
+                }
+            }
+        }
+    }
+}
+
+fn invalid(path: &str, len: usize, reason: String) -> AssetError {
+    AssetError::InvalidFormat {
+        path: path.to_string(),
+        len,
+        reason,
+    }
+}
+
+fn parse_wrap(v: &Value) -> Result<TextureWrap, String> {
+    match v.as_str() {
+        Some("clamp") => Ok(TextureWrap::ClampToEdge),
+        Some("repeat") => Ok(TextureWrap::Repeat),
+        Some("mirror") => Ok(TextureWrap::MirroredRepeat),
+        _ => Err(format!("unknown wrap {}", v)),
+    }
+}
+
+fn parse_bool(root: &Value, key: &str) -> Result<Option<bool>, String> {
+    match root.get(key) {
+        None => Ok(None),
+        Some(v) => v.as_bool()
+            .map(Some)
+            .ok_or_else(|| format!("{} is not a bool", key)),
+    }
+}
+
+fn parse_settings(root: &Value) -> Result<ImportSettings, String> {
+    if !root.is_object() {
+        return Err("not an object".to_string());
+    }
+
+    let mut settings = ImportSettings::default();
+
+    if let Some(v) = root.get("filtering") {
+        settings.texture.filtering = Some(match v.as_str() {
+            Some("linear") => TextureFiltering::Linear,
+            Some("nearest") => TextureFiltering::Nearest,
+            _ => return Err(format!("unknown filtering {}", v)),
+        });
+    }
+
+    if let Some(v) = root.get("wrap") {
+        settings.texture.wrap = Some(match v.as_array() {
+            Some(uv) if uv.len() == 2 => (parse_wrap(&uv[0])?, parse_wrap(&uv[1])?),
+            Some(_) => return Err("wrap is not [u, v]".to_string()),
+            None => (parse_wrap(v)?, parse_wrap(v)?),
+        });
+    }
+
+    settings.texture.srgb = parse_bool(root, "srgb")?;
+    settings.texture.mipmaps = parse_bool(root, "mipmaps")?;
+
+    if let Some(v) = root.get("scale") {
+        settings.mesh.scale = v.as_f64().ok_or("scale is not a number")? as f32;
+    }
+
+    let mesh = &mut settings.mesh;
+    mesh.flip_x = parse_bool(root, "flip_x")?.unwrap_or(false);
+    mesh.flip_y = parse_bool(root, "flip_y")?.unwrap_or(false);
+    mesh.flip_z = parse_bool(root, "flip_z")?.unwrap_or(false);
+    mesh.swap_yz = parse_bool(root, "swap_yz")?.unwrap_or(false);
+
+    Ok(settings)
+}
+
+impl ImportSettings {
+    /// Parse the JSON of the sidecar file `path`
+    pub fn parse(path: &str, buf: &[u8]) -> AssetResult<ImportSettings> {
+        let root: Value =
+            serde_json::from_slice(buf).map_err(|e| invalid(path, buf.len(), format!("{}", e)))?;
+
+        parse_settings(&root).map_err(|e| invalid(path, buf.len(), e))
+    }
+}
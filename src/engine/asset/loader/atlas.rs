@@ -0,0 +1,176 @@
+use engine::asset::loader::{Loadable, Loader};
+use engine::asset::{AssetError, AssetResult, AssetSystem, File};
+use engine::render::{AtlasData, AtlasFrame, SpriteBorder};
+
+use math::*;
+use serde_json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Reads the JSON of TexturePacker (hash or array frames) and Aseprite.
+/// The texture is `meta.image`, relative to the JSON file. A frame with a
+/// `border` of `left`, `top`, `right` and `bottom` pixels is a nine-slice.
+pub struct AtlasLoader {}
+
+fn invalid(file: &str, len: usize, reason: &str) -> AssetError {
+    AssetError::InvalidFormat {
+        path: file.to_string(),
+        len,
+        reason: reason.to_string(),
+    }
+}
+
+fn rect(v: &Value) -> Option<(f32, f32, f32, f32)> {
+    Some((
+        v["x"].as_f64()? as f32,
+        v["y"].as_f64()? as f32,
+        v["w"].as_f64()? as f32,
+        v["h"].as_f64()? as f32,
+    ))
+}
+
+fn size(v: &Value) -> Option<(f32, f32)> {
+    Some((v["w"].as_f64()? as f32, v["h"].as_f64()? as f32))
+}
+
+/// The frame `v` in a texture of `tex_size` pixels
+fn parse_frame(v: &Value, tex_size: (f32, f32)) -> Result<AtlasFrame, &'static str> {
+    if v["rotated"].as_bool().unwrap_or(false) {
+        return Err("rotated frames are not supported");
+    }
+
+    let (x, y, w, h) = rect(&v["frame"]).ok_or("a frame without its rect")?;
+
+    // The pivot is from the top left of the untrimmed sprite in the file
+    let pivot = match (v["pivot"]["x"].as_f64(), v["pivot"]["y"].as_f64()) {
+        (Some(px), Some(py)) => (px as f32, py as f32),
+        _ => (0.5, 0.5),
+    };
+
+    let (ox, oy, sw, sh) = match (rect(&v["spriteSourceSize"]), size(&v["sourceSize"])) {
+        (Some((ox, oy, _, _)), Some((sw, sh))) => (ox, oy, sw, sh),
+        _ => (0.0, 0.0, w, h),
+    };
+
+    let border = match v.get("border") {
+        Some(b) => {
+            let inset = |side: &str| b[side].as_f64().map(|p| p as f32);
+            Some(SpriteBorder::new(
+                inset("left").ok_or("a border without left")?,
+                inset("bottom").ok_or("a border without bottom")?,
+                inset("right").ok_or("a border without right")?,
+                inset("top").ok_or("a border without top")?,
+            ))
+        }
+        None => None,
+    };
+
+    Ok(AtlasFrame {
+        region: (
+            Vector2f::new(x / tex_size.0, y / tex_size.1),
+            Vector2f::new((x + w) / tex_size.0, (y + h) / tex_size.1),
+        ),
+        size: Vector2f::new(w, h),
+        pivot: Vector2f::new(
+            (pivot.0 * sw - ox) / w,
+            1.0 - (pivot.1 * sh - oy) / h,
+        ),
+        border,
+    })
+}
+
+impl Loader<AtlasData> for AtlasLoader {
+    fn load<A>(asys: A, mut file: Box<File>) -> AssetResult<AtlasData>
+    where
+        A: AssetSystem + Clone,
+    {
+        let name = file.name();
+        let buf = file.read_binary()
+            .map_err(|_| AssetError::ReadBufferFail(name.clone()))?;
+        let len = buf.len();
+
+        let root: Value = serde_json::from_slice(&buf)
+            .map_err(|e| invalid(&name, len, &format!("{:?}", e)))?;
+
+        let meta = &root["meta"];
+        let image = meta["image"]
+            .as_str()
+            .ok_or_else(|| invalid(&name, len, "no meta.image"))?;
+        let tex_size = size(&meta["size"]).ok_or_else(|| invalid(&name, len, "no meta.size"))?;
+
+        let parent = match Path::new(&name).parent().and_then(|p| p.to_str()) {
+            Some(p) if !p.is_empty() => p.to_string() + "/",
+            _ => "".to_string(),
+        };
+
+        let mut frames = HashMap::new();
+        // In file order, for the Aseprite tags
+        let mut names = Vec::new();
+
+        match root["frames"] {
+            Value::Object(ref map) => for (k, v) in map.iter() {
+                let frame = parse_frame(v, tex_size).map_err(|e| invalid(&name, len, e))?;
+                frames.insert(k.clone(), frame);
+                names.push(k.clone());
+            },
+            Value::Array(ref list) => for v in list.iter() {
+                let k = v["filename"]
+                    .as_str()
+                    .ok_or_else(|| invalid(&name, len, "a frame without filename"))?;
+                let frame = parse_frame(v, tex_size).map_err(|e| invalid(&name, len, e))?;
+                frames.insert(k.to_string(), frame);
+                names.push(k.to_string());
+            },
+            _ => return Err(invalid(&name, len, "no frames")),
+        }
+
+        let mut animations = HashMap::new();
+
+        // TexturePacker: "animations": { "walk": ["walk_0", "walk_1"] }
+        if let Some(map) = root["animations"].as_object() {
+            for (k, v) in map.iter() {
+                let list = v.as_array()
+                    .ok_or_else(|| invalid(&name, len, "an animation is not a list"))?;
+                let seq = list.iter()
+                    .filter_map(|f| f.as_str().map(|s| s.to_string()))
+                    .collect();
+                animations.insert(k.clone(), seq);
+            }
+        }
+
+        // Aseprite: "frameTags": [{ "name": "walk", "from": 0, "to": 3 }], indices of the
+        // frames in file order
+        if let Some(tags) = meta["frameTags"].as_array() {
+            for tag in tags.iter() {
+                let range = (tag["from"].as_u64(), tag["to"].as_u64());
+                let (k, from, to) = match (tag["name"].as_str(), range) {
+                    (Some(k), (Some(from), Some(to))) => (k, from as usize, to as usize),
+                    _ => continue,
+                };
+
+                let mut seq: Vec<String> = names.iter()
+                    .skip(from)
+                    .take((to + 1).saturating_sub(from))
+                    .cloned()
+                    .collect();
+
+                if tag["direction"].as_str() == Some("reverse") {
+                    seq.reverse();
+                }
+
+                animations.insert(k.to_string(), seq);
+            }
+        }
+
+        Ok(AtlasData {
+            texture: asys.new_texture(&format!("{}{}", parent, image)),
+            frames,
+            animations,
+        })
+    }
+}
+
+impl Loadable for AtlasData {
+    type Loader = AtlasLoader;
+}
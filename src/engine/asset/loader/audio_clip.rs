@@ -0,0 +1,142 @@
+use engine::asset::loader::{Loadable, Loader};
+#[cfg(not(target_arch = "wasm32"))]
+use engine::asset::worker;
+use engine::asset::{AssetError, AssetResult, AssetSystem, File, FileFuture};
+use engine::sound::AudioClipData;
+
+#[cfg(target_arch = "wasm32")]
+use futures::future;
+use futures::prelude::*;
+use hound::{SampleFormat, WavReader};
+use lewton::inside_ogg::OggStreamReader;
+use std::io::Cursor;
+
+#[cfg(target_arch = "wasm32")]
+use super::web_audio;
+
+/// Decodes the WAV and OGG Vorbis files, told apart by their header. The
+/// decoding runs on a worker thread on native, and in the browser on wasm.
+pub struct AudioClipLoader {}
+
+fn invalid_audio(name: &str, len: usize, reason: String) -> AssetError {
+    AssetError::InvalidFormat {
+        path: name.to_string(),
+        len,
+        reason,
+    }
+}
+
+fn decode_wav(name: &str, bytes: &[u8]) -> AssetResult<AudioClipData> {
+    let invalid = |e: ::hound::Error| invalid_audio(name, bytes.len(), format!("{:?}", e));
+
+    let mut wav = WavReader::new(bytes).map_err(invalid)?;
+    let spec = wav.spec();
+
+    let samples = match spec.sample_format {
+        SampleFormat::Float => wav.samples::<f32>().collect::<Result<Vec<_>, _>>(),
+        SampleFormat::Int => {
+            let coef = 2.0 / (1u64 << spec.bits_per_sample) as f32;
+            wav.samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * coef))
+                .collect::<Result<Vec<_>, _>>()
+        }
+    }.map_err(invalid)?;
+
+    Ok(AudioClipData {
+        channels: spec.channels as usize,
+        sample_rate: spec.sample_rate as usize,
+        samples,
+    })
+}
+
+fn decode_ogg(name: &str, bytes: &[u8]) -> AssetResult<AudioClipData> {
+    let invalid = |e: ::lewton::VorbisError| invalid_audio(name, bytes.len(), format!("{:?}", e));
+
+    let mut ogg = OggStreamReader::new(Cursor::new(bytes)).map_err(invalid)?;
+    let mut samples = Vec::new();
+
+    // Interleaved packets
+    while let Some(packet) = ogg.read_dec_packet_itl().map_err(invalid)? {
+        samples.extend(packet.into_iter().map(|s| s as f32 / 32768.0));
+    }
+
+    Ok(AudioClipData {
+        channels: ogg.ident_hdr.audio_channels as usize,
+        sample_rate: ogg.ident_hdr.audio_sample_rate as usize,
+        samples,
+    })
+}
+
+fn decode(name: &str, bytes: &[u8]) -> AssetResult<AudioClipData> {
+    if bytes.starts_with(b"OggS") {
+        decode_ogg(name, bytes)
+    } else if bytes.starts_with(b"RIFF") {
+        decode_wav(name, bytes)
+    } else {
+        Err(invalid_audio(
+            name,
+            bytes.len(),
+            "Not a WAV or OGG file".to_string(),
+        ))
+    }
+}
+
+impl Loader<AudioClipData> for AudioClipLoader {
+    fn load<A>(_asys: A, mut file: Box<File>) -> AssetResult<AudioClipData> {
+        let name = file.name();
+        let bytes = file.read_binary()
+            .map_err(|_| AssetError::ReadBufferFail(name.clone()))?;
+
+        decode(&name, &bytes)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn decode_off_main_thread(
+    bytes: Vec<u8>,
+    name: String,
+) -> Box<Future<Item = AudioClipData, Error = AssetError>> {
+    let path = name.clone();
+    let len = bytes.len();
+
+    let decoded = worker::spawn(move || decode(&name, &bytes));
+
+    Box::new(decoded.then(move |r| match r {
+        Ok(clip) => clip,
+        Err(reason) => Err(AssetError::InvalidFormat { path, len, reason }),
+    }))
+}
+
+/// The browser decodes the clips with decodeAudioData. The ones it can't
+/// decode, e.g. OGG Vorbis on Safari, are decoded here.
+#[cfg(target_arch = "wasm32")]
+fn decode_off_main_thread(
+    bytes: Vec<u8>,
+    name: String,
+) -> Box<Future<Item = AudioClipData, Error = AssetError>> {
+    if !web_audio::is_supported() {
+        return Box::new(future::result(decode(&name, &bytes)));
+    }
+
+    let decoded = web_audio::decode(&bytes, name.clone());
+    Box::new(decoded.or_else(move |_| decode(&name, &bytes)))
+}
+
+impl Loadable for AudioClipData {
+    type Loader = AudioClipLoader;
+
+    fn load_future<A>(_asys: A, f0: FileFuture) -> Box<Future<Item = Self, Error = AssetError>>
+    where
+        Self: 'static,
+        A: AssetSystem + Clone + 'static,
+    {
+        let bytes = f0.then(|r| {
+            let mut file = r.map_err(AssetError::FileIoError)?;
+            let bytes = file.read_binary()
+                .map_err(|_| AssetError::ReadBufferFail(file.name()))?;
+            Ok((bytes, file.name()))
+        });
+
+        Box::new(bytes.and_then(|(bytes, name)| decode_off_main_thread(bytes, name)))
+    }
+}
@@ -0,0 +1,962 @@
+use engine::asset::{AssetError, AssetSystem, FileFuture, Resource};
+use engine::core::GameObject;
+use engine::engine::IEngine;
+use engine::render::{Material, Mesh, MeshBuffer, MeshData};
+
+use super::gltf::{decompose_matrix, smooth_normals};
+use super::prefab::{parent_path, ObjMaterial};
+
+use math::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use xml::reader::{EventReader, XmlEvent};
+
+use futures::future::*;
+
+/// A node of a COLLADA visual scene, its indices are in the `ColladaScene` lists
+pub struct ColladaNode {
+    pub name: Option<String>,
+    pub transform: Isometry3<f32>,
+    pub scale: Vector3f,
+    /// The meshes of its geometry and controller instances
+    pub meshes: Vec<usize>,
+    /// A node of type JOINT, a bone of a skeleton
+    pub joint: bool,
+    pub children: Vec<usize>,
+}
+
+/// The joints influencing each vertex of a surface, with their weights
+#[derive(Clone, Debug, Default)]
+pub struct SkinWeights {
+    /// Indices in `ColladaSkin::joints`, the unused ones have a weight of 0
+    pub joints: Vec<[u16; 4]>,
+    /// The 4 largest weights of the vertex, normalized
+    pub weights: Vec<[f32; 4]>,
+}
+
+/// The skin controller of a mesh
+pub struct ColladaSkin {
+    /// The mesh it deforms, its vertices are in the bind pose
+    pub mesh: usize,
+    /// The joint nodes
+    pub joints: Vec<usize>,
+    /// From the bind pose of the mesh to the space of each joint
+    pub inverse_bind_matrices: Vec<Matrix4f>,
+    /// The weights of each surface of the mesh, in the vertex order of its buffer
+    pub surfaces: Vec<SkinWeights>,
+}
+
+/// The visual scene of a COLLADA 1.4 .dae file, see `AssetSystem::new_collada`.
+///
+/// Meshes have the "unrust/obj" material of their phong, blinn or lambert effect,
+/// see `ObjMaterial::build_material`. Triangles, polylists and polygons are read
+/// with their first TEXCOORD set, and the scene is converted to Y up and meters.
+///
+/// Skinned meshes are in their bind pose with the bind shape matrix applied, the
+/// engine has no skinning pass: `skins` gives their joints, inverse bind matrices
+/// and vertex weights. Animations are not read.
+pub struct ColladaScene {
+    pub meshes: Vec<Mesh>,
+    pub skins: Vec<ColladaSkin>,
+    /// Every node of the scene, parents before their children
+    pub nodes: Vec<ColladaNode>,
+    /// The nodes at the root of the scene
+    pub roots: Vec<usize>,
+}
+
+/// The game objects of an instantiated `ColladaScene`, keep it to keep them alive
+pub struct ColladaInstance {
+    pub roots: Vec<Rc<RefCell<GameObject>>>,
+    /// The game object of each node, in the order of `ColladaScene::nodes`
+    pub objects: Vec<Rc<RefCell<GameObject>>>,
+}
+
+impl ColladaScene {
+    /// Create the game objects of the nodes under `parent`, with their mesh
+    /// components. The joints are game objects too, see `ColladaSkin::joints`.
+    pub fn instantiate(&self, engine: &mut IEngine, parent: &GameObject) -> ColladaInstance {
+        let mut objects = vec![None; self.nodes.len()];
+        let roots = self.roots
+            .iter()
+            .map(|&node| self.instantiate_node(engine, parent, node, &mut objects))
+            .collect();
+
+        ColladaInstance {
+            roots,
+            objects: objects.into_iter().filter_map(|o| o).collect(),
+        }
+    }
+
+    fn instantiate_node(
+        &self,
+        engine: &mut IEngine,
+        parent: &GameObject,
+        index: usize,
+        objects: &mut Vec<Option<Rc<RefCell<GameObject>>>>,
+    ) -> Rc<RefCell<GameObject>> {
+        let node = &self.nodes[index];
+        let go = engine.new_game_object(parent);
+
+        {
+            let mut go = go.borrow_mut();
+            go.name = node.name.clone().unwrap_or_default();
+            go.transform.set_local(node.transform);
+            go.transform.set_local_scale(node.scale);
+
+            for mesh in node.meshes.iter().filter_map(|&i| self.meshes.get(i)) {
+                go.add_component(mesh.clone());
+            }
+        }
+
+        objects[index] = Some(go.clone());
+
+        for &child in node.children.iter() {
+            self.instantiate_node(engine, &go.borrow(), child, objects);
+        }
+
+        go
+    }
+
+    pub fn load_future<A>(asys: A, file: FileFuture) -> Box<Future<Item = Self, Error = AssetError>>
+    where
+        A: AssetSystem + Clone + 'static,
+    {
+        Box::new(
+            file.map_err(AssetError::FileIoError)
+                .and_then(move |mut f| {
+                    let name = f.name();
+                    let bytes = f.read_binary().map_err(AssetError::FileIoError)?;
+
+                    ColladaLoader::load_scene(&asys, &name, &bytes).map_err(|reason| {
+                        AssetError::InvalidFormat {
+                            path: name.clone(),
+                            len: bytes.len(),
+                            reason,
+                        }
+                    })
+                }),
+        )
+    }
+}
+
+/// Reads the geometries, materials, skins and visual scene of a .dae file
+pub struct ColladaLoader {}
+
+/// An element of the document, with its text content
+#[derive(Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.0 == name)
+            .map(|a| a.1.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// The first element named `name` in this subtree
+    fn find(&self, name: &str) -> Option<&Element> {
+        for c in self.children.iter() {
+            if c.name == name {
+                return Some(c);
+            }
+            if let Some(found) = c.find(name) {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    fn floats(&self) -> Vec<f32> {
+        self.text
+            .split_whitespace()
+            .map(|s| s.parse().unwrap_or(0.0))
+            .collect()
+    }
+
+    fn indices(&self) -> Result<Vec<usize>, String> {
+        self.text
+            .split_whitespace()
+            .map(|s| s.parse().map_err(|_| format!("Invalid index {}", s)))
+            .collect()
+    }
+}
+
+fn read_document(bytes: &[u8]) -> Result<Element, String> {
+    let mut stack = vec![Element::default()];
+
+    for event in EventReader::new(bytes) {
+        match event.map_err(|e| e.to_string())? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(Element {
+                name: name.local_name,
+                attributes: attributes
+                    .into_iter()
+                    .map(|a| (a.name.local_name, a.value))
+                    .collect(),
+                ..Element::default()
+            }),
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().ok_or("Unbalanced element")?;
+                stack
+                    .last_mut()
+                    .ok_or("Unbalanced element")?
+                    .children
+                    .push(element);
+            }
+            XmlEvent::Characters(s) | XmlEvent::CData(s) => {
+                if let Some(e) = stack.last_mut() {
+                    e.text.push_str(&s);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stack
+        .pop()
+        .and_then(|document| document.children.into_iter().next())
+        .filter(|root| root.name == "COLLADA")
+        .ok_or_else(|| "Not a COLLADA document".to_owned())
+}
+
+/// "#id" to "id"
+fn url_id(url: &str) -> &str {
+    url.trim_left_matches('#')
+}
+
+/// A row major COLLADA matrix, to the column major of cgmath
+fn row_major(m: &[f32]) -> Matrix4f {
+    Matrix4::new(
+        m[0], m[4], m[8], m[12], m[1], m[5], m[9], m[13], m[2], m[6], m[10], m[14], m[3], m[7],
+        m[11], m[15],
+    )
+}
+
+/// The transform elements of a node, applied in document order
+fn node_matrix(node: &Element) -> Matrix4f {
+    let mut m = Matrix4::identity();
+
+    for c in node.children.iter() {
+        let f = c.floats();
+        m = m * match c.name.as_str() {
+            "matrix" if f.len() >= 16 => row_major(&f),
+            "translate" if f.len() >= 3 => {
+                Matrix4::from_translation(Vector3f::new(f[0], f[1], f[2]))
+            }
+            "rotate" if f.len() >= 4 => Matrix4::from_axis_angle(
+                Vector3f::new(f[0], f[1], f[2]).normalize(),
+                Deg(f[3]),
+            ),
+            "scale" if f.len() >= 3 => Matrix4::from_nonuniform_scale(f[0], f[1], f[2]),
+            _ => continue,
+        };
+    }
+
+    m
+}
+
+/// A float source of a mesh or a skin
+struct Source {
+    data: Vec<f32>,
+    stride: usize,
+}
+
+impl Source {
+    fn get(&self, i: usize, n: usize) -> Result<&[f32], String> {
+        self.data
+            .get(i * self.stride..i * self.stride + n)
+            .ok_or_else(|| format!("Index {} out of its source", i))
+    }
+}
+
+fn source(e: &Element) -> Source {
+    let stride = e.find("accessor")
+        .and_then(|a| a.attr("stride"))
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1);
+
+    Source {
+        data: e.child("float_array").map(|a| a.floats()).unwrap_or_default(),
+        stride,
+    }
+}
+
+/// The names of a joint source, matching the sid (Name_array) or the id
+/// (IDREF_array) of the joint nodes
+fn joint_names(e: &Element) -> Vec<String> {
+    e.child("Name_array")
+        .or_else(|| e.child("IDREF_array"))
+        .map(|a| a.text.split_whitespace().map(|s| s.to_owned()).collect())
+        .unwrap_or_default()
+}
+
+/// The 4 largest influences of a vertex, normalized
+fn strongest_influences(mut influences: Vec<(u16, f32)>) -> ([u16; 4], [f32; 4]) {
+    influences.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(::std::cmp::Ordering::Equal));
+    influences.truncate(4);
+
+    let total: f32 = influences.iter().map(|i| i.1).sum();
+    let mut joints = [0; 4];
+    let mut weights = [0.0; 4];
+    for (k, &(joint, weight)) in influences.iter().enumerate() {
+        joints[k] = joint;
+        weights[k] = if total > 0.0 { weight / total } else { 0.0 };
+    }
+
+    (joints, weights)
+}
+
+/// The controller of an instance_controller, read before the joints are resolved
+struct SkinController<'a> {
+    geometry: &'a str,
+    bind_shape: Matrix4f,
+    joint_names: Vec<String>,
+    inverse_bind_matrices: Vec<Matrix4f>,
+    /// The influences of each position of the geometry
+    influences: Vec<Vec<(u16, f32)>>,
+}
+
+fn skin_controller(controller: &Element) -> Result<SkinController, String> {
+    let skin = controller.child("skin").ok_or("A controller without skin")?;
+    let geometry = skin.attr("source").map(url_id).ok_or("A skin without source")?;
+
+    let bind_shape = skin.child("bind_shape_matrix")
+        .map(|m| m.floats())
+        .filter(|m| m.len() >= 16)
+        .map_or_else(Matrix4::identity, |m| row_major(&m));
+
+    let sources: HashMap<&str, &Element> = skin.children("source")
+        .filter_map(|s| s.attr("id").map(|id| (id, s)))
+        .collect();
+    let input_source = |inputs: &Element, semantic: &str| {
+        inputs
+            .children("input")
+            .find(|i| i.attr("semantic") == Some(semantic))
+            .and_then(|i| i.attr("source"))
+            .and_then(|s| sources.get(url_id(s)).cloned())
+            .ok_or_else(|| format!("A skin without {}", semantic))
+    };
+
+    let joints = skin.child("joints").ok_or("A skin without joints")?;
+    let joint_names = joint_names(input_source(joints, "JOINT")?);
+    let inverse_bind_matrices: Vec<Matrix4f> = source(input_source(joints, "INV_BIND_MATRIX")?)
+        .data
+        .chunks(16)
+        .filter(|m| m.len() == 16)
+        .map(row_major)
+        .collect();
+
+    if inverse_bind_matrices.len() < joint_names.len() {
+        return Err("A skin without the inverse bind matrix of each joint".to_owned());
+    }
+
+    let vertex_weights = skin.child("vertex_weights")
+        .ok_or("A skin without vertex_weights")?;
+    let weights = source(input_source(vertex_weights, "WEIGHT")?);
+    let offset = |semantic: &str| {
+        vertex_weights
+            .children("input")
+            .find(|i| i.attr("semantic") == Some(semantic))
+            .and_then(|i| i.attr("offset"))
+            .and_then(|o| o.parse::<usize>().ok())
+            .ok_or_else(|| format!("A skin without {} offset", semantic))
+    };
+    let (joint_offset, weight_offset) = (offset("JOINT")?, offset("WEIGHT")?);
+    let stride = vertex_weights.children("input").count();
+
+    let vcount = vertex_weights.child("vcount").map_or(Ok(vec![]), |e| e.indices())?;
+    let v: Vec<i64> = vertex_weights
+        .child("v")
+        .map(|e| {
+            e.text
+                .split_whitespace()
+                .map(|s| s.parse().unwrap_or(-1))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut influences = Vec::with_capacity(vcount.len());
+    let mut k = 0;
+    for &count in vcount.iter() {
+        let mut vertex = Vec::with_capacity(count);
+        for _ in 0..count {
+            let joint = *v.get(k + joint_offset).ok_or("Truncated vertex weights")?;
+            let weight = *v.get(k + weight_offset).ok_or("Truncated vertex weights")?;
+            k += stride;
+
+            // -1 is the bind shape itself
+            if joint < 0 || joint as usize >= joint_names.len() || weight < 0 {
+                continue;
+            }
+            vertex.push((joint as u16, weights.get(weight as usize, 1)?[0]));
+        }
+        influences.push(vertex);
+    }
+
+    Ok(SkinController {
+        geometry,
+        bind_shape,
+        joint_names,
+        inverse_bind_matrices,
+        influences,
+    })
+}
+
+/// Indices are u16, larger primitives are split in several surfaces
+const MAX_SURFACE_VERTICES: usize = 65536;
+
+/// The vertices of a surface, one per polygon corner
+#[derive(Default)]
+struct SurfaceData {
+    vertices: Vec<f32>,
+    uvs: Vec<f32>,
+    normals: Vec<f32>,
+    /// The position index of each vertex, for the skin weights
+    positions: Vec<usize>,
+}
+
+/// The inputs of a primitive, indices in each of its corners
+struct PrimitiveInputs<'a> {
+    stride: usize,
+    position: (usize, &'a Source),
+    normal: Option<(usize, &'a Source)>,
+    uv: Option<(usize, &'a Source)>,
+}
+
+impl<'a> PrimitiveInputs<'a> {
+    fn add_vertex(&self, corner: &[usize], surface: &mut SurfaceData) -> Result<(), String> {
+        let p = corner[self.position.0];
+        surface
+            .vertices
+            .extend_from_slice(self.position.1.get(p, 3)?);
+        surface.positions.push(p);
+
+        if let Some((offset, source)) = self.normal {
+            surface
+                .normals
+                .extend_from_slice(source.get(corner[offset], 3)?);
+        }
+        if let Some((offset, source)) = self.uv {
+            let uv = source.get(corner[offset], 2)?;
+            surface.uvs.push(uv[0]);
+            surface.uvs.push(1.0 - uv[1]);
+        }
+
+        Ok(())
+    }
+}
+
+struct ColladaContext<'a, A: 'a> {
+    asys: &'a A,
+    parent: &'a str,
+    root: &'a Element,
+    /// The materials by id, shared by the instances binding them
+    materials: RefCell<HashMap<String, Rc<Material>>>,
+}
+
+impl<'a, A> ColladaContext<'a, A>
+where
+    A: AssetSystem + Clone + 'static,
+{
+    /// The element with the id `id` in the library `library`
+    fn library_element(&self, library: &str, id: &str) -> Option<&'a Element> {
+        self.root
+            .children
+            .iter()
+            .filter(|l| l.name == library)
+            .flat_map(|l| l.children.iter())
+            .find(|e| e.attr("id") == Some(id))
+    }
+
+    fn image_path(&self, image_id: &str) -> Option<String> {
+        let path = self.library_element("library_images", image_id)?
+            .find("init_from")?
+            .text
+            .trim()
+            .replace("%20", " ");
+
+        if path.starts_with("file://") {
+            Some(path.trim_left_matches("file://").to_owned())
+        } else if path.starts_with('/') {
+            Some(path)
+        } else {
+            Some(self.parent.to_owned() + path.trim_left_matches("./"))
+        }
+    }
+
+    /// The image of a `<texture>` of a profile_COMMON effect, through its
+    /// sampler and surface newparams
+    fn texture_path(&self, profile: &Element, texture: &Element) -> Option<String> {
+        let sampler = texture.attr("texture")?;
+        let newparam = |sid: &str| {
+            profile
+                .children("newparam")
+                .find(|p| p.attr("sid") == Some(sid))
+        };
+
+        let image = match newparam(sampler) {
+            Some(p) => {
+                let surface = p.find("source")?.text.trim().to_owned();
+                match newparam(surface.as_str()) {
+                    Some(s) => s.find("init_from")?.text.trim().to_owned(),
+                    None => surface,
+                }
+            }
+            // Some exporters refer to the image directly
+            None => sampler.to_owned(),
+        };
+
+        self.image_path(&image)
+    }
+
+    fn material(&self, material_id: &str) -> Rc<Material> {
+        if let Some(material) = self.materials.borrow().get(material_id) {
+            return material.clone();
+        }
+
+        let material = self.effect_material(material_id);
+        self.materials
+            .borrow_mut()
+            .insert(material_id.to_owned(), material.clone());
+        material
+    }
+
+    /// The material of the profile_COMMON effect of a material, the default one
+    /// without it
+    fn effect_material(&self, material_id: &str) -> Rc<Material> {
+        let mut obj_mat = ObjMaterial::default();
+
+        let profile = self.library_element("library_materials", material_id)
+            .and_then(|m| m.child("instance_effect"))
+            .and_then(|e| e.attr("url"))
+            .and_then(|url| self.library_element("library_effects", url_id(url)))
+            .and_then(|effect| effect.child("profile_COMMON"));
+
+        if let Some(profile) = profile {
+            let technique = profile
+                .child("technique")
+                .and_then(|t| {
+                    t.children
+                        .iter()
+                        .find(|s| ["phong", "blinn", "lambert", "constant"].contains(&&*s.name))
+                });
+
+            if let Some(shading) = technique {
+                let color = |name: &str| {
+                    shading
+                        .child(name)
+                        .and_then(|c| c.child("color"))
+                        .map(|c| c.floats())
+                        .filter(|c| c.len() >= 3)
+                        .map(|c| Vector3f::new(c[0], c[1], c[2]))
+                };
+                let texture = |name: &str| {
+                    shading
+                        .child(name)
+                        .and_then(|c| c.child("texture"))
+                        .and_then(|t| self.texture_path(profile, t))
+                };
+                let float = |name: &str| {
+                    shading
+                        .child(name)
+                        .and_then(|c| c.child("float"))
+                        .and_then(|f| f.text.trim().parse::<f32>().ok())
+                };
+
+                obj_mat.ambient = color("ambient");
+                obj_mat.diffuse = color("diffuse");
+                obj_mat.diffuse_map = texture("diffuse");
+                obj_mat.specular = color("specular");
+                obj_mat.specular_map = texture("specular");
+                obj_mat.shininess = float("shininess");
+                obj_mat.alpha_mask = texture("transparent");
+
+                // A transparency of 1 is opaque in the A_ONE mode of most exporters
+                obj_mat.alpha = float("transparency").filter(|&a| a > 0.0);
+            }
+
+            // The bump map of the FCOLLADA and OpenCOLLADA extras
+            obj_mat.normal_map = profile
+                .find("bump")
+                .and_then(|b| b.child("texture"))
+                .and_then(|t| self.texture_path(profile, t));
+        }
+
+        ObjMaterial::build_material(self.asys, obj_mat)
+    }
+
+    /// The materials of an instance, by the symbol of the primitives
+    fn bind_material(&self, instance: &Element) -> HashMap<String, Rc<Material>> {
+        let mut materials = HashMap::new();
+
+        if let Some(bind) = instance.child("bind_material") {
+            if let Some(technique) = bind.child("technique_common") {
+                for m in technique.children("instance_material") {
+                    if let (Some(symbol), Some(target)) = (m.attr("symbol"), m.attr("target")) {
+                        materials.insert(symbol.to_owned(), self.material(url_id(target)));
+                    }
+                }
+            }
+        }
+
+        materials
+    }
+
+    /// The surfaces of a geometry, with the position index of their vertices
+    fn geometry(
+        &self,
+        geometry: &Element,
+        materials: &HashMap<String, Rc<Material>>,
+        bind_shape: Option<Matrix4f>,
+    ) -> Result<(Mesh, Vec<Vec<usize>>), String> {
+        let mesh_element = geometry.child("mesh").ok_or("A geometry without mesh")?;
+
+        let sources: HashMap<&str, Source> = mesh_element
+            .children("source")
+            .filter_map(|s| s.attr("id").map(|id| (id, source(s))))
+            .collect();
+        let vertices = mesh_element
+            .child("vertices")
+            .ok_or("A mesh without vertices")?;
+        let vertices_id = vertices.attr("id").unwrap_or("");
+        let vertices_input = |semantic: &str| {
+            vertices
+                .children("input")
+                .find(|i| i.attr("semantic") == Some(semantic))
+                .and_then(|i| i.attr("source"))
+                .and_then(|s| sources.get(url_id(s)))
+        };
+
+        let mut mesh = Mesh::new();
+        let mut surface_positions = Vec::new();
+
+        for primitive in mesh_element.children.iter() {
+            let polygons: Vec<Vec<usize>> = match primitive.name.as_str() {
+                "triangles" | "polylist" | "polygons" => primitive
+                    .children("p")
+                    .map(|p| p.indices())
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => continue,
+            };
+
+            let mut inputs = PrimitiveInputs {
+                stride: 1,
+                position: (0, vertices_input("POSITION").ok_or("A mesh without positions")?),
+                normal: vertices_input("NORMAL").map(|s| (0, s)),
+                uv: vertices_input("TEXCOORD").map(|s| (0, s)),
+            };
+
+            let mut uv_set = None;
+            for input in primitive.children("input") {
+                let offset = input
+                    .attr("offset")
+                    .and_then(|o| o.parse().ok())
+                    .unwrap_or(0);
+                let source_id = input.attr("source").map(url_id).unwrap_or("");
+                inputs.stride = inputs.stride.max(offset + 1);
+
+                match input.attr("semantic") {
+                    Some("VERTEX") if source_id == vertices_id => {
+                        inputs.position.0 = offset;
+                        if let Some(n) = inputs.normal.as_mut() {
+                            n.0 = offset;
+                        }
+                        if let Some(uv) = inputs.uv.as_mut() {
+                            uv.0 = offset;
+                        }
+                    }
+                    Some("NORMAL") => {
+                        inputs.normal = sources.get(source_id).map(|s| (offset, s));
+                    }
+                    // The first uv set only
+                    Some("TEXCOORD") => {
+                        let set = input.attr("set").unwrap_or("0");
+                        if uv_set.is_none() || uv_set == Some(set) {
+                            uv_set = Some(set);
+                            inputs.uv = sources.get(source_id).map(|s| (offset, s));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            // Split the indices of triangles and polylists in polygons
+            let polygons = match primitive.name.as_str() {
+                "triangles" => polygons
+                    .concat()
+                    .chunks(inputs.stride * 3)
+                    .map(|c| c.to_vec())
+                    .collect(),
+                "polylist" => {
+                    let p = polygons.concat();
+                    let vcount = primitive
+                        .child("vcount")
+                        .map_or(Ok(vec![]), |e| e.indices())?;
+
+                    let mut list = Vec::with_capacity(vcount.len());
+                    let mut start = 0;
+                    for n in vcount {
+                        let end = start + n * inputs.stride;
+                        list.push(p.get(start..end).ok_or("Truncated polylist")?.to_vec());
+                        start = end;
+                    }
+                    list
+                }
+                _ => polygons,
+            };
+
+            let mut surfaces = vec![SurfaceData::default()];
+            for polygon in polygons.iter() {
+                let corners: Vec<&[usize]> = polygon
+                    .chunks(inputs.stride)
+                    .filter(|c| c.len() == inputs.stride)
+                    .collect();
+
+                // Fan triangulation, the polygons of exporters are convex
+                for i in 1..corners.len().saturating_sub(1) {
+                    if surfaces.last().unwrap().positions.len() + 3 > MAX_SURFACE_VERTICES {
+                        surfaces.push(SurfaceData::default());
+                    }
+
+                    let surface = surfaces.last_mut().unwrap();
+                    inputs.add_vertex(corners[0], surface)?;
+                    inputs.add_vertex(corners[i], surface)?;
+                    inputs.add_vertex(corners[i + 1], surface)?;
+                }
+            }
+
+            let material = primitive
+                .attr("material")
+                .and_then(|symbol| materials.get(symbol).cloned())
+                .unwrap_or_else(|| self.material(""));
+
+            for surface in surfaces {
+                if surface.positions.is_empty() {
+                    continue;
+                }
+
+                let SurfaceData {
+                    mut vertices,
+                    uvs,
+                    mut normals,
+                    positions,
+                } = surface;
+
+                let indices: Vec<u16> = (0..positions.len()).map(|i| i as u16).collect();
+                if normals.is_empty() {
+                    normals = smooth_normals(&vertices, &indices);
+                }
+
+                if let Some(m) = bind_shape {
+                    transform_vertices(&m, &mut vertices, &mut normals);
+                }
+
+                let data = MeshData {
+                    indices,
+                    vertices,
+                    uvs: if uvs.is_empty() { None } else { Some(uvs) },
+                    normals: Some(normals),
+                    tangents: None,
+                    bitangents: None,
+                };
+
+                mesh.add_surface(
+                    MeshBuffer::new_from_resource(Resource::new(data)),
+                    material.clone(),
+                );
+                surface_positions.push(positions);
+            }
+        }
+
+        Ok((mesh, surface_positions))
+    }
+
+    /// The nodes under `e` in preorder, with their instances to resolve
+    fn nodes(
+        &self,
+        e: &'a Element,
+        nodes: &mut Vec<ColladaNode>,
+        elements: &mut Vec<&'a Element>,
+    ) -> Vec<usize> {
+        let mut indices = Vec::new();
+
+        for n in e.children("node") {
+            let m = node_matrix(n);
+            let m: &[f32; 16] = m.as_ref();
+            let (transform, scale) = decompose_matrix(m);
+
+            let index = nodes.len();
+            nodes.push(ColladaNode {
+                name: n.attr("name").map(|s| s.to_owned()),
+                transform,
+                scale,
+                meshes: Vec::new(),
+                joint: n.attr("type") == Some("JOINT"),
+                children: Vec::new(),
+            });
+            elements.push(n);
+
+            nodes[index].children = self.nodes(n, nodes, elements);
+            indices.push(index);
+        }
+
+        indices
+    }
+}
+
+/// Apply the bind shape matrix of a skin to its vertices
+fn transform_vertices(m: &Matrix4f, vertices: &mut [f32], normals: &mut [f32]) {
+    let normal_matrix = Matrix3::new(
+        m.x.x, m.x.y, m.x.z, m.y.x, m.y.y, m.y.z, m.z.x, m.z.y, m.z.z,
+    ).invert()
+        .map_or_else(Matrix3::identity, |n| n.transpose());
+
+    for v in vertices.chunks_mut(3) {
+        let p = m.transform_point(Point3::new(v[0], v[1], v[2]));
+        v.copy_from_slice(&[p.x, p.y, p.z]);
+    }
+    for n in normals.chunks_mut(3) {
+        let n3 = (normal_matrix * Vector3f::new(n[0], n[1], n[2])).normalize();
+        n.copy_from_slice(&[n3.x, n3.y, n3.z]);
+    }
+}
+
+impl ColladaLoader {
+    fn load_scene<A>(asys: &A, name: &str, bytes: &[u8]) -> Result<ColladaScene, String>
+    where
+        A: AssetSystem + Clone + 'static,
+    {
+        let root = read_document(bytes)?;
+        let parent = parent_path(name);
+
+        let asset = root.child("asset");
+        let up_axis = match asset
+            .and_then(|a| a.child("up_axis"))
+            .map(|u| u.text.trim())
+        {
+            Some("Z_UP") => Quaternion::from_angle_x(Deg(-90.0)),
+            Some("X_UP") => Quaternion::from_angle_z(Deg(90.0)),
+            _ => Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        };
+        let meter = asset
+            .and_then(|a| a.child("unit"))
+            .and_then(|u| u.attr("meter"))
+            .and_then(|m| m.parse::<f32>().ok())
+            .filter(|&m| m > 0.0)
+            .unwrap_or(1.0);
+
+        let ctx = ColladaContext {
+            asys,
+            parent: &parent,
+            root: &root,
+            materials: RefCell::new(HashMap::new()),
+        };
+
+        let scene_id = root.child("scene")
+            .and_then(|s| s.child("instance_visual_scene"))
+            .and_then(|i| i.attr("url"))
+            .map(url_id)
+            .ok_or("A document without scene")?;
+        let visual_scene = ctx.library_element("library_visual_scenes", scene_id)
+            .ok_or_else(|| format!("Unknown visual scene {}", scene_id))?;
+
+        let mut nodes = Vec::new();
+        let mut elements = Vec::new();
+        let roots = ctx.nodes(visual_scene, &mut nodes, &mut elements);
+
+        // The scene is converted to Y up meters by its roots
+        for &r in roots.iter() {
+            let node = &mut nodes[r];
+            node.transform.disp = up_axis.rotate_vector(node.transform.disp * meter);
+            node.transform.rot = up_axis * node.transform.rot;
+            node.scale *= meter;
+        }
+
+        // Joints are found by sid, then id, then name
+        let find_joint = |joint: &str| {
+            let by = |attr: &str| elements.iter().position(|e| e.attr(attr) == Some(joint));
+            by("sid").or_else(|| by("id")).or_else(|| by("name"))
+        };
+
+        let mut meshes = Vec::new();
+        let mut skins = Vec::new();
+
+        for (index, element) in elements.iter().enumerate() {
+            for instance in element.children("instance_geometry") {
+                let url = instance.attr("url").map(url_id).unwrap_or("");
+                let geometry = ctx.library_element("library_geometries", url)
+                    .ok_or_else(|| format!("Unknown geometry {}", url))?;
+
+                let (mesh, _) = ctx.geometry(geometry, &ctx.bind_material(instance), None)?;
+                nodes[index].meshes.push(meshes.len());
+                meshes.push(mesh);
+            }
+
+            for instance in element.children("instance_controller") {
+                let url = instance.attr("url").map(url_id).unwrap_or("");
+                let controller = ctx.library_element("library_controllers", url)
+                    .ok_or_else(|| format!("Unknown controller {}", url))?;
+                let skin = skin_controller(controller)?;
+
+                let geometry = ctx.library_element("library_geometries", skin.geometry)
+                    .ok_or_else(|| format!("Unknown geometry {}", skin.geometry))?;
+                let (mesh, surface_positions) = ctx.geometry(
+                    geometry,
+                    &ctx.bind_material(instance),
+                    Some(skin.bind_shape),
+                )?;
+
+                let joints = skin.joint_names
+                    .iter()
+                    .map(|j| find_joint(j).ok_or_else(|| format!("Unknown joint {}", j)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let surfaces = surface_positions
+                    .into_iter()
+                    .map(|positions| {
+                        let mut weights = SkinWeights::default();
+                        for p in positions {
+                            let influences = skin.influences.get(p).cloned().unwrap_or_default();
+                            let (j, w) = strongest_influences(influences);
+                            weights.joints.push(j);
+                            weights.weights.push(w);
+                        }
+                        weights
+                    })
+                    .collect();
+
+                nodes[index].meshes.push(meshes.len());
+                skins.push(ColladaSkin {
+                    mesh: meshes.len(),
+                    joints,
+                    inverse_bind_matrices: skin.inverse_bind_matrices,
+                    surfaces,
+                });
+                meshes.push(mesh);
+            }
+        }
+
+        Ok(ColladaScene {
+            meshes,
+            skins,
+            nodes,
+            roots,
+        })
+    }
+}
@@ -0,0 +1,29 @@
+use engine::asset::loader::{Loadable, Loader};
+use engine::asset::{AssetError, AssetResult, AssetSystem, CustomData, File};
+
+/// Hands the file to the loader registered for its extension
+pub struct CustomAssetLoader {}
+
+impl Loader<CustomData> for CustomAssetLoader {
+    fn load<A>(asys: A, mut file: Box<File>) -> AssetResult<CustomData>
+    where
+        A: AssetSystem + Clone,
+    {
+        let name = file.name();
+        let loader = asys.custom_loader(&name)
+            .ok_or_else(|| AssetError::InvalidFormat {
+                path: name.clone(),
+                len: 0,
+                reason: "No loader is registered for the extension".to_string(),
+            })?;
+
+        let bytes = file.read_binary()
+            .map_err(|_| AssetError::ReadBufferFail(name.clone()))?;
+
+        loader(&name, bytes).map(CustomData)
+    }
+}
+
+impl Loadable for CustomData {
+    type Loader = CustomAssetLoader;
+}
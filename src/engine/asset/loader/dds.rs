@@ -9,6 +9,9 @@ pub struct DDSReader {}
 pub enum DDSFormat {
     DXT1,
     DXT5,
+    /// Of a KTX file, with the GL internal format of the blocks
+    ETC2(u32),
+    ASTC(u32),
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,924 @@
+use engine::asset::{Asset, AssetError, AssetSystem, File, FileFuture, LoadableAsset, MemoryFile,
+                    Resource};
+use engine::core::GameObject;
+use engine::engine::IEngine;
+use engine::render::{Blend, Camera, CullMode, Material, Mesh, MeshBuffer, MeshData,
+                     MipmapFiltering, RenderQueue, Texture, TextureAsset, TextureFiltering,
+                     TextureImage, TextureSampler, TextureWrap};
+
+use super::prefab::parent_path;
+
+use math::*;
+use serde_json;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::future;
+use futures::future::*;
+
+/// A node of a glTF scene, its indices are in the `GltfScene` lists
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub transform: Isometry3<f32>,
+    pub scale: Vector3f,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum GltfCamera {
+    Perspective {
+        yfov: f32,
+        /// The aspect of the screen when not set
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        /// Infinite when not set, the default far plane of `Camera` is used
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+/// The default scene of a glTF 2.0 file, a .gltf with its buffers and images
+/// or a .glb, see `AssetSystem::new_gltf`.
+///
+/// Meshes have the "unrust/pbr" material of their glTF one, with `TEXCOORD_0`
+/// as their only uv set. Primitives are triangle lists of at most 65536
+/// vertices, as the indices are u16. Skins, morph targets, animations and
+/// sparse accessors are not supported.
+pub struct GltfScene {
+    /// A mesh per glTF mesh, with a surface per primitive
+    pub meshes: Vec<Mesh>,
+    pub cameras: Vec<GltfCamera>,
+    pub nodes: Vec<GltfNode>,
+    /// The nodes at the root of the scene
+    pub roots: Vec<usize>,
+}
+
+/// The game objects of an instantiated `GltfScene`, keep it to keep them alive
+pub struct GltfInstance {
+    pub roots: Vec<Rc<RefCell<GameObject>>>,
+    /// Every game object of the scene, in creation order
+    pub objects: Vec<Rc<RefCell<GameObject>>>,
+}
+
+impl GltfScene {
+    /// Create the game objects of the nodes under `parent`, with their mesh and
+    /// camera components. The view of a camera is the one of its node at this
+    /// point, it does not follow the node afterwards.
+    pub fn instantiate(&self, engine: &mut IEngine, parent: &GameObject) -> GltfInstance {
+        let mut objects = Vec::new();
+        let roots = self.roots
+            .iter()
+            .map(|&node| self.instantiate_node(engine, parent, node, &mut objects))
+            .collect();
+
+        GltfInstance { roots, objects }
+    }
+
+    fn instantiate_node(
+        &self,
+        engine: &mut IEngine,
+        parent: &GameObject,
+        index: usize,
+        objects: &mut Vec<Rc<RefCell<GameObject>>>,
+    ) -> Rc<RefCell<GameObject>> {
+        let node = &self.nodes[index];
+        let go = engine.new_game_object(parent);
+
+        {
+            let mut go = go.borrow_mut();
+            go.name = node.name.clone().unwrap_or_default();
+            go.transform.set_local(node.transform);
+            go.transform.set_local_scale(node.scale);
+
+            if let Some(mesh) = node.mesh.and_then(|i| self.meshes.get(i)) {
+                go.add_component(mesh.clone());
+            }
+
+            if let Some(camera) = node.camera.and_then(|i| self.cameras.get(i)) {
+                let world = go.transform.as_global_matrix();
+                go.add_component(camera.to_camera(engine.screen_size(), &world));
+            }
+        }
+
+        objects.push(go.clone());
+
+        for &child in node.children.iter() {
+            self.instantiate_node(engine, &go.borrow(), child, objects);
+        }
+
+        go
+    }
+
+    pub fn load_future<A>(asys: A, file: FileFuture) -> Box<Future<Item = Self, Error = AssetError>>
+    where
+        A: AssetSystem + Clone + 'static,
+    {
+        let document = {
+            let asys = asys.clone();
+            file.map_err(AssetError::FileIoError)
+                .and_then(move |mut f| {
+                    let name = f.name();
+                    let bytes = f.read_binary().map_err(AssetError::FileIoError)?;
+                    let (json, bin) = read_document(&bytes)
+                        .map_err(|reason| invalid(&name, bytes.len(), reason))?;
+
+                    let parent = parent_path(&name);
+                    let files: Vec<_> = external_buffers(&json)
+                        .into_iter()
+                        .map(|uri| asys.new_file(&(parent.clone() + &uri)))
+                        .collect();
+
+                    let document = GltfDocument {
+                        name,
+                        json,
+                        bin,
+                        parent,
+                    };
+
+                    Ok(join_all(files)
+                        .map_err(AssetError::FileIoError)
+                        .map(move |files| (document, files)))
+                })
+                .flatten()
+        };
+
+        Box::new(document.and_then(move |(document, files)| {
+            let mut external = Vec::new();
+            for mut f in files {
+                external.push(f.read_binary().map_err(AssetError::FileIoError)?);
+            }
+
+            GltfLoader::load_scene(&asys, document, external)
+        }))
+    }
+}
+
+impl GltfCamera {
+    fn to_camera(self, screen_size: (u32, u32), world: &Matrix4f) -> Camera {
+        let mut camera = Camera::new();
+        camera.v = world.invert().unwrap_or_else(Matrix4::identity);
+
+        match self {
+            GltfCamera::Perspective {
+                yfov,
+                aspect_ratio,
+                znear,
+                zfar,
+            } => {
+                let screen_aspect = screen_size.0 as f32 / screen_size.1.max(1) as f32;
+
+                camera.znear = znear;
+                camera.zfar = zfar.unwrap_or(camera.zfar);
+                camera.projection = Some(
+                    PerspectiveFov {
+                        fovy: Rad(yfov),
+                        aspect: aspect_ratio.unwrap_or(screen_aspect).max(0.001),
+                        near: camera.znear,
+                        far: camera.zfar,
+                    }.into(),
+                );
+            }
+            GltfCamera::Orthographic { ymag, znear, zfar } => {
+                camera.znear = znear;
+                camera.zfar = zfar;
+                camera.orthographic_size = Some(ymag);
+            }
+        }
+
+        camera
+    }
+}
+
+/// Reads the JSON of a .gltf or .glb file and the buffers it refers to
+pub struct GltfLoader {}
+
+struct GltfDocument {
+    name: String,
+    json: Value,
+    /// The BIN chunk of a .glb
+    bin: Option<Vec<u8>>,
+    parent: String,
+}
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+/// The most components of an accessor without buffer view, all zeros, which
+/// is not bounded by a buffer
+const MAX_ZERO_ACCESSOR_VALUES: usize = 1 << 24;
+
+fn invalid(file: &str, len: usize, reason: String) -> AssetError {
+    AssetError::InvalidFormat {
+        path: file.to_string(),
+        len,
+        reason,
+    }
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset + 4)?;
+    Some(u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16 | u32::from(b[3]) << 24)
+}
+
+fn truncated() -> String {
+    "Truncated GLB".to_owned()
+}
+
+/// The JSON and the binary chunk of a .glb, or the JSON of a .gltf
+fn read_document(bytes: &[u8]) -> Result<(Value, Option<Vec<u8>>), String> {
+    if !bytes.starts_with(GLB_MAGIC) {
+        let json = serde_json::from_slice(bytes).map_err(|e| format!("{}", e))?;
+        return Ok((json, None));
+    }
+
+    if le_u32(bytes, 4).ok_or_else(truncated)? != 2 {
+        return Err("Unsupported GLB version, only support 2".to_owned());
+    }
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+
+    while offset + 8 <= bytes.len() {
+        let len = le_u32(bytes, offset).ok_or_else(truncated)? as usize;
+        let kind = le_u32(bytes, offset + 4).ok_or_else(truncated)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + len)
+            .ok_or_else(truncated)?;
+
+        match kind {
+            GLB_CHUNK_JSON => {
+                json = Some(serde_json::from_slice(data).map_err(|e| format!("{}", e))?)
+            }
+            GLB_CHUNK_BIN => bin = Some(data.to_vec()),
+            // Unknown chunks are skipped
+            _ => {}
+        }
+
+        offset += 8 + len;
+    }
+
+    Ok((json.ok_or("GLB without JSON chunk")?, bin))
+}
+
+fn is_data_uri(uri: &str) -> bool {
+    uri.starts_with("data:")
+}
+
+/// The uris of the buffers in other files, in the buffer order
+fn external_buffers(json: &Value) -> Vec<String> {
+    json["buffers"]
+        .as_array()
+        .map(|buffers| {
+            buffers
+                .iter()
+                .filter_map(|b| b["uri"].as_str())
+                .filter(|uri| !is_data_uri(uri))
+                .map(|uri| uri.to_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The bytes of a base64 data uri
+fn decode_data_uri(uri: &str) -> Option<Vec<u8>> {
+    let comma = uri.find(',')?;
+    if !uri[..comma].ends_with(";base64") {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity((uri.len() - comma) * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for c in uri[comma + 1..].bytes() {
+        let v = match c {
+            c if c >= b'A' && c <= b'Z' => c - b'A',
+            c if c >= b'a' && c <= b'z' => c - b'a' + 26,
+            c if c >= b'0' && c <= b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+
+        acc = acc << 6 | u32::from(v);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    Some(data)
+}
+
+fn floats(v: &Value, n: usize) -> Option<Vec<f32>> {
+    let array = v.as_array()?;
+    if array.len() != n {
+        return None;
+    }
+
+    array.iter().map(|f| f.as_f64().map(|f| f as f32)).collect()
+}
+
+fn index(v: &Value) -> Option<usize> {
+    v.as_u64().map(|i| i as usize)
+}
+
+/// The glTF default sampler repeats and has trilinear filtering
+fn texture_sampler(v: &Value) -> TextureSampler {
+    let mut sampler = TextureSampler::new(TextureFiltering::Linear);
+
+    let filter = |f: u64| match f {
+        9728 | 9984 | 9986 => TextureFiltering::Nearest,
+        _ => TextureFiltering::Linear,
+    };
+    let wrap = |w: Option<u64>| match w {
+        Some(33071) => TextureWrap::ClampToEdge,
+        Some(33648) => TextureWrap::MirroredRepeat,
+        _ => TextureWrap::Repeat,
+    };
+
+    if let Some(mag) = v["magFilter"].as_u64() {
+        sampler.mag_filter = filter(mag);
+    }
+
+    if let Some(min) = v["minFilter"].as_u64() {
+        sampler.min_filter = filter(min);
+        sampler.mipmap_filter = match min {
+            9984 | 9985 => MipmapFiltering::Nearest,
+            9986 | 9987 => MipmapFiltering::Linear,
+            _ => MipmapFiltering::None,
+        };
+    }
+
+    sampler.wrap_u = wrap(v["wrapS"].as_u64());
+    sampler.wrap_v = wrap(v["wrapT"].as_u64());
+    sampler
+}
+
+/// Translation, rotation and scale of a column major affine matrix
+pub fn decompose_matrix(m: &[f32]) -> (Isometry3<f32>, Vector3f) {
+    let column = |i: usize| Vector3f::new(m[i * 4], m[i * 4 + 1], m[i * 4 + 2]);
+    let scale = Vector3f::new(
+        column(0).magnitude(),
+        column(1).magnitude(),
+        column(2).magnitude(),
+    );
+    let safe = |s: f32| if s > 0.0 { s } else { 1.0 };
+    let rot = Matrix3::from_cols(
+        column(0) / safe(scale.x),
+        column(1) / safe(scale.y),
+        column(2) / safe(scale.z),
+    );
+
+    let transform = Decomposed {
+        scale: 1.0,
+        rot: Quaternion::from(rot).normalize(),
+        disp: column(3),
+    };
+
+    (transform, scale)
+}
+
+/// Translation, rotation and scale of a node, from its TRS or its matrix
+fn node_transform(v: &Value) -> (Isometry3<f32>, Vector3f) {
+    if let Some(m) = floats(&v["matrix"], 16) {
+        return decompose_matrix(&m);
+    }
+
+    let t = floats(&v["translation"], 3).unwrap_or_else(|| vec![0.0; 3]);
+    let r = floats(&v["rotation"], 4).unwrap_or_else(|| vec![0.0, 0.0, 0.0, 1.0]);
+    let s = floats(&v["scale"], 3).unwrap_or_else(|| vec![1.0; 3]);
+
+    let transform = Decomposed {
+        scale: 1.0,
+        rot: Quaternion::new(r[3], r[0], r[1], r[2]),
+        disp: Vector3f::new(t[0], t[1], t[2]),
+    };
+
+    (transform, Vector3f::new(s[0], s[1], s[2]))
+}
+
+fn camera(v: &Value) -> Result<GltfCamera, String> {
+    let field = |obj: &Value, name: &str| {
+        obj[name]
+            .as_f64()
+            .map(|f| f as f32)
+            .ok_or_else(|| format!("A camera without {}", name))
+    };
+
+    match v["type"].as_str() {
+        Some("perspective") => {
+            let p = &v["perspective"];
+            Ok(GltfCamera::Perspective {
+                yfov: field(p, "yfov")?,
+                aspect_ratio: p["aspectRatio"].as_f64().map(|f| f as f32),
+                znear: field(p, "znear")?,
+                zfar: p["zfar"].as_f64().map(|f| f as f32),
+            })
+        }
+        Some("orthographic") => {
+            let o = &v["orthographic"];
+            Ok(GltfCamera::Orthographic {
+                ymag: field(o, "ymag")?,
+                znear: field(o, "znear")?,
+                zfar: field(o, "zfar")?,
+            })
+        }
+        _ => Err("A camera of unknown type".to_owned()),
+    }
+}
+
+/// Area weighted normals of the triangles around each vertex
+pub fn smooth_normals(vertices: &[f32], indices: &[u16]) -> Vec<f32> {
+    let position = |i: u16| {
+        let i = i as usize * 3;
+        Vector3f::new(vertices[i], vertices[i + 1], vertices[i + 2])
+    };
+
+    let mut normals = vec![0.0; vertices.len()];
+    for tri in indices.chunks(3).filter(|tri| tri.len() == 3) {
+        let n = (position(tri[1]) - position(tri[0])).cross(position(tri[2]) - position(tri[0]));
+        for &i in tri.iter() {
+            let i = i as usize * 3;
+            normals[i] += n.x;
+            normals[i + 1] += n.y;
+            normals[i + 2] += n.z;
+        }
+    }
+
+    for n in normals.chunks_mut(3) {
+        let v = Vector3f::new(n[0], n[1], n[2]);
+        let v = if v.magnitude2() > 0.0 {
+            v.normalize()
+        } else {
+            Vector3f::unit_y()
+        };
+        n.copy_from_slice(&[v.x, v.y, v.z]);
+    }
+
+    normals
+}
+
+struct GltfContext<'a, A: 'a> {
+    asys: &'a A,
+    json: &'a Value,
+    buffers: Vec<Vec<u8>>,
+    parent: &'a str,
+    textures: Vec<Option<Rc<Texture>>>,
+}
+
+impl<'a, A> GltfContext<'a, A>
+where
+    A: AssetSystem + Clone + 'static,
+{
+    /// The components of accessor `index` as floats, with the number of
+    /// components per element
+    fn accessor(&self, index: usize) -> Result<(Vec<f32>, usize), String> {
+        let acc = &self.json["accessors"][index];
+        if acc.is_null() {
+            return Err(format!("No accessor {}", index));
+        }
+
+        if !acc["sparse"].is_null() {
+            return Err("Unsupported sparse accessor".to_owned());
+        }
+
+        let count = index_field(&acc["count"], "An accessor without count")?;
+        let components = match acc["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            Some("MAT4") => 16,
+            t => return Err(format!("Unsupported accessor type {:?}", t)),
+        };
+
+        let normalized = acc["normalized"].as_bool().unwrap_or(false);
+        let component_type = acc["componentType"].as_u64();
+        let (size, scale, read): (usize, f32, fn(&[u8]) -> f32) = match component_type {
+            Some(5120) => (1, 1.0 / 127.0, |b| f32::from(b[0] as i8)),
+            Some(5121) => (1, 1.0 / 255.0, |b| f32::from(b[0])),
+            Some(5122) => (2, 1.0 / 32767.0, |b| {
+                f32::from((u16::from(b[0]) | u16::from(b[1]) << 8) as i16)
+            }),
+            Some(5123) => (2, 1.0 / 65535.0, |b| {
+                f32::from(u16::from(b[0]) | u16::from(b[1]) << 8)
+            }),
+            Some(5125) => (4, 1.0, |b| le_u32(b, 0).unwrap() as f32),
+            Some(5126) => (4, 1.0, |b| f32::from_bits(le_u32(b, 0).unwrap())),
+            _ => {
+                return Err(format!(
+                    "Unsupported accessor component type {:?}",
+                    component_type
+                ))
+            }
+        };
+        let scale = if normalized { scale } else { 1.0 };
+
+        let view_index = match index_field(&acc["bufferView"], "") {
+            Ok(view) => view,
+            // Without buffer view, all zeros
+            Err(_) => {
+                return match count.checked_mul(components) {
+                    Some(n) if n <= MAX_ZERO_ACCESSOR_VALUES => Ok((vec![0.0; n], components)),
+                    _ => Err(format!("An accessor of {} zeros", count)),
+                }
+            }
+        };
+
+        let view = &self.json["bufferViews"][view_index];
+        let buffer = index(&view["buffer"])
+            .and_then(|b| self.buffers.get(b))
+            .ok_or_else(|| format!("No buffer for the buffer view {}", view_index))?;
+
+        let view_offset = index(&view["byteOffset"]).unwrap_or(0);
+        let view_length = index_field(&view["byteLength"], "A buffer view without byteLength")?;
+        let acc_offset = index(&acc["byteOffset"]).unwrap_or(0);
+        let element = size * components;
+        let stride = index(&view["byteStride"]).unwrap_or(element);
+        if stride < element {
+            return Err(format!("A buffer view stride of {} bytes", stride));
+        }
+
+        // The last element ends in the view, and the view in its buffer, which
+        // bounds `count` by the size of the file
+        let accessor_end = match count.checked_sub(1) {
+            Some(last) => last.checked_mul(stride)
+                .and_then(|n| n.checked_add(acc_offset))
+                .and_then(|n| n.checked_add(element)),
+            None => Some(0),
+        };
+        let view_end = view_offset.checked_add(view_length);
+        match (accessor_end, view_end) {
+            (Some(a), Some(v)) if a <= view_length && v <= buffer.len() => (),
+            _ => return Err("An accessor outside of its buffer view".to_owned()),
+        }
+
+        let offset = view_offset + acc_offset;
+
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            for c in 0..components {
+                let start = offset + i * stride + c * size;
+                let bytes = buffer
+                    .get(start..start + size)
+                    .ok_or("An accessor outside of its buffer")?;
+                values.push(read(bytes) * scale);
+            }
+        }
+
+        Ok((values, components))
+    }
+
+    fn attribute(
+        &self,
+        attributes: &Value,
+        name: &str,
+        components: usize,
+    ) -> Result<Option<Vec<f32>>, String> {
+        let index = match index(&attributes[name]) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let (values, n) = self.accessor(index)?;
+        if n != components {
+            return Err(format!("{} with {} components", name, n));
+        }
+
+        Ok(Some(values))
+    }
+
+    fn primitive(&self, v: &Value) -> Result<MeshData, String> {
+        if v["mode"].as_u64().unwrap_or(4) != 4 {
+            return Err("Unsupported primitive mode, only support triangles".to_owned());
+        }
+
+        let attributes = &v["attributes"];
+        let vertices = self.attribute(attributes, "POSITION", 3)?
+            .ok_or("A primitive without POSITION")?;
+        let count = vertices.len() / 3;
+
+        if count > 65536 {
+            return Err(format!(
+                "Unsupported primitive of {} vertices, indices are u16",
+                count
+            ));
+        }
+
+        let indices: Vec<u16> = match index(&v["indices"]) {
+            Some(i) => {
+                let (values, _) = self.accessor(i)?;
+                let mut indices = Vec::with_capacity(values.len());
+                for i in values {
+                    if i as usize >= count {
+                        return Err("An index outside of the vertices".to_owned());
+                    }
+                    indices.push(i as u16);
+                }
+                indices
+            }
+            None => (0..count).map(|i| i as u16).collect(),
+        };
+
+        let normals = match self.attribute(attributes, "NORMAL", 3)? {
+            Some(normals) => normals,
+            None => smooth_normals(&vertices, &indices),
+        };
+
+        Ok(MeshData {
+            uvs: self.attribute(attributes, "TEXCOORD_0", 2)?,
+            normals: Some(normals),
+            tangents: None,
+            bitangents: None,
+            vertices,
+            indices,
+        })
+    }
+
+    /// The bytes of buffer view `index`
+    fn buffer_view(&self, view_index: usize) -> Result<Vec<u8>, String> {
+        let view = &self.json["bufferViews"][view_index];
+        let offset = index(&view["byteOffset"]).unwrap_or(0);
+        let len = index_field(&view["byteLength"], "A buffer view without byteLength")?;
+
+        index(&view["buffer"])
+            .and_then(|b| self.buffers.get(b))
+            .and_then(|b| b.get(offset..offset + len))
+            .map(|b| b.to_vec())
+            .ok_or_else(|| format!("Invalid buffer view {}", view_index))
+    }
+
+    fn texture(&self, v: &Value) -> Result<Rc<Texture>, String> {
+        let image = index(&v["source"])
+            .map(|i| &self.json["images"][i])
+            .ok_or("A texture without source")?;
+
+        let texture = match image["uri"].as_str() {
+            Some(uri) if !is_data_uri(uri) => {
+                self.asys.new_texture(&(self.parent.to_owned() + uri))
+            }
+            uri => {
+                let data = match uri {
+                    Some(uri) => decode_data_uri(uri).ok_or("Invalid image data uri")?,
+                    None => self.buffer_view(
+                        index(&image["bufferView"]).ok_or("An image without data")?,
+                    )?,
+                };
+
+                let file: Box<File> = Box::new(MemoryFile {
+                    name: format!("{}(embedded image)", self.parent),
+                    data,
+                });
+                let file: FileFuture = Box::new(future::ok(file));
+
+                let image = Texture::load_resource::<TextureImage, A>(self.asys.clone(), file);
+                Texture::new(TextureAsset::Single(image))
+            }
+        };
+
+        let sampler = match index(&v["sampler"]) {
+            Some(i) => texture_sampler(&self.json["samplers"][i]),
+            None => texture_sampler(&Value::Null),
+        };
+        texture.set_sampler(sampler);
+
+        Ok(texture)
+    }
+
+    /// The texture of the textureInfo `v`, if any
+    fn texture_info(&self, v: &Value) -> Option<Rc<Texture>> {
+        index(&v["index"])
+            .and_then(|i| self.textures.get(i))
+            .and_then(|t| t.clone())
+    }
+
+    /// The PBR material of `v`, or on WebGL1 the phong one of its base color
+    /// texture, see `Material::new_phong`
+    fn material(&self, v: &Value) -> Material {
+        let float = |v: &Value, default: f32| v.as_f64().map_or(default, |f| f as f32);
+        let mut material = if self.asys.capabilities().webgl2 {
+            self.pbr_material(v)
+        } else {
+            let albedo = self.texture_info(&v["pbrMetallicRoughness"]["baseColorTexture"]);
+            Material::new_phong(
+                self.asys,
+                albedo.unwrap_or_else(|| self.asys.new_texture("default_white")),
+            )
+        };
+
+        match v["alphaMode"].as_str() {
+            Some("MASK") => material.set_cutout(float(&v["alphaCutoff"], 0.5)),
+            Some("BLEND") => {
+                material.render_queue = RenderQueue::Transparent;
+                material.states.depth_write = Some(false);
+                material.states.alpha_blending = Some(true);
+                material.states.blend = Some(Blend::default());
+            }
+            _ => {}
+        }
+
+        if v["doubleSided"].as_bool().unwrap_or(false) {
+            material.states.cull = Some(CullMode::Off);
+        }
+
+        material
+    }
+
+    fn pbr_material(&self, v: &Value) -> Material {
+        let material = Material::new_pbr(self.asys);
+        let pbr = &v["pbrMetallicRoughness"];
+        let float = |v: &Value, default: f32| v.as_f64().map_or(default, |f| f as f32);
+
+        if let Some(c) = floats(&pbr["baseColorFactor"], 4) {
+            material.set("uMaterial.albedo", Vector4::new(c[0], c[1], c[2], c[3]));
+        }
+        if let Some(t) = self.texture_info(&pbr["baseColorTexture"]) {
+            material.set("uMaterial.albedo_map", t);
+        }
+
+        material.set("uMaterial.metallic", float(&pbr["metallicFactor"], 1.0));
+        material.set("uMaterial.roughness", float(&pbr["roughnessFactor"], 1.0));
+        if let Some(t) = self.texture_info(&pbr["metallicRoughnessTexture"]) {
+            material.set("uMaterial.metallic_roughness_map", t);
+        }
+
+        let normal = &v["normalTexture"];
+        if let Some(t) = self.texture_info(normal) {
+            material.set("uMaterial.normal_map", t);
+            material.set("uMaterial.normal_scale", float(&normal["scale"], 1.0));
+        }
+
+        let occlusion = &v["occlusionTexture"];
+        if let Some(t) = self.texture_info(occlusion) {
+            material.set("uMaterial.occlusion_map", t);
+            material.set(
+                "uMaterial.occlusion_strength",
+                float(&occlusion["strength"], 1.0),
+            );
+        }
+
+        if let Some(c) = floats(&v["emissiveFactor"], 3) {
+            material.set("uMaterial.emissive", Vector3f::new(c[0], c[1], c[2]));
+        }
+        if let Some(t) = self.texture_info(&v["emissiveTexture"]) {
+            material.set("uMaterial.emissive_map", t);
+        }
+
+        material
+    }
+}
+
+fn index_field(v: &Value, reason: &str) -> Result<usize, String> {
+    index(v).ok_or_else(|| reason.to_owned())
+}
+
+fn array(json: &Value, name: &str) -> Vec<Value> {
+    json[name].as_array().cloned().unwrap_or_default()
+}
+
+impl GltfLoader {
+    fn load_scene<A>(
+        asys: &A,
+        document: GltfDocument,
+        external: Vec<Vec<u8>>,
+    ) -> Result<GltfScene, AssetError>
+    where
+        A: AssetSystem + Clone + 'static,
+    {
+        let name = document.name.clone();
+        GltfLoader::scene(asys, document, external).map_err(|reason| invalid(&name, 0, reason))
+    }
+
+    fn scene<A>(
+        asys: &A,
+        document: GltfDocument,
+        external: Vec<Vec<u8>>,
+    ) -> Result<GltfScene, String>
+    where
+        A: AssetSystem + Clone + 'static,
+    {
+        let GltfDocument {
+            json,
+            bin,
+            parent,
+            ..
+        } = document;
+        let json = &json;
+        if !json["extensionsRequired"].is_null() {
+            return Err(format!(
+                "Unsupported required extensions {}",
+                json["extensionsRequired"]
+            ));
+        }
+
+        // The buffers in order, the external ones are loaded in `external` order
+        let mut external = external.into_iter();
+        let mut bin = bin;
+        let mut buffers = Vec::new();
+        for b in array(json, "buffers") {
+            buffers.push(match b["uri"].as_str() {
+                Some(uri) if is_data_uri(uri) => {
+                    decode_data_uri(uri).ok_or("Invalid buffer data uri")?
+                }
+                Some(_) => external.next().ok_or("Missing external buffer")?,
+                None => bin.take().ok_or("A buffer without uri")?,
+            });
+        }
+
+        let mut ctx = GltfContext {
+            asys,
+            json,
+            buffers,
+            parent: &parent,
+            textures: Vec::new(),
+        };
+
+        // An invalid texture only leaves its material slots to the default
+        ctx.textures = array(json, "textures")
+            .iter()
+            .map(|t| ctx.texture(t).ok())
+            .collect();
+
+        let materials: Vec<Rc<Material>> = array(json, "materials")
+            .iter()
+            .map(|m| Rc::new(ctx.material(m)))
+            .collect();
+        let default_material = Rc::new(Material::new_pbr(asys));
+
+        let mut meshes = Vec::new();
+        for m in array(json, "meshes") {
+            let mut mesh = Mesh::new();
+
+            for p in array(&m, "primitives") {
+                let data = ctx.primitive(&p)?;
+                let material = index(&p["material"])
+                    .and_then(|i| materials.get(i))
+                    .unwrap_or(&default_material);
+
+                mesh.add_surface(
+                    MeshBuffer::new_from_resource(Resource::new(data)),
+                    material.clone(),
+                );
+            }
+
+            meshes.push(mesh);
+        }
+
+        let cameras = array(json, "cameras")
+            .iter()
+            .map(camera)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let nodes: Vec<GltfNode> = array(json, "nodes")
+            .iter()
+            .map(|n| {
+                let (transform, scale) = node_transform(n);
+                GltfNode {
+                    name: n["name"].as_str().map(|s| s.to_owned()),
+                    transform,
+                    scale,
+                    mesh: index(&n["mesh"]),
+                    camera: index(&n["camera"]),
+                    children: array(n, "children").iter().filter_map(index).collect(),
+                }
+            })
+            .collect();
+
+        // The default scene, or the first one
+        let scene = index(&json["scene"]).unwrap_or(0);
+        let roots: Vec<usize> = array(&json["scenes"][scene], "nodes")
+            .iter()
+            .filter_map(index)
+            .collect();
+
+        // Instantiating walks the nodes recursively, they have to be a forest
+        let mut visited = vec![false; nodes.len()];
+        let mut stack = roots.clone();
+        while let Some(i) = stack.pop() {
+            match visited.get_mut(i) {
+                Some(v) if !*v => *v = true,
+                _ => return Err(format!("Node {} is not in a tree", i)),
+            }
+            stack.extend_from_slice(&nodes[i].children);
+        }
+
+        Ok(GltfScene {
+            meshes,
+            cameras,
+            nodes,
+            roots,
+        })
+    }
+}
@@ -1,5 +1,7 @@
 use engine::asset::loader::{Loadable, Loader};
 use engine::asset::{AssetError, AssetResult, AssetSystem, File, FileFuture};
+#[cfg(not(target_arch = "wasm32"))]
+use engine::asset::worker;
 use engine::TextureImage;
 use image::png;
 use image::tga;
@@ -13,7 +15,10 @@ use std::fmt::Debug;
 use uni_app;
 use std::path::Path;
 
-use super::dds::{DDSFormat, DDSReader};
+use super::dds::{DDSFormat, DDSReader, DDS};
+use super::ktx::{KTXReader, KTX_MAGIC_BYTES};
+#[cfg(target_arch = "wasm32")]
+use super::web_image;
 
 pub struct ImageLoader {}
 
@@ -239,9 +244,14 @@ where
         });
 
         Ok(buff_future.map(move |buf| match ctx.color {
-            image::ColorType::RGBA(8) | image::ColorType::RGB(8) | image::ColorType::Gray(8) => {
-                Ok((buf, ctx))
-            }
+            image::ColorType::RGBA(8)
+            | image::ColorType::RGB(8)
+            | image::ColorType::Gray(8)
+            | image::ColorType::GrayA(8)
+            | image::ColorType::RGBA(16)
+            | image::ColorType::RGB(16)
+            | image::ColorType::Gray(16)
+            | image::ColorType::GrayA(16) => Ok((buf, ctx)),
             _ => Err(make_invalid_format(
                 &ctx.info,
                 image::ImageError::UnsupportedColor(ctx.color),
@@ -264,6 +274,20 @@ where
         let decoded_len = decoded.len();
 
         let img = match ctx.color {
+            image::ColorType::Gray(16) => {
+                image::ImageBuffer::from_raw(ctx.w, ctx.h, big_endian_u16(&decoded))
+                    .map(TextureImage::Luma16)
+            }
+            image::ColorType::RGBA(16)
+            | image::ColorType::RGB(16)
+            | image::ColorType::GrayA(16) => {
+                let rgba = to_rgba(&big_endian_u16(&decoded), ctx.color, 65535);
+                image::ImageBuffer::from_raw(ctx.w, ctx.h, rgba).map(TextureImage::Rgba16)
+            }
+            image::ColorType::GrayA(_) => {
+                image::ImageBuffer::from_raw(ctx.w, ctx.h, to_rgba(&decoded, ctx.color, 255))
+                    .map(TextureImage::Rgba)
+            }
             image::ColorType::RGBA(_) => {
                 image::ImageBuffer::from_raw(ctx.w, ctx.h, decoded).map(TextureImage::Rgba)
             }
@@ -297,22 +321,95 @@ where
     Box::new(img)
 }
 
+/// The samples of a 16 bits PNG, stored big endian
+fn big_endian_u16(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks(2)
+        .map(|b| u16::from(b[0]) << 8 | u16::from(*b.get(1).unwrap_or(&0)))
+        .collect()
+}
+
+/// Expand the RGB and gray alpha samples to RGBA, `max` being the opaque alpha
+fn to_rgba<T: Copy>(samples: &[T], color: image::ColorType, max: T) -> Vec<T> {
+    match color {
+        image::ColorType::RGB(_) => samples
+            .chunks(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], max])
+            .collect(),
+        image::ColorType::GrayA(_) => samples
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        _ => samples.to_vec(),
+    }
+}
+
 static DDS_MAGIC_BYTES: &'static [u8] = b"DDS ";
+static HDR_MAGIC_BYTES: &'static [&'static [u8]] = &[b"#?RADIANCE", b"#?RGBE"];
+#[cfg(target_arch = "wasm32")]
+static PNG_MAGIC_BYTES: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+/// Smaller images decode quickly enough without the Web Worker round trip
+#[cfg(target_arch = "wasm32")]
+const WEB_DECODE_MIN_BYTES: usize = 256 * 1024;
+
+fn load_future_hdr<T>(img_buf: T) -> Box<Future<Item = TextureImage, Error = AssetError>>
+where
+    T: Future<Item = (Vec<u8>, String), Error = AssetError> + 'static,
+{
+    let img = img_buf.and_then(|(whole_buf, file_name)| {
+        let info = ImageFileInfo {
+            file_name: file_name,
+            orig_len: whole_buf.len(),
+        };
+
+        let decoder = image::hdr::HDRDecoder::new(io::Cursor::new(whole_buf))
+            .map_err(|e| make_invalid_format(&info, e))?;
+        let meta = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()
+            .map_err(|e| make_invalid_format(&info, e))?;
+
+        let data: Vec<f32> = pixels.iter().flat_map(|p| p.data.iter().cloned()).collect();
+
+        image::ImageBuffer::from_raw(meta.width, meta.height, data)
+            .map(TextureImage::RgbF32)
+            .ok_or_else(|| make_invalid_format(&info, "Truncated hdr image"))
+    });
+
+    Box::new(img)
+}
 
 fn load_future_dds<T>(img_buf: T) -> Box<Future<Item = TextureImage, Error = AssetError>>
 where
     T: Future<Item = (Vec<u8>, String), Error = AssetError> + 'static,
 {
     let img = img_buf.and_then(|(whole_buf, file_name)| {
-        DDSReader::read(whole_buf, &file_name).map(|dds| match dds.format {
-            DDSFormat::DXT1 => TextureImage::DXT1(dds),
-            DDSFormat::DXT5 => TextureImage::DXT5(dds),
-        })
+        DDSReader::read(whole_buf, &file_name).map(compressed_image)
     });
 
     Box::new(img)
 }
 
+fn load_future_ktx<T>(img_buf: T) -> Box<Future<Item = TextureImage, Error = AssetError>>
+where
+    T: Future<Item = (Vec<u8>, String), Error = AssetError> + 'static,
+{
+    let img = img_buf.and_then(|(whole_buf, file_name)| {
+        KTXReader::read(whole_buf, &file_name).map(compressed_image)
+    });
+
+    Box::new(img)
+}
+
+fn compressed_image(dds: DDS) -> TextureImage {
+    match dds.format {
+        DDSFormat::DXT1 => TextureImage::DXT1(dds),
+        DDSFormat::DXT5 => TextureImage::DXT5(dds),
+        DDSFormat::ETC2(_) => TextureImage::ETC2(dds),
+        DDSFormat::ASTC(_) => TextureImage::ASTC(dds),
+    }
+}
+
 impl Loadable for TextureImage {
     type Loader = ImageLoader;
 
@@ -331,11 +428,65 @@ impl Loadable for TextureImage {
         });
 
         Box::new(img_buf.and_then(|(whole_buf, file_name)| {
-            if whole_buf.starts_with(DDS_MAGIC_BYTES) {
-                return load_future_dds(future::result(Ok((whole_buf, file_name))));
-            }
-
-            load_future_uncompressed(future::result(Ok((whole_buf, file_name))))
+            decode_off_main_thread(whole_buf, file_name)
         }))
     }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn decode_off_main_thread(
+    whole_buf: Vec<u8>,
+    file_name: String,
+) -> Box<Future<Item = TextureImage, Error = AssetError>> {
+    let path = file_name.clone();
+    let len = whole_buf.len();
+
+    let decoded = worker::spawn(move || worker::block_on(decode_future(whole_buf, file_name)));
+
+    Box::new(decoded.then(move |r| match r {
+        Ok(img) => img,
+        Err(reason) => Err(AssetError::InvalidFormat { path, len, reason }),
+    }))
+}
+
+/// The large 8 bits PNG files are decoded by the browser in a Web Worker. The
+/// other ones are decoded here, spread over the frames.
+#[cfg(target_arch = "wasm32")]
+fn decode_off_main_thread(
+    whole_buf: Vec<u8>,
+    file_name: String,
+) -> Box<Future<Item = TextureImage, Error = AssetError>> {
+    if whole_buf.len() >= WEB_DECODE_MIN_BYTES && is_8bits_png(&whole_buf)
+        && web_image::is_supported()
+    {
+        return Box::new(web_image::decode(&whole_buf, file_name));
+    }
+
+    decode_future(whole_buf, file_name)
+}
+
+/// The browser would reduce the 16 bits PNG files to 8 bits
+#[cfg(target_arch = "wasm32")]
+fn is_8bits_png(buf: &[u8]) -> bool {
+    // The bit depth follows the width and height in the IHDR chunk
+    buf.starts_with(PNG_MAGIC_BYTES) && buf.len() > 24 && buf[24] <= 8
+}
+
+fn decode_future(
+    whole_buf: Vec<u8>,
+    file_name: String,
+) -> Box<Future<Item = TextureImage, Error = AssetError>> {
+    if whole_buf.starts_with(DDS_MAGIC_BYTES) {
+        return load_future_dds(future::result(Ok((whole_buf, file_name))));
+    }
+
+    if whole_buf.starts_with(KTX_MAGIC_BYTES) {
+        return load_future_ktx(future::result(Ok((whole_buf, file_name))));
+    }
+
+    if HDR_MAGIC_BYTES.iter().any(|magic| whole_buf.starts_with(magic)) {
+        return load_future_hdr(future::result(Ok((whole_buf, file_name))));
+    }
+
+    load_future_uncompressed(future::result(Ok((whole_buf, file_name))))
+}
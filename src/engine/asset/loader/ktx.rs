@@ -0,0 +1,289 @@
+use engine::asset::{AssetError, AssetResult};
+
+use super::dds::{DDSFormat, DDSImage, DDS};
+
+/// Reads the compressed mip chain of a KTX 1.1 file into a `DDS`, DXT1, DXT5,
+/// ETC2 or ASTC. Each format fails to upload on the contexts without its
+/// extension, see `Capabilities::s3tc`, `etc2` and `astc`.
+pub struct KTXReader {}
+
+pub static KTX_MAGIC_BYTES: &'static [u8] = &[
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A
+];
+
+const KTX_ENDIANNESS: u32 = 0x0403_0201;
+/// Identifier, then 13 u32 fields
+const KTX_HEADER_SIZE: usize = 12 + 13 * 4;
+
+const COMPRESSED_RGB_S3TC_DXT1: u32 = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: u32 = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT5: u32 = 0x83F3;
+/// From COMPRESSED_R11_EAC to COMPRESSED_SRGB8_ALPHA8_ETC2_EAC
+const ETC2_FORMATS: (u32, u32) = (0x9270, 0x9279);
+const COMPRESSED_SRGB8_ETC2: u32 = 0x9275;
+const COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2: u32 = 0x9277;
+const COMPRESSED_SRGB8_ALPHA8_ETC2_EAC: u32 = 0x9279;
+/// From COMPRESSED_RGBA_ASTC_4x4 to COMPRESSED_RGBA_ASTC_12x12
+const ASTC_FORMATS: (u32, u32) = (0x93B0, 0x93BD);
+/// From COMPRESSED_SRGB8_ALPHA8_ASTC_4x4 to COMPRESSED_SRGB8_ALPHA8_ASTC_12x12
+const ASTC_SRGB_FORMATS: (u32, u32) = (0x93D0, 0x93DD);
+/// The block sizes of the ASTC formats, in their order
+const ASTC_BLOCKS: [(u32, u32); 14] = [
+    (4, 4),
+    (5, 4),
+    (5, 5),
+    (6, 5),
+    (6, 6),
+    (8, 5),
+    (8, 6),
+    (8, 8),
+    (10, 5),
+    (10, 6),
+    (10, 8),
+    (10, 10),
+    (12, 10),
+    (12, 12),
+];
+
+fn within(internal_format: u32, (first, last): (u32, u32)) -> bool {
+    internal_format >= first && internal_format <= last
+}
+
+/// The format, whether it has alpha, the block size in texels and the bytes
+/// of a block of `internal_format`. The sRGB formats are read as their linear
+/// one, the shaders decode the sRGB textures, see `Texture::set_srgb`.
+fn block_format(internal_format: u32) -> Option<(DDSFormat, bool, (u32, u32), usize)> {
+    let format = match internal_format {
+        COMPRESSED_RGB_S3TC_DXT1 => (DDSFormat::DXT1, false, (4, 4), 8),
+        COMPRESSED_RGBA_S3TC_DXT1 => (DDSFormat::DXT1, true, (4, 4), 8),
+        COMPRESSED_RGBA_S3TC_DXT5 => (DDSFormat::DXT5, true, (4, 4), 16),
+        COMPRESSED_SRGB8_ETC2
+        | COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2
+        | COMPRESSED_SRGB8_ALPHA8_ETC2_EAC => return block_format(internal_format - 1),
+        f if within(f, ETC2_FORMATS) => {
+            // R11, signed R11, RG11, signed RG11, RGB8, RGB8 punchthrough, RGBA8
+            let index = f - ETC2_FORMATS.0;
+            let has_alpha = index >= 6;
+            let block_bytes = match index {
+                2 | 3 | 8 => 16,
+                _ => 8,
+            };
+            (DDSFormat::ETC2(f), has_alpha, (4, 4), block_bytes)
+        }
+        f if within(f, ASTC_SRGB_FORMATS) => {
+            return block_format(f - ASTC_SRGB_FORMATS.0 + ASTC_FORMATS.0)
+        }
+        f if within(f, ASTC_FORMATS) => {
+            let block = ASTC_BLOCKS[(f - ASTC_FORMATS.0) as usize];
+            (DDSFormat::ASTC(f), true, block, 16)
+        }
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+/// The bytes of a level of `size`, the partial blocks at the edges are whole
+fn level_bytes((w, h): (u32, u32), (bw, bh): (u32, u32), block_bytes: usize) -> Option<usize> {
+    let blocks = |texels: u32, block: u32| {
+        let partial = if texels % block == 0 { 0 } else { 1 };
+        (texels / block + partial) as usize
+    };
+
+    blocks(w, bw)
+        .checked_mul(blocks(h, bh))?
+        .checked_mul(block_bytes)
+}
+
+fn invalid(buff: &[u8], file_name: &String, reason: String) -> AssetError {
+    AssetError::InvalidFormat {
+        len: buff.len(),
+        path: file_name.clone(),
+        reason,
+    }
+}
+
+impl KTXReader {
+    pub fn read(buff: Vec<u8>, file_name: &String) -> AssetResult<DDS> {
+        if buff.len() < KTX_HEADER_SIZE || !buff.starts_with(KTX_MAGIC_BYTES) {
+            return Err(invalid(&buff, file_name, "Invalid KTX Header".to_owned()));
+        }
+
+        let le = |offset: usize| {
+            u32::from(buff[offset]) | u32::from(buff[offset + 1]) << 8
+                | u32::from(buff[offset + 2]) << 16 | u32::from(buff[offset + 3]) << 24
+        };
+
+        // The file is in the endianness of the machine which wrote it
+        let swap = le(12) != KTX_ENDIANNESS;
+        let read_u32 = |offset: usize| {
+            if swap {
+                le(offset).swap_bytes()
+            } else {
+                le(offset)
+            }
+        };
+        let field = |i: usize| read_u32(12 + i * 4);
+
+        let internal_format = field(4);
+        let width = field(6);
+        let height = field(7);
+        let depth = field(8);
+        let array_elements = field(9);
+        let faces = field(10);
+        let mipmap_count = field(11).max(1);
+        let key_value_bytes = field(12) as usize;
+
+        let (format, has_alpha, block, block_bytes) = match block_format(internal_format) {
+            Some(format) => format,
+            None => {
+                return Err(invalid(
+                    &buff,
+                    file_name,
+                    format!(
+                        "Unsupported Format, only support DXT1, DXT5, ETC2 and ASTC \
+                         (current: 0x{:X})",
+                        internal_format
+                    ),
+                ))
+            }
+        };
+
+        if width == 0 || height == 0 {
+            return Err(invalid(&buff, file_name, "Empty KTX texture".to_owned()));
+        }
+
+        if depth > 1 || array_elements > 1 || faces > 1 {
+            return Err(invalid(
+                &buff,
+                file_name,
+                "Unsupported KTX, only 2D textures without array or cube faces".to_owned(),
+            ));
+        }
+
+        let mut width = width;
+        let mut height = height;
+        let mut data_offset = KTX_HEADER_SIZE.saturating_add(key_value_bytes);
+
+        let mut images = Vec::new();
+
+        for _ in 0..mipmap_count {
+            let truncated = || invalid(&buff, file_name, "Truncated KTX mipmap".to_owned());
+
+            if data_offset.checked_add(4).map_or(true, |end| end > buff.len()) {
+                return Err(truncated());
+            }
+
+            let image_size = read_u32(data_offset) as usize;
+            data_offset += 4;
+
+            let expected = match level_bytes((width, height), block, block_bytes) {
+                Some(expected) if expected <= image_size => expected,
+                _ => return Err(truncated()),
+            };
+
+            if buff.len() - data_offset < image_size {
+                return Err(truncated());
+            }
+
+            images.push(DDSImage {
+                width,
+                height,
+                data: buff[data_offset..data_offset + expected].to_vec(),
+            });
+
+            // Each level is padded to 4 bytes
+            data_offset += (image_size + 3) & !3;
+            width = 1.max(width >> 1);
+            height = 1.max(height >> 1);
+        }
+
+        Ok(DDS {
+            format,
+            images,
+            has_alpha,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A KTX file of `internal_format` with a level of each of `levels` bytes
+    fn ktx(internal_format: u32, (width, height): (u32, u32), levels: &[usize]) -> Vec<u8> {
+        let fields = [
+            KTX_ENDIANNESS,
+            0,
+            1,
+            0,
+            internal_format,
+            0,
+            width,
+            height,
+            0,
+            0,
+            1,
+            levels.len() as u32,
+            0,
+        ];
+
+        let mut buff = KTX_MAGIC_BYTES.to_vec();
+        for field in fields.iter() {
+            buff.extend_from_slice(&field.to_le_bytes());
+        }
+        for &size in levels.iter() {
+            buff.extend_from_slice(&(size as u32).to_le_bytes());
+            buff.extend(vec![0; (size + 3) & !3]);
+        }
+        buff
+    }
+
+    fn read(buff: Vec<u8>) -> AssetResult<DDS> {
+        KTXReader::read(buff, &"test.ktx".to_owned())
+    }
+
+    #[test]
+    fn partial_blocks() {
+        // 12x12, 6x6 and 3x3 levels, the last two have partial blocks
+        let dds = read(ktx(COMPRESSED_RGBA_S3TC_DXT5, (12, 12), &[144, 64, 16])).unwrap();
+        let sizes: Vec<_> = dds.images.iter().map(|i| (i.width, i.data.len())).collect();
+        assert_eq!(sizes, vec![(12, 144), (6, 64), (3, 16)]);
+
+        // 8 bytes blocks
+        let dds = read(ktx(COMPRESSED_RGB_S3TC_DXT1, (6, 6), &[32])).unwrap();
+        assert_eq!(dds.images[0].data.len(), 32);
+        assert!(read(ktx(COMPRESSED_RGB_S3TC_DXT1, (6, 6), &[8])).is_err());
+    }
+
+    #[test]
+    fn etc2_and_astc() {
+        let dds = read(ktx(0x9278, (8, 8), &[64])).unwrap();
+        match dds.format {
+            DDSFormat::ETC2(0x9278) => (),
+            ref f => panic!("{:?}", f),
+        }
+
+        // The sRGB 6x6 blocks are read as the linear ones
+        let dds = read(ktx(0x93D4, (12, 12), &[64])).unwrap();
+        match dds.format {
+            DDSFormat::ASTC(0x93B4) => (),
+            ref f => panic!("{:?}", f),
+        }
+        assert_eq!(dds.images[0].data.len(), 64);
+
+        assert!(read(ktx(0x93C0, (4, 4), &[16])).is_err());
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let huge = (u32::max_value(), u32::max_value());
+        assert!(read(ktx(COMPRESSED_RGBA_S3TC_DXT5, huge, &[16])).is_err());
+        assert!(read(ktx(COMPRESSED_RGBA_S3TC_DXT5, (0, 4), &[16])).is_err());
+
+        // The file ends in the level
+        let mut buff = ktx(COMPRESSED_RGBA_S3TC_DXT5, (4, 4), &[16]);
+        buff.truncate(buff.len() - 1);
+        assert!(read(buff).is_err());
+    }
+}
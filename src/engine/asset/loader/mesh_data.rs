@@ -1,7 +1,8 @@
 use engine::asset::loader::{Loadable, Loader};
-use engine::asset::{AssetError, AssetResult, File};
+use engine::asset::{worker, AssetError, AssetResult, AssetSystem, File, FileFuture};
 use engine::render::MeshData;
 
+use futures::prelude::*;
 use obj;
 use obj::SimplePolygon;
 use std::io::BufReader;
@@ -11,52 +12,82 @@ impl Loader<MeshData> for MeshDataLoader {
     fn load<A>(_asys: A, mut file: Box<File>) -> AssetResult<MeshData> {
         let bytes = file.read_binary()
             .map_err(|_| AssetError::ReadBufferFail(file.name()))?;
-        let mut r = BufReader::new(bytes.as_slice());
-
-        let m = obj::Obj::<SimplePolygon>::load_buf(&mut r);
-        let model = m.unwrap();
-
-        let vertices: Vec<f32> = model
-            .position
-            .into_iter()
-            .flat_map(|s| s.to_vec().into_iter())
-            .collect();
-
-        let uvs = model
-            .texture
-            .into_iter()
-            .flat_map(|s| s.to_vec().into_iter())
-            .collect();
-
-        let normals = model
-            .normal
-            .into_iter()
-            .flat_map(|s| s.to_vec().into_iter())
-            .collect();
-
-        let mut indices: Vec<u16> = Vec::new();
-
-        for o in model.objects {
-            for g in o.groups {
-                for poly in g.polys {
-                    for index_tuple in poly {
-                        indices.push(index_tuple.0 as u16);
-                    }
+        Ok(parse_obj(&bytes))
+    }
+}
+
+fn parse_obj(bytes: &[u8]) -> MeshData {
+    let mut r = BufReader::new(bytes);
+
+    let m = obj::Obj::<SimplePolygon>::load_buf(&mut r);
+    let model = m.unwrap();
+
+    let vertices: Vec<f32> = model
+        .position
+        .into_iter()
+        .flat_map(|s| s.to_vec().into_iter())
+        .collect();
+
+    let uvs = model
+        .texture
+        .into_iter()
+        .flat_map(|s| s.to_vec().into_iter())
+        .collect();
+
+    let normals = model
+        .normal
+        .into_iter()
+        .flat_map(|s| s.to_vec().into_iter())
+        .collect();
+
+    let mut indices: Vec<u16> = Vec::new();
+
+    for o in model.objects {
+        for g in o.groups {
+            for poly in g.polys {
+                for index_tuple in poly {
+                    indices.push(index_tuple.0 as u16);
                 }
             }
         }
+    }
 
-        Ok(MeshData {
-            indices,
-            vertices,
-            uvs: Some(uvs),
-            normals: Some(normals),
-            tangents: None,
-            bitangents: None,
-        })
+    MeshData {
+        indices,
+        vertices,
+        uvs: Some(uvs),
+        normals: Some(normals),
+        tangents: None,
+        bitangents: None,
     }
 }
 
 impl Loadable for MeshData {
     type Loader = MeshDataLoader;
+
+    fn load_future<A>(asys: A, f0: FileFuture) -> Box<Future<Item = Self, Error = AssetError>>
+    where
+        Self: 'static,
+        A: AssetSystem + Clone + 'static,
+    {
+        if cfg!(target_arch = "wasm32") {
+            return Box::new(f0.then(move |r| {
+                let f = r.map_err(AssetError::FileIoError)?;
+                Self::load(asys, f)
+            }));
+        }
+
+        let bytes = f0.then(|r| {
+            let mut file = r.map_err(AssetError::FileIoError)?;
+            let bytes = file.read_binary()
+                .map_err(|_| AssetError::ReadBufferFail(file.name()))?;
+            Ok((bytes, file.name()))
+        });
+
+        Box::new(bytes.and_then(|(bytes, path)| {
+            let len = bytes.len();
+            worker::spawn(move || parse_obj(&bytes))
+                .map_err(move |reason| AssetError::InvalidFormat { path, len, reason })
+        }))
+    }
 }
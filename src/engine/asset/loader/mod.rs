@@ -3,10 +3,30 @@ mod image;
 mod shader;
 mod mesh_data;
 mod prefab;
+mod gltf;
+mod collada;
 mod dds;
+mod ktx;
+mod atlas;
+mod sdf_font;
+mod ttf_font;
+mod audio_clip;
+mod custom;
+#[cfg(target_arch = "wasm32")]
+mod web_image;
+#[cfg(target_arch = "wasm32")]
+mod web_audio;
 
 pub use self::loader::{Loadable, Loader};
 pub use self::image::ImageLoader;
 pub use self::shader::{ShaderFSLoader, ShaderVSLoader};
 pub use self::prefab::{ObjMaterial, Prefab, PrefabLoader};
-pub use self::dds::DDS;
+pub use self::gltf::{GltfCamera, GltfInstance, GltfLoader, GltfNode, GltfScene};
+pub use self::collada::{ColladaInstance, ColladaLoader, ColladaNode, ColladaScene, ColladaSkin,
+                        SkinWeights};
+pub use self::dds::{DDSFormat, DDS};
+pub use self::atlas::AtlasLoader;
+pub use self::sdf_font::SdfFontLoader;
+pub use self::ttf_font::TtfFontLoader;
+pub use self::audio_clip::AudioClipLoader;
+pub use self::custom::CustomAssetLoader;
@@ -1,5 +1,5 @@
 use engine::asset::{Asset, AssetError, AssetSystem, FileFuture, Resource};
-use engine::render::{Material, Mesh, MeshBuffer, MeshData};
+use engine::render::{Material, Mesh, MeshBuffer, MeshData, RenderQueue, Texture, TextureWrap};
 use std::borrow::Cow;
 use std::path::Path;
 
@@ -17,7 +17,7 @@ pub struct Prefab {
     pub meshes: Vec<Mesh>,
 }
 
-fn parent_path(filename: &str) -> String {
+pub fn parent_path(filename: &str) -> String {
     let path = Path::new(filename);
     let parent = path.parent();
     let mut parent = parent
@@ -33,135 +33,6 @@ fn parent_path(filename: &str) -> String {
 
 pub struct PrefabLoader {}
 
-struct TangentSpace {
-    tangents: Option<Vec<f32>>,
-    bitangents: Option<Vec<f32>>,
-}
-
-#[inline]
-fn from_slice_v3(i: usize, s: &[f32]) -> Vector3f {
-    return Vector3::new(s[i * 3 + 0], s[i * 3 + 1], s[i * 3 + 2]);
-}
-
-#[inline]
-fn from_slice_v2(i: usize, s: &[f32]) -> Vector2f {
-    return Vector2::new(s[i * 2 + 0], s[i * 2 + 1]);
-}
-
-trait CloseToZero {
-    fn close_to_zero(&self) -> bool;
-}
-
-impl CloseToZero for f32 {
-    fn close_to_zero(&self) -> bool {
-        self.abs() < 0.0001
-    }
-}
-
-fn compute_tangents(
-    v_array: &Vec<f32>,
-    uv_array: &Option<Vec<f32>>,
-    n_array: &Option<Vec<f32>>,
-    indices: &Vec<u16>,
-) -> TangentSpace {
-    if uv_array.is_none() || n_array.is_none() {
-        return TangentSpace {
-            tangents: None,
-            bitangents: None,
-        };
-    }
-
-    let uv_array = uv_array.as_ref().unwrap();
-    let n_array = n_array.as_ref().unwrap();
-
-    assert!(v_array.len() % 9 == 0);
-    assert!(uv_array.len() % 6 == 0);
-    assert!(n_array.len() % 9 == 0);
-    assert!(indices.len() % 3 == 0);
-
-    let mut tangents = Vec::with_capacity(v_array.len());
-    let mut bittangents = Vec::with_capacity(v_array.len());
-
-    let mut i = 0;
-    while i < indices.len() {
-        let pos1 = from_slice_v3(i, &v_array);
-        let pos2 = from_slice_v3(i + 1, &v_array);
-        let pos3 = from_slice_v3(i + 2, &v_array);
-
-        let uv1 = from_slice_v2(i, &uv_array);
-        let uv2 = from_slice_v2(i + 1, &uv_array);
-        let uv3 = from_slice_v2(i + 2, &uv_array);
-
-        let edge1 = pos2 - pos1;
-        let edge2 = pos3 - pos1;
-        let delta_uv1 = uv2 - uv1;
-        let delta_uv2 = uv3 - uv1;
-
-        let d = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
-        let tangent: Vector3f;
-        let bitangent: Vector3f;
-
-        if !d.close_to_zero() {
-            let r = 1.0 / d;
-            tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
-            bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
-        } else {
-            // For some ill condition mesh, d would be zero, just fix it
-            if !delta_uv1.x.close_to_zero() {
-                tangent = edge1 / delta_uv1.x;
-            } else if !delta_uv2.x.close_to_zero() {
-                tangent = edge2 / delta_uv2.x;
-            } else {
-                tangent = Vector3::zero();
-            }
-
-            if !delta_uv1.y.close_to_zero() {
-                bitangent = edge1 / delta_uv1.y;
-            } else if !delta_uv2.y.close_to_zero() {
-                bitangent = edge2 / delta_uv2.y;
-            } else {
-                bitangent = Vector3::zero();
-            }
-        }
-
-        // Triangle share same tangent space
-        tangents.extend_from_slice(&tangent[..]);
-        tangents.extend_from_slice(&tangent[..]);
-        tangents.extend_from_slice(&tangent[..]);
-
-        bittangents.extend_from_slice(&bitangent[..]);
-        bittangents.extend_from_slice(&bitangent[..]);
-        bittangents.extend_from_slice(&bitangent[..]);
-
-        i += 3;
-    }
-
-    for i in 0..indices.len() {
-        let n = from_slice_v3(i, &n_array);
-        let mut t = from_slice_v3(i, &tangents);
-        let b = from_slice_v3(i, &bittangents);
-
-        // Gram-Schmidt orthogonalize
-        t = t - n * n.dot(t);
-
-        if n.cross(t).dot(b) < 0.0 {
-            t = -t;
-        }
-
-        tangents[i * 3 + 0] = t.x;
-        tangents[i * 3 + 1] = t.y;
-        tangents[i * 3 + 2] = t.z;
-    }
-
-    assert!(tangents.len() == v_array.len());
-    assert!(bittangents.len() == v_array.len());
-
-    return TangentSpace {
-        tangents: Some(tangents),
-        bitangents: Some(bittangents),
-    };
-}
-
 #[derive(Clone, Copy, Debug)]
 struct WithNormalMap(bool);
 
@@ -229,6 +100,110 @@ impl ObjMaterial {
 
         obj_mat
     }
+
+    /// Default material of `AssetSystem::new_obj`, the "unrust/obj" program with the
+    /// colors and maps of the MTL file, "uNormalMap" for `map_Bump`.
+    ///
+    /// Materials with a `map_d` mask are cut out at 0.5, the ones with `d` below 1
+    /// go to the transparent queue.
+    ///
+    /// The program is GLSL 300 es, on WebGL1 the material is the `Material::new_phong`
+    /// one of the `map_Kd` texture.
+    pub fn build_material(asys: &AssetSystem, obj_mat: ObjMaterial) -> Rc<Material> {
+        let diffuse_map = obj_mat.diffuse_map.as_ref().map_or("default_white", |s| s.as_str());
+        let mut material = if asys.capabilities().webgl2 {
+            ObjMaterial::obj_material(asys, &obj_mat)
+        } else {
+            Material::new_phong(asys, repeat_texture(asys, diffuse_map))
+        };
+
+        if obj_mat.alpha_mask.is_some() {
+            material.set_cutout(0.5);
+        }
+        if obj_mat.alpha.unwrap_or(1.0) < 0.9999 {
+            material.render_queue = RenderQueue::Transparent;
+        }
+
+        Rc::new(material)
+    }
+
+    fn obj_material(asys: &AssetSystem, obj_mat: &ObjMaterial) -> Material {
+        let material = Material::new(asys.new_program("unrust/obj"));
+
+        let texture = |name: &str| repeat_texture(asys, name);
+        let one = Vector3::new(1.0, 1.0, 1.0);
+
+        material.set("uMaterial.ambient", obj_mat.ambient.unwrap_or(one));
+        material.set("uMaterial.diffuse", obj_mat.diffuse.unwrap_or(one));
+        material.set(
+            "uMaterial.diffuse_tex",
+            texture(obj_mat.diffuse_map.as_ref().map_or("default_white", |s| s.as_str())),
+        );
+        material.set("uMaterial.specular", obj_mat.specular.unwrap_or(one));
+        material.set(
+            "uMaterial.specular_tex",
+            texture(obj_mat.specular_map.as_ref().map_or("default_black", |s| s.as_str())),
+        );
+        material.set("uMaterial.shininess", obj_mat.shininess.unwrap_or(32.0));
+        material.set("uMaterial.transparent", obj_mat.alpha.unwrap_or(1.0));
+        material.set(
+            "uMaterial.mask_tex",
+            texture(obj_mat.alpha_mask.as_ref().map_or("default_white", |s| s.as_str())),
+        );
+
+        if let Some(ref nm) = obj_mat.normal_map {
+            material.set("uNormalMap", texture(nm));
+        }
+
+        material
+    }
+}
+
+fn repeat_texture(asys: &AssetSystem, name: &str) -> Rc<Texture> {
+    let tex = asys.new_texture(name);
+    tex.set_wrap(TextureWrap::Repeat, TextureWrap::Repeat);
+    tex
+}
+
+/// Indices are u16, larger groups are split in several surfaces
+const MAX_SURFACE_VERTICES: usize = 65536;
+
+/// The vertices of a surface, one per polygon corner
+#[derive(Default)]
+struct SurfaceData {
+    indices: Vec<u16>,
+    vertices: Vec<f32>,
+    uvs: Vec<f32>,
+    normals: Vec<f32>,
+}
+
+impl SurfaceData {
+    fn add_vertex(&mut self, index_tuple: obj::IndexTuple, model: &obj::Obj<SimplePolygon>) {
+        self.indices.push(self.indices.len() as u16);
+        self.vertices.extend_from_slice(&model.position[index_tuple.0]);
+        if let Some(uv) = index_tuple.1 {
+            self.uvs.push(model.texture[uv][0]);
+            self.uvs.push(1.0 - model.texture[uv][1]);
+        }
+        if let Some(n) = index_tuple.2 {
+            self.normals.extend_from_slice(&model.normal[n]);
+        }
+    }
+
+    fn into_mesh_data(self) -> MeshData {
+        MeshData {
+            indices: self.indices,
+            vertices: self.vertices,
+            uvs: if self.uvs.is_empty() { None } else { Some(self.uvs) },
+            tangents: None,
+            bitangents: None,
+            normals: if self.normals.is_empty() {
+                None
+            } else {
+                Some(self.normals)
+            },
+        }
+    }
 }
 
 type MaterialBuilder = Box<Fn(&AssetSystem, ObjMaterial) -> Rc<Material>>;
@@ -241,6 +216,7 @@ where
     parent_path: String,
     asys: A,
     builder: MaterialBuilder,
+    default: Option<(WithNormalMap, Rc<Material>)>,
 }
 
 impl<A> MaterialCache<A>
@@ -253,9 +229,21 @@ where
             parent_path,
             asys,
             builder,
+            default: None,
         }
     }
 
+    /// The material of the groups without `usemtl`
+    fn get_or_insert_default(&mut self) -> (WithNormalMap, Rc<Material>) {
+        if self.default.is_none() {
+            let material = (*self.builder)(&self.asys, ObjMaterial::default());
+            self.default = Some((WithNormalMap(false), material));
+        }
+
+        let &(b, ref mat) = self.default.as_ref().unwrap();
+        (b, mat.clone())
+    }
+
     fn get_or_insert(&mut self, gm: &obj::Material) -> (WithNormalMap, Rc<Material>) {
         let entry = self.map.get(&gm.name);
 
@@ -289,95 +277,49 @@ impl PrefabLoader {
     where
         A: AssetSystem + Clone + 'static,
     {
-        let vertices: Vec<[f32; 3]> = model.position;
-        let uvs: Vec<[f32; 2]> = model.texture;
-        let normals: Vec<[f32; 3]> = model.normal;
-
         // create the mesh componet
         let mut mesh = Mesh::new();
 
         let mut material_cache = MaterialCache::new(asys.clone(), parent.clone(), builder);
 
-        for o in model.objects {
-            for g in o.groups {
-                if g.material.is_none() {
-                    continue;
-                }
-                let material = g.material.as_ref().unwrap();
-                let (has_normal_map, material) = material_cache.get_or_insert(&material);
-
-                let mut indices = Vec::new();
-                let mut v_array = Vec::new();
-                let mut uv_array = Vec::new();
-                let mut n_array = Vec::new();
-
-                let mut add_v = |index_tuple: obj::IndexTuple| {
-                    indices.push(indices.len() as u16);
-                    v_array.extend_from_slice(&vertices[index_tuple.0]);
-                    index_tuple.1.map(|uv| {
-                        uv_array.push(uvs[uv][0]);
-                        uv_array.push(1.0 - uvs[uv][1]);
-                    });
-                    index_tuple.2.map(|n| {
-                        n_array.extend_from_slice(&normals[n]);
-                    });
+        for o in &model.objects {
+            for g in &o.groups {
+                let (has_normal_map, material) = match g.material {
+                    Some(ref material) => material_cache.get_or_insert(material),
+                    None => material_cache.get_or_insert_default(),
                 };
 
-                for poly in g.polys {
-                    // assert_eq!(poly.len(), 3, "We only handle triangle obj files");
-                    match poly.len() {
-                        3 => {
-                            add_v(poly[0]);
-                            add_v(poly[1]);
-                            add_v(poly[2]);
-                        }
-                        4 => {
-                            add_v(poly[0]);
-                            add_v(poly[1]);
-                            add_v(poly[2]);
-
-                            add_v(poly[2]);
-                            add_v(poly[3]);
-                            add_v(poly[0]);
+                let mut surfaces = vec![SurfaceData::default()];
+
+                for poly in &g.polys {
+                    // Fan triangulation, the polygons of obj files are convex
+                    for i in 1..poly.len().saturating_sub(1) {
+                        if surfaces.last().unwrap().indices.len() + 3 > MAX_SURFACE_VERTICES {
+                            surfaces.push(SurfaceData::default());
                         }
 
-                        _ => panic!("We only handle triangle or quad obj files"),
+                        let surface = surfaces.last_mut().unwrap();
+                        surface.add_vertex(poly[0], &model);
+                        surface.add_vertex(poly[i], &model);
+                        surface.add_vertex(poly[i + 1], &model);
                     }
                 }
 
-                let uv_array = if uv_array.len() > 0 {
-                    Some(uv_array)
-                } else {
-                    None
-                };
-                let n_array = if n_array.len() > 0 {
-                    Some(n_array)
-                } else {
-                    None
-                };
-
-                let tangent_space = if has_normal_map.0 {
-                    compute_tangents(&v_array, &uv_array, &n_array, &indices)
-                } else {
-                    TangentSpace {
-                        tangents: None,
-                        bitangents: None,
+                for surface in surfaces {
+                    if surface.indices.is_empty() {
+                        continue;
                     }
-                };
 
-                let mesh_data = MeshData {
-                    indices: indices,
-                    vertices: v_array,
-                    uvs: uv_array,
-                    tangents: tangent_space.tangents,
-                    bitangents: tangent_space.bitangents,
-                    normals: n_array,
-                };
+                    let mut mesh_data = surface.into_mesh_data();
+                    if has_normal_map.0 {
+                        mesh_data.compute_tangents();
+                    }
 
-                mesh.add_surface(
-                    MeshBuffer::new_from_resource(Resource::new(mesh_data)),
-                    material,
-                );
+                    mesh.add_surface(
+                        MeshBuffer::new_from_resource(Resource::new(mesh_data)),
+                        material.clone(),
+                    );
+                }
             }
         }
 
@@ -0,0 +1,200 @@
+use engine::asset::loader::{Loadable, Loader};
+use engine::asset::{Asset, AssetError, AssetResult, File};
+use engine::render::{SdfFontData, SdfGlyph, Texture, TextureImage};
+
+use image;
+use image::ImageBuffer;
+use math::*;
+use rusttype::{point, Font, Scale};
+use std::collections::HashMap;
+
+/// Pixels of an em in the atlas
+const GLYPH_PIXELS: f32 = 32.0;
+/// Distance in pixels covered by the field on each side of the glyph edges
+const SPREAD: i32 = 4;
+const ATLAS_WIDTH: u32 = 512;
+
+/// Generates the signed distance field atlas of a TTF file at load time, for
+/// the printable ASCII and Latin-1 characters
+pub struct SdfFontLoader {}
+
+/// The glyph coverage of a rusttype glyph
+struct Bitmap {
+    width: i32,
+    height: i32,
+    coverage: Vec<f32>,
+}
+
+impl Bitmap {
+    fn inside(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return false;
+        }
+
+        self.coverage[(x + y * self.width) as usize] >= 0.5
+    }
+
+    /// The field of the bitmap with `SPREAD` pixels of padding, 0.5 on the
+    /// edges and above inside
+    fn distance_field(&self) -> Vec<u8> {
+        let (w, h) = (self.width + SPREAD * 2, self.height + SPREAD * 2);
+        let mut field = Vec::with_capacity((w * h) as usize);
+
+        for y in 0..h {
+            for x in 0..w {
+                let (bx, by) = (x - SPREAD, y - SPREAD);
+                let inside = self.inside(bx, by);
+
+                // The nearest pixel on the other side of the edge
+                let mut nearest = (SPREAD * SPREAD) as f32;
+                for dy in -SPREAD..SPREAD + 1 {
+                    for dx in -SPREAD..SPREAD + 1 {
+                        if self.inside(bx + dx, by + dy) != inside {
+                            nearest = nearest.min((dx * dx + dy * dy) as f32);
+                        }
+                    }
+                }
+
+                let d = nearest.sqrt().min(SPREAD as f32);
+                let signed = if inside { d } else { -d };
+                let v = 0.5 + signed / (SPREAD as f32 * 2.0);
+
+                field.push((v.max(0.0).min(1.0) * 255.0) as u8);
+            }
+        }
+
+        field
+    }
+}
+
+fn charset() -> Vec<char> {
+    (32u8..127).chain(160u8..=255).map(|c| c as char).collect()
+}
+
+impl Loader<SdfFontData> for SdfFontLoader {
+    fn load<A>(_asys: A, mut file: Box<File>) -> AssetResult<SdfFontData> {
+        let name = file.name();
+        let bytes = file.read_binary()
+            .map_err(|_| AssetError::ReadBufferFail(name.clone()))?;
+        let len = bytes.len();
+
+        let font = Font::from_bytes(bytes).map_err(|e| AssetError::InvalidFormat {
+            path: name.clone(),
+            len,
+            reason: format!("{:?}", e),
+        })?;
+
+        let scale = Scale::uniform(GLYPH_PIXELS);
+        let v_metrics = font.v_metrics(scale);
+
+        // Shelf packing, the atlas height is known at the end
+        let mut placed = Vec::new();
+        let (mut x, mut y, mut row_height) = (0u32, 0u32, 0u32);
+
+        for c in charset() {
+            let glyph = font.glyph(c).scaled(scale).positioned(point(0.0, 0.0));
+            let advance = glyph.unpositioned().h_metrics().advance_width;
+
+            let bitmap = glyph.pixel_bounding_box().map(|bb| {
+                let mut bitmap = Bitmap {
+                    width: bb.width(),
+                    height: bb.height(),
+                    coverage: vec![0.0; (bb.width() * bb.height()) as usize],
+                };
+
+                glyph.draw(|gx, gy, v| {
+                    bitmap.coverage[(gx as i32 + gy as i32 * bb.width()) as usize] = v;
+                });
+
+                (bb.min, bitmap)
+            });
+
+            let (min, field, w, h) = match bitmap {
+                Some((min, bitmap)) => {
+                    let field = bitmap.distance_field();
+                    let (w, h) = (bitmap.width + SPREAD * 2, bitmap.height + SPREAD * 2);
+                    (min, field, w as u32, h as u32)
+                }
+                None => {
+                    // Spaces only advance
+                    placed.push((c, advance, None));
+                    continue;
+                }
+            };
+
+            if x + w > ATLAS_WIDTH {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+
+            placed.push((c, advance, Some((min, field, (x, y), (w, h)))));
+            x += w;
+            row_height = row_height.max(h);
+        }
+
+        let height = (y + row_height).next_power_of_two().max(1);
+        let empty = image::Rgba([0xff, 0xff, 0xff, 0]);
+        let mut img = ImageBuffer::from_pixel(ATLAS_WIDTH, height, empty);
+        let mut glyphs = HashMap::new();
+        let uv = |x: f32, y: f32| Vector2f::new(x / ATLAS_WIDTH as f32, y / height as f32);
+
+        for (c, advance, rect) in placed.into_iter() {
+            let glyph = match rect {
+                Some((min, field, (px, py), (w, h))) => {
+                    for gy in 0..h {
+                        for gx in 0..w {
+                            let v = field[(gx + gy * w) as usize];
+                            img.put_pixel(px + gx, py + gy, image::Rgba([0xff, 0xff, 0xff, v]));
+                        }
+                    }
+
+                    let (px, py) = (px as f32, py as f32);
+                    let (w, h) = (w as f32, h as f32);
+
+                    SdfGlyph {
+                        uv: (uv(px, py), uv(px + w, py + h)),
+                        // The bounding box is in pixels down from the baseline
+                        offset: Vector2f::new(
+                            (min.x - SPREAD) as f32 / GLYPH_PIXELS,
+                            -(min.y - SPREAD) as f32 / GLYPH_PIXELS,
+                        ),
+                        size: Vector2f::new(w, h) / GLYPH_PIXELS,
+                        advance: advance / GLYPH_PIXELS,
+                    }
+                }
+                None => SdfGlyph {
+                    uv: (Vector2f::zero(), Vector2f::zero()),
+                    offset: Vector2f::zero(),
+                    size: Vector2f::zero(),
+                    advance: advance / GLYPH_PIXELS,
+                },
+            };
+
+            glyphs.insert(c, glyph);
+        }
+
+        let mut kerning = HashMap::new();
+        for a in (32u8..127).map(|c| c as char) {
+            for b in (32u8..127).map(|c| c as char) {
+                let k = font.pair_kerning(scale, a, b);
+                if k.abs() > 0.0 {
+                    kerning.insert((a, b), k / GLYPH_PIXELS);
+                }
+            }
+        }
+
+        Ok(SdfFontData {
+            texture: Texture::new(TextureImage::Rgba(img)),
+            glyphs,
+            kerning,
+            line_height: (v_metrics.ascent - v_metrics.descent + v_metrics.line_gap)
+                / GLYPH_PIXELS,
+            spread: SPREAD as f32 / GLYPH_PIXELS,
+        })
+    }
+}
+
+impl Loadable for SdfFontData {
+    type Loader = SdfFontLoader;
+}
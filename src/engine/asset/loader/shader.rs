@@ -28,7 +28,8 @@ where
             reason: format!("{:?}", e),
         })?;
 
-        let code = PreprocessedShaderCode::new(T::kind(), s, &HashMap::new()).unwrap();
+        let code =
+            PreprocessedShaderCode::new(T::kind(), &file.name(), s, &HashMap::new()).unwrap();
         Ok(Shader::<T>::from_preprocessed(&file.name(), code))
     }
 }
@@ -90,7 +91,12 @@ where
     }
 
     fn parse(&self) -> Result<PreprocessedShaderCode, PreprocessError> {
-        return PreprocessedShaderCode::new(self.kind, &self.source, &self.extern_files);
+        return PreprocessedShaderCode::new(
+            self.kind,
+            &self.filename,
+            &self.source,
+            &self.extern_files,
+        );
     }
 }
 
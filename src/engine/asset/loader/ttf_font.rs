@@ -0,0 +1,29 @@
+use engine::asset::loader::{Loadable, Loader};
+use engine::asset::{AssetError, AssetResult, File};
+use engine::render::TtfFontData;
+
+use rusttype::Font;
+
+/// Only parses the font, its glyphs are rasterized on demand by `TtfFont`
+pub struct TtfFontLoader {}
+
+impl Loader<TtfFontData> for TtfFontLoader {
+    fn load<A>(_asys: A, mut file: Box<File>) -> AssetResult<TtfFontData> {
+        let name = file.name();
+        let bytes = file.read_binary()
+            .map_err(|_| AssetError::ReadBufferFail(name.clone()))?;
+        let len = bytes.len();
+
+        let font = Font::from_bytes(bytes).map_err(|e| AssetError::InvalidFormat {
+            path: name.clone(),
+            len,
+            reason: format!("{:?}", e),
+        })?;
+
+        Ok(TtfFontData { font })
+    }
+}
+
+impl Loadable for TtfFontData {
+    type Loader = TtfFontLoader;
+}
@@ -0,0 +1,124 @@
+use engine::asset::AssetError;
+use engine::sound::AudioClipData;
+use futures::{Async, Future, Poll};
+use std::cell::Cell;
+use stdweb::unstable::TryInto;
+use stdweb::web::TypedArray;
+use stdweb::Value;
+
+thread_local!(static NEXT_ID: Cell<u32> = Cell::new(0));
+
+/// The rate the browser resamples the decoded clips to
+const SAMPLE_RATE: u32 = 44100;
+
+/// Create the OfflineAudioContext decoding the clips, false when the browser
+/// lacks the Web Audio API
+pub fn is_supported() -> bool {
+    let supported = js! {
+        var dec = window.unrustAudioDecoder;
+        if (dec === undefined) {
+            dec = window.unrustAudioDecoder = { results: {}, ctx: null };
+
+            var Offline = window.OfflineAudioContext || window.webkitOfflineAudioContext;
+            if (Offline !== undefined) {
+                // Offline, it decodes without a user gesture
+                dec.ctx = new Offline(1, 1, @{SAMPLE_RATE});
+            }
+        }
+
+        return dec.ctx !== null;
+    };
+
+    supported.try_into().unwrap_or(false)
+}
+
+/// A clip decoded by the browser with decodeAudioData, the channels
+/// interleaved. `is_supported` must be true.
+pub struct WebAudioFuture {
+    id: u32,
+    path: String,
+    len: usize,
+}
+
+pub fn decode(bytes: &[u8], path: String) -> WebAudioFuture {
+    let id = NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+
+    let len = bytes.len();
+    let bytes = TypedArray::<u8>::from(bytes);
+    js! {
+        var bytes = @{bytes};
+        var dec = window.unrustAudioDecoder;
+        var id = @{id};
+
+        // The callbacks, the promise is missing in the older browsers
+        dec.ctx.decodeAudioData(bytes.buffer, function(buffer) {
+            dec.results[id] = { buffer: buffer };
+        }, function(err) {
+            dec.results[id] = { error: String(err) };
+        });
+    }
+
+    WebAudioFuture { id, path, len }
+}
+
+impl WebAudioFuture {
+    fn invalid_format(&self, reason: String) -> AssetError {
+        AssetError::InvalidFormat {
+            path: self.path.clone(),
+            len: self.len,
+            reason,
+        }
+    }
+}
+
+impl Future for WebAudioFuture {
+    type Item = AudioClipData;
+    type Error = AssetError;
+
+    fn poll(&mut self) -> Poll<AudioClipData, AssetError> {
+        let result = js! {
+            var dec = window.unrustAudioDecoder;
+            var r = dec.results[@{self.id}];
+            if (r === undefined) {
+                return null;
+            }
+
+            delete dec.results[@{self.id}];
+            if (r.error !== undefined) {
+                return r.error;
+            }
+
+            var buffer = r.buffer;
+            var channels = buffer.numberOfChannels;
+            var samples = new Float32Array(buffer.length * channels);
+            for (var c = 0; c < channels; c++) {
+                var data = buffer.getChannelData(c);
+                for (var i = 0; i < data.length; i++) {
+                    samples[i * channels + c] = data[i];
+                }
+            }
+
+            return [channels, buffer.sampleRate, samples];
+        };
+
+        let result = match result {
+            Value::Null => return Ok(Async::NotReady),
+            Value::String(reason) => return Err(self.invalid_format(reason)),
+            result => result,
+        };
+
+        let channels: f64 = js!(return @{&result}[0];).try_into().unwrap();
+        let sample_rate: f64 = js!(return @{&result}[1];).try_into().unwrap();
+        let samples: TypedArray<f32> = js!(return @{&result}[2];).try_into().unwrap();
+
+        Ok(Async::Ready(AudioClipData {
+            channels: channels as usize,
+            sample_rate: sample_rate as usize,
+            samples: samples.to_vec(),
+        }))
+    }
+}
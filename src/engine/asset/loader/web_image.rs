@@ -0,0 +1,131 @@
+use engine::asset::AssetError;
+use engine::TextureImage;
+use futures::{Async, Future, Poll};
+use image::RgbaImage;
+use std::cell::Cell;
+use stdweb::unstable::TryInto;
+use stdweb::web::TypedArray;
+use stdweb::Value;
+
+thread_local!(static NEXT_ID: Cell<u32> = Cell::new(0));
+
+/// Start the Web Worker decoding the images, false when the browser lacks
+/// Worker, OffscreenCanvas or createImageBitmap
+pub fn is_supported() -> bool {
+    let supported = js! {
+        var dec = window.unrustImageDecoder;
+        if (dec === undefined) {
+            dec = window.unrustImageDecoder = { results: {}, worker: null };
+
+            if (typeof Worker !== "undefined" && typeof OffscreenCanvas !== "undefined"
+                && typeof createImageBitmap !== "undefined") {
+                // The worker is built from the source of this function
+                var main = function() {
+                    self.onmessage = function(e) {
+                        var id = e.data.id;
+                        var options = { premultiplyAlpha: "none", colorSpaceConversion: "none" };
+
+                        createImageBitmap(new Blob([e.data.bytes]), options).then(function(bmp) {
+                            var w = bmp.width;
+                            var h = bmp.height;
+                            var ctx = new OffscreenCanvas(w, h).getContext("2d");
+                            ctx.drawImage(bmp, 0, 0);
+
+                            var pixels = ctx.getImageData(0, 0, w, h).data.buffer;
+                            var result = { id: id, width: w, height: h, pixels: pixels };
+                            self.postMessage(result, [pixels]);
+                        }).catch(function(err) {
+                            self.postMessage({ id: id, error: String(err) });
+                        });
+                    };
+                };
+
+                var source = "(" + main.toString() + ")()";
+                dec.worker = new Worker(URL.createObjectURL(new Blob([source])));
+                dec.worker.onmessage = function(e) {
+                    dec.results[e.data.id] = e.data;
+                };
+            }
+        }
+
+        return dec.worker !== null;
+    };
+
+    supported.try_into().unwrap_or(false)
+}
+
+/// An image decoded by the browser, as RGBA. `is_supported` must be true.
+pub struct WebImageFuture {
+    id: u32,
+    path: String,
+    len: usize,
+}
+
+pub fn decode(bytes: &[u8], path: String) -> WebImageFuture {
+    let id = NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+
+    let len = bytes.len();
+    let bytes = TypedArray::<u8>::from(bytes);
+    js! {
+        var bytes = @{bytes};
+        var dec = window.unrustImageDecoder;
+        dec.worker.postMessage({ id: @{id}, bytes: bytes.buffer }, [bytes.buffer]);
+    }
+
+    WebImageFuture {
+        id,
+        path,
+        len,
+    }
+}
+
+impl WebImageFuture {
+    fn invalid_format(&self, reason: String) -> AssetError {
+        AssetError::InvalidFormat {
+            path: self.path.clone(),
+            len: self.len,
+            reason,
+        }
+    }
+}
+
+impl Future for WebImageFuture {
+    type Item = TextureImage;
+    type Error = AssetError;
+
+    fn poll(&mut self) -> Poll<TextureImage, AssetError> {
+        let result = js! {
+            var dec = window.unrustImageDecoder;
+            var r = dec.results[@{self.id}];
+            if (r === undefined) {
+                return null;
+            }
+
+            delete dec.results[@{self.id}];
+            if (r.error !== undefined) {
+                return r.error;
+            }
+
+            return [r.width, r.height, new Uint8Array(r.pixels)];
+        };
+
+        let result = match result {
+            Value::Null => return Ok(Async::NotReady),
+            Value::String(reason) => return Err(self.invalid_format(reason)),
+            result => result,
+        };
+
+        let w: f64 = js!(return @{&result}[0];).try_into().unwrap();
+        let h: f64 = js!(return @{&result}[1];).try_into().unwrap();
+        let pixels: TypedArray<u8> = js!(return @{&result}[2];).try_into().unwrap();
+
+        match RgbaImage::from_raw(w as u32, h as u32, pixels.to_vec()) {
+            Some(img) => Ok(Async::Ready(TextureImage::Rgba(img))),
+            None => Err(self.invalid_format("Wrong size of the decoded pixels".to_string())),
+        }
+    }
+}
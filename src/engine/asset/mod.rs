@@ -1,18 +1,32 @@
 mod asset_database;
+mod bundle;
+mod custom;
 mod default_font_bitmap;
 mod quad;
 mod fs;
+mod import_settings;
 mod primitives;
+mod progress;
 mod resource;
 mod skybox;
+mod vfs;
 
 pub mod loader;
+pub mod worker;
 pub use self::primitives::{CubeMesh, PlaneMesh};
 pub use self::quad::QuadMesh;
 pub use self::skybox::SkyboxMesh;
 pub use self::asset_database::{Asset, AssetDatabase, AssetError, AssetResult, AssetSystem,
-                               LoadableAsset};
-pub use self::loader::{ObjMaterial, Prefab, DDS};
+                               CustomLoader, LoadableAsset};
+pub use self::bundle::{AssetBundle, AssetBundleWriter, BundleCompression};
+pub use self::custom::{CustomAsset, CustomData};
+pub use self::import_settings::{ImportSettings, ImportSettingsFuture, MeshImportSettings,
+                               TextureImportSettings};
+pub use self::progress::{AssetState, LoadingProgress};
+pub use self::vfs::MountSource;
+pub use self::loader::{ColladaInstance, ColladaNode, ColladaScene, ColladaSkin, DDSFormat,
+                       GltfCamera, GltfInstance, GltfNode, GltfScene, ObjMaterial, Prefab,
+                       SkinWeights, DDS};
 
 pub use self::resource::Resource;
 pub use self::fs::*;
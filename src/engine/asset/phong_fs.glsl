@@ -5,53 +5,67 @@ out vec4 FragColor;
 #define texture2D texture
 #endif
 
+#ifndef UNI_POINT_LIGHTS
 #define UNI_POINT_LIGHTS 4
+#endif
+#ifndef UNI_SPOT_LIGHTS
+#define UNI_SPOT_LIGHTS 4
+#endif
 
-struct DirectionalLight {
-    vec3 direction;
-  
-    vec3 ambient;
-    vec3 diffuse;
-    vec3 specular;
-};
-
-struct PointLight {
-    vec3 position;
-    
-    float constant;
-    float linear;
-    float quadratic;
-	
-    vec3 ambient;
-    vec3 diffuse;
-    vec3 specular;
-
-    float rate;
-};
+#include "unrust/phong_light.glsl"
 
 struct Material {
     sampler2D diffuse;
+    // Set by the material along the texture, see Texture::set_srgb
+    bool diffuse_linear;
     float shininess;
 };
 
-uniform vec3 uViewPos;
 uniform Material uMaterial;
+uniform float uAlphaCutoff;
 
 varying vec3 vFragPos;
 varying vec2 vTexCoords;       
 varying vec3 vNormal;                       
+varying vec3 vTangent;
+varying vec3 vBitangent;
+
+// vTexCoords after the parallax offset
+vec2 texCoords;
+
+#include "unrust/color_space.glsl"
+
+vec4 DiffuseColor()
+{
+    vec4 color = texture2D(uMaterial.diffuse, texCoords);
+    if (uMaterial.diffuse_linear) {
+        return color;
+    }
+    return DecodeColor(color);
+}
+
+#include "unrust/normal_map.glsl"
 
 // Lights
 uniform DirectionalLight uDirectionalLight;
 uniform PointLight uPointLights[UNI_POINT_LIGHTS];
+uniform SpotLight uSpotLights[UNI_SPOT_LIGHTS];
 
 vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir);
 vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
 
 void main(void) {
-    vec3 norm = normalize(vNormal);
+    vec3 normal = normalize(vNormal);
     vec3 viewDir = normalize(uViewPos - vFragPos);
 
+    texCoords = ParallaxOcclusion(vTexCoords, viewDir, normal, vTangent, vBitangent);
+
+    // Alpha cutout
+    if (texture2D(uMaterial.diffuse, texCoords).a < uAlphaCutoff)
+        discard;
+
+    vec3 norm = ApplyNormalMap(normal, vTangent, vBitangent, texCoords);
+
     // Directional Light
     vec3 result = CalcDirectionalLight(uDirectionalLight, norm, viewDir);
     
@@ -59,17 +73,22 @@ void main(void) {
     for(int i = 0; i < UNI_POINT_LIGHTS; i++)
         result += CalcPointLight(uPointLights[i], norm, vFragPos, viewDir);
 
-    gl_FragColor = vec4(result, 1.0);           
+    // Spot Lights
+    for(int i = 0; i < UNI_SPOT_LIGHTS; i++)
+        result += CalcSpotLight(uSpotLights[i], norm, vFragPos, viewDir, DiffuseColor().rgb,
+                                DiffuseColor().rgb, uMaterial.shininess, vec3(1.0));
+
+    gl_FragColor = EncodeColor(vec4(result, 1.0));           
 }
 
 vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir)
 {
     // diffuse
-    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, vTexCoords));
+    vec3 ambient = light.ambient * vec3(DiffuseColor());
 
     vec3 lightDir = normalize(-light.direction);  
     float diff = max(dot(normal, lightDir), 0.0);
-    vec3 diffuse = light.diffuse * diff * texture2D(uMaterial.diffuse, vTexCoords).rgb;  
+    vec3 diffuse = light.diffuse * diff * DiffuseColor().rgb;  
 
     // specular    
     vec3 reflectDir = reflect(-lightDir, normal);  
@@ -95,8 +114,8 @@ vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
     float attenuation = 1.0 / max(d, 0.001);
     
     // combine results
-    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, vTexCoords));
-    vec3 diffuse = light.diffuse * diff * vec3(texture2D(uMaterial.diffuse, vTexCoords));
+    vec3 ambient = light.ambient * vec3(DiffuseColor());
+    vec3 diffuse = light.diffuse * diff * vec3(DiffuseColor());
     vec3 specular = light.specular * spec;
     
     ambient *= attenuation;
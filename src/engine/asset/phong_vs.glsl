@@ -5,20 +5,52 @@
 
 attribute vec3 aVertexPosition;
 attribute vec3 aVertexNormal;
+attribute vec3 aVertexTangent;
+attribute vec3 aVertexBitangent;
 attribute vec2 aTextureCoord;
 
-uniform mat4 uMVMatrix;
+uniform mat4 uVMatrix;
 uniform mat4 uPMatrix;
+
+#ifdef INSTANCING
+attribute mat4 aInstanceModel;
+
+// The inverse transpose of the 3x3 part of the model matrix, from its
+// cofactors, for the non uniformly scaled instances
+mat4 InstanceNormalMatrix(mat4 m) {
+    vec3 x = m[0].xyz;
+    vec3 y = m[1].xyz;
+    vec3 z = m[2].xyz;
+    vec3 cx = cross(y, z);
+    float det = dot(x, cx);
+
+    return mat4(
+        vec4(cx / det, 0.0),
+        vec4(cross(z, x) / det, 0.0),
+        vec4(cross(x, y) / det, 0.0),
+        vec4(0.0, 0.0, 0.0, 1.0));
+}
+
+#define uMMatrix aInstanceModel
+#define uNMatrix InstanceNormalMatrix(aInstanceModel)
+#define uMVMatrix (uVMatrix * aInstanceModel)
+#else
+uniform mat4 uMVMatrix;
 uniform mat4 uNMatrix;
 uniform mat4 uMMatrix;
+#endif
 
 varying vec3 vFragPos;
 varying vec3 vNormal;
+varying vec3 vTangent;
+varying vec3 vBitangent;
 varying vec2 vTexCoords;
 
 void main(void) {
     vFragPos = vec3(uMMatrix * vec4(aVertexPosition, 1.0));            
     vNormal = mat3(uNMatrix) * aVertexNormal;
+    vTangent = mat3(uMMatrix) * aVertexTangent;
+    vBitangent = mat3(uMMatrix) * aVertexBitangent;
     vTexCoords = aTextureCoord;
 
     gl_Position = uPMatrix * uMVMatrix * vec4(aVertexPosition, 1.0);
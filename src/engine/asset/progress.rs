@@ -0,0 +1,209 @@
+use engine::asset::fs::{File, FileFuture, FileIoError, MemoryFile};
+use futures::{Async, Future, Poll};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+
+/// The loading state of a file opened by `AssetSystem::new_file`
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetState {
+    /// Opened, the file system was not asked yet
+    Queued,
+    /// Being read by the file system, which does not report the bytes so far
+    Loading,
+    /// Read, the asset is decoded when it is first used
+    Ready { bytes: usize },
+    Failed(String),
+}
+
+impl AssetState {
+    pub fn is_finished(&self) -> bool {
+        match *self {
+            AssetState::Ready { .. } | AssetState::Failed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The files opened since the start or the last `AssetSystem::reset`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadingProgress {
+    pub total: usize,
+    pub ready: usize,
+    pub failed: usize,
+    /// Of the ready files
+    pub bytes: usize,
+}
+
+impl LoadingProgress {
+    pub fn finished(&self) -> usize {
+        self.ready + self.failed
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.finished() == self.total
+    }
+
+    /// From 0 to 1, 1 when nothing is loading
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+
+        self.finished() as f32 / self.total as f32
+    }
+}
+
+impl fmt::Display for LoadingProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} assets, {:.0}%",
+            self.finished(),
+            self.total,
+            self.fraction() * 100.0
+        )
+    }
+}
+
+enum Slot {
+    Pending(FileFuture),
+    Done(Result<Box<File>, FileIoError>),
+    Taken,
+}
+
+/// A file shared by the tracker and the future of its asset, whichever polls it
+struct TrackedFile {
+    name: String,
+    slot: Slot,
+    state: AssetState,
+}
+
+impl TrackedFile {
+    fn poll(&mut self) {
+        let result = match self.slot {
+            Slot::Pending(ref mut f) => match f.poll() {
+                Ok(Async::NotReady) => {
+                    self.state = AssetState::Loading;
+                    return;
+                }
+                Ok(Async::Ready(mut file)) => file.read_binary().map(|data| MemoryFile {
+                    name: file.name(),
+                    data,
+                }),
+                Err(e) => Err(e),
+            },
+            _ => return,
+        };
+
+        self.state = match result {
+            Ok(ref file) => AssetState::Ready {
+                bytes: file.data.len(),
+            },
+            Err(ref e) => AssetState::Failed(format!("{:?}", e)),
+        };
+        self.slot = Slot::Done(result.map(|file| Box::new(file) as Box<File>));
+    }
+}
+
+struct TrackedFileFuture(Rc<RefCell<TrackedFile>>);
+
+impl Future for TrackedFileFuture {
+    type Item = Box<File>;
+    type Error = FileIoError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut tracked = self.0.borrow_mut();
+        tracked.poll();
+
+        match mem::replace(&mut tracked.slot, Slot::Taken) {
+            Slot::Done(result) => result.map(Async::Ready),
+            slot => {
+                tracked.slot = slot;
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// Follows the files opened by the asset system, reading them in `poll`
+/// even when their assets are not used yet
+#[derive(Default)]
+pub struct FileTracker {
+    pending: RefCell<Vec<Rc<RefCell<TrackedFile>>>>,
+    states: RefCell<HashMap<String, AssetState>>,
+    finished: RefCell<Vec<(String, AssetState)>>,
+}
+
+impl FileTracker {
+    pub fn track(&self, name: &str, f: FileFuture) -> FileFuture {
+        let tracked = Rc::new(RefCell::new(TrackedFile {
+            name: name.to_string(),
+            slot: Slot::Pending(f),
+            state: AssetState::Queued,
+        }));
+
+        self.states
+            .borrow_mut()
+            .insert(name.to_string(), AssetState::Queued);
+        self.pending.borrow_mut().push(tracked.clone());
+
+        Box::new(TrackedFileFuture(tracked))
+    }
+
+    /// Read the pending files, the ones finishing are kept for `finished`
+    pub fn poll(&self) {
+        let mut states = self.states.borrow_mut();
+        let mut finished = self.finished.borrow_mut();
+        finished.clear();
+
+        self.pending.borrow_mut().retain(|tracked| {
+            let mut tracked = tracked.borrow_mut();
+            tracked.poll();
+
+            states.insert(tracked.name.clone(), tracked.state.clone());
+            if tracked.state.is_finished() {
+                finished.push((tracked.name.clone(), tracked.state.clone()));
+                return false;
+            }
+
+            true
+        });
+    }
+
+    pub fn state(&self, name: &str) -> Option<AssetState> {
+        self.states.borrow().get(name).cloned()
+    }
+
+    pub fn progress(&self) -> LoadingProgress {
+        let mut progress = LoadingProgress::default();
+
+        for state in self.states.borrow().values() {
+            progress.total += 1;
+
+            match *state {
+                AssetState::Ready { bytes } => {
+                    progress.ready += 1;
+                    progress.bytes += bytes;
+                }
+                AssetState::Failed(_) => progress.failed += 1,
+                _ => (),
+            }
+        }
+
+        progress
+    }
+
+    /// The files which finished during the last `poll`
+    pub fn finished(&self) -> Vec<(String, AssetState)> {
+        self.finished.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.pending.borrow_mut().clear();
+        self.states.borrow_mut().clear();
+        self.finished.borrow_mut().clear();
+    }
+}
@@ -0,0 +1,158 @@
+use engine::asset::AssetBundle;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// Where the files of a mount are read from, see `AssetSystem::mount`
+pub enum MountSource {
+    /// A directory of the file system of the engine, relative to its root
+    Directory(String),
+    /// A bundle file read through the mounts, see `AssetBundle`. The files
+    /// opened while it loads wait for it.
+    Bundle(String),
+    /// A bundle embedded in the executable, e.g. with `include_bytes!`
+    EmbeddedBundle(&'static [u8]),
+    /// Files embedded in the executable, by path relative to the mount point
+    Embedded(Vec<(&'static str, &'static [u8])>),
+    /// An URL root, e.g. of a CDN. The file system of the engine fetches URLs
+    /// only on the web, natively the files are read from the next mounts.
+    Http(String),
+}
+
+enum MountedSource {
+    Directory(String),
+    Bundle(AssetBundle),
+    Embedded(HashMap<String, &'static [u8]>),
+    Http(String),
+}
+
+struct Mount {
+    point: String,
+    priority: i32,
+    /// Equal priorities are searched from the last mounted
+    order: u32,
+    source: MountedSource,
+}
+
+/// A place to read a file from, in search order
+pub enum VfsSource {
+    /// The file was found in memory, or failed to decompress
+    Data(Result<Vec<u8>, String>),
+    /// A path for the file system of the engine, below the asset path
+    Path(String),
+    /// A full URL for the file system of the engine
+    Url(String),
+}
+
+/// The mount points of the asset system. A file is searched in the mounts
+/// whose point starts its path, from the highest priority, then in the file
+/// system of the engine.
+#[derive(Default)]
+pub struct Vfs {
+    mounts: RefCell<Vec<Mount>>,
+    next_order: Cell<u32>,
+}
+
+/// "textures" and "textures/" both mount at "textures/", "" at the root
+fn normalize_point(point: &str) -> String {
+    let point = point.replace("\\", "/");
+    let point = point.trim_matches('/');
+
+    if point.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", point)
+    }
+}
+
+fn join(root: &str, relative: &str) -> String {
+    if root.is_empty() || root.ends_with('/') {
+        format!("{}{}", root, relative)
+    } else {
+        format!("{}/{}", root, relative)
+    }
+}
+
+impl Vfs {
+    fn add(&self, point: &str, priority: i32, source: MountedSource) {
+        let order = self.next_order.get();
+        self.next_order.set(order + 1);
+
+        let mut mounts = self.mounts.borrow_mut();
+        mounts.push(Mount {
+            point: normalize_point(point),
+            priority,
+            order,
+            source,
+        });
+
+        mounts.sort_by(|a, b| (b.priority, b.order).cmp(&(a.priority, a.order)));
+    }
+
+    /// Mount a source already in memory. `MountSource::Bundle` is mounted by
+    /// `mount_bundle` once the bundle is loaded.
+    pub fn mount(&self, point: &str, source: MountSource, priority: i32) -> Result<(), String> {
+        let source = match source {
+            MountSource::Directory(root) => MountedSource::Directory(root),
+            MountSource::Http(root) => MountedSource::Http(root),
+            MountSource::EmbeddedBundle(data) => {
+                MountedSource::Bundle(AssetBundle::parse(data.to_vec())?)
+            }
+            MountSource::Embedded(files) => MountedSource::Embedded(
+                files
+                    .into_iter()
+                    .map(|(name, data)| (name.replace("\\", "/"), data))
+                    .collect(),
+            ),
+            MountSource::Bundle(name) => {
+                return Err(format!("The bundle {} is not loaded", name));
+            }
+        };
+
+        self.add(point, priority, source);
+        Ok(())
+    }
+
+    pub fn mount_bundle(&self, point: &str, bundle: AssetBundle, priority: i32) {
+        self.add(point, priority, MountedSource::Bundle(bundle));
+    }
+
+    pub fn unmount(&self, point: &str) {
+        let point = normalize_point(point);
+        self.mounts.borrow_mut().retain(|m| m.point != point);
+    }
+
+    /// The places to read `name` from, the mounts not having it in memory
+    /// are skipped
+    pub fn resolve(&self, name: &str) -> Vec<VfsSource> {
+        let name = name.replace("\\", "/");
+        let mut sources = Vec::new();
+
+        for mount in self.mounts.borrow().iter() {
+            if !name.starts_with(&mount.point) {
+                continue;
+            }
+
+            let relative = &name[mount.point.len()..];
+
+            match mount.source {
+                MountedSource::Directory(ref root) => {
+                    sources.push(VfsSource::Path(join(root, relative)))
+                }
+                MountedSource::Http(ref root) => sources.push(VfsSource::Url(join(root, relative))),
+                MountedSource::Bundle(ref bundle) => {
+                    if let Some(data) = bundle.read(relative) {
+                        sources.push(VfsSource::Data(data));
+                    }
+                }
+                MountedSource::Embedded(ref files) => {
+                    if let Some(data) = files.get(relative) {
+                        sources.push(VfsSource::Data(Ok(data.to_vec())));
+                    }
+                }
+            }
+        }
+
+        sources.push(VfsSource::Path(name));
+        sources
+    }
+}
@@ -0,0 +1,203 @@
+use futures::executor::{self, Notify, NotifyHandle};
+use futures::{Async, Future, Poll};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// The decodes running at once by default
+const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// The result of a job run by `spawn`, polled on the main thread like the
+/// other asset futures. It fails when the job panicked.
+pub struct WorkFuture<T> {
+    rx: Receiver<T>,
+}
+
+impl<T> Future for WorkFuture<T> {
+    type Item = T;
+    type Error = String;
+
+    fn poll(&mut self) -> Poll<T, String> {
+        match self.rx.try_recv() {
+            Ok(v) => Ok(Async::Ready(v)),
+            Err(TryRecvError::Empty) => Ok(Async::NotReady),
+            Err(TryRecvError::Disconnected) => Err("The asset worker panicked".to_string()),
+        }
+    }
+}
+
+struct NoNotify;
+
+impl Notify for NoNotify {
+    fn notify(&self, _id: usize) {}
+}
+
+/// Poll `f` until it is done, in a job. The asset futures returning NotReady
+/// to spread their work over the frames are polled again right away.
+pub fn block_on<F: Future>(f: F) -> Result<F::Item, F::Error> {
+    let notify = NotifyHandle::from(Arc::new(NoNotify));
+    let mut f = executor::spawn(f);
+
+    loop {
+        if let Async::Ready(v) = f.poll_future_notify(&notify, 0)? {
+            return Ok(v);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod pool {
+    use super::DEFAULT_MAX_IN_FLIGHT;
+    use std::boxed::FnBox;
+    use std::collections::VecDeque;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::thread;
+
+    type Job = Box<FnBox() + Send>;
+
+    struct Queue {
+        jobs: VecDeque<Job>,
+        running: usize,
+        threads: usize,
+        max_in_flight: usize,
+    }
+
+    struct Shared {
+        queue: Mutex<Queue>,
+        ready: Condvar,
+    }
+
+    /// Threads started on demand up to the max in flight count, they wait for
+    /// jobs once started
+    struct WorkerPool {
+        shared: Arc<Shared>,
+    }
+
+    thread_local!(static POOL: WorkerPool = WorkerPool::new());
+
+    impl WorkerPool {
+        fn new() -> WorkerPool {
+            WorkerPool {
+                shared: Arc::new(Shared {
+                    queue: Mutex::new(Queue {
+                        jobs: VecDeque::new(),
+                        running: 0,
+                        threads: 0,
+                        max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+                    }),
+                    ready: Condvar::new(),
+                }),
+            }
+        }
+
+        fn start_threads(&self, queue: &mut Queue) {
+            while queue.threads < queue.max_in_flight {
+                let shared = self.shared.clone();
+                thread::Builder::new()
+                    .name("unrust-asset-worker".to_string())
+                    .spawn(move || work(&shared))
+                    .expect("Fail to start an asset worker");
+                queue.threads += 1;
+            }
+        }
+
+        fn push(&self, job: Job) {
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.jobs.push_back(job);
+            self.start_threads(&mut queue);
+            self.shared.ready.notify_one();
+        }
+
+        fn set_max_in_flight(&self, n: usize) {
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.max_in_flight = n.max(1);
+            self.start_threads(&mut queue);
+            self.shared.ready.notify_all();
+        }
+
+        fn max_in_flight(&self) -> usize {
+            self.shared.queue.lock().unwrap().max_in_flight
+        }
+    }
+
+    fn work(shared: &Shared) {
+        loop {
+            let job = {
+                let mut queue = shared.queue.lock().unwrap();
+                while queue.jobs.is_empty() || queue.running >= queue.max_in_flight {
+                    queue = shared.ready.wait(queue).unwrap();
+                }
+
+                queue.running += 1;
+                queue.jobs.pop_front().unwrap()
+            };
+
+            // A panicking job drops its sender, so its future fails
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| job.call_box(())));
+
+            shared.queue.lock().unwrap().running -= 1;
+            shared.ready.notify_one();
+        }
+    }
+
+    pub fn push(job: Job) {
+        POOL.with(|pool| pool.push(job));
+    }
+
+    pub fn set_max_in_flight(n: usize) {
+        POOL.with(|pool| pool.set_max_in_flight(n));
+    }
+
+    pub fn max_in_flight() -> usize {
+        POOL.with(|pool| pool.max_in_flight())
+    }
+}
+
+/// Run `f` on a worker thread, the jobs beyond the max in flight count wait
+/// for a free worker in order. On wasm there are no threads and `f` runs now.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn spawn<T, F>(f: F) -> WorkFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = channel();
+
+    pool::push(Box::new(move || {
+        // The receiver is gone when the asset system was reset
+        let _ = tx.send(f());
+    }));
+
+    WorkFuture { rx }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn spawn<T, F>(f: F) -> WorkFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = channel();
+    let _ = tx.send(f());
+
+    WorkFuture { rx }
+}
+
+/// The number of jobs running at once, at least 1
+#[cfg(not(target_arch = "wasm32"))]
+pub fn set_max_in_flight(n: usize) {
+    pool::set_max_in_flight(n);
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn set_max_in_flight(_n: usize) {}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn max_in_flight() -> usize {
+    pool::max_in_flight()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn max_in_flight() -> usize {
+    1
+}
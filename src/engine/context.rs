@@ -1,37 +1,28 @@
 use engine::asset::AssetResult;
 use engine::core::Component;
 use engine::engine::EngineStats;
-use engine::render::{CullMode, DepthTest, Material, MaterialState, MeshBuffer, ShaderProgram,
-                     Texture};
+use engine::render::{Blend, CullMode, DepthTest, Material, MaterialState, MeshBuffer, ProbeMaps,
+                     RenderBackend, ShaderProgram, Texture};
+use math::Matrix4;
+use std::cmp::{max, min};
 use std::collections::VecDeque;
 use std::rc::{Rc, Weak};
 use std::sync::Arc;
-use uni_gl;
-use uni_gl::{Culling, Flag, WebGLRenderingContext};
-
-trait ToGLState<T> {
-    fn as_gl_state(&self) -> T;
-}
-
-impl ToGLState<uni_gl::DepthTest> for DepthTest {
-    fn as_gl_state(&self) -> uni_gl::DepthTest {
-        match self {
-            &DepthTest::Never => uni_gl::DepthTest::Never,
-            &DepthTest::Always => uni_gl::DepthTest::Always,
-            &DepthTest::Less => uni_gl::DepthTest::Less,
-            &DepthTest::LessEqual => uni_gl::DepthTest::Lequal,
-            &DepthTest::Greater => uni_gl::DepthTest::Greater,
-            &DepthTest::NotEqual => uni_gl::DepthTest::Notequal,
-            &DepthTest::GreaterEqual => uni_gl::DepthTest::Gequal,
-            &DepthTest::Equal => uni_gl::DepthTest::Equal,
-        }
-    }
-}
+use uni_gl::{Parameter, WebGLRenderingContext};
 
 #[derive(Default)]
 pub struct StateCache {
     state: MaterialState,
     curr: MaterialState,
+
+    /// Color outputs of the bound frame buffer, draw buffers are only
+    /// switched when there are several
+    pub color_attachments: usize,
+
+    /// Applied over the queue and material states, e.g. after a depth prepass
+    pub overrides: MaterialState,
+    /// Swap front and back face culling, for mirrored view matrices
+    pub invert_culling: bool,
 }
 
 impl StateCache {
@@ -40,7 +31,9 @@ impl StateCache {
             cull: Some(CullMode::Back),
             depth_test: Some(DepthTest::Less),
             alpha_blending: Some(false),
+            blend: Some(Blend::default()),
             depth_write: Some(true),
+            draw_buffers: Some(!0),
         }
     }
 
@@ -50,15 +43,55 @@ impl StateCache {
         ms.depth_write.map(|s| self.curr.depth_write = Some(s));
         ms.alpha_blending
             .map(|s| self.curr.alpha_blending = Some(s));
+        ms.blend.map(|s| self.curr.blend = Some(s));
+        ms.draw_buffers.map(|s| self.curr.draw_buffers = Some(s));
     }
 
     pub fn commit(&mut self, gl: &WebGLRenderingContext) {
-        self.curr.cull.map(|s| self.apply_cull(gl, &s));
+        if let Some(cull) = self.curr.cull {
+            let cull = match cull {
+                CullMode::Front if self.invert_culling => CullMode::Back,
+                CullMode::Back if self.invert_culling => CullMode::Front,
+                cull => cull,
+            };
+            self.apply_cull(gl, &cull);
+        }
         self.curr.depth_test.map(|s| self.apply_depth_test(gl, &s));
         self.curr.depth_write.map(|s| self.apply_depth_write(gl, s));
         self.curr
             .alpha_blending
             .map(|s| self.apply_alpha_blending(gl, s));
+        if let Some(true) = self.curr.alpha_blending {
+            self.curr.blend.map(|s| self.apply_blend(gl, &s));
+        }
+        self.curr
+            .draw_buffers
+            .map(|s| self.apply_draw_buffers(gl, s));
+    }
+
+    fn apply_draw_buffers(&mut self, gl: &WebGLRenderingContext, mask: u32) {
+        if self.color_attachments < 2 {
+            return;
+        }
+
+        let mask = mask & ((1 << self.color_attachments) - 1);
+
+        match self.state.draw_buffers {
+            Some(curr) if curr == mask => return,
+            // The frame buffer binds all its outputs
+            None if mask == (1 << self.color_attachments) - 1 => {
+                self.state.draw_buffers = Some(mask);
+                return;
+            }
+            _ => (),
+        }
+
+        let outputs: Vec<_> = (0..self.color_attachments)
+            .map(|i| mask & (1 << i) != 0)
+            .collect();
+
+        gl.set_color_outputs(&outputs);
+        self.state.draw_buffers = Some(mask);
     }
 
     fn apply_depth_write(&mut self, gl: &WebGLRenderingContext, b: bool) {
@@ -68,7 +101,7 @@ impl StateCache {
             }
         }
 
-        gl.depth_mask(b);
+        gl.set_depth_write(b);
         self.state.depth_write = Some(b);
     }
 
@@ -79,15 +112,25 @@ impl StateCache {
             }
         }
 
-        if b {
-            gl.enable(Flag::Blend as i32);
-        } else {
-            gl.disable(Flag::Blend as i32);
-        }
+        gl.enable_blending(b);
 
         self.state.alpha_blending = Some(b);
     }
 
+    fn apply_blend(&mut self, gl: &WebGLRenderingContext, blend: &Blend) {
+        let curr = self.state.blend;
+
+        if curr.map(|b| b.equation) != Some(blend.equation) {
+            gl.set_blend_equation(blend.equation);
+        }
+
+        if curr.map(|b| (b.src, b.dst)) != Some((blend.src, blend.dst)) {
+            gl.set_blend_factors(blend.src, blend.dst);
+        }
+
+        self.state.blend = Some(*blend);
+    }
+
     fn apply_depth_test(&mut self, gl: &WebGLRenderingContext, ct: &DepthTest) {
         if let Some(s) = self.state.depth_test {
             if s == *ct {
@@ -95,12 +138,7 @@ impl StateCache {
             }
         }
 
-        if let &DepthTest::Never = ct {
-            gl.disable(uni_gl::Flag::DepthTest as i32);
-        } else {
-            gl.enable(uni_gl::Flag::DepthTest as i32);
-            gl.depth_func(ct.as_gl_state());
-        }
+        gl.set_depth_compare(*ct);
 
         self.state.depth_test = Some(*ct);
     }
@@ -112,23 +150,7 @@ impl StateCache {
             }
         }
 
-        match cm {
-            &CullMode::Off => {
-                gl.disable(Culling::CullFace as i32);
-            }
-            &CullMode::Front => {
-                gl.enable(Culling::CullFace as i32);
-                gl.cull_face(Culling::Front);
-            }
-            &CullMode::Back => {
-                gl.enable(Culling::CullFace as i32);
-                gl.cull_face(Culling::Back);
-            }
-            &CullMode::FrontAndBack => {
-                gl.enable(Culling::CullFace as i32);
-                gl.cull_face(Culling::FrontAndBack);
-            }
-        }
+        gl.set_cull_mode(*cm);
 
         self.state.cull = Some(*cm);
     }
@@ -137,10 +159,17 @@ impl StateCache {
 pub struct EngineContext {
     pub mesh_buffer: Weak<MeshBuffer>,
     pub prog: Weak<ShaderProgram>,
-    pub textures: VecDeque<(u32, Weak<Texture>)>,
+    /// Units of the material textures
+    pub textures: TextureUnits,
+    /// Units of the engine textures, e.g. shadow maps and light cookies
+    pub engine_textures: TextureUnits,
 
     pub main_light: Option<Arc<Component>>,
     pub point_lights: Vec<Arc<Component>>,
+    pub spot_lights: Vec<Arc<Component>>,
+    pub area_lights: Vec<Arc<Component>>,
+    /// Maps of the `ReflectionProbe` nearest to the camera
+    pub reflection_probe: Option<ProbeMaps>,
 
     pub switch_mesh: u32,
     pub switch_prog: u32,
@@ -150,18 +179,31 @@ pub struct EngineContext {
     pub states: StateCache,
 
     pub last_light_bound: Option<Weak<ShaderProgram>>,
+    pub last_light_layer: u32,
+    /// The program, view, projection and sRGB encoding of the last camera
+    /// uniforms set, see `Engine::setup_camera`
+    pub last_camera_bound: Option<(Weak<ShaderProgram>, Matrix4<f32>, Matrix4<f32>, bool)>,
     pub last_material_bound: Option<Weak<Material>>,
+    pub last_material_instancing: bool,
 }
 
 impl EngineContext {
-    pub fn new() -> EngineContext {
+    /// `texture_units` is the number of units of the GL context, see `max_texture_units`
+    pub fn new(texture_units: u32) -> EngineContext {
+        let reserved = min(MAX_ENGINE_TEXTURE_UNITS, texture_units / 2);
+        let material_units = min(MAX_MATERIAL_TEXTURE_UNITS, texture_units - reserved);
+
         EngineContext {
             mesh_buffer: Default::default(),
             prog: Default::default(),
-            textures: Default::default(),
+            textures: TextureUnits::new(0, material_units),
+            engine_textures: TextureUnits::new(material_units, reserved),
 
             main_light: Default::default(),
             point_lights: Default::default(),
+            spot_lights: Default::default(),
+            area_lights: Default::default(),
+            reflection_probe: None,
 
             switch_mesh: 0,
             switch_prog: 0,
@@ -171,7 +213,10 @@ impl EngineContext {
 
             states: Default::default(),
             last_light_bound: None,
+            last_light_layer: 0,
+            last_camera_bound: None,
             last_material_bound: None,
+            last_material_instancing: false,
         }
     }
 }
@@ -193,7 +238,89 @@ pub trait EngineCacher {
 impl_cacher!(prog, ShaderProgram);
 impl_cacher!(mesh_buffer, MeshBuffer);
 
-const MAX_TEXTURE_UNITS: u32 = 8;
+/// The minimum of `MAX_COMBINED_TEXTURE_IMAGE_UNITS` in WebGL
+const MIN_TEXTURE_UNITS: u32 = 8;
+/// Bound on the units of each LRU cache, to keep the lookups short
+const MAX_MATERIAL_TEXTURE_UNITS: u32 = 24;
+/// The most engine textures a program can sample in one draw: the two shadow
+/// maps, the LTC and cluster pairs, SSAO, camera depth, 2D lights, the two
+/// IBL maps and 5 cookies, see `Engine::setup_material`
+const MAX_ENGINE_TEXTURE_UNITS: u32 = 16;
+
+/// Number of texture units of the GL context
+pub fn max_texture_units(gl: &WebGLRenderingContext) -> u32 {
+    let units = gl.get_parameter(Parameter::MaxCombinedTextureImageUnits);
+
+    max(units, MIN_TEXTURE_UNITS as i32) as u32
+}
+
+/// Texture units `first..first + count`, reused in least recently used order
+#[derive(Debug)]
+pub struct TextureUnits {
+    first: u32,
+    count: u32,
+    bound: VecDeque<(u32, Weak<Texture>)>,
+    /// Units the current draw samples, which must not be reused before it
+    pinned: Vec<u32>,
+}
+
+impl TextureUnits {
+    pub fn new(first: u32, count: u32) -> TextureUnits {
+        TextureUnits {
+            first,
+            count,
+            bound: VecDeque::new(),
+            pinned: Vec::new(),
+        }
+    }
+
+    /// Forget the units pinned by the previous draw
+    pub fn begin_draw(&mut self) {
+        self.pinned.clear();
+    }
+
+    #[cfg_attr(feature = "flame_it", flame)]
+    pub fn find(&self, new_tex: &Rc<Texture>) -> Option<(usize, u32)> {
+        for (pos, &(u, ref tex)) in self.bound.iter().enumerate() {
+            if let Some(ref p) = tex.upgrade() {
+                if Rc::ptr_eq(new_tex, p) {
+                    return Some((pos, u));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The unit of `new_tex` if it is bound, moved to the back of the LRU order
+    fn touch(&mut self, new_tex: &Rc<Texture>) -> Option<u32> {
+        let (pos, unit) = self.find(new_tex)?;
+
+        self.bound.remove(pos);
+        self.bound.push_back((unit, Rc::downgrade(&new_tex)));
+
+        Some(unit)
+    }
+
+    /// A free unit, or the least recently used one not pinned by the current draw
+    fn take_unit(&mut self) -> Option<u32> {
+        let used = self.bound.len() as u32;
+        if used < self.count {
+            return Some(self.first + used);
+        }
+
+        let pinned = &self.pinned;
+        let unpinned = |u: u32| !pinned.contains(&u);
+
+        // find the empty slots.
+        let opt_pos = self.bound
+            .iter()
+            .position(|&(u, ref t)| t.upgrade().is_none() && unpinned(u))
+            .or_else(|| self.bound.iter().position(|&(u, _)| unpinned(u)));
+
+        opt_pos.map(|pos| self.bound.remove(pos).unwrap().0)
+    }
+}
 
 impl EngineContext {
     #[cfg_attr(feature = "flame_it", flame)]
@@ -210,65 +337,84 @@ impl EngineContext {
         Ok(())
     }
 
+    /// Bind a material texture, returns its unit
     #[cfg_attr(feature = "flame_it", flame)]
-    pub fn find_cache_tex(&self, new_tex: &Rc<Texture>) -> Option<(usize, u32)> {
-        for (pos, &(u, ref tex)) in self.textures.iter().enumerate() {
-            if let Some(ref p) = tex.upgrade() {
-                if Rc::ptr_eq(new_tex, p) {
-                    return Some((pos, u));
-                }
-            }
-        }
-
-        None
+    pub fn prepare_cache_tex<F>(&mut self, new_tex: &Rc<Texture>, bind_func: F) -> AssetResult<u32>
+    where
+        F: FnOnce(&mut EngineContext, u32) -> AssetResult<()>,
+    {
+        self.prepare_units_tex(false, new_tex, bind_func)
     }
 
+    /// Bind an engine texture in the reserved units, so it never evicts
+    /// the material textures of the same draw
     #[cfg_attr(feature = "flame_it", flame)]
-    pub fn prepare_cache_tex<F>(&mut self, new_tex: &Rc<Texture>, bind_func: F) -> AssetResult<u32>
+    pub fn prepare_engine_tex<F>(&mut self, new_tex: &Rc<Texture>, bind_func: F) -> AssetResult<u32>
     where
         F: FnOnce(&mut EngineContext, u32) -> AssetResult<()>,
     {
-        if let Some((pos, unit)) = self.find_cache_tex(new_tex) {
-            // move the used unit pos to the back
-            self.textures.remove(pos);
-            self.textures.push_back((unit, Rc::downgrade(&new_tex)));
+        self.prepare_units_tex(true, new_tex, bind_func)
+    }
 
-            return Ok(unit);
+    fn texture_units(&mut self, engine: bool) -> &mut TextureUnits {
+        if engine {
+            &mut self.engine_textures
+        } else {
+            &mut self.textures
         }
+    }
 
-        let mut unit = self.textures.len() as u32;
-
-        // find the empty slots.
-        if unit >= MAX_TEXTURE_UNITS {
-            let opt_pos = self.textures
-                .iter()
-                .position(|&(_, ref t)| t.upgrade().is_none());
-
-            unit = match opt_pos {
-                Some(pos) => self.textures.remove(pos).unwrap().0,
-                None => self.textures.pop_front().unwrap().0,
+    fn prepare_units_tex<F>(
+        &mut self,
+        engine: bool,
+        new_tex: &Rc<Texture>,
+        bind_func: F,
+    ) -> AssetResult<u32>
+    where
+        F: FnOnce(&mut EngineContext, u32) -> AssetResult<()>,
+    {
+        if let Some(unit) = self.texture_units(engine).touch(new_tex) {
+            if engine {
+                self.engine_textures.pinned.push(unit);
             }
+            return Ok(unit);
         }
 
+        let opt_unit = self.texture_units(engine).take_unit();
+        let unit = match opt_unit {
+            Some(unit) => unit,
+            None => panic!(
+                "A draw samples more engine textures than the {} reserved texture units",
+                self.engine_textures.count
+            ),
+        };
+
         debug_assert!(
-            self.textures
+            self.texture_units(engine)
+                .bound
                 .iter()
-                .position(|&(u, _)| u == (unit as u32))
+                .position(|&(u, _)| u == unit)
                 .is_none(),
-            format!("{:?}", self.textures)
+            format!("{:?}", self.texture_units(engine))
         );
 
-        let result = bind_func(self, unit).map(|_| {
-            self.textures.push_back((unit, Rc::downgrade(new_tex)));
-            unit
-        });
+        let result = bind_func(self, unit);
 
-        if result.is_err() {
-            // add the unit to the front
-            self.textures.push_front((unit, Weak::new()));
+        let units = self.texture_units(engine);
+        match result {
+            Ok(_) => {
+                units.bound.push_back((unit, Rc::downgrade(new_tex)));
+                if engine {
+                    units.pinned.push(unit);
+                }
+                Ok(unit)
+            }
+            Err(e) => {
+                // add the unit to the front
+                units.bound.push_front((unit, Weak::new()));
+                Err(e)
+            }
         }
-
-        result
     }
 
     #[cfg_attr(feature = "flame_it", flame)]
@@ -0,0 +1,127 @@
+use engine::core::{Component, ComponentBased, GameObject};
+
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The callbacks of a component type registered in `Engine::behaviours`,
+/// called by `Engine::update_behaviours` for the enabled components of the
+/// active game objects
+pub trait Behaviour {
+    /// Before the first update, once the object is active and the component
+    /// enabled
+    fn start(&mut self, _go: &mut GameObject) {}
+
+    fn update(&mut self, _go: &mut GameObject, _delta: f32) {}
+
+    /// After the updates of every behaviour, e.g. for a camera following an
+    /// object moved by its update
+    fn late_update(&mut self, _go: &mut GameObject, _delta: f32) {}
+
+    /// The started component is dropped, with its object or removed from it
+    fn on_destroy(&mut self) {}
+}
+
+struct BehaviourType {
+    type_id: TypeId,
+    call: Box<Fn(&Component, &mut FnMut(&mut Behaviour))>,
+}
+
+/// The component types having their `Behaviour` called each frame
+#[derive(Default)]
+pub struct Behaviours {
+    types: Vec<BehaviourType>,
+    /// By component id, with the index of their type. Holding the components
+    /// keeps them for `on_destroy`.
+    started: HashMap<u64, (usize, Arc<Component>)>,
+}
+
+impl Behaviours {
+    pub fn register<T>(&mut self)
+    where
+        T: Behaviour + ComponentBased + 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        if self.types.iter().any(|t| t.type_id == type_id) {
+            return;
+        }
+
+        self.types.push(BehaviourType {
+            type_id,
+            call: Box::new(|c: &Component, f: &mut FnMut(&mut Behaviour)| {
+                if let Some(c) = c.try_as::<T>() {
+                    f(&mut *c.borrow_mut());
+                }
+            }),
+        });
+    }
+
+    /// Start the new behaviours of `objects` then update them, the borrowed
+    /// objects are skipped
+    pub fn update(&mut self, objects: Vec<Rc<RefCell<GameObject>>>, delta: f32) {
+        let mut active = Vec::new();
+        for obj in objects {
+            let go = match obj.try_borrow() {
+                Ok(go) => go,
+                Err(_) => continue,
+            };
+
+            if !go.active {
+                continue;
+            }
+
+            for c in go.components() {
+                if !c.enabled() {
+                    continue;
+                }
+
+                if let Some(i) = self.types.iter().position(|t| t.type_id == c.typeid()) {
+                    active.push((obj.clone(), i, c.clone()));
+                }
+            }
+        }
+
+        // The new behaviours start before any update of the frame
+        for &(ref obj, i, ref c) in active.iter() {
+            if self.started.contains_key(&c.id()) {
+                continue;
+            }
+
+            self.started.insert(c.id(), (i, c.clone()));
+            if let Ok(mut go) = obj.try_borrow_mut() {
+                (self.types[i].call)(&**c, &mut |b| b.start(&mut go));
+            }
+        }
+
+        for &(ref obj, i, ref c) in active.iter() {
+            if let Ok(mut go) = obj.try_borrow_mut() {
+                (self.types[i].call)(&**c, &mut |b| b.update(&mut go, delta));
+            }
+        }
+
+        for &(ref obj, i, ref c) in active.iter() {
+            if let Ok(mut go) = obj.try_borrow_mut() {
+                (self.types[i].call)(&**c, &mut |b| b.late_update(&mut go, delta));
+            }
+        }
+
+        drop(active);
+        self.destroy_dropped();
+    }
+
+    /// Call `on_destroy` of the started components only held here
+    fn destroy_dropped(&mut self) {
+        let dropped: Vec<u64> = self.started
+            .iter()
+            .filter(|&(_, &(_, ref c))| Arc::strong_count(c) == 1)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in dropped {
+            let (i, c) = self.started.remove(&id).unwrap();
+            (self.types[i].call)(&*c, &mut |b| b.on_destroy());
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use math::*;
 use std::any::{Any, TypeId};
-use std::cell::{Ref, RefCell, RefMut};
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::marker::PhantomData;
 use std::rc;
 use std::rc::Rc;
@@ -8,10 +8,21 @@ use std::sync::Arc;
 
 use super::component_arena::ComponentArena;
 use super::scene_tree::{ComponentEvent, NodeTransform, SceneTree};
+use super::layer::Layers;
+use super::tag::Tag;
 
 use std::sync::atomic::AtomicU32;
 use std::sync::atomic::Ordering;
 
+/// Incremented by every `Component::set_enabled` changing the state
+static ENABLED_CHANGES: AtomicU32 = AtomicU32::new(0);
+
+/// A counter changing whenever a component is enabled or disabled, to detect
+/// the changes without walking the objects
+pub fn component_enabled_changes() -> u32 {
+    ENABLED_CHANGES.load(Ordering::SeqCst)
+}
+
 fn next_component_id() -> u64 {
     static CURR_COMPONENT_COUNTER: AtomicU32 = AtomicU32::new(1);;
 
@@ -24,6 +35,12 @@ pub trait Component: Any {
     fn typeid(&self) -> TypeId;
 
     fn as_any(&self) -> &Any;
+
+    /// A disabled component is kept on its object but skipped by the engine,
+    /// e.g. a `Mesh` is not rendered and a `Light` does not light
+    fn enabled(&self) -> bool;
+
+    fn set_enabled(&self, enabled: bool);
 }
 
 pub struct ComponentType<T: 'static> {
@@ -33,6 +50,7 @@ pub struct ComponentType<T: 'static> {
 
     // data is a kind of lock to do runtime borrow checking
     data: RefCell<()>,
+    enabled: Cell<bool>,
 }
 
 impl<T: 'static> ComponentType<T> {
@@ -62,6 +80,16 @@ where
     fn as_any(&self) -> &Any {
         self
     }
+
+    fn enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        if self.enabled.replace(enabled) != enabled {
+            ENABLED_CHANGES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
 }
 
 impl<T> Drop for ComponentType<T>
@@ -99,6 +127,7 @@ impl Component {
             arena: arena.clone(),
             phantom: PhantomData::default(),
             data: RefCell::new(()),
+            enabled: Cell::new(true),
         };
 
         Arc::new(c)
@@ -122,7 +151,11 @@ impl GameObjectUtil {
         GameObject {
             transform: Transform::new(node_id, tree),
             arena: Rc::downgrade(arena),
+            name: String::new(),
             active: true,
+            tag: Tag::default(),
+            layer: 0,
+            is_static: false,
             components: vec![],
         }
     }
@@ -161,15 +194,10 @@ impl Transform {
         tree.get_local_matrix(self.node_id)
     }
 
+    /// The world matrix, cached until the transform or an ancestor one changes
     pub fn as_global_matrix(&self) -> Matrix4<f32> {
-        self.parent_global_matrix() * self.as_local_matrix()
-    }
-
-    fn parent_global_matrix(&self) -> Matrix4<f32> {
         let tree = self.tree.upgrade().unwrap();
-        let parent_id = tree.get_parent_id(self.node_id);
-
-        tree.get_global_matrix(parent_id)
+        tree.get_global_matrix(self.node_id)
     }
 
     pub fn global(&self) -> Isometry3<f32> {
@@ -224,7 +252,17 @@ impl Transform {
 
 pub struct GameObject {
     pub transform: Transform,
+    /// Not unique, see `SceneTree::find` and `SceneTree::find_by_name`
+    pub name: String,
     pub active: bool,
+    /// See `set_tag` and `Engine::find_objects_with_tag`
+    pub tag: Tag,
+    /// Layer index in 0..32, see `Layers`. Matched against the culling masks of
+    /// the cameras and lights, the collision matrix of `Physics2D` and the
+    /// masks of `Engine::raycast`.
+    pub layer: u32,
+    /// The object never moves, its mesh can be merged by `Engine::build_static_batches`
+    pub is_static: bool,
     components: Vec<Arc<Component>>,
     arena: rc::Weak<ComponentArena>,
 }
@@ -234,7 +272,11 @@ impl GameObject {
     pub fn empty() -> Rc<RefCell<GameObject>> {
         Rc::new(RefCell::new(GameObject {
             transform: Transform::new(0, rc::Weak::new()),
+            name: String::new(),
             active: true,
+            tag: Tag::default(),
+            layer: 0,
+            is_static: false,
             arena: rc::Weak::new(),
             components: vec![],
         }))
@@ -244,6 +286,32 @@ impl GameObject {
         self.transform.tree.upgrade().unwrap()
     }
 
+    /// Tag the object, e.g. "enemy", "" to untag it
+    pub fn set_tag(&mut self, tag: &str) {
+        self.tag = Tag::new(tag);
+    }
+
+    pub fn compare_tag(&self, tag: &str) -> bool {
+        Tag::find(tag) == Some(self.tag)
+    }
+
+    /// Move the object to the layer registered as `name`, false when there is
+    /// none
+    pub fn set_layer_name(&mut self, name: &str) -> bool {
+        match Layers::index(name) {
+            Some(layer) => {
+                self.layer = layer;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// In the order they were added
+    pub fn components(&self) -> &[Arc<Component>] {
+        &self.components
+    }
+
     pub fn find_component<T>(&self) -> Option<(Ref<T>, &Arc<Component>)>
     where
         T: 'static,
@@ -259,6 +327,19 @@ impl GameObject {
         }
     }
 
+    /// The first enabled component of type `T`, see `Component::enabled`
+    pub fn find_enabled_component<T>(&self) -> Option<(Ref<T>, &Arc<Component>)>
+    where
+        T: 'static,
+    {
+        let typeid = TypeId::of::<T>();
+
+        self.components
+            .iter()
+            .find(|c| c.typeid() == typeid && c.enabled())
+            .map(|c| (c.try_as::<T>().unwrap().borrow(), c))
+    }
+
     pub fn find_component_mut<T>(&self) -> Option<(RefMut<T>, Arc<Component>)>
     where
         T: 'static,
@@ -325,4 +406,14 @@ impl GameObject {
     pub fn childen(&self) -> Vec<Rc<RefCell<GameObject>>> {
         self.tree().get_childen(self.transform.node_id)
     }
+
+    /// The descendant at `path` relative to this object, e.g. "Arm/Hand"
+    pub fn find(&self, path: &str) -> Option<Rc<RefCell<GameObject>>> {
+        self.tree().find_from(self.transform.node_id, path)
+    }
+
+    /// The names from the root to this object, e.g. "Player/Arm/Hand"
+    pub fn path(&self) -> String {
+        self.tree().path(self.transform.node_id, &self.name)
+    }
 }
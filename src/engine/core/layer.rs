@@ -0,0 +1,69 @@
+use std::cell::RefCell;
+
+thread_local!(static LAYER_NAMES: RefCell<Vec<String>> = RefCell::new({
+    let mut names = vec![String::new(); 32];
+    names[0] = "Default".to_string();
+    names
+}));
+
+/// The names of the 32 layers of `GameObject::layer`, used to build the
+/// masks of `Camera::culling_mask`, `Light::culling_mask`, the collision
+/// matrix of `Physics2D` and `Engine::raycast`. Layer 0 is "Default".
+pub struct Layers;
+
+impl Layers {
+    /// Name the layer `index`, an empty name unnames it. A name is only kept
+    /// by one layer.
+    pub fn register(index: u32, name: &str) {
+        assert!(index < 32, "Layer index {} out of 0..32", index);
+
+        LAYER_NAMES.with(|names| {
+            let mut names = names.borrow_mut();
+            if !name.is_empty() {
+                for n in names.iter_mut().filter(|n| *n == name) {
+                    n.clear();
+                }
+            }
+
+            names[index as usize] = name.to_string();
+        });
+    }
+
+    /// The name of the layer `index`, None when it is unnamed
+    pub fn name(index: u32) -> Option<String> {
+        if index >= 32 {
+            return None;
+        }
+
+        LAYER_NAMES.with(|names| {
+            let name = &names.borrow()[index as usize];
+            if name.is_empty() {
+                None
+            } else {
+                Some(name.clone())
+            }
+        })
+    }
+
+    pub fn index(name: &str) -> Option<u32> {
+        if name.is_empty() {
+            return None;
+        }
+
+        LAYER_NAMES.with(|names| {
+            names
+                .borrow()
+                .iter()
+                .position(|n| n == name)
+                .map(|i| i as u32)
+        })
+    }
+
+    /// The mask of the layers `names`, the unknown names are skipped
+    pub fn mask(names: &[&str]) -> u32 {
+        names
+            .iter()
+            .filter_map(|name| Layers::index(name))
+            .fold(0, |mask, i| mask | (1 << i))
+    }
+}
@@ -64,6 +64,26 @@ impl Aabb {
         ]
     }
 
+    pub fn contains(&self, other: &Self) -> bool {
+        self.min.x <= other.min.x && self.min.y <= other.min.y && self.min.z <= other.min.z
+            && self.max.x >= other.max.x && self.max.y >= other.max.y
+            && self.max.z >= other.max.z
+    }
+
+    pub fn expanded(&self, margin: f32) -> Self {
+        let m = Vector3f::new(margin, margin, margin);
+
+        Self {
+            min: self.min - m,
+            max: self.max + m,
+        }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     pub fn sphere(&self) -> (Vector3f, f32) {
         let center = (self.max + self.min) * 0.5;
 
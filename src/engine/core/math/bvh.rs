@@ -0,0 +1,322 @@
+use super::Aabb;
+
+struct BvhNode<T> {
+    aabb: Aabb,
+    parent: Option<usize>,
+    children: Option<(usize, usize)>,
+    data: Option<T>,
+}
+
+/// A dynamic aabb tree, leaves are kept with a margin around their aabb so
+/// small moves don't change the tree
+pub struct Bvh<T> {
+    nodes: Vec<BvhNode<T>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    margin: f32,
+}
+
+impl<T> Default for Bvh<T> {
+    fn default() -> Bvh<T> {
+        Bvh::new(0.1)
+    }
+}
+
+fn merged(a: &Aabb, b: &Aabb) -> Aabb {
+    let mut m = *a;
+    m.merge(b);
+    m
+}
+
+impl<T> Bvh<T> {
+    pub fn new(margin: f32) -> Bvh<T> {
+        Bvh {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            margin,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.iter().filter(|n| n.data.is_some()).count()
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = None;
+    }
+
+    /// Add a leaf, returns its id
+    pub fn insert(&mut self, aabb: Aabb, data: T) -> usize {
+        let aabb = aabb.expanded(self.margin);
+        let leaf = self.alloc(BvhNode {
+            aabb,
+            parent: None,
+            children: None,
+            data: Some(data),
+        });
+
+        self.insert_leaf(leaf);
+        leaf
+    }
+
+    pub fn remove(&mut self, leaf: usize) -> Option<T> {
+        if self.nodes[leaf].data.is_none() {
+            return None;
+        }
+
+        self.remove_leaf(leaf);
+        let data = self.nodes[leaf].data.take();
+        self.release(leaf);
+
+        data
+    }
+
+    /// Move a leaf, the tree only changes when the aabb leaves the margin.
+    /// Returns whether it did.
+    pub fn update(&mut self, leaf: usize, aabb: Aabb) -> bool {
+        if self.nodes[leaf].aabb.contains(&aabb) {
+            return false;
+        }
+
+        self.remove_leaf(leaf);
+        let aabb = aabb.expanded(self.margin);
+        self.nodes[leaf].aabb = aabb;
+        self.insert_leaf(leaf);
+
+        true
+    }
+
+    pub fn get(&self, leaf: usize) -> Option<&T> {
+        self.nodes.get(leaf).and_then(|n| n.data.as_ref())
+    }
+
+    /// Visit the leaves whose aabb and every parent one pass `test`
+    pub fn query<F, V>(&self, mut test: F, mut visit: V)
+    where
+        F: FnMut(&Aabb) -> bool,
+        V: FnMut(&T),
+    {
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if !test(&node.aabb) {
+                continue;
+            }
+
+            match node.children {
+                Some((a, b)) => {
+                    stack.push(a);
+                    stack.push(b);
+                }
+                None => {
+                    if let Some(ref data) = node.data {
+                        visit(data);
+                    }
+                }
+            }
+        }
+    }
+
+    fn alloc(&mut self, node: BvhNode<T>) -> usize {
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, i: usize) {
+        let node = &mut self.nodes[i];
+        node.parent = None;
+        node.children = None;
+        node.data = None;
+
+        self.free.push(i);
+    }
+
+    fn replace_child(&mut self, parent: Option<usize>, old: usize, new: usize) {
+        match parent {
+            None => self.root = Some(new),
+            Some(p) => {
+                let (a, b) = self.nodes[p].children.unwrap();
+                self.nodes[p].children = Some(if a == old { (new, b) } else { (a, new) });
+            }
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        let mut sibling = match self.root {
+            Some(root) => root,
+            None => {
+                self.nodes[leaf].parent = None;
+                self.root = Some(leaf);
+                return;
+            }
+        };
+
+        // Walk down where the aabbs grow the least
+        let aabb = self.nodes[leaf].aabb;
+        while let Some((a, b)) = self.nodes[sibling].children {
+            let cost = |i: usize| {
+                let node_aabb = &self.nodes[i].aabb;
+                merged(node_aabb, &aabb).surface_area() - node_aabb.surface_area()
+            };
+
+            sibling = if cost(a) <= cost(b) { a } else { b };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent_aabb = merged(&self.nodes[sibling].aabb, &aabb);
+        let new_parent = self.alloc(BvhNode {
+            aabb: parent_aabb,
+            parent: old_parent,
+            children: Some((sibling, leaf)),
+            data: None,
+        });
+
+        self.nodes[sibling].parent = Some(new_parent);
+        self.nodes[leaf].parent = Some(new_parent);
+        self.replace_child(old_parent, sibling, new_parent);
+
+        self.refit(old_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        let parent = match self.nodes[leaf].parent {
+            Some(parent) => parent,
+            None => {
+                self.root = None;
+                return;
+            }
+        };
+
+        let (a, b) = self.nodes[parent].children.unwrap();
+        let sibling = if a == leaf { b } else { a };
+        let grand_parent = self.nodes[parent].parent;
+
+        self.nodes[sibling].parent = grand_parent;
+        self.replace_child(grand_parent, parent, sibling);
+        self.nodes[leaf].parent = None;
+        self.release(parent);
+
+        self.refit(grand_parent);
+    }
+
+    fn refit(&mut self, mut i: Option<usize>) {
+        while let Some(n) = i {
+            let (a, b) = self.nodes[n].children.unwrap();
+            self.nodes[n].aabb = merged(&self.nodes[a].aabb, &self.nodes[b].aabb);
+            i = self.nodes[n].parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::Vector3f;
+    use std::collections::BTreeSet;
+
+    fn aabb(x: f32, y: f32, size: f32) -> Aabb {
+        Aabb {
+            min: Vector3f::new(x, y, 0.0),
+            max: Vector3f::new(x + size, y + size, size),
+        }
+    }
+
+    fn overlaps(a: &Aabb, b: &Aabb) -> bool {
+        a.min.x <= b.max.x && b.min.x <= a.max.x && a.min.y <= b.max.y && b.min.y <= a.max.y
+            && a.min.z <= b.max.z && b.min.z <= a.max.z
+    }
+
+    fn query(bvh: &Bvh<usize>, area: &Aabb) -> BTreeSet<usize> {
+        let mut found = BTreeSet::new();
+        bvh.query(|b| overlaps(b, area), |&i| assert!(found.insert(i)));
+        found
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut bvh = Bvh::new(0.0);
+        let a = bvh.insert(aabb(0.0, 0.0, 1.0), 0);
+        let b = bvh.insert(aabb(5.0, 0.0, 1.0), 1);
+        let c = bvh.insert(aabb(0.0, 5.0, 1.0), 2);
+        assert_eq!(bvh.len(), 3);
+
+        let all = aabb(-10.0, -10.0, 20.0);
+        assert_eq!(query(&bvh, &all), vec![0, 1, 2].into_iter().collect());
+        assert_eq!(query(&bvh, &aabb(4.5, -0.5, 1.0)), vec![1].into_iter().collect());
+
+        assert_eq!(bvh.remove(b), Some(1));
+        assert_eq!(bvh.remove(b), None);
+        assert_eq!(query(&bvh, &all), vec![0, 2].into_iter().collect());
+
+        // The freed nodes are reused
+        let d = bvh.insert(aabb(5.0, 5.0, 1.0), 3);
+        assert_eq!(d, b);
+        assert_eq!(bvh.get(d), Some(&3));
+
+        assert_eq!(bvh.remove(a), Some(0));
+        assert_eq!(bvh.remove(c), Some(2));
+        assert_eq!(bvh.remove(d), Some(3));
+        assert_eq!(bvh.len(), 0);
+        assert!(query(&bvh, &all).is_empty());
+    }
+
+    #[test]
+    fn churn() {
+        let mut seed = 12345u32;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+
+        let mut bvh = Bvh::new(0.0);
+        // The aabbs of the leaves, the tree keeps the old one of a move in it
+        let mut leaves: Vec<Option<(usize, Aabb)>> = Vec::new();
+
+        for step in 0..2000 {
+            let i = (random() * 64.0) as usize;
+            let moved = aabb(random() * 100.0, random() * 100.0, random() * 8.0);
+
+            if leaves.len() <= i {
+                leaves.resize(i + 1, None);
+            }
+
+            leaves[i] = match leaves[i] {
+                None => Some((bvh.insert(moved, i), moved)),
+                Some((leaf, _)) if step % 5 == 0 => {
+                    assert_eq!(bvh.remove(leaf), Some(i));
+                    None
+                }
+                Some((leaf, old)) => {
+                    let changed = bvh.update(leaf, moved);
+                    assert_eq!(changed, !old.contains(&moved));
+                    Some((leaf, if changed { moved } else { old }))
+                }
+            };
+
+            let area = aabb(random() * 100.0, random() * 100.0, random() * 30.0);
+            let expected: BTreeSet<usize> = leaves
+                .iter()
+                .enumerate()
+                .filter_map(|(i, leaf)| match *leaf {
+                    Some((_, ref b)) if overlaps(b, &area) => Some(i),
+                    _ => None,
+                })
+                .collect();
+
+            assert_eq!(query(&bvh, &area), expected);
+            assert_eq!(bvh.len(), leaves.iter().filter(|l| l.is_some()).count());
+        }
+    }
+}
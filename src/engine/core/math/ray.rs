@@ -0,0 +1,53 @@
+use super::Aabb;
+use math::*;
+use std::f32;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Vector3f,
+    /// Normalized direction
+    pub direction: Vector3f,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3f, direction: Vector3f) -> Ray {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// The point at distance `t` along the ray
+    pub fn at(&self, t: f32) -> Vector3f {
+        self.origin + self.direction * t
+    }
+
+    /// Distance to the first hit with the aabb, 0 when the origin is inside
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut tmin: f32 = 0.0;
+        let mut tmax = f32::MAX;
+
+        for i in 0..3 {
+            let (o, d) = (self.origin[i], self.direction[i]);
+
+            if d.abs() < 1e-8 {
+                if o < aabb.min[i] || o > aabb.max[i] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t1 = (aabb.min[i] - o) / d;
+            let t2 = (aabb.max[i] - o) / d;
+
+            tmin = tmin.max(t1.min(t2));
+            tmax = tmax.min(t1.max(t2));
+
+            if tmin > tmax {
+                return None;
+            }
+        }
+
+        Some(tmin)
+    }
+}
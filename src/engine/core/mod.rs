@@ -1,12 +1,21 @@
+mod behaviour;
 mod component_arena;
 mod game_object;
+mod layer;
 mod math;
+mod scene_format;
 mod scene_tree;
+mod tag;
 
+pub use self::behaviour::{Behaviour, Behaviours};
 pub use self::component_arena::ComponentArena;
-pub use self::game_object::{Component, ComponentBased, ComponentType, GameObject, IntoComponentPtr};
+pub use self::game_object::{component_enabled_changes, Component, ComponentBased, ComponentType,
+                            GameObject, IntoComponentPtr};
+pub use self::layer::Layers;
 pub use self::math::*;
+pub use self::scene_format::{SceneFormat, SceneInstance};
 pub use self::scene_tree::{ComponentEvent, SceneTree};
+pub use self::tag::Tag;
 
 pub mod internal {
     pub use super::game_object::GameObjectUtil;
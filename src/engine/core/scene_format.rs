@@ -0,0 +1,229 @@
+use engine::core::{Component, ComponentBased, GameObject, IntoComponentPtr, SceneTree};
+use engine::IEngine;
+use math::*;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use serde_json::{Map, Value};
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+struct ComponentFormat {
+    name: String,
+    type_id: TypeId,
+    save: Box<Fn(&Component) -> Result<Value, String>>,
+    load: Box<Fn(&mut GameObject, Value) -> Result<Arc<Component>, String>>,
+}
+
+/// The game objects of a loaded scene, keep it to keep them alive
+pub struct SceneInstance {
+    pub roots: Vec<Rc<RefCell<GameObject>>>,
+    /// Every game object of the scene, parents first
+    pub objects: Vec<Rc<RefCell<GameObject>>>,
+}
+
+/// Saves the game objects of a `SceneTree` to JSON and loads them back, so the
+/// levels can be data. An object has its local transform, `name`, `active`,
+/// `tag`, `layer`, `is_static`, its children and the components of the
+/// registered types, the others are not saved:
+///
+/// ```json
+/// { "objects": [{
+///     "name": "Goblin", "active": true, "tag": "enemy", "layer": 0, "static": false,
+///     "position": [0, 1, 0], "rotation": [0, 0, 0, 1], "scale": [1, 1, 1],
+///     "components": [{ "type": "Spin", "enabled": true, "data": { "speed": 2.0 } }],
+///     "children": []
+/// }] }
+/// ```
+///
+/// The rotation is a quaternion, x y z then w. The missing fields keep the
+/// defaults of a new game object.
+#[derive(Default)]
+pub struct SceneFormat {
+    components: Vec<ComponentFormat>,
+}
+
+fn floats(v: &Value, default: &[f32]) -> Result<Vec<f32>, String> {
+    if v.is_null() {
+        return Ok(default.to_vec());
+    }
+
+    let list = match v.as_array() {
+        Some(list) if list.len() == default.len() => list,
+        _ => return Err(format!("{} is not {} numbers", v, default.len())),
+    };
+
+    list.iter()
+        .map(|f| f.as_f64().map(|f| f as f32))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("{} is not {} numbers", v, default.len()))
+}
+
+impl SceneFormat {
+    pub fn new() -> SceneFormat {
+        Default::default()
+    }
+
+    /// Save and load the components of type `T` with serde, as `name` in the
+    /// scene files
+    pub fn register<T>(&mut self, name: &str)
+    where
+        T: ComponentBased + IntoComponentPtr + Serialize + DeserializeOwned + 'static,
+    {
+        self.register_with::<T, _, _>(
+            name,
+            |c| serde_json::to_value(c).map_err(|e| format!("{}", e)),
+            |v| serde_json::from_value(v).map_err(|e| format!("{}", e)),
+        );
+    }
+
+    /// Save and load the components of type `T` with conversions of their own,
+    /// e.g. for the components holding assets
+    pub fn register_with<T, S, L>(&mut self, name: &str, save: S, load: L)
+    where
+        T: ComponentBased + IntoComponentPtr + 'static,
+        S: Fn(&T) -> Result<Value, String> + 'static,
+        L: Fn(Value) -> Result<T, String> + 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        self.components.retain(|f| f.name != name && f.type_id != type_id);
+
+        self.components.push(ComponentFormat {
+            name: name.to_string(),
+            type_id,
+            save: Box::new(move |c| match c.try_as::<T>() {
+                Some(c) => save(&*c.borrow()),
+                None => Err("Mismatched component type".to_string()),
+            }),
+            load: Box::new(move |go, v| Ok(go.add_component(load(v)?))),
+        });
+    }
+
+    /// The JSON of the game objects of `tree`
+    pub fn save(&self, tree: &SceneTree) -> Result<String, String> {
+        let objects = tree.root()
+            .childen()
+            .iter()
+            .map(|go| self.save_object(&go.borrow()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut root = Map::new();
+        root.insert("objects".to_string(), Value::Array(objects));
+
+        serde_json::to_string_pretty(&Value::Object(root)).map_err(|e| format!("{}", e))
+    }
+
+    fn save_object(&self, go: &GameObject) -> Result<Value, String> {
+        let local = go.transform.local();
+        let (p, r) = (local.disp, local.rot);
+        let s = go.transform.local_scale();
+
+        let mut components = Vec::new();
+        for c in go.components() {
+            if let Some(format) = self.components.iter().find(|f| f.type_id == c.typeid()) {
+                let data = (format.save)(&**c)
+                    .map_err(|e| format!("Failed to save {}, reason {}", format.name, e))?;
+
+                let mut component = Map::new();
+                component.insert("type".to_string(), Value::from(format.name.clone()));
+                component.insert("enabled".to_string(), Value::from(c.enabled()));
+                component.insert("data".to_string(), data);
+                components.push(Value::Object(component));
+            }
+        }
+
+        let children = go.childen()
+            .iter()
+            .map(|child| self.save_object(&child.borrow()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut obj = Map::new();
+        obj.insert("name".to_string(), Value::from(go.name.clone()));
+        obj.insert("active".to_string(), Value::from(go.active));
+        obj.insert("tag".to_string(), Value::from(go.tag.name()));
+        obj.insert("layer".to_string(), Value::from(go.layer));
+        obj.insert("static".to_string(), Value::from(go.is_static));
+        obj.insert("position".to_string(), Value::from(vec![p.x, p.y, p.z]));
+        obj.insert("rotation".to_string(), Value::from(vec![r.v.x, r.v.y, r.v.z, r.s]));
+        obj.insert("scale".to_string(), Value::from(vec![s.x, s.y, s.z]));
+        obj.insert("components".to_string(), Value::Array(components));
+        obj.insert("children".to_string(), Value::Array(children));
+
+        Ok(Value::Object(obj))
+    }
+
+    /// Create the game objects of the scene `text` under `parent`. On error
+    /// the objects created so far are removed.
+    pub fn load(
+        &self,
+        engine: &mut IEngine,
+        parent: &GameObject,
+        text: &str,
+    ) -> Result<SceneInstance, String> {
+        let root: Value = serde_json::from_str(text).map_err(|e| format!("{}", e))?;
+        let list = root["objects"]
+            .as_array()
+            .ok_or_else(|| "No objects".to_string())?;
+
+        let mut objects = Vec::new();
+        let roots = list.iter()
+            .map(|v| self.load_object(engine, parent, v, &mut objects))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SceneInstance { roots, objects })
+    }
+
+    fn load_object(
+        &self,
+        engine: &mut IEngine,
+        parent: &GameObject,
+        v: &Value,
+        objects: &mut Vec<Rc<RefCell<GameObject>>>,
+    ) -> Result<Rc<RefCell<GameObject>>, String> {
+        let go = engine.new_game_object(parent);
+        objects.push(go.clone());
+
+        {
+            let mut go = go.borrow_mut();
+            go.name = v["name"].as_str().unwrap_or("").to_string();
+            go.active = v["active"].as_bool().unwrap_or(true);
+            go.set_tag(v["tag"].as_str().unwrap_or(""));
+            go.layer = v["layer"].as_u64().unwrap_or(0) as u32;
+            go.is_static = v["static"].as_bool().unwrap_or(false);
+
+            let p = floats(&v["position"], &[0.0, 0.0, 0.0])?;
+            let r = floats(&v["rotation"], &[0.0, 0.0, 0.0, 1.0])?;
+            let s = floats(&v["scale"], &[1.0, 1.0, 1.0])?;
+
+            go.transform.set_local(Decomposed {
+                scale: 1.0,
+                rot: Quaternion::new(r[3], r[0], r[1], r[2]).normalize(),
+                disp: Vector3::new(p[0], p[1], p[2]),
+            });
+            go.transform.set_local_scale(Vector3::new(s[0], s[1], s[2]));
+
+            for c in v["components"].as_array().into_iter().flat_map(|c| c.iter()) {
+                let name = c["type"]
+                    .as_str()
+                    .ok_or_else(|| "A component without type".to_string())?;
+                let format = self.components
+                    .iter()
+                    .find(|f| f.name == name)
+                    .ok_or_else(|| format!("Unregistered component type {}", name))?;
+
+                let component = (format.load)(&mut *go, c["data"].clone())
+                    .map_err(|e| format!("Failed to load {}, reason {}", name, e))?;
+                component.set_enabled(c["enabled"].as_bool().unwrap_or(true));
+            }
+        }
+
+        for child in v["children"].as_array().into_iter().flat_map(|c| c.iter()) {
+            self.load_object(engine, &go.borrow(), child, objects)?;
+        }
+
+        Ok(go)
+    }
+}
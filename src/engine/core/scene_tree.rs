@@ -27,6 +27,7 @@ struct Node {
     go: Weak<RefCell<GameObject>>,
     transform: NodeTransform,
     global_m_cache: Matrix4f,
+    /// `global_m_cache` is stale, the children of a dirty node are dirty too
     dirty: bool,
 }
 
@@ -41,6 +42,9 @@ pub struct SceneTree {
     nodes: RefCell<BTreeMap<u64, Node>>,
     curr_id: Cell<u64>,
     weak_self: RefCell<Weak<SceneTree>>,
+    /// Objects moved, or whose components were added or removed, since the
+    /// last `take_changed`
+    changed: RefCell<Vec<Weak<RefCell<GameObject>>>>,
 
     component_watcher:
         RefCell<Vec<Box<FnMut(ComponentEvent, &Rc<RefCell<GameObject>>, &Arc<Component>)>>>,
@@ -59,6 +63,7 @@ impl SceneTree {
             nodes: RefCell::new(BTreeMap::default()),
             root: GameObject::empty(),
             weak_self: RefCell::new(Weak::new()),
+            changed: Default::default(),
             curr_id: Cell::new(1),
             component_watcher: Default::default(),
         };
@@ -145,11 +150,16 @@ impl SceneTree {
         parent_node.children.retain(|&x| x != node_id);
         drop(parent_node);
 
-        for child_id in children_id {
-            let child_node = nodes.get_mut(&child_id).unwrap();
+        for child_id in children_id.iter() {
+            let child_node = nodes.get_mut(child_id).unwrap();
             // Root adapted.
             child_node.parent = 0;
         }
+        drop(nodes);
+
+        for child_id in children_id {
+            self.set_dirty(child_id);
+        }
     }
 
     pub fn add_child(&self, parent_id: u64, child_id: u64) -> Rc<RefCell<GameObject>> {
@@ -168,7 +178,11 @@ impl SceneTree {
         let parent_node = nodes.get_mut(&old_parent_id).unwrap();
         parent_node.children.retain(|&x| x != child_id);
 
-        parent_node.go.upgrade().unwrap_or(self.root.clone())
+        let old_parent = parent_node.go.upgrade().unwrap_or(self.root.clone());
+        drop(nodes);
+
+        self.set_dirty(child_id);
+        old_parent
     }
 
     pub fn set_local_transform(&self, node_id: u64, t: NodeTransform) {
@@ -176,26 +190,40 @@ impl SceneTree {
         let n = nodes.get_mut(&node_id).unwrap();
 
         n.transform = t;
-        n.dirty = true;
         drop(nodes);
 
         // set all child
         self.set_dirty(node_id);
     }
 
+    /// Mark the world matrix of the node and its descendants to recompute
     pub fn set_dirty(&self, node_id: u64) {
         let mut nodes = self.nodes.borrow_mut();
-        let n = nodes.get_mut(&node_id).unwrap();
+        let mut changed = self.changed.borrow_mut();
+        let mut stack = vec![node_id];
 
-        if n.dirty {
-            return;
-        }
+        while let Some(id) = stack.pop() {
+            let n = nodes.get_mut(&id).unwrap();
 
-        for c in n.children.iter() {
-            self.set_dirty(*c);
+            // A dirty child has dirty descendants already, which were
+            // reported when they became dirty
+            if n.dirty && id != node_id {
+                continue;
+            }
+
+            n.dirty = true;
+            changed.push(n.go.clone());
+            stack.extend(n.children.iter().cloned());
         }
     }
 
+    /// The objects moved, or whose components were added or removed, since
+    /// the previous call, e.g. to update a spatial index. May hold duplicates
+    /// and dropped objects.
+    pub fn take_changed(&self) -> Vec<Weak<RefCell<GameObject>>> {
+        self.changed.replace(Vec::new())
+    }
+
     pub fn get_local_transform(&self, node_id: u64) -> NodeTransform {
         let nodes = self.nodes.borrow();
         nodes.get(&node_id).unwrap().transform
@@ -283,8 +311,91 @@ impl SceneTree {
         self.nodes.borrow().len()
     }
 
+    /// The first child of `node_id` named `name`, the borrowed children are skipped
+    fn find_child(&self, node_id: u64, name: &str) -> Option<u64> {
+        let nodes = self.nodes.borrow();
+
+        nodes.get(&node_id)?.children.iter().cloned().find(|id| {
+            nodes
+                .get(id)
+                .and_then(|n| n.go.upgrade())
+                .map_or(false, |go| go.try_borrow().map(|go| go.name == name).unwrap_or(false))
+        })
+    }
+
+    /// The object at `path` below the root, the names separated by '/', e.g.
+    /// "Player/Arm/Hand". Each name takes the first child having it.
+    pub fn find(&self, path: &str) -> Option<Rc<RefCell<GameObject>>> {
+        self.find_from(0, path)
+    }
+
+    /// The object at `path` below the node `node_id`
+    pub fn find_from(&self, node_id: u64, path: &str) -> Option<Rc<RefCell<GameObject>>> {
+        let mut current = node_id;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            current = self.find_child(current, name)?;
+        }
+
+        if current == node_id {
+            return None;
+        }
+
+        self.nodes.borrow().get(&current)?.go.upgrade()
+    }
+
+    /// The first object named `name`, depth first
+    pub fn find_by_name(&self, name: &str) -> Option<Rc<RefCell<GameObject>>> {
+        let nodes = self.nodes.borrow();
+        let mut stack = vec![0];
+
+        while let Some(id) = stack.pop() {
+            let node = match nodes.get(&id) {
+                Some(node) => node,
+                None => continue,
+            };
+
+            if id != 0 {
+                if let Some(go) = node.go.upgrade() {
+                    if go.try_borrow().map(|go| go.name == name).unwrap_or(false) {
+                        return Some(go);
+                    }
+                }
+            }
+
+            stack.extend(node.children.iter().rev());
+        }
+
+        None
+    }
+
+    /// The path of the node `node_id` named `name`, from the names of its parents
+    pub fn path(&self, node_id: u64, name: &str) -> String {
+        let nodes = self.nodes.borrow();
+        let mut names = vec![name.to_string()];
+
+        let mut id = nodes.get(&node_id).map_or(0, |n| n.parent);
+        while id != 0 {
+            let node = match nodes.get(&id) {
+                Some(node) => node,
+                None => break,
+            };
+
+            names.push(
+                node.go
+                    .upgrade()
+                    .and_then(|go| go.try_borrow().ok().map(|go| go.name.clone()))
+                    .unwrap_or_default(),
+            );
+            id = node.parent;
+        }
+
+        names.reverse();
+        names.join("/")
+    }
+
     pub fn notifiy_component(&self, evt: ComponentEvent, node_id: u64, c: Arc<Component>) {
         let go = { self.nodes.borrow().get(&node_id).unwrap().go.clone() };
+        self.changed.borrow_mut().push(go.clone());
 
         let mut watchers = self.component_watcher.borrow_mut();
 
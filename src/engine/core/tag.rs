@@ -0,0 +1,54 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+struct TagTable {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+thread_local!(static TAGS: RefCell<TagTable> = RefCell::new(TagTable {
+    names: vec![String::new()],
+    ids: HashMap::new(),
+}));
+
+/// The tag of a `GameObject`, an interned string compared by id. The default
+/// is the empty tag, of the untagged objects.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tag(u32);
+
+impl Tag {
+    pub fn new(name: &str) -> Tag {
+        if name.is_empty() {
+            return Tag(0);
+        }
+
+        TAGS.with(|t| {
+            let mut t = t.borrow_mut();
+            if let Some(id) = t.ids.get(name) {
+                return Tag(*id);
+            }
+
+            let id = t.names.len() as u32;
+            t.names.push(name.to_string());
+            t.ids.insert(name.to_string(), id);
+            Tag(id)
+        })
+    }
+
+    /// The tag of `name` if an object was ever given it, without interning it
+    pub fn find(name: &str) -> Option<Tag> {
+        if name.is_empty() {
+            return Some(Tag(0));
+        }
+
+        TAGS.with(|t| t.borrow().ids.get(name).map(|id| Tag(*id)))
+    }
+
+    pub fn name(&self) -> String {
+        TAGS.with(|t| t.borrow().names[self.0 as usize].clone())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
@@ -1,19 +1,37 @@
 use math::*;
 use uni_gl::*;
 
-use std::cell::RefCell;
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::borrow::Cow;
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::mem;
+use std::ptr;
 use std::rc::{Rc, Weak};
 use std::sync::Arc;
 
 use engine::asset::{AssetError, AssetResult, AssetSystem};
-use engine::context::EngineContext;
-use engine::core::{Component, ComponentArena, ComponentBased, GameObject, SceneTree};
-use engine::render::Camera;
-use engine::render::{DepthTest, DirectionalLight, Light, Material, MaterialState, Mesh,
-                     MeshSurface, ShaderProgram};
+use engine::context::{max_texture_units, EngineContext};
+use engine::core::{component_enabled_changes, Behaviours, Bvh, Component, ComponentArena,
+                   ComponentBased, GameObject, Ray, SceneTree, Tag};
+use engine::physics2d::{Collider2D, Physics2D, RigidBody2D};
+use engine::render::{end_gpu_memory_frame, end_shader_frame, gpu_memory_stats,
+                     next_context_generation, set_gpu_memory_budget, split_screen_rects,
+                     srgb_to_linear, take_context_events, Camera, CameraClearFlags, Capabilities,
+                     ColorSpace, GpuMemoryStats, RenderBackend};
+use engine::render::{bounds_gizmo, camera_gizmo, light_gizmo, Blend, BlendEquation, BlendFactor,
+                     CameraDepthState, CullMode, DebugDraw, DepthTest, DirectionalLight,
+                     DynamicBatcher, Fog, GizmoSettings, InstancedMesh, Light, Light2D,
+                     LightClusters, Lighting2DState, Material, MaterialPropertyBlock, MaterialState,
+                     Mesh, MeshBuffer, MeshData, MeshSurface, OcclusionBuffer, ParticleEmitter,
+                     PassInput, PlanarReflection, PostProcess, ProbeMaps, ReflectionProbe,
+                     RenderTexture, ShaderProgram, ShadowSettings, Skybox, SortingGroup,
+                     SortingLayers, SpriteAnimation, SpriteBatcher, SpriteInstance, SpriteRenderer,
+                     SsaoState, StaticBatch, TextMesh, Texture, TextureAttachment, TextureFiltering,
+                     UniformBlock, UniformTarget, DEFAULT_SORTING_LAYER, MAX_BATCH_VERTICES,
+                     MAX_CLUSTERED_LIGHTS, PROBE_IRRADIANCE_SIZE, PROBE_SPECULAR_LEVELS};
 use engine::render::{Frustum, RenderQueue};
 use image;
+use uni_app;
 use math::Aabb;
 
 use std::default::Default;
@@ -41,6 +59,23 @@ pub struct EngineStats {
     pub transparent_count: u32,
     pub total_opaque_count: u32,
     pub total_transparent_count: u32,
+    /// Uniform uploads skipped because the program already had the value
+    pub uniform_calls_saved: u32,
+    /// Draw calls saved by merging small meshes into dynamic batches
+    pub dynamic_batched_count: u32,
+    /// Surfaces outside the camera frustum
+    pub culled_count: u32,
+    /// Surfaces hidden behind occluders
+    pub occluded_count: u32,
+}
+
+/// The nearest object hit by `Engine::raycast`
+#[derive(Clone)]
+pub struct RaycastHit {
+    pub object: Rc<RefCell<GameObject>>,
+    /// Along the ray, to the world bounds of the mesh
+    pub distance: f32,
+    pub point: Vector3f,
 }
 
 pub struct Engine<A>
@@ -48,8 +83,13 @@ where
     A: AssetSystem,
 {
     pub gl: WebGLRenderingContext,
+    /// Texture units of the GL context, shared between materials and engine textures
+    texture_units: u32,
+    /// The features of `gl`, detected once
+    capabilities: Capabilities,
     pub objects: Vec<Weak<RefCell<GameObject>>>,
-    pub program_cache: RefCell<HashMap<&'static str, Rc<ShaderProgram>>>,
+    /// Keyword permutations of the programs, keyed by (program, sorted keywords)
+    pub program_cache: RefCell<HashMap<(usize, Vec<String>), Rc<ShaderProgram>>>,
     pub asset_system: Box<A>,
     pub screen_size: (u32, u32),
     pub hidpi: f32,
@@ -58,12 +98,410 @@ where
     pub arena: Rc<ComponentArena>,
 
     pub stats: EngineStats,
+
+    /// Point lights closest to the camera which are bound per frame, the
+    /// shaders get it as UNI_POINT_LIGHTS
+    pub max_point_lights: usize,
+    /// Spot lights closest to the camera which are bound per frame, the
+    /// shaders get it as UNI_SPOT_LIGHTS
+    pub max_spot_lights: usize,
+    /// Area lights closest to the camera which are bound per frame, the
+    /// shaders get it as UNI_AREA_LIGHTS
+    pub max_area_lights: usize,
+
+    /// The two LTC fit tables (matrix and amplitude) for area light specular,
+    /// loaded by `AssetSystem::new_texture`. Without them an approximation is used.
+    pub area_light_lut: Option<(Rc<Texture>, Rc<Texture>)>,
+
+    /// Shade the point lights beyond `max_point_lights` through view space
+    /// clusters, only GLSL 300 es shaders like "unrust/phong_shadow" support it
+    pub clustered_lighting: bool,
+
+    /// Scene fog of the standard shaders, none by default
+    pub fog: Option<Fog>,
+
+    /// The built-in camera, light and bounds gizmos of the overlay, off by default
+    pub gizmos: GizmoSettings,
+
+    /// Named layers ordering the sprites and the transparent surfaces with a
+    /// `SortingGroup`, before the camera distance
+    pub sorting_layers: SortingLayers,
+
+    /// The simulation of the `RigidBody2D` and `Collider2D` components
+    pub physics_2d: Physics2D,
+
+    /// The component types updated by `update_behaviours`
+    pub behaviours: Behaviours,
+
+    /// The main light shadow is rendered by another system this frame, e.g. the
+    /// cascades of the `ShadowPass` actor, so the engine skips its own map.
+    /// Reset at the end of each frame.
+    pub external_directional_shadow: bool,
+
+    /// The space the lighting is computed in, see `ColorSpace`
+    pub color_space: ColorSpace,
+
+    /// Merge consecutive small meshes sharing a material into one draw every
+    /// frame, off by default
+    pub dynamic_batching: bool,
+    /// Meshes with more vertices are not dynamically batched
+    pub dynamic_batch_max_vertices: usize,
+
+    /// Keep the mesh objects in a BVH by their world bounds, so frustum culling
+    /// and `query_objects` don't walk every object
+    pub spatial_index: bool,
+
+    /// Between `context_lost` and `context_restored`
+    context_lost: bool,
+    events: Vec<EngineEvent>,
+    light_clusters: Option<LightClusters>,
+    hdr_target: Option<((u32, u32), Rc<RenderTexture>)>,
+    /// The object of the `PlanarReflection` being rendered, left out of its
+    /// own reflection: its materials sample the render texture drawn into
+    reflecting_object: Option<Rc<RefCell<GameObject>>>,
+    tonemap_material: Option<Rc<Material>>,
+    depth_prepass_material: Option<Rc<Material>>,
+    /// Irradiance and specular convolutions of the reflection probes
+    probe_materials: Option<(Rc<Material>, Rc<Material>)>,
+    /// Equirectangular image to cube atlas, for the environment probes
+    environment_material: Option<Rc<Material>>,
+    /// Simulation step of the GPU particles
+    particle_update_material: Option<Rc<Material>>,
+    ssao: Option<SsaoState>,
+    camera_depth: Option<CameraDepthState>,
+    lighting_2d: Option<Lighting2DState>,
+    shadow: Option<ShadowMapState>,
+    point_shadow: Option<PointShadowState>,
+    /// The camera uniforms of the programs declaring the "Camera" block, on
+    /// the contexts with uniform buffers
+    camera_block: UniformBlock,
+    /// The lights of the programs declaring the "Lights" block, with
+    /// (`max_point_lights`, `max_spot_lights`) slots
+    lights_block: RefCell<Option<((usize, usize), UniformBlock)>>,
+    /// The shadow maps of this frame are rendered, they are shared by its passes
+    shadows_rendered: bool,
+    static_batches: Vec<StaticBatch>,
+    dynamic_batcher: DynamicBatcher,
+    sprite_batcher: SpriteBatcher,
+    object_index: RefCell<ObjectIndex>,
+    debug_draw: RefCell<DebugDraw>,
+    debug_line_material: Option<Rc<Material>>,
+    /// The lines of the built-in gizmos, refilled for every camera
+    gizmo_draw: RefCell<DebugDraw>,
+    /// The `Overlay` queue of the last scene pass, see `render_overlay`
+    overlay_queue: Option<RenderQueueState>,
+    depth_copy_material: Option<Rc<Material>>,
+    /// The reference resolution target of the pixel perfect cameras
+    pixel_perfect_target: Option<((u32, u32), Rc<RenderTexture>)>,
+    /// Copies a texture into a viewport, e.g. to upscale the pixel perfect cameras
+    blit_material: Option<Rc<Material>>,
+    /// Queue lists of the previous passes, reused to keep their allocations
+    render_queue_pool: RefCell<Vec<RenderQueueList>>,
+}
+
+/// The BVH of `Engine::spatial_index`. The inactive objects are indexed too,
+/// `active` is checked by the users of the index.
+#[derive(Default)]
+struct ObjectIndex {
+    bvh: Bvh<Weak<RefCell<GameObject>>>,
+    /// Leaf of each indexed object, by object address
+    leaves: HashMap<usize, usize>,
+    /// Objects which can't be culled by their bounds yet, e.g. loading meshes
+    unbounded: Vec<Weak<RefCell<GameObject>>>,
+    /// Objects whose meshes are loading, or borrowed during the update
+    loading: Vec<Weak<RefCell<GameObject>>>,
+    /// `loading` was checked in the current frame
+    loading_checked: bool,
+    /// The trees of the objects, which report the moved objects
+    trees: Vec<Weak<SceneTree>>,
+    /// Walk every object on the next update, e.g. after static batching
+    rebuild: bool,
+    /// `component_enabled_changes` at the last update
+    enabled_changes: u32,
+}
+
+impl ObjectIndex {
+    fn add_tree(&mut self, tree: Rc<SceneTree>) {
+        let known = self.trees
+            .iter()
+            .any(|t| t.upgrade().map_or(false, |t| Rc::ptr_eq(&t, &tree)));
+
+        if !known {
+            self.trees.push(Rc::downgrade(&tree));
+        }
+    }
+
+    /// Move the leaf of `obj` to its current bounds, or out of the BVH
+    fn update(&mut self, obj: &Rc<RefCell<GameObject>>) {
+        let key = &**obj as *const RefCell<GameObject> as usize;
+        let weak = Rc::downgrade(obj);
+
+        self.unbounded.retain(|o| !o.upgrade().map_or(false, |o| Rc::ptr_eq(&o, obj)));
+        self.loading.retain(|o| !o.upgrade().map_or(false, |o| Rc::ptr_eq(&o, obj)));
+
+        let object = match obj.try_borrow() {
+            Ok(object) => object,
+            Err(_) => {
+                self.loading.push(weak.clone());
+                self.unbounded.push(weak);
+                return;
+            }
+        };
+
+        // Static batched meshes are culled with their batch
+        let bounds = match object.find_enabled_component::<Mesh>() {
+            Some((ref mesh, _)) if !mesh.static_batched.get() => Some(mesh.bounds()),
+            _ => None,
+        };
+        // Instances, skyboxes, particles, sprites and texts are not culled by the object bounds
+        let unbounded = object.find_enabled_component::<InstancedMesh>().is_some()
+            || object.find_enabled_component::<Skybox>().is_some()
+            || object.find_enabled_component::<ParticleEmitter>().is_some()
+            || object.find_enabled_component::<SpriteRenderer>().is_some()
+            || object.find_enabled_component::<TextMesh>().is_some();
+
+        let world = match bounds {
+            Some(Some(bounds)) if !unbounded => Some(bounds.transform(&compute_model_m(&object))),
+            Some(None) if !unbounded => {
+                self.loading.push(weak.clone());
+                self.unbounded.push(weak.clone());
+                None
+            }
+            None if !unbounded => None,
+            _ => {
+                self.unbounded.push(weak.clone());
+                None
+            }
+        };
+
+        // The address may be reused by a new object
+        let leaf = self.leaves.get(&key).cloned().filter(|&leaf| {
+            self.bvh
+                .get(leaf)
+                .and_then(|o| o.upgrade())
+                .map_or(false, |o| Rc::ptr_eq(&o, obj))
+        });
+
+        match (leaf, world) {
+            (Some(leaf), Some(world)) => {
+                self.bvh.update(leaf, world.aabb);
+            }
+            (_, Some(world)) => {
+                if let Some(old) = self.leaves.remove(&key) {
+                    self.bvh.remove(old);
+                }
+
+                let leaf = self.bvh.insert(world.aabb, weak);
+                self.leaves.insert(key, leaf);
+            }
+            (_, None) => {
+                if let Some(old) = self.leaves.remove(&key) {
+                    self.bvh.remove(old);
+                }
+            }
+        }
+    }
+
+    /// Remove the leaves of the dropped objects
+    fn prune(&mut self) {
+        let bvh = &mut self.bvh;
+
+        self.leaves.retain(|_, leaf| {
+            let alive = bvh.get(*leaf).map_or(false, |o| o.upgrade().is_some());
+            if !alive {
+                bvh.remove(*leaf);
+            }
+            alive
+        });
+
+        self.unbounded.retain(|o| o.upgrade().is_some());
+        self.loading.retain(|o| o.upgrade().is_some());
+    }
+}
+
+const POINT_SHADOW_NEAR: f32 = 0.1;
+const MAX_POINT_SHADOWS: usize = 4;
+const MAX_SPOT_COOKIES: usize = 4;
+/// The UNI_POINT_LIGHTS, UNI_SPOT_LIGHTS and UNI_AREA_LIGHTS of the shaders
+/// when they are not defined
+const DEFAULT_LIGHT_SLOTS: usize = 4;
+
+struct ShadowMapState {
+    rt: Rc<RenderTexture>,
+    size: u32,
+    material: Rc<Material>,
+    light_matrix: Matrix4f,
+    settings: ShadowSettings,
+    enabled: bool,
+}
+
+impl ShadowMapState {
+    fn bind(&self, prog: &ShaderProgram) {
+        let size = self.size as f32;
+
+        prog.set("uShadowEnabled", true);
+        self.settings.bind("uShadowSettings", prog);
+        prog.set("uShadowMap[0].light_matrix", self.light_matrix);
+        prog.set("uShadowMap[0].map_size", Vector2f::new(size, size));
+        prog.set("uShadowMap[0].range", Vector2f::new(-1.0, 1.0));
+        prog.set("uShadowMap[0].viewport_offset", Vector2f::new(0.0, 0.0));
+        prog.set("uShadowMap[0].viewport_scale", Vector2f::new(1.0, 1.0));
+        prog.set("uShadowMap[0].tex_size", 2.0 / size);
+
+        // Only a single map covering the whole scene is rendered,
+        // push the others out of range so the first one is always picked.
+        for i in 1..4 {
+            prog.set(format!("uShadowMap[{}].range", i), Vector2f::new(1.0, 1.0));
+        }
+    }
+}
+
+#[derive(Clone)]
+struct PointShadowLight {
+    /// The light owning the tile, each camera orders its point lights differently
+    light: std::sync::Weak<Component>,
+    position: Vector3f,
+    depth_range: (f32, f32),
+    settings: ShadowSettings,
+}
+
+struct PointShadowState {
+    // 6 cube faces per column, one row per point light
+    rt: Rc<RenderTexture>,
+    tile_size: u32,
+    material: Rc<Material>,
+    lights: Vec<Option<PointShadowLight>>,
+    enabled: bool,
+}
+
+impl PointShadowState {
+    /// The atlas row of `light`, if it has one
+    fn tile_of(&self, light: &Arc<Component>) -> Option<(usize, &PointShadowLight)> {
+        self.lights
+            .iter()
+            .enumerate()
+            .filter_map(|(row, l)| match l {
+                &Some(ref l) => match l.light.upgrade() {
+                    Some(ref owner) if Arc::ptr_eq(owner, light) => Some((row, l)),
+                    _ => None,
+                },
+                &None => None,
+            })
+            .next()
+    }
+
+    /// `uPointShadows[i]` describes the shadow of `uPointLights[i]`, found by
+    /// the identity of the light in `point_lights`
+    fn bind(&self, prog: &ShaderProgram, point_lights: &[Arc<Component>]) {
+        prog.set(
+            "uPointShadowMapSize",
+            Vector2f::new(
+                (self.tile_size * 6) as f32,
+                (self.tile_size * MAX_POINT_SHADOWS as u32) as f32,
+            ),
+        );
+
+        for i in 0..MAX_POINT_SHADOWS {
+            let name = format!("uPointShadows[{}]", i);
+
+            match point_lights.get(i).and_then(|c| self.tile_of(c)) {
+                Some((row, l)) => {
+                    let (near, far) = l.depth_range;
+
+                    prog.set(name.clone() + ".enabled", true);
+                    prog.set(name.clone() + ".tile", row as f32);
+                    prog.set(name.clone() + ".position", l.position);
+                    prog.set(name.clone() + ".depth_range", Vector2f::new(near, far));
+                    l.settings.bind(&(name + ".settings"), prog);
+                }
+                None => prog.set(name + ".enabled", false),
+            }
+        }
+    }
+}
+
+/// Bind lights into the uniform arrays `names`, switching off the slots
+/// culled by `layer` and the ones left over from previous frames.
+/// Lights keep their slot when culled, so the shadow maps still line up.
+fn bind_light_slots<U: UniformTarget>(
+    prog: &U,
+    names: &[&str],
+    lights: &[Arc<Component>],
+    max: usize,
+    layer: u32,
+) {
+    for i in 0..max {
+        let light = lights
+            .get(i)
+            .map(|c| c.try_as::<Light>().unwrap().borrow());
+
+        for name in names.iter() {
+            let slot = format!("{}[{}]", name, i);
+
+            match light {
+                Some(ref l) if l.affects_layer(layer) => l.bind(&slot, prog),
+                _ => prog.set(slot + ".rate", 0.0),
+            }
+        }
+    }
+}
+
+/// The camera uniforms shared by the programs, in the "Camera" block or
+/// the plain uniforms of a program
+fn bind_camera_uniforms<U: UniformTarget>(
+    target: &U,
+    camera: &Camera,
+    perspective: Matrix4f,
+    linear: bool,
+    encode_srgb: bool,
+) {
+    target.set("uVMatrix", camera.v);
+    target.set("uPMatrix", perspective);
+    target.set("uViewPos", camera.eye());
+    target.set("uLinearColor", linear);
+    target.set("uEncodeSrgb", encode_srgb);
+}
+
+/// Forward and up axes of the cube faces, in +X, -X, +Y, -Y, +Z, -Z order
+fn cube_face_axes() -> [(Vector3f, Vector3f); 6] {
+    [
+        (Vector3::unit_x(), -Vector3::unit_y()),
+        (-Vector3::unit_x(), -Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (-Vector3::unit_y(), -Vector3::unit_z()),
+        (Vector3::unit_z(), -Vector3::unit_y()),
+        (-Vector3::unit_z(), -Vector3::unit_y()),
+    ]
+}
+
+/// Halve the requested shadow map size until a texture `scale` times larger
+/// fits `max_texture_size`, e.g. the 6 faces wide point light atlas
+fn fit_shadow_size(requested: u32, scale: u32, max_texture_size: u32) -> u32 {
+    let mut size = requested.max(1);
+    if max_texture_size == 0 {
+        return size;
+    }
+
+    while size > 1 && size.saturating_mul(scale) > max_texture_size {
+        size /= 2;
+    }
+
+    size
 }
 
 struct RenderCommand {
     pub surface: Rc<MeshSurface>,
+    pub properties: Option<Rc<MaterialPropertyBlock>>,
+    /// World matrices of an `InstancedMesh`, `model_m` is then the game object one
+    pub instances: Option<Rc<Vec<Matrix4f>>>,
     pub model_m: Matrix4<f32>,
     pub cam_distance: f32,
+    pub layer: u32,
+    /// Sorting layer rank and order in layer, before `cam_distance` in the
+    /// transparent queue
+    pub sorting: (i32, i32),
+    /// Program, texture set, material and mesh keys of `sort_by_state`
+    pub state_key: (u64, u64, usize, usize),
 }
 
 #[derive(Default)]
@@ -72,13 +510,44 @@ struct RenderQueueState {
     commands: Vec<RenderCommand>,
 }
 
+impl RenderCommand {
+    fn state_key(surface: &MeshSurface) -> (u64, u64, usize, usize) {
+        let material = &surface.material;
+        let (program, textures) = material.state_keys();
+
+        (
+            program,
+            textures,
+            &**material as *const Material as usize,
+            &*surface.buffer as *const MeshBuffer as usize,
+        )
+    }
+
+    /// Whether both commands can be drawn in a single instanced call
+    fn can_instance_with(&self, other: &RenderCommand) -> bool {
+        self.can_batch_with(other) && Rc::ptr_eq(&self.surface.buffer, &other.surface.buffer)
+    }
+
+    /// Whether both commands can be merged in a dynamic batch,
+    /// the mesh sizes and layouts are checked when rendering
+    fn can_batch_with(&self, other: &RenderCommand) -> bool {
+        self.instances.is_none() && other.instances.is_none() && self.properties.is_none()
+            && other.properties.is_none() && self.surface.properties.is_none()
+            && other.surface.properties.is_none() && self.layer == other.layer
+            && Rc::ptr_eq(&self.surface.material, &other.surface.material)
+    }
+}
+
 impl RenderQueueState {
+    /// Back to front, after the sorting layers and orders in layer
     fn sort_by_cam_distance(&mut self) -> &mut Self {
         self.commands.sort_unstable_by(|a, b| {
             let adist: f32 = a.cam_distance;
             let bdist: f32 = b.cam_distance;
 
-            bdist.partial_cmp(&adist).unwrap()
+            a.sorting
+                .cmp(&b.sorting)
+                .then_with(|| bdist.partial_cmp(&adist).unwrap())
         });
 
         self
@@ -95,15 +564,13 @@ impl RenderQueueState {
         self
     }
 
-    fn sort_by_material(&mut self) -> &mut Self {
-        self.commands.sort_by(|a, b| {
-            let prog_a: &Material = &a.surface.material;
-            let prog_b: &Material = &b.surface.material;
-
-            let adist = prog_a as *const Material;
-            let bdist = prog_b as *const Material;
-
-            adist.partial_cmp(&bdist).unwrap()
+    /// Sort by program, texture set, material and mesh, then front to back,
+    /// so consecutive draws hit the caches of `EngineContext` and can be batched
+    fn sort_by_state(&mut self) -> &mut Self {
+        self.commands.sort_unstable_by(|a, b| {
+            a.state_key
+                .cmp(&b.state_key)
+                .then_with(|| a.cam_distance.partial_cmp(&b.cam_distance).unwrap())
         });
 
         self
@@ -114,6 +581,8 @@ impl RenderQueueState {
 struct RenderQueueList {
     aabb: Option<Aabb>,
     queues: BTreeMap<RenderQueue, RenderQueueState>,
+    /// The visible sprites, merged into the `Sprite` queue once gathered
+    sprites: Vec<SpriteInstance>,
 }
 
 impl RenderQueueList {
@@ -125,6 +594,11 @@ impl RenderQueueList {
         state.states.alpha_blending = Some(false);
         qlist.queues.insert(RenderQueue::Opaque, state);
 
+        // Alpha Test Queue
+        let mut state = RenderQueueState::default();
+        state.states.alpha_blending = Some(false);
+        qlist.queues.insert(RenderQueue::AlphaTest, state);
+
         // Skybox Queue
         let mut state = RenderQueueState::default();
         state.states.depth_write = Some(false);
@@ -138,6 +612,19 @@ impl RenderQueueList {
         state.states.depth_write = Some(false);
         qlist.queues.insert(RenderQueue::Transparent, state);
 
+        // Sprite Queue
+        let mut state = RenderQueueState::default();
+        state.states.alpha_blending = Some(true);
+        state.states.depth_write = Some(false);
+        qlist.queues.insert(RenderQueue::Sprite, state);
+
+        // Overlay Queue
+        let mut state = RenderQueueState::default();
+        state.states.alpha_blending = Some(true);
+        state.states.depth_write = Some(false);
+        state.states.depth_test = Some(DepthTest::LessEqual);
+        qlist.queues.insert(RenderQueue::Overlay, state);
+
         // UI Queue
         let mut state = RenderQueueState::default();
         state.states.alpha_blending = Some(true);
@@ -146,6 +633,16 @@ impl RenderQueueList {
         qlist
     }
 
+    /// Empty the queues for the next pass, keeping the command capacity
+    fn clear(&mut self) {
+        self.aabb = None;
+        self.sprites.clear();
+
+        for (_, q) in self.queues.iter_mut() {
+            q.commands.clear();
+        }
+    }
+
     fn surface_count(&self) -> usize {
         let mut n = 0;
         for (_, q) in self.queues.iter() {
@@ -155,10 +652,49 @@ impl RenderQueueList {
     }
 }
 
+/// Normal maps need tangents, generate them for meshes which miss them
+fn ensure_tangents(surface: &MeshSurface) {
+    if surface.material.has("uNormalMap") {
+        surface.buffer.ensure_tangents();
+    }
+}
+
+/// The default state of a new gl context
+fn init_gl_state(gl: &WebGLRenderingContext, size: (u32, u32)) {
+    // Clear the canvas
+    gl.clear_color(0.5, 0.5, 0.5, 1.0);
+
+    // Enable alpha blending
+    gl.enable_blending(true);
+
+    // Clear the color buffer bit
+    gl.clear(BufferBit::Color);
+    gl.clear(BufferBit::Depth);
+    gl.set_blend_factors(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha);
+
+    // Explict set the clear depth value
+    gl.clear_depth(1.0);
+
+    // Set the view port
+    gl.set_viewport(0, 0, size.0, size.1);
+}
+
 fn compute_model_m(object: &GameObject) -> Matrix4<f32> {
     object.transform.as_global_matrix()
 }
 
+/// Notifications of the engine to the game, kept until the end of the frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EngineEvent {
+    /// The gl context is lost, nothing is rendered until it is restored
+    ContextLost,
+    /// The gl context is back, the GPU resources are uploaded again when used
+    ContextRestored,
+    /// The last of the files being loaded finished, see `AssetSystem::loading_progress`
+    AssetsLoaded,
+}
+
+#[derive(Copy, Clone)]
 pub struct ClearOption {
     pub color: Option<(f32, f32, f32, f32)>,
     pub clear_color: bool,
@@ -177,8 +713,27 @@ impl Default for ClearOption {
     }
 }
 
-fn get_max_scale(s: &Vector3<f32>) -> f32 {
-    s[0].max(s[1]).max(s[2])
+/// The clear of a camera, `default` is the one of the skybox flag
+fn camera_clear_option(flags: CameraClearFlags, default: ClearOption) -> ClearOption {
+    let nothing = ClearOption {
+        color: None,
+        clear_color: false,
+        clear_depth: false,
+        clear_stencil: false,
+    };
+
+    match flags {
+        CameraClearFlags::Skybox => default,
+        CameraClearFlags::SolidColor(color) => ClearOption {
+            color: Some(color),
+            ..default
+        },
+        CameraClearFlags::DepthOnly => ClearOption {
+            clear_depth: true,
+            ..nothing
+        },
+        CameraClearFlags::Nothing => nothing,
+    }
 }
 
 impl<A> Engine<A>
@@ -189,21 +744,94 @@ where
         SceneTree::new()
     }
 
+    /// The game objects tagged `tag`, see `GameObject::set_tag`
+    pub fn find_objects_with_tag(&self, tag: &str) -> Vec<Rc<RefCell<GameObject>>> {
+        let tag = match Tag::find(tag) {
+            Some(tag) => tag,
+            None => return Vec::new(),
+        };
+
+        self.objects
+            .iter()
+            .filter_map(|obj| obj.upgrade())
+            .filter(|obj| obj.borrow().tag == tag)
+            .collect()
+    }
+
+    /// The first game object tagged `tag`, in creation order
+    pub fn find_object_with_tag(&self, tag: &str) -> Option<Rc<RefCell<GameObject>>> {
+        let tag = Tag::find(tag)?;
+
+        self.objects
+            .iter()
+            .filter_map(|obj| obj.upgrade())
+            .find(|obj| obj.borrow().tag == tag)
+    }
+
     #[cfg_attr(feature = "flame_it", flame)]
     pub fn clear(&self, option: ClearOption) {
-        if let Some(col) = option.color {
-            self.gl.clear_color(col.0, col.1, col.2, col.3);
-        }
+        self.gl.clear_target(
+            option.color,
+            option.clear_color,
+            option.clear_depth,
+            option.clear_stencil,
+        );
+    }
 
-        if option.clear_color {
-            self.gl.clear(BufferBit::Color);
+    /// Call `context_lost` and `context_restored` for the events of the canvas
+    /// since the last call, once per frame. `World` polls them in its step.
+    pub fn poll_context_loss(&mut self) {
+        for lost in take_context_events(&self.gl) {
+            if lost {
+                self.context_lost();
+            } else {
+                self.context_restored();
+            }
         }
-        if option.clear_depth {
-            self.gl.clear(BufferBit::Depth);
+    }
+
+    /// Call on `webglcontextlost`, after preventing its default so the browser
+    /// restores the context. Nothing is rendered until `context_restored`.
+    pub fn context_lost(&mut self) {
+        if !self.context_lost {
+            self.context_lost = true;
+            self.events.push(EngineEvent::ContextLost);
         }
-        if option.clear_stencil {
-            self.gl.clear(BufferBit::Stencil);
+    }
+
+    /// Call on `webglcontextrestored`. The meshes, textures, shader programs and
+    /// render textures are created again from their CPU side data when next used.
+    pub fn context_restored(&mut self) {
+        if !self.context_lost {
+            return;
         }
+
+        self.context_lost = false;
+        next_context_generation();
+        init_gl_state(&self.gl, self.screen_size);
+
+        // The extensions of WebGL1 are enabled on the new context
+        self.capabilities = Capabilities::detect(&self.gl);
+        self.asset_system.set_capabilities(self.capabilities);
+        // Created again with the attachment the new context renders into
+        self.hdr_target = None;
+
+        // The probes were rendered once into the lost render textures
+        self.map_component::<ReflectionProbe, _>(|_, c| {
+            c.try_as::<ReflectionProbe>().unwrap().borrow_mut().bake();
+            true
+        });
+
+        self.events.push(EngineEvent::ContextRestored);
+    }
+
+    pub fn is_context_lost(&self) -> bool {
+        self.context_lost
+    }
+
+    /// The events since the start of the frame
+    pub fn events(&self) -> &[EngineEvent] {
+        &self.events
     }
 
     pub fn resize(&mut self, size: (u32, u32)) {
@@ -212,150 +840,668 @@ where
         self.gui_context.borrow_mut().reset();
     }
 
+    /// The permutation of the material program for its enabled keywords,
+    /// plus `INSTANCING` for instanced draws and the light counts which are
+    /// not the defaults of the shaders
+    fn material_program(&self, material: &Material, instancing: bool) -> Rc<ShaderProgram> {
+        let mut keywords = material.keywords();
+        if instancing {
+            keywords.push("INSTANCING".to_owned());
+        }
+
+        let light_slots = [
+            ("UNI_POINT_LIGHTS", self.max_point_lights),
+            ("UNI_SPOT_LIGHTS", self.max_spot_lights),
+            ("UNI_AREA_LIGHTS", self.max_area_lights),
+        ];
+        for &(name, count) in light_slots.iter() {
+            if count != DEFAULT_LIGHT_SLOTS {
+                keywords.push(format!("{}={}", name, count));
+            }
+        }
+
+        keywords.sort();
+
+        if keywords.is_empty() {
+            return material.program.clone();
+        }
+
+        let key = (&*material.program as *const ShaderProgram as usize, keywords);
+        let mut cache = self.program_cache.borrow_mut();
+
+        if let Some(prog) = cache.get(&key) {
+            return prog.clone();
+        }
+
+        let prog = ShaderProgram::new_variant(&material.program, key.1.clone());
+        cache.insert(key, prog.clone());
+        prog
+    }
+
     #[cfg_attr(feature = "flame_it", flame)]
-    fn setup_material(&self, ctx: &mut EngineContext, material: &Rc<Material>) -> AssetResult<()> {
+    fn setup_material(
+        &self,
+        ctx: &mut EngineContext,
+        material: &Rc<Material>,
+        instancing: bool,
+    ) -> AssetResult<()> {
         if let Some(ref last_material) = ctx.last_material_bound {
             if let Some(last_material) = last_material.upgrade() {
-                if Rc::ptr_eq(&last_material, &material) {
+                if Rc::ptr_eq(&last_material, &material)
+                    && ctx.last_material_instancing == instancing
+                {
                     return Ok(());
                 }
             }
         }
 
-        ctx.prepare_cache(&material.program, |ctx| {
-            material.program.bind(&self.gl)?;
+        let prog = self.material_program(material, instancing);
+
+        ctx.prepare_cache(&prog, |ctx| {
+            prog.bind(&self.gl, &self.capabilities)?;
             ctx.switch_prog += 1;
             Ok(())
         })?;
 
-        material.bind(|tex| {
+        ctx.engine_textures.begin_draw();
+
+        material.bind(&prog, |tex| {
             ctx.prepare_cache_tex(tex, |ctx, unit| {
                 // Binding texture
-                tex.bind(&self.gl, unit)?;
+                tex.bind(&self.gl, &self.capabilities, unit)?;
 
                 ctx.switch_tex += 1;
                 Ok(())
             })
         })?;
 
-        self.setup_light(ctx);
+        let prog = &prog;
 
-        ctx.last_material_bound = Some(Rc::downgrade(&material));
+        prog.set("uHasNormalMap", material.has("uNormalMap"));
+        prog.set("uHasHeightMap", material.has("uHeightMap"));
+        if !material.has("uAlphaCutoff") {
+            prog.set("uAlphaCutoff", 0.0);
+        }
 
-        Ok(())
-    }
+        if let Some(ref shadow) = self.shadow {
+            if shadow.enabled {
+                self.bind_texture(ctx, prog, "uShadowMapTexture", &shadow.rt.as_texture())?;
+            }
+        }
 
-    #[cfg_attr(feature = "flame_it", flame)]
-    fn setup_camera(&self, ctx: &mut EngineContext, modelm: Matrix4<f32>, camera: &Camera) {
-        let prog = ctx.prog.upgrade().unwrap();
-        // setup_camera
-        let perspective = camera.perspective(self.screen_size);
+        if let Some(ref shadow) = self.point_shadow {
+            if shadow.enabled {
+                self.bind_texture(
+                    ctx,
+                    prog,
+                    "uPointShadowMapTexture",
+                    &shadow.rt.as_texture(),
+                )?;
+            }
+        }
 
-        prog.set("uMVMatrix", camera.v * modelm);
-        prog.set("uPMatrix", perspective);
+        match self.area_light_lut {
+            Some((ref ltc1, ref ltc2)) if !ctx.area_lights.is_empty() => {
+                self.bind_texture(ctx, prog, "uLTC1", ltc1)?;
+                self.bind_texture(ctx, prog, "uLTC2", ltc2)?;
+                prog.set("uLTCEnabled", true);
+            }
+            _ => prog.set("uLTCEnabled", false),
+        }
 
-        let skybox_v: Matrix3<_> = Matrix3::from_cols(
-            camera.v.x.truncate(),
-            camera.v.y.truncate(),
-            camera.v.z.truncate(),
-        );
+        if let Some(ref clusters) = self.light_clusters {
+            if clusters.enabled {
+                self.bind_texture(ctx, prog, "uClusterLightTexture", &clusters.light_texture)?;
+                self.bind_texture(ctx, prog, "uClusterTexture", &clusters.cluster_texture)?;
+            }
+        }
 
-        prog.set("uPVMatrix", perspective * camera.v);
-        prog.set("uPVSkyboxMatrix", perspective * Matrix4::from(skybox_v));
+        if let Some(ref ssao) = self.ssao {
+            if ssao.enabled {
+                self.bind_texture(ctx, prog, "uSSAOTexture", &ssao.blur_rt.as_texture())?;
+            }
+        }
 
-        prog.set("uNMatrix", modelm.inverse_transform().unwrap().transpose());
-        prog.set("uMMatrix", modelm);
-        prog.set("uViewPos", camera.eye());
-    }
+        if let Some(ref depth) = self.camera_depth {
+            if depth.enabled {
+                self.bind_texture(ctx, prog, "uCameraDepthTexture", &depth.texture())?;
+            }
+        }
 
-    #[cfg_attr(feature = "flame_it", flame)]
-    fn setup_light(&self, ctx: &mut EngineContext) {
-        // Setup light
-        let prog = ctx.prog.upgrade().unwrap();
+        if let Some(ref lighting) = self.lighting_2d {
+            if lighting.enabled {
+                self.bind_texture(ctx, prog, "uLight2DTexture", &lighting.light_rt.as_texture())?;
+            }
+        }
 
-        if let Some(ref last_prog) = ctx.last_light_bound {
-            if let Some(last_prog) = last_prog.upgrade() {
-                if Rc::ptr_eq(&prog, &last_prog) {
-                    return;
+        // Image based lighting of the PBR materials, see Material::new_pbr
+        if material.has("uMaterial.metallic") {
+            match ctx.reflection_probe.clone() {
+                Some(maps) => {
+                    self.bind_texture(ctx, prog, "uIrradianceMap", &maps.irradiance.as_texture())?;
+                    self.bind_texture(ctx, prog, "uSpecularMap", &maps.specular.as_texture())?;
+                    maps.bind(prog);
                 }
+                None => prog.set("uIBLEnabled", false),
             }
         }
 
-        ctx.last_light_bound = Some(ctx.prog.clone());
+        // Light cookies
+        let main_cookie = ctx.main_light.as_ref().and_then(|c| {
+            let light = c.try_as::<Light>().unwrap();
+            let cookie = light.borrow().directional().and_then(|l| l.cookie.clone());
+            cookie
+        });
 
-        let light_com = ctx.main_light.as_ref().unwrap();
-        let light = light_com.try_as::<Light>().unwrap();
+        if let Some(ref tex) = main_cookie {
+            self.bind_texture(ctx, prog, "uDirectionalLightCookie", tex)?;
+        }
 
-        light.borrow().bind("uDirectionalLight", &prog);
-        // So shader needs to have a vs stage light
-        light.borrow().bind("uDirectionalLightVS", &prog);
+        let spot_cookies: Vec<_> = ctx.spot_lights
+            .iter()
+            .take(MAX_SPOT_COOKIES)
+            .map(|c| {
+                let light = c.try_as::<Light>().unwrap();
+                let cookie = light.borrow().spot().and_then(|l| l.cookie.clone());
+                cookie
+            })
+            .collect();
 
-        for (i, plight_com) in ctx.point_lights.iter().enumerate() {
-            let plight = plight_com.try_as::<Light>().unwrap();
-            let name = format!("uPointLights[{}]", i);
-            plight.borrow().bind(&name, &prog);
+        for (i, cookie) in spot_cookies.iter().enumerate() {
+            if let Some(ref tex) = *cookie {
+                self.bind_texture(ctx, prog, format!("uSpotLightCookies[{}]", i), tex)?;
+            }
+        }
 
-            let name = format!("uPointLightsVS[{}]", i);
-            plight.borrow().bind(&name, &prog);
+        match self.fog {
+            Some(ref fog) if material.fog => fog.bind(prog),
+            _ => prog.set("uFogEnabled", false),
         }
+
+        ctx.last_material_bound = Some(Rc::downgrade(&material));
+        ctx.last_material_instancing = instancing;
+
+        Ok(())
     }
 
-    #[cfg_attr(feature = "flame_it", flame)]
-    fn render_commands(
-        &self,
-        ctx: &mut EngineContext,
-        q: &RenderQueueState,
-        camera: &Camera,
-        material: Option<&Rc<Material>>,
-    ) {
-        let gl = &self.gl;
+    /// What the GL context supports, for the materials and features adapting
+    /// to WebGL1
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
 
-        for cmd in q.commands.iter() {
-            let mat = match material.as_ref() {
-                Some(&m) => &m,
-                None => &cmd.surface.material,
-            };
+    fn instancing_supported(&self) -> bool {
+        self.capabilities.instancing
+    }
 
-            ctx.states.apply_defaults();
-            ctx.states.apply(&q.states);
-            ctx.states.apply(&mat.states);
-            ctx.states.commit(gl);
+    /// The GPU particles need float render targets and vertex texture fetch
+    pub fn gpu_particles_supported(&self) -> bool {
+        self.capabilities.float_render_targets && self.capabilities.vertex_textures
+    }
 
-            if let Err(err) = self.setup_material(ctx, mat) {
-                if let AssetError::NotReady = err {
-                    continue;
-                }
+    /// Above `bytes` of estimated GPU memory at the end of a frame, the least
+    /// recently bound textures and mesh buffers are evicted until it fits. They
+    /// keep their CPU side data and are uploaded again when next bound. The
+    /// render textures are never evicted. None for no budget, the default.
+    pub fn set_gpu_memory_budget(&self, bytes: Option<usize>) {
+        set_gpu_memory_budget(bytes);
+    }
 
-                panic!(format!("Failed to load material, reason {:?}", err));
-            }
+    pub fn gpu_memory(&self) -> GpuMemoryStats {
+        gpu_memory_stats()
+    }
 
-            let prog = ctx.prog.upgrade().unwrap();
+    /// Override the material params for the current draw
+    fn setup_properties(
+        &self,
+        ctx: &mut EngineContext,
+        prog: &ShaderProgram,
+        properties: &MaterialPropertyBlock,
+    ) -> AssetResult<()> {
+        properties.bind(prog, |tex| {
+            ctx.prepare_cache_tex(tex, |ctx, unit| {
+                tex.bind(&self.gl, &self.capabilities, unit)?;
 
-            let r = ctx.prepare_cache(&cmd.surface.buffer, |ctx| {
-                cmd.surface.buffer.bind(&self.gl, &prog)?;
-                ctx.switch_mesh += 1;
+                ctx.switch_tex += 1;
                 Ok(())
-            });
+            })
+        })?;
 
-            match r {
-                Ok(_) => {
-                    self.setup_camera(ctx, cmd.model_m, camera);
-                    prog.commit(gl);
-                    // if let RenderQueue::UI = mat.render_queue
-                    {
-                        cmd.surface.buffer.render(gl);
-                    }
+        // the next draw has to set the material params again
+        ctx.last_material_bound = None;
 
-                    cmd.surface.buffer.unbind(gl);
-                }
-                Err(ref err) => match *err {
-                    AssetError::NotReady => (),
+        Ok(())
+    }
+
+    /// Reset the params of `properties` after the draw, so they don't leak
+    /// into the next draws with the same program
+    fn reset_properties(
+        &self,
+        ctx: &mut EngineContext,
+        prog: &ShaderProgram,
+        material: &Material,
+        properties: &MaterialPropertyBlock,
+    ) -> AssetResult<()> {
+        let default_tex = self.asset_system.new_texture("default_white");
+
+        properties.reset(prog, material, &default_tex, |tex| {
+            ctx.prepare_cache_tex(tex, |ctx, unit| {
+                tex.bind(&self.gl, &self.capabilities, unit)?;
+
+                ctx.switch_tex += 1;
+                Ok(())
+            })
+        })
+    }
+
+    /// Bind an engine texture, in the units reserved from the material ones
+    fn bind_texture<S>(
+        &self,
+        ctx: &mut EngineContext,
+        prog: &ShaderProgram,
+        name: S,
+        tex: &Rc<Texture>,
+    ) -> AssetResult<()>
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        // Only the textures the program samples take one of the reserved units
+        let name = name.into();
+        if !prog.has_uniform(&self.gl, &name) {
+            return Ok(());
+        }
+
+        let unit = ctx.prepare_engine_tex(tex, |ctx, unit| {
+            tex.bind(&self.gl, &self.capabilities, unit)?;
+
+            ctx.switch_tex += 1;
+            Ok(())
+        })?;
+
+        prog.set(name, (Rc::downgrade(tex), unit));
+
+        Ok(())
+    }
+
+    /// The model matrices are set for each draw, the camera uniforms only when
+    /// the program or the camera changes. The programs declaring the "Camera"
+    /// block share its buffer, uploaded when the camera changes.
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn setup_camera(&self, ctx: &mut EngineContext, modelm: Matrix4<f32>, camera: &Camera) {
+        let prog = ctx.prog.upgrade().unwrap();
+        // setup_camera
+        prog.set("uMVMatrix", camera.v * modelm);
+        prog.set("uNMatrix", modelm.inverse_transform().unwrap().transpose());
+        prog.set("uMMatrix", modelm);
+
+        let perspective = camera.perspective(self.screen_size);
+
+        // HDR targets are encoded by the tonemapping
+        let linear = self.color_space == ColorSpace::Linear;
+        let encode_srgb = linear && !self.renders_hdr(camera);
+
+        if let Some((ref last_prog, v, p, encode)) = ctx.last_camera_bound {
+            if let Some(last_prog) = last_prog.upgrade() {
+                if Rc::ptr_eq(&prog, &last_prog) && v == camera.v && p == perspective
+                    && encode == encode_srgb
+                {
+                    return;
+                }
+            }
+        }
+
+        ctx.last_camera_bound = Some((ctx.prog.clone(), camera.v, perspective, encode_srgb));
+
+        let block = &self.camera_block;
+        if self.capabilities.uniform_buffers && prog.bind_uniform_block(&self.gl, block) {
+            bind_camera_uniforms(block, camera, perspective, linear, encode_srgb);
+            block.upload(&self.gl);
+        } else {
+            bind_camera_uniforms(&*prog, camera, perspective, linear, encode_srgb);
+        }
+
+        let skybox_v: Matrix3<_> = Matrix3::from_cols(
+            camera.v.x.truncate(),
+            camera.v.y.truncate(),
+            camera.v.z.truncate(),
+        );
+
+        prog.set("uPVMatrix", perspective * camera.v);
+        prog.set("uPVSkyboxMatrix", perspective * Matrix4::from(skybox_v));
+    }
+
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn setup_light(&self, ctx: &mut EngineContext, layer: u32) {
+        // Setup light
+        let prog = ctx.prog.upgrade().unwrap();
+
+        if let Some(ref last_prog) = ctx.last_light_bound {
+            if let Some(last_prog) = last_prog.upgrade() {
+                if Rc::ptr_eq(&prog, &last_prog) && ctx.last_light_layer == layer {
+                    return;
+                }
+            }
+        }
+
+        ctx.last_light_bound = Some(ctx.prog.clone());
+        ctx.last_light_layer = layer;
+
+        // The directional, point and spot lights of the programs declaring the
+        // "Lights" block, uploaded when they change
+        let mut lights_block = self.lights_block.borrow_mut();
+        let block = if self.capabilities.uniform_buffers {
+            let slots = (self.max_point_lights, self.max_spot_lights);
+            if lights_block.as_ref().map_or(true, |&(s, _)| s != slots) {
+                *lights_block = Some((slots, UniformBlock::lights(slots.0, slots.1)));
+            }
+
+            match *lights_block {
+                Some((_, ref block)) if prog.bind_uniform_block(&self.gl, block) => Some(block),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let light_com = ctx.main_light.as_ref().unwrap();
+        let light = light_com.try_as::<Light>().unwrap();
+
+        if light.borrow().affects_layer(layer) {
+            match block {
+                Some(block) => light.borrow().bind("uDirectionalLight", block),
+                None => light.borrow().bind("uDirectionalLight", &*prog),
+            }
+            // So shader needs to have a vs stage light
+            light.borrow().bind("uDirectionalLightVS", &*prog);
+        } else {
+            let unlit = Light::new(DirectionalLight {
+                ambient: Vector3f::zero(),
+                diffuse: Vector3f::zero(),
+                specular: Vector3f::zero(),
+                ..Default::default()
+            });
+
+            match block {
+                Some(block) => unlit.bind("uDirectionalLight", block),
+                None => unlit.bind("uDirectionalLight", &*prog),
+            }
+            unlit.bind("uDirectionalLightVS", &*prog);
+        }
+
+        match block {
+            Some(block) => {
+                let (points, max_points) = (&ctx.point_lights, self.max_point_lights);
+                bind_light_slots(block, &["uPointLights"], points, max_points, layer);
+                bind_light_slots(&*prog, &["uPointLightsVS"], points, max_points, layer);
+
+                let (spots, max_spots) = (&ctx.spot_lights, self.max_spot_lights);
+                bind_light_slots(block, &["uSpotLights"], spots, max_spots, layer);
+                block.upload(&self.gl);
+            }
+            None => {
+                bind_light_slots(
+                    &*prog,
+                    &["uPointLights", "uPointLightsVS"],
+                    &ctx.point_lights,
+                    self.max_point_lights,
+                    layer,
+                );
+                bind_light_slots(
+                    &*prog,
+                    &["uSpotLights"],
+                    &ctx.spot_lights,
+                    self.max_spot_lights,
+                    layer,
+                );
+            }
+        }
+        bind_light_slots(
+            &*prog,
+            &["uAreaLights"],
+            &ctx.area_lights,
+            self.max_area_lights,
+            layer,
+        );
+
+        match self.light_clusters {
+            Some(ref clusters) if clusters.enabled => clusters.bind(&prog, layer),
+            _ => prog.set("uClusteredEnabled", false),
+        }
+
+        match self.ssao {
+            Some(ref ssao) if ssao.enabled => ssao.bind(&prog),
+            _ => prog.set("uSSAOEnabled", false),
+        }
+
+        match self.camera_depth {
+            Some(ref depth) if depth.enabled => depth.bind(&prog),
+            _ => prog.set("uCameraDepthEnabled", false),
+        }
+
+        match self.lighting_2d {
+            Some(ref lighting) if lighting.enabled => lighting.bind(&prog),
+            _ => prog.set("uLight2DEnabled", false),
+        }
+
+        if let Some(ref shadow) = self.shadow {
+            if shadow.enabled {
+                shadow.bind(&prog);
+            }
+        }
+
+        if let Some(ref shadow) = self.point_shadow {
+            if shadow.enabled {
+                shadow.bind(&prog, &ctx.point_lights);
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn render_commands(
+        &self,
+        ctx: &mut EngineContext,
+        q: &RenderQueueState,
+        camera: &Camera,
+        material: Option<&Rc<Material>>,
+    ) {
+        let gl = &self.gl;
+        let instancing_supported = self.instancing_supported();
+
+        let mut i = 0;
+        while i < q.commands.len() {
+            let cmd = &q.commands[i];
+
+            // Consecutive commands drawing the same mesh and material are instanced
+            let mut count = 1;
+            if instancing_supported {
+                while i + count < q.commands.len() && cmd.can_instance_with(&q.commands[i + count])
+                {
+                    count += 1;
+                }
+            }
+
+            // Otherwise consecutive small meshes sharing the material are merged
+            let mut batch = None;
+            if count == 1 && self.dynamic_batching {
+                if let Some((n, buffer)) = self.dynamic_batch(&q.commands[i..]) {
+                    ctx.stats.dynamic_batched_count += (n - 1) as u32;
+                    count = n;
+                    batch = Some(buffer);
+                }
+            }
+
+            let batched: Vec<Matrix4f>;
+            let instances: Option<&[Matrix4f]> = match cmd.instances {
+                Some(ref instances) => Some(&instances[..]),
+                None if count > 1 && batch.is_none() => {
+                    batched = q.commands[i..i + count]
+                        .iter()
+                        .map(|c| c.model_m)
+                        .collect();
+                    Some(&batched[..])
+                }
+                None => None,
+            };
+
+            i += count;
+
+            let mat = match material.as_ref() {
+                Some(&m) => &m,
+                None => &cmd.surface.material,
+            };
+
+            let overrides = ctx.states.overrides;
+
+            ctx.states.apply_defaults();
+            ctx.states.apply(&q.states);
+            if material.is_some() {
+                // An override material without a cull mode keeps the one of the surface
+                ctx.states.apply(&MaterialState {
+                    cull: cmd.surface.material.states.cull,
+                    ..Default::default()
+                });
+            }
+            ctx.states.apply(&mat.states);
+            ctx.states.apply(&overrides);
+            ctx.states.commit(gl);
+
+            let instancing = instancing_supported && instances.is_some();
+
+            if let Err(err) = self.setup_material(ctx, mat, instancing) {
+                if let AssetError::NotReady = err {
+                    continue;
+                }
+
+                panic!(format!("Failed to load material, reason {:?}", err));
+            }
+
+            self.setup_light(ctx, cmd.layer);
+
+            let prog = ctx.prog.upgrade().unwrap();
+
+            // The shaders without an `#ifdef INSTANCING` path draw the
+            // instances one by one
+            let instanced = instancing && prog.attrib_loc(gl, "aInstanceModel").is_some();
+
+            let blocks = [
+                cmd.properties.as_ref().map(|p| &**p),
+                cmd.surface.properties.as_ref(),
+            ];
+
+            if let Err(err) = blocks
+                .iter()
+                .filter_map(|b| *b)
+                .map(|block| self.setup_properties(ctx, &prog, block))
+                .collect::<AssetResult<Vec<_>>>()
+            {
+                if let AssetError::NotReady = err {
+                    continue;
+                }
+
+                panic!(format!("Failed to load material properties, reason {:?}", err));
+            }
+
+            // The merged vertices are already in world space
+            let (buffer, model_m) = match batch {
+                Some(ref buffer) => {
+                    // The shared buffer was refilled, so always bind it again
+                    ctx.mesh_buffer = Weak::new();
+                    (buffer, Matrix4::identity())
+                }
+                None => (&cmd.surface.buffer, cmd.model_m),
+            };
+
+            let r = ctx.prepare_cache(buffer, |ctx| {
+                buffer.bind(&self.gl, &prog)?;
+                ctx.switch_mesh += 1;
+                Ok(())
+            });
+
+            match r {
+                Ok(_) => {
+                    match instances {
+                        Some(instances) if instanced => {
+                            self.setup_camera(ctx, cmd.model_m, camera);
+                            prog.commit(gl);
+                            buffer.render_instanced(gl, instances);
+                        }
+                        // No instancing support in the context or the program,
+                        // draw them one by one
+                        Some(instances) => for m in instances.iter() {
+                            self.setup_camera(ctx, *m, camera);
+                            prog.commit(gl);
+                            buffer.render(gl);
+                        },
+                        None => {
+                            self.setup_camera(ctx, model_m, camera);
+                            prog.commit(gl);
+                            buffer.render(gl);
+                        }
+                    }
+                    ctx.stats.uniform_calls_saved += prog.take_saved_uniform_calls();
+
+                    // Committed with the uniforms of the next draw
+                    for block in blocks.iter().filter_map(|b| *b) {
+                        if let Err(err) = self.reset_properties(ctx, &prog, mat, block) {
+                            panic!(format!(
+                                "Failed to reset material properties, reason {:?}",
+                                err
+                            ));
+                        }
+                    }
+
+                    buffer.unbind(gl);
+                }
+                Err(ref err) => match *err {
+                    AssetError::NotReady => (),
                     _ => panic!(format!("Failed to load mesh, reason {:?}", err)),
                 },
             }
         }
     }
 
+    /// Merge the small meshes at the start of `commands` sharing the material of
+    /// the first one, returns how many commands the merged buffer draws
+    fn dynamic_batch(&self, commands: &[RenderCommand]) -> Option<(usize, Rc<MeshBuffer>)> {
+        let first = &commands[0];
+        let mut meshes: Vec<(Ref<MeshData>, Matrix4f)> = Vec::new();
+        let mut vertices = 0;
+
+        for cmd in commands.iter() {
+            if !first.can_batch_with(cmd) {
+                break;
+            }
+
+            let data = match cmd.surface.buffer.mesh_data() {
+                Ok(data) => data,
+                Err(_) => break,
+            };
+
+            let n = data.vertices.len() / 3;
+            if n > self.dynamic_batch_max_vertices || vertices + n > MAX_BATCH_VERTICES
+                || (!meshes.is_empty() && meshes[0].0.layout() != data.layout())
+            {
+                break;
+            }
+
+            vertices += n;
+            meshes.push((data, cmd.model_m));
+        }
+
+        if meshes.len() < 2 {
+            return None;
+        }
+
+        let merged: Vec<(&MeshData, Matrix4f)> = meshes
+            .iter()
+            .map(|&(ref data, m)| (&**data, m))
+            .collect();
+
+        Some((meshes.len(), self.dynamic_batcher.batch(&merged)))
+    }
+
+    /// Call `func` with the first enabled component `T` of each object, until it
+    /// returns false
     fn map_component<T, F>(&self, mut func: F)
     where
         T: 'static + ComponentBased,
@@ -365,7 +1511,7 @@ where
             let result = obj.upgrade().and_then(|obj| {
                 obj.try_borrow()
                     .ok()
-                    .and_then(|o| o.find_component::<T>().map(|(_, c)| c.clone()))
+                    .and_then(|o| o.find_enabled_component::<T>().map(|(_, c)| c.clone()))
             });
 
             if let Some(com) = result {
@@ -415,15 +1561,19 @@ where
             .nth(0)
     }
 
-    fn prepare_ctx(&self, ctx: &mut EngineContext) {
-        // Update all components which need to update
-        // Update lights
+    fn update_lights(&self) {
         self.map_component::<Light, _>(|obj, c| {
             let modelm = obj.borrow().transform.as_global_matrix();
 
             c.try_as::<Light>().unwrap().borrow_mut().update(&modelm);
             true
         });
+    }
+
+    fn prepare_ctx(&self, ctx: &mut EngineContext, camera: &Camera) {
+        // Update all components which need to update
+        // Update lights
+        self.update_lights();
 
         // prepare main light.
         let main_light = self.find_main_light()
@@ -431,20 +1581,86 @@ where
 
         ctx.main_light = Some(main_light);
 
-        ctx.point_lights = self.find_all_components::<Light>()
-                .into_iter()
-                .filter(|c| {
-                    let light_com = c.try_as::<Light>().unwrap();
-                    match *light_com.borrow() {
-                        Light::Point(_) => true,
-                        _ => false,
-                    }
-                })
-                .take(4)            // only take 4 points light.
-                .map(
-                    |c| c.clone()
-                )
-                .collect();
+        let eye = camera.eye();
+        ctx.point_lights = self.find_point_lights(&eye);
+        ctx.spot_lights = self.find_spot_lights(&eye);
+        ctx.area_lights = self.find_nearest_lights(&eye, self.max_area_lights, |l| {
+            l.area().map(|a| (a.world_space_center(), 0.0))
+        });
+        ctx.reflection_probe = self.find_reflection_probe(&eye);
+    }
+
+    /// The baked `ReflectionProbe` nearest to `eye`, by the distance to its radius
+    fn find_reflection_probe(&self, eye: &Vector3f) -> Option<ProbeMaps> {
+        let mut nearest: Option<(f32, ProbeMaps)> = None;
+
+        self.map_component::<ReflectionProbe, _>(|obj, c| {
+            let object = obj.borrow();
+            if !object.active {
+                return true;
+            }
+
+            let probe = c.try_as::<ReflectionProbe>().unwrap();
+            let probe = probe.borrow();
+
+            if let Some(maps) = probe.maps() {
+                let position = object.transform.as_global_matrix().w.truncate();
+                let d = (position - *eye).magnitude() - probe.radius;
+
+                if nearest.as_ref().map_or(true, |n| d < n.0) {
+                    nearest = Some((d, maps.clone()));
+                }
+            }
+
+            true
+        });
+
+        nearest.map(|n| n.1)
+    }
+
+    /// Pick at most `max` lights, ordered by the distance between `eye`
+    /// and the sphere of influence of each light.
+    fn find_nearest_lights<F>(&self, eye: &Vector3f, max: usize, f: F) -> Vec<Arc<Component>>
+    where
+        F: Fn(&Light) -> Option<(Vector3f, f32)>,
+    {
+        let mut lights: Vec<_> = self.find_all_components::<Light>()
+            .into_iter()
+            .filter_map(|c| {
+                let light = c.try_as::<Light>().unwrap();
+                let dist = f(&light.borrow())
+                    .map(|(pos, range)| ((pos - eye).magnitude() - range).max(0.0));
+
+                dist.map(|d| (d, c))
+            })
+            .collect();
+
+        lights.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        lights.into_iter().take(max).map(|(_, c)| c).collect()
+    }
+
+    fn find_point_lights(&self, eye: &Vector3f) -> Vec<Arc<Component>> {
+        self.find_nearest_lights(eye, self.max_point_lights, |l| {
+            l.point().map(|p| (p.world_space_position, p.range()))
+        })
+    }
+
+    /// Point lights left over by `find_point_lights`
+    fn find_clustered_lights(&self, eye: &Vector3f) -> Vec<Arc<Component>> {
+        let max = self.max_point_lights + MAX_CLUSTERED_LIGHTS;
+
+        self.find_nearest_lights(eye, max, |l| {
+            l.point().map(|p| (p.world_space_position, p.range()))
+        }).into_iter()
+            .skip(self.max_point_lights)
+            .collect()
+    }
+
+    fn find_spot_lights(&self, eye: &Vector3f) -> Vec<Arc<Component>> {
+        self.find_nearest_lights(eye, self.max_spot_lights, |l| {
+            l.spot().map(|s| (s.world_space_position, s.range))
+        })
     }
 
     fn gather_render_commands(
@@ -453,6 +1669,7 @@ where
         cam_pos: &Vector3<f32>,
         update_bounds_only: bool,
         frustum_opt: &Option<Frustum>,
+        occlusion: &Option<OcclusionBuffer>,
         render_q: &mut RenderQueueList,
         included_render_queues: &Option<BTreeSet<RenderQueue>>,
         eng_stats: &mut Option<&mut EngineStats>,
@@ -461,14 +1678,19 @@ where
             return;
         }
 
-        let result = object.find_component::<Mesh>();
-        if let Some((mesh, _)) = result {
+        let sorting = match object.find_component::<SortingGroup>() {
+            Some((group, _)) => (
+                self.sorting_layers.index(&group.sorting_layer),
+                group.order_in_layer,
+            ),
+            None => self.default_sorting(),
+        };
+
+        let result = object.find_enabled_component::<Mesh>();
+        if let Some((mesh, _)) = result.filter(|&(ref mesh, _)| !mesh.static_batched.get()) {
             let m = compute_model_m(&*object);
             use math::*;
 
-            // TODO: local scale only ?? should be using global scale??
-            let scale = get_max_scale(&object.transform.local_scale());
-
             for surface in mesh.surfaces.iter() {
                 if let &Some(ref included) = included_render_queues {
                     if included.get(&surface.material.render_queue).is_none() {
@@ -476,10 +1698,14 @@ where
                     }
                 }
 
+                ensure_tangents(surface);
+
                 if let &mut Some(ref mut stats) = eng_stats {
                     match surface.material.render_queue {
                         RenderQueue::Transparent => stats.total_transparent_count += 1,
-                        RenderQueue::Opaque => stats.total_opaque_count += 1,
+                        RenderQueue::Opaque | RenderQueue::AlphaTest => {
+                            stats.total_opaque_count += 1
+                        }
                         _ => (),
                     }
                 }
@@ -489,47 +1715,45 @@ where
                     match surface.material.render_queue {
                         RenderQueue::Skybox | RenderQueue::UI => (),
                         _ => {
-                            let bounds = surface.buffer.bounds();
-                            if bounds.is_none() {
-                                continue;
-                            }
-
-                            let bounds = bounds.unwrap();
-                            let (center, r) = bounds.local_aabb().sphere();
-
-                            let scaled_r = r * scale;
-                            let p = m.transform_point(Point3::from_vec(center));
-
-                            if !frustum.collide_sphere(&p.to_vec(), scaled_r) {
+                            let bounds = match surface.buffer.bounds() {
+                                Some(bounds) => bounds.transform(&m),
+                                None => continue,
+                            };
+
+                            // The sphere test is cheaper, the aabb one tighter
+                            let (center, r) = bounds.sphere();
+                            if !frustum.collide_sphere(&center, r)
+                                || !frustum.collide_aabb(&bounds.aabb)
+                            {
+                                if let &mut Some(ref mut stats) = eng_stats {
+                                    stats.culled_count += 1;
+                                }
                                 continue;
                             }
 
-                            if render_q.aabb.is_none() {
-                                render_q.aabb = Some(Aabb::empty());
+                            if let &Some(ref occlusion) = occlusion {
+                                if !mesh.occluder && !occlusion.is_visible(&bounds.aabb) {
+                                    if let &mut Some(ref mut stats) = eng_stats {
+                                        stats.occluded_count += 1;
+                                    }
+                                    continue;
+                                }
                             }
 
                             render_q
                                 .aabb
-                                .as_mut()
-                                .unwrap()
-                                .merge_sphere(&p.to_vec(), scaled_r);
+                                .get_or_insert_with(Aabb::empty)
+                                .merge_sphere(&center, r);
                         }
                     }
                 } else {
-                    let bounds = surface.buffer.bounds();
-                    if let Some(bounds) = bounds {
-                        let (center, r) = bounds.local_aabb().sphere();
-                        let p = m.transform_point(Point3::from_vec(center));
-
-                        if render_q.aabb.is_none() {
-                            render_q.aabb = Some(Aabb::empty());
-                        }
+                    if let Some(bounds) = surface.buffer.bounds() {
+                        let (center, r) = bounds.transform(&m).sphere();
 
                         render_q
                             .aabb
-                            .as_mut()
-                            .unwrap()
-                            .merge_sphere(&p.to_vec(), r * scale);
+                            .get_or_insert_with(Aabb::empty)
+                            .merge_sphere(&center, r);
                     }
                 }
 
@@ -539,47 +1763,329 @@ where
                         .get_mut(&surface.material.render_queue)
                         .unwrap();
 
-                    let cam_dist = (cam_pos - object.transform.global().disp).magnitude();
+                    let cam_dist = (cam_pos - m.w.truncate()).magnitude();
 
                     q.commands.push(RenderCommand {
                         surface: surface.clone(),
+                        properties: mesh.properties.clone(),
+                        instances: None,
                         model_m: m,
                         cam_distance: cam_dist,
+                        layer: object.layer,
+                        sorting,
+                        state_key: RenderCommand::state_key(surface),
                     })
                 }
             }
         }
-    }
 
-    pub fn get_bounds(&self, camera: &Camera) -> Option<Aabb> {
-        let render_q = self.gather_all_render_commands(camera, true, None);
+        if let Some((skybox, _)) = object.find_enabled_component::<Skybox>() {
+            let included = match included_render_queues {
+                &Some(ref included) => included.contains(&RenderQueue::Skybox),
+                &None => true,
+            };
 
-        return render_q.aabb;
-    }
+            // Drawn around the camera, the model matrix is unused
+            if included && !update_bounds_only {
+                let surface = skybox.surface();
+
+                let q = render_q.queues.get_mut(&RenderQueue::Skybox).unwrap();
+                q.commands.push(RenderCommand {
+                    surface: surface.clone(),
+                    properties: None,
+                    instances: None,
+                    model_m: Matrix4::identity(),
+                    cam_distance: 0.0,
+                    layer: object.layer,
+                    sorting,
+                    state_key: RenderCommand::state_key(surface),
+                })
+            }
+        }
 
-    fn gather_all_render_commands(
-        &self,
-        camera: &Camera,
-        update_bounds_only: bool,
-        mut eng_stats: Option<&mut EngineStats>,
-    ) -> RenderQueueList {
-        let mut render_q = RenderQueueList::new();
-        let objects = &self.objects;
+        if let Some((emitter, _)) = object.find_enabled_component::<ParticleEmitter>() {
+            let draws: Vec<(Rc<MeshSurface>, Option<Rc<MaterialPropertyBlock>>)> =
+                match emitter.gpu_surfaces() {
+                    Some((surfaces, properties)) => surfaces
+                        .iter()
+                        .map(|s| (s.clone(), Some(properties.clone())))
+                        .collect(),
+                    None => emitter.surface().map(|s| (s.clone(), None)).into_iter().collect(),
+                };
+
+            // The particles are simulated in world space, with their own bounds
+            let bounds = emitter.bounds().unwrap_or_else(Aabb::empty);
+            let visible = match frustum_opt {
+                &Some(ref frustum) => frustum.collide_aabb(&bounds),
+                &None => true,
+            };
 
-        let frustum = if camera.enable_frustum_culling {
+            for (surface, properties) in draws.into_iter() {
+                if let &Some(ref included) = included_render_queues {
+                    if !included.contains(&surface.material.render_queue) {
+                        continue;
+                    }
+                }
+
+                if !visible {
+                    if let &mut Some(ref mut stats) = eng_stats {
+                        stats.culled_count += 1;
+                    }
+                    continue;
+                }
+
+                let (center, r) = bounds.sphere();
+                render_q
+                    .aabb
+                    .get_or_insert_with(Aabb::empty)
+                    .merge_sphere(&center, r);
+
+                if !update_bounds_only {
+                    let q = render_q
+                        .queues
+                        .get_mut(&surface.material.render_queue)
+                        .unwrap();
+
+                    q.commands.push(RenderCommand {
+                        state_key: RenderCommand::state_key(&surface),
+                        surface: surface,
+                        properties: properties,
+                        instances: None,
+                        model_m: Matrix4::identity(),
+                        cam_distance: (cam_pos - center).magnitude(),
+                        layer: object.layer,
+                        sorting,
+                    })
+                }
+            }
+        }
+
+        if let Some((sprite, _)) = object.find_enabled_component::<SpriteRenderer>() {
+            let queue = match sprite.material {
+                Some(ref material) => material.render_queue,
+                None => RenderQueue::Sprite,
+            };
+
+            let included = match included_render_queues {
+                &Some(ref included) => included.contains(&queue),
+                &None => true,
+            };
+
+            if included {
+                let model_m = compute_model_m(&*object);
+                let corners = sprite.corners(&model_m);
+                let mut bounds = Aabb::empty();
+                for c in corners.iter() {
+                    bounds.merge_point(c);
+                }
+
+                let visible = match frustum_opt {
+                    &Some(ref frustum) => frustum.collide_aabb(&bounds),
+                    &None => true,
+                };
+
+                if !visible {
+                    if let &mut Some(ref mut stats) = eng_stats {
+                        stats.culled_count += 1;
+                    }
+                } else {
+                    let (center, r) = bounds.sphere();
+                    render_q
+                        .aabb
+                        .get_or_insert_with(Aabb::empty)
+                        .merge_sphere(&center, r);
+
+                    // Batched by gather_all_render_commands
+                    if !update_bounds_only {
+                        render_q.sprites.push(SpriteInstance {
+                            quads: sprite.quads(&model_m),
+                            color: sprite.color,
+                            sorting: (
+                                self.sorting_layers.index(&sprite.sorting_layer),
+                                sprite.order_in_layer,
+                            ),
+                            cam_distance: (cam_pos - center).magnitude(),
+                            texture: sprite.texture.clone(),
+                            normal_map: sprite.normal_map.clone(),
+                            material: sprite.material.clone(),
+                            layer: object.layer,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some((text, _)) = object.find_enabled_component::<TextMesh>() {
+            let included = match included_render_queues {
+                &Some(ref included) => included.contains(&text.material.render_queue),
+                &None => true,
+            };
+
+            let surface = if included { text.surface() } else { None };
+            let bounds = surface.as_ref().and_then(|s| s.buffer.bounds());
+
+            if let (Some(surface), Some(bounds)) = (surface, bounds) {
+                let m = compute_model_m(&*object);
+                let bounds = bounds.transform(&m);
+
+                let visible = match frustum_opt {
+                    &Some(ref frustum) => frustum.collide_aabb(&bounds.aabb),
+                    &None => true,
+                };
+
+                if !visible {
+                    if let &mut Some(ref mut stats) = eng_stats {
+                        stats.culled_count += 1;
+                    }
+                } else {
+                    let (center, r) = bounds.sphere();
+                    render_q
+                        .aabb
+                        .get_or_insert_with(Aabb::empty)
+                        .merge_sphere(&center, r);
+
+                    if !update_bounds_only {
+                        let q = render_q
+                            .queues
+                            .get_mut(&surface.material.render_queue)
+                            .unwrap();
+
+                        q.commands.push(RenderCommand {
+                            state_key: RenderCommand::state_key(&surface),
+                            properties: text.properties(),
+                            surface,
+                            instances: None,
+                            model_m: m,
+                            cam_distance: (cam_pos - center).magnitude(),
+                            layer: object.layer,
+                            sorting,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some((instanced, _)) = object.find_enabled_component::<InstancedMesh>() {
+            let surface = &instanced.surface;
+
+            if let &Some(ref included) = included_render_queues {
+                if included.get(&surface.material.render_queue).is_none() {
+                    return;
+                }
+            }
+
+            ensure_tangents(surface);
+
+            let m = compute_model_m(&*object);
+            let instances: Vec<Matrix4f> = instanced.instances.iter().map(|i| m * *i).collect();
+
+            // The instances are not culled, but still count in the bounds
+            if let Some(bounds) = surface.buffer.bounds() {
+                let (center, r) = bounds.local_aabb().sphere();
+
+                for i in instances.iter() {
+                    let scale = i.x.truncate()
+                        .magnitude()
+                        .max(i.y.truncate().magnitude())
+                        .max(i.z.truncate().magnitude());
+                    let p = i.transform_point(Point3::from_vec(center));
+
+                    render_q
+                        .aabb
+                        .get_or_insert_with(Aabb::empty)
+                        .merge_sphere(&p.to_vec(), r * scale);
+                }
+            }
+
+            if !update_bounds_only && !instances.is_empty() {
+                let q = render_q
+                    .queues
+                    .get_mut(&surface.material.render_queue)
+                    .unwrap();
+
+                q.commands.push(RenderCommand {
+                    surface: surface.clone(),
+                    properties: None,
+                    instances: Some(Rc::new(instances)),
+                    model_m: m,
+                    cam_distance: (cam_pos - m.w.truncate()).magnitude(),
+                    layer: object.layer,
+                    sorting,
+                    state_key: RenderCommand::state_key(surface),
+                })
+            }
+        }
+    }
+
+    pub fn get_bounds(&self, camera: &Camera) -> Option<Aabb> {
+        let render_q = self.gather_all_render_commands(camera, true, None);
+        let aabb = render_q.aabb;
+        self.recycle_render_queues(render_q);
+
+        aabb
+    }
+
+    /// The sorting of the objects without a `SortingGroup`
+    fn default_sorting(&self) -> (i32, i32) {
+        (self.sorting_layers.index(DEFAULT_SORTING_LAYER), 0)
+    }
+
+    /// Give back a queue list of `gather_all_render_commands` for the next passes
+    fn recycle_render_queues(&self, mut render_q: RenderQueueList) {
+        render_q.clear();
+        self.render_queue_pool.borrow_mut().push(render_q);
+    }
+
+    fn gather_all_render_commands(
+        &self,
+        camera: &Camera,
+        update_bounds_only: bool,
+        mut eng_stats: Option<&mut EngineStats>,
+    ) -> RenderQueueList {
+        let pooled = self.render_queue_pool.borrow_mut().pop();
+        let mut render_q = pooled.unwrap_or_else(RenderQueueList::new);
+
+        let frustum = if camera.enable_frustum_culling {
             Some(camera.calc_frustum(self.screen_size))
         } else {
             None
         };
 
+        let visible;
+        let objects = match frustum {
+            Some(ref frustum) if self.spatial_index => {
+                visible = self.visible_objects(frustum);
+                &visible
+            }
+            _ => &self.objects,
+        };
+
+        let occlusion = match frustum {
+            Some(_) if camera.enable_occlusion_culling && !update_bounds_only => {
+                Some(self.build_occlusion_buffer(camera, objects))
+            }
+            _ => None,
+        };
+
         for obj in objects.iter() {
             obj.upgrade().map(|obj| {
+                if let Some(ref reflecting) = self.reflecting_object {
+                    if Rc::ptr_eq(&obj, reflecting) {
+                        return;
+                    }
+                }
+
                 if let Ok(object) = obj.try_borrow() {
+                    if !camera.renders_layer(object.layer) {
+                        return;
+                    }
+
                     self.gather_render_commands(
                         &object,
                         &camera.eye(),
                         update_bounds_only,
                         &frustum,
+                        &occlusion,
                         &mut render_q,
                         &camera.included_render_queues,
                         &mut eng_stats,
@@ -588,7 +2094,363 @@ where
             });
         }
 
+        for batch in self.static_batches.iter() {
+            if !camera.renders_layer(batch.layer) {
+                continue;
+            }
+
+            self.gather_static_batch(
+                batch,
+                &camera.eye(),
+                update_bounds_only,
+                &frustum,
+                &mut render_q,
+                &camera.included_render_queues,
+                &mut eng_stats,
+            );
+        }
+
+        if !render_q.sprites.is_empty() {
+            let batches = self.sprite_batcher.batch(&mut render_q.sprites, &*self.asset_system);
+
+            // Queued in the batcher order, which the Sprite queue keeps
+            for batch in batches.into_iter() {
+                let q = render_q
+                    .queues
+                    .get_mut(&batch.surface.material.render_queue)
+                    .unwrap();
+
+                q.commands.push(RenderCommand {
+                    state_key: RenderCommand::state_key(&batch.surface),
+                    surface: batch.surface,
+                    properties: Some(batch.properties),
+                    instances: None,
+                    model_m: Matrix4::identity(),
+                    cam_distance: 0.0,
+                    layer: batch.layer,
+                    sorting: batch.sorting,
+                });
+            }
+        }
+
+        render_q
+    }
+
+    fn gather_static_batch(
+        &self,
+        batch: &StaticBatch,
+        cam_pos: &Vector3<f32>,
+        update_bounds_only: bool,
+        frustum_opt: &Option<Frustum>,
+        render_q: &mut RenderQueueList,
+        included_render_queues: &Option<BTreeSet<RenderQueue>>,
+        eng_stats: &mut Option<&mut EngineStats>,
+    ) {
+        let surface = &batch.surface;
+        let default_sorting = self.default_sorting();
+
+        if let &Some(ref included) = included_render_queues {
+            if included.get(&surface.material.render_queue).is_none() {
+                return;
+            }
+        }
+
+        if let &mut Some(ref mut stats) = eng_stats {
+            match surface.material.render_queue {
+                RenderQueue::Transparent => stats.total_transparent_count += 1,
+                RenderQueue::Opaque | RenderQueue::AlphaTest => stats.total_opaque_count += 1,
+                _ => (),
+            }
+        }
+
+        // The batch is already in world space
+        let (center, r) = match surface.buffer.bounds() {
+            Some(bounds) => bounds.local_aabb().sphere(),
+            None => return,
+        };
+
+        if let &Some(ref frustum) = frustum_opt {
+            if !frustum.collide_sphere(&center, r) {
+                if let &mut Some(ref mut stats) = eng_stats {
+                    stats.culled_count += 1;
+                }
+                return;
+            }
+        }
+
         render_q
+            .aabb
+            .get_or_insert_with(Aabb::empty)
+            .merge_sphere(&center, r);
+
+        if !update_bounds_only {
+            let q = render_q
+                .queues
+                .get_mut(&surface.material.render_queue)
+                .unwrap();
+
+            q.commands.push(RenderCommand {
+                surface: surface.clone(),
+                properties: None,
+                instances: None,
+                model_m: Matrix4::identity(),
+                cam_distance: (cam_pos - center).magnitude(),
+                layer: batch.layer,
+                sorting: default_sorting,
+                state_key: RenderCommand::state_key(surface),
+            })
+        }
+    }
+
+    /// Rasterize the occluder meshes for the camera
+    fn build_occlusion_buffer(
+        &self,
+        camera: &Camera,
+        objects: &[Weak<RefCell<GameObject>>],
+    ) -> OcclusionBuffer {
+        let mut buffer = OcclusionBuffer::new(camera.perspective(self.screen_size) * camera.v);
+
+        for obj in objects.iter().filter_map(|o| o.upgrade()) {
+            let object = match obj.try_borrow() {
+                Ok(object) => object,
+                Err(_) => continue,
+            };
+
+            let (mesh, _) = match object.find_enabled_component::<Mesh>() {
+                Some(result) => result,
+                None => continue,
+            };
+
+            if !object.active || !mesh.occluder || !camera.renders_layer(object.layer) {
+                continue;
+            }
+
+            let m = compute_model_m(&object);
+            for surface in mesh.surfaces.iter() {
+                if let Ok(data) = surface.buffer.mesh_data() {
+                    buffer.add_occluder(&data, &m);
+                }
+            }
+        }
+
+        buffer.finish();
+        buffer
+    }
+
+    /// Add, move and remove the leaves of the objects changed since the last
+    /// update, as reported by their scene trees. The objects whose mesh is
+    /// loading are checked again once per frame.
+    fn update_object_index(&self) {
+        let mut index = self.object_index.borrow_mut();
+        let index = &mut *index;
+
+        let mut changed = Vec::new();
+        index.trees.retain(|t| t.upgrade().is_some());
+        for tree in index.trees.iter().filter_map(|t| t.upgrade()) {
+            changed.extend(tree.take_changed());
+        }
+
+        if !index.loading_checked {
+            index.loading_checked = true;
+            changed.extend(index.loading.drain(..));
+        }
+
+        // Enabling a component is not reported by the trees
+        let enabled_changes = component_enabled_changes();
+        if index.enabled_changes != enabled_changes {
+            index.enabled_changes = enabled_changes;
+            index.rebuild = true;
+        }
+
+        if index.rebuild {
+            index.rebuild = false;
+            changed = self.objects.clone();
+        }
+
+        let mut seen = HashSet::new();
+        for obj in changed.iter().filter_map(|o| o.upgrade()) {
+            if seen.insert(&*obj as *const RefCell<GameObject> as usize) {
+                index.update(&obj);
+            }
+        }
+    }
+
+    /// The objects which may be inside the frustum, from the spatial index
+    fn visible_objects(&self, frustum: &Frustum) -> Vec<Weak<RefCell<GameObject>>> {
+        self.update_object_index();
+
+        let index = self.object_index.borrow();
+        let mut result = index.unbounded.clone();
+        index
+            .bvh
+            .query(|aabb| frustum.collide_aabb(aabb), |obj| result.push(obj.clone()));
+
+        result
+    }
+
+    /// The active objects with an enabled mesh whose world bounds pass `test`, e.g. to find the
+    /// candidates of a raycast. With `spatial_index` the bounds have a small margin.
+    pub fn query_objects<F>(&self, mut test: F) -> Vec<Rc<RefCell<GameObject>>>
+    where
+        F: FnMut(&Aabb) -> bool,
+    {
+        let mut result = Vec::new();
+
+        if self.spatial_index {
+            self.update_object_index();
+
+            let index = self.object_index.borrow();
+            index
+                .bvh
+                .query(|aabb| test(aabb), |obj| result.extend(obj.upgrade()));
+
+            result.retain(|obj| obj.try_borrow().map_or(false, |o| o.active));
+            return result;
+        }
+
+        for obj in self.objects.iter().filter_map(|o| o.upgrade()) {
+            let object = match obj.try_borrow() {
+                Ok(object) => object,
+                Err(_) => continue,
+            };
+
+            let hit = match object.find_enabled_component::<Mesh>() {
+                Some((ref mesh, _)) if object.active => mesh.world_bounds(&object)
+                    .map_or(false, |b| test(&b.aabb)),
+                _ => false,
+            };
+
+            if hit {
+                result.push(obj.clone());
+            }
+        }
+
+        result
+    }
+
+    /// The nearest active object with an enabled mesh of the layers in `layer_mask`
+    /// whose world bounds `ray` hits within `max_distance`
+    pub fn raycast(&self, ray: &Ray, max_distance: f32, layer_mask: u32) -> Option<RaycastHit> {
+        let hit = |aabb: &Aabb| ray.intersect_aabb(aabb).filter(|t| *t <= max_distance);
+
+        let mut nearest: Option<RaycastHit> = None;
+        for obj in self.query_objects(|aabb| hit(aabb).is_some()) {
+            let distance = {
+                let object = match obj.try_borrow() {
+                    Ok(object) => object,
+                    Err(_) => continue,
+                };
+
+                if !object.active || object.layer >= 32 || layer_mask & (1 << object.layer) == 0 {
+                    continue;
+                }
+
+                // The index bounds have a margin
+                let bounds = object
+                    .find_enabled_component::<Mesh>()
+                    .and_then(|(mesh, _)| mesh.world_bounds(&object));
+                match bounds.and_then(|b| hit(&b.aabb)) {
+                    Some(distance) => distance,
+                    None => continue,
+                }
+            };
+
+            if nearest.as_ref().map_or(true, |n| distance < n.distance) {
+                nearest = Some(RaycastHit {
+                    object: obj,
+                    distance,
+                    point: ray.at(distance),
+                });
+            }
+        }
+
+        nearest
+    }
+
+    /// Merge the enabled meshes of the active static game objects sharing a material
+    /// and a layer into world space buffers, drawn instead of the original meshes.
+    ///
+    /// Meant to run once the scene is set up, call it again after adding,
+    /// moving, disabling or changing static objects. Meshes with property blocks or not
+    /// loaded yet are drawn on their own.
+    pub fn build_static_batches(&mut self) {
+        let mut batches: Vec<(Rc<Material>, u32, MeshData)> = Vec::new();
+
+        for obj in self.objects.iter().filter_map(|o| o.upgrade()) {
+            let object = match obj.try_borrow() {
+                Ok(object) => object,
+                Err(_) => continue,
+            };
+
+            let (mesh, c) = match object.find_component::<Mesh>() {
+                Some(result) => result,
+                None => continue,
+            };
+
+            mesh.static_batched.set(false);
+
+            if !object.active || !c.enabled() || !object.is_static || mesh.properties.is_some() {
+                continue;
+            }
+
+            let mergeable = mesh.surfaces.iter().all(|s| {
+                s.properties.is_none() && match s.buffer.mesh_data() {
+                    Ok(data) => data.vertices.len() / 3 <= MAX_BATCH_VERTICES,
+                    Err(_) => false,
+                }
+            });
+
+            if !mergeable {
+                continue;
+            }
+
+            let m = compute_model_m(&object);
+
+            for surface in mesh.surfaces.iter() {
+                ensure_tangents(surface);
+
+                let data = surface.buffer.mesh_data().unwrap();
+                let layout = data.layout();
+                let vertex_count = data.vertices.len() / 3;
+
+                let pos = batches.iter().position(|&(ref material, layer, ref batch)| {
+                    Rc::ptr_eq(material, &surface.material) && layer == object.layer
+                        && batch.layout() == layout
+                        && batch.vertices.len() / 3 + vertex_count <= MAX_BATCH_VERTICES
+                });
+
+                let i = match pos {
+                    Some(i) => i,
+                    None => {
+                        batches.push((
+                            surface.material.clone(),
+                            object.layer,
+                            MeshData::with_layout(layout),
+                        ));
+                        batches.len() - 1
+                    }
+                };
+
+                batches[i].2.append_transformed(&data, &m);
+            }
+
+            mesh.static_batched.set(true);
+        }
+
+        // The batched meshes leave the spatial index
+        self.object_index.borrow_mut().rebuild = true;
+
+        self.static_batches = batches
+            .into_iter()
+            .map(|(material, layer, data)| StaticBatch {
+                surface: Rc::new(MeshSurface {
+                    buffer: MeshBuffer::new(data),
+                    material,
+                    properties: None,
+                }),
+                layer,
+            })
+            .collect();
     }
 
     #[cfg_attr(feature = "flame_it", flame)]
@@ -598,25 +2460,44 @@ where
         material: Option<&Rc<Material>>,
         clear_option: ClearOption,
     ) -> EngineStats {
-        let mut ctx: EngineContext = EngineContext::new();
+        self.render_camera(
+            camera,
+            camera.render_texture.as_ref(),
+            camera.rect,
+            material,
+            clear_option,
+        )
+    }
+
+    /// Render `camera` into `target` instead of its own render texture and rect
+    fn render_camera(
+        &mut self,
+        camera: &Camera,
+        target: Option<&Rc<RenderTexture>>,
+        rect: Option<((i32, i32), (u32, u32))>,
+        material: Option<&Rc<Material>>,
+        clear_option: ClearOption,
+    ) -> EngineStats {
+        let mut ctx: EngineContext = EngineContext::new(self.texture_units);
+        ctx.states.invert_culling = camera.v.determinant() < 0.0;
 
-        if let Some(ref rt) = camera.render_texture {
-            rt.bind_frame_buffer(&self.gl);
+        if let Some(rt) = target {
+            rt.bind_frame_buffer(&self.gl, &self.capabilities);
+            ctx.states.color_attachments = rt.color_count();
         }
 
-        match camera.rect {
+        match rect {
             Some(((x, y), (w, h))) => {
-                self.gl.viewport(x, y, w, h);
+                self.gl.set_viewport(x, y, w, h);
             }
             None => {
-                self.gl
-                    .viewport(0, 0, self.screen_size.0, self.screen_size.1);
+                self.gl.set_viewport(0, 0, self.screen_size.0, self.screen_size.1);
             }
         }
 
         self.clear(clear_option);
 
-        self.prepare_ctx(&mut ctx);
+        self.prepare_ctx(&mut ctx, camera);
 
         // gather commands
         let mut render_q = self.gather_all_render_commands(&camera, false, Some(&mut ctx.stats));
@@ -626,8 +2507,14 @@ where
             .queues
             .get_mut(&RenderQueue::Opaque)
             .unwrap()
-            .sort_by_cam_distance_reverse()
-            .sort_by_material();
+            .sort_by_state();
+
+        // Sort the alpha test queue
+        render_q
+            .queues
+            .get_mut(&RenderQueue::AlphaTest)
+            .unwrap()
+            .sort_by_state();
 
         // Sort the transparent queue
         render_q
@@ -643,91 +2530,1650 @@ where
             .unwrap()
             .commands
             .len() as u32;
-        ctx.stats.opaque_count = render_q
-            .queues
-            .get(&RenderQueue::Opaque)
-            .unwrap()
-            .commands
-            .len() as u32;
+        ctx.stats.opaque_count = [RenderQueue::Opaque, RenderQueue::AlphaTest]
+            .iter()
+            .map(|q| render_q.queues.get(q).unwrap().commands.len() as u32)
+            .sum();
+
+        let depth_prepass = camera.depth_prepass && material.is_none();
+        if depth_prepass {
+            let opaque = render_q.queues.get(&RenderQueue::Opaque).unwrap();
+            self.render_depth_prepass(&mut ctx, opaque, camera);
+        }
+
+        for (queue, q) in render_q.queues.iter() {
+            if let CameraClearFlags::SolidColor(_) = camera.clear_flags {
+                if *queue == RenderQueue::Skybox {
+                    continue;
+                }
+            }
+
+            // Drawn by render_overlay after the post processing
+            if *queue == RenderQueue::Overlay {
+                continue;
+            }
+
+            if depth_prepass && *queue == RenderQueue::Opaque {
+                // Only the surfaces in front are shaded
+                ctx.states.overrides = MaterialState {
+                    depth_test: Some(DepthTest::Equal),
+                    depth_write: Some(false),
+                    ..Default::default()
+                };
+            }
 
-        for (_, q) in render_q.queues.iter() {
             self.render_commands(&mut ctx, &q, camera, material);
+            ctx.states.overrides = MaterialState::default();
+        }
+
+        if material.is_none() {
+            let overlay = render_q.queues.get_mut(&RenderQueue::Overlay).unwrap();
+            self.overlay_queue = Some(RenderQueueState {
+                states: overlay.states,
+                commands: mem::replace(&mut overlay.commands, Vec::new()),
+            });
         }
 
-        if let Some(ref rt) = camera.render_texture {
+        self.recycle_render_queues(render_q);
+
+        if let Some(rt) = target {
             rt.unbind_frame_buffer(&self.gl);
         }
 
         ctx.stats
     }
 
-    #[cfg_attr(feature = "flame_it", flame)]
-    pub fn render_pass(&mut self, camera: &Camera, clear_option: ClearOption) -> EngineStats {
-        self.render_pass_with_material(camera, None, clear_option)
+    /// Write the depth of the opaque queue, without any color output
+    fn render_depth_prepass(
+        &mut self,
+        ctx: &mut EngineContext,
+        q: &RenderQueueState,
+        camera: &Camera,
+    ) {
+        if self.depth_prepass_material.is_none() {
+            let mut material = Material::new(self.asset_system.new_program("unrust/depth_only"));
+            material.states.draw_buffers = Some(0);
+            self.depth_prepass_material = Some(Rc::new(material));
+        }
+
+        let material = self.depth_prepass_material.clone().unwrap();
+        self.render_commands(ctx, q, camera, Some(&material));
     }
 
-    pub fn main_camera(&self) -> Option<Arc<Component>> {
-        let mut found = self.current_camera.borrow_mut();
-        match *found {
-            None => *found = self.find_component::<Camera>().map(|c| c.clone()),
-            _ => (),
+    fn light_space_matrix(&self, light_cam: &Camera, direction: Vector3f) -> Option<Matrix4f> {
+        let mut up = Vector3::unit_y();
+        if up.dot(direction.normalize()).abs() > 0.9999 {
+            up = Vector3::unit_z();
         }
 
-        if let Some(ref c) = *found {
-            return Some(c.clone());
-        }
+        let view = Matrix4::look_at(Point3::new(0.0, 0.0, 0.0), Point3::from_vec(direction), up);
+
+        // Fit the light frustum to the bounds of all shadow casters
+        let aabb = self.get_bounds(light_cam)?
+            .corners()
+            .iter()
+            .fold(Aabb::empty(), |mut acc, p| {
+                acc.merge_point(&view.transform_point(Point3::from_vec(*p)).to_vec());
+                acc
+            });
+
+        // Right hand coordinate system, visible z are negative.
+        let proj = ortho(
+            aabb.min.x,
+            aabb.max.x,
+            aabb.min.y,
+            aabb.max.y,
+            -aabb.max.z,
+            -aabb.min.z,
+        );
+
+        Some(proj * view)
+    }
+
+    fn shadow_camera() -> Camera {
+        let mut light_cam = Camera::new();
+        light_cam.enable_frustum_culling = false;
+        light_cam.included_render_queues = Some(Default::default());
+        light_cam
+            .included_render_queues
+            .as_mut()
+            .unwrap()
+            .insert(RenderQueue::Opaque);
+
+        light_cam
+    }
+
+    /// Render the shadow maps once per frame, the point lights nearest to `eye`
+    /// get the shadows
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn render_shadow_passes(&mut self, eye: &Vector3f) {
+        if self.shadows_rendered {
+            return;
+        }
+
+        self.shadows_rendered = true;
+        self.update_lights();
+
+        // The shadow maps are depth textures
+        if !self.capabilities.depth_texture {
+            return;
+        }
+
+        if self.external_directional_shadow {
+            if let Some(ref mut shadow) = self.shadow {
+                shadow.enabled = false;
+            }
+        } else {
+            self.render_directional_shadow();
+        }
+
+        self.render_point_shadows(eye);
+    }
+
+    fn render_directional_shadow(&mut self) {
+        if let Some(ref mut shadow) = self.shadow {
+            shadow.enabled = false;
+        }
+
+        let (direction, settings) = match self.find_main_light() {
+            Some(ref c) => match c.try_as::<Light>().unwrap().borrow().directional() {
+                Some(l) if l.cast_shadows => (l.world_space_direction, l.shadow),
+                _ => return,
+            },
+            None => return,
+        };
+
+        let size = fit_shadow_size(settings.resolution, 1, self.capabilities.max_texture_size);
+
+        let mut light_cam = Self::shadow_camera();
+
+        let light_matrix = match self.light_space_matrix(&light_cam, direction) {
+            Some(m) => m,
+            None => return,
+        };
+
+        if self.shadow.is_none() {
+            let material = Material::new(self.asset_system.new_program("unrust/shadow"));
+
+            self.shadow = Some(ShadowMapState {
+                rt: Rc::new(RenderTexture::new(size, size, TextureAttachment::Depth)),
+                size: size,
+                material: Rc::new(material),
+                light_matrix: Matrix4f::identity(),
+                settings: settings,
+                enabled: false,
+            });
+        }
+
+        let material = {
+            let shadow = self.shadow.as_mut().unwrap();
+            if shadow.size != size {
+                shadow.rt = Rc::new(RenderTexture::new(size, size, TextureAttachment::Depth));
+                shadow.size = size;
+            }
+
+            shadow.light_matrix = light_matrix;
+            shadow.settings = settings;
+
+            light_cam.render_texture = Some(shadow.rt.clone());
+            light_cam.rect = Some(((0, 0), (size, size)));
+
+            shadow.material.clone()
+        };
+
+        material.set("uShadowMatrix", light_matrix);
+
+        self.render_pass_with_material(
+            &light_cam,
+            Some(&material),
+            ClearOption {
+                color: None,
+                clear_color: false,
+                clear_depth: true,
+                clear_stencil: false,
+            },
+        );
+
+        self.shadow.as_mut().unwrap().enabled = true;
+    }
+
+    fn render_point_shadows(&mut self, eye: &Vector3f) {
+        if let Some(ref mut shadow) = self.point_shadow {
+            shadow.enabled = false;
+        }
+
+        let mut lights: Vec<_> = self.find_point_lights(eye)
+            .iter()
+            .take(MAX_POINT_SHADOWS)
+            .map(|c| {
+                let light = c.try_as::<Light>().unwrap();
+                let light = light.borrow();
+                let l = light.point().unwrap();
+
+                if l.cast_shadows {
+                    Some(PointShadowLight {
+                        light: Arc::downgrade(c),
+                        position: l.world_space_position,
+                        depth_range: (POINT_SHADOW_NEAR, l.range()),
+                        settings: l.shadow,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        // All lights share the atlas, so the tiles fit the largest request
+        let max_resolution = lights
+            .iter()
+            .filter_map(|l| l.as_ref().map(|l| l.settings.resolution))
+            .max();
+        let tile = match max_resolution {
+            Some(size) => fit_shadow_size(
+                size,
+                6u32.max(MAX_POINT_SHADOWS as u32),
+                self.capabilities.max_texture_size,
+            ),
+            None => return,
+        };
+
+        lights.resize(MAX_POINT_SHADOWS, None);
+
+        let new_atlas = || {
+            Rc::new(RenderTexture::new(
+                tile * 6,
+                tile * MAX_POINT_SHADOWS as u32,
+                TextureAttachment::Depth,
+            ))
+        };
+
+        if self.point_shadow.is_none() {
+            let material = Material::new(self.asset_system.new_program("unrust/point_shadow"));
+
+            self.point_shadow = Some(PointShadowState {
+                rt: new_atlas(),
+                tile_size: tile,
+                material: Rc::new(material),
+                lights: Vec::new(),
+                enabled: false,
+            });
+        }
+
+        let (rt, material) = {
+            let shadow = self.point_shadow.as_mut().unwrap();
+            if shadow.tile_size != tile {
+                shadow.rt = new_atlas();
+                shadow.tile_size = tile;
+            }
+
+            (shadow.rt.clone(), shadow.material.clone())
+        };
+
+        let mut light_cam = Self::shadow_camera();
+        light_cam.render_texture = Some(rt);
+
+        let mut clear_depth = true;
+
+        for (i, light) in lights.iter().enumerate() {
+            let (pos, (near, far)) = match light {
+                &Some(ref l) => (l.position, l.depth_range),
+                &None => continue,
+            };
+
+            let proj: Matrix4f = PerspectiveFov {
+                fovy: Deg(90.0).into(),
+                aspect: 1.0,
+                near: near,
+                far: far,
+            }.into();
+
+            for (face, &(forward, up)) in cube_face_axes().iter().enumerate() {
+                let view = Matrix4::look_at(
+                    Point3::from_vec(pos),
+                    Point3::from_vec(pos + forward),
+                    up,
+                );
+
+                material.set("uShadowMatrix", proj * view);
+                light_cam.rect = Some((
+                    ((face as u32 * tile) as i32, (i as u32 * tile) as i32),
+                    (tile, tile),
+                ));
+
+                // glClear ignores the viewport, so only clear the atlas once
+                self.render_pass_with_material(
+                    &light_cam,
+                    Some(&material),
+                    ClearOption {
+                        color: None,
+                        clear_color: false,
+                        clear_depth: clear_depth,
+                        clear_stencil: false,
+                    },
+                );
+
+                clear_depth = false;
+            }
+        }
+
+        let shadow = self.point_shadow.as_mut().unwrap();
+        shadow.lights = lights;
+        shadow.enabled = true;
+    }
+
+    /// Render the `PlanarReflection` components seen by `camera`
+    /// and bind them to the materials of their meshes
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn render_planar_reflections(&mut self, camera: &Camera) {
+        let mut reflections = Vec::new();
+        self.map_component::<PlanarReflection, _>(|obj, c| {
+            reflections.push((obj, c));
+            true
+        });
+
+        for (obj, c) in reflections.into_iter() {
+            let (point, normal, materials) = {
+                let object = obj.borrow();
+                let materials: Vec<Rc<Material>> = match object.find_component::<Mesh>() {
+                    Some((ref mesh, _)) if object.active => {
+                        mesh.surfaces.iter().map(|s| s.material.clone()).collect()
+                    }
+                    _ => continue,
+                };
+
+                let m = object.transform.as_global_matrix();
+                let normal = m.transform_vector(Vector3::unit_y()).normalize();
+                (m.w.truncate(), normal, materials)
+            };
+
+            // Seen from behind
+            if (camera.eye() - point).dot(normal) <= 0.0 {
+                continue;
+            }
+
+            let reflection = c.try_as::<PlanarReflection>().unwrap();
+            let (cam, name) = {
+                let mut reflection = reflection.borrow_mut();
+                let cam = reflection.reflection_camera(camera, point, normal, self.screen_size);
+                (cam, reflection.texture_name.clone())
+            };
+
+            let rt = cam.render_texture.clone().unwrap();
+            self.reflecting_object = Some(obj);
+            self.render_camera(&cam, Some(&rt), cam.rect, None, ClearOption::default());
+            self.reflecting_object = None;
+
+            for material in materials.iter() {
+                material.set(name.clone(), rt.as_texture());
+            }
+        }
+    }
+
+    /// Step the `ParticleEmitter` components of the active objects by `dt` seconds,
+    /// on the GPU for those with `gpu_simulation` when it is supported
+    #[cfg_attr(feature = "flame_it", flame)]
+    pub fn update_particles(&mut self, dt: f32) {
+        let gpu_supported = self.gpu_particles_supported();
+
+        let mut emitters = Vec::new();
+        self.map_component::<ParticleEmitter, _>(|obj, c| {
+            emitters.push((obj, c));
+            true
+        });
+
+        for (obj, c) in emitters.into_iter() {
+            let m = {
+                let object = obj.borrow();
+                if !object.active {
+                    continue;
+                }
+
+                object.transform.as_global_matrix()
+            };
+
+            let emitter = c.try_as::<ParticleEmitter>().unwrap();
+            let mut emitter = emitter.borrow_mut();
+
+            if !gpu_supported || !emitter.gpu_simulation {
+                emitter.update(dt, &m);
+                continue;
+            }
+
+            if self.particle_update_material.is_none() {
+                let program = self.asset_system.new_program("unrust/particle_update");
+                self.particle_update_material = Some(Rc::new(Material::new(program)));
+            }
+
+            let material = self.particle_update_material.clone().unwrap();
+            let step = emitter.begin_gpu_step(dt, &m, &material);
+            let rect = ((0, 0), step.size);
+
+            material.set("uVelocityPass", false);
+            self.render_fullscreen(&material, Some(&step.positions), rect);
+            material.set("uVelocityPass", true);
+            self.render_fullscreen(&material, Some(&step.velocities), rect);
+
+            emitter.end_gpu_step();
+        }
+    }
+
+    /// Step the `SpriteAnimation` components of the active objects by `dt` seconds,
+    /// with the `SpriteRenderer` of their object
+    pub fn update_sprite_animations(&self, dt: f32) {
+        let mut animations = Vec::new();
+        self.map_component::<SpriteAnimation, _>(|obj, c| {
+            animations.push((obj, c));
+            true
+        });
+
+        for (obj, c) in animations.into_iter() {
+            let sprite = {
+                let object = obj.borrow();
+                if !object.active {
+                    continue;
+                }
+
+                match object.find_component::<SpriteRenderer>() {
+                    Some((_, sprite)) => sprite.clone(),
+                    None => continue,
+                }
+            };
+
+            let animation = c.try_as::<SpriteAnimation>().unwrap();
+            let sprite = sprite.try_as::<SpriteRenderer>().unwrap();
+            animation.borrow_mut().update(dt, &mut sprite.borrow_mut());
+        }
+    }
+
+    /// Call the `Behaviour` of the components registered in `behaviours`, of
+    /// the active objects
+    pub fn update_behaviours(&mut self, dt: f32) {
+        let objects = self.objects.iter().filter_map(|obj| obj.upgrade()).collect();
+        self.behaviours.update(objects, dt);
+    }
+
+    /// Run the fixed steps of `physics_2d` covering `dt` seconds over the active objects
+    pub fn update_physics_2d(&mut self, dt: f32) {
+        let mut objects = Vec::new();
+        for obj in self.objects.iter().filter_map(|obj| obj.upgrade()) {
+            let simulated = match obj.try_borrow() {
+                Ok(object) => {
+                    object.active
+                        && (object.find_component::<RigidBody2D>().is_some()
+                            || object.find_component::<Collider2D>().is_some())
+                }
+                Err(_) => false,
+            };
+
+            if simulated {
+                objects.push(obj);
+            }
+        }
+
+        self.physics_2d.update(dt, objects);
+    }
+
+    /// Capture and convolve the `ReflectionProbe` components waiting for a bake
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn bake_reflection_probes(&mut self) {
+        let mut probes = Vec::new();
+        self.map_component::<ReflectionProbe, _>(|obj, c| {
+            probes.push((obj, c));
+            true
+        });
+
+        for (obj, c) in probes.into_iter() {
+            let position = {
+                let object = obj.borrow();
+                if !object.active {
+                    continue;
+                }
+
+                object.transform.as_global_matrix().w.truncate()
+            };
+
+            let probe = c.try_as::<ReflectionProbe>().unwrap();
+            let (maps, culling_mask, environment) = {
+                let mut probe = probe.borrow_mut();
+                let culling_mask = probe.culling_mask;
+                let environment = probe.environment().cloned();
+                match probe.begin_bake() {
+                    Some(maps) => (maps, culling_mask, environment),
+                    None => continue,
+                }
+            };
+
+            match environment {
+                Some(source) => match source.prepare(&self.gl, &self.capabilities, 0) {
+                    Ok(_) => self.render_environment(&source, &maps),
+                    // Baked once the image is loaded
+                    Err(AssetError::NotReady) => continue,
+                    Err(err) => panic!(format!("Failed to load environment, reason {:?}", err)),
+                },
+                None => self.capture_reflection_probe(position, culling_mask, &maps),
+            }
+
+            self.convolve_reflection_probe(&maps);
+
+            probe.borrow_mut().end_bake();
+        }
+    }
+
+    /// Render the 6 faces around `position` into the capture atlas
+    fn capture_reflection_probe(
+        &mut self,
+        position: Vector3f,
+        culling_mask: u32,
+        maps: &ProbeMaps,
+    ) {
+        let size = maps.size;
+        let eye = Point3::from_vec(position);
+
+        let mut cam = Camera::new();
+        cam.projection = Some(
+            PerspectiveFov {
+                fovy: Deg(90.0).into(),
+                aspect: 1.0,
+                near: cam.znear,
+                far: cam.zfar,
+            }.into(),
+        );
+        cam.culling_mask = culling_mask;
+
+        for (face, &(forward, up)) in cube_face_axes().iter().enumerate() {
+            cam.lookat(&eye, &(eye + forward), &up);
+            let rect = Some((((face as u32 * size) as i32, 0), (size, size)));
+
+            // glClear ignores the viewport, so only clear the color once
+            let clear = ClearOption {
+                clear_color: face == 0,
+                ..Default::default()
+            };
+
+            self.render_camera(&cam, Some(&maps.capture), rect, None, clear);
+        }
+    }
+
+    /// Render the equirectangular image `source` into the capture atlas
+    fn render_environment(&mut self, source: &Rc<Texture>, maps: &ProbeMaps) {
+        if self.environment_material.is_none() {
+            let material = Material::new(self.asset_system.new_program("unrust/equirect_to_cube"));
+            self.environment_material = Some(Rc::new(material));
+        }
+
+        let material = self.environment_material.clone().unwrap();
+        material.set("uSourceTexture", source.clone());
+        material.set("uSourceRGBM", source.is_rgbm());
+
+        self.render_fullscreen(
+            &material,
+            Some(&maps.capture),
+            ((0, 0), (maps.size * 6, maps.size)),
+        );
+    }
+
+    /// Fill the irradiance map and the levels of the specular map from the capture
+    fn convolve_reflection_probe(&mut self, maps: &ProbeMaps) {
+        if self.probe_materials.is_none() {
+            let irradiance = Material::new(self.asset_system.new_program("unrust/ibl_irradiance"));
+            let specular = Material::new(self.asset_system.new_program("unrust/ibl_specular"));
+            self.probe_materials = Some((Rc::new(irradiance), Rc::new(specular)));
+        }
+
+        let (irradiance, specular) = self.probe_materials.clone().unwrap();
+        let size = maps.size;
+        let source_size = Vector2f::new((size * 6) as f32, size as f32);
+
+        irradiance.set("uSourceTexture", maps.capture.as_texture());
+        irradiance.set("uSourceSize", source_size);
+        self.render_fullscreen(
+            &irradiance,
+            Some(&maps.irradiance),
+            ((0, 0), (PROBE_IRRADIANCE_SIZE * 6, PROBE_IRRADIANCE_SIZE)),
+        );
+
+        specular.set("uSourceTexture", maps.capture.as_texture());
+        specular.set("uSourceSize", source_size);
+        for level in 0..PROBE_SPECULAR_LEVELS {
+            let roughness = level as f32 / (PROBE_SPECULAR_LEVELS - 1) as f32;
+            specular.set("uRoughness", roughness);
+            self.render_fullscreen(
+                &specular,
+                Some(&maps.specular),
+                ((0, (level * size) as i32), (size * 6, size)),
+            );
+        }
+    }
+
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn update_light_clusters(&mut self, camera: &Camera, viewport: ((i32, i32), (u32, u32))) {
+        if !self.clustered_lighting {
+            return;
+        }
+
+        let lights = self.find_clustered_lights(&camera.eye());
+        let proj = camera.perspective(self.screen_size);
+
+        if self.light_clusters.is_none() {
+            self.light_clusters = Some(LightClusters::new());
+        }
+
+        self.light_clusters.as_mut().unwrap().update(
+            &camera.v,
+            &proj,
+            camera.znear,
+            camera.zfar,
+            viewport,
+            &lights,
+        );
+    }
+
+    #[cfg_attr(feature = "flame_it", flame)]
+    pub fn render_pass(&mut self, camera: &Camera, clear_option: ClearOption) -> EngineStats {
+        let (stats, depth) = self.render_scene_pass(camera, clear_option);
+
+        let target = camera.render_texture.clone();
+        let rect = camera.rect.unwrap_or(((0, 0), self.screen_size));
+        self.render_overlay(camera, target.as_ref(), rect, depth);
+
+        stats
+    }
+
+    /// `render_pass` without the overlay, with the depth texture of the scene
+    /// when it was not rendered into the camera target
+    fn render_scene_pass(
+        &mut self,
+        camera: &Camera,
+        clear_option: ClearOption,
+    ) -> (EngineStats, Option<Rc<Texture>>) {
+        self.render_shadow_passes(&camera.eye());
+
+        let rect = camera.rect.unwrap_or(((0, 0), self.screen_size));
+        let hdr = if self.renders_hdr(camera) {
+            camera.hdr.as_ref()
+        } else {
+            None
+        };
+        let viewport = match hdr {
+            Some(_) => ((0, 0), rect.1),
+            None => rect,
+        };
+
+        self.render_planar_reflections(camera);
+        self.update_light_clusters(camera, viewport);
+        self.render_ssao(camera, viewport);
+        self.render_camera_depth(camera, viewport);
+        self.render_lighting_2d(camera, viewport);
+
+        let (stats, depth) = match hdr {
+            Some(hdr) => {
+                let rt = self.hdr_target(rect.1);
+
+                // The clear color is sRGB, like the other colors
+                let clear_option = match self.color_space {
+                    ColorSpace::Linear => ClearOption {
+                        color: clear_option.color.map(srgb_to_linear),
+                        ..clear_option
+                    },
+                    ColorSpace::Gamma => clear_option,
+                };
+
+                let stats =
+                    self.render_camera(camera, Some(&rt), Some(viewport), None, clear_option);
+
+                if self.tonemap_material.is_none() {
+                    let prog = self.asset_system.new_program("unrust/tonemap");
+                    self.tonemap_material = Some(Rc::new(Material::new(prog)));
+                }
+
+                let material = self.tonemap_material.clone().unwrap();
+                material.set("uScreenTexture", rt.as_texture());
+                material.set("uExposure", hdr.exposure);
+                material.set("uTonemapping", hdr.tonemapping as i32);
+                material.set("uEncodeSrgb", self.color_space == ColorSpace::Linear);
+
+                self.render_fullscreen(&material, camera.render_texture.as_ref(), rect);
+                (stats, rt.depth_texture())
+            }
+            None => (
+                self.render_pass_with_material(camera, None, clear_option),
+                None,
+            ),
+        };
+
+        // Only valid for this camera
+        if let Some(ref mut clusters) = self.light_clusters {
+            clusters.enabled = false;
+        }
+
+        if let Some(ref mut ssao) = self.ssao {
+            ssao.enabled = false;
+        }
+
+        if let Some(ref mut depth) = self.camera_depth {
+            depth.enabled = false;
+        }
+
+        if let Some(ref mut lighting) = self.lighting_2d {
+            lighting.enabled = false;
+        }
+
+        camera.set_prev_view_proj(camera.perspective(self.screen_size) * camera.v);
+
+        (stats, depth)
+    }
+
+    /// The immediate mode debug shapes, drawn in the overlay of every camera
+    pub fn debug_draw(&self) -> RefMut<DebugDraw> {
+        self.debug_draw.borrow_mut()
+    }
+
+    /// Draw the `Overlay` queue of the last scene pass of `camera`, the debug
+    /// shapes and the gizmos over the final image, after the post processing.
+    /// `depth` is the scene depth to test against when it is not in `target`,
+    /// without depth texture writes (WebGL1) the overlay is drawn on top.
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn render_overlay(
+        &mut self,
+        camera: &Camera,
+        target: Option<&Rc<RenderTexture>>,
+        rect: ((i32, i32), (u32, u32)),
+        depth: Option<Rc<Texture>>,
+    ) {
+        let overlay = self.overlay_queue.take();
+
+        self.update_gizmos(camera);
+        let mut buffers = self.debug_draw.borrow_mut().line_buffers();
+        buffers.extend(self.gizmo_draw.borrow_mut().line_buffers());
+
+        let has_commands = overlay.as_ref().map_or(false, |q| !q.commands.is_empty());
+        if !has_commands && buffers.is_empty() {
+            return;
+        }
+
+        if let Some(depth) = depth {
+            if self.depth_copy_supported() {
+                if self.depth_copy_material.is_none() {
+                    let prog = self.asset_system.new_program("unrust/depth_copy");
+                    let mut material = Material::new(prog);
+                    material.states.depth_test = Some(DepthTest::Always);
+                    material.states.depth_write = Some(true);
+                    material.states.alpha_blending = Some(true);
+                    material.states.blend = Some(Blend {
+                        equation: BlendEquation::Add,
+                        src: BlendFactor::Zero,
+                        dst: BlendFactor::One,
+                    });
+                    self.depth_copy_material = Some(Rc::new(material));
+                }
+
+                let material = self.depth_copy_material.clone().unwrap();
+                material.set("uDepthTexture", depth);
+                self.render_fullscreen(&material, target, rect);
+            } else {
+                // The scene depth is lost, nothing should hide the overlay
+                let ((x, y), (w, h)) = rect;
+                self.gl.set_viewport(x, y, w, h);
+                self.clear(ClearOption {
+                    color: None,
+                    clear_color: false,
+                    clear_depth: true,
+                    clear_stencil: false,
+                });
+            }
+        }
+
+        if self.debug_line_material.is_none() {
+            let prog = self.asset_system.new_program("unrust/debug_line");
+            self.debug_line_material = Some(Rc::new(Material::new(prog)));
+        }
+        let line_material = self.debug_line_material.clone().unwrap();
+
+        let mut ctx = EngineContext::new(self.texture_units);
+        ctx.states.invert_culling = camera.v.determinant() < 0.0;
+
+        if let Some(rt) = target {
+            rt.bind_frame_buffer(&self.gl, &self.capabilities);
+            ctx.states.color_attachments = rt.color_count();
+        }
+
+        let ((x, y), (w, h)) = rect;
+        self.gl.set_viewport(x, y, w, h);
+
+        if let Some(ref q) = overlay {
+            self.prepare_ctx(&mut ctx, camera);
+            self.render_commands(&mut ctx, q, camera, None);
+        }
+
+        let perspective = camera.perspective(self.screen_size);
+        let gl = &self.gl;
+
+        for (buffer, depth_test) in buffers.into_iter() {
+            ctx.states.apply_defaults();
+            ctx.states.apply(&MaterialState {
+                cull: Some(CullMode::Off),
+                depth_test: Some(if depth_test {
+                    DepthTest::LessEqual
+                } else {
+                    DepthTest::Never
+                }),
+                depth_write: Some(false),
+                alpha_blending: Some(false),
+                blend: None,
+                draw_buffers: None,
+            });
+            ctx.states.commit(gl);
+
+            match self.setup_material(&mut ctx, &line_material, false) {
+                Ok(_) => {
+                    let prog = ctx.prog.upgrade().unwrap();
+
+                    if buffer.bind(gl, &prog).is_ok() {
+                        prog.set("uPMatrix", perspective);
+                        prog.set("uVMatrix", camera.v);
+                        prog.commit(gl);
+                        buffer.render_lines(gl);
+                        buffer.unbind(gl);
+                    }
+                }
+                Err(AssetError::NotReady) => (),
+                Err(err) => panic!(format!("Failed to load material, reason {:?}", err)),
+            }
+        }
+
+        if let Some(rt) = target {
+            rt.unbind_frame_buffer(gl);
+        }
+    }
+
+    /// Writing the depth from a fragment shader
+    fn depth_copy_supported(&self) -> bool {
+        self.capabilities.frag_depth
+    }
+
+    /// Fill the gizmo lines of `gizmos` for the view of `camera`
+    fn update_gizmos(&self, camera: &Camera) {
+        let mut draw = self.gizmo_draw.borrow_mut();
+        draw.clear();
+
+        let settings = self.gizmos;
+        if !settings.enabled {
+            return;
+        }
+
+        draw.depth_test = settings.depth_test;
+
+        if settings.cameras {
+            self.map_component::<Camera, _>(|obj, c| {
+                let cam = c.try_as::<Camera>().unwrap().borrow();
+                if obj.borrow().active && !ptr::eq(&*cam, camera) {
+                    camera_gizmo(&mut draw, &cam, self.screen_size);
+                }
+                true
+            });
+        }
+
+        if settings.lights {
+            self.map_component::<Light, _>(|obj, c| {
+                let object = obj.borrow();
+                if object.active {
+                    let position = object.transform.as_global_matrix().w.truncate();
+                    light_gizmo(&mut draw, &c.try_as::<Light>().unwrap().borrow(), position);
+                }
+                true
+            });
+        }
+
+        if settings.bounds {
+            self.map_component::<Mesh, _>(|obj, c| {
+                let object = obj.borrow();
+                if object.active {
+                    let mesh = c.try_as::<Mesh>().unwrap().borrow();
+                    if let Some(bounds) = mesh.bounds() {
+                        bounds_gizmo(&mut draw, &bounds.transform(&compute_model_m(&object)).aabb);
+                    }
+                }
+                true
+            });
+        }
+    }
+
+    /// Add the `DebugDraw::text3d` labels to imgui, projected with the main camera
+    fn debug_draw_labels(&self) {
+        let debug_draw = self.debug_draw.borrow();
+        if debug_draw.texts().is_empty() {
+            return;
+        }
+
+        let camera = match self.main_camera() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let camera = camera.try_as::<Camera>().unwrap().borrow();
+
+        imgui::pivot((0.5, 0.5));
+        for text in debug_draw.texts().iter() {
+            if let Some(p) = camera.world_to_screen(text.position, self.screen_size, self.hidpi) {
+                imgui::label(imgui::Metric::Pixel(p.x, p.y), &text.text);
+            }
+        }
+        imgui::pivot((0.0, 0.0));
+    }
+
+    /// Render the opaque queue with `material` into `rt`, used by the prepasses
+    fn render_opaque_prepass(
+        &self,
+        camera: &Camera,
+        rt: &Rc<RenderTexture>,
+        material: &Rc<Material>,
+        size: (u32, u32),
+    ) {
+        let mut ctx = EngineContext::new(self.texture_units);
+
+        rt.bind_frame_buffer(&self.gl, &self.capabilities);
+        self.gl.set_viewport(0, 0, size.0, size.1);
+
+        // Facing the camera at the far plane
+        self.clear(ClearOption {
+            color: Some((0.5, 0.5, 1.0, 1.0)),
+            clear_color: true,
+            clear_depth: true,
+            clear_stencil: false,
+        });
+
+        self.prepare_ctx(&mut ctx, camera);
+
+        let mut render_q = self.gather_all_render_commands(camera, false, None);
+        if let Some(q) = render_q.queues.get_mut(&RenderQueue::Opaque) {
+            q.sort_by_cam_distance_reverse();
+            self.render_commands(&mut ctx, q, camera, Some(material));
+        }
+        self.recycle_render_queues(render_q);
+
+        rt.unbind_frame_buffer(&self.gl);
+    }
+
+    /// Build the ambient occlusion of `camera`, sampled by the main pass
+    /// at `viewport`
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn render_ssao(&mut self, camera: &Camera, viewport: ((i32, i32), (u32, u32))) {
+        let settings = match camera.ssao {
+            Some(settings) => settings,
+            None => return,
+        };
+
+        let size = viewport.1;
+        let recreate = match self.ssao {
+            Some(ref ssao) => ssao.size() != size,
+            None => true,
+        };
+
+        if recreate {
+            self.ssao = Some(SsaoState::new(&*self.asset_system, size));
+        }
+
+        let (normal_rt, prepass_material) = {
+            let ssao = self.ssao.as_mut().unwrap();
+            ssao.enabled = false;
+            ssao.setup(&settings, &camera.perspective(self.screen_size), viewport);
+            (ssao.normal_rt.clone(), ssao.prepass_material.clone())
+        };
+
+        self.render_opaque_prepass(camera, &normal_rt, &prepass_material, size);
+
+        {
+            let ssao = self.ssao.as_ref().unwrap();
+            let (w, h) = ssao.half_size();
+
+            ssao.ao_material
+                .set("uScreenSize", Vector2f::new(w as f32, h as f32));
+            self.render_fullscreen(&ssao.ao_material, Some(&ssao.ao_rt), ((0, 0), (w, h)));
+
+            ssao.blur_material
+                .set("uScreenSize", Vector2f::new(w as f32, h as f32));
+            self.render_fullscreen(&ssao.blur_material, Some(&ssao.blur_rt), ((0, 0), (w, h)));
+        }
+
+        self.ssao.as_mut().unwrap().enabled = true;
+    }
+
+    /// Render the depth of the opaque queue of `camera` into the texture
+    /// sampled by the main pass at `viewport`
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn render_camera_depth(&mut self, camera: &Camera, viewport: ((i32, i32), (u32, u32))) {
+        if !camera.depth_texture {
+            return;
+        }
+
+        let size = viewport.1;
+        let recreate = match self.camera_depth {
+            Some(ref depth) => depth.size() != size,
+            None => true,
+        };
+
+        if recreate {
+            self.camera_depth = Some(CameraDepthState::new(&*self.asset_system, size));
+        }
+
+        let (rt, prepass_material) = {
+            let depth = self.camera_depth.as_mut().unwrap();
+            depth.enabled = false;
+            depth.setup((camera.znear, camera.zfar), viewport);
+            (depth.rt.clone(), depth.prepass_material.clone())
+        };
+
+        self.render_opaque_prepass(camera, &rt, &prepass_material, size);
+
+        self.camera_depth.as_mut().unwrap().enabled = true;
+    }
+
+    /// Render the normals of the sprites into `rt`, facing the camera where
+    /// there is no sprite or no normal map
+    fn render_sprite_normals(
+        &self,
+        camera: &Camera,
+        rt: &Rc<RenderTexture>,
+        material: &Rc<Material>,
+        size: (u32, u32),
+    ) {
+        let mut ctx = EngineContext::new(self.texture_units);
+
+        rt.bind_frame_buffer(&self.gl, &self.capabilities);
+        self.gl.set_viewport(0, 0, size.0, size.1);
+
+        self.clear(ClearOption {
+            color: Some((0.5, 0.5, 1.0, 1.0)),
+            clear_color: true,
+            clear_depth: false,
+            clear_stencil: false,
+        });
+
+        self.prepare_ctx(&mut ctx, camera);
+
+        let render_q = self.gather_all_render_commands(camera, false, None);
+        if let Some(q) = render_q.queues.get(&RenderQueue::Sprite) {
+            self.render_commands(&mut ctx, q, camera, Some(material));
+        }
+        self.recycle_render_queues(render_q);
+
+        rt.unbind_frame_buffer(&self.gl);
+    }
+
+    /// Build the light texture of the `Light2D` components for `camera`,
+    /// multiplied over the sprites of the main pass at `viewport`
+    #[cfg_attr(feature = "flame_it", flame)]
+    fn render_lighting_2d(&mut self, camera: &Camera, viewport: ((i32, i32), (u32, u32))) {
+        let settings = match camera.lighting_2d {
+            Some(settings) => settings,
+            None => return,
+        };
+
+        let size = viewport.1;
+        let recreate = match self.lighting_2d {
+            Some(ref lighting) => lighting.size() != size,
+            None => true,
+        };
+
+        if recreate {
+            self.lighting_2d = Some(Lighting2DState::new(&*self.asset_system, size));
+        }
+
+        let view_proj = camera.perspective(self.screen_size) * camera.v;
+        let (light_rt, normal_rt, normal_material) = {
+            let lighting = self.lighting_2d.as_mut().unwrap();
+            lighting.enabled = false;
+            lighting.setup(&settings, &view_proj, viewport);
+            (
+                lighting.light_rt.clone(),
+                lighting.normal_rt.clone(),
+                lighting.normal_material.clone(),
+            )
+        };
+
+        if settings.normal_maps {
+            self.render_sprite_normals(camera, &normal_rt, &normal_material, size);
+        }
+
+        light_rt.bind_frame_buffer(&self.gl, &self.capabilities);
+        self.gl.set_viewport(0, 0, size.0, size.1);
+        self.clear(ClearOption {
+            color: Some((settings.ambient.x, settings.ambient.y, settings.ambient.z, 1.0)),
+            clear_color: true,
+            clear_depth: false,
+            clear_stencil: false,
+        });
+        light_rt.unbind_frame_buffer(&self.gl);
+
+        let mut lights = Vec::new();
+        self.map_component::<Light2D, _>(|obj, c| {
+            let object = obj.borrow();
+            if object.active && camera.renders_layer(object.layer) {
+                let light = c.try_as::<Light2D>().unwrap().borrow().clone();
+                lights.push((light, compute_model_m(&object)));
+            }
+            true
+        });
+
+        {
+            let lighting = self.lighting_2d.as_ref().unwrap();
+
+            // One additive screen pass per light
+            for &(ref light, ref transform) in lights.iter() {
+                lighting.setup_light(light, transform);
+                self.render_fullscreen(&lighting.light_material, Some(&light_rt), ((0, 0), size));
+            }
+        }
+
+        self.lighting_2d.as_mut().unwrap().enabled = true;
+    }
+
+    /// The attachment of the HDR target, half floats when the context can't
+    /// render into float textures, None when it can't render into either
+    fn hdr_attachment(&self) -> Option<TextureAttachment> {
+        if self.capabilities.float_render_targets {
+            Some(TextureAttachment::Color0Hdr)
+        } else if self.capabilities.half_float_render_targets {
+            Some(TextureAttachment::Color0HalfFloat)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `camera` renders into the HDR target, the HDR cameras render
+    /// like the others without float render targets
+    fn renders_hdr(&self, camera: &Camera) -> bool {
+        camera.hdr.is_some() && self.hdr_attachment().is_some()
+    }
+
+    /// The floating point target HDR cameras render into before tonemapping
+    fn hdr_target(&mut self, size: (u32, u32)) -> Rc<RenderTexture> {
+        match self.hdr_target {
+            Some((s, ref rt)) if s == size => return rt.clone(),
+            _ => (),
+        }
+
+        let attachment = self.hdr_attachment().unwrap_or(TextureAttachment::Color0Hdr);
+        let rt = Rc::new(RenderTexture::new_with_depth(size.0, size.1, attachment));
+
+        self.hdr_target = Some((size, rt.clone()));
+        rt
+    }
+
+    /// Render `camera` into the buffers of `post_process`, then run its effects
+    /// into the render texture (or the screen) the camera was set to.
+    #[cfg_attr(feature = "flame_it", flame)]
+    pub fn render_post_process(
+        &mut self,
+        camera: &Arc<Component>,
+        post_process: &Arc<Component>,
+        clear_option: ClearOption,
+    ) -> EngineStats {
+        let camera = camera.try_as::<Camera>().unwrap();
+        let post_process = post_process.try_as::<PostProcess>().unwrap();
+
+        let (target, rect, hdr, near_far) = {
+            let cam = camera.borrow();
+            (
+                cam.render_texture.clone(),
+                cam.rect,
+                self.renders_hdr(&cam),
+                Vector2f::new(cam.znear, cam.zfar),
+            )
+        };
+
+        let size = rect.map(|r| r.1).unwrap_or(self.screen_size);
+
+        // Before render_pass replaces it with this frame
+        let (view_proj, prev_view_proj) = {
+            let cam = camera.borrow();
+            let view_proj = cam.perspective(self.screen_size) * cam.v;
+            (view_proj, cam.prev_view_proj().unwrap_or(view_proj))
+        };
+        let inv_view_proj = view_proj.invert().unwrap_or(Matrix4f::identity());
+
+        let (effects, buffers) = {
+            let mut post = post_process.borrow_mut();
+            let effects: Vec<_> = post.effects
+                .iter()
+                .filter(|e| e.enabled())
+                .map(|e| e.passes())
+                .filter(|passes| !passes.is_empty())
+                .collect();
+
+            (effects, post.buffers(size))
+        };
+
+        if effects.is_empty() {
+            return self.render_pass(&camera.borrow(), clear_option);
+        }
+
+        {
+            let mut cam = camera.borrow_mut();
+            cam.render_texture = Some(buffers[0].clone());
+            cam.rect = Some(((0, 0), size));
+        }
+
+        let (stats, _) = self.render_scene_pass(&camera.borrow(), clear_option);
+
+        {
+            let mut cam = camera.borrow_mut();
+            cam.render_texture = target.clone();
+            cam.rect = rect;
+        }
+
+        // HDR cameras are tonemapped into the first buffer, the depth stays in the HDR target
+        let depth = match self.hdr_target {
+            Some((_, ref rt)) if hdr => rt.depth_texture().unwrap(),
+            _ => buffers[0].depth_texture().unwrap(),
+        };
+        let mut src = 0;
+
+        for (i, passes) in effects.iter().enumerate() {
+            let mut outputs: Vec<Rc<RenderTexture>> = Vec::new();
+
+            for (j, pass) in passes.iter().enumerate() {
+                let material = &pass.material;
+                let last = j + 1 == passes.len();
+
+                let pass_size = if last {
+                    size
+                } else {
+                    let scale = pass.scale.max(1);
+                    ((size.0 / scale).max(1), (size.1 / scale).max(1))
+                };
+
+                material.set("uScreenTexture", buffers[src].as_texture());
+                material.set("uDepthTexture", depth.clone());
+                material.set("uCameraNearFar", near_far);
+                material.set("uInvViewProjection", inv_view_proj);
+                material.set("uPrevViewProjection", prev_view_proj);
+                material.set(
+                    "uScreenSize",
+                    Vector2f::new(pass_size.0 as f32, pass_size.1 as f32),
+                );
+
+                // Only the earlier passes have an output
+                for &(name, input) in pass.inputs.iter() {
+                    match input {
+                        PassInput::Pass(k) if k < outputs.len() => {
+                            material.set(name, outputs[k].as_texture())
+                        }
+                        PassInput::Pass(k) => uni_app::App::print(format!(
+                            "Post pass {} reads {} from pass {}, which is not an earlier pass\n",
+                            j, name, k
+                        )),
+                    }
+                }
+
+                if !last {
+                    let rt = post_process.borrow_mut().pass_target(j, pass_size);
+                    self.render_fullscreen(material, Some(&rt), ((0, 0), pass_size));
+                    outputs.push(rt);
+                } else if i + 1 == effects.len() {
+                    let rect = rect.unwrap_or(((0, 0), self.screen_size));
+                    self.render_fullscreen(material, target.as_ref(), rect);
+                } else {
+                    // Ping-pong between the two color buffers
+                    let dst = if src == 1 { 2 } else { 1 };
+                    self.render_fullscreen(material, Some(&buffers[dst]), ((0, 0), size));
+                    src = dst;
+                }
+            }
+        }
+
+        let rect = rect.unwrap_or(((0, 0), self.screen_size));
+        self.render_overlay(&camera.borrow(), target.as_ref(), rect, Some(depth));
+
+        stats
+    }
+
+    /// Draw `material` over the "screen_quad" mesh, without depth test
+    fn render_fullscreen(
+        &self,
+        material: &Rc<Material>,
+        target: Option<&Rc<RenderTexture>>,
+        rect: ((i32, i32), (u32, u32)),
+    ) {
+        let mut ctx = EngineContext::new(self.texture_units);
+        let gl = &self.gl;
+
+        if let Some(rt) = target {
+            rt.bind_frame_buffer(gl, &self.capabilities);
+            ctx.states.color_attachments = rt.color_count();
+        }
+
+        let ((x, y), (w, h)) = rect;
+        gl.set_viewport(x, y, w, h);
+
+        ctx.states.apply_defaults();
+        ctx.states.apply(&MaterialState {
+            cull: Some(CullMode::Off),
+            depth_test: Some(DepthTest::Never),
+            depth_write: Some(false),
+            alpha_blending: Some(false),
+            blend: None,
+            draw_buffers: None,
+        });
+        ctx.states.apply(&material.states);
+        ctx.states.commit(gl);
+
+        let quad = self.asset_system.new_mesh_buffer("screen_quad");
+
+        match self.setup_material(&mut ctx, material, false) {
+            Ok(_) => {
+                let prog = ctx.prog.upgrade().unwrap();
+
+                if quad.bind(gl, &prog).is_ok() {
+                    prog.set("uMMatrix", Matrix4f::identity());
+                    prog.commit(gl);
+                    quad.render(gl);
+                    quad.unbind(gl);
+                }
+            }
+            Err(AssetError::NotReady) => (),
+            Err(err) => panic!(format!("Failed to load material, reason {:?}", err)),
+        }
+
+        if let Some(rt) = target {
+            rt.unbind_frame_buffer(gl);
+        }
+    }
+
+    fn find_post_process(&self, camera: &Arc<Component>) -> Option<Arc<Component>> {
+        let mut result = None;
+
+        self.map_component::<Camera, _>(|obj, c| {
+            if !Arc::ptr_eq(&c, camera) {
+                return true;
+            }
+
+            result = obj.borrow()
+                .find_component::<PostProcess>()
+                .map(|(_, c)| c.clone());
+            false
+        });
+
+        result
+    }
+
+    pub fn main_camera(&self) -> Option<Arc<Component>> {
+        let mut found = self.current_camera.borrow_mut();
+        // The cached camera may have been disabled since
+        if found.as_ref().map_or(true, |c| !c.enabled()) {
+            *found = self.find_component::<Camera>();
+        }
+
+        if let Some(ref c) = *found {
+            return Some(c.clone());
+        }
 
         None
     }
 
+    /// All the cameras, by increasing `Camera::depth`
+    pub fn cameras(&self) -> Vec<Arc<Component>> {
+        let mut cameras = self.find_all_components::<Camera>();
+        cameras.sort_by_key(|c| c.try_as::<Camera>().unwrap().borrow().depth);
+        cameras
+    }
+
+    /// Render every camera, `clear_option` is the clear of the `Skybox` clear flags.
+    /// The stats are the ones of the main camera.
     #[cfg_attr(feature = "flame_it", flame)]
     pub fn render(&mut self, clear_option: ClearOption) {
+        if self.context_lost {
+            return;
+        }
+
+        self.debug_draw_labels();
         imgui::pre_render(self);
 
-        if let Some(ref camera) = self.main_camera() {
-            self.stats =
-                self.render_pass(&camera.try_as::<Camera>().unwrap().borrow(), clear_option);
-        } else {
-            // We dont have a main camera here, just clean the screen.
+        let cameras = self.cameras();
+        if cameras.is_empty() {
+            // We dont have a camera here, just clean the screen.
             self.clear(clear_option);
+            return;
+        }
+
+        let main_camera = self.main_camera();
+
+        // The shadows of the main camera are shared by the other cameras and passes
+        if let Some(ref c) = main_camera {
+            let eye = c.try_as::<Camera>().unwrap().borrow().eye();
+            self.render_shadow_passes(&eye);
+        }
+
+        self.bake_reflection_probes();
+
+        for camera in cameras.iter() {
+            let clear = {
+                let flags = camera.try_as::<Camera>().unwrap().borrow().clear_flags;
+                camera_clear_option(flags, clear_option)
+            };
+
+            let stats = self.render_camera_component(camera, clear);
+
+            if main_camera.as_ref().map_or(false, |c| Arc::ptr_eq(c, camera)) {
+                self.stats = stats;
+            }
         }
     }
 
-    pub fn new(webgl_ctx: WebGLContext, size: (u32, u32), hidpi: f32) -> Engine<A> {
-        let gl = WebGLRenderingContext::new(webgl_ctx);
+    /// Render one camera with its post process
+    fn render_camera_component(
+        &mut self,
+        camera: &Arc<Component>,
+        clear_option: ClearOption,
+    ) -> EngineStats {
+        if camera.try_as::<Camera>().unwrap().borrow().pixel_perfect.is_some() {
+            return self.render_pixel_perfect(camera, clear_option);
+        }
+
+        match self.find_post_process(camera) {
+            Some(ref post_process) => self.render_post_process(camera, post_process, clear_option),
+            None => self.render_pass(&camera.try_as::<Camera>().unwrap().borrow(), clear_option),
+        }
+    }
+
+    /// Render a camera with `pixel_perfect` into a texture of its reference
+    /// resolution, then scale it by an integer without filtering into the
+    /// camera target, see `PixelPerfect`
+    fn render_pixel_perfect(
+        &mut self,
+        camera: &Arc<Component>,
+        clear_option: ClearOption,
+    ) -> EngineStats {
+        let cam = camera.try_as::<Camera>().unwrap();
+
+        let (pixel_perfect, target, rect) = {
+            let cam = cam.borrow();
+            (
+                cam.pixel_perfect.unwrap(),
+                cam.render_texture.clone(),
+                cam.rect,
+            )
+        };
+
+        let size = pixel_perfect.reference_resolution;
+        let rt = self.pixel_perfect_target(size);
+
+        // Redirect the camera while its passes run
+        {
+            let mut cam = cam.borrow_mut();
+            cam.render_texture = Some(rt.clone());
+            cam.rect = Some(((0, 0), size));
+        }
+
+        let stats = match self.find_post_process(camera) {
+            Some(ref post_process) => self.render_post_process(camera, post_process, clear_option),
+            None => self.render_pass(&cam.borrow(), clear_option),
+        };
+
+        {
+            let mut cam = cam.borrow_mut();
+            cam.render_texture = target.clone();
+            cam.rect = rect;
+        }
+
+        if self.blit_material.is_none() {
+            let prog = self.asset_system.new_program("unrust/blit");
+            self.blit_material = Some(Rc::new(Material::new(prog)));
+        }
+
+        let material = self.blit_material.clone().unwrap();
+        material.set("uScreenTexture", rt.as_texture());
+
+        let output = pixel_perfect.output_rect(rect.unwrap_or(((0, 0), self.screen_size)));
+        self.render_fullscreen(&material, target.as_ref(), output);
+
+        stats
+    }
+
+    /// The color and depth target of the pixel perfect cameras, nearest filtered
+    fn pixel_perfect_target(&mut self, size: (u32, u32)) -> Rc<RenderTexture> {
+        match self.pixel_perfect_target {
+            Some((s, ref rt)) if s == size => return rt.clone(),
+            _ => (),
+        }
+
+        let rt = Rc::new(RenderTexture::new_with_depth(
+            size.0,
+            size.1,
+            TextureAttachment::Color0,
+        ));
+        rt.as_texture().set_filtering(TextureFiltering::Nearest);
+
+        self.pixel_perfect_target = Some((size, rt.clone()));
+        rt
+    }
+
+    /// Render the `players` cameras tiled over the window, see `split_screen_rects`,
+    /// instead of `render`. The rects of the cameras are set here and `listener` is
+    /// called before each player renders, e.g. to move its audio listener.
+    /// The screen is cleared once with `clear_option`, the stats are the ones of
+    /// the first player. Nothing is rendered while the context is lost.
+    #[cfg_attr(feature = "flame_it", flame)]
+    pub fn render_split_screen<F>(
+        &mut self,
+        players: &[Arc<Component>],
+        clear_option: ClearOption,
+        mut listener: F,
+    ) where
+        F: FnMut(usize, &Camera),
+    {
+        if self.context_lost {
+            return;
+        }
+
+        self.debug_draw_labels();
+        imgui::pre_render(self);
+
+        self.gl.set_viewport(0, 0, self.screen_size.0, self.screen_size.1);
+        self.clear(clear_option);
+
+        if players.is_empty() {
+            return;
+        }
+
+        self.bake_reflection_probes();
+
+        let rects = split_screen_rects(players.len(), self.screen_size);
 
-        /*=========Drawing the triangle===========*/
+        for (i, (camera, rect)) in players.iter().zip(rects.into_iter()).enumerate() {
+            let clear = {
+                let mut cam = camera.try_as::<Camera>().unwrap().borrow_mut();
+                cam.rect = Some(rect);
+                listener(i, &cam);
 
-        // Clear the canvas
-        gl.clear_color(0.5, 0.5, 0.5, 1.0);
+                // glClear ignores the viewport, keep the other players
+                let clear = camera_clear_option(cam.clear_flags, clear_option);
+                ClearOption {
+                    clear_color: false,
+                    ..clear
+                }
+            };
 
-        // Enable alpha blending
-        gl.enable(Flag::Blend as i32);
+            let stats = self.render_camera_component(camera, clear);
 
-        // Clear the color buffer bit
-        gl.clear(BufferBit::Color);
-        gl.clear(BufferBit::Depth);
-        gl.blend_func(BlendMode::SrcAlpha, BlendMode::OneMinusSrcAlpha);
+            if i == 0 {
+                self.stats = stats;
+            }
+        }
+    }
 
-        // Explict set the clear depth value
-        gl.clear_depth(1.0);
+    pub fn new(webgl_ctx: WebGLContext, size: (u32, u32), hidpi: f32) -> Engine<A> {
+        let gl = WebGLRenderingContext::new(webgl_ctx);
+        let texture_units = max_texture_units(&gl);
+        let capabilities = Capabilities::detect(&gl);
 
-        // Set the view port
-        gl.viewport(0, 0, size.0, size.1);
+        init_gl_state(&gl, size);
 
         let gui_tree = SceneTree::new();
 
+        let asset_system = A::new();
+        asset_system.set_capabilities(capabilities);
+
         Engine {
             gl: gl,
+            texture_units,
+            capabilities,
             objects: vec![],
             program_cache: RefCell::new(HashMap::new()),
-            asset_system: Box::new(A::new()),
+            asset_system: Box::new(asset_system),
             gui_context: Rc::new(RefCell::new(imgui::Context::new(gui_tree))),
             screen_size: size,
             hidpi: hidpi,
             current_camera: RefCell::new(None),
             stats: Default::default(),
             arena: Rc::new(ComponentArena::new()),
+            max_point_lights: DEFAULT_LIGHT_SLOTS,
+            max_spot_lights: DEFAULT_LIGHT_SLOTS,
+            max_area_lights: DEFAULT_LIGHT_SLOTS,
+            area_light_lut: None,
+            clustered_lighting: false,
+            fog: None,
+            color_space: ColorSpace::Gamma,
+            dynamic_batching: false,
+            dynamic_batch_max_vertices: 300,
+            spatial_index: false,
+            context_lost: false,
+            events: Vec::new(),
+            light_clusters: None,
+            hdr_target: None,
+            reflecting_object: None,
+            tonemap_material: None,
+            depth_prepass_material: None,
+            probe_materials: None,
+            environment_material: None,
+            particle_update_material: None,
+            ssao: None,
+            camera_depth: None,
+            lighting_2d: None,
+            shadow: None,
+            point_shadow: None,
+            camera_block: UniformBlock::camera(),
+            lights_block: RefCell::new(None),
+            shadows_rendered: false,
+            external_directional_shadow: false,
+            static_batches: Vec::new(),
+            dynamic_batcher: Default::default(),
+            sprite_batcher: Default::default(),
+            object_index: RefCell::new(ObjectIndex {
+                rebuild: true,
+                ..Default::default()
+            }),
+            debug_draw: Default::default(),
+            debug_line_material: None,
+            gizmo_draw: Default::default(),
+            overlay_queue: None,
+            depth_copy_material: None,
+            pixel_perfect_target: None,
+            blit_material: None,
+            gizmos: Default::default(),
+            sorting_layers: Default::default(),
+            physics_2d: Default::default(),
+            behaviours: Default::default(),
+            render_queue_pool: Default::default(),
         }
     }
 
@@ -735,12 +4181,43 @@ where
         imgui::begin();
 
         self.asset_system_mut().step();
+
+        let asys = self.asset_system();
+        if !asys.finished_files().is_empty() && asys.loading_progress().is_complete() {
+            self.events.push(EngineEvent::AssetsLoaded);
+        }
     }
 
     pub fn end(&mut self) {
+        self.events.clear();
+
+        end_gpu_memory_frame();
+        end_shader_frame();
+
+        self.shadows_rendered = false;
+        self.external_directional_shadow = false;
+
         // drop all gameobjects if there are no other references
+        let count = self.objects.len();
         self.objects.retain(|obj| obj.upgrade().is_some());
 
+        {
+            let mut index = self.object_index.borrow_mut();
+            index.loading_checked = false;
+
+            if self.objects.len() != count {
+                index.prune();
+            }
+
+            // Keep the reports of the trees from piling up
+            if !self.spatial_index {
+                for tree in index.trees.iter().filter_map(|t| t.upgrade()) {
+                    tree.take_changed();
+                }
+                index.rebuild = true;
+            }
+        }
+
         // drop camera cache if it is only by holded by ourself
         let mut cam_mut = self.current_camera.borrow_mut();
         if let Some(ref c) = *cam_mut {
@@ -776,7 +4253,9 @@ where
 
 impl<A: AssetSystem> IEngine for Engine<A> {
     fn new_game_object(&mut self, parent: &GameObject) -> Rc<RefCell<GameObject>> {
-        let go = parent.tree().new_node(parent, &self.arena);
+        let tree = parent.tree();
+        let go = tree.new_node(parent, &self.arena);
+        self.object_index.borrow_mut().add_tree(tree);
 
         self.objects.push(Rc::downgrade(&go));
         go
@@ -1,4 +1,5 @@
 use engine::{Asset, Component, GameObject, Material, Mesh, MeshBuffer, RenderQueue, SceneTree};
+use engine::render::TtfFont;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -15,11 +16,14 @@ struct LabelRenderer {
     go: Option<Rc<RefCell<GameObject>>>,
     mesh: Option<Arc<Component>>,
     material: Option<Rc<Material>>,
+    /// The materials sampling the atlas of each TTF font
+    ttf_materials: Vec<(Rc<TtfFont>, Rc<Material>)>,
 }
 
 struct LabelHandle {
     mesh: Arc<Component>,
     mesh_buffer: Option<Rc<MeshBuffer>>,
+    material: Rc<Material>,
 }
 
 impl Drop for LabelHandle {
@@ -37,9 +41,29 @@ impl LabelRenderer {
             go: None,
             material: None,
             mesh: None,
+            ttf_materials: Vec::new(),
         }
     }
 
+    fn ttf_material(&mut self, font: &Rc<TtfFont>, engine: &mut IEngine) -> Rc<Material> {
+        if let Some(&(_, ref material)) = self.ttf_materials
+            .iter()
+            .find(|&&(ref f, _)| Rc::ptr_eq(f, font))
+        {
+            return material.clone();
+        }
+
+        let db = engine.asset_system();
+        let mut material = Material::new(db.new_program("default_ui"));
+        // White glyphs with the coverage in alpha
+        material.set("uDiffuse", font.texture());
+        material.render_queue = RenderQueue::UI;
+
+        let material = Rc::new(material);
+        self.ttf_materials.push((font.clone(), material.clone()));
+        material
+    }
+
     fn bind(
         &mut self,
         ssize: (u32, u32),
@@ -48,13 +72,18 @@ impl LabelRenderer {
         parent: &GameObject,
         engine: &mut IEngine,
     ) -> LabelHandle {
-        let material = self.material.get_or_insert_with(|| {
-            let db = engine.asset_system();
-            let mut material = Material::new(db.new_program("default_ui"));
-            material.set("uDiffuse", db.new_texture("default_font_bitmap"));
-            material.render_queue = RenderQueue::UI;
-            Rc::new(material)
-        });
+        let material = match label.font() {
+            Some(font) => self.ttf_material(&font.font, engine),
+            None => self.material
+                .get_or_insert_with(|| {
+                    let db = engine.asset_system();
+                    let mut material = Material::new(db.new_program("default_ui"));
+                    material.set("uDiffuse", db.new_texture("default_font_bitmap"));
+                    material.render_queue = RenderQueue::UI;
+                    Rc::new(material)
+                })
+                .clone(),
+        };
 
         let go = self.go
             .get_or_insert_with(|| engine.new_game_object(parent));
@@ -80,7 +109,13 @@ impl LabelRenderer {
             mesh_data
         };
 
-        match old_handle {
+        // Upload the glyphs rasterized by the layout
+        if let Some(font) = label.font() {
+            font.font.texture();
+        }
+
+        // The surface of another font is removed with the old handle
+        match old_handle.filter(|h| Rc::ptr_eq(&h.material, &material)) {
             Some(h) => {
                 if let Some(ref mb) = h.mesh_buffer {
                     let mesh_buffer = mb.clone();
@@ -90,12 +125,14 @@ impl LabelRenderer {
                     return LabelHandle {
                         mesh: h.mesh.clone(),
                         mesh_buffer: Some(mesh_buffer),
+                        material,
                     };
                 }
 
                 LabelHandle {
                     mesh: h.mesh.clone(),
                     mesh_buffer: h.mesh_buffer.clone(),
+                    material,
                 }
             }
             None => {
@@ -111,6 +148,7 @@ impl LabelRenderer {
                 LabelHandle {
                     mesh: mesh.clone(),
                     mesh_buffer: Some(mesh_buffer),
+                    material,
                 }
             }
         }
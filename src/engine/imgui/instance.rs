@@ -1,3 +1,4 @@
+use super::label::LabelFont;
 use super::widgets;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
@@ -12,6 +13,8 @@ pub struct ImguiState {
 pub struct ImguiRaw {
     pub id: u32,
     pub state: ImguiState,
+    /// The font of the next labels, see `imgui::font`
+    pub font: Option<LabelFont>,
     pub render_list: Vec<Rc<widgets::Widget>>,
 }
 
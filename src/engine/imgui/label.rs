@@ -4,6 +4,30 @@ use super::widgets::Widget;
 use super::{Metric, TextAlign};
 
 use engine::MeshData;
+use engine::render::TtfFont;
+use math::*;
+use std::fmt;
+use std::rc::Rc;
+
+/// The TTF font of the labels, see `imgui::font`
+#[derive(Clone)]
+pub struct LabelFont {
+    pub font: Rc<TtfFont>,
+    /// Height of an em in points, rasterized at the hidpi pixels
+    pub size: f32,
+}
+
+impl PartialEq for LabelFont {
+    fn eq(&self, other: &LabelFont) -> bool {
+        Rc::ptr_eq(&self.font, &other.font) && self.size == other.size
+    }
+}
+
+impl fmt::Debug for LabelFont {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LabelFont {{ size: {} }}", self.size)
+    }
+}
 
 struct BitmapFontData {
     hidpi: f32,
@@ -125,25 +149,132 @@ fn make_text_mesh_data(text_data: TextData) -> MeshData {
     }
 }
 
+/// The glyph quads of `s` in normalized device coordinates, from the top left
+/// of its first line at the origin. Empty while the font is loading.
+fn make_ttf_text_mesh_data(
+    s: &str,
+    align: TextAlign,
+    font: &LabelFont,
+    ssize: (u32, u32),
+    hidpi: f32,
+) -> MeshData {
+    let mut data = MeshData::with_layout([true, false, false, false]);
+
+    let pixels = font.size * hidpi;
+    let metrics = match font.font.line_metrics(pixels) {
+        Some(metrics) => metrics,
+        None => return data,
+    };
+
+    // From the glyph pixels, y up
+    let scale = Vector2f::new(2.0 / ssize.0 as f32, 2.0 / ssize.1 as f32);
+
+    // The first vertex and the width of each line, aligned once all are known
+    let mut lines = Vec::new();
+
+    for (i, line) in s.split('\n').enumerate() {
+        let y = -metrics.ascent - i as f32 * metrics.line_height();
+        let start = data.vertices.len();
+        let mut pen = 0.0;
+        let mut prev = None;
+
+        for c in line.chars() {
+            if let Some(p) = prev {
+                pen += font.font.kerning(p, c, pixels);
+            }
+            prev = Some(c);
+
+            let glyph = match font.font.glyph(c, pixels) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+
+            if glyph.size.x > 0.0 {
+                let min = Vector2f::new(pen, y) + glyph.offset;
+                let (x0, x1) = (min.x * scale.x, (min.x + glyph.size.x) * scale.x);
+                let (y0, y1) = ((min.y - glyph.size.y) * scale.y, min.y * scale.y);
+                let (uv0, uv1) = glyph.uv;
+                let base = (data.vertices.len() / 3) as u16;
+
+                data.vertices
+                    .extend_from_slice(&[x0, y0, 0.0, x1, y0, 0.0, x1, y1, 0.0, x0, y1, 0.0]);
+                data.uvs.as_mut().unwrap().extend_from_slice(&[
+                    uv0.x, uv1.y, uv1.x, uv1.y, uv1.x, uv0.y, uv0.x, uv0.y,
+                ]);
+                data.indices
+                    .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+
+            pen += glyph.advance;
+        }
+
+        lines.push((start, pen * scale.x));
+    }
+
+    let max_width = lines.iter().fold(0.0f32, |acc, &(_, w)| acc.max(w));
+
+    for (i, &(start, width)) in lines.iter().enumerate() {
+        let end = lines.get(i + 1).map_or(data.vertices.len(), |&(next, _)| next);
+        let shift = match align {
+            TextAlign::Left => 0.0,
+            TextAlign::Right => max_width - width,
+            TextAlign::Center => (max_width - width) * 0.5,
+        };
+
+        let mut x = start;
+        while x < end {
+            data.vertices[x] += shift;
+            x += 3;
+        }
+    }
+
+    data
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Label {
     id: u32,
     pub pos: Metric,
     pub state: ImguiState,
     s: String,
+    font: Option<LabelFont>,
+    /// Whether the font is loaded and the generation of its atlas, the glyphs
+    /// change with them
+    font_state: (bool, u32),
 }
 
 impl Label {
-    pub fn new(id: u32, pos: Metric, state: ImguiState, s: String) -> Widget {
+    pub fn new(
+        id: u32,
+        pos: Metric,
+        state: ImguiState,
+        s: String,
+        font: Option<LabelFont>,
+    ) -> Widget {
+        let font_state = font.as_ref().map_or((false, 0), |f| {
+            (f.font.data().is_some(), f.font.generation())
+        });
+
         Widget::Label(Self {
             id: id,
             pos: pos,
             state,
             s: s,
+            font,
+            font_state,
         })
     }
 
+    /// The TTF font, the bitmap font for None
+    pub fn font(&self) -> Option<&LabelFont> {
+        self.font.as_ref()
+    }
+
     pub fn bind(&self, ssize: (u32, u32), hidpi: f32) -> MeshData {
+        if let Some(ref font) = self.font {
+            return make_ttf_text_mesh_data(&self.s, self.state.text_align, font, ssize, hidpi);
+        }
+
         // Mesh Data
         let meshdata = {
             make_text_mesh_data(TextData {
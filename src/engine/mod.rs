@@ -1,5 +1,6 @@
 mod asset;
 mod core;
+mod physics2d;
 mod render;
 
 pub mod context;
@@ -10,13 +11,15 @@ pub mod sound;
 pub use self::imgui::Metric;
 
 pub use self::asset::*;
-pub use self::core::Aabb;
-pub use self::core::{Component, ComponentArena, ComponentBased, ComponentEvent, ComponentType,
-                     GameObject, IntoComponentPtr, SceneTree};
+pub use self::core::{Aabb, Bvh, Ray};
+pub use self::core::{Behaviour, Behaviours, Component, ComponentArena, ComponentBased,
+                     ComponentEvent, ComponentType, GameObject, IntoComponentPtr, Layers,
+                     SceneFormat, SceneInstance, SceneTree, Tag};
+pub use self::physics2d::*;
 pub use self::render::*;
 
-pub use self::engine::{ClearOption, IEngine};
+pub use self::engine::{ClearOption, EngineEvent, IEngine, RaycastHit};
 
-pub use self::sound::{SoundHandle, SoundSystem};
+pub use self::sound::{AudioClip, AudioClipData, SoundHandle, SoundSystem};
 
 pub type Engine<FS, F> = engine::Engine<AssetDatabase<FS, F>>;
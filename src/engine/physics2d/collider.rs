@@ -0,0 +1,114 @@
+use engine::GameObject;
+use math::*;
+use std::cell::RefCell;
+use std::rc::Weak;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape2D {
+    Box { half_extents: Vector2f },
+    Circle { radius: f32 },
+    /// A convex polygon, counter clockwise
+    Polygon { points: Vec<Vector2f> },
+}
+
+impl Shape2D {
+    /// The moment of inertia of `mass` spread over the shape moved by
+    /// `offset`, around the origin
+    pub fn inertia(&self, mass: f32, offset: Vector2f) -> f32 {
+        let shifted = mass * offset.magnitude2();
+
+        match *self {
+            Shape2D::Box { half_extents: h } => mass * (h.x * h.x + h.y * h.y) / 3.0 + shifted,
+            Shape2D::Circle { radius } => 0.5 * mass * radius * radius + shifted,
+            Shape2D::Polygon { ref points } => {
+                let mut area = 0.0;
+                let mut inertia = 0.0;
+
+                for i in 0..points.len() {
+                    let a = points[i] + offset;
+                    let b = points[(i + 1) % points.len()] + offset;
+                    let cross = a.perp_dot(b);
+
+                    area += cross;
+                    inertia += cross * (a.dot(a) + a.dot(b) + b.dot(b));
+                }
+
+                if area.abs() > 0.0 {
+                    mass * inertia / (6.0 * area)
+                } else {
+                    shifted
+                }
+            }
+        }
+    }
+}
+
+/// See `Collider2D::events`, the other game object is gone when it was
+/// removed during the step
+#[derive(Clone)]
+pub enum Collision2DEvent {
+    CollisionEnter(Weak<RefCell<GameObject>>),
+    CollisionExit(Weak<RefCell<GameObject>>),
+    TriggerEnter(Weak<RefCell<GameObject>>),
+    TriggerExit(Weak<RefCell<GameObject>>),
+}
+
+/// The shape of its game object in the 2D physics, static without a
+/// `RigidBody2D`. The shape is scaled by the uniform scale of the transform.
+#[derive(Component, Clone)]
+pub struct Collider2D {
+    pub shape: Shape2D,
+    /// From the game object origin
+    pub offset: Vector2f,
+    /// Reports the overlaps as trigger events without any collision response
+    pub is_trigger: bool,
+    pub friction: f32,
+    /// Bounciness from 0 to 1
+    pub restitution: f32,
+
+    events: Vec<Collision2DEvent>,
+}
+
+impl Collider2D {
+    pub fn new(shape: Shape2D) -> Collider2D {
+        Collider2D {
+            shape,
+            offset: Vector2f::zero(),
+            is_trigger: false,
+            friction: 0.4,
+            restitution: 0.0,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn new_box(half_extents: Vector2f) -> Collider2D {
+        Collider2D::new(Shape2D::Box { half_extents })
+    }
+
+    pub fn new_circle(radius: f32) -> Collider2D {
+        Collider2D::new(Shape2D::Circle { radius })
+    }
+
+    /// Panics with less than 3 points or a repeated point, the polygons of
+    /// no area don't collide
+    pub fn new_polygon(points: Vec<Vector2f>) -> Collider2D {
+        assert!(points.len() >= 3, "a polygon needs at least 3 points");
+        for i in 0..points.len() {
+            assert!(
+                points[i] != points[(i + 1) % points.len()],
+                "a polygon has no repeated points"
+            );
+        }
+
+        Collider2D::new(Shape2D::Polygon { points })
+    }
+
+    /// The events of the last update
+    pub fn events(&self) -> &[Collision2DEvent] {
+        &self.events
+    }
+
+    pub(super) fn events_mut(&mut self) -> &mut Vec<Collision2DEvent> {
+        &mut self.events
+    }
+}
@@ -0,0 +1,418 @@
+use engine::physics2d::Shape2D;
+use math::*;
+
+/// A shape placed in the world
+pub enum WorldShape {
+    Circle {
+        center: Vector2f,
+        radius: f32,
+    },
+    Polygon {
+        vertices: Vec<Vector2f>,
+        normals: Vec<Vector2f>,
+    },
+}
+
+pub struct Contact {
+    pub point: Vector2f,
+    pub penetration: f32,
+}
+
+/// The contacts of two shapes, `normal` points from the first to the second
+pub struct Manifold {
+    pub normal: Vector2f,
+    pub contacts: Vec<Contact>,
+}
+
+fn rotate(v: Vector2f, angle: f32) -> Vector2f {
+    let (s, c) = angle.sin_cos();
+    Vector2f::new(v.x * c - v.y * s, v.x * s + v.y * c)
+}
+
+/// Below it an edge has no direction, for the degenerate polygons
+const MIN_EDGE_LENGTH2: f32 = 1e-12;
+
+impl WorldShape {
+    /// None for the polygons with less than 3 points or an edge of no length,
+    /// e.g. a repeated point or a zero scale, they don't collide
+    pub fn new(
+        shape: &Shape2D,
+        offset: Vector2f,
+        scale: f32,
+        position: Vector2f,
+        angle: f32,
+    ) -> Option<WorldShape> {
+        let place = |p: Vector2f| position + rotate((p + offset) * scale, angle);
+
+        let points = match *shape {
+            Shape2D::Circle { radius } => {
+                return Some(WorldShape::Circle {
+                    center: place(Vector2f::zero()),
+                    radius: radius * scale,
+                })
+            }
+            Shape2D::Box { half_extents: h } => vec![
+                Vector2f::new(-h.x, -h.y),
+                Vector2f::new(h.x, -h.y),
+                Vector2f::new(h.x, h.y),
+                Vector2f::new(-h.x, h.y),
+            ],
+            Shape2D::Polygon { ref points } => points.clone(),
+        };
+
+        let vertices: Vec<Vector2f> = points.into_iter().map(place).collect();
+        if vertices.len() < 3 {
+            return None;
+        }
+
+        let mut normals = Vec::with_capacity(vertices.len());
+        for i in 0..vertices.len() {
+            let edge = vertices[(i + 1) % vertices.len()] - vertices[i];
+            let length2 = edge.magnitude2();
+            if length2.is_nan() || length2 <= MIN_EDGE_LENGTH2 {
+                return None;
+            }
+            normals.push(Vector2f::new(edge.y, -edge.x).normalize());
+        }
+
+        Some(WorldShape::Polygon { vertices, normals })
+    }
+
+    pub fn aabb(&self) -> (Vector2f, Vector2f) {
+        match *self {
+            WorldShape::Circle { center, radius } => (
+                center - Vector2f::new(radius, radius),
+                center + Vector2f::new(radius, radius),
+            ),
+            WorldShape::Polygon { ref vertices, .. } => {
+                let mut min = Vector2f::new(::std::f32::MAX, ::std::f32::MAX);
+                let mut max = -min;
+                for v in vertices.iter() {
+                    min = Vector2f::new(min.x.min(v.x), min.y.min(v.y));
+                    max = Vector2f::new(max.x.max(v.x), max.y.max(v.y));
+                }
+                (min, max)
+            }
+        }
+    }
+}
+
+fn circle_circle(ca: Vector2f, ra: f32, cb: Vector2f, rb: f32) -> Option<Manifold> {
+    let d = cb - ca;
+    let dist = d.magnitude();
+    if dist >= ra + rb {
+        return None;
+    }
+
+    let normal = if dist > 0.0 {
+        d / dist
+    } else {
+        Vector2f::unit_x()
+    };
+
+    Some(Manifold {
+        normal,
+        contacts: vec![Contact {
+            point: ca + normal * ra,
+            penetration: ra + rb - dist,
+        }],
+    })
+}
+
+fn polygon_circle(
+    vertices: &[Vector2f],
+    normals: &[Vector2f],
+    center: Vector2f,
+    radius: f32,
+) -> Option<Manifold> {
+    // The face of the least penetration
+    let mut face = 0;
+    let mut separation = ::std::f32::MIN;
+    for i in 0..vertices.len() {
+        let s = normals[i].dot(center - vertices[i]);
+        if s > radius {
+            return None;
+        }
+        if s > separation {
+            separation = s;
+            face = i;
+        }
+    }
+
+    let v1 = vertices[face];
+    let v2 = vertices[(face + 1) % vertices.len()];
+
+    let vertex_contact = |v: Vector2f| {
+        let d = center - v;
+        let dist = d.magnitude();
+        if dist > radius || dist <= 0.0 {
+            return None;
+        }
+
+        Some(Manifold {
+            normal: d / dist,
+            contacts: vec![Contact {
+                point: v,
+                penetration: radius - dist,
+            }],
+        })
+    };
+
+    // Past the ends of the face the closest feature is a vertex
+    if separation > 0.0 {
+        if (center - v1).dot(v2 - v1) <= 0.0 {
+            return vertex_contact(v1);
+        }
+        if (center - v2).dot(v1 - v2) <= 0.0 {
+            return vertex_contact(v2);
+        }
+    }
+
+    let normal = normals[face];
+    Some(Manifold {
+        normal,
+        contacts: vec![Contact {
+            point: center - normal * radius,
+            penetration: radius - separation,
+        }],
+    })
+}
+
+/// The face of `a` separating the most from `b`, with the separation
+fn least_penetration(a: (&[Vector2f], &[Vector2f]), b: &[Vector2f]) -> (usize, f32) {
+    let mut best = (0, ::std::f32::MIN);
+
+    for i in 0..a.0.len() {
+        let n = a.1[i];
+        let s = b.iter()
+            .map(|v| n.dot(*v - a.0[i]))
+            .fold(::std::f32::MAX, f32::min);
+
+        if s > best.1 {
+            best = (i, s);
+        }
+    }
+
+    best
+}
+
+fn polygon_polygon(
+    a: (&[Vector2f], &[Vector2f]),
+    b: (&[Vector2f], &[Vector2f]),
+) -> Option<Manifold> {
+    let (face_a, sep_a) = least_penetration(a, b.0);
+    if sep_a > 0.0 {
+        return None;
+    }
+
+    let (face_b, sep_b) = least_penetration(b, a.0);
+    if sep_b > 0.0 {
+        return None;
+    }
+
+    // The reference face is the one separating the most, biased toward `a`
+    // to keep the same one between the steps
+    let (reference, incident, face, flip) = if sep_a >= sep_b * 0.95 + 0.01 * sep_a {
+        (a, b, face_a, false)
+    } else {
+        (b, a, face_b, true)
+    };
+
+    let ref_normal = reference.1[face];
+    let r1 = reference.0[face];
+    let r2 = reference.0[(face + 1) % reference.0.len()];
+
+    // The incident face is the most opposed to the reference normal
+    let inc = (0..incident.1.len())
+        .min_by(|&i, &j| {
+            let di = incident.1[i].dot(ref_normal);
+            let dj = incident.1[j].dot(ref_normal);
+            di.partial_cmp(&dj).unwrap_or(::std::cmp::Ordering::Equal)
+        })
+        .unwrap_or(0);
+
+    let mut points = vec![incident.0[inc], incident.0[(inc + 1) % incident.0.len()]];
+
+    // Clip the incident face to the sides of the reference face
+    let tangent = (r2 - r1).normalize();
+    points = clip(&points, -tangent, -tangent.dot(r1))?;
+    points = clip(&points, tangent, tangent.dot(r2))?;
+
+    let contacts: Vec<Contact> = points
+        .into_iter()
+        .filter_map(|p| {
+            let depth = ref_normal.dot(p - r1);
+            if depth <= 0.0 {
+                Some(Contact {
+                    point: p,
+                    penetration: -depth,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    if contacts.is_empty() {
+        return None;
+    }
+
+    Some(Manifold {
+        normal: if flip { -ref_normal } else { ref_normal },
+        contacts,
+    })
+}
+
+/// Keep the part of the segment `points` where dot(n, p) <= offset
+fn clip(points: &[Vector2f], n: Vector2f, offset: f32) -> Option<Vec<Vector2f>> {
+    let d0 = n.dot(points[0]) - offset;
+    let d1 = n.dot(points[1]) - offset;
+
+    let mut out = Vec::with_capacity(2);
+    if d0 <= 0.0 {
+        out.push(points[0]);
+    }
+    if d1 <= 0.0 {
+        out.push(points[1]);
+    }
+    if d0 * d1 < 0.0 {
+        let t = d0 / (d0 - d1);
+        out.push(points[0] + (points[1] - points[0]) * t);
+    }
+
+    if out.len() < 2 {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// The contacts of `a` and `b`, None when they do not overlap
+pub fn collide(a: &WorldShape, b: &WorldShape) -> Option<Manifold> {
+    match (a, b) {
+        (
+            &WorldShape::Circle {
+                center: ca,
+                radius: ra,
+            },
+            &WorldShape::Circle {
+                center: cb,
+                radius: rb,
+            },
+        ) => circle_circle(ca, ra, cb, rb),
+        (
+            &WorldShape::Polygon {
+                ref vertices,
+                ref normals,
+            },
+            &WorldShape::Circle { center, radius },
+        ) => polygon_circle(vertices, normals, center, radius),
+        (
+            &WorldShape::Circle { center, radius },
+            &WorldShape::Polygon {
+                ref vertices,
+                ref normals,
+            },
+        ) => polygon_circle(vertices, normals, center, radius).map(|mut m| {
+            m.normal = -m.normal;
+            m
+        }),
+        (
+            &WorldShape::Polygon {
+                vertices: ref va,
+                normals: ref na,
+            },
+            &WorldShape::Polygon {
+                vertices: ref vb,
+                normals: ref nb,
+            },
+        ) => polygon_polygon((&va[..], &na[..]), (&vb[..], &nb[..])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(shape: &Shape2D, x: f32, y: f32) -> WorldShape {
+        WorldShape::new(shape, Vector2f::zero(), 1.0, Vector2f::new(x, y), 0.0).unwrap()
+    }
+
+    fn unit_box() -> Shape2D {
+        Shape2D::Box {
+            half_extents: Vector2f::new(1.0, 1.0),
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn box_box() {
+        let m = collide(&place(&unit_box(), 0.0, 0.0), &place(&unit_box(), 1.5, 0.25)).unwrap();
+
+        assert!(close(m.normal.x, 1.0) && close(m.normal.y, 0.0));
+        assert_eq!(m.contacts.len(), 2);
+        for c in m.contacts.iter() {
+            assert!(close(c.penetration, 0.5));
+        }
+
+        // The normal points from the first shape to the second
+        let m = collide(&place(&unit_box(), 0.0, 1.5), &place(&unit_box(), 0.0, 0.0)).unwrap();
+        assert!(close(m.normal.x, 0.0) && close(m.normal.y, -1.0));
+    }
+
+    #[test]
+    fn circle_polygon() {
+        let circle = Shape2D::Circle { radius: 0.5 };
+
+        // On a face
+        let m = collide(&place(&unit_box(), 0.0, 0.0), &place(&circle, 0.0, 1.25)).unwrap();
+        assert!(close(m.normal.x, 0.0) && close(m.normal.y, 1.0));
+        assert!(close(m.contacts[0].penetration, 0.25));
+
+        let m = collide(&place(&circle, 0.0, 1.25), &place(&unit_box(), 0.0, 0.0)).unwrap();
+        assert!(close(m.normal.y, -1.0));
+
+        // Past a corner
+        let m = collide(&place(&unit_box(), 0.0, 0.0), &place(&circle, 1.3, 1.3)).unwrap();
+        let diagonal = 0.5f32.sqrt();
+        assert!(close(m.normal.x, diagonal) && close(m.normal.y, diagonal));
+        assert!(close(m.contacts[0].point.x, 1.0) && close(m.contacts[0].point.y, 1.0));
+    }
+
+    #[test]
+    fn separated() {
+        let circle = Shape2D::Circle { radius: 0.5 };
+        let triangle = Shape2D::Polygon {
+            points: vec![
+                Vector2f::new(0.0, 0.0),
+                Vector2f::new(1.0, 0.0),
+                Vector2f::new(0.0, 1.0),
+            ],
+        };
+
+        let a = place(&unit_box(), 0.0, 0.0);
+        assert!(collide(&a, &place(&unit_box(), 2.5, 0.0)).is_none());
+        assert!(collide(&a, &place(&circle, 1.45, 1.45)).is_none());
+        assert!(collide(&a, &place(&triangle, 1.2, 1.2)).is_none());
+        assert!(collide(&place(&circle, 0.0, 0.0), &place(&circle, 1.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn degenerate_polygons() {
+        let shape = |points: Vec<Vector2f>| {
+            let polygon = Shape2D::Polygon { points };
+            WorldShape::new(&polygon, Vector2f::zero(), 1.0, Vector2f::zero(), 0.0)
+        };
+
+        assert!(shape(Vec::new()).is_none());
+        assert!(shape(vec![Vector2f::zero(), Vector2f::unit_x()]).is_none());
+        assert!(shape(vec![Vector2f::zero(), Vector2f::zero(), Vector2f::unit_y()]).is_none());
+
+        // A zero scale
+        let scaled = WorldShape::new(&unit_box(), Vector2f::zero(), 0.0, Vector2f::zero(), 0.0);
+        assert!(scaled.is_none());
+    }
+}
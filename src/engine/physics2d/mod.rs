@@ -0,0 +1,497 @@
+mod collider;
+mod collision;
+mod rigid_body;
+
+pub use self::collider::{Collider2D, Collision2DEvent, Shape2D};
+pub use self::rigid_body::{BodyType2D, RigidBody2D};
+
+use self::collision::{collide, Manifold, WorldShape};
+use engine::{Component, GameObject};
+use math::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+
+/// A simulated game object during an update
+struct Body {
+    object: Rc<RefCell<GameObject>>,
+    rigid_body: Option<Arc<Component>>,
+    collider: Option<Arc<Component>>,
+
+    layer: u32,
+    body_type: BodyType2D,
+    position: Vector2f,
+    angle: f32,
+    scale: f32,
+    velocity: Vector2f,
+    angular_velocity: f32,
+    force: Vector2f,
+    torque: f32,
+    inv_mass: f32,
+    inv_inertia: f32,
+    gravity_scale: f32,
+    linear_damping: f32,
+    angular_damping: f32,
+
+    shape: Option<(Shape2D, Vector2f)>,
+    is_trigger: bool,
+    friction: f32,
+    restitution: f32,
+}
+
+/// Identifies a collider between the steps
+fn collider_key(c: &Arc<Component>) -> usize {
+    &**c as *const Component as *const u8 as usize
+}
+
+fn cross(a: Vector2f, b: Vector2f) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// The velocity of a point at `r` of a body rotating at `w`
+fn cross_sv(w: f32, r: Vector2f) -> Vector2f {
+    Vector2f::new(-w * r.y, w * r.x)
+}
+
+impl Body {
+    fn read(object: Rc<RefCell<GameObject>>) -> Option<Body> {
+        let (rigid_body, collider, transform, layer) = {
+            let go = object.try_borrow().ok()?;
+            (
+                go.find_component::<RigidBody2D>().map(|(_, c)| c.clone()),
+                go.find_component::<Collider2D>().map(|(_, c)| c.clone()),
+                go.transform.global(),
+                go.layer,
+            )
+        };
+
+        if rigid_body.is_none() && collider.is_none() {
+            return None;
+        }
+
+        let (shape, is_trigger, friction, restitution) = match collider {
+            Some(ref c) => {
+                let c = c.try_as::<Collider2D>().unwrap().borrow();
+                (
+                    Some((c.shape.clone(), c.offset)),
+                    c.is_trigger,
+                    c.friction,
+                    c.restitution,
+                )
+            }
+            None => (None, false, 0.0, 0.0),
+        };
+
+        let scale = transform.scale;
+        let q = transform.rot;
+
+        let mut body = Body {
+            object,
+            rigid_body: rigid_body.clone(),
+            collider,
+            layer,
+            body_type: BodyType2D::Static,
+            position: Vector2f::new(transform.disp.x, transform.disp.y),
+            // The rotation around Z
+            angle: 2.0 * q.v.z.atan2(q.s),
+            scale,
+            velocity: Vector2f::zero(),
+            angular_velocity: 0.0,
+            force: Vector2f::zero(),
+            torque: 0.0,
+            inv_mass: 0.0,
+            inv_inertia: 0.0,
+            gravity_scale: 0.0,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            shape,
+            is_trigger,
+            friction,
+            restitution,
+        };
+
+        if let Some(ref rb) = rigid_body {
+            let rb = rb.try_as::<RigidBody2D>().unwrap().borrow();
+            let (force, torque) = rb.forces();
+
+            body.body_type = rb.body_type;
+            body.velocity = rb.velocity;
+            body.angular_velocity = rb.angular_velocity;
+            body.force = force;
+            body.torque = torque;
+            body.gravity_scale = rb.gravity_scale;
+            body.linear_damping = rb.linear_damping;
+            body.angular_damping = rb.angular_damping;
+
+            if rb.body_type == BodyType2D::Dynamic && rb.mass > 0.0 {
+                body.inv_mass = 1.0 / rb.mass;
+
+                let inertia = match body.shape {
+                    Some((ref shape, offset)) => shape.inertia(rb.mass, offset) * scale * scale,
+                    None => 0.0,
+                };
+
+                if !rb.fixed_rotation && inertia > 0.0 {
+                    body.inv_inertia = 1.0 / inertia;
+                }
+            }
+        }
+
+        Some(body)
+    }
+
+    fn world_shape(&self) -> Option<WorldShape> {
+        self.shape.as_ref().and_then(|&(ref shape, offset)| {
+            WorldShape::new(shape, offset, self.scale, self.position, self.angle)
+        })
+    }
+
+    fn apply_impulse(&mut self, impulse: Vector2f, r: Vector2f) {
+        self.velocity += impulse * self.inv_mass;
+        self.angular_velocity += cross(r, impulse) * self.inv_inertia;
+    }
+
+    /// Write the state back into the components and the transform
+    fn write(&self) {
+        if let Some(ref rb) = self.rigid_body {
+            let mut rb = rb.try_as::<RigidBody2D>().unwrap().borrow_mut();
+            rb.velocity = self.velocity;
+            rb.angular_velocity = self.angular_velocity;
+            rb.clear_forces();
+        }
+
+        if self.body_type == BodyType2D::Static {
+            return;
+        }
+
+        if let Ok(mut go) = self.object.try_borrow_mut() {
+            let mut transform = go.transform.global();
+            transform.disp.x = self.position.x;
+            transform.disp.y = self.position.y;
+            transform.rot = Quaternion::from_angle_z(Rad(self.angle));
+            go.transform.set_global(transform);
+        }
+    }
+}
+
+/// A contact of the last step, the pair of collider keys and whether one
+/// of them is a trigger
+type ContactPair = ((usize, usize), bool);
+
+/// A simple impulse based 2D physics for the `RigidBody2D` and `Collider2D`
+/// components, see `Engine::physics_2d`. The bodies move in the XY plane
+/// and rotate around their game object origin, the simulation runs at a
+/// fixed time step and the colliders report their collisions as events.
+pub struct Physics2D {
+    pub gravity: Vector2f,
+    /// Seconds of a simulation step
+    pub fixed_timestep: f32,
+    /// Steps of an update at most, the time beyond is dropped
+    pub max_steps: u32,
+    /// Iterations of the contact solver
+    pub iterations: u32,
+
+    accumulator: f32,
+    contacts: HashMap<(usize, usize), bool>,
+    /// The layers colliding with each layer, as bit masks
+    collision_matrix: [u32; 32],
+}
+
+impl Default for Physics2D {
+    fn default() -> Physics2D {
+        Physics2D {
+            gravity: Vector2f::new(0.0, -9.81),
+            fixed_timestep: 1.0 / 60.0,
+            max_steps: 5,
+            iterations: 8,
+            accumulator: 0.0,
+            contacts: HashMap::new(),
+            collision_matrix: [!0; 32],
+        }
+    }
+}
+
+impl Physics2D {
+    /// Whether the colliders of the game object layers `a` and `b` collide and
+    /// trigger, all the layers collide by default
+    pub fn set_layers_collide(&mut self, a: u32, b: u32, collide: bool) {
+        assert!(a < 32 && b < 32, "Layer index out of 0..32");
+
+        if collide {
+            self.collision_matrix[a as usize] |= 1 << b;
+            self.collision_matrix[b as usize] |= 1 << a;
+        } else {
+            self.collision_matrix[a as usize] &= !(1 << b);
+            self.collision_matrix[b as usize] &= !(1 << a);
+        }
+    }
+
+    pub fn layers_collide(&self, a: u32, b: u32) -> bool {
+        a < 32 && b < 32 && self.collision_matrix[a as usize] & (1 << b) != 0
+    }
+
+    /// Run the fixed steps covering `dt` seconds over the game objects with
+    /// a `RigidBody2D` or a `Collider2D`
+    pub fn update(&mut self, dt: f32, objects: Vec<Rc<RefCell<GameObject>>>) {
+        let mut bodies: Vec<Body> = objects.into_iter().filter_map(Body::read).collect();
+
+        for b in bodies.iter() {
+            if let Some(ref c) = b.collider {
+                c.try_as::<Collider2D>()
+                    .unwrap()
+                    .borrow_mut()
+                    .events_mut()
+                    .clear();
+            }
+        }
+
+        let step = self.fixed_timestep.max(0.0001);
+        self.accumulator = (self.accumulator + dt).min(step * self.max_steps as f32);
+
+        let mut stepped = false;
+        while self.accumulator >= step {
+            self.step(&mut bodies, step);
+            self.accumulator -= step;
+            stepped = true;
+        }
+
+        if stepped {
+            for b in bodies.iter() {
+                b.write();
+            }
+        }
+    }
+
+    fn step(&mut self, bodies: &mut [Body], dt: f32) {
+        for b in bodies.iter_mut() {
+            if b.body_type != BodyType2D::Dynamic {
+                continue;
+            }
+
+            b.velocity += (self.gravity * b.gravity_scale + b.force * b.inv_mass) * dt;
+            b.angular_velocity += b.torque * b.inv_inertia * dt;
+            b.velocity *= 1.0 / (1.0 + dt * b.linear_damping);
+            b.angular_velocity *= 1.0 / (1.0 + dt * b.angular_damping);
+        }
+
+        let (manifolds, pairs) = self.detect(bodies);
+
+        for _ in 0..self.iterations {
+            for &(i, j, ref m) in manifolds.iter() {
+                resolve(bodies, i, j, m, self.gravity.magnitude() * dt);
+            }
+        }
+
+        for &(i, j, ref m) in manifolds.iter() {
+            correct_positions(bodies, i, j, m);
+        }
+
+        for b in bodies.iter_mut() {
+            if b.body_type == BodyType2D::Static {
+                continue;
+            }
+
+            b.position += b.velocity * dt;
+            b.angle += b.angular_velocity * dt;
+        }
+
+        self.dispatch_events(bodies, pairs);
+    }
+
+    /// The manifolds of the colliding pairs and every touching pair
+    fn detect(&self, bodies: &[Body]) -> (Vec<(usize, usize, Manifold)>, Vec<ContactPair>) {
+        let shapes: Vec<_> = bodies
+            .iter()
+            .map(|b| b.world_shape().map(|s| (s.aabb(), s)))
+            .collect();
+
+        let mut manifolds = Vec::new();
+        let mut pairs = Vec::new();
+
+        for i in 0..bodies.len() {
+            for j in i + 1..bodies.len() {
+                let (a, b) = (&bodies[i], &bodies[j]);
+
+                let (&(ref box_a, ref sa), &(ref box_b, ref sb)) = match (&shapes[i], &shapes[j]) {
+                    (&Some(ref x), &Some(ref y)) => (x, y),
+                    _ => continue,
+                };
+
+                // Only the moving bodies collide
+                let moving = |b: &Body| b.body_type != BodyType2D::Static;
+                if !moving(a) && !moving(b) {
+                    continue;
+                }
+
+                if !self.layers_collide(a.layer, b.layer) {
+                    continue;
+                }
+
+                if box_a.1.x < box_b.0.x || box_b.1.x < box_a.0.x || box_a.1.y < box_b.0.y
+                    || box_b.1.y < box_a.0.y
+                {
+                    continue;
+                }
+
+                if let Some(m) = collide(sa, sb) {
+                    let trigger = a.is_trigger || b.is_trigger;
+                    let ka = collider_key(a.collider.as_ref().unwrap());
+                    let kb = collider_key(b.collider.as_ref().unwrap());
+                    pairs.push(((ka.min(kb), ka.max(kb)), trigger));
+
+                    if !trigger {
+                        manifolds.push((i, j, m));
+                    }
+                }
+            }
+        }
+
+        (manifolds, pairs)
+    }
+
+    /// Compare the contacts with those of the last step
+    fn dispatch_events(&mut self, bodies: &[Body], pairs: Vec<ContactPair>) {
+        let current: HashMap<(usize, usize), bool> = pairs.into_iter().collect();
+
+        let find = |key: usize| {
+            bodies.iter().find(|b| {
+                b.collider
+                    .as_ref()
+                    .map(|c| collider_key(c) == key)
+                    .unwrap_or(false)
+            })
+        };
+
+        let notify = |a: &Body,
+                      b: &Body,
+                      event: fn(Weak<RefCell<GameObject>>) -> Collision2DEvent| {
+            for &(target, other) in [(a, b), (b, a)].iter() {
+                let collider = target.collider.as_ref().unwrap();
+                collider
+                    .try_as::<Collider2D>()
+                    .unwrap()
+                    .borrow_mut()
+                    .events_mut()
+                    .push(event(Rc::downgrade(&other.object)));
+            }
+        };
+
+        for (&(ka, kb), &trigger) in current.iter() {
+            if self.contacts.contains_key(&(ka, kb)) {
+                continue;
+            }
+
+            if let (Some(a), Some(b)) = (find(ka), find(kb)) {
+                if trigger {
+                    notify(a, b, Collision2DEvent::TriggerEnter);
+                } else {
+                    notify(a, b, Collision2DEvent::CollisionEnter);
+                }
+            }
+        }
+
+        for (&(ka, kb), &trigger) in self.contacts.iter() {
+            if current.contains_key(&(ka, kb)) {
+                continue;
+            }
+
+            if let (Some(a), Some(b)) = (find(ka), find(kb)) {
+                if trigger {
+                    notify(a, b, Collision2DEvent::TriggerExit);
+                } else {
+                    notify(a, b, Collision2DEvent::CollisionExit);
+                }
+            }
+        }
+
+        self.contacts = current;
+    }
+}
+
+/// Two mutable bodies of `bodies`, `i` < `j`
+fn pair_mut(bodies: &mut [Body], i: usize, j: usize) -> (&mut Body, &mut Body) {
+    let (left, right) = bodies.split_at_mut(j);
+    (&mut left[i], &mut right[0])
+}
+
+/// Apply the normal and friction impulses of the contacts of `m`.
+/// Below `resting` the approach speed does not bounce.
+fn resolve(bodies: &mut [Body], i: usize, j: usize, m: &Manifold, resting: f32) {
+    let (a, b) = pair_mut(bodies, i, j);
+    if a.inv_mass + b.inv_mass <= 0.0 {
+        return;
+    }
+
+    let restitution = a.restitution.max(b.restitution);
+    let friction = (a.friction * b.friction).sqrt();
+    let n = m.normal;
+    let count = m.contacts.len() as f32;
+
+    for contact in m.contacts.iter() {
+        let ra = contact.point - a.position;
+        let rb = contact.point - b.position;
+
+        let relative = |a: &Body, b: &Body| {
+            b.velocity + cross_sv(b.angular_velocity, rb) - a.velocity
+                - cross_sv(a.angular_velocity, ra)
+        };
+
+        let rv = relative(a, b);
+        let vn = rv.dot(n);
+        if vn > 0.0 {
+            continue;
+        }
+
+        let (ran, rbn) = (cross(ra, n), cross(rb, n));
+        let inv_mass_sum =
+            a.inv_mass + b.inv_mass + ran * ran * a.inv_inertia + rbn * rbn * b.inv_inertia;
+
+        let e = if -vn < resting { 0.0 } else { restitution };
+        let jn = -(1.0 + e) * vn / inv_mass_sum / count;
+
+        a.apply_impulse(-n * jn, ra);
+        b.apply_impulse(n * jn, rb);
+
+        // Friction along the contact, bounded by the normal impulse
+        let rv = relative(a, b);
+        let t = rv - n * rv.dot(n);
+        if t.magnitude2() <= 0.0 {
+            continue;
+        }
+        let t = t.normalize();
+
+        let (rat, rbt) = (cross(ra, t), cross(rb, t));
+        let inv_mass_sum =
+            a.inv_mass + b.inv_mass + rat * rat * a.inv_inertia + rbt * rbt * b.inv_inertia;
+
+        let jt = (-rv.dot(t) / inv_mass_sum / count)
+            .max(-jn * friction)
+            .min(jn * friction);
+
+        a.apply_impulse(-t * jt, ra);
+        b.apply_impulse(t * jt, rb);
+    }
+}
+
+/// Push the bodies out of each other, the solver leaves them slightly
+/// overlapping
+fn correct_positions(bodies: &mut [Body], i: usize, j: usize, m: &Manifold) {
+    const SLOP: f32 = 0.01;
+    const PERCENT: f32 = 0.4;
+
+    let (a, b) = pair_mut(bodies, i, j);
+    let inv_mass_sum = a.inv_mass + b.inv_mass;
+    if inv_mass_sum <= 0.0 {
+        return;
+    }
+
+    let penetration = m.contacts
+        .iter()
+        .map(|c| c.penetration)
+        .fold(0.0, f32::max);
+
+    let correction = m.normal * ((penetration - SLOP).max(0.0) / inv_mass_sum * PERCENT);
+    a.position -= correction * a.inv_mass;
+    b.position += correction * b.inv_mass;
+}
@@ -0,0 +1,75 @@
+use math::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BodyType2D {
+    /// Moved by the gravity, the forces and the collisions
+    Dynamic,
+    /// Moved by its velocity only, it pushes the dynamic bodies
+    Kinematic,
+    /// Never moved by the simulation, like a collider without a body
+    Static,
+}
+
+/// Simulates its game object in the XY plane with `Physics2D`, the position
+/// and the rotation around Z are read from the transform and written back
+/// every fixed step. The shape is the `Collider2D` of the game object.
+#[derive(Component, Clone, Debug)]
+pub struct RigidBody2D {
+    pub body_type: BodyType2D,
+    pub mass: f32,
+    pub velocity: Vector2f,
+    /// Radians per second, counter clockwise
+    pub angular_velocity: f32,
+    pub gravity_scale: f32,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    /// Do not rotate from the collisions
+    pub fixed_rotation: bool,
+
+    force: Vector2f,
+    torque: f32,
+}
+
+impl RigidBody2D {
+    pub fn new(body_type: BodyType2D) -> RigidBody2D {
+        RigidBody2D {
+            body_type,
+            mass: 1.0,
+            velocity: Vector2f::zero(),
+            angular_velocity: 0.0,
+            gravity_scale: 1.0,
+            linear_damping: 0.0,
+            angular_damping: 0.05,
+            fixed_rotation: false,
+            force: Vector2f::zero(),
+            torque: 0.0,
+        }
+    }
+
+    /// Applied during the fixed steps of the next update
+    pub fn add_force(&mut self, force: Vector2f) {
+        self.force += force;
+    }
+
+    pub fn add_torque(&mut self, torque: f32) {
+        self.torque += torque;
+    }
+
+    /// Change the velocity at once, only the dynamic bodies are affected
+    pub fn add_impulse(&mut self, impulse: Vector2f) {
+        if self.body_type == BodyType2D::Dynamic && self.mass > 0.0 {
+            self.velocity += impulse / self.mass;
+        }
+    }
+
+    /// The accumulated force and torque
+    pub(super) fn forces(&self) -> (Vector2f, f32) {
+        (self.force, self.torque)
+    }
+
+    /// Once the forces were applied by a step
+    pub(super) fn clear_forces(&mut self) {
+        self.force = Vector2f::zero();
+        self.torque = 0.0;
+    }
+}
@@ -0,0 +1,113 @@
+use engine::asset::{Asset, AssetSystem, FileFuture, LoadableAsset, Resource};
+use engine::render::{NineSlice, SpriteBorder, SpriteRenderer, Texture};
+use math::*;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A sprite in a `TextureAtlas`
+#[derive(Copy, Clone, Debug)]
+pub struct AtlasFrame {
+    /// The (min, max) uv in the texture, as `SpriteRenderer::region`
+    pub region: (Vector2f, Vector2f),
+    /// Size in pixels
+    pub size: Vector2f,
+    /// As `SpriteRenderer::pivot`, moved for the trimmed frames
+    pub pivot: Vector2f,
+    /// The nine-slice insets in pixels
+    pub border: Option<SpriteBorder>,
+}
+
+#[derive(Debug)]
+pub struct AtlasData {
+    pub texture: Rc<Texture>,
+    pub frames: HashMap<String, AtlasFrame>,
+    /// Named frame sequences
+    pub animations: HashMap<String, Vec<String>>,
+}
+
+/// The named sprites of a texture, loaded from the JSON metadata of
+/// TexturePacker or Aseprite by `AssetSystem::new_atlas`. Their animations
+/// are played by `SpriteAnimation`.
+pub struct TextureAtlas {
+    data: Resource<AtlasData>,
+    /// Pixels of a frame in a world unit, for the sprite size
+    pub pixels_per_unit: Cell<f32>,
+}
+
+impl Asset for TextureAtlas {
+    type Resource = Resource<AtlasData>;
+
+    fn new_from_resource(r: Self::Resource) -> Rc<Self> {
+        Rc::new(TextureAtlas {
+            data: r,
+            pixels_per_unit: Cell::new(100.0),
+        })
+    }
+}
+
+impl LoadableAsset for TextureAtlas {
+    fn load<T>(asys: &T, mut files: Vec<FileFuture>) -> Self::Resource
+    where
+        T: AssetSystem + Clone + 'static,
+    {
+        Self::load_resource::<AtlasData, T>(asys.clone(), files.remove(0))
+    }
+
+    fn gather<T: AssetSystem>(asys: &T, fname: &str) -> Vec<FileFuture> {
+        vec![asys.new_file(fname)]
+    }
+}
+
+impl TextureAtlas {
+    /// None while loading
+    pub fn texture(&self) -> Option<Rc<Texture>> {
+        self.data.try_borrow().ok().map(|d| d.texture.clone())
+    }
+
+    pub fn frame(&self, name: &str) -> Option<AtlasFrame> {
+        self.data
+            .try_borrow()
+            .ok()
+            .and_then(|d| d.frames.get(name).cloned())
+    }
+
+    pub fn frame_names(&self) -> Vec<String> {
+        match self.data.try_borrow() {
+            Ok(d) => d.frames.keys().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// The frames of the animation `name` of the file
+    pub fn animation(&self, name: &str) -> Option<Vec<String>> {
+        self.data
+            .try_borrow()
+            .ok()
+            .and_then(|d| d.animations.get(name).cloned())
+    }
+
+    /// Show the frame `name` with `sprite`, false if it is not loaded or missing.
+    /// The nine-slice frames keep the size of the sprite, they stretch to it.
+    pub fn apply(&self, sprite: &mut SpriteRenderer, name: &str) -> bool {
+        let data = match self.data.try_borrow() {
+            Ok(data) => data,
+            Err(_) => return false,
+        };
+
+        match data.frames.get(name) {
+            Some(frame) => {
+                sprite.texture = data.texture.clone();
+                sprite.region = frame.region;
+                sprite.pivot = frame.pivot;
+                sprite.nine_slice = frame.border.map(|b| NineSlice::new(b, frame.size));
+                if sprite.nine_slice.is_none() {
+                    sprite.size = frame.size / self.pixels_per_unit.get();
+                }
+                sprite.pixels_per_unit = self.pixels_per_unit.get();
+                true
+            }
+            None => false,
+        }
+    }
+}
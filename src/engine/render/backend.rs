@@ -0,0 +1,636 @@
+use engine::render::raw_gl;
+use engine::render::{color_attachment, BlendEquation, BlendFactor, CullMode, DepthTest,
+                     MipmapFiltering, TextureFiltering, TextureSampler, TextureWrap};
+use uni_gl;
+use uni_gl::*;
+
+/// What a buffer holds, vertex attributes or u16 indices
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BufferTarget {
+    Vertex,
+    Index,
+}
+
+/// How often a buffer is rewritten, `Stream` for data uploaded every frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BufferUsage {
+    Static,
+    Stream,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DrawTopology {
+    Triangles,
+    Lines,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+/// The f32 components of a vertex attribute
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VertexFormat {
+    Float2,
+    Float3,
+    Float4,
+}
+
+/// Where a texture is attached in the bound frame buffer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FrameAttachment {
+    /// The color output at a location, below `MAX_COLOR_ATTACHMENTS`
+    Color(usize),
+    Depth,
+}
+
+/// The kind of texture a sampler is set on
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureTarget {
+    Texture2d,
+    CubeMap,
+    /// `Texture::new_array` on WebGL2
+    Array,
+    /// `Texture::new_3d` on WebGL2
+    Volume,
+}
+
+/// The graphics API the renderer submits its work to.
+///
+/// The WebGL context is the only implementation for now. Frame commands, mesh
+/// buffers and their vertex layouts, draws, frame buffers, samplers, shader
+/// programs and the pipeline states go through this trait, so a WebGPU backend
+/// only has to provide these resources. The texture uploads other than rgba8,
+/// e.g. the float, compressed and cube map ones, and the uniforms still use the
+/// context directly.
+pub trait RenderBackend {
+    type Buffer;
+    type Texture;
+    type Program;
+    type Shader;
+    type FrameBuffer;
+    /// The vertex buffers of a mesh bound to the attribute locations
+    type VertexLayout;
+
+    // Frame commands
+
+    fn set_viewport(&self, x: i32, y: i32, w: u32, h: u32);
+
+    /// Clear the bound target, with `color` as the new clear color if any
+    fn clear_target(
+        &self,
+        color: Option<(f32, f32, f32, f32)>,
+        clear_color: bool,
+        clear_depth: bool,
+        clear_stencil: bool,
+    );
+
+    /// Draw `count` u16 indices of the bound index buffer, `instances` times
+    /// when it is set
+    fn draw_indexed(&self, topology: DrawTopology, count: usize, instances: Option<usize>);
+
+    // Buffers
+
+    fn create_buffer_with(
+        &self,
+        target: BufferTarget,
+        data: &[u8],
+        usage: BufferUsage,
+    ) -> Self::Buffer;
+
+    /// Replace the whole content of `buffer`
+    fn upload_buffer(
+        &self,
+        buffer: &Self::Buffer,
+        target: BufferTarget,
+        data: &[u8],
+        usage: BufferUsage,
+    );
+
+    /// Write `data` at `offset` bytes, inside the current size of `buffer`
+    fn update_buffer(
+        &self,
+        buffer: &Self::Buffer,
+        target: BufferTarget,
+        offset: usize,
+        data: &[u8],
+    );
+
+    fn destroy_buffer(&self, buffer: &Self::Buffer);
+
+    // Vertex layouts
+
+    fn new_vertex_layout(&self) -> Self::VertexLayout;
+
+    /// The layout the next `vertex_attribute`, `bind_indices` and draws use
+    fn bind_vertex_layout(&self, layout: &Self::VertexLayout);
+
+    fn destroy_vertex_layout(&self, layout: &Self::VertexLayout);
+
+    /// Read the attribute at `location` of each vertex from `buffer`
+    fn vertex_attribute(&self, buffer: &Self::Buffer, location: u32, format: VertexFormat);
+
+    /// Read the attribute at `location` of each instance from `buffer`, at
+    /// `offset` bytes of each `stride`
+    fn instance_attribute(
+        &self,
+        buffer: &Self::Buffer,
+        location: u32,
+        format: VertexFormat,
+        stride: usize,
+        offset: usize,
+    );
+
+    /// Stop reading the attribute at `location`, back to per vertex
+    fn disable_attribute(&self, location: u32);
+
+    /// The u16 indices of `draw_indexed`
+    fn bind_indices(&self, buffer: &Self::Buffer);
+
+    // Textures
+
+    /// Upload rgba8 pixels to the first level of the bound 2D texture
+    fn upload_texture_rgba(&self, size: (u32, u32), data: &[u8]);
+
+    fn bind_texture_unit(&self, texture: &Self::Texture, unit: u32);
+
+    fn destroy_texture(&self, texture: &Self::Texture);
+
+    // Samplers
+
+    /// Set the filters and wraps of `sampler` on the texture bound to `target`
+    fn apply_sampler(&self, target: TextureTarget, sampler: &TextureSampler);
+
+    /// The anisotropic filtering of the texture bound to `target`, only with
+    /// `Capabilities::max_anisotropy` above 1
+    fn set_anisotropy(&self, target: TextureTarget, samples: f32);
+
+    // Frame buffers
+
+    fn new_frame_buffer(&self) -> Self::FrameBuffer;
+
+    /// Render into `frame_buffer`, or into the screen for None
+    fn bind_frame_buffer(&self, frame_buffer: Option<&Self::FrameBuffer>);
+
+    /// Attach the first level of the 2D `texture` to the bound frame buffer
+    fn attach_texture(&self, texture: &Self::Texture, attachment: FrameAttachment);
+
+    /// Which color outputs of the bound frame buffer the draws write, by location
+    fn set_color_outputs(&self, outputs: &[bool]);
+
+    // Programs
+
+    /// Panics with the compile log when `source` doesn't compile
+    fn build_shader(&self, stage: ShaderStage, source: &str) -> Self::Shader;
+
+    /// Link `shaders`, with the attributes named in `attributes` at their locations
+    fn build_program(&self, shaders: &[&Self::Shader], attributes: &[(&str, u32)])
+        -> Self::Program;
+
+    fn attribute_location(&self, program: &Self::Program, name: &str) -> Option<u32>;
+
+    // Pipelines
+
+    fn bind_program(&self, program: &Self::Program);
+
+    fn set_cull_mode(&self, cull: CullMode);
+
+    /// `DepthTest::Never` disables the depth test
+    fn set_depth_compare(&self, test: DepthTest);
+
+    fn set_depth_write(&self, enabled: bool);
+
+    /// Blend the draws, or replace the target
+    fn enable_blending(&self, enabled: bool);
+
+    /// `equation(src * color, dst * target)` of the blended draws
+    fn set_blend_equation(&self, equation: BlendEquation);
+
+    fn set_blend_factors(&self, src: BlendFactor, dst: BlendFactor);
+}
+
+fn buffer_kind(target: BufferTarget) -> BufferKind {
+    match target {
+        BufferTarget::Vertex => BufferKind::Array,
+        BufferTarget::Index => BufferKind::ElementArray,
+    }
+}
+
+fn draw_mode(usage: BufferUsage) -> DrawMode {
+    match usage {
+        BufferUsage::Static => DrawMode::Static,
+        BufferUsage::Stream => DrawMode::Stream,
+    }
+}
+
+fn attribute_size(format: VertexFormat) -> AttributeSize {
+    match format {
+        VertexFormat::Float2 => AttributeSize::Two,
+        VertexFormat::Float3 => AttributeSize::Three,
+        VertexFormat::Float4 => AttributeSize::Four,
+    }
+}
+
+trait ToGLState<T> {
+    fn as_gl_state(&self) -> T;
+}
+
+impl ToGLState<uni_gl::DepthTest> for DepthTest {
+    fn as_gl_state(&self) -> uni_gl::DepthTest {
+        match self {
+            &DepthTest::Never => uni_gl::DepthTest::Never,
+            &DepthTest::Always => uni_gl::DepthTest::Always,
+            &DepthTest::Less => uni_gl::DepthTest::Less,
+            &DepthTest::LessEqual => uni_gl::DepthTest::Lequal,
+            &DepthTest::Greater => uni_gl::DepthTest::Greater,
+            &DepthTest::NotEqual => uni_gl::DepthTest::Notequal,
+            &DepthTest::GreaterEqual => uni_gl::DepthTest::Gequal,
+            &DepthTest::Equal => uni_gl::DepthTest::Equal,
+        }
+    }
+}
+
+impl ToGLState<BlendMode> for BlendFactor {
+    fn as_gl_state(&self) -> BlendMode {
+        match self {
+            &BlendFactor::Zero => BlendMode::Zero,
+            &BlendFactor::One => BlendMode::One,
+            &BlendFactor::SrcColor => BlendMode::SrcColor,
+            &BlendFactor::OneMinusSrcColor => BlendMode::OneMinusSrcColor,
+            &BlendFactor::DstColor => BlendMode::DstColor,
+            &BlendFactor::OneMinusDstColor => BlendMode::OneMinusDstColor,
+            &BlendFactor::SrcAlpha => BlendMode::SrcAlpha,
+            &BlendFactor::OneMinusSrcAlpha => BlendMode::OneMinusSrcAlpha,
+            &BlendFactor::DstAlpha => BlendMode::DstAlpha,
+            &BlendFactor::OneMinusDstAlpha => BlendMode::OneMinusDstAlpha,
+        }
+    }
+}
+
+impl ToGLState<uni_gl::BlendEquation> for BlendEquation {
+    fn as_gl_state(&self) -> uni_gl::BlendEquation {
+        match self {
+            &BlendEquation::Add => uni_gl::BlendEquation::FuncAdd,
+            &BlendEquation::Subtract => uni_gl::BlendEquation::FuncSubtract,
+            &BlendEquation::ReverseSubtract => uni_gl::BlendEquation::FuncReverseSubtract,
+        }
+    }
+}
+
+fn min_filter(sampler: &TextureSampler) -> TextureMinFilter {
+    match (sampler.min_filter, sampler.mipmap_filter) {
+        (TextureFiltering::Nearest, MipmapFiltering::None) => TextureMinFilter::Nearest,
+        (TextureFiltering::Nearest, MipmapFiltering::Nearest) => {
+            TextureMinFilter::NearestMipmapNearest
+        }
+        (TextureFiltering::Nearest, MipmapFiltering::Linear) => {
+            TextureMinFilter::NearestMipmapLinear
+        }
+        (TextureFiltering::Linear, MipmapFiltering::None) => TextureMinFilter::Linear,
+        (TextureFiltering::Linear, MipmapFiltering::Nearest) => {
+            TextureMinFilter::LinearMipmapNearest
+        }
+        (TextureFiltering::Linear, MipmapFiltering::Linear) => TextureMinFilter::LinearMipmapLinear,
+    }
+}
+
+fn gl_wrap(wrap: TextureWrap) -> i32 {
+    match wrap {
+        TextureWrap::Repeat => uni_gl::TextureWrap::Repeat as i32,
+        TextureWrap::ClampToEdge => uni_gl::TextureWrap::ClampToEdge as i32,
+        TextureWrap::MirroredRepeat => uni_gl::TextureWrap::MirroredRepeat as i32,
+    }
+}
+
+fn gl_target(target: TextureTarget) -> u32 {
+    match target {
+        TextureTarget::Texture2d => uni_gl::TextureKind::Texture2d as u32,
+        TextureTarget::CubeMap => uni_gl::TextureKind::TextureCubeMap as u32,
+        TextureTarget::Array => raw_gl::TEXTURE_2D_ARRAY,
+        TextureTarget::Volume => raw_gl::TEXTURE_3D,
+    }
+}
+
+impl RenderBackend for WebGLRenderingContext {
+    type Buffer = WebGLBuffer;
+    type Texture = WebGLTexture;
+    type Program = WebGLProgram;
+    type Shader = WebGLShader;
+    type FrameBuffer = WebGLFrameBuffer;
+    type VertexLayout = WebGLVertexArray;
+
+    fn set_viewport(&self, x: i32, y: i32, w: u32, h: u32) {
+        self.viewport(x, y, w, h);
+    }
+
+    fn clear_target(
+        &self,
+        color: Option<(f32, f32, f32, f32)>,
+        clear_color: bool,
+        clear_depth: bool,
+        clear_stencil: bool,
+    ) {
+        if let Some(col) = color {
+            self.clear_color(col.0, col.1, col.2, col.3);
+        }
+
+        if clear_color {
+            self.clear(BufferBit::Color);
+        }
+        if clear_depth {
+            self.clear(BufferBit::Depth);
+        }
+        if clear_stencil {
+            self.clear(BufferBit::Stencil);
+        }
+    }
+
+    fn draw_indexed(&self, topology: DrawTopology, count: usize, instances: Option<usize>) {
+        let mode = match topology {
+            DrawTopology::Triangles => Primitives::Triangles,
+            DrawTopology::Lines => Primitives::Lines,
+        };
+
+        match instances {
+            Some(n) => self.draw_elements_instanced(mode, count, DataType::U16, 0, n),
+            None => self.draw_elements(mode, count, DataType::U16, 0),
+        }
+    }
+
+    fn create_buffer_with(
+        &self,
+        target: BufferTarget,
+        data: &[u8],
+        usage: BufferUsage,
+    ) -> WebGLBuffer {
+        let buffer = self.create_buffer();
+        self.upload_buffer(&buffer, target, data, usage);
+        buffer
+    }
+
+    fn upload_buffer(
+        &self,
+        buffer: &WebGLBuffer,
+        target: BufferTarget,
+        data: &[u8],
+        usage: BufferUsage,
+    ) {
+        let kind = buffer_kind(target);
+        self.bind_buffer(kind, buffer);
+        self.buffer_data(kind, data, draw_mode(usage));
+        self.unbind_buffer(kind);
+    }
+
+    fn update_buffer(
+        &self,
+        buffer: &WebGLBuffer,
+        target: BufferTarget,
+        offset: usize,
+        data: &[u8],
+    ) {
+        let kind = buffer_kind(target);
+        self.bind_buffer(kind, buffer);
+        self.buffer_sub_data(kind, offset as u32, data);
+        self.unbind_buffer(kind);
+    }
+
+    fn destroy_buffer(&self, buffer: &WebGLBuffer) {
+        self.delete_buffer(buffer);
+    }
+
+    fn new_vertex_layout(&self) -> WebGLVertexArray {
+        self.create_vertex_array()
+    }
+
+    fn bind_vertex_layout(&self, layout: &WebGLVertexArray) {
+        self.bind_vertex_array(layout);
+    }
+
+    fn destroy_vertex_layout(&self, layout: &WebGLVertexArray) {
+        self.delete_vertex_array(layout);
+    }
+
+    fn vertex_attribute(&self, buffer: &WebGLBuffer, location: u32, format: VertexFormat) {
+        self.bind_buffer(BufferKind::Array, buffer);
+
+        self.enable_vertex_attrib_array(location);
+        self.vertex_attrib_pointer(location, attribute_size(format), DataType::Float, false, 0, 0);
+    }
+
+    fn instance_attribute(
+        &self,
+        buffer: &WebGLBuffer,
+        location: u32,
+        format: VertexFormat,
+        stride: usize,
+        offset: usize,
+    ) {
+        self.bind_buffer(BufferKind::Array, buffer);
+
+        self.enable_vertex_attrib_array(location);
+        self.vertex_attrib_pointer(
+            location,
+            attribute_size(format),
+            DataType::Float,
+            false,
+            stride as _,
+            offset as _,
+        );
+        self.vertex_attrib_divisor(location, 1);
+    }
+
+    fn disable_attribute(&self, location: u32) {
+        self.vertex_attrib_divisor(location, 0);
+        self.disable_vertex_attrib_array(location);
+    }
+
+    fn bind_indices(&self, buffer: &WebGLBuffer) {
+        self.bind_buffer(BufferKind::ElementArray, buffer);
+    }
+
+    fn upload_texture_rgba(&self, size: (u32, u32), data: &[u8]) {
+        self.tex_image2d(
+            TextureBindPoint::Texture2d,
+            0,
+            size.0 as u16,
+            size.1 as u16,
+            PixelFormat::Rgba,
+            PixelType::UnsignedByte,
+            data,
+        );
+    }
+
+    fn bind_texture_unit(&self, texture: &WebGLTexture, unit: u32) {
+        self.active_texture(unit);
+        self.bind_texture(texture);
+    }
+
+    fn destroy_texture(&self, texture: &WebGLTexture) {
+        self.delete_texture(texture);
+    }
+
+    fn apply_sampler(&self, target: TextureTarget, sampler: &TextureSampler) {
+        let min_filter = min_filter(sampler) as i32;
+        let mag_filter = match sampler.mag_filter {
+            TextureFiltering::Nearest => TextureMagFilter::Nearest,
+            TextureFiltering::Linear => TextureMagFilter::Linear,
+        } as i32;
+
+        let kind = match target {
+            TextureTarget::Texture2d => uni_gl::TextureKind::Texture2d,
+            TextureTarget::CubeMap => uni_gl::TextureKind::TextureCubeMap,
+            TextureTarget::Array | TextureTarget::Volume => {
+                let target = gl_target(target);
+                let set = |pname, value| raw_gl::tex_parameteri_3d(self, target, pname, value);
+
+                set(raw_gl::TEXTURE_MIN_FILTER, min_filter);
+                set(raw_gl::TEXTURE_MAG_FILTER, mag_filter);
+                set(raw_gl::TEXTURE_WRAP_S, gl_wrap(sampler.wrap_u));
+                set(raw_gl::TEXTURE_WRAP_T, gl_wrap(sampler.wrap_v));
+                set(raw_gl::TEXTURE_WRAP_R, gl_wrap(sampler.wrap_w));
+                return;
+            }
+        };
+
+        self.tex_parameteri(kind, TextureParameter::TextureMinFilter, min_filter);
+        self.tex_parameteri(kind, TextureParameter::TextureMagFilter, mag_filter);
+
+        self.tex_parameteri(kind, TextureParameter::TextureWrapS, gl_wrap(sampler.wrap_u));
+        self.tex_parameteri(kind, TextureParameter::TextureWrapT, gl_wrap(sampler.wrap_v));
+
+        if let TextureTarget::CubeMap = target {
+            self.tex_parameteri(kind, TextureParameter::TextureWrapR, gl_wrap(sampler.wrap_w));
+        }
+    }
+
+    fn set_anisotropy(&self, target: TextureTarget, samples: f32) {
+        raw_gl::tex_parameterf(
+            self,
+            gl_target(target),
+            raw_gl::TEXTURE_MAX_ANISOTROPY_EXT,
+            samples,
+        );
+    }
+
+    fn new_frame_buffer(&self) -> WebGLFrameBuffer {
+        self.create_framebuffer()
+    }
+
+    fn bind_frame_buffer(&self, frame_buffer: Option<&WebGLFrameBuffer>) {
+        match frame_buffer {
+            Some(fb) => self.bind_framebuffer(Buffers::Framebuffer, fb),
+            None => self.unbind_framebuffer(Buffers::Framebuffer),
+        }
+    }
+
+    fn attach_texture(&self, texture: &WebGLTexture, attachment: FrameAttachment) {
+        let buffer = match attachment {
+            FrameAttachment::Color(location) => color_attachment(location).0,
+            FrameAttachment::Depth => Buffers::DepthAttachment,
+        };
+
+        self.framebuffer_texture2d(
+            Buffers::Framebuffer,
+            buffer,
+            TextureBindPoint::Texture2d,
+            texture,
+            0,
+        );
+    }
+
+    fn set_color_outputs(&self, outputs: &[bool]) {
+        let buffers: Vec<_> = outputs
+            .iter()
+            .enumerate()
+            .map(|(location, &output)| {
+                if output {
+                    color_attachment(location).1
+                } else {
+                    ColorBuffer::None
+                }
+            })
+            .collect();
+
+        self.draw_buffer(&buffers);
+    }
+
+    fn build_shader(&self, stage: ShaderStage, source: &str) -> WebGLShader {
+        let shader = self.create_shader(match stage {
+            ShaderStage::Vertex => uni_gl::ShaderKind::Vertex,
+            ShaderStage::Fragment => uni_gl::ShaderKind::Fragment,
+        });
+
+        self.shader_source(&shader, source);
+        // uni-gl panics with the log itself
+        self.compile_shader(&shader);
+        shader
+    }
+
+    fn build_program(&self, shaders: &[&WebGLShader], attributes: &[(&str, u32)]) -> WebGLProgram {
+        let program = self.create_program();
+
+        for shader in shaders.iter() {
+            self.attach_shader(&program, shader);
+        }
+        for &(name, location) in attributes.iter() {
+            self.bind_attrib_location(&program, name, location);
+        }
+
+        self.link_program(&program);
+        program
+    }
+
+    fn attribute_location(&self, program: &WebGLProgram, name: &str) -> Option<u32> {
+        self.get_attrib_location(program, name.into())
+    }
+
+    fn bind_program(&self, program: &WebGLProgram) {
+        self.use_program(program);
+    }
+
+    fn set_cull_mode(&self, cull: CullMode) {
+        let face = match cull {
+            CullMode::Off => {
+                self.disable(Culling::CullFace as i32);
+                return;
+            }
+            CullMode::Front => Culling::Front,
+            CullMode::Back => Culling::Back,
+            CullMode::FrontAndBack => Culling::FrontAndBack,
+        };
+
+        self.enable(Culling::CullFace as i32);
+        self.cull_face(face);
+    }
+
+    fn set_depth_compare(&self, test: DepthTest) {
+        if let DepthTest::Never = test {
+            self.disable(Flag::DepthTest as i32);
+        } else {
+            self.enable(Flag::DepthTest as i32);
+            self.depth_func(test.as_gl_state());
+        }
+    }
+
+    fn set_depth_write(&self, enabled: bool) {
+        self.depth_mask(enabled);
+    }
+
+    fn enable_blending(&self, enabled: bool) {
+        if enabled {
+            self.enable(Flag::Blend as i32);
+        } else {
+            self.disable(Flag::Blend as i32);
+        }
+    }
+
+    fn set_blend_equation(&self, equation: BlendEquation) {
+        self.blend_equation(equation.as_gl_state());
+    }
+
+    fn set_blend_factors(&self, src: BlendFactor, dst: BlendFactor) {
+        self.blend_func(src.as_gl_state(), dst.as_gl_state());
+    }
+}
@@ -0,0 +1,45 @@
+use engine::asset::Asset;
+use engine::render::{MeshBuffer, MeshData, MeshSurface};
+use math::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Most vertices in a merged mesh, as indices are u16
+pub const MAX_BATCH_VERTICES: usize = 65536;
+
+/// Static meshes sharing a material, merged into one buffer in world space
+pub struct StaticBatch {
+    pub surface: Rc<MeshSurface>,
+    pub layer: u32,
+}
+
+/// Streaming buffers the small dynamic meshes are merged into on the CPU,
+/// one shared buffer per vertex layout which is refilled for every batch
+#[derive(Default)]
+pub struct DynamicBatcher {
+    buffers: RefCell<HashMap<[bool; 4], Rc<MeshBuffer>>>,
+}
+
+impl DynamicBatcher {
+    /// Merge the meshes in world space into the buffer of their layout,
+    /// all of them must have the same layout
+    pub fn batch(&self, meshes: &[(&MeshData, Matrix4f)]) -> Rc<MeshBuffer> {
+        let layout = meshes[0].0.layout();
+
+        let mut data = MeshData::with_layout(layout);
+        for &(mesh, ref m) in meshes.iter() {
+            data.append_transformed(mesh, m);
+        }
+
+        let mut buffers = self.buffers.borrow_mut();
+        let buffer = buffers.entry(layout).or_insert_with(|| {
+            let buffer = MeshBuffer::new(MeshData::with_layout(layout));
+            buffer.set_streaming(true);
+            buffer
+        });
+
+        buffer.update_mesh_data(data);
+        buffer.clone()
+    }
+}
@@ -1,5 +1,7 @@
-use engine::render::{RenderQueue, RenderTexture};
+use engine::core::{Aabb, Ray};
+use engine::render::{Lighting2DSettings, RenderQueue, RenderTexture, SsaoSettings};
 use math::*;
+use std::cell::Cell;
 use std::collections::BTreeSet;
 use std::rc::Rc;
 
@@ -9,12 +11,16 @@ pub struct Plane {
 }
 
 impl Plane {
-    /// Make a plane from 3 points (anti-clockwise)
-    pub fn from_3_points(p0: &Vector3<f32>, p1: &Vector3<f32>, p2: &Vector3<f32>) -> Plane {
-        let n = (p1 - p0).cross(p2 - p1).normalize();
-        let offset = n.dot(*p0);
-
-        Plane { n, offset }
+    /// Make a plane from a (a, b, c, d) row of a clip matrix, points with
+    /// a * x + b * y + c * z + d >= 0 are inside
+    fn from_clip_row(v: Vector4<f32>) -> Plane {
+        let n = v.truncate();
+        let len = n.magnitude();
+
+        Plane {
+            n: n / len,
+            offset: -v.w / len,
+        }
     }
 }
 
@@ -23,6 +29,23 @@ pub struct Frustum {
 }
 
 impl Frustum {
+    /// Extract the planes from a clip matrix, e.g. projection * view
+    /// (Gribb & Hartmann)
+    pub fn from_matrix(m: &Matrix4<f32>) -> Frustum {
+        let (x, y, z, w) = (m.row(0), m.row(1), m.row(2), m.row(3));
+
+        Frustum {
+            planes: [
+                Plane::from_clip_row(w + x),
+                Plane::from_clip_row(w - x),
+                Plane::from_clip_row(w - y),
+                Plane::from_clip_row(w + y),
+                Plane::from_clip_row(w + z),
+                Plane::from_clip_row(w - z),
+            ],
+        }
+    }
+
     pub fn collide_sphere(&self, p: &Vector3<f32>, r: f32) -> bool {
         for plane in self.planes.iter() {
             // Distance = (A*x0+B*y0+C*z0+D)/Sqrt(A*A+B*B+C*C)
@@ -33,25 +56,156 @@ impl Frustum {
 
         true
     }
+
+    pub fn collide_aabb(&self, aabb: &Aabb) -> bool {
+        for plane in self.planes.iter() {
+            // The corner furthest along the plane normal
+            let p = Vector3::new(
+                if plane.n.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.n.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.n.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+
+            if plane.n.dot(p) - plane.offset < 0.0 {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// What a camera clears before rendering. glClear ignores `Camera::rect`,
+/// so cameras drawn over the others usually use `DepthOnly` or `Nothing`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CameraClearFlags {
+    /// Clear with the engine clear color, the skybox queue is drawn over it
+    Skybox,
+    /// Clear with the color, the skybox queue is skipped
+    SolidColor((f32, f32, f32, f32)),
+    /// Keep the color of the cameras rendered before
+    DepthOnly,
+    Nothing,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Tonemapping {
+    /// Clamp to [0, 1]
+    None = 0,
+    Reinhard = 1,
+    /// Filmic curve fitted by Krzysztof Narkowicz
+    Aces = 2,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct HdrSettings {
+    pub tonemapping: Tonemapping,
+    pub exposure: f32,
+}
+
+/// Crisp 2D rendering of an orthographic camera: the scene is rendered at
+/// `reference_resolution` with the view snapped to whole pixels, then scaled
+/// by the largest integer fitting the camera viewport, centered, without
+/// filtering. The bars around it keep the screen clear color.
+#[derive(Copy, Clone, Debug)]
+pub struct PixelPerfect {
+    /// Screen pixels in a world unit, the one of the `TextureAtlas` of the sprites
+    pub pixels_per_unit: f32,
+    /// Size of the rendered image in pixels, before the integer scaling
+    pub reference_resolution: (u32, u32),
+}
+
+impl PixelPerfect {
+    /// The integer scale of the image in a viewport of `size` pixels, at least 1
+    pub fn scale(&self, size: (u32, u32)) -> u32 {
+        let (w, h) = self.reference_resolution;
+        (size.0 / w.max(1)).min(size.1 / h.max(1)).max(1)
+    }
+
+    /// The (pos, size) of the scaled image centered in the viewport `rect`
+    pub fn output_rect(&self, rect: ((i32, i32), (u32, u32))) -> ((i32, i32), (u32, u32)) {
+        let ((x, y), size) = rect;
+        let scale = self.scale(size);
+        let (w, h) = (
+            self.reference_resolution.0 * scale,
+            self.reference_resolution.1 * scale,
+        );
+
+        (
+            (
+                x + (size.0 as i32 - w as i32) / 2,
+                y + (size.1 as i32 - h as i32) / 2,
+            ),
+            (w, h),
+        )
+    }
+}
+
+impl Default for HdrSettings {
+    fn default() -> HdrSettings {
+        HdrSettings {
+            tonemapping: Tonemapping::Aces,
+            exposure: 1.0,
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct Camera {
     pub v: Matrix4<f32>,
 
+    /// Cameras are rendered by increasing depth, later ones draw over the others
+    pub depth: i32,
+    pub clear_flags: CameraClearFlags,
+    /// Bit mask of the GameObject layers rendered by this camera, see `Layers::mask`
+    pub culling_mask: u32,
+
     pub enable_frustum_culling: bool,
+    /// Skip the meshes hidden behind `Mesh::occluder` ones, needs frustum culling
+    pub enable_occlusion_culling: bool,
+    /// Render the depth of the opaque queue first, so its heavy fragment
+    /// shaders only run once per pixel
+    pub depth_prepass: bool,
 
     /// Optional viewport of this camera,  (pos, size) in pixels
     /// from 0 (left/top) to screen width/height (right/bottom)
     pub rect: Option<((i32, i32), (u32, u32))>,
     pub znear: f32,
     pub zfar: f32,
+    /// Used instead of the perspective of `znear`, `zfar` and the aspect
+    pub projection: Option<Matrix4<f32>>,
+    /// Half of the view height in world units, for an orthographic
+    /// projection instead of the perspective
+    pub orthographic_size: Option<f32>,
+    /// Orthographic and snapped to pixels, see `PixelPerfect`, it replaces
+    /// `orthographic_size`
+    pub pixel_perfect: Option<PixelPerfect>,
 
     pub included_render_queues: Option<BTreeSet<RenderQueue>>,
 
     eye: Point3<f32>,
 
     pub render_texture: Option<Rc<RenderTexture>>,
+
+    /// Render into a floating point target, tonemapped into
+    /// `render_texture` or the screen at the end of the pass
+    pub hdr: Option<HdrSettings>,
+
+    /// Screen space ambient occlusion from a depth/normal prepass of the opaque queue,
+    /// applied to the ambient terms of the phong shaders
+    pub ssao: Option<SsaoSettings>,
+
+    /// Render the depth of the opaque queue into a texture first, sampled by
+    /// the shaders of the pass through `unrust/camera_depth.glsl`, e.g. for
+    /// soft particles, decals or depth based fog
+    pub depth_texture: bool,
+
+    /// Light the sprites with the `Light2D` components instead of the full
+    /// brightness of their texture
+    pub lighting_2d: Option<Lighting2DSettings>,
+
+    // Projection * view of the last rendered frame, for reprojection
+    prev_view_proj: Cell<Option<Matrix4<f32>>>,
 }
 
 impl Default for Camera {
@@ -61,19 +215,60 @@ impl Default for Camera {
     }
 }
 
-fn extract_forward(m: &Matrix4<f32>) -> Vector3<f32> {
-    //-Vector3::new(m.data[2], m.data[2 + 1 * 4], m.data[2 + 2 * 4])
-    -m.row(2).truncate()
+/// Replace the near plane of a projection by a view space plane, the
+/// far plane is adjusted to keep the depth range, see Eric Lengyel's
+/// "Oblique View Frustum Depth Projection and Clipping"
+pub fn oblique_near_plane(proj: &Matrix4<f32>, plane: Vector4<f32>) -> Matrix4<f32> {
+    let inv = match proj.invert() {
+        Some(inv) => inv,
+        None => return *proj,
+    };
+
+    // The corner of the frustum opposite to the plane
+    let q = inv * Vector4::new(plane.x.signum(), plane.y.signum(), 1.0, 1.0);
+    let c = plane * (2.0 / plane.dot(q));
+
+    let mut m = *proj;
+    m.x.z = c.x - m.x.w;
+    m.y.z = c.y - m.y.w;
+    m.z.z = c.z - m.z.w;
+    m.w.z = c.w - m.w.w;
+    m
 }
 
-fn extract_up(m: &Matrix4<f32>) -> Vector3<f32> {
-    //Vector3::new(m.data[1], m.data[1 + 1 * 4], m.data[1 + 2 * 4])
-    m.row(1).truncate()
+/// Viewport rects tiling `screen_size` for `count` players, left to right and
+/// top to bottom. The cells of an incomplete last row share its width.
+pub fn split_screen_rects(count: usize, screen_size: (u32, u32)) -> Vec<((i32, i32), (u32, u32))> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let cols = (count as f32).sqrt().ceil() as usize;
+    let rows = (count + cols - 1) / cols;
+    let (w, h) = (screen_size.0 as usize, screen_size.1 as usize);
+
+    let mut rects = Vec::with_capacity(count);
+    for row in 0..rows {
+        let cells = (count - row * cols).min(cols);
+        let (y0, y1) = (h * row / rows, h * (row + 1) / rows);
+
+        for col in 0..cells {
+            let (x0, x1) = (w * col / cells, w * (col + 1) / cells);
+
+            // GL viewports start at the bottom
+            rects.push((
+                (x0 as i32, (h - y1) as i32),
+                ((x1 - x0) as u32, (y1 - y0) as u32),
+            ));
+        }
+    }
+
+    rects
 }
 
-fn extract_right(m: &Matrix4<f32>) -> Vector3<f32> {
-    //Vector3::new(m.data[0], m.data[0 + 1 * 4], m.data[0 + 2 * 4])
-    m.row(0).truncate()
+fn extract_forward(m: &Matrix4<f32>) -> Vector3<f32> {
+    //-Vector3::new(m.data[2], m.data[2 + 1 * 4], m.data[2 + 2 * 4])
+    -m.row(2).truncate()
 }
 
 impl Camera {
@@ -117,7 +312,51 @@ impl Camera {
         aspect
     }
 
+    /// The projection matrix, `projection` when it is set, else the pixel
+    /// perfect or orthographic one when enabled
     pub fn perspective(&self, screen_size: (u32, u32)) -> Matrix4<f32> {
+        if let Some(m) = self.projection {
+            return m;
+        }
+
+        if let Some(ref pixel_perfect) = self.pixel_perfect {
+            return self.pixel_perfect_projection(pixel_perfect);
+        }
+
+        match self.orthographic_size {
+            Some(size) => {
+                let half_width = size * self.calc_aspect(screen_size).max(0.001);
+                ortho(-half_width, half_width, -size, size, self.znear, self.zfar)
+            }
+            None => self.fov_perspective(screen_size),
+        }
+    }
+
+    /// An orthographic projection of the reference resolution with its texels
+    /// on whole pixels, the view position is snapped to them too
+    fn pixel_perfect_projection(&self, pixel_perfect: &PixelPerfect) -> Matrix4<f32> {
+        let ppu = pixel_perfect.pixels_per_unit.max(0.001);
+        let (w, h) = pixel_perfect.reference_resolution;
+
+        // Odd sizes keep the pixel edges at the view center
+        let left = -((w / 2) as f32) / ppu;
+        let bottom = -((h / 2) as f32) / ppu;
+        let proj = ortho(
+            left,
+            left + w as f32 / ppu,
+            bottom,
+            bottom + h as f32 / ppu,
+            self.znear,
+            self.zfar,
+        );
+
+        let snap = |t: f32| (t * ppu).round() / ppu - t;
+        let offset = Vector3::new(snap(self.v.w.x), snap(self.v.w.y), 0.0);
+
+        proj * Matrix4::from_translation(offset)
+    }
+
+    fn fov_perspective(&self, screen_size: (u32, u32)) -> Matrix4<f32> {
         use math::*;
 
         let aspect = self.calc_aspect(screen_size).max(0.001);
@@ -133,63 +372,150 @@ impl Camera {
     pub fn new() -> Camera {
         Camera {
             v: Matrix4::identity(),
+            depth: 0,
+            clear_flags: CameraClearFlags::Skybox,
+            culling_mask: !0,
             eye: Point3::new(0.0, 0.0, 0.0),
             rect: None,
             znear: 0.03,
             zfar: 1000.0,
+            projection: None,
+            orthographic_size: None,
+            pixel_perfect: None,
             enable_frustum_culling: true,
+            enable_occlusion_culling: false,
+            depth_prepass: false,
             included_render_queues: None,
             render_texture: None,
+            hdr: None,
+            ssao: None,
+            depth_texture: false,
+            lighting_2d: None,
+            prev_view_proj: Cell::new(None),
         }
     }
 
+    /// Projection * view of the last frame rendered by `Engine::render_pass`
+    pub fn prev_view_proj(&self) -> Option<Matrix4<f32>> {
+        self.prev_view_proj.get()
+    }
+
+    pub fn set_prev_view_proj(&self, m: Matrix4<f32>) {
+        self.prev_view_proj.set(Some(m));
+    }
+
+    pub fn renders_layer(&self, layer: u32) -> bool {
+        layer < 32 && self.culling_mask & (1 << layer) != 0
+    }
+
     pub fn eye(&self) -> Vector3<f32> {
         Vector3::new(self.eye.x, self.eye.y, self.eye.z)
     }
 
+    /// The frustum of `perspective` * `v` in world space
     pub fn calc_frustum(&self, screen_size: (u32, u32)) -> Frustum {
-        let forward = extract_forward(&self.v);
-        let up = extract_up(&self.v);
-        let right = extract_right(&self.v);
-        let aspect = self.calc_aspect(screen_size);
-
-        let near_center = self.eye.to_vec() + forward * self.znear;
-        let far_center = self.eye.to_vec() + forward * self.zfar;
-
-        let fovy: f32 = 3.1415 / 4.0;
-
-        let near_height = 2.0 * (fovy * 0.5).tan() * self.znear;
-        let far_height = 2.0 * (fovy * 0.5).tan() * self.zfar;
-        let near_width = near_height * aspect;
-        let far_width = far_height * aspect;
-
-        let far_top_left = far_center + up * (far_height * 0.5) - right * (far_width * 0.5);
-        let far_top_right = far_center + up * (far_height * 0.5) + right * (far_width * 0.5);
-        let far_bottom_left = far_center - up * (far_height * 0.5) - right * (far_width * 0.5);
-        let far_bottom_right = far_center - up * (far_height * 0.5) + right * (far_width * 0.5);
-
-        let near_top_left = near_center + up * (near_height * 0.5) - right * (near_width * 0.5);
-        let near_top_right = near_center + up * (near_height * 0.5) + right * (near_width * 0.5);
-        let near_bottom_left = near_center - up * (near_height * 0.5) - right * (near_width * 0.5);
-        let near_bottom_right = near_center - up * (near_height * 0.5) + right * (near_width * 0.5);
-
-        let plane_left = Plane::from_3_points(&near_bottom_left, &far_bottom_left, &far_top_left);
-        let plane_right = Plane::from_3_points(&near_bottom_right, &near_top_right, &far_top_right);
-        let plane_top = Plane::from_3_points(&near_top_right, &near_top_left, &far_top_left);
-        let plane_bottom =
-            Plane::from_3_points(&near_bottom_left, &near_bottom_right, &far_bottom_right);
-        let plane_near = Plane::from_3_points(&near_bottom_left, &near_top_left, &near_top_right);
-        let plane_far = Plane::from_3_points(&far_bottom_left, &far_bottom_right, &far_top_right);
+        Frustum::from_matrix(&(self.perspective(screen_size) * self.v))
+    }
 
-        Frustum {
-            planes: [
-                plane_left,
-                plane_right,
-                plane_top,
-                plane_bottom,
-                plane_near,
-                plane_far,
-            ],
+    /// The projection, see `perspective`, with its near plane replaced by a
+    /// world space plane, only the side `normal` points to is rendered. Set it
+    /// as `projection` for planar reflections, so what is behind the mirror is
+    /// clipped.
+    pub fn oblique_projection(
+        &self,
+        screen_size: (u32, u32),
+        point: Vector3<f32>,
+        normal: Vector3<f32>,
+    ) -> Matrix4<f32> {
+        let world_plane = normal.extend(-normal.dot(point));
+
+        // Planes transform by the inverse transpose
+        let plane = match self.v.invert() {
+            Some(inv) => inv.transpose() * world_plane,
+            None => return self.perspective(screen_size),
+        };
+
+        oblique_near_plane(&self.perspective(screen_size), plane)
+    }
+
+    /// The (pos, size) of the viewport in pixels, `rect` is applied as the GL viewport
+    fn viewport(&self, screen_size: (u32, u32)) -> ((f32, f32), (f32, f32)) {
+        let ((x, y), (w, h)) = self.rect.unwrap_or(((0, 0), screen_size));
+        ((x as f32, y as f32), (w.max(1) as f32, h.max(1) as f32))
+    }
+
+    /// Project a world point to the screen, (x, y) are in points from the
+    /// left/top of the screen like mouse positions, z is the depth in [0, 1].
+    /// None behind the camera.
+    pub fn world_to_screen(
+        &self,
+        p: Vector3<f32>,
+        screen_size: (u32, u32),
+        hidpi: f32,
+    ) -> Option<Vector3<f32>> {
+        let clip = self.perspective(screen_size) * self.v * p.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
         }
+
+        let ndc = clip.truncate() / clip.w;
+        let ((x, y), (w, h)) = self.viewport(screen_size);
+
+        // GL viewports start at the bottom of the screen
+        let px = x + (ndc.x * 0.5 + 0.5) * w;
+        let py = screen_size.1 as f32 - (y + (ndc.y * 0.5 + 0.5) * h);
+
+        Some(Vector3::new(px / hidpi, py / hidpi, ndc.z * 0.5 + 0.5))
+    }
+
+    /// The world point under a screen point in points, at `depth` world units
+    /// in front of the camera
+    pub fn screen_to_world(
+        &self,
+        p: Vector2<f32>,
+        depth: f32,
+        screen_size: (u32, u32),
+        hidpi: f32,
+    ) -> Vector3<f32> {
+        let ray = self.screen_point_to_ray(p.x, p.y, screen_size, hidpi);
+        let forward = self.forward();
+
+        // The ray starts on the near plane
+        let near_depth = (ray.origin - self.world_eye()).dot(forward);
+        ray.at((depth - near_depth) / ray.direction.dot(forward))
+    }
+
+    /// The ray from the near plane through a screen point in points,
+    /// e.g. to pick objects under the mouse
+    pub fn screen_point_to_ray(&self, x: f32, y: f32, screen_size: (u32, u32), hidpi: f32) -> Ray {
+        let ((vx, vy), (w, h)) = self.viewport(screen_size);
+
+        let px = x * hidpi;
+        let py = screen_size.1 as f32 - y * hidpi;
+
+        let ndc_x = (px - vx) / w * 2.0 - 1.0;
+        let ndc_y = (py - vy) / h * 2.0 - 1.0;
+
+        let inv = (self.perspective(screen_size) * self.v)
+            .invert()
+            .unwrap_or(Matrix4::identity());
+
+        let unproject = |z: f32| {
+            let p = inv * Vector4::new(ndc_x, ndc_y, z, 1.0);
+            p.truncate() / p.w
+        };
+
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+
+        Ray::new(near, far - near)
+    }
+
+    /// The camera position from the view matrix, `eye` is only set by `lookat`
+    fn world_eye(&self) -> Vector3<f32> {
+        self.v
+            .invert()
+            .map(|m| m.w.truncate())
+            .unwrap_or(Vector3::zero())
     }
 }
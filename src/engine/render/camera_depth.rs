@@ -0,0 +1,66 @@
+use engine::asset::AssetSystem;
+use engine::render::{Material, RenderTexture, ShaderProgram, Texture, TextureAttachment};
+use math::*;
+use std::rc::Rc;
+
+/// The depth of the opaque queue of a camera, rendered before its pass and
+/// bound to the shaders of the pass, see `Camera::depth_texture` and
+/// `unrust/camera_depth.glsl`
+pub struct CameraDepthState {
+    pub rt: Rc<RenderTexture>,
+    pub prepass_material: Rc<Material>,
+    pub enabled: bool,
+
+    size: (u32, u32),
+    viewport: ((i32, i32), (u32, u32)),
+    near_far: (f32, f32),
+}
+
+impl CameraDepthState {
+    pub fn new(db: &AssetSystem, size: (u32, u32)) -> CameraDepthState {
+        let mut prepass_material = Material::new(db.new_program("unrust/depth_only"));
+        prepass_material.states.draw_buffers = Some(0);
+
+        CameraDepthState {
+            rt: Rc::new(RenderTexture::new_with_depth(
+                size.0,
+                size.1,
+                TextureAttachment::Color0,
+            )),
+            prepass_material: Rc::new(prepass_material),
+            enabled: false,
+            size,
+            viewport: ((0, 0), size),
+            near_far: (0.0, 1.0),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// The window depth of the scene, in [0, 1]
+    pub fn texture(&self) -> Rc<Texture> {
+        self.rt.depth_texture().unwrap()
+    }
+
+    pub fn setup(&mut self, near_far: (f32, f32), viewport: ((i32, i32), (u32, u32))) {
+        self.near_far = near_far;
+        self.viewport = viewport;
+    }
+
+    /// Bind the uniforms, the texture goes through the engine texture units
+    pub fn bind(&self, prog: &ShaderProgram) {
+        let ((x, y), (w, h)) = self.viewport;
+
+        prog.set("uCameraDepthEnabled", true);
+        prog.set(
+            "uCameraDepthViewport",
+            Vector4::new(x as f32, y as f32, w as f32, h as f32),
+        );
+        prog.set(
+            "uCameraNearFar",
+            Vector2::new(self.near_far.0, self.near_far.1),
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use engine::render::raw_gl::{enable_extension, max_anisotropy, max_draw_buffers};
+use engine::render::MAX_COLOR_ATTACHMENTS;
+use uni_gl;
+use uni_gl::{Parameter, WebGLRenderingContext};
+
+/// The WebGL1 extensions enabled on the context, all false on WebGL2 and
+/// desktop GL where their features are core
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct WebGL1Extensions {
+    pub angle_instanced_arrays: bool,
+    pub oes_texture_float: bool,
+    pub oes_texture_half_float: bool,
+    pub webgl_color_buffer_float: bool,
+    pub ext_color_buffer_half_float: bool,
+    pub ext_frag_depth: bool,
+    pub webgl_depth_texture: bool,
+    pub ext_srgb: bool,
+}
+
+impl WebGL1Extensions {
+    /// Enable the extensions the browser supports on the WebGL1 context
+    fn enable(gl: &WebGLRenderingContext) -> WebGL1Extensions {
+        WebGL1Extensions {
+            angle_instanced_arrays: enable_extension(gl, "ANGLE_instanced_arrays"),
+            oes_texture_float: enable_extension(gl, "OES_texture_float"),
+            oes_texture_half_float: enable_extension(gl, "OES_texture_half_float"),
+            webgl_color_buffer_float: enable_extension(gl, "WEBGL_color_buffer_float"),
+            ext_color_buffer_half_float: enable_extension(gl, "EXT_color_buffer_half_float"),
+            ext_frag_depth: enable_extension(gl, "EXT_frag_depth"),
+            webgl_depth_texture: enable_extension(gl, "WEBGL_depth_texture"),
+            ext_srgb: enable_extension(gl, "EXT_sRGB"),
+        }
+    }
+}
+
+/// The features of the GL context of the engine, see `Engine::capabilities`.
+/// In the browser uni-gl creates a WebGL2 context when it can, and falls back
+/// to WebGL1 otherwise; natively the context is desktop GL 3.2, which has
+/// every WebGL2 feature.
+///
+/// On WebGL1 the features are only reported when the renderer can use the
+/// extension providing them, see `extensions` for what the browser has.
+///
+/// The engine detects them again when the context is restored, and passes
+/// them to the textures and the shader programs it binds. The default has
+/// none of the features, it is what the asset system reports until the
+/// engine is created, see `AssetSystem::capabilities`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    /// WebGL2 or desktop GL, the GLSL 300 es shaders compile
+    pub webgl2: bool,
+    pub vertex_array_objects: bool,
+    /// Not with ANGLE_instanced_arrays, uni-gl draws the instances with the
+    /// WebGL2 entry points
+    pub instancing: bool,
+    /// Uniform blocks in the shaders, filled from uniform buffers. The camera
+    /// and the lights of the GLSL 300 es shaders are uploaded once per pass.
+    pub uniform_buffers: bool,
+    /// Color outputs of a render texture, 1 without multiple render targets.
+    /// The least of MAX_COLOR_ATTACHMENTS and MAX_DRAW_BUFFERS, at most
+    /// `MAX_COLOR_ATTACHMENTS`.
+    pub max_color_attachments: usize,
+    /// TEXTURE_3D and TEXTURE_2D_ARRAY textures for `Texture::new_array` and
+    /// `Texture::new_3d`, which lay out 2D textures without them
+    pub texture_3d: bool,
+    /// Float textures for the HDR and 16 bits images, RGBA16F for the
+    /// equirectangular cube maps. They are RGBM encoded without them, see
+    /// `RGBM_RANGE`.
+    pub float_textures: bool,
+    /// Float textures and render targets, `TextureAttachment::Color0Hdr`.
+    /// EXT_color_buffer_float on WebGL2, OES_texture_float and
+    /// WEBGL_color_buffer_float on WebGL1.
+    pub float_render_targets: bool,
+    /// Half float render targets, `TextureAttachment::Color0HalfFloat`.
+    /// EXT_color_buffer_float or EXT_color_buffer_half_float on WebGL2,
+    /// OES_texture_half_float and EXT_color_buffer_half_float on WebGL1.
+    pub half_float_render_targets: bool,
+    /// `gl_FragDepth` in the fragment shaders. Not with EXT_frag_depth, the
+    /// shaders writing the depth are GLSL 300 es.
+    pub frag_depth: bool,
+    /// Depth render textures for the shadow maps, WEBGL_depth_texture on WebGL1
+    pub depth_texture: bool,
+    /// SRGB8_ALPHA8 textures, decoded to linear when sampled. EXT_sRGB on
+    /// WebGL1, where the format is SRGB_ALPHA_EXT.
+    pub srgb: bool,
+    /// KHR_parallel_shader_compile, the programs poll their link status instead
+    /// of waiting for the next frame
+    pub parallel_shader_compile: bool,
+    /// DXT1 and DXT5 compressed textures, from DDS and KTX files. An extension
+    /// on WebGL2 too, mostly missing on mobile GPUs.
+    pub s3tc: bool,
+    /// ETC2 compressed textures from KTX files, WEBGL_compressed_texture_etc.
+    /// Mostly on mobile GPUs, not reported natively.
+    pub etc2: bool,
+    /// ASTC compressed textures from KTX files, WEBGL_compressed_texture_astc.
+    /// Mostly on mobile GPUs, not reported natively.
+    pub astc: bool,
+    /// Texture fetches in the vertex shaders
+    pub vertex_textures: bool,
+    /// The most samples of `TextureSampler::anisotropy`, 1 without
+    /// EXT_texture_filter_anisotropic
+    pub max_anisotropy: f32,
+    pub max_texture_size: u32,
+    pub max_texture_units: u32,
+    pub extensions: WebGL1Extensions,
+}
+
+impl Capabilities {
+    pub fn detect(gl: &WebGLRenderingContext) -> Capabilities {
+        let core = gl.is_webgl2 || !uni_gl::IS_GL_ES;
+        let ext = if core {
+            WebGL1Extensions::default()
+        } else {
+            WebGL1Extensions::enable(gl)
+        };
+        let parameter = |p: Parameter| gl.get_parameter(p).max(0) as u32;
+
+        let s3tc = !uni_gl::IS_GL_ES || enable_extension(gl, "WEBGL_compressed_texture_s3tc");
+        let etc2 = enable_extension(gl, "WEBGL_compressed_texture_etc");
+        let astc = enable_extension(gl, "WEBGL_compressed_texture_astc");
+
+        // Float textures are core on WebGL2, rendering into them is not
+        let (float_render_targets, half_float_render_targets) = if !uni_gl::IS_GL_ES {
+            (true, true)
+        } else if gl.is_webgl2 {
+            let float = enable_extension(gl, "EXT_color_buffer_float");
+            (float, float || enable_extension(gl, "EXT_color_buffer_half_float"))
+        } else {
+            (
+                ext.oes_texture_float && ext.webgl_color_buffer_float,
+                ext.oes_texture_half_float && ext.ext_color_buffer_half_float,
+            )
+        };
+
+        // uni-gl draws into several attachments with the WebGL2 drawBuffers
+        let max_color_attachments = if core {
+            (max_draw_buffers(gl) as usize).max(1).min(MAX_COLOR_ATTACHMENTS)
+        } else {
+            1
+        };
+
+        Capabilities {
+            webgl2: core,
+            vertex_array_objects: core,
+            instancing: core,
+            uniform_buffers: core,
+            max_color_attachments,
+            texture_3d: core,
+            float_textures: core,
+            float_render_targets,
+            half_float_render_targets,
+            frag_depth: core,
+            depth_texture: core || ext.webgl_depth_texture,
+            srgb: core || ext.ext_srgb,
+            parallel_shader_compile: enable_extension(gl, "KHR_parallel_shader_compile"),
+            s3tc,
+            etc2,
+            astc,
+            vertex_textures: parameter(Parameter::MaxVertexTextureImageUnits) > 0,
+            max_anisotropy: max_anisotropy(gl),
+            max_texture_size: parameter(Parameter::MaxTextureSize),
+            max_texture_units: parameter(Parameter::MaxCombinedTextureImageUnits),
+            extensions: ext,
+        }
+    }
+
+    /// The shader keywords of the supported features, for the materials
+    /// choosing a code path with `Material::enable_keyword`
+    pub fn keywords(&self) -> Vec<&'static str> {
+        let features = [
+            (self.webgl2, "WEBGL2"),
+            (self.instancing, "INSTANCING"),
+            (self.uniform_buffers, "UNIFORM_BUFFERS"),
+            (self.max_color_attachments > 1, "MULTIPLE_RENDER_TARGETS"),
+            (self.texture_3d, "TEXTURE_3D"),
+            (self.float_render_targets, "FLOAT_RENDER_TARGETS"),
+            (self.half_float_render_targets, "HALF_FLOAT_RENDER_TARGETS"),
+            (self.frag_depth, "FRAG_DEPTH"),
+            (self.depth_texture, "DEPTH_TEXTURE"),
+            (self.srgb, "SRGB"),
+            (self.vertex_textures, "VERTEX_TEXTURES"),
+        ];
+
+        features
+            .iter()
+            .filter(|&&(supported, _)| supported)
+            .map(|&(_, keyword)| keyword)
+            .collect()
+    }
+
+    /// The keywords predefined in every shader the programs compile,
+    /// UNIFORM_BUFFERS for the camera and light blocks and TEXTURE_3D for the
+    /// shaders sampling the array and 3D textures
+    pub(crate) fn shader_defines(&self) -> Vec<String> {
+        let mut defines = Vec::new();
+        if self.uniform_buffers {
+            defines.push("UNIFORM_BUFFERS".to_string());
+        }
+        if self.texture_3d {
+            defines.push("TEXTURE_3D".to_string());
+        }
+        defines
+    }
+}
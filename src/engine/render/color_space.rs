@@ -0,0 +1,25 @@
+/// The space the lighting is computed in, see `Engine::color_space`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorSpace {
+    /// The colors are lit as they are stored, in sRGB, which washes out
+    /// the lighting
+    Gamma,
+    /// The color textures, the vertex colors and the material colors are
+    /// decoded from sRGB before the lighting, and the result is encoded back
+    /// on output, or by the tonemapping of the HDR cameras. The light colors
+    /// are linear. Without sRGB render targets the blending of transparent
+    /// surfaces still happens in sRGB, except in HDR targets.
+    Linear,
+}
+
+impl Default for ColorSpace {
+    fn default() -> ColorSpace {
+        ColorSpace::Gamma
+    }
+}
+
+/// An sRGB color in linear space, the alpha is kept
+pub fn srgb_to_linear(color: (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+    let decode = |c: f32| c.max(0.0).powf(2.2);
+    (decode(color.0), decode(color.1), decode(color.2), color.3)
+}
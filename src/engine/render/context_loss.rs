@@ -0,0 +1,18 @@
+use std::cell::Cell;
+
+thread_local!(static CONTEXT_GENERATION: Cell<u32> = Cell::new(0));
+
+/// The gl context the GPU resources are created in, bumped by
+/// `next_context_generation` when a lost context is restored.
+///
+/// Meshes, textures, programs and frame buffers remember the generation of
+/// their gl state and recreate it from their CPU side data when it is older,
+/// the handles of the lost context are never used or deleted.
+pub fn context_generation() -> u32 {
+    CONTEXT_GENERATION.with(|g| g.get())
+}
+
+/// Invalidate the gl state of every GPU resource
+pub fn next_context_generation() {
+    CONTEXT_GENERATION.with(|g| g.set(g.get().wrapping_add(1)));
+}
@@ -0,0 +1,222 @@
+use engine::asset::Asset;
+use engine::core::{Aabb, Ray};
+use engine::render::{MeshBuffer, MeshData, MAX_BATCH_VERTICES};
+use math::*;
+use std::f32;
+use std::rc::Rc;
+
+/// Segments of the circles of `wire_sphere`
+const SPHERE_SEGMENTS: usize = 24;
+
+struct DebugLine {
+    from: Vector3f,
+    to: Vector3f,
+    color: Vector3f,
+    depth_test: bool,
+    remaining: f32,
+}
+
+/// A label in world space, see `DebugDraw::text3d`
+pub struct DebugText {
+    pub position: Vector3f,
+    pub text: String,
+    remaining: f32,
+}
+
+/// Immediate mode shapes for debugging, e.g. physics and AI, see
+/// `Engine::debug_draw`. The lines are batched into one streaming buffer per
+/// depth test mode and drawn in the overlay of every camera, after its post
+/// processing.
+///
+/// `duration` and `depth_test` apply to the following calls until changed.
+pub struct DebugDraw {
+    /// Seconds a shape stays, 0 draws it for a single frame
+    pub duration: f32,
+    /// Hide the lines behind the scene, else draw them on top
+    pub depth_test: bool,
+
+    lines: Vec<DebugLine>,
+    texts: Vec<DebugText>,
+    buffers: Option<[Rc<MeshBuffer>; 2]>,
+}
+
+impl Default for DebugDraw {
+    fn default() -> DebugDraw {
+        DebugDraw {
+            duration: 0.0,
+            depth_test: true,
+            lines: Vec::new(),
+            texts: Vec::new(),
+            buffers: None,
+        }
+    }
+}
+
+impl DebugDraw {
+    pub fn new() -> DebugDraw {
+        Default::default()
+    }
+
+    pub fn line(&mut self, from: Vector3f, to: Vector3f, color: Vector3f) {
+        self.lines.push(DebugLine {
+            from,
+            to,
+            color,
+            depth_test: self.depth_test,
+            remaining: self.duration,
+        });
+    }
+
+    /// The first `length` world units of `ray`
+    pub fn ray(&mut self, ray: &Ray, length: f32, color: Vector3f) {
+        self.line(ray.origin, ray.at(length), color);
+    }
+
+    pub fn wire_box(&mut self, aabb: &Aabb, color: Vector3f) {
+        let c = aabb.corners();
+
+        for i in 0..4 {
+            // The rings at min and max z, and the edges between them
+            self.line(c[i], c[(i + 1) % 4], color);
+            self.line(c[i + 4], c[(i + 1) % 4 + 4], color);
+            self.line(c[i], c[i + 4], color);
+        }
+    }
+
+    /// A circle in each of the XY, YZ and ZX planes
+    pub fn wire_sphere(&mut self, center: Vector3f, radius: f32, color: Vector3f) {
+        let point = |i: usize, axes: (Vector3f, Vector3f)| {
+            let a = i as f32 / SPHERE_SEGMENTS as f32 * 2.0 * f32::consts::PI;
+            center + (axes.0 * a.cos() + axes.1 * a.sin()) * radius
+        };
+
+        let planes = [
+            (Vector3f::unit_x(), Vector3f::unit_y()),
+            (Vector3f::unit_y(), Vector3f::unit_z()),
+            (Vector3f::unit_z(), Vector3f::unit_x()),
+        ];
+
+        for &axes in planes.iter() {
+            for i in 0..SPHERE_SEGMENTS {
+                self.line(point(i, axes), point(i + 1, axes), color);
+            }
+        }
+    }
+
+    /// The X, Y and Z axes of `transform` in red, green and blue, `size` long
+    pub fn axes(&mut self, transform: &Matrix4f, size: f32) {
+        let origin = transform.w.truncate();
+
+        self.line(
+            origin,
+            origin + transform.x.truncate().normalize() * size,
+            Vector3f::new(1.0, 0.0, 0.0),
+        );
+        self.line(
+            origin,
+            origin + transform.y.truncate().normalize() * size,
+            Vector3f::new(0.0, 1.0, 0.0),
+        );
+        self.line(
+            origin,
+            origin + transform.z.truncate().normalize() * size,
+            Vector3f::new(0.0, 0.0, 1.0),
+        );
+    }
+
+    /// A label centered on `position`, drawn by imgui over the main camera
+    /// view, always on top
+    pub fn text3d<S: Into<String>>(&mut self, position: Vector3f, text: S) {
+        self.texts.push(DebugText {
+            position,
+            text: text.into(),
+            remaining: self.duration,
+        });
+    }
+
+    pub fn texts(&self) -> &[DebugText] {
+        &self.texts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.texts.is_empty()
+    }
+
+    /// Remove everything now
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.texts.clear();
+    }
+
+    /// Age the shapes by `dt` seconds once per frame, those out of time since
+    /// the last step are removed. A shape is drawn at least once.
+    pub fn step(&mut self, dt: f32) {
+        self.lines.retain(|l| l.remaining >= 0.0);
+        self.texts.retain(|t| t.remaining >= 0.0);
+
+        for l in self.lines.iter_mut() {
+            l.remaining -= dt;
+        }
+
+        for t in self.texts.iter_mut() {
+            t.remaining -= dt;
+        }
+    }
+
+    /// The buffers of the depth tested lines and of the lines on top with
+    /// their depth test, refilled with the current lines. Buffers without lines
+    /// are skipped, the lines beyond one buffer are dropped.
+    pub fn line_buffers(&mut self) -> Vec<(Rc<MeshBuffer>, bool)> {
+        if self.buffers.is_none() {
+            let buffer = || {
+                let buffer = MeshBuffer::new(MeshData::with_layout([false, true, false, false]));
+                buffer.set_streaming(true);
+                buffer
+            };
+
+            self.buffers = Some([buffer(), buffer()]);
+        }
+
+        let buffers = self.buffers.clone().unwrap();
+        let mut result = Vec::new();
+
+        for (buffer, &depth_test) in buffers.iter().zip([true, false].iter()) {
+            // The color is the normal
+            let mut data = MeshData::with_layout([false, true, false, false]);
+
+            {
+                let colors = data.normals.as_mut().unwrap();
+                let lines = self.lines.iter().filter(|l| l.depth_test == depth_test);
+
+                for (i, l) in lines.take(MAX_BATCH_VERTICES / 2).enumerate() {
+                    data.vertices.extend_from_slice(&[
+                        l.from.x,
+                        l.from.y,
+                        l.from.z,
+                        l.to.x,
+                        l.to.y,
+                        l.to.z,
+                    ]);
+                    colors.extend_from_slice(&[
+                        l.color.x,
+                        l.color.y,
+                        l.color.z,
+                        l.color.x,
+                        l.color.y,
+                        l.color.z,
+                    ]);
+
+                    let base = (i * 2) as u16;
+                    data.indices.extend_from_slice(&[base, base + 1]);
+                }
+            }
+
+            if !data.indices.is_empty() {
+                buffer.update_mesh_data(data);
+                result.push((buffer.clone(), depth_test));
+            }
+        }
+
+        result
+    }
+}
@@ -0,0 +1,44 @@
+use engine::render::ShaderProgram;
+use math::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FogMode {
+    /// No fog before `start`, full fog after `end`, in world units from the camera
+    Linear { start: f32, end: f32 },
+    /// `exp(-density * distance)` of the color is kept
+    Exponential { density: f32 },
+    /// `exp(-(density * distance)^2)` of the color is kept
+    ExponentialSquared { density: f32 },
+}
+
+/// The scene fog of `Engine::fog`, blended by the standard shaders over the
+/// distance to the camera. Materials opt out with `Material::fog`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Vector3f,
+}
+
+impl Default for Fog {
+    fn default() -> Fog {
+        Fog {
+            mode: FogMode::ExponentialSquared { density: 0.02 },
+            color: Vector3f::new(0.5, 0.5, 0.5),
+        }
+    }
+}
+
+impl Fog {
+    pub fn bind(&self, prog: &ShaderProgram) {
+        let (mode, params): (i32, Vector3f) = match self.mode {
+            FogMode::Linear { start, end } => (0, Vector3f::new(start, end, 0.0)),
+            FogMode::Exponential { density } => (1, Vector3f::new(0.0, 0.0, density)),
+            FogMode::ExponentialSquared { density } => (2, Vector3f::new(0.0, 0.0, density)),
+        };
+
+        prog.set("uFogEnabled", true);
+        prog.set("uFogMode", mode);
+        prog.set("uFogColor", self.color);
+        prog.set("uFogParams", params);
+    }
+}
@@ -2,43 +2,126 @@ use uni_gl::*;
 
 use std::rc::Rc;
 use std::cell::RefCell;
-use engine::render::{Texture, TextureAttachment};
+use engine::render::backend::RenderBackend;
+use engine::render::{context_generation, Capabilities, Texture, TextureAttachment,
+                     MAX_COLOR_ATTACHMENTS};
 
 pub struct FrameBuffer {
     pub texture: Rc<Texture>,
-    handle: RefCell<Option<WebGLFrameBuffer>>,
+    /// Color outputs after `texture`, at locations 1, 2...
+    pub extra_textures: Vec<Rc<Texture>>,
+    pub depth: Option<Rc<Texture>>,
+    /// The frame buffer and the `context_generation` it was created in
+    handle: RefCell<Option<(WebGLFrameBuffer, u32)>>,
 }
 
 impl FrameBuffer {
     pub fn new(width: u32, height: u32, attach: TextureAttachment) -> FrameBuffer {
         let texture = Texture::new_render_texture(width, height, attach);
         let handle = RefCell::new(None);
-        FrameBuffer { texture, handle }
+        FrameBuffer {
+            texture,
+            extra_textures: Vec::new(),
+            depth: None,
+            handle,
+        }
+    }
+
+    pub fn new_with_depth(width: u32, height: u32, attach: TextureAttachment) -> FrameBuffer {
+        let texture = Texture::new_render_texture(width, height, attach);
+        let depth = Texture::new_render_texture(width, height, TextureAttachment::Depth);
+        let handle = RefCell::new(None);
+        FrameBuffer {
+            texture,
+            extra_textures: Vec::new(),
+            depth: Some(depth),
+            handle,
+        }
+    }
+
+    /// One color texture per entry of `attachments`, at the matching output location
+    pub fn new_mrt(
+        width: u32,
+        height: u32,
+        attachments: &[TextureAttachment],
+        with_depth: bool,
+    ) -> FrameBuffer {
+        assert!(
+            !attachments.is_empty() && attachments.len() <= MAX_COLOR_ATTACHMENTS,
+            "Invalid number of color attachments"
+        );
+
+        let mut textures: Vec<_> = attachments
+            .iter()
+            .map(|attach| Texture::new_render_texture(width, height, *attach))
+            .collect();
+
+        let texture = textures.remove(0);
+        let depth = if with_depth {
+            Some(Texture::new_render_texture(
+                width,
+                height,
+                TextureAttachment::Depth,
+            ))
+        } else {
+            None
+        };
+
+        FrameBuffer {
+            texture,
+            extra_textures: textures,
+            depth,
+            handle: RefCell::new(None),
+        }
+    }
+
+    pub fn color_count(&self) -> usize {
+        1 + self.extra_textures.len()
     }
 
     fn create_fb(&self, gl: &WebGLRenderingContext) {
-        *self.handle.borrow_mut() = Some(gl.create_framebuffer());
+        *self.handle.borrow_mut() = Some((gl.new_frame_buffer(), context_generation()));
     }
 
-    pub fn prepare(&self, gl: &WebGLRenderingContext) {
-        if self.handle.borrow().is_some() {
-            return;
+    pub fn prepare(&self, gl: &WebGLRenderingContext, caps: &Capabilities) {
+        match *self.handle.borrow() {
+            Some((_, context)) if context == context_generation() => return,
+            _ => {}
         }
 
         self.create_fb(gl);
-        self.bind(gl);
+        self.bind(gl, caps);
         self.unbind(gl);
     }
 
-    pub fn bind(&self, gl: &WebGLRenderingContext) {
+    pub fn bind(&self, gl: &WebGLRenderingContext, caps: &Capabilities) {
         let ho = self.handle.borrow();
-        let h = ho.as_ref().unwrap();
+        let &(ref h, _) = ho.as_ref().unwrap();
 
-        gl.bind_framebuffer(Buffers::Framebuffer, &h);
-        self.texture.bind_with_frame_buffer(gl, 0).unwrap();
+        gl.bind_frame_buffer(Some(h));
+        self.texture.bind_with_frame_buffer(gl, caps, 0).unwrap();
+
+        if !self.extra_textures.is_empty() {
+            for (i, tex) in self.extra_textures.iter().enumerate() {
+                tex.bind_color_with_frame_buffer(gl, caps, 0, i + 1).unwrap();
+            }
+
+            gl.set_color_outputs(&vec![true; self.color_count()]);
+        }
+
+        if let Some(ref depth) = self.depth {
+            depth.bind_depth_with_frame_buffer(gl, caps, 0).unwrap();
+        }
     }
 
     pub fn unbind(&self, gl: &WebGLRenderingContext) {
-        gl.unbind_framebuffer(Buffers::Framebuffer);
+        gl.bind_frame_buffer(None);
+    }
+
+    pub fn generate_mipmaps(&self, gl: &WebGLRenderingContext) {
+        self.texture.generate_mipmaps(gl);
+        for tex in self.extra_textures.iter() {
+            tex.generate_mipmaps(gl);
+        }
     }
 }
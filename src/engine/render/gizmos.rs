@@ -0,0 +1,139 @@
+use engine::core::Aabb;
+use engine::render::{Camera, DebugDraw, Light};
+use math::*;
+
+const CAMERA_COLOR: (f32, f32, f32) = (0.8, 0.8, 0.8);
+const LIGHT_COLOR: (f32, f32, f32) = (1.0, 0.85, 0.3);
+const BOUNDS_COLOR: (f32, f32, f32) = (0.3, 1.0, 0.4);
+
+/// Segments of the spot light cone
+const CONE_SEGMENTS: usize = 16;
+
+fn color(c: (f32, f32, f32)) -> Vector3f {
+    Vector3f::new(c.0, c.1, c.2)
+}
+
+/// The built-in gizmos of the overlay, drawn after the post processing of
+/// every camera. Toggle them at runtime through `Engine::gizmos`.
+#[derive(Copy, Clone, Debug)]
+pub struct GizmoSettings {
+    pub enabled: bool,
+    /// The frustum of the other cameras
+    pub cameras: bool,
+    /// The direction of the directional lights, the range of the point
+    /// lights and the cone of the spot lights
+    pub lights: bool,
+    /// The world bounds of the meshes
+    pub bounds: bool,
+    /// Hide the gizmos behind the scene
+    pub depth_test: bool,
+}
+
+impl Default for GizmoSettings {
+    fn default() -> GizmoSettings {
+        GizmoSettings {
+            enabled: false,
+            cameras: true,
+            lights: true,
+            bounds: false,
+            depth_test: true,
+        }
+    }
+}
+
+/// The edges of the frustum of `camera`
+pub fn camera_gizmo(draw: &mut DebugDraw, camera: &Camera, screen_size: (u32, u32)) {
+    let inv = match (camera.perspective(screen_size) * camera.v).invert() {
+        Some(inv) => inv,
+        None => return,
+    };
+
+    let corner = |x: f32, y: f32, z: f32| {
+        let p = inv * Vector4::new(x, y, z, 1.0);
+        p.truncate() / p.w
+    };
+
+    let near = [
+        corner(-1.0, -1.0, -1.0),
+        corner(1.0, -1.0, -1.0),
+        corner(1.0, 1.0, -1.0),
+        corner(-1.0, 1.0, -1.0),
+    ];
+    let far = [
+        corner(-1.0, -1.0, 1.0),
+        corner(1.0, -1.0, 1.0),
+        corner(1.0, 1.0, 1.0),
+        corner(-1.0, 1.0, 1.0),
+    ];
+
+    let c = color(CAMERA_COLOR);
+    for i in 0..4 {
+        draw.line(near[i], near[(i + 1) % 4], c);
+        draw.line(far[i], far[(i + 1) % 4], c);
+        draw.line(near[i], far[i], c);
+    }
+}
+
+/// `position` is where the light object is, directional lights are drawn
+/// as an arrow from it
+pub fn light_gizmo(draw: &mut DebugDraw, light: &Light, position: Vector3f) {
+    let c = color(LIGHT_COLOR);
+
+    match *light {
+        Light::Directional(ref l) => {
+            let dir = l.world_space_direction.normalize();
+            let end = position + dir;
+            draw.line(position, end, c);
+
+            // The arrow head
+            let side = perpendicular(dir) * 0.15;
+            draw.line(end, end - dir * 0.25 + side, c);
+            draw.line(end, end - dir * 0.25 - side, c);
+        }
+        Light::Point(ref l) => {
+            draw.wire_sphere(l.world_space_position, l.range(), c);
+        }
+        Light::Spot(ref l) => {
+            let dir = l.world_space_direction.normalize();
+            let u = perpendicular(dir);
+            let v = dir.cross(u);
+
+            let angle = Rad::from(l.outer_angle).0;
+            let base = l.world_space_position + dir * l.range * angle.cos();
+            let radius = l.range * angle.sin();
+
+            let point = |i: usize| {
+                let a = i as f32 / CONE_SEGMENTS as f32 * 2.0 * ::std::f32::consts::PI;
+                base + (u * a.cos() + v * a.sin()) * radius
+            };
+
+            for i in 0..CONE_SEGMENTS {
+                draw.line(point(i), point(i + 1), c);
+                if i % 4 == 0 {
+                    draw.line(l.world_space_position, point(i), c);
+                }
+            }
+        }
+        Light::Area(ref l) => {
+            let p = &l.world_space_points;
+            for i in 0..4 {
+                draw.line(p[i], p[(i + 1) % 4], c);
+            }
+        }
+    }
+}
+
+pub fn bounds_gizmo(draw: &mut DebugDraw, aabb: &Aabb) {
+    draw.wire_box(aabb, color(BOUNDS_COLOR));
+}
+
+/// A unit vector perpendicular to the normalized `v`
+fn perpendicular(v: Vector3f) -> Vector3f {
+    let axis = if v.y.abs() < 0.99 {
+        Vector3f::unit_y()
+    } else {
+        Vector3f::unit_x()
+    };
+
+    v.cross(axis).normalize()
+}
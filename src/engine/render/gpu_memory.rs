@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// The estimated GPU memory of the textures and mesh buffers, see
+/// `Engine::gpu_memory`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GpuMemoryStats {
+    /// In bytes
+    pub used: usize,
+    pub budget: Option<usize>,
+    pub textures: usize,
+    pub mesh_buffers: usize,
+    /// Since the start
+    pub evictions: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GpuResourceKind {
+    Texture,
+    MeshBuffer,
+}
+
+struct Entry {
+    kind: GpuResourceKind,
+    bytes: usize,
+    last_used: u64,
+    /// Drops the gl state, None when it can not be created again
+    evict: Option<Box<Fn()>>,
+}
+
+#[derive(Default)]
+struct GpuMemory {
+    entries: HashMap<u64, Entry>,
+    next_id: u64,
+    frame: u64,
+    budget: Option<usize>,
+    evictions: usize,
+}
+
+thread_local!(static GPU_MEMORY: RefCell<GpuMemory> = RefCell::new(Default::default()));
+
+/// The memory of a gl state, counted until it is dropped
+#[derive(Debug)]
+pub struct GpuAllocation {
+    id: u64,
+}
+
+impl GpuAllocation {
+    pub fn new(kind: GpuResourceKind, bytes: usize) -> GpuAllocation {
+        GPU_MEMORY.with(|m| {
+            let mut m = m.borrow_mut();
+            let id = m.next_id;
+            m.next_id += 1;
+
+            let last_used = m.frame;
+            m.entries.insert(
+                id,
+                Entry {
+                    kind,
+                    bytes,
+                    last_used,
+                    evict: None,
+                },
+            );
+
+            GpuAllocation { id }
+        })
+    }
+
+    /// Let the budget evict the gl state with `f`, which drops it. It is
+    /// created again from the CPU side data when next used.
+    pub fn set_evict<F: Fn() + 'static>(&self, f: F) {
+        GPU_MEMORY.with(|m| {
+            if let Some(entry) = m.borrow_mut().entries.get_mut(&self.id) {
+                entry.evict = Some(Box::new(f));
+            }
+        });
+    }
+
+    /// Mark as used this frame
+    pub fn touch(&self) {
+        GPU_MEMORY.with(|m| {
+            let mut m = m.borrow_mut();
+            let frame = m.frame;
+            if let Some(entry) = m.entries.get_mut(&self.id) {
+                entry.last_used = frame;
+            }
+        });
+    }
+}
+
+impl Drop for GpuAllocation {
+    fn drop(&mut self) {
+        GPU_MEMORY.with(|m| {
+            m.borrow_mut().entries.remove(&self.id);
+        });
+    }
+}
+
+/// None for no budget, the default
+pub fn set_gpu_memory_budget(bytes: Option<usize>) {
+    GPU_MEMORY.with(|m| m.borrow_mut().budget = bytes);
+}
+
+pub fn gpu_memory_stats() -> GpuMemoryStats {
+    GPU_MEMORY.with(|m| {
+        let m = m.borrow();
+        let mut stats = GpuMemoryStats {
+            budget: m.budget,
+            evictions: m.evictions,
+            ..Default::default()
+        };
+
+        for entry in m.entries.values() {
+            stats.used += entry.bytes;
+            match entry.kind {
+                GpuResourceKind::Texture => stats.textures += 1,
+                GpuResourceKind::MeshBuffer => stats.mesh_buffers += 1,
+            }
+        }
+
+        stats
+    })
+}
+
+/// Over the budget, evict the least recently used resources not used during
+/// this frame, then start the next frame
+pub fn end_gpu_memory_frame() {
+    let evicts = GPU_MEMORY.with(|m| {
+        let mut m = m.borrow_mut();
+        let frame = m.frame;
+        m.frame += 1;
+
+        let budget = match m.budget {
+            Some(budget) => budget,
+            None => return Vec::new(),
+        };
+
+        let mut used: usize = m.entries.values().map(|e| e.bytes).sum();
+        if used <= budget {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<_> = m.entries
+            .iter()
+            .filter(|&(_, e)| e.evict.is_some() && e.last_used < frame)
+            .map(|(id, e)| (e.last_used, *id))
+            .collect();
+        candidates.sort();
+
+        let mut evicts = Vec::new();
+        for (_, id) in candidates {
+            if used <= budget {
+                break;
+            }
+
+            let entry = m.entries.get_mut(&id).unwrap();
+            used -= entry.bytes;
+            evicts.extend(entry.evict.take());
+        }
+
+        m.evictions += evicts.len();
+        evicts
+    });
+
+    // Dropping the gl states removes their entries
+    for evict in evicts {
+        evict();
+    }
+}
@@ -1,4 +1,5 @@
-use super::ShaderProgram;
+use super::uniforms::UniformTarget;
+use super::{ShaderProgram, Texture};
 use math::*;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -8,6 +9,8 @@ use unrust::engine::{Component, ComponentArena, IntoComponentPtr};
 pub enum Light {
     Directional(DirectionalLight),
     Point(PointLight),
+    Spot(SpotLight),
+    Area(AreaLight),
 }
 
 macro_rules! impl_light {
@@ -33,6 +36,8 @@ macro_rules! impl_light {
 impl Light {
     impl_light!(directional, directional_mut, Directional, DirectionalLight);
     impl_light!(point, point_mut, Point, PointLight);
+    impl_light!(spot, spot_mut, Spot, SpotLight);
+    impl_light!(area, area_mut, Area, AreaLight);
 
     pub fn new<T>(a: T) -> Light
     where
@@ -45,23 +50,100 @@ impl Light {
         match *self {
             Light::Directional(ref mut l) => l.update(model),
             Light::Point(ref mut l) => l.update(model),
+            Light::Spot(ref mut l) => l.update(model),
+            Light::Area(ref mut l) => l.update(model),
         }
     }
 
-    pub fn bind(&self, lightname: &str, prog: &ShaderProgram) {
+    /// Bit mask of the GameObject layers lit by this light
+    pub fn culling_mask(&self) -> u32 {
+        match *self {
+            Light::Directional(ref l) => l.culling_mask,
+            Light::Point(ref l) => l.culling_mask,
+            Light::Spot(ref l) => l.culling_mask,
+            Light::Area(ref l) => l.culling_mask,
+        }
+    }
+
+    pub fn affects_layer(&self, layer: u32) -> bool {
+        layer < 32 && self.culling_mask() & (1 << layer) != 0
+    }
+
+    /// Set the struct `lightname` of a program, or of the "Lights" uniform block
+    pub fn bind<U: UniformTarget>(&self, lightname: &str, prog: &U) {
         match *self {
             Light::Directional(ref l) => l.bind(lightname, prog),
             Light::Point(ref l) => l.bind(lightname, prog),
+            Light::Spot(ref l) => l.bind(lightname, prog),
+            Light::Area(ref l) => l.bind(lightname, prog),
         }
     }
 }
 
+/// Bias and filtering of the shadows cast by a light.
+/// Raise the biases against shadow acne, lower them against peter-panning.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowSettings {
+    /// Constant depth bias, in shadow map texels for directional lights
+    /// and in world units per unit of distance for point lights
+    pub bias: f32,
+    /// Depth bias added as the surface turns away from the light
+    pub slope_bias: f32,
+    /// Offset of the lookup position along the surface normal, in world units
+    pub normal_bias: f32,
+    /// Size of the shadow map, per cube face for point lights. Halved until
+    /// the map fits the max texture size of the context.
+    pub resolution: u32,
+    /// Filter over (2 * pcf_radius + 1)^2 texels, at most MAX_PCF_RADIUS (3) in the shaders
+    pub pcf_radius: u32,
+}
+
+impl ShadowSettings {
+    pub fn directional() -> ShadowSettings {
+        ShadowSettings {
+            bias: 0.25,
+            slope_bias: 1.5,
+            normal_bias: 0.02,
+            resolution: 2048,
+            pcf_radius: 1,
+        }
+    }
+
+    pub fn point() -> ShadowSettings {
+        ShadowSettings {
+            bias: 0.005,
+            slope_bias: 0.05,
+            normal_bias: 0.0,
+            resolution: 512,
+            pcf_radius: 1,
+        }
+    }
+
+    pub fn bind(&self, name: &str, prog: &ShaderProgram) {
+        prog.set(name.to_string() + ".bias", self.bias);
+        prog.set(name.to_string() + ".slope_bias", self.slope_bias);
+        prog.set(name.to_string() + ".normal_bias", self.normal_bias);
+        prog.set(name.to_string() + ".pcf_radius", self.pcf_radius as i32);
+    }
+}
+
 pub struct DirectionalLight {
     pub direction: Vector3<f32>,
     pub ambient: Vector3<f32>,
     pub diffuse: Vector3<f32>,
     pub specular: Vector3<f32>,
 
+    /// Render a depth map from this light and shadow the scene with it
+    pub cast_shadows: bool,
+    pub shadow: ShadowSettings,
+
+    /// Bit mask of the GameObject layers lit by this light
+    pub culling_mask: u32,
+
+    /// Texture projected along the light direction, tiled every `cookie_size` units
+    pub cookie: Option<Rc<Texture>>,
+    pub cookie_size: f32,
+
     pub world_space_direction: Vector3f,
 }
 
@@ -80,6 +162,14 @@ impl Default for DirectionalLight {
             diffuse: Vector3::new(1.0, 0.957, 0.839),
             specular: Vector3::new(1.0, 1.0, 1.0),
 
+            cast_shadows: false,
+            shadow: ShadowSettings::directional(),
+
+            culling_mask: !0,
+
+            cookie: None,
+            cookie_size: 10.0,
+
             world_space_direction: light_dir,
         }
     }
@@ -92,7 +182,7 @@ impl From<DirectionalLight> for Light {
 }
 
 impl DirectionalLight {
-    fn bind(&self, lightname: &str, prog: &ShaderProgram) {
+    fn bind<U: UniformTarget>(&self, lightname: &str, prog: &U) {
         prog.set(
             lightname.to_string() + ".direction",
             self.world_space_direction,
@@ -100,6 +190,25 @@ impl DirectionalLight {
         prog.set(lightname.to_string() + ".ambient", self.ambient);
         prog.set(lightname.to_string() + ".diffuse", self.diffuse);
         prog.set(lightname.to_string() + ".specular", self.specular);
+
+        prog.set(lightname.to_string() + ".has_cookie", self.cookie.is_some());
+        if self.cookie.is_some() {
+            prog.set(lightname.to_string() + ".cookie_matrix", self.cookie_matrix());
+        }
+    }
+
+    /// World space to cookie texture coordinates
+    pub fn cookie_matrix(&self) -> Matrix4f {
+        let dir = self.world_space_direction.normalize();
+        let mut up = Vector3::unit_y();
+
+        if up.dot(dir).abs() > 0.9999 {
+            up = Vector3::unit_z();
+        }
+
+        let view = Matrix4::look_at(Point3::new(0.0, 0.0, 0.0), Point3::from_vec(dir), up);
+
+        Matrix4::from_scale(1.0 / self.cookie_size) * view
     }
 
     fn update(&mut self, modelm: &Matrix4f) {
@@ -119,6 +228,13 @@ pub struct PointLight {
     pub linear: f32,
     pub quadratic: f32,
 
+    /// Render a cube depth map around this light and shadow the scene with it
+    pub cast_shadows: bool,
+    pub shadow: ShadowSettings,
+
+    /// Bit mask of the GameObject layers lit by this light
+    pub culling_mask: u32,
+
     pub world_space_position: Vector3f,
 }
 
@@ -139,12 +255,29 @@ impl Default for PointLight {
             constant: 1.0,
             linear: 0.022,
             quadratic: 0.0019,
+            cast_shadows: false,
+            shadow: ShadowSettings::point(),
+            culling_mask: !0,
         }
     }
 }
 
 impl PointLight {
-    fn bind(&self, lightname: &str, prog: &ShaderProgram) {
+    /// Distance where the attenuation drops the light under 1/256 of its intensity
+    pub fn range(&self) -> f32 {
+        let c = self.constant - 256.0;
+
+        if self.quadratic > 0.0 {
+            let det = self.linear * self.linear - 4.0 * self.quadratic * c;
+            (-self.linear + det.sqrt()) / (2.0 * self.quadratic)
+        } else if self.linear > 0.0 {
+            -c / self.linear
+        } else {
+            1000.0
+        }
+    }
+
+    fn bind<U: UniformTarget>(&self, lightname: &str, prog: &U) {
         prog.set(
             lightname.to_string() + ".position",
             self.world_space_position,
@@ -168,6 +301,197 @@ impl PointLight {
     }
 }
 
+pub struct SpotLight {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+
+    /// Half angle of the fully lit cone
+    pub inner_angle: Deg<f32>,
+    /// Half angle where the light fades out completely
+    pub outer_angle: Deg<f32>,
+    pub range: f32,
+
+    pub ambient: Vector3<f32>,
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>,
+
+    /// Bit mask of the GameObject layers lit by this light
+    pub culling_mask: u32,
+
+    /// Texture projected through the outer cone
+    pub cookie: Option<Rc<Texture>>,
+
+    pub world_space_position: Vector3f,
+    pub world_space_direction: Vector3f,
+}
+
+impl From<SpotLight> for Light {
+    fn from(w: SpotLight) -> Light {
+        Light::Spot(w)
+    }
+}
+
+impl Default for SpotLight {
+    fn default() -> SpotLight {
+        let direction = Vector3::new(0.0, -1.0, 0.0);
+
+        SpotLight {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            direction: direction,
+            inner_angle: Deg(12.5),
+            outer_angle: Deg(17.5),
+            range: 50.0,
+            ambient: Vector3::new(0.0, 0.0, 0.0),
+            diffuse: Vector3::new(1.0, 1.0, 1.0),
+            specular: Vector3::new(1.0, 1.0, 1.0),
+            culling_mask: !0,
+            cookie: None,
+            world_space_position: Vector3f::zero(),
+            world_space_direction: direction,
+        }
+    }
+}
+
+impl SpotLight {
+    fn bind<U: UniformTarget>(&self, lightname: &str, prog: &U) {
+        prog.set(
+            lightname.to_string() + ".position",
+            self.world_space_position,
+        );
+        prog.set(
+            lightname.to_string() + ".direction",
+            self.world_space_direction,
+        );
+
+        prog.set(lightname.to_string() + ".cut_off", self.inner_angle.cos());
+        prog.set(
+            lightname.to_string() + ".outer_cut_off",
+            self.outer_angle.cos(),
+        );
+        prog.set(lightname.to_string() + ".range", self.range);
+
+        prog.set(lightname.to_string() + ".ambient", self.ambient);
+        prog.set(lightname.to_string() + ".diffuse", self.diffuse);
+        prog.set(lightname.to_string() + ".specular", self.specular);
+
+        prog.set(lightname.to_string() + ".rate", 1.0);
+
+        prog.set(lightname.to_string() + ".has_cookie", self.cookie.is_some());
+        if self.cookie.is_some() {
+            prog.set(lightname.to_string() + ".cookie_matrix", self.cookie_matrix());
+        }
+    }
+
+    /// World space to the clip space of the light cone
+    pub fn cookie_matrix(&self) -> Matrix4f {
+        let dir = self.world_space_direction;
+        let mut up = Vector3::unit_y();
+
+        if up.dot(dir).abs() > 0.9999 {
+            up = Vector3::unit_z();
+        }
+
+        let pos = self.world_space_position;
+        let view = Matrix4::look_at(Point3::from_vec(pos), Point3::from_vec(pos + dir), up);
+
+        let proj: Matrix4f = PerspectiveFov {
+            fovy: (self.outer_angle * 2.0).into(),
+            aspect: 1.0,
+            near: 0.05,
+            far: self.range.max(0.1),
+        }.into();
+
+        proj * view
+    }
+
+    fn update(&mut self, modelm: &Matrix4f) {
+        self.world_space_position = modelm
+            .transform_point(Point3::from_vec(self.position))
+            .to_vec();
+
+        let m = modelm.inverse_transform().unwrap().transpose();
+        self.world_space_direction = m.transform_vector(self.direction).normalize();
+    }
+}
+
+/// A rectangle light lying in the local XY plane, centered at `position`
+/// and emitting along the local -Z axis.
+pub struct AreaLight {
+    pub position: Vector3<f32>,
+    pub width: f32,
+    pub height: f32,
+
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>,
+
+    /// Emit from both faces of the rectangle
+    pub two_sided: bool,
+
+    /// Bit mask of the GameObject layers lit by this light
+    pub culling_mask: u32,
+
+    pub world_space_points: [Vector3f; 4],
+}
+
+impl From<AreaLight> for Light {
+    fn from(w: AreaLight) -> Light {
+        Light::Area(w)
+    }
+}
+
+impl Default for AreaLight {
+    fn default() -> AreaLight {
+        AreaLight {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            width: 1.0,
+            height: 1.0,
+            diffuse: Vector3::new(1.0, 1.0, 1.0),
+            specular: Vector3::new(1.0, 1.0, 1.0),
+            two_sided: false,
+            culling_mask: !0,
+            world_space_points: [Vector3f::zero(); 4],
+        }
+    }
+}
+
+impl AreaLight {
+    pub fn world_space_center(&self) -> Vector3f {
+        let p = &self.world_space_points;
+        (p[0] + p[1] + p[2] + p[3]) * 0.25
+    }
+
+    fn bind<U: UniformTarget>(&self, lightname: &str, prog: &U) {
+        for (i, p) in self.world_space_points.iter().enumerate() {
+            prog.set(format!("{}.points[{}]", lightname, i), *p);
+        }
+
+        prog.set(lightname.to_string() + ".diffuse", self.diffuse);
+        prog.set(lightname.to_string() + ".specular", self.specular);
+        prog.set(lightname.to_string() + ".two_sided", self.two_sided);
+
+        prog.set(lightname.to_string() + ".rate", 1.0);
+    }
+
+    fn update(&mut self, modelm: &Matrix4f) {
+        let hw = self.width * 0.5;
+        let hh = self.height * 0.5;
+
+        // Counter-clockwise seen from +Z, the back side of the light.
+        let corners = [
+            Vector3::new(-hw, -hh, 0.0),
+            Vector3::new(hw, -hh, 0.0),
+            Vector3::new(hw, hh, 0.0),
+            Vector3::new(-hw, hh, 0.0),
+        ];
+
+        for (i, c) in corners.iter().enumerate() {
+            self.world_space_points[i] = modelm
+                .transform_point(Point3::from_vec(self.position + c))
+                .to_vec();
+        }
+    }
+}
+
 impl IntoComponentPtr for DirectionalLight {
     fn into_component_ptr(self, arena: &Rc<ComponentArena>) -> Arc<Component> {
         let light: Light = self.into();
@@ -181,3 +505,17 @@ impl IntoComponentPtr for PointLight {
         Component::new(light, arena)
     }
 }
+
+impl IntoComponentPtr for SpotLight {
+    fn into_component_ptr(self, arena: &Rc<ComponentArena>) -> Arc<Component> {
+        let light: Light = self.into();
+        Component::new(light, arena)
+    }
+}
+
+impl IntoComponentPtr for AreaLight {
+    fn into_component_ptr(self, arena: &Rc<ComponentArena>) -> Arc<Component> {
+        let light: Light = self.into();
+        Component::new(light, arena)
+    }
+}
@@ -0,0 +1,178 @@
+use engine::asset::AssetSystem;
+use engine::render::{Blend, Material, RenderTexture, ShaderProgram, TextureAttachment};
+use math::*;
+use std::rc::Rc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Light2DShape {
+    Point,
+    /// Lights along the local Y axis of the game object, the angles are the
+    /// full widths in degrees, fading from `inner_angle` to `outer_angle`
+    Cone { inner_angle: f32, outer_angle: f32 },
+}
+
+/// A light of the 2D pipeline, it lights the sprites around its game object
+/// in the XY plane for the cameras with `lighting_2d`. The 3D lights do not
+/// affect the sprites.
+#[derive(Component, Clone, Debug)]
+pub struct Light2D {
+    pub shape: Light2DShape,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    /// Distance in world units where the light fades out
+    pub radius: f32,
+    /// Exponent of the attenuation, 1 is linear
+    pub falloff: f32,
+    /// Distance of the light above the sprites, for their normal maps
+    pub height: f32,
+}
+
+impl Light2D {
+    pub fn new_point(color: Vector3<f32>, radius: f32) -> Light2D {
+        Light2D {
+            shape: Light2DShape::Point,
+            color,
+            intensity: 1.0,
+            radius,
+            falloff: 1.0,
+            height: 1.0,
+        }
+    }
+
+    pub fn new_cone(
+        color: Vector3<f32>,
+        radius: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Light2D {
+        Light2D {
+            shape: Light2DShape::Cone {
+                inner_angle,
+                outer_angle,
+            },
+            ..Light2D::new_point(color, radius)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Lighting2DSettings {
+    /// The light of the sprites out of reach of every `Light2D`
+    pub ambient: Vector3<f32>,
+    /// Render the normal maps of the sprites first, see `SpriteRenderer::normal_map`,
+    /// otherwise the sprites face the camera
+    pub normal_maps: bool,
+}
+
+impl Default for Lighting2DSettings {
+    fn default() -> Lighting2DSettings {
+        Lighting2DSettings {
+            ambient: Vector3::new(0.2, 0.2, 0.2),
+            normal_maps: false,
+        }
+    }
+}
+
+/// The screen space light texture of the 2D lights, the ambient light plus
+/// every `Light2D` added on top, multiplied over the sprites of the camera.
+/// The normal target holds the sprite normals the lights are shaded with.
+pub struct Lighting2DState {
+    pub light_rt: Rc<RenderTexture>,
+    pub normal_rt: Rc<RenderTexture>,
+
+    pub light_material: Rc<Material>,
+    pub normal_material: Rc<Material>,
+
+    /// Set by the engine for the pass the light texture is built for
+    pub enabled: bool,
+
+    size: (u32, u32),
+    viewport: ((i32, i32), (u32, u32)),
+}
+
+impl Lighting2DState {
+    pub fn new(db: &AssetSystem, size: (u32, u32)) -> Lighting2DState {
+        let mut light_material = Material::new(db.new_program("unrust/light2d"));
+        light_material.states.alpha_blending = Some(true);
+        light_material.states.blend = Some(Blend::additive());
+
+        Lighting2DState {
+            light_rt: Rc::new(RenderTexture::new(size.0, size.1, TextureAttachment::Color0)),
+            normal_rt: Rc::new(RenderTexture::new(size.0, size.1, TextureAttachment::Color0)),
+            light_material: Rc::new(light_material),
+            normal_material: Rc::new(Material::new(db.new_program("unrust/sprite_normal"))),
+            enabled: false,
+            size: size,
+            viewport: ((0, 0), size),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Update the light pass uniforms shared by the lights of a camera
+    pub fn setup(
+        &mut self,
+        settings: &Lighting2DSettings,
+        view_proj: &Matrix4f,
+        viewport: ((i32, i32), (u32, u32)),
+    ) {
+        let mat = &self.light_material;
+
+        mat.set("uNormalTexture", self.normal_rt.as_texture());
+        mat.set("uNormalMaps", settings.normal_maps);
+        mat.set(
+            "uInvViewProj",
+            view_proj.invert().unwrap_or(Matrix4f::identity()),
+        );
+        mat.set(
+            "uScreenSize",
+            Vector2f::new(self.size.0 as f32, self.size.1 as f32),
+        );
+
+        self.viewport = viewport;
+    }
+
+    /// Set the uniforms of `light` at `transform` before drawing it
+    pub fn setup_light(&self, light: &Light2D, transform: &Matrix4f) {
+        let mat = &self.light_material;
+        let position = transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+        let direction = transform
+            .transform_vector(Vector3::unit_y())
+            .truncate()
+            .normalize();
+
+        // The cosines of the half angles, a point light covers every direction
+        let cone = match light.shape {
+            Light2DShape::Point => Vector2f::new(-2.0, -1.0),
+            Light2DShape::Cone {
+                inner_angle,
+                outer_angle,
+            } => {
+                let outer = Deg(outer_angle.max(0.0) * 0.5).cos();
+                let inner = Deg(inner_angle.max(0.0).min(outer_angle) * 0.5).cos();
+                Vector2f::new(outer, inner.max(outer + 0.0001))
+            }
+        };
+
+        mat.set("uLightPosition", position.to_vec());
+        mat.set("uLightDirection", direction);
+        mat.set("uLightColor", light.color * light.intensity);
+        mat.set("uLightRadius", light.radius.max(0.0001));
+        mat.set("uLightFalloff", light.falloff);
+        mat.set("uLightHeight", light.height);
+        mat.set("uLightCone", cone);
+    }
+
+    /// Bind the uniforms, the texture goes through the engine texture units
+    pub fn bind(&self, prog: &ShaderProgram) {
+        let ((x, y), (w, h)) = self.viewport;
+
+        prog.set("uLight2DEnabled", true);
+        prog.set(
+            "uLight2DViewport",
+            Vector4::new(x as f32, y as f32, w as f32, h as f32),
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use engine::core::Component;
+use engine::render::{Light, ShaderProgram, Texture};
+use math::*;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Number of clusters along the screen width, height and view depth,
+/// should match CLUSTER_X, CLUSTER_Y and CLUSTER_Z in clustered_light.glsl
+pub const CLUSTER_X: usize = 16;
+pub const CLUSTER_Y: usize = 9;
+pub const CLUSTER_Z: usize = 24;
+
+/// Lights beyond these limits are dropped
+pub const MAX_CLUSTERED_LIGHTS: usize = 256;
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 32;
+
+// Each light is 5 vec4, one float per texel
+const LIGHT_TEXELS: usize = 20;
+// A count followed by 4 light indices per texel
+const CLUSTER_TEXELS: usize = 1 + MAX_LIGHTS_PER_CLUSTER / 4;
+
+/// Point lights binned into view space froxels (Forward+).
+///
+/// Both tables are stored in rgba8 textures read with texelFetch,
+/// so only GLSL 300 es shaders can use them:
+///
+/// * `light_texture`: one row per light, the raw bits of each float spread over a texel
+/// * `cluster_texture`: one row of CLUSTER_TEXELS per cluster, the light count in the
+///   first texel and the light indices in the following ones
+pub struct LightClusters {
+    pub light_texture: Rc<Texture>,
+    pub cluster_texture: Rc<Texture>,
+
+    /// Set by the engine for the pass the clusters are built for
+    pub enabled: bool,
+
+    bounds: Vec<Aabb>,
+    bounds_key: Option<(Matrix4f, f32, f32)>,
+
+    depth: Vector4<f32>,
+    viewport: ((i32, i32), (u32, u32)),
+}
+
+fn push_f32(data: &mut Vec<u8>, f: f32) {
+    push_u32(data, f.to_bits());
+}
+
+fn push_u32(data: &mut Vec<u8>, u: u32) {
+    data.push(u as u8);
+    data.push((u >> 8) as u8);
+    data.push((u >> 16) as u8);
+    data.push((u >> 24) as u8);
+}
+
+fn push_vec4(data: &mut Vec<u8>, v: Vector3f, w: f32) {
+    push_f32(data, v.x);
+    push_f32(data, v.y);
+    push_f32(data, v.z);
+    push_f32(data, w);
+}
+
+fn slice_depth(k: usize, near: f32, far: f32) -> f32 {
+    near * (far / near).powf(k as f32 / CLUSTER_Z as f32)
+}
+
+fn sphere_intersects(aabb: &Aabb, center: &Vector3f, r: f32) -> bool {
+    let mut d2 = 0.0;
+
+    for i in 0..3 {
+        let v = center[i];
+        if v < aabb.min[i] {
+            d2 += (aabb.min[i] - v) * (aabb.min[i] - v);
+        } else if v > aabb.max[i] {
+            d2 += (v - aabb.max[i]) * (v - aabb.max[i]);
+        }
+    }
+
+    d2 <= r * r
+}
+
+impl LightClusters {
+    pub fn new() -> LightClusters {
+        LightClusters {
+            light_texture: Texture::new_data_texture(
+                LIGHT_TEXELS as u32,
+                MAX_CLUSTERED_LIGHTS as u32,
+            ),
+            cluster_texture: Texture::new_data_texture(
+                (CLUSTER_TEXELS * CLUSTER_X) as u32,
+                (CLUSTER_Y * CLUSTER_Z) as u32,
+            ),
+            enabled: false,
+            bounds: Vec::new(),
+            bounds_key: None,
+            depth: Vector4::zero(),
+            viewport: ((0, 0), (0, 0)),
+        }
+    }
+
+    /// View space bounds of every cluster, indexed by (z * CLUSTER_Y + y) * CLUSTER_X + x
+    fn update_bounds(&mut self, proj: &Matrix4f, near: f32, far: f32) {
+        let key = Some((*proj, near, far));
+        if self.bounds_key == key {
+            return;
+        }
+
+        self.bounds.clear();
+
+        for z in 0..CLUSTER_Z {
+            let depths = [slice_depth(z, near, far), slice_depth(z + 1, near, far)];
+
+            for y in 0..CLUSTER_Y {
+                for x in 0..CLUSTER_X {
+                    let x0 = -1.0 + 2.0 * x as f32 / CLUSTER_X as f32;
+                    let x1 = -1.0 + 2.0 * (x + 1) as f32 / CLUSTER_X as f32;
+                    let y0 = -1.0 + 2.0 * y as f32 / CLUSTER_Y as f32;
+                    let y1 = -1.0 + 2.0 * (y + 1) as f32 / CLUSTER_Y as f32;
+
+                    let mut aabb = Aabb::empty();
+
+                    for &d in depths.iter() {
+                        for &(nx, ny) in [(x0, y0), (x1, y0), (x1, y1), (x0, y1)].iter() {
+                            // Right hand coordinate system, visible z are negative.
+                            aabb.merge_point(&Vector3f::new(
+                                nx * d / proj.x.x,
+                                ny * d / proj.y.y,
+                                -d,
+                            ));
+                        }
+                    }
+
+                    self.bounds.push(aabb);
+                }
+            }
+        }
+
+        self.bounds_key = key;
+    }
+
+    /// Rebuild both tables from the point lights in `lights`
+    #[cfg_attr(feature = "flame_it", flame)]
+    pub fn update(
+        &mut self,
+        view: &Matrix4f,
+        proj: &Matrix4f,
+        near: f32,
+        far: f32,
+        viewport: ((i32, i32), (u32, u32)),
+        lights: &[Arc<Component>],
+    ) {
+        self.update_bounds(proj, near, far);
+
+        let mut light_data = Vec::with_capacity(LIGHT_TEXELS * MAX_CLUSTERED_LIGHTS * 4);
+        let mut spheres = Vec::new();
+
+        for c in lights.iter().take(MAX_CLUSTERED_LIGHTS) {
+            let light = c.try_as::<Light>().unwrap().borrow();
+            let l = match light.point() {
+                Some(l) => l,
+                None => continue,
+            };
+
+            let range = l.range();
+
+            push_vec4(&mut light_data, l.world_space_position, range);
+            push_vec4(&mut light_data, l.ambient, l.constant);
+            push_vec4(&mut light_data, l.diffuse, l.linear);
+            push_vec4(&mut light_data, l.specular, l.quadratic);
+            push_u32(&mut light_data, l.culling_mask);
+            push_u32(&mut light_data, 0);
+            push_u32(&mut light_data, 0);
+            push_u32(&mut light_data, 0);
+
+            let center = view.transform_point(Point3::from_vec(l.world_space_position));
+            spheres.push((center.to_vec(), range));
+        }
+
+        light_data.resize(LIGHT_TEXELS * MAX_CLUSTERED_LIGHTS * 4, 0);
+
+        let width = CLUSTER_TEXELS * CLUSTER_X;
+        let mut cluster_data = vec![0u8; width * CLUSTER_Y * CLUSTER_Z * 4];
+        let log_scale = CLUSTER_Z as f32 / (far / near).ln();
+
+        for (i, &(center, r)) in spheres.iter().enumerate() {
+            let d_min = (-center.z - r).max(near);
+            let d_max = (-center.z + r).min(far);
+            if d_min > d_max {
+                continue;
+            }
+
+            let slice = |d: f32| (((d / near).ln() * log_scale) as usize).min(CLUSTER_Z - 1);
+
+            for z in slice(d_min)..slice(d_max) + 1 {
+                for y in 0..CLUSTER_Y {
+                    for x in 0..CLUSTER_X {
+                        let aabb = &self.bounds[(z * CLUSTER_Y + y) * CLUSTER_X + x];
+                        if !sphere_intersects(aabb, &center, r) {
+                            continue;
+                        }
+
+                        let row = (z * CLUSTER_Y + y) * width;
+                        let base = (row + x * CLUSTER_TEXELS) * 4;
+                        let count = cluster_data[base] as usize;
+
+                        if count >= MAX_LIGHTS_PER_CLUSTER {
+                            continue;
+                        }
+
+                        cluster_data[base + 4 + count] = i as u8;
+                        cluster_data[base] = (count + 1) as u8;
+                    }
+                }
+            }
+        }
+
+        self.light_texture.set_data(light_data);
+        self.cluster_texture.set_data(cluster_data);
+
+        self.depth = Vector4::new(near, far, log_scale, -near.ln() * log_scale);
+        self.viewport = viewport;
+        self.enabled = true;
+    }
+
+    /// Bind the uniforms, the textures go through the engine texture units
+    pub fn bind(&self, prog: &ShaderProgram, layer: u32) {
+        let ((x, y), (w, h)) = self.viewport;
+
+        prog.set("uClusteredEnabled", true);
+        prog.set("uClusterDepth", self.depth);
+        prog.set(
+            "uClusterViewport",
+            Vector4::new(x as f32, y as f32, w as f32, h as f32),
+        );
+        prog.set(
+            "uClusterLayer",
+            if layer < 32 { layer as i32 } else { -1 },
+        );
+    }
+}
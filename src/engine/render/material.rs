@@ -1,12 +1,32 @@
-use engine::asset::{Asset, AssetResult};
+use engine::asset::{Asset, AssetResult, AssetSystem};
 use engine::render::{RenderQueue, ShaderProgram, Texture};
 
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHasher};
 use math::*;
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
+thread_local!(
+    static LINEAR_NAMES: RefCell<FnvHashMap<Cow<'static, str>, &'static str>> =
+        RefCell::new(FnvHashMap::default())
+);
+
+/// The `<name>_linear` uniform of the texture param `name`, formatted once per
+/// name and kept for the following binds
+fn linear_name(name: &Cow<'static, str>) -> &'static str {
+    LINEAR_NAMES.with(|names| {
+        if let Some(linear) = names.borrow().get(&**name) {
+            return *linear;
+        }
+
+        let linear: &'static str = Box::leak(format!("{}_linear", name).into_boxed_str());
+        names.borrow_mut().insert(name.clone(), linear);
+        linear
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct TexturePtr(Rc<Texture>);
 
@@ -50,6 +70,29 @@ impl_from_material_param!(Vector4<f32>, Vec4);
 impl_from_material_param!(Matrix4<f32>, Matrix4);
 impl_from_material_param!(MaterialParamMap, Params);
 
+impl MaterialParam {
+    /// The value of the same type a uniform starts with, `default_tex` for
+    /// the textures
+    fn zero(&self, default_tex: &Rc<Texture>) -> MaterialParam {
+        match *self {
+            MaterialParam::Texture(_) => MaterialParam::Texture(TexturePtr(default_tex.clone())),
+            MaterialParam::Float(_) => MaterialParam::Float(0.0),
+            MaterialParam::Int(_) => MaterialParam::Int(0),
+            MaterialParam::Bool(_) => MaterialParam::Bool(false),
+            MaterialParam::Vec2(_) => MaterialParam::Vec2(Vector2::zero()),
+            MaterialParam::Vec3(_) => MaterialParam::Vec3(Vector3::zero()),
+            MaterialParam::Vec4(_) => MaterialParam::Vec4(Vector4::zero()),
+            MaterialParam::Matrix4(_) => MaterialParam::Matrix4(Matrix4::zero()),
+            MaterialParam::Params(ref params) => MaterialParam::Params(
+                params
+                    .iter()
+                    .map(|(name, p)| (name.clone(), p.zero(default_tex)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
 impl From<Rc<Texture>> for MaterialParam {
     fn from(b: Rc<Texture>) -> MaterialParam {
         MaterialParam::Texture(TexturePtr(b))
@@ -83,12 +126,198 @@ impl Default for DepthTest {
     }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BlendEquation {
+    Add,
+    Subtract,
+    ReverseSubtract,
+}
+
+/// How a fragment is blended: `equation(src * color, dst * framebuffer)`
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Blend {
+    pub equation: BlendEquation,
+    pub src: BlendFactor,
+    pub dst: BlendFactor,
+}
+
+impl Blend {
+    pub fn additive() -> Blend {
+        Blend {
+            equation: BlendEquation::Add,
+            src: BlendFactor::One,
+            dst: BlendFactor::One,
+        }
+    }
+
+    pub fn premultiplied() -> Blend {
+        Blend {
+            equation: BlendEquation::Add,
+            src: BlendFactor::One,
+            dst: BlendFactor::OneMinusSrcAlpha,
+        }
+    }
+}
+
+impl Default for Blend {
+    fn default() -> Blend {
+        Blend {
+            equation: BlendEquation::Add,
+            src: BlendFactor::SrcAlpha,
+            dst: BlendFactor::OneMinusSrcAlpha,
+        }
+    }
+}
+
+/// The render states of a draw, `None` keeps the value of the render queue
 #[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
 pub struct MaterialState {
     pub cull: Option<CullMode>,
     pub alpha_blending: Option<bool>,
+    /// Used when `alpha_blending` is on
+    pub blend: Option<Blend>,
     pub depth_write: Option<bool>,
     pub depth_test: Option<DepthTest>,
+    /// Bit mask of the color outputs written when the target has several,
+    /// bit N is `layout(location = N)` in a GLSL 300 es fragment shader
+    pub draw_buffers: Option<u32>,
+}
+
+fn bind_params<F>(
+    prog: &ShaderProgram,
+    params: &MaterialParamMap,
+    request_tex_unit: &mut F,
+    level: u32,
+) -> AssetResult<()>
+where
+    F: FnMut(&Rc<Texture>) -> AssetResult<u32>,
+{
+    for (name, param) in params.iter() {
+        match param {
+            &MaterialParam::Texture(ref tex) => {
+                let new_unit = request_tex_unit(&tex.0)?;
+                prog.set(name.clone(), (Rc::downgrade(&tex.0), new_unit));
+                prog.set(linear_name(name), !tex.0.is_srgb());
+            }
+            &MaterialParam::Bool(v) => {
+                prog.set(name.clone(), v);
+            }
+            &MaterialParam::Float(f) => {
+                prog.set(name.clone(), f);
+            }
+            &MaterialParam::Int(v) => {
+                prog.set(name.clone(), v);
+            }
+            &MaterialParam::Vec2(v) => {
+                prog.set(name.clone(), v);
+            }
+            &MaterialParam::Vec3(v) => {
+                prog.set(name.clone(), v);
+            }
+            &MaterialParam::Vec4(v) => {
+                prog.set(name.clone(), v);
+            }
+            &MaterialParam::Matrix4(v) => {
+                prog.set(name.clone(), v);
+            }
+            &MaterialParam::Params(ref pm) => {
+                bind_params(prog, &pm, request_tex_unit, level + 1)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Params overriding those of a material for a single draw, so objects can
+/// share one `Material` (and its program and textures) with different values.
+///
+/// After the draw the params the material has are bound again, the others
+/// are reset to zero, see `reset`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialPropertyBlock {
+    params: MaterialParamMap,
+}
+
+impl MaterialPropertyBlock {
+    pub fn new() -> MaterialPropertyBlock {
+        Default::default()
+    }
+
+    pub fn set<T, S>(&mut self, name: S, t: T)
+    where
+        T: Into<MaterialParam>,
+        S: Into<Cow<'static, str>>,
+    {
+        self.params.insert(name.into(), t.into());
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.params.contains_key(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.params.clear();
+    }
+
+    pub fn bind<F>(&self, prog: &ShaderProgram, mut request_tex_unit: F) -> AssetResult<()>
+    where
+        F: FnMut(&Rc<Texture>) -> AssetResult<u32>,
+    {
+        bind_params(prog, &self.params, &mut request_tex_unit, 0)
+    }
+
+    /// Undo `bind` for the following draws with the same program: the params
+    /// `material` doesn't have are set to zero, and the textures to `default_tex`.
+    /// The params of `material` are bound again with it.
+    pub fn reset<F>(
+        &self,
+        prog: &ShaderProgram,
+        material: &Material,
+        default_tex: &Rc<Texture>,
+        mut request_tex_unit: F,
+    ) -> AssetResult<()>
+    where
+        F: FnMut(&Rc<Texture>) -> AssetResult<u32>,
+    {
+        let reset: MaterialParamMap = self.params
+            .iter()
+            .filter(|&(name, _)| !material.has(name))
+            .map(|(name, param)| (name.clone(), param.zero(default_tex)))
+            .collect();
+
+        bind_params(prog, &reset, &mut request_tex_unit, 0)
+    }
+}
+
+fn collect_textures(params: &MaterialParamMap, out: &mut Vec<usize>) {
+    for param in params.values() {
+        match *param {
+            MaterialParam::Texture(TexturePtr(ref tex)) => {
+                out.push(&**tex as *const Texture as usize)
+            }
+            MaterialParam::Params(ref params) => collect_textures(params, out),
+            _ => (),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -96,15 +325,19 @@ pub struct Material {
     pub program: Rc<ShaderProgram>,
     pub render_queue: RenderQueue,
     pub states: MaterialState,
+    /// Whether the scene fog of the engine is applied, see `Fog`
+    pub fog: bool,
 
     params: RefCell<MaterialParamMap>,
+    keywords: RefCell<Vec<String>>,
 }
 
 impl PartialEq for Material {
     fn eq(&self, other: &Self) -> bool {
         Rc::ptr_eq(&self.program, &other.program) && self.render_queue == other.render_queue
-            && self.states == other.states
+            && self.states == other.states && self.fog == other.fog
             && *self.params.borrow() == *other.params.borrow()
+            && *self.keywords.borrow() == *other.keywords.borrow()
     }
 }
 
@@ -114,10 +347,61 @@ impl Material {
             render_queue: RenderQueue::Opaque,
             program: program,
             params: RefCell::new(FnvHashMap::default()),
+            keywords: RefCell::new(Vec::new()),
             states: MaterialState::default(),
+            fog: true,
         };
     }
 
+    /// The built-in metallic-roughness material ("unrust/pbr"), with every slot
+    /// set to a neutral default. Its parameters follow the glTF 2.0 conventions:
+    ///
+    /// * `uMaterial.albedo` (vec4) and `uMaterial.albedo_map`
+    /// * `uMaterial.metallic`, `uMaterial.roughness` and `uMaterial.metallic_roughness_map`,
+    ///   roughness in the green channel and metallic in the blue one
+    /// * `uMaterial.normal_map` and `uMaterial.normal_scale`
+    /// * `uMaterial.occlusion_map` (red channel) and `uMaterial.occlusion_strength`
+    /// * `uMaterial.emissive` (vec3) and `uMaterial.emissive_map`
+    ///
+    /// Its program is GLSL 300 es, on WebGL1 this is the `new_phong` material
+    /// of a white texture instead.
+    pub fn new_pbr(db: &AssetSystem) -> Material {
+        if !db.capabilities().webgl2 {
+            return Material::new_phong(db, db.new_texture("default_white"));
+        }
+
+        let material = Material::new(db.new_program("unrust/pbr"));
+
+        material.set("uMaterial.albedo", Vector4::new(1.0, 1.0, 1.0, 1.0));
+        material.set("uMaterial.albedo_map", db.new_texture("default_white"));
+        material.set("uMaterial.metallic", 0.0);
+        material.set("uMaterial.roughness", 0.5);
+        material.set(
+            "uMaterial.metallic_roughness_map",
+            db.new_texture("default_white"),
+        );
+        material.set("uMaterial.normal_map", db.new_texture("default_normal_map"));
+        material.set("uMaterial.normal_scale", 1.0);
+        material.set("uMaterial.occlusion_map", db.new_texture("default_white"));
+        material.set("uMaterial.occlusion_strength", 1.0);
+        material.set("uMaterial.emissive", Vector3f::zero());
+        material.set("uMaterial.emissive_map", db.new_texture("default_white"));
+
+        material
+    }
+
+    /// The material of the "default" phong program, which the built-in materials
+    /// fall back to on WebGL1. `uMaterial.diffuse` is `diffuse`, with a shininess
+    /// of 32 in `uMaterial.shininess`.
+    pub fn new_phong(db: &AssetSystem, diffuse: Rc<Texture>) -> Material {
+        let material = Material::new(db.new_program("default"));
+
+        material.set("uMaterial.diffuse", diffuse);
+        material.set("uMaterial.shininess", 32.0);
+
+        material
+    }
+
     pub fn set<T, S>(&self, name: S, t: T)
     where
         T: Into<MaterialParam>,
@@ -126,57 +410,61 @@ impl Material {
         self.params.borrow_mut().insert(name.into(), t.into());
     }
 
-    fn bind_params<F>(
-        &self,
-        params: &MaterialParamMap,
-        request_tex_unit: &mut F,
-        level: u32,
-    ) -> AssetResult<()>
-    where
-        F: FnMut(&Rc<Texture>) -> AssetResult<u32>,
-    {
-        for (name, param) in params.iter() {
-            match param {
-                &MaterialParam::Texture(ref tex) => {
-                    let new_unit = request_tex_unit(&tex.0)?;
-                    self.program
-                        .set(name.clone(), (Rc::downgrade(&tex.0), new_unit));
-                }
-                &MaterialParam::Bool(v) => {
-                    self.program.set(name.clone(), v);
-                }
-                &MaterialParam::Float(f) => {
-                    self.program.set(name.clone(), f);
-                }
-                &MaterialParam::Int(v) => {
-                    self.program.set(name.clone(), v);
-                }
-                &MaterialParam::Vec2(v) => {
-                    self.program.set(name.clone(), v);
-                }
-                &MaterialParam::Vec3(v) => {
-                    self.program.set(name.clone(), v);
-                }
-                &MaterialParam::Vec4(v) => {
-                    self.program.set(name.clone(), v);
-                }
-                &MaterialParam::Matrix4(v) => {
-                    self.program.set(name.clone(), v);
-                }
-                &MaterialParam::Params(ref pm) => {
-                    self.bind_params(&pm, request_tex_unit, level + 1)?;
-                }
-            }
+    /// Discard the fragments with an alpha below `threshold` ("uAlphaCutoff"),
+    /// drawn in the `AlphaTest` queue so they still write depth
+    pub fn set_cutout(&mut self, threshold: f32) {
+        self.render_queue = RenderQueue::AlphaTest;
+        self.set("uAlphaCutoff", threshold);
+    }
+
+    /// Define `keyword` (e.g. "NORMAL_MAP") when compiling the program of this material,
+    /// each set of keywords is a separate permutation of the program
+    pub fn enable_keyword<S: Into<String>>(&self, keyword: S) {
+        let keyword = keyword.into();
+        let mut keywords = self.keywords.borrow_mut();
+
+        if let Err(pos) = keywords.binary_search(&keyword) {
+            keywords.insert(pos, keyword);
         }
+    }
 
-        Ok(())
+    pub fn disable_keyword(&self, keyword: &str) {
+        self.keywords.borrow_mut().retain(|k| k != keyword);
+    }
+
+    /// The enabled keywords, sorted
+    pub fn keywords(&self) -> Vec<String> {
+        self.keywords.borrow().clone()
+    }
+
+    /// Keys of the (program variant, texture set) of the material, sorting the
+    /// draws by them saves program and texture switches
+    pub fn state_keys(&self) -> (u64, u64) {
+        let mut hasher = FnvHasher::default();
+        (&*self.program as *const ShaderProgram as usize).hash(&mut hasher);
+        self.keywords.borrow().hash(&mut hasher);
+        let program = hasher.finish();
+
+        let mut textures = Vec::new();
+        collect_textures(&self.params.borrow(), &mut textures);
+        textures.sort();
+
+        let mut hasher = FnvHasher::default();
+        textures.hash(&mut hasher);
+
+        (program, hasher.finish())
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.params.borrow().contains_key(name)
     }
 
-    pub fn bind<F>(&self, mut request_tex_unit: F) -> AssetResult<()>
+    /// Set the params on `prog`, the program of this material or one of its permutations
+    pub fn bind<F>(&self, prog: &ShaderProgram, mut request_tex_unit: F) -> AssetResult<()>
     where
         F: FnMut(&Rc<Texture>) -> AssetResult<u32>,
     {
-        self.bind_params(&self.params.borrow(), &mut request_tex_unit, 0)?;
+        bind_params(prog, &self.params.borrow(), &mut request_tex_unit, 0)?;
 
         Ok(())
     }
@@ -1,11 +1,13 @@
-use engine::core::Aabb;
-use engine::render::{Material, MeshBuffer};
+use engine::core::{Aabb, GameObject};
+use engine::render::{Material, MaterialPropertyBlock, MeshBuffer};
+use math::*;
 use std::cell::Cell;
 use std::rc::Rc;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct MeshBound {
     pub aabb: Aabb,
+    /// Radius of the sphere around the origin enclosing the mesh
     pub r: f32,
 }
 
@@ -25,24 +27,59 @@ impl MeshBound {
         self.aabb.merge(&other.aabb);
         self.r = self.r.max(other.r);
     }
+
+    /// The (center, radius) sphere enclosing the aabb
+    pub fn sphere(&self) -> (Vector3f, f32) {
+        self.aabb.sphere()
+    }
+
+    /// The bounds in the space `m` transforms to, e.g. world space with
+    /// the global matrix of the game object
+    pub fn transform(&self, m: &Matrix4f) -> MeshBound {
+        let mut aabb = Aabb::empty();
+        for c in self.aabb.corners().iter() {
+            aabb.merge_point(&m.transform_point(Point3::from_vec(*c)).to_vec());
+        }
+
+        let scale = m.x
+            .truncate()
+            .magnitude()
+            .max(m.y.truncate().magnitude())
+            .max(m.z.truncate().magnitude());
+
+        MeshBound {
+            aabb,
+            r: self.r * scale,
+        }
+    }
 }
 
 pub struct MeshSurface {
     pub buffer: Rc<MeshBuffer>,
     pub material: Rc<Material>,
+    /// Overrides the material (and mesh) params for this surface
+    pub properties: Option<MaterialPropertyBlock>,
 }
 
 #[derive(Component, Clone)]
 pub struct Mesh {
     pub surfaces: Vec<Rc<MeshSurface>>,
-    pub mesh_bounds: Cell<Option<MeshBound>>,
+    /// Overrides the material params for every surface
+    pub properties: Option<Rc<MaterialPropertyBlock>>,
+    /// Set when the mesh is drawn as part of a static batch
+    pub static_batched: Cell<bool>,
+    /// Large opaque mesh hiding the others when the camera does occlusion culling,
+    /// its triangles are rasterized on the CPU so keep it simple
+    pub occluder: bool,
 }
 
 impl Mesh {
     pub fn new() -> Mesh {
         Mesh {
             surfaces: Vec::new(),
-            mesh_bounds: Cell::new(None),
+            properties: None,
+            static_batched: Cell::new(false),
+            occluder: false,
         }
     }
 
@@ -54,6 +91,23 @@ impl Mesh {
         self.surfaces.push(Rc::new(MeshSurface {
             buffer: buffer.into(),
             material: material.into(),
+            properties: None,
+        }));
+    }
+
+    pub fn add_surface_with_properties<U, T>(
+        &mut self,
+        buffer: U,
+        material: T,
+        properties: MaterialPropertyBlock,
+    ) where
+        U: Into<Rc<MeshBuffer>>,
+        T: Into<Rc<Material>>,
+    {
+        self.surfaces.push(Rc::new(MeshSurface {
+            buffer: buffer.into(),
+            material: material.into(),
+            properties: Some(properties),
         }));
     }
 
@@ -62,12 +116,9 @@ impl Mesh {
             .retain(|surface| !Rc::ptr_eq(buffer, &surface.buffer))
     }
 
-    /// bounds return (vmin, vmax)
+    /// The local bounds of all the surfaces, None until they are all loaded.
+    /// Not cached, as the buffers keep their own up to date.
     pub fn bounds(&self) -> Option<MeshBound> {
-        if let Some(_) = self.mesh_bounds.get() {
-            return self.mesh_bounds.get();
-        }
-
         if self.surfaces
             .iter()
             .find(|s| s.buffer.bounds().is_none())
@@ -82,7 +133,42 @@ impl Mesh {
             acc
         });
 
-        self.mesh_bounds.set(Some(bb));
-        self.mesh_bounds.get()
+        Some(bb)
+    }
+
+    /// The bounds transformed by the global matrix of the game object
+    pub fn world_bounds(&self, object: &GameObject) -> Option<MeshBound> {
+        self.bounds().map(|b| b.transform(&object.transform.as_global_matrix()))
+    }
+}
+
+/// Draws one surface once per instance, in a single instanced draw call
+/// where the context supports it and the shader reads `aInstanceModel`
+/// under `#ifdef INSTANCING`, see `unrust/default_uniforms.glsl`
+#[derive(Component, Clone)]
+pub struct InstancedMesh {
+    pub surface: Rc<MeshSurface>,
+    /// Model matrix of each instance, relative to the game object
+    pub instances: Vec<Matrix4f>,
+}
+
+impl InstancedMesh {
+    pub fn new<U, T>(buffer: U, material: T) -> InstancedMesh
+    where
+        U: Into<Rc<MeshBuffer>>,
+        T: Into<Rc<Material>>,
+    {
+        InstancedMesh {
+            surface: Rc::new(MeshSurface {
+                buffer: buffer.into(),
+                material: material.into(),
+                properties: None,
+            }),
+            instances: Vec::new(),
+        }
+    }
+
+    pub fn add_instance(&mut self, m: Matrix4f) {
+        self.instances.push(m);
     }
 }
@@ -2,6 +2,10 @@ use std::mem::size_of;
 use uni_gl::*;
 
 use super::ShaderProgram;
+use engine::render::backend::{BufferTarget, BufferUsage, DrawTopology, RenderBackend,
+                              VertexFormat};
+use engine::render::context_loss::context_generation;
+use engine::render::gpu_memory::{GpuAllocation, GpuResourceKind};
 use engine::asset::{Asset, AssetResult, AssetSystem, FileFuture, LoadableAsset, Resource};
 use engine::core::Aabb;
 use engine::render::mesh::MeshBound;
@@ -9,8 +13,9 @@ use engine::render::shader_program::ShaderAttrib;
 
 use math::*;
 use std::cell::Cell;
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell};
 use std::f32::{MAX, MIN};
+use std::ops::Range;
 use std::rc::Rc;
 use std::rc::Weak;
 
@@ -47,9 +52,16 @@ struct MeshGLState {
     pub btb: Option<WebGLBuffer>,
 
     pub ib: WebGLBuffer,
+    /// Per instance model matrices, created on the first instanced draw
+    pub instance_b: Option<WebGLBuffer>,
     pub gl: WebGLRenderingContext,
+    /// The `context_generation` the buffers were created in
+    pub context: u32,
+    pub memory: GpuAllocation,
 
     pub rebind_actions: Vec<RebindAction>,
+    /// Vertex or index ranges to upload into the existing buffers
+    pub range_actions: Vec<(RebindAction, Range<usize>)>,
 }
 
 impl MeshGLState {
@@ -57,66 +69,128 @@ impl MeshGLState {
         &mut self,
         data: &MeshData,
         tt: &RebindAction,
-    ) -> (BufferKind, Vec<u8>, &mut WebGLBuffer) {
+    ) -> (BufferTarget, Vec<u8>, &mut WebGLBuffer) {
         match *tt {
             RebindAction::Vertices => (
-                BufferKind::Array,
+                BufferTarget::Vertex,
                 data.vertices.clone().into_bytes(),
                 &mut self.vb,
             ),
             RebindAction::UV => (
-                BufferKind::Array,
+                BufferTarget::Vertex,
                 data.uvs.clone().unwrap().into_bytes(),
                 self.uvb.as_mut().unwrap(),
             ),
             RebindAction::Normal => (
-                BufferKind::Array,
+                BufferTarget::Vertex,
                 data.normals.clone().unwrap().into_bytes(),
                 self.nb.as_mut().unwrap(),
             ),
             RebindAction::Tangent => (
-                BufferKind::Array,
+                BufferTarget::Vertex,
                 data.tangents.clone().unwrap().into_bytes(),
                 self.tb.as_mut().unwrap(),
             ),
             RebindAction::Bitangent => (
-                BufferKind::Array,
+                BufferTarget::Vertex,
                 data.bitangents.clone().unwrap().into_bytes(),
                 self.btb.as_mut().unwrap(),
             ),
             RebindAction::Indices => (
-                BufferKind::ElementArray,
+                BufferTarget::Index,
                 data.indices.clone().into_bytes(),
                 &mut self.ib,
             ),
         }
     }
 
-    fn rebind(&mut self, actions: &Vec<RebindAction>, data: &MeshData, gl: &WebGLRenderingContext) {
+    fn rebind(
+        &mut self,
+        actions: &Vec<RebindAction>,
+        data: &MeshData,
+        streaming: bool,
+        gl: &WebGLRenderingContext,
+    ) {
         for action in actions.iter() {
             let (k, p, buf) = self.rebind_buffer(data, action);
 
-            gl.bind_buffer(k, &buf);
-            gl.buffer_data(k, &p, DrawMode::Static);
-            gl.unbind_buffer(k);
+            let usage = if streaming {
+                BufferUsage::Stream
+            } else {
+                BufferUsage::Static
+            };
+
+            gl.upload_buffer(buf, k, &p, usage);
         }
     }
 }
 
+impl MeshGLState {
+    fn update_range(
+        &self,
+        data: &MeshData,
+        tt: &RebindAction,
+        range: &Range<usize>,
+        gl: &WebGLRenderingContext,
+    ) {
+        let (k, p, offset, buf) = match *tt {
+            RebindAction::Indices => (
+                BufferTarget::Index,
+                data.indices[range.clone()].to_vec().into_bytes(),
+                range.start * size_of::<u16>(),
+                &self.ib,
+            ),
+            _ => {
+                let (n, values, buf) = match *tt {
+                    RebindAction::Vertices => (3, &data.vertices, &self.vb),
+                    RebindAction::UV => (2, data.uvs.as_ref().unwrap(), self.uvb.as_ref().unwrap()),
+                    RebindAction::Normal => {
+                        (3, data.normals.as_ref().unwrap(), self.nb.as_ref().unwrap())
+                    }
+                    RebindAction::Tangent => {
+                        (3, data.tangents.as_ref().unwrap(), self.tb.as_ref().unwrap())
+                    }
+                    RebindAction::Bitangent => (
+                        3,
+                        data.bitangents.as_ref().unwrap(),
+                        self.btb.as_ref().unwrap(),
+                    ),
+                    RebindAction::Indices => unreachable!(),
+                };
+
+                (
+                    BufferTarget::Vertex,
+                    values[range.start * n..range.end * n].to_vec().into_bytes(),
+                    range.start * n * size_of::<f32>(),
+                    buf,
+                )
+            }
+        };
+
+        gl.update_buffer(buf, k, offset, &p);
+    }
+}
+
 impl Drop for MeshGLState {
     fn drop(&mut self) {
-        self.gl.delete_buffer(&self.vb);
-        self.uvb.as_ref().map(|b| self.gl.delete_buffer(&b));
-        self.nb.as_ref().map(|b| self.gl.delete_buffer(&b));
-        self.tb.as_ref().map(|b| self.gl.delete_buffer(&b));
-        self.btb.as_ref().map(|b| self.gl.delete_buffer(&b));
-        self.gl.delete_buffer(&self.ib);
+        // The handles went away with the lost context
+        if self.context != context_generation() {
+            return;
+        }
 
-        self.gl.delete_vertex_array(&self.vao);
+        self.gl.destroy_buffer(&self.vb);
+        self.uvb.as_ref().map(|b| self.gl.destroy_buffer(&b));
+        self.nb.as_ref().map(|b| self.gl.destroy_buffer(&b));
+        self.tb.as_ref().map(|b| self.gl.destroy_buffer(&b));
+        self.btb.as_ref().map(|b| self.gl.destroy_buffer(&b));
+        self.gl.destroy_buffer(&self.ib);
+        self.instance_b.as_ref().map(|b| self.gl.destroy_buffer(&b));
+
+        self.gl.destroy_vertex_layout(&self.vao);
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct MeshData {
     pub vertices: Vec<f32>,
     pub uvs: Option<Vec<f32>>,
@@ -156,12 +230,187 @@ impl MeshData {
             *v += disp[i % 3];
         }
     }
+
+    /// Which optional attributes (uvs, normals, tangents, bitangents) the mesh has,
+    /// only meshes with the same layout can be merged
+    pub fn layout(&self) -> [bool; 4] {
+        [
+            self.uvs.is_some(),
+            self.normals.is_some(),
+            self.tangents.is_some(),
+            self.bitangents.is_some(),
+        ]
+    }
+
+    /// An empty mesh with the attributes of `layout`
+    pub fn with_layout(layout: [bool; 4]) -> MeshData {
+        let attr = |b: bool| if b { Some(Vec::new()) } else { None };
+
+        MeshData {
+            vertices: Vec::new(),
+            uvs: attr(layout[0]),
+            normals: attr(layout[1]),
+            tangents: attr(layout[2]),
+            bitangents: attr(layout[3]),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Append `data` transformed by `m`, both meshes must have the same layout
+    pub fn append_transformed(&mut self, data: &MeshData, m: &Matrix4f) {
+        let base = (self.vertices.len() / 3) as u16;
+
+        let m3 = Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate());
+        let nm = m3.invert().unwrap_or(m3).transpose();
+
+        for v in data.vertices.chunks(3) {
+            let p = m.transform_point(Point3::new(v[0], v[1], v[2]));
+            self.vertices.extend_from_slice(&[p.x, p.y, p.z]);
+        }
+
+        if let (&mut Some(ref mut uvs), &Some(ref data)) = (&mut self.uvs, &data.uvs) {
+            uvs.extend_from_slice(data);
+        }
+
+        append_directions(&mut self.normals, &data.normals, &nm);
+        append_directions(&mut self.tangents, &data.tangents, &m3);
+        append_directions(&mut self.bitangents, &data.bitangents, &m3);
+
+        self.indices.extend(data.indices.iter().map(|i| i + base));
+    }
+
+    /// Generate the tangents and bitangents from the uvs and normals, the
+    /// tangent of a vertex is the angle weighted average of its triangles ones
+    /// orthogonalized against the normal. Returns false without uvs or normals.
+    pub fn compute_tangents(&mut self) -> bool {
+        let (tangents, bitangents) = match (self.uvs.as_ref(), self.normals.as_ref()) {
+            (Some(uvs), Some(normals)) => {
+                tangent_space(&self.vertices, uvs, normals, &self.indices)
+            }
+            _ => return false,
+        };
+
+        self.tangents = Some(tangents);
+        self.bitangents = Some(bitangents);
+        true
+    }
+}
+
+#[inline]
+fn from_slice_v3(i: usize, s: &[f32]) -> Vector3f {
+    Vector3::new(s[i * 3], s[i * 3 + 1], s[i * 3 + 2])
+}
+
+#[inline]
+fn from_slice_v2(i: usize, s: &[f32]) -> Vector2f {
+    Vector2::new(s[i * 2], s[i * 2 + 1])
+}
+
+fn tangent_space(
+    vertices: &[f32],
+    uvs: &[f32],
+    normals: &[f32],
+    indices: &[u16],
+) -> (Vec<f32>, Vec<f32>) {
+    let count = vertices.len() / 3;
+    let mut tan_sum = vec![Vector3f::zero(); count];
+    let mut bitan_sum = vec![Vector3f::zero(); count];
+
+    for tri in indices.chunks(3).filter(|tri| tri.len() == 3) {
+        let ids = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+        let p = [
+            from_slice_v3(ids[0], vertices),
+            from_slice_v3(ids[1], vertices),
+            from_slice_v3(ids[2], vertices),
+        ];
+        let uv = [
+            from_slice_v2(ids[0], uvs),
+            from_slice_v2(ids[1], uvs),
+            from_slice_v2(ids[2], uvs),
+        ];
+
+        let edge1 = p[1] - p[0];
+        let edge2 = p[2] - p[0];
+        let delta_uv1 = uv[1] - uv[0];
+        let delta_uv2 = uv[2] - uv[0];
+
+        let d = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if d.abs() < 1e-8 {
+            // Degenerated uvs, the other triangles decide
+            continue;
+        }
+
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / d;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) / d;
+
+        if tangent.magnitude2() < 1e-12 || bitangent.magnitude2() < 1e-12 {
+            continue;
+        }
+
+        let tangent = tangent.normalize();
+        let bitangent = bitangent.normalize();
+
+        for k in 0..3 {
+            let a = p[(k + 1) % 3] - p[k];
+            let b = p[(k + 2) % 3] - p[k];
+            let len = a.magnitude() * b.magnitude();
+            if len < 1e-12 {
+                continue;
+            }
+
+            let angle = (a.dot(b) / len).max(-1.0).min(1.0).acos();
+            tan_sum[ids[k]] += tangent * angle;
+            bitan_sum[ids[k]] += bitangent * angle;
+        }
+    }
+
+    let mut tangents = Vec::with_capacity(count * 3);
+    let mut bitangents = Vec::with_capacity(count * 3);
+
+    for i in 0..count {
+        let n = from_slice_v3(i, normals);
+
+        // Gram-Schmidt orthogonalize
+        let mut t = tan_sum[i] - n * n.dot(tan_sum[i]);
+        if t.magnitude2() < 1e-12 {
+            // No uv gradient, any tangent will do
+            let axis = if n.x.abs() < 0.9 {
+                Vector3f::unit_x()
+            } else {
+                Vector3f::unit_y()
+            };
+            t = axis - n * n.dot(axis);
+        }
+        let t = t.normalize();
+
+        // Keep the handedness of the uv mapping
+        let mut b = n.cross(t);
+        if b.dot(bitan_sum[i]) < 0.0 {
+            b = -b;
+        }
+
+        tangents.extend_from_slice(&[t.x, t.y, t.z]);
+        bitangents.extend_from_slice(&[b.x, b.y, b.z]);
+    }
+
+    (tangents, bitangents)
+}
+
+fn append_directions(out: &mut Option<Vec<f32>>, data: &Option<Vec<f32>>, m: &Matrix3<f32>) {
+    if let (&mut Some(ref mut out), &Some(ref data)) = (out, data) {
+        for v in data.chunks(3) {
+            let v = (m * Vector3::new(v[0], v[1], v[2])).normalize();
+            out.extend_from_slice(&[v.x, v.y, v.z]);
+        }
+    }
 }
 
 pub struct MeshBuffer {
     data: Resource<MeshData>,
-    gl_state: RefCell<Option<MeshGLState>>,
+    /// Shared with the eviction of the GPU memory budget
+    gl_state: Rc<RefCell<Option<MeshGLState>>>,
     bounds: Cell<Option<MeshBound>>,
+    streaming: Cell<bool>,
 
     bound_prog: RefCell<Weak<ShaderProgram>>,
 }
@@ -174,6 +423,7 @@ impl Asset for MeshBuffer {
             data: r,
             gl_state: Default::default(),
             bounds: Default::default(),
+            streaming: Cell::new(false),
             bound_prog: RefCell::new(Weak::new()),
         })
     }
@@ -192,25 +442,16 @@ impl LoadableAsset for MeshBuffer {
     }
 }
 
-pub fn bind_buffer(
-    gl: &WebGLRenderingContext,
-    buffer: &WebGLBuffer,
-    coord: u32,
-    asize: AttributeSize,
-) {
-    gl.bind_buffer(BufferKind::Array, buffer);
-
-    gl.enable_vertex_attrib_array(coord);
-    gl.vertex_attrib_pointer(coord, asize, DataType::Float, false, 0, 0);
-}
-
 impl MeshBuffer {
     pub fn update_mesh_data(&self, mesh_data: MeshData) {
         let mut actions = Vec::new();
+        let mut relayout = false;
 
         match self.data.try_borrow() {
             Err(_) => {}
-            Ok(_da) => {
+            Ok(da) => {
+                relayout = da.layout() != mesh_data.layout();
+
                 actions.push(RebindAction::Vertices);
 
                 mesh_data.uvs.as_ref().map(|_| {
@@ -233,8 +474,16 @@ impl MeshBuffer {
             }
         };
 
+        self.bounds.set(Some(mesh_data.compute_bound()));
         self.data.replace(mesh_data);
 
+        // Attributes were added or removed, recreate the buffers on next prepare
+        if relayout {
+            self.gl_state.replace(None);
+            *self.bound_prog.borrow_mut() = Weak::new();
+            return;
+        }
+
         // check whether the state is ready
         match *self.gl_state.borrow_mut() {
             None => {}
@@ -245,24 +494,121 @@ impl MeshBuffer {
         }
     }
 
-    pub fn prepare(&self, gl: &WebGLRenderingContext) -> AssetResult<()> {
-        if let Some(ref mut state) = *self.gl_state.borrow_mut() {
-            if state.rebind_actions.len() > 0 {
-                gl.bind_vertex_array(&state.vao);
+    /// Generate the tangents of a loaded mesh which has uvs and normals but no
+    /// tangents, for materials with a normal map. Returns whether it did.
+    pub fn ensure_tangents(&self) -> bool {
+        let mut data = match self.data.try_borrow() {
+            Ok(ref data) if data.tangents.is_none() && data.uvs.is_some()
+                && data.normals.is_some() =>
+            {
+                data.clone()
+            }
+            _ => return false,
+        };
 
-                let data = self.data.try_borrow()?;
-                let rebind_actions = state.rebind_actions.drain(..).collect();
+        data.compute_tangents();
+        self.update_mesh_data(data);
+        true
+    }
 
-                // Rebind the mesh
-                state.rebind(&rebind_actions, &data, gl);
+    /// Replace the mesh data by one with the same vertex and index counts,
+    /// only the vertices and indices in the ranges are uploaded again
+    pub fn update_mesh_data_ranges(
+        &self,
+        mesh_data: MeshData,
+        vertices: Option<Range<usize>>,
+        indices: Option<Range<usize>>,
+    ) {
+        let same_size = match self.data.try_borrow() {
+            Ok(data) => {
+                data.vertices.len() == mesh_data.vertices.len()
+                    && data.indices.len() == mesh_data.indices.len()
+                    && data.layout() == mesh_data.layout()
             }
+            Err(_) => false,
+        };
 
-            return Ok(());
+        if !same_size {
+            return self.update_mesh_data(mesh_data);
+        }
+
+        let mut actions = Vec::new();
+
+        if let Some(range) = vertices {
+            actions.push((RebindAction::Vertices, range.clone()));
+
+            mesh_data.uvs.as_ref().map(|_| {
+                actions.push((RebindAction::UV, range.clone()));
+            });
+
+            mesh_data.normals.as_ref().map(|_| {
+                actions.push((RebindAction::Normal, range.clone()));
+            });
+
+            mesh_data.tangents.as_ref().map(|_| {
+                actions.push((RebindAction::Tangent, range.clone()));
+            });
+
+            mesh_data.bitangents.as_ref().map(|_| {
+                actions.push((RebindAction::Bitangent, range.clone()));
+            });
+        }
+
+        if let Some(range) = indices {
+            actions.push((RebindAction::Indices, range));
+        }
+
+        self.bounds.set(Some(mesh_data.compute_bound()));
+        self.data.replace(mesh_data);
+
+        match *self.gl_state.borrow_mut() {
+            None => {}
+            Some(ref mut state) => {
+                state.range_actions.append(&mut actions);
+                *self.bound_prog.borrow_mut() = Weak::new();
+            }
+        }
+    }
+
+    pub fn prepare(&self, gl: &WebGLRenderingContext) -> AssetResult<()> {
+        match *self.gl_state.borrow_mut() {
+            Some(ref mut state) if state.context == context_generation() => {
+                state.memory.touch();
+
+                if !state.rebind_actions.is_empty() || !state.range_actions.is_empty() {
+                    gl.bind_vertex_layout(&state.vao);
+
+                    let data = self.data.try_borrow()?;
+                    let rebind_actions = state.rebind_actions.drain(..).collect();
+
+                    // Rebind the mesh
+                    state.rebind(&rebind_actions, &data, self.streaming.get(), gl);
+
+                    // Buffers fully uploaded above already have the ranges
+                    let range_actions: Vec<_> = state.range_actions.drain(..).collect();
+                    if rebind_actions.is_empty() {
+                        for &(ref action, ref range) in range_actions.iter() {
+                            state.update_range(&data, action, range, gl);
+                        }
+                    }
+                }
+
+                return Ok(());
+            }
+            // Stale or missing, created below
+            _ => {}
         }
 
         let data = self.data.try_borrow()?;
 
-        self.gl_state.replace(Some(mesh_bind_buffer(
+        if self.bounds.get().is_none() {
+            self.bounds.set(Some(data.compute_bound()));
+        }
+
+        // The attributes are bound again to the new vertex array
+        *self.bound_prog.borrow_mut() = Weak::new();
+
+        let state = mesh_bind_buffer(
             &data.vertices,
             &data.uvs,
             &data.normals,
@@ -270,11 +616,40 @@ impl MeshBuffer {
             &data.bitangents,
             &data.indices,
             gl,
-        )));
+        );
+
+        // Dropping the state destroys the buffers, they are created again from
+        // the mesh data when next used
+        let gl_state = Rc::downgrade(&self.gl_state);
+        state.memory.set_evict(move || {
+            if let Some(gl_state) = gl_state.upgrade() {
+                if let Ok(mut state) = gl_state.try_borrow_mut() {
+                    state.take();
+                }
+            }
+        });
+
+        self.gl_state.replace(Some(state));
 
         Ok(())
     }
 
+    /// Free the gl buffers now instead of when the mesh buffer is dropped,
+    /// they are created again from the mesh data when next bound
+    pub fn release_gpu(&self) {
+        self.gl_state.replace(None);
+    }
+
+    /// Hint that the data is replaced every frame through `update_mesh_data`
+    pub fn set_streaming(&self, streaming: bool) {
+        self.streaming.set(streaming);
+    }
+
+    /// The mesh data, once loaded
+    pub fn mesh_data(&self) -> AssetResult<Ref<MeshData>> {
+        self.data.try_borrow()
+    }
+
     fn compute_bounds(&self) -> Option<MeshBound> {
         let data = self.data.try_borrow().ok()?;
         Some(data.compute_bound())
@@ -300,7 +675,7 @@ impl MeshBuffer {
         let state = state_option.as_ref().unwrap();
 
         /*======= Associating shaders to buffer objects =======*/
-        gl.bind_vertex_array(&state.vao);
+        gl.bind_vertex_layout(&state.vao);
 
         if gl.is_webgl2 {
             if let Some(_) = self.bound_prog.borrow().upgrade() {
@@ -310,40 +685,38 @@ impl MeshBuffer {
 
         // Bind vertex buffer object
         // "aVertexPosition"
-        bind_buffer(
-            gl,
+        gl.vertex_attribute(
             &state.vb,
             ShaderAttrib::Position as u32,
-            AttributeSize::Three,
+            VertexFormat::Float3,
         );
 
         // "aTextureCoord"
         if let Some(ref uvb) = state.uvb {
-            bind_buffer(gl, uvb, ShaderAttrib::UV0 as u32, AttributeSize::Two);
+            gl.vertex_attribute(uvb, ShaderAttrib::UV0 as u32, VertexFormat::Float2);
         }
 
         // "aVertexNormal"
         if let Some(ref nb) = state.nb {
-            bind_buffer(gl, nb, ShaderAttrib::Normal as u32, AttributeSize::Three);
+            gl.vertex_attribute(nb, ShaderAttrib::Normal as u32, VertexFormat::Float3);
         }
 
         // "aVertexTangent"
         if let Some(ref tb) = state.tb {
-            bind_buffer(gl, tb, ShaderAttrib::Tangent as u32, AttributeSize::Three);
+            gl.vertex_attribute(tb, ShaderAttrib::Tangent as u32, VertexFormat::Float3);
         }
 
         // "aVertexBitangent"
         if let Some(ref btb) = state.btb {
-            bind_buffer(
-                gl,
+            gl.vertex_attribute(
                 btb,
                 ShaderAttrib::Bitangent as u32,
-                AttributeSize::Three,
+                VertexFormat::Float3,
             );
         }
 
         // Bind index buffer object
-        gl.bind_buffer(BufferKind::ElementArray, &state.ib);
+        gl.bind_indices(&state.ib);
 
         *self.bound_prog.borrow_mut() = Rc::downgrade(program);
 
@@ -354,7 +727,63 @@ impl MeshBuffer {
     pub fn render(&self, gl: &WebGLRenderingContext) {
         let data = self.data.try_borrow().unwrap();
 
-        gl.draw_elements(Primitives::Triangles, data.indices.len(), DataType::U16, 0);
+        gl.draw_indexed(DrawTopology::Triangles, data.indices.len(), None);
+    }
+
+    /// Draw the indices as pairs of line end points instead of triangles
+    pub fn render_lines(&self, gl: &WebGLRenderingContext) {
+        let data = self.data.try_borrow().unwrap();
+
+        gl.draw_indexed(DrawTopology::Lines, data.indices.len(), None);
+    }
+
+    /// Draw the mesh once per model matrix in `instances` with a single call,
+    /// the program reads them from the `aInstanceModel` attribute
+    #[cfg_attr(feature = "flame_it", flame)]
+    pub fn render_instanced(&self, gl: &WebGLRenderingContext, instances: &[Matrix4f]) {
+        let data = self.data.try_borrow().unwrap();
+        let mut state_option = self.gl_state.borrow_mut();
+        let state = state_option.as_mut().unwrap();
+
+        let mut matrices: Vec<f32> = Vec::with_capacity(instances.len() * 16);
+        for m in instances.iter() {
+            let m: &[f32; 16] = m.as_ref();
+            matrices.extend_from_slice(m);
+        }
+        let matrices = matrices.into_bytes();
+
+        if state.instance_b.is_none() {
+            state.instance_b = Some(gl.create_buffer_with(
+                BufferTarget::Vertex,
+                &matrices,
+                BufferUsage::Stream,
+            ));
+        } else {
+            let b = state.instance_b.as_ref().unwrap();
+            gl.upload_buffer(b, BufferTarget::Vertex, &matrices, BufferUsage::Stream);
+        }
+
+        // A mat4 attribute is bound as 4 vec4 columns
+        let loc = ShaderAttrib::InstanceModel as u32;
+        for i in 0..4 {
+            gl.instance_attribute(
+                state.instance_b.as_ref().unwrap(),
+                loc + i,
+                VertexFormat::Float4,
+                64,
+                (i * 16) as usize,
+            );
+        }
+
+        gl.draw_indexed(
+            DrawTopology::Triangles,
+            data.indices.len(),
+            Some(instances.len()),
+        );
+
+        for i in 0..4 {
+            gl.disable_attribute(loc + i);
+        }
     }
 
     pub fn unbind(&self, _gl: &WebGLRenderingContext) {
@@ -367,21 +796,8 @@ impl MeshBuffer {
 }
 
 fn bind_f32_array(gl: &WebGLRenderingContext, data: &Vec<f32>) -> WebGLBuffer {
-    // Create an empty buffer object to store vertex buffer
-    let vb = gl.create_buffer();
-    {
-        // Bind appropriate array buffer to it
-        gl.bind_buffer(BufferKind::Array, &vb);
-
-        // Pass the vertex data to the buffer
-        let cv = data.clone();
-        gl.buffer_data(BufferKind::Array, &cv.into_bytes(), DrawMode::Static);
-
-        // Unbind the buffer
-        gl.unbind_buffer(BufferKind::Array);
-    }
-
-    vb
+    let cv = data.clone();
+    gl.create_buffer_with(BufferTarget::Vertex, &cv.into_bytes(), BufferUsage::Static)
 }
 
 fn mesh_bind_buffer(
@@ -394,8 +810,8 @@ fn mesh_bind_buffer(
     gl: &WebGLRenderingContext,
 ) -> MeshGLState {
     // some opengl 3.x core profile require a VAO. See issue #11
-    let vao = gl.create_vertex_array();
-    gl.bind_vertex_array(&vao);
+    let vao = gl.new_vertex_layout();
+    gl.bind_vertex_layout(&vao);
 
     let vertex_buffer = bind_f32_array(&gl, vertices);
     let uv_buffer = uvs.as_ref().map(|data| bind_f32_array(gl, data));
@@ -403,19 +819,19 @@ fn mesh_bind_buffer(
     let tangent_buffer = tangents.as_ref().map(|data| bind_f32_array(gl, data));
     let bitangent_buffer = bitangents.as_ref().map(|data| bind_f32_array(gl, data));
 
-    // Create an empty buffer object to store Index buffer
-    let index_buffer = gl.create_buffer();
-    {
-        // Bind appropriate array buffer to it
-        gl.bind_buffer(BufferKind::ElementArray, &index_buffer);
-
-        // Pass the vertex data to the buffer
-        let ci = indices.clone();
-        gl.buffer_data(BufferKind::ElementArray, &ci.into_bytes(), DrawMode::Static);
+    let ci = indices.clone();
+    let index_buffer =
+        gl.create_buffer_with(BufferTarget::Index, &ci.into_bytes(), BufferUsage::Static);
 
-        // Unbind the buffer
-        gl.unbind_buffer(BufferKind::ElementArray);
-    }
+    let attributes = [uvs, normals, tangents, bitangents];
+    let floats = vertices.len() + attributes
+        .iter()
+        .map(|a| match **a {
+            Some(ref a) => a.len(),
+            None => 0,
+        })
+        .sum::<usize>();
+    let bytes = floats * size_of::<f32>() + indices.len() * size_of::<u16>();
 
     MeshGLState {
         vao,
@@ -427,8 +843,12 @@ fn mesh_bind_buffer(
         btb: bitangent_buffer,
 
         ib: index_buffer,
+        instance_b: None,
         gl: gl.clone(),
+        context: context_generation(),
+        memory: GpuAllocation::new(GpuResourceKind::MeshBuffer, bytes),
 
         rebind_actions: Vec::new(),
+        range_actions: Vec::new(),
     }
 }
@@ -0,0 +1,175 @@
+use engine::asset::Asset;
+use engine::render::{MeshBuffer, MeshData};
+use math::*;
+use std::cmp::{max, min};
+use std::ops::Range;
+use std::rc::Rc;
+
+/// A vertex of a `MeshBuilder`, attributes the mesh does not have are ignored
+#[derive(Debug, Clone, Copy)]
+pub struct MeshVertex {
+    pub position: Vector3f,
+    pub uv: Vector2f,
+    pub normal: Vector3f,
+    pub tangent: Vector3f,
+    pub bitangent: Vector3f,
+}
+
+impl Default for MeshVertex {
+    fn default() -> MeshVertex {
+        MeshVertex {
+            position: Vector3f::zero(),
+            uv: Vector2f::zero(),
+            normal: Vector3f::unit_y(),
+            tangent: Vector3f::unit_x(),
+            bitangent: Vector3f::unit_z(),
+        }
+    }
+}
+
+impl MeshVertex {
+    pub fn new(position: Vector3f) -> MeshVertex {
+        MeshVertex {
+            position,
+            ..Default::default()
+        }
+    }
+}
+
+/// Builds and edits mesh data at runtime, `upload` only sends the changed
+/// vertices and indices to the `MeshBuffer` unless the mesh was resized
+#[derive(Default)]
+pub struct MeshBuilder {
+    data: MeshData,
+    dirty_vertices: Option<Range<usize>>,
+    dirty_indices: Option<Range<usize>>,
+    resized: bool,
+    buffer: Option<Rc<MeshBuffer>>,
+}
+
+fn set3(v: &mut Vec<f32>, i: usize, p: &Vector3f) {
+    v[i * 3] = p.x;
+    v[i * 3 + 1] = p.y;
+    v[i * 3 + 2] = p.z;
+}
+
+fn mark(dirty: &mut Option<Range<usize>>, i: Range<usize>) {
+    *dirty = Some(match dirty.take() {
+        Some(r) => min(r.start, i.start)..max(r.end, i.end),
+        None => i,
+    });
+}
+
+impl MeshBuilder {
+    /// A builder starting from `data`, its optional attributes decide the ones
+    /// the vertices have
+    pub fn new(data: MeshData) -> MeshBuilder {
+        MeshBuilder {
+            data,
+            resized: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.data.vertices.len() / 3
+    }
+
+    pub fn index_count(&self) -> usize {
+        self.data.indices.len()
+    }
+
+    pub fn push_vertex(&mut self, v: MeshVertex) -> u16 {
+        let i = self.vertex_count();
+
+        self.data.vertices.extend_from_slice(&[0.0; 3]);
+        self.data.uvs.as_mut().map(|d| d.extend_from_slice(&[0.0; 2]));
+        self.data.normals.as_mut().map(|d| d.extend_from_slice(&[0.0; 3]));
+        self.data.tangents.as_mut().map(|d| d.extend_from_slice(&[0.0; 3]));
+        self.data.bitangents.as_mut().map(|d| d.extend_from_slice(&[0.0; 3]));
+
+        self.set_vertex(i, v);
+        self.resized = true;
+
+        i as u16
+    }
+
+    pub fn push_triangle(&mut self, a: u16, b: u16, c: u16) {
+        self.data.indices.extend_from_slice(&[a, b, c]);
+        self.resized = true;
+    }
+
+    pub fn set_vertex(&mut self, i: usize, v: MeshVertex) {
+        set3(&mut self.data.vertices, i, &v.position);
+
+        if let Some(ref mut uvs) = self.data.uvs {
+            uvs[i * 2] = v.uv.x;
+            uvs[i * 2 + 1] = v.uv.y;
+        }
+
+        self.data.normals.as_mut().map(|d| set3(d, i, &v.normal));
+        self.data.tangents.as_mut().map(|d| set3(d, i, &v.tangent));
+        self.data.bitangents.as_mut().map(|d| set3(d, i, &v.bitangent));
+
+        mark(&mut self.dirty_vertices, i..i + 1);
+    }
+
+    pub fn set_position(&mut self, i: usize, p: Vector3f) {
+        set3(&mut self.data.vertices, i, &p);
+        mark(&mut self.dirty_vertices, i..i + 1);
+    }
+
+    /// Change the vertices of the `i`th triangle
+    pub fn set_triangle(&mut self, i: usize, a: u16, b: u16, c: u16) {
+        self.data.indices[i * 3] = a;
+        self.data.indices[i * 3 + 1] = b;
+        self.data.indices[i * 3 + 2] = c;
+
+        mark(&mut self.dirty_indices, i * 3..i * 3 + 3);
+    }
+
+    /// Remove the vertices and triangles after the given counts
+    pub fn truncate(&mut self, vertices: usize, triangles: usize) {
+        self.data.vertices.truncate(vertices * 3);
+        self.data.uvs.as_mut().map(|d| d.truncate(vertices * 2));
+        self.data.normals.as_mut().map(|d| d.truncate(vertices * 3));
+        self.data.tangents.as_mut().map(|d| d.truncate(vertices * 3));
+        self.data.bitangents.as_mut().map(|d| d.truncate(vertices * 3));
+        self.data.indices.truncate(triangles * 3);
+
+        self.resized = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0, 0);
+    }
+
+    /// The buffer of the mesh, with the edits since the last upload
+    pub fn upload(&mut self) -> Rc<MeshBuffer> {
+        let data = self.data.clone();
+        let vertices = self.dirty_vertices.take();
+        let indices = self.dirty_indices.take();
+
+        let buffer = match self.buffer {
+            Some(ref buffer) if self.resized => {
+                buffer.update_mesh_data(data);
+                buffer.clone()
+            }
+            Some(ref buffer) => {
+                if vertices.is_some() || indices.is_some() {
+                    buffer.update_mesh_data_ranges(data, vertices, indices);
+                }
+                buffer.clone()
+            }
+            None => MeshBuffer::new(data),
+        };
+
+        self.resized = false;
+        self.buffer = Some(buffer.clone());
+        buffer
+    }
+}
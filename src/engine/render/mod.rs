@@ -4,31 +4,107 @@ mod shader_program;
 mod texture;
 mod material;
 mod light;
+mod light_cluster;
 mod shader;
 mod uniforms;
+mod uniform_block;
 mod frame_buffer;
 mod render_texture;
 mod mesh_buffer;
+mod mesh_builder;
+mod post_process;
+mod post_effects;
+mod backend;
+mod capabilities;
+mod context_loss;
+mod raw_gl;
+mod gpu_memory;
+mod color_space;
+mod ssao;
+mod camera_depth;
+mod light2d;
+mod batching;
+mod occlusion;
+mod planar_reflection;
+mod fog;
+mod reflection_probe;
+mod skybox;
+mod particles;
+mod debug_draw;
+mod gizmos;
+mod sprite;
+mod nine_slice;
+mod atlas;
+mod sprite_animation;
+mod sorting;
+mod sdf_font;
+mod ttf_font;
+mod text_mesh;
 
 #[derive(Hash, Eq, Ord, PartialOrd, PartialEq, Copy, Clone, Debug)]
 pub enum RenderQueue {
     Opaque = 1000,
+    /// Opaque surfaces with cutout holes, e.g. foliage and fences
+    AlphaTest = 1500,
     Skybox = 2000,
     Transparent = 3000,
+    /// Sprites, alpha blended without depth write in the order of the sprite batcher
+    Sprite = 3500,
+    /// Gizmos and overlays, drawn over the final image after the post processing
+    /// of the camera, without depth write and depth tested against the scene
+    Overlay = 4000,
     UI = 5000,
 }
 
 pub mod mesh_util;
 
-pub use self::camera::{Camera, Frustum};
+pub use self::camera::{oblique_near_plane, split_screen_rects, Camera, CameraClearFlags, Frustum,
+                       HdrSettings, PixelPerfect, Tonemapping};
 pub use self::shader::{PreprocessedShaderCode, Shader, ShaderFs, ShaderKind, ShaderKindFs,
-                       ShaderKindProvider, ShaderKindVs, ShaderVs};
-pub use self::shader_program::ShaderProgram;
-pub use self::texture::{Texture, TextureAsset, TextureAttachment, TextureFiltering, TextureImage,
-                        TextureWrap};
-pub use self::mesh::{Mesh, MeshSurface};
+                       ShaderKindProvider, ShaderKindVs, ShaderVs, SourceLocation};
+pub use self::shader_program::{end_shader_frame, ShaderProgram};
+pub use self::texture::{color_attachment, MipmapFiltering, Texture, TextureAsset,
+                        TextureAttachment, TextureFiltering, TextureImage, TextureSampler,
+                        TextureWrap, MAX_COLOR_ATTACHMENTS, RGBM_RANGE};
+pub use self::mesh::{InstancedMesh, Mesh, MeshBound, MeshSurface};
+pub use self::batching::{DynamicBatcher, StaticBatch, MAX_BATCH_VERTICES};
+pub use self::occlusion::{OcclusionBuffer, OCCLUSION_BUFFER_SIZE};
+pub use self::planar_reflection::PlanarReflection;
+pub use self::fog::{Fog, FogMode};
+pub use self::skybox::{Skybox, SkyboxSource};
+pub use self::particles::{Curve, GpuParticleStep, ParticleEmitter, GPU_PARTICLE_STATE_WIDTH};
+pub use self::debug_draw::{DebugDraw, DebugText};
+pub use self::gizmos::{bounds_gizmo, camera_gizmo, light_gizmo, GizmoSettings};
+pub use self::sprite::{SpriteBatch, SpriteBatcher, SpriteInstance, SpriteQuad, SpriteRenderer};
+pub use self::nine_slice::{NineSlice, NineSlicePatch, SpriteBorder};
+pub use self::atlas::{AtlasData, AtlasFrame, TextureAtlas};
+pub use self::sprite_animation::{SpriteAnimation, SpriteAnimationEvent};
+pub use self::sorting::{SortingGroup, SortingLayers, DEFAULT_SORTING_LAYER};
+pub use self::sdf_font::{SdfFont, SdfFontData, SdfGlyph};
+pub use self::ttf_font::{TtfFont, TtfFontData, TtfGlyph, TtfLineMetrics, TTF_ATLAS_SIZE};
+pub use self::text_mesh::{TextAlignment, TextFont, TextMesh};
+pub use self::reflection_probe::{ProbeMaps, ReflectionProbe, PROBE_IRRADIANCE_SIZE,
+                                 PROBE_SPECULAR_LEVELS};
 pub use self::mesh_buffer::{MeshBuffer, MeshData};
-pub use self::material::{CullMode, DepthTest, Material, MaterialParam, MaterialParamMap,
-                         MaterialState};
-pub use self::light::{DirectionalLight, Light, PointLight};
+pub use self::mesh_builder::{MeshBuilder, MeshVertex};
+pub use self::material::{Blend, BlendEquation, BlendFactor, CullMode, DepthTest, Material,
+                         MaterialParam, MaterialParamMap, MaterialPropertyBlock, MaterialState};
+pub use self::light::{AreaLight, DirectionalLight, Light, PointLight, ShadowSettings, SpotLight};
+pub use self::light_cluster::{LightClusters, MAX_CLUSTERED_LIGHTS};
 pub use self::render_texture::RenderTexture;
+pub use self::post_process::{MaterialEffect, PassInput, PostEffect, PostPass, PostProcess};
+pub use self::post_effects::{Bloom, BokehQuality, ColorGrading, DepthOfField, Fxaa,
+                             MotionBlur};
+pub use self::backend::{BufferTarget, BufferUsage, DrawTopology, FrameAttachment, RenderBackend,
+                        ShaderStage, TextureTarget, VertexFormat};
+pub use self::capabilities::{Capabilities, WebGL1Extensions};
+pub use self::uniforms::UniformTarget;
+pub use self::uniform_block::{UniformBlock, CAMERA_BLOCK_BINDING, LIGHTS_BLOCK_BINDING};
+pub use self::context_loss::{context_generation, next_context_generation};
+pub(crate) use self::raw_gl::take_context_events;
+pub use self::gpu_memory::{end_gpu_memory_frame, gpu_memory_stats, set_gpu_memory_budget,
+                           GpuMemoryStats};
+pub use self::color_space::{srgb_to_linear, ColorSpace};
+pub use self::ssao::{SsaoSettings, SsaoState, SSAO_SAMPLES};
+pub use self::camera_depth::CameraDepthState;
+pub use self::light2d::{Light2D, Light2DShape, Lighting2DSettings, Lighting2DState};
@@ -0,0 +1,149 @@
+use math::*;
+
+/// Insets from the sides of an image, in pixels
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SpriteBorder {
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+    pub top: f32,
+}
+
+impl SpriteBorder {
+    pub fn new(left: f32, bottom: f32, right: f32, top: f32) -> SpriteBorder {
+        SpriteBorder {
+            left,
+            bottom,
+            right,
+            top,
+        }
+    }
+
+    /// The same inset on every side
+    pub fn uniform(inset: f32) -> SpriteBorder {
+        SpriteBorder::new(inset, inset, inset, inset)
+    }
+}
+
+/// A patch of a nine-slice: the (min, max) position and the uvs at these
+/// positions
+pub type NineSlicePatch = ((Vector2f, Vector2f), (Vector2f, Vector2f));
+
+/// Splits an image into 9 patches with a border: the corners keep their size,
+/// the edges stretch along the sides and the center stretches in both
+/// directions, so panels and frames can be scaled without distortion.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NineSlice {
+    pub border: SpriteBorder,
+    /// The size in pixels of the sliced image, the region of a sprite
+    pub source_size: Vector2f,
+}
+
+/// The 4 cuts of a rect from `p0` to `p1` along an axis, with the insets and
+/// their fractions of the image from each side
+fn cuts(
+    p0: f32,
+    p1: f32,
+    t0: f32,
+    t1: f32,
+    insets: (f32, f32),
+    f: (f32, f32),
+) -> [(f32, f32); 4] {
+    // The corners shrink when the rect is smaller than them
+    let total = insets.0 + insets.1;
+    let k = if total > p1 - p0 && total > 0.0 {
+        (p1 - p0).max(0.0) / total
+    } else {
+        1.0
+    };
+
+    [
+        (p0, t0),
+        (p0 + insets.0 * k, t0 + (t1 - t0) * f.0),
+        (p1 - insets.1 * k, t1 - (t1 - t0) * f.1),
+        (p1, t1),
+    ]
+}
+
+impl NineSlice {
+    pub fn new(border: SpriteBorder, source_size: Vector2f) -> NineSlice {
+        NineSlice {
+            border,
+            source_size,
+        }
+    }
+
+    /// The border with its sides swapped, for a flipped image
+    pub fn flipped(&self, flip_x: bool, flip_y: bool) -> NineSlice {
+        let mut slice = *self;
+
+        if flip_x {
+            ::std::mem::swap(&mut slice.border.left, &mut slice.border.right);
+        }
+        if flip_y {
+            ::std::mem::swap(&mut slice.border.bottom, &mut slice.border.top);
+        }
+
+        slice
+    }
+
+    /// The patches of the rect (`min`, `max`), `min` being the bottom left,
+    /// which shows the image from `uv_min` at `min` to `uv_max` at `max`.
+    /// A pixel of the border is `pixel` wide and high, the empty patches are
+    /// skipped.
+    pub fn patches(
+        &self,
+        min: Vector2f,
+        max: Vector2f,
+        uv_min: Vector2f,
+        uv_max: Vector2f,
+        pixel: Vector2f,
+    ) -> Vec<NineSlicePatch> {
+        let b = &self.border;
+        let frac = |inset: f32, size: f32| if size > 0.0 { inset / size } else { 0.0 };
+
+        let xs = cuts(
+            min.x,
+            max.x,
+            uv_min.x,
+            uv_max.x,
+            (b.left * pixel.x, b.right * pixel.x),
+            (
+                frac(b.left, self.source_size.x),
+                frac(b.right, self.source_size.x),
+            ),
+        );
+        let ys = cuts(
+            min.y,
+            max.y,
+            uv_min.y,
+            uv_max.y,
+            (b.bottom * pixel.y, b.top * pixel.y),
+            (
+                frac(b.bottom, self.source_size.y),
+                frac(b.top, self.source_size.y),
+            ),
+        );
+
+        let mut patches = Vec::with_capacity(9);
+        for j in 0..3 {
+            for i in 0..3 {
+                let (x0, u0) = xs[i];
+                let (x1, u1) = xs[i + 1];
+                let (y0, v0) = ys[j];
+                let (y1, v1) = ys[j + 1];
+
+                if x1 - x0 <= 0.0 || y1 - y0 <= 0.0 {
+                    continue;
+                }
+
+                patches.push((
+                    (Vector2f::new(x0, y0), Vector2f::new(x1, y1)),
+                    (Vector2f::new(u0, v0), Vector2f::new(u1, v1)),
+                ));
+            }
+        }
+
+        patches
+    }
+}
@@ -0,0 +1,171 @@
+use engine::core::Aabb;
+use engine::render::MeshData;
+use math::*;
+use std::f32;
+
+/// Size in pixels of the occlusion depth buffer
+pub const OCCLUSION_BUFFER_SIZE: (usize, usize) = (256, 128);
+const TILE_SIZE: usize = 8;
+
+/// A small software depth buffer with the occluder meshes, and the farthest
+/// depth of each tile to test the other objects against
+pub struct OcclusionBuffer {
+    view_proj: Matrix4f,
+    width: usize,
+    height: usize,
+    depth: Vec<f32>,
+    tiles: Vec<f32>,
+}
+
+impl OcclusionBuffer {
+    pub fn new(view_proj: Matrix4f) -> OcclusionBuffer {
+        let (width, height) = OCCLUSION_BUFFER_SIZE;
+
+        OcclusionBuffer {
+            view_proj,
+            width,
+            height,
+            depth: vec![1.0; width * height],
+            tiles: vec![1.0; (width / TILE_SIZE) * (height / TILE_SIZE)],
+        }
+    }
+
+    /// (x, y) in pixels and depth in [0, 1], None behind the near plane
+    fn project(&self, m: &Matrix4f, p: Vector3f) -> Option<Vector3f> {
+        let clip = m * p.extend(1.0);
+        if clip.w <= 1e-5 {
+            return None;
+        }
+
+        let ndc = clip.truncate() / clip.w;
+
+        Some(Vector3f::new(
+            (ndc.x * 0.5 + 0.5) * self.width as f32,
+            (0.5 - ndc.y * 0.5) * self.height as f32,
+            ndc.z * 0.5 + 0.5,
+        ))
+    }
+
+    /// Rasterize the triangles of an occluder with its model matrix
+    pub fn add_occluder(&mut self, data: &MeshData, model_m: &Matrix4f) {
+        let m = self.view_proj * *model_m;
+
+        for tri in data.indices.chunks(3).filter(|tri| tri.len() == 3) {
+            let mut p = [Vector3f::zero(); 3];
+            let mut clipped = false;
+
+            for k in 0..3 {
+                let i = tri[k] as usize * 3;
+                let v = Vector3f::new(data.vertices[i], data.vertices[i + 1], data.vertices[i + 2]);
+
+                // Dropping the triangle only hides less, so it stays conservative
+                match self.project(&m, v) {
+                    Some(v) => p[k] = v,
+                    None => clipped = true,
+                }
+            }
+
+            if !clipped {
+                self.rasterize(&p);
+            }
+        }
+    }
+
+    fn rasterize(&mut self, p: &[Vector3f; 3]) {
+        let area = edge(&p[0], &p[1], &p[2]);
+        if area.abs() < 1e-6 {
+            return;
+        }
+
+        let min_x = p[0].x.min(p[1].x).min(p[2].x).max(0.0) as usize;
+        let min_y = p[0].y.min(p[1].y).min(p[2].y).max(0.0) as usize;
+        let max_x = (p[0].x.max(p[1].x).max(p[2].x).ceil() as usize).min(self.width);
+        let max_y = (p[0].y.max(p[1].y).max(p[2].y).ceil() as usize).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let c = Vector3f::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+
+                let w0 = edge(&p[1], &p[2], &c) / area;
+                let w1 = edge(&p[2], &p[0], &c) / area;
+                let w2 = edge(&p[0], &p[1], &c) / area;
+
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+
+                let z = w0 * p[0].z + w1 * p[1].z + w2 * p[2].z;
+                let d = &mut self.depth[y * self.width + x];
+                *d = d.min(z);
+            }
+        }
+    }
+
+    /// Compute the farthest depth of each tile, call it after adding the occluders
+    pub fn finish(&mut self) {
+        let tiles_x = self.width / TILE_SIZE;
+
+        for (i, tile) in self.tiles.iter_mut().enumerate() {
+            let (tx, ty) = (i % tiles_x, i / tiles_x);
+            let mut far: f32 = 0.0;
+
+            for y in ty * TILE_SIZE..(ty + 1) * TILE_SIZE {
+                let row = &self.depth[y * self.width..(y + 1) * self.width];
+                for d in row[tx * TILE_SIZE..(tx + 1) * TILE_SIZE].iter() {
+                    far = far.max(*d);
+                }
+            }
+
+            *tile = far;
+        }
+    }
+
+    /// Whether a world space aabb may be visible, false when every tile it
+    /// covers has occluders in front of its nearest point
+    pub fn is_visible(&self, aabb: &Aabb) -> bool {
+        let mut min = Vector3f::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vector3f::new(f32::MIN, f32::MIN, f32::MIN);
+
+        for c in aabb.corners().iter() {
+            match self.project(&self.view_proj, *c) {
+                Some(p) => {
+                    min = Vector3f::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+                    max = Vector3f::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+                }
+                // Crossing the near plane
+                None => return true,
+            }
+        }
+
+        let tiles_x = self.width / TILE_SIZE;
+        let tiles_y = self.height / TILE_SIZE;
+
+        let tile_range = |lo: f32, hi: f32, count: usize| {
+            let lo = (lo.max(0.0) as usize / TILE_SIZE).min(count);
+            let hi = ((hi.max(0.0).ceil() as usize + TILE_SIZE - 1) / TILE_SIZE).min(count);
+            lo..hi
+        };
+
+        let xs = tile_range(min.x, max.x, tiles_x);
+        let ys = tile_range(min.y, max.y, tiles_y);
+
+        if xs.start >= xs.end || ys.start >= ys.end {
+            // Outside of the buffer, leave it to frustum culling
+            return true;
+        }
+
+        for ty in ys {
+            for tx in xs.clone() {
+                if self.tiles[ty * tiles_x + tx] >= min.z {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+fn edge(a: &Vector3f, b: &Vector3f, c: &Vector3f) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
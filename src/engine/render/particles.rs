@@ -0,0 +1,592 @@
+use engine::asset::{Asset, AssetSystem};
+use engine::core::Aabb;
+use engine::render::{Blend, CullMode, Material, MaterialPropertyBlock, MeshBuffer, MeshData,
+                     MeshSurface, RenderQueue, RenderTexture, Texture, TextureAttachment,
+                     MAX_BATCH_VERTICES};
+use math::*;
+use std::collections::VecDeque;
+use std::f32;
+use std::ops::{Add, Mul};
+use std::rc::Rc;
+
+/// A value over the normalized lifetime of a particle, linear between the
+/// `(time, value)` keys sorted by time in [0, 1]
+#[derive(Clone, Debug)]
+pub struct Curve<T> {
+    pub keys: Vec<(f32, T)>,
+}
+
+impl<T> Curve<T>
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    pub fn constant(value: T) -> Curve<T> {
+        Curve {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    pub fn linear(start: T, end: T) -> Curve<T> {
+        Curve {
+            keys: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    pub fn evaluate(&self, t: f32) -> T {
+        let keys = &self.keys;
+        let first = keys[0];
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for w in keys.windows(2) {
+            let ((t0, v0), (t1, v1)) = (w[0], w[1]);
+            if t <= t1 {
+                let f = (t - t0) / (t1 - t0).max(1e-6);
+                return v0 * (1.0 - f) + v1 * f;
+            }
+        }
+
+        keys[keys.len() - 1].1
+    }
+}
+
+struct Particle {
+    position: Vector3f,
+    velocity: Vector3f,
+    age: f32,
+    lifetime: f32,
+    size: f32,
+    rotation: f32,
+    color: Vector4<f32>,
+}
+
+/// Width in texels of the GPU simulation state textures, one texel per particle
+pub const GPU_PARTICLE_STATE_WIDTH: u32 = 256;
+
+/// The simulation state of a GPU emitter, ping-ponged every step. Each
+/// particle is a texel of (position, age) and one of (velocity, lifetime),
+/// a particle is dead when its age reaches its lifetime.
+struct GpuParticles {
+    capacity: u32,
+    size: (u32, u32),
+    positions: [Rc<RenderTexture>; 2],
+    velocities: [Rc<RenderTexture>; 2],
+    /// The textures written by the last step
+    current: usize,
+    /// Next slot to emit into, slots are reused in order
+    cursor: u32,
+    /// Bursts requested since the last step
+    pending: u32,
+    /// The quads of every slot, in chunks fitting the u16 indices
+    surfaces: Vec<Rc<MeshSurface>>,
+    properties: Rc<MaterialPropertyBlock>,
+    /// Emitter positions of the last `lifetime.1` seconds, for the bounds
+    trail: VecDeque<(f32, Vector3f)>,
+}
+
+impl GpuParticles {
+    fn new(capacity: u32, material: &Rc<Material>) -> GpuParticles {
+        let width = GPU_PARTICLE_STATE_WIDTH;
+        let height = (capacity + width - 1) / width;
+        let state = || Rc::new(RenderTexture::new(width, height, TextureAttachment::Color0Hdr));
+
+        // Each vertex has the quad corner as uv, and the texel of its
+        // particle and the slot index as normal
+        let per_chunk = (MAX_BATCH_VERTICES / 4) as u32;
+        let mut surfaces = Vec::new();
+        let mut first = 0;
+
+        while first < capacity {
+            let count = per_chunk.min(capacity - first);
+            let mut data = MeshData::with_layout([true, true, false, false]);
+
+            {
+                let uvs = data.uvs.as_mut().unwrap();
+                let normals = data.normals.as_mut().unwrap();
+
+                for i in 0..count {
+                    let slot = first + i;
+                    let s = ((slot % width) as f32 + 0.5) / width as f32;
+                    let t = ((slot / width) as f32 + 0.5) / height as f32;
+
+                    for &(u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter() {
+                        data.vertices.extend_from_slice(&[0.0, 0.0, 0.0]);
+                        uvs.extend_from_slice(&[u, v]);
+                        normals.extend_from_slice(&[s, t, slot as f32]);
+                    }
+
+                    let base = (i * 4) as u16;
+                    data.indices.extend_from_slice(&[
+                        base,
+                        base + 1,
+                        base + 2,
+                        base,
+                        base + 2,
+                        base + 3,
+                    ]);
+                }
+            }
+
+            surfaces.push(Rc::new(MeshSurface {
+                buffer: MeshBuffer::new(data),
+                material: material.clone(),
+                properties: None,
+            }));
+
+            first += count;
+        }
+
+        GpuParticles {
+            capacity,
+            size: (width, height),
+            positions: [state(), state()],
+            velocities: [state(), state()],
+            current: 0,
+            cursor: 0,
+            pending: 0,
+            surfaces,
+            properties: Rc::new(MaterialPropertyBlock::new()),
+            trail: VecDeque::new(),
+        }
+    }
+}
+
+/// The targets of a GPU simulation step, see `ParticleEmitter::begin_gpu_step`
+pub struct GpuParticleStep {
+    /// Size of the state textures, the viewport of the passes
+    pub size: (u32, u32),
+    /// Target of the pass with `uVelocityPass` false
+    pub positions: Rc<RenderTexture>,
+    /// Target of the pass with `uVelocityPass` true
+    pub velocities: Rc<RenderTexture>,
+}
+
+/// Emits particles from its game object along the local +Y axis, simulated on
+/// the CPU in world space. They are drawn as camera facing quads in the
+/// `Transparent` queue, all of an emitter in one streaming `MeshBuffer`.
+///
+/// The quads are built in "unrust/particle_vs.glsl" from the vertex attributes:
+/// the particle center as the position, the corner as the uv, the color as the
+/// normal and (size, rotation, alpha) as the tangent.
+///
+/// With `gpu_simulation` the particles are simulated in float render
+/// textures instead, for hundreds of thousands of them. It needs WebGL2 (or
+/// desktop GL), the engine keeps the CPU simulation as the WebGL1 fallback.
+/// The GPU path enables the `GPU_PARTICLES` keyword of the material, where
+/// the vertex shader reads the particles from the state textures. Only the
+/// start and end of the lifetime curves are used there.
+#[derive(Component)]
+pub struct ParticleEmitter {
+    /// Length of an emission cycle in seconds, the bursts repeat every cycle
+    pub duration: f32,
+    pub looping: bool,
+    pub emitting: bool,
+    /// Particles per second
+    pub rate: f32,
+    /// `(time, count)`: emit `count` particles at `time` seconds in the cycle
+    pub bursts: Vec<(f32, u32)>,
+    /// Alive particles are capped to it, and on the CPU to what fits in one buffer
+    pub max_particles: usize,
+    /// Simulate on the GPU when supported, see above
+    pub gpu_simulation: bool,
+
+    /// The start values are picked at random between the two bounds
+    pub lifetime: (f32, f32),
+    pub start_speed: (f32, f32),
+    pub start_size: (f32, f32),
+    /// In radians, around the view direction
+    pub start_rotation: (f32, f32),
+    pub start_color: Vector4<f32>,
+    /// Half angle in radians of the emission cone around +Y
+    pub spread: f32,
+    /// Acceleration in world space
+    pub gravity: Vector3f,
+
+    /// Multiplies the start color and size over the lifetime
+    pub color_over_lifetime: Curve<Vector4<f32>>,
+    pub size_over_lifetime: Curve<f32>,
+
+    particles: Vec<Particle>,
+    time: f32,
+    emit_remainder: f32,
+    seed: u32,
+    bounds: Option<Aabb>,
+    surface: Rc<MeshSurface>,
+    gpu: Option<GpuParticles>,
+}
+
+/// Layout of the particle vertices: uvs, normals and tangents
+const PARTICLE_LAYOUT: [bool; 4] = [true, true, true, false];
+
+impl ParticleEmitter {
+    pub fn new(material: Rc<Material>) -> ParticleEmitter {
+        let buffer = MeshBuffer::new(MeshData::with_layout(PARTICLE_LAYOUT));
+        buffer.set_streaming(true);
+
+        ParticleEmitter {
+            duration: 5.0,
+            looping: true,
+            emitting: true,
+            rate: 10.0,
+            bursts: Vec::new(),
+            max_particles: 1000,
+            gpu_simulation: false,
+
+            lifetime: (5.0, 5.0),
+            start_speed: (1.0, 1.0),
+            start_size: (1.0, 1.0),
+            start_rotation: (0.0, 0.0),
+            start_color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            spread: 0.4,
+            gravity: Vector3f::zero(),
+
+            color_over_lifetime: Curve::constant(Vector4::new(1.0, 1.0, 1.0, 1.0)),
+            size_over_lifetime: Curve::constant(1.0),
+
+            particles: Vec::new(),
+            time: 0.0,
+            emit_remainder: 0.0,
+            seed: 0x9e37_79b9,
+            bounds: None,
+            surface: Rc::new(MeshSurface {
+                buffer,
+                material,
+                properties: None,
+            }),
+            gpu: None,
+        }
+    }
+
+    /// The alpha blended material of "unrust/particle", `texture` is multiplied
+    /// by the particle color
+    pub fn default_material(db: &AssetSystem, texture: Rc<Texture>) -> Material {
+        let mut material = Material::new(db.new_program("unrust/particle"));
+        material.set("uTexture", texture);
+
+        material.render_queue = RenderQueue::Transparent;
+        material.states.cull = Some(CullMode::Off);
+        material.states.depth_write = Some(false);
+        material.states.alpha_blending = Some(true);
+        material.states.blend = Some(Blend::default());
+        material.fog = false;
+
+        material
+    }
+
+    /// Alive particles of the CPU simulation, the GPU one is not read back
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    /// World bounds of the alive particles, estimated on the GPU path
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    /// The surface to draw, None without alive particles
+    pub fn surface(&self) -> Option<&Rc<MeshSurface>> {
+        if self.particles.is_empty() {
+            None
+        } else {
+            Some(&self.surface)
+        }
+    }
+
+    /// Restart the emission cycle and remove the alive particles
+    pub fn restart(&mut self) {
+        self.particles.clear();
+        self.time = 0.0;
+        self.emit_remainder = 0.0;
+        self.emitting = true;
+        self.bounds = None;
+        self.gpu = None;
+    }
+
+    /// Emit `count` particles now, `transform` is the world matrix of the emitter
+    pub fn burst(&mut self, count: u32, transform: &Matrix4f) {
+        if let Some(ref mut gpu) = self.gpu {
+            // Emitted by the next step, from the emitter position then
+            gpu.pending += count;
+            return;
+        }
+
+        for _ in 0..count {
+            self.spawn(transform);
+        }
+    }
+
+    /// Random in [0, 1), xorshift so every emitter has its own sequence
+    fn random(&mut self) -> f32 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+
+        (x >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn random_range(&mut self, range: (f32, f32)) -> f32 {
+        range.0 + (range.1 - range.0) * self.random()
+    }
+
+    fn spawn(&mut self, transform: &Matrix4f) {
+        let max = self.max_particles.min(MAX_BATCH_VERTICES / 4);
+        if self.particles.len() >= max {
+            return;
+        }
+
+        // A direction in the cone around +Y
+        let cos_theta = 1.0 - self.random() * (1.0 - self.spread.cos());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = self.random() * 2.0 * f32::consts::PI;
+        let local = Vector3f::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+        let direction = transform.transform_vector(local).normalize();
+
+        let (speed, lifetime, size, rotation) = (
+            self.start_speed,
+            self.lifetime,
+            self.start_size,
+            self.start_rotation,
+        );
+        let speed = self.random_range(speed);
+        let lifetime = self.random_range(lifetime).max(1e-3);
+        let size = self.random_range(size);
+        let rotation = self.random_range(rotation);
+
+        self.particles.push(Particle {
+            position: transform.w.truncate(),
+            velocity: direction * speed,
+            age: 0.0,
+            lifetime,
+            size,
+            rotation,
+            color: self.start_color,
+        });
+    }
+
+    /// Step the simulation by `dt` seconds and refill the buffer,
+    /// `transform` is the world matrix of the emitter
+    pub fn update(&mut self, dt: f32, transform: &Matrix4f) {
+        if self.gpu.is_some() {
+            self.gpu = None;
+            self.bounds = None;
+            self.surface.material.disable_keyword("GPU_PARTICLES");
+        }
+
+        let gravity = self.gravity;
+        for p in self.particles.iter_mut() {
+            p.age += dt;
+            p.velocity += gravity * dt;
+            p.position += p.velocity * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        for _ in 0..self.emit_count(dt) {
+            self.spawn(transform);
+        }
+
+        self.update_buffer();
+    }
+
+    /// Particles to emit in the next `dt` seconds, from the rate and the bursts
+    fn emit_count(&mut self, dt: f32) -> u32 {
+        if !self.emitting {
+            return 0;
+        }
+
+        let start = self.time;
+        let end = start + dt;
+        let mut total = 0;
+
+        // Bursts crossed in this step, modulo the cycles
+        for &(time, count) in self.bursts.iter() {
+            let mut t = time;
+            while t < end {
+                if t >= start {
+                    total += count;
+                }
+
+                if !self.looping || self.duration <= 0.0 {
+                    break;
+                }
+                t += self.duration;
+            }
+        }
+
+        let emitted = self.rate * dt + self.emit_remainder;
+        let count = emitted.floor();
+        self.emit_remainder = emitted - count;
+        total += count as u32;
+
+        self.time = end;
+        if !self.looping && self.time >= self.duration {
+            self.emitting = false;
+        }
+
+        total
+    }
+
+    fn update_buffer(&mut self) {
+        let mut data = MeshData::with_layout(PARTICLE_LAYOUT);
+        let mut bounds = Aabb::empty();
+
+        {
+            let uvs = data.uvs.as_mut().unwrap();
+            let normals = data.normals.as_mut().unwrap();
+            let tangents = data.tangents.as_mut().unwrap();
+
+            for (i, p) in self.particles.iter().enumerate() {
+                let t = p.age / p.lifetime;
+                let color = p.color.mul_element_wise(self.color_over_lifetime.evaluate(t));
+                let size = p.size * self.size_over_lifetime.evaluate(t);
+
+                for &(u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].iter() {
+                    data.vertices
+                        .extend_from_slice(&[p.position.x, p.position.y, p.position.z]);
+                    uvs.extend_from_slice(&[u, v]);
+                    normals.extend_from_slice(&[color.x, color.y, color.z]);
+                    tangents.extend_from_slice(&[size, p.rotation, color.w]);
+                }
+
+                let base = (i * 4) as u16;
+                data.indices.extend_from_slice(&[
+                    base,
+                    base + 1,
+                    base + 2,
+                    base,
+                    base + 2,
+                    base + 3,
+                ]);
+
+                // The rotated quad fits in the sphere through its corners
+                bounds.merge_sphere(&p.position, size * 0.75);
+            }
+        }
+
+        self.bounds = if self.particles.is_empty() {
+            None
+        } else {
+            Some(bounds)
+        };
+
+        self.surface.buffer.update_mesh_data(data);
+    }
+
+    /// Start a GPU step of `dt` seconds, `transform` is the world matrix of the
+    /// emitter. The state is (re)allocated when needed and the uniforms of the
+    /// "unrust/particle_update" `material` are set, the engine then renders its
+    /// two passes into the returned targets and calls `end_gpu_step`.
+    pub fn begin_gpu_step(
+        &mut self,
+        dt: f32,
+        transform: &Matrix4f,
+        material: &Material,
+    ) -> GpuParticleStep {
+        let capacity = self.max_particles.max(1) as u32;
+        if self.gpu.as_ref().map(|gpu| gpu.capacity) != Some(capacity) {
+            self.gpu = Some(GpuParticles::new(capacity, &self.surface.material));
+            self.particles.clear();
+            self.surface.material.enable_keyword("GPU_PARTICLES");
+        }
+
+        let count = self.emit_count(dt);
+        let seed = self.random();
+        let position = transform.w.truncate();
+
+        // How far a particle can get from where it was emitted
+        let lifetime = self.lifetime.0.max(self.lifetime.1);
+        let reach = self.start_speed.0.abs().max(self.start_speed.1.abs()) * lifetime
+            + 0.5 * self.gravity.magnitude() * lifetime * lifetime
+            + self.start_size.0.max(self.start_size.1);
+
+        material.set("uEmitterMatrix", *transform);
+        material.set("uDeltaTime", dt);
+        material.set("uSeed", seed);
+        material.set("uGravity", self.gravity);
+        material.set("uSpread", self.spread);
+        material.set(
+            "uStartSpeed",
+            Vector2f::new(self.start_speed.0, self.start_speed.1),
+        );
+        material.set("uLifetime", Vector2f::new(self.lifetime.0, self.lifetime.1));
+
+        let gpu = self.gpu.as_mut().unwrap();
+        let count = (count + gpu.pending).min(capacity);
+        gpu.pending = 0;
+
+        let source = gpu.current;
+        material.set("uPositions", gpu.positions[source].as_texture());
+        material.set("uVelocities", gpu.velocities[source].as_texture());
+        material.set(
+            "uStateSize",
+            Vector2f::new(gpu.size.0 as f32, gpu.size.1 as f32),
+        );
+        material.set("uCapacity", capacity as f32);
+        material.set("uEmitStart", gpu.cursor as f32);
+        material.set("uEmitCount", count as f32);
+        gpu.cursor = (gpu.cursor + count) % capacity;
+
+        for p in gpu.trail.iter_mut() {
+            p.0 += dt;
+        }
+        gpu.trail.push_back((0.0, position));
+        while gpu.trail.len() > 1 && gpu.trail[0].0 > lifetime {
+            gpu.trail.pop_front();
+        }
+
+        let mut bounds = Aabb::empty();
+        for &(_, p) in gpu.trail.iter() {
+            bounds.merge_sphere(&p, reach);
+        }
+        self.bounds = Some(bounds);
+
+        let target = 1 - source;
+        GpuParticleStep {
+            size: gpu.size,
+            positions: gpu.positions[target].clone(),
+            velocities: gpu.velocities[target].clone(),
+        }
+    }
+
+    /// Draw the targets of the step from now on
+    pub fn end_gpu_step(&mut self) {
+        let mut properties = MaterialPropertyBlock::new();
+        properties.set(
+            "uStartSize",
+            Vector2f::new(self.start_size.0, self.start_size.1),
+        );
+        properties.set(
+            "uStartRotation",
+            Vector2f::new(self.start_rotation.0, self.start_rotation.1),
+        );
+        properties.set(
+            "uColorStart",
+            self.start_color
+                .mul_element_wise(self.color_over_lifetime.evaluate(0.0)),
+        );
+        properties.set(
+            "uColorEnd",
+            self.start_color
+                .mul_element_wise(self.color_over_lifetime.evaluate(1.0)),
+        );
+        properties.set("uSizeStart", self.size_over_lifetime.evaluate(0.0));
+        properties.set("uSizeEnd", self.size_over_lifetime.evaluate(1.0));
+
+        if let Some(ref mut gpu) = self.gpu {
+            gpu.current = 1 - gpu.current;
+            properties.set("uParticlePositions", gpu.positions[gpu.current].as_texture());
+            properties.set("uParticleVelocities", gpu.velocities[gpu.current].as_texture());
+            gpu.properties = Rc::new(properties);
+        }
+    }
+
+    /// The chunks of the GPU simulated particles to draw with the properties,
+    /// None on the CPU path
+    pub fn gpu_surfaces(&self) -> Option<(&[Rc<MeshSurface>], &Rc<MaterialPropertyBlock>)> {
+        self.gpu
+            .as_ref()
+            .map(|gpu| (&gpu.surfaces[..], &gpu.properties))
+    }
+}
@@ -0,0 +1,129 @@
+use engine::render::{Camera, RenderTexture, TextureAttachment};
+use math::*;
+use std::borrow::Cow;
+use std::rc::Rc;
+
+/// Add it next to a flat `Mesh` to render the scene mirrored about the local
+/// XZ plane of the object, for mirrors and water. The reflection is bound
+/// to `texture_name` in the materials of the mesh, to sample with the
+/// screen position of the fragment.
+#[derive(Component)]
+pub struct PlanarReflection {
+    /// Size of the reflection relative to the camera viewport
+    pub resolution_scale: f32,
+    /// Bit mask of the GameObject layers in the reflection
+    pub culling_mask: u32,
+    /// Moves the clip plane along the normal, hides seams at the surface
+    pub clip_offset: f32,
+    pub texture_name: Cow<'static, str>,
+
+    rt: Option<((u32, u32), Rc<RenderTexture>)>,
+}
+
+impl Default for PlanarReflection {
+    fn default() -> PlanarReflection {
+        PlanarReflection {
+            resolution_scale: 0.5,
+            culling_mask: !0,
+            clip_offset: 0.05,
+            texture_name: "uReflectionTexture".into(),
+            rt: None,
+        }
+    }
+}
+
+/// Mirror matrix about the plane through `point` with `normal`
+fn reflection_matrix(point: Vector3f, normal: Vector3f) -> Matrix4f {
+    let n = normal.normalize();
+    let d = -n.dot(point);
+
+    Matrix4::new(
+        1.0 - 2.0 * n.x * n.x,
+        -2.0 * n.y * n.x,
+        -2.0 * n.z * n.x,
+        0.0,
+        -2.0 * n.x * n.y,
+        1.0 - 2.0 * n.y * n.y,
+        -2.0 * n.z * n.y,
+        0.0,
+        -2.0 * n.x * n.z,
+        -2.0 * n.y * n.z,
+        1.0 - 2.0 * n.z * n.z,
+        0.0,
+        -2.0 * d * n.x,
+        -2.0 * d * n.y,
+        -2.0 * d * n.z,
+        1.0,
+    )
+}
+
+impl PlanarReflection {
+    pub fn new() -> PlanarReflection {
+        Default::default()
+    }
+
+    /// The render texture for a viewport of `size`, recreated when it changes
+    fn render_texture(&mut self, size: (u32, u32)) -> Rc<RenderTexture> {
+        let size = (
+            ((size.0 as f32 * self.resolution_scale) as u32).max(1),
+            ((size.1 as f32 * self.resolution_scale) as u32).max(1),
+        );
+
+        match self.rt {
+            Some((s, ref rt)) if s == size => return rt.clone(),
+            _ => (),
+        }
+
+        let rt = Rc::new(RenderTexture::new_with_depth(
+            size.0,
+            size.1,
+            TextureAttachment::Color0,
+        ));
+        self.rt = Some((size, rt.clone()));
+        rt
+    }
+
+    /// The mirrored `camera` rendering into the reflection texture, clipped by
+    /// the plane. Its view matrix is mirrored, so the engine inverts culling,
+    /// and its projection is the one of `camera`, custom or orthographic.
+    pub fn reflection_camera(
+        &mut self,
+        camera: &Camera,
+        point: Vector3f,
+        normal: Vector3f,
+        screen_size: (u32, u32),
+    ) -> Camera {
+        let viewport = camera.rect.map(|r| r.1).unwrap_or(screen_size);
+        let rt = self.render_texture(viewport);
+        let size = self.rt.as_ref().unwrap().0;
+
+        let m = reflection_matrix(point, normal);
+        let forward = m.transform_vector(camera.forward());
+        let eye = m.transform_point(Point3::from_vec(camera.eye()));
+
+        let mut up = Vector3::unit_y();
+        if up.dot(forward.normalize()).abs() > 0.9999 {
+            up = Vector3::unit_z();
+        }
+
+        let mut cam = Camera::new();
+        // Sets the eye, the view is replaced by the mirrored one
+        cam.lookat(&eye, &(eye + forward), &up);
+        cam.v = camera.v * m;
+
+        cam.znear = camera.znear;
+        cam.zfar = camera.zfar;
+        cam.culling_mask = self.culling_mask;
+        cam.included_render_queues = camera.included_render_queues.clone();
+        cam.rect = Some(((0, 0), size));
+        cam.render_texture = Some(rt);
+
+        // The reflection has the aspect of the viewport at any scale
+        cam.projection = Some(camera.perspective(screen_size));
+
+        let clip_point = point + normal.normalize() * self.clip_offset;
+        cam.projection = Some(cam.oblique_projection(screen_size, clip_point, normal));
+
+        cam
+    }
+}
@@ -0,0 +1,269 @@
+use engine::asset::AssetSystem;
+use engine::render::{Material, PassInput, PostEffect, PostPass, Texture};
+use math::*;
+use std::rc::Rc;
+
+/// Bright pass, a downsample chain, a separable gaussian blur at the
+/// smallest level, then an additive composite over the screen.
+pub struct Bloom {
+    /// Luminance above which pixels bleed
+    pub threshold: f32,
+    /// Width of the soft transition around `threshold`
+    pub soft_knee: f32,
+    pub intensity: f32,
+    pub enabled: bool,
+
+    threshold_material: Rc<Material>,
+    downsample_materials: [Rc<Material>; 2],
+    blur_materials: [Rc<Material>; 2],
+    composite_material: Rc<Material>,
+}
+
+impl Bloom {
+    pub fn new(db: &AssetSystem) -> Bloom {
+        let material = |name| Rc::new(Material::new(db.new_program(name)));
+
+        let blur_materials = [material("unrust/gaussian_blur"), material("unrust/gaussian_blur")];
+        blur_materials[0].set("uDirection", Vector2f::new(1.0, 0.0));
+        blur_materials[1].set("uDirection", Vector2f::new(0.0, 1.0));
+
+        Bloom {
+            threshold: 0.8,
+            soft_knee: 0.5,
+            intensity: 1.0,
+            enabled: true,
+            threshold_material: material("unrust/bloom_threshold"),
+            downsample_materials: [material("unrust/downsample"), material("unrust/downsample")],
+            blur_materials: blur_materials,
+            composite_material: material("unrust/bloom_composite"),
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn material(&self) -> Rc<Material> {
+        self.composite_material.clone()
+    }
+
+    fn passes(&self) -> Vec<PostPass> {
+        self.threshold_material.set("uThreshold", self.threshold);
+        self.threshold_material.set("uSoftKnee", self.soft_knee);
+        self.composite_material.set("uIntensity", self.intensity);
+
+        vec![
+            PostPass::scaled(self.threshold_material.clone(), 2),
+            PostPass::scaled(self.downsample_materials[0].clone(), 4)
+                .with_input("uSourceTexture", PassInput::Pass(0)),
+            PostPass::scaled(self.downsample_materials[1].clone(), 8)
+                .with_input("uSourceTexture", PassInput::Pass(1)),
+            PostPass::scaled(self.blur_materials[0].clone(), 8)
+                .with_input("uSourceTexture", PassInput::Pass(2)),
+            PostPass::scaled(self.blur_materials[1].clone(), 8)
+                .with_input("uSourceTexture", PassInput::Pass(3)),
+            PostPass::new(self.composite_material.clone())
+                .with_input("uBloomTexture", PassInput::Pass(4)),
+        ]
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Fast approximate anti-aliasing, add it last to the stack so it
+/// smooths the final colors
+pub struct Fxaa {
+    /// Minimum local contrast to process a pixel
+    pub edge_threshold: f32,
+    /// Subpixel aliasing removal, 0 is off and 1 is the softest
+    pub subpixel: f32,
+    pub enabled: bool,
+
+    material: Rc<Material>,
+}
+
+impl Fxaa {
+    pub fn new(db: &AssetSystem) -> Fxaa {
+        Fxaa {
+            edge_threshold: 0.125,
+            subpixel: 0.75,
+            enabled: true,
+            material: Rc::new(Material::new(db.new_program("unrust/fxaa"))),
+        }
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn material(&self) -> Rc<Material> {
+        self.material.set("uEdgeThreshold", self.edge_threshold);
+        self.material.set("uSubpixel", self.subpixel);
+        self.material.clone()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Color grading through lookup tables stored as 2D strips: `size` square
+/// slices of increasing blue laid side by side, so a 16 entries LUT is a
+/// 256x16 texture. Load them with `AssetSystem::new_texture`.
+pub struct ColorGrading {
+    pub lut: Rc<Texture>,
+    /// When set, `blend` fades from `lut` to this grade instead
+    pub target_lut: Option<Rc<Texture>>,
+    /// Entries per channel of the LUTs
+    pub size: u32,
+    /// 0 keeps the source (or `lut`), 1 is fully graded (or `target_lut`)
+    pub blend: f32,
+    pub enabled: bool,
+
+    material: Rc<Material>,
+}
+
+impl ColorGrading {
+    pub fn new(db: &AssetSystem, lut: Rc<Texture>, size: u32) -> ColorGrading {
+        ColorGrading {
+            lut: lut,
+            target_lut: None,
+            size: size,
+            blend: 1.0,
+            enabled: true,
+            material: Rc::new(Material::new(db.new_program("unrust/color_grading"))),
+        }
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn material(&self) -> Rc<Material> {
+        let mat = &self.material;
+
+        mat.set("uLut", self.lut.clone());
+        mat.set("uLutSize", self.size as f32);
+        mat.set("uBlend", self.blend);
+
+        match self.target_lut {
+            Some(ref target) => {
+                mat.set("uTargetLut", target.clone());
+                mat.set("uHasTargetLut", true);
+            }
+            None => {
+                // Keep the sampler valid
+                mat.set("uTargetLut", self.lut.clone());
+                mat.set("uHasTargetLut", false);
+            }
+        }
+
+        mat.clone()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BokehQuality {
+    /// 2 rings of samples
+    Low = 2,
+    Medium = 3,
+    /// 4 rings of samples
+    High = 4,
+}
+
+/// Depth of field from the camera depth: the circle of confusion is computed
+/// at half resolution, gathered over a disk, then blended with the sharp image.
+pub struct DepthOfField {
+    /// View distance of the sharp plane
+    pub focus_distance: f32,
+    /// Larger values blur faster away from `focus_distance`
+    pub aperture: f32,
+    /// Largest blur radius at half resolution, in pixels
+    pub max_radius: f32,
+    pub quality: BokehQuality,
+    pub enabled: bool,
+
+    coc_material: Rc<Material>,
+    bokeh_material: Rc<Material>,
+    composite_material: Rc<Material>,
+}
+
+impl DepthOfField {
+    pub fn new(db: &AssetSystem) -> DepthOfField {
+        let material = |name| Rc::new(Material::new(db.new_program(name)));
+
+        DepthOfField {
+            focus_distance: 10.0,
+            aperture: 1.0,
+            max_radius: 8.0,
+            quality: BokehQuality::Medium,
+            enabled: true,
+            coc_material: material("unrust/dof_coc"),
+            bokeh_material: material("unrust/dof_bokeh"),
+            composite_material: material("unrust/dof_composite"),
+        }
+    }
+}
+
+impl PostEffect for DepthOfField {
+    fn material(&self) -> Rc<Material> {
+        self.composite_material.clone()
+    }
+
+    fn passes(&self) -> Vec<PostPass> {
+        for mat in [&self.coc_material, &self.composite_material].iter() {
+            mat.set("uFocusDistance", self.focus_distance);
+            mat.set("uAperture", self.aperture);
+        }
+
+        self.bokeh_material.set("uMaxRadius", self.max_radius);
+        self.bokeh_material.set("uRings", self.quality as i32);
+
+        vec![
+            PostPass::scaled(self.coc_material.clone(), 2),
+            PostPass::scaled(self.bokeh_material.clone(), 2)
+                .with_input("uSourceTexture", PassInput::Pass(0)),
+            PostPass::new(self.composite_material.clone())
+                .with_input("uBokehTexture", PassInput::Pass(1)),
+        ]
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Camera motion blur, each pixel is reprojected with the view projection
+/// of the last frame and blurred along its screen velocity.
+pub struct MotionBlur {
+    /// Fraction of the frame motion to blur over, 1 is a full frame shutter
+    pub intensity: f32,
+    /// Samples along the velocity, at most 16
+    pub samples: u32,
+    pub enabled: bool,
+
+    material: Rc<Material>,
+}
+
+impl MotionBlur {
+    pub fn new(db: &AssetSystem) -> MotionBlur {
+        MotionBlur {
+            intensity: 0.5,
+            samples: 8,
+            enabled: true,
+            material: Rc::new(Material::new(db.new_program("unrust/motion_blur"))),
+        }
+    }
+}
+
+impl PostEffect for MotionBlur {
+    fn material(&self) -> Rc<Material> {
+        self.material.set("uIntensity", self.intensity);
+        self.material.set("uSamples", self.samples.max(1).min(16) as i32);
+        self.material.clone()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
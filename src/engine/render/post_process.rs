@@ -0,0 +1,150 @@
+use engine::render::{Material, RenderTexture, ShaderProgram, TextureAttachment};
+use std::rc::Rc;
+
+/// A source a `PostPass` can read besides `uScreenTexture`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PassInput {
+    /// The output of an earlier pass of the same effect, by pass index.
+    /// Other indices are reported and skipped.
+    Pass(usize),
+}
+
+/// One fullscreen draw of a `PostEffect`
+pub struct PostPass {
+    pub material: Rc<Material>,
+    /// The target is the camera size divided by `scale`, ignored for the last pass
+    /// which always writes the output of the effect
+    pub scale: u32,
+    pub inputs: Vec<(&'static str, PassInput)>,
+}
+
+impl PostPass {
+    pub fn new(material: Rc<Material>) -> PostPass {
+        PostPass {
+            material: material,
+            scale: 1,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn scaled(material: Rc<Material>, scale: u32) -> PostPass {
+        PostPass {
+            scale: scale,
+            ..PostPass::new(material)
+        }
+    }
+
+    pub fn with_input(mut self, name: &'static str, input: PassInput) -> PostPass {
+        self.inputs.push((name, input));
+        self
+    }
+}
+
+/// A fullscreen effect of a `PostProcess` stack.
+///
+/// Each pass material is drawn over the "screen_quad" mesh, and its program receives:
+///
+/// * `uScreenTexture`: the output of the previous effect (the camera image for the first one)
+/// * `uDepthTexture`: the depth of the scene
+/// * `uCameraNearFar`: the clip planes of the camera, to linearize the depth
+/// * `uInvViewProjection`, `uPrevViewProjection`: to reproject the depth into the last frame
+/// * `uScreenSize`: the size of the pass target in pixels
+///
+/// See `unrust/post_process_vs.glsl` for a vertex shader to include.
+pub trait PostEffect {
+    fn material(&self) -> Rc<Material>;
+
+    /// Override for effects made of several passes, the default draws `material` once
+    fn passes(&self) -> Vec<PostPass> {
+        vec![PostPass::new(self.material())]
+    }
+
+    fn enabled(&self) -> bool {
+        true
+    }
+}
+
+/// An effect made of a single user program, set its uniforms through `material`
+pub struct MaterialEffect {
+    pub material: Rc<Material>,
+    pub enabled: bool,
+}
+
+impl MaterialEffect {
+    pub fn new(program: Rc<ShaderProgram>) -> MaterialEffect {
+        MaterialEffect {
+            material: Rc::new(Material::new(program)),
+            enabled: true,
+        }
+    }
+}
+
+impl PostEffect for MaterialEffect {
+    fn material(&self) -> Rc<Material> {
+        self.material.clone()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Add it next to a `Camera` to render the camera into an internal
+/// render texture, then apply `effects` in order before presenting.
+#[derive(Component)]
+pub struct PostProcess {
+    pub effects: Vec<Box<PostEffect>>,
+
+    // The scene with its depth, then the ping-pong targets of the effects
+    buffers: Option<((u32, u32), [Rc<RenderTexture>; 3])>,
+    // Intermediate targets of multi-pass effects, by pass index
+    pass_targets: Vec<Option<((u32, u32), Rc<RenderTexture>)>>,
+}
+
+impl PostProcess {
+    pub fn new() -> PostProcess {
+        PostProcess {
+            effects: Vec::new(),
+            buffers: None,
+            pass_targets: Vec::new(),
+        }
+    }
+
+    pub fn add<T>(&mut self, effect: T)
+    where
+        T: PostEffect + 'static,
+    {
+        self.effects.push(Box::new(effect));
+    }
+
+    pub fn buffers(&mut self, size: (u32, u32)) -> [Rc<RenderTexture>; 3] {
+        match self.buffers {
+            Some((s, ref buffers)) if s == size => return buffers.clone(),
+            _ => (),
+        }
+
+        let buffers = [
+            Rc::new(RenderTexture::new_with_depth(size.0, size.1, TextureAttachment::Color0)),
+            Rc::new(RenderTexture::new(size.0, size.1, TextureAttachment::Color0)),
+            Rc::new(RenderTexture::new(size.0, size.1, TextureAttachment::Color0)),
+        ];
+
+        self.buffers = Some((size, buffers.clone()));
+        buffers
+    }
+
+    pub fn pass_target(&mut self, index: usize, size: (u32, u32)) -> Rc<RenderTexture> {
+        if self.pass_targets.len() <= index {
+            self.pass_targets.resize(index + 1, None);
+        }
+
+        match self.pass_targets[index] {
+            Some((s, ref rt)) if s == size => return rt.clone(),
+            _ => (),
+        }
+
+        let rt = Rc::new(RenderTexture::new(size.0, size.1, TextureAttachment::Color0));
+        self.pass_targets[index] = Some((size, rt.clone()));
+        rt
+    }
+}
@@ -0,0 +1,450 @@
+use uni_gl::{WebGLProgram, WebGLRenderingContext, WebGLTexture};
+
+#[cfg(not(target_arch = "wasm32"))]
+use gl;
+#[cfg(not(target_arch = "wasm32"))]
+use std::ffi::CString;
+#[cfg(not(target_arch = "wasm32"))]
+use std::mem;
+#[cfg(target_arch = "wasm32")]
+use stdweb::unstable::TryInto;
+#[cfg(target_arch = "wasm32")]
+use stdweb::web::TypedArray;
+#[cfg(target_arch = "wasm32")]
+use stdweb::Reference;
+
+// The gl calls uni-gl has no entry point for, made on the context it created.
+// In the browser uni-gl keeps its JS objects in `Module.gl`, by the reference
+// its handles wrap; natively the context is the current desktop GL one.
+
+/// Enable the extension `name` on the context, false when the browser doesn't
+/// support it
+#[cfg(target_arch = "wasm32")]
+pub fn enable_extension(gl: &WebGLRenderingContext, name: &str) -> bool {
+    let enabled = js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        return !!(ctx && ctx.getExtension(@{name}));
+    };
+    enabled.try_into().unwrap_or(false)
+}
+
+/// Natively the context is desktop GL, whose features are core
+#[cfg(not(target_arch = "wasm32"))]
+pub fn enable_extension(_gl: &WebGLRenderingContext, _name: &str) -> bool {
+    false
+}
+
+/// `COMPLETION_STATUS_KHR` of the program, true without KHR_parallel_shader_compile
+#[cfg(target_arch = "wasm32")]
+pub fn link_completion_status(gl: &WebGLRenderingContext, prog: &WebGLProgram) -> bool {
+    let done = js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        var ext = ctx.getExtension("KHR_parallel_shader_compile");
+        return !ext || ctx.getProgramParameter(Module.gl.get(@{**prog}), ext.COMPLETION_STATUS_KHR);
+    };
+    done.try_into().unwrap_or(true)
+}
+
+/// Only the browsers report the extension
+#[cfg(not(target_arch = "wasm32"))]
+pub fn link_completion_status(_gl: &WebGLRenderingContext, _prog: &WebGLProgram) -> bool {
+    true
+}
+
+const MAX_COLOR_ATTACHMENTS: u32 = 0x8CDF;
+const MAX_DRAW_BUFFERS: u32 = 0x8824;
+
+/// The color attachments a frame buffer can draw into at once, the least of
+/// MAX_COLOR_ATTACHMENTS and MAX_DRAW_BUFFERS. WebGL2 and desktop GL only.
+#[cfg(target_arch = "wasm32")]
+pub fn max_draw_buffers(gl: &WebGLRenderingContext) -> u32 {
+    let count = js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        return Math.min(ctx.getParameter(@{MAX_COLOR_ATTACHMENTS}),
+                        ctx.getParameter(@{MAX_DRAW_BUFFERS})) || 1;
+    };
+    count.try_into().unwrap_or(1)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn max_draw_buffers(_gl: &WebGLRenderingContext) -> u32 {
+    let (mut attachments, mut buffers) = (0, 0);
+    unsafe {
+        gl::GetIntegerv(MAX_COLOR_ATTACHMENTS, &mut attachments);
+        gl::GetIntegerv(MAX_DRAW_BUFFERS, &mut buffers);
+    }
+    attachments.min(buffers).max(1) as u32
+}
+
+const UNIFORM_BUFFER: u32 = 0x8A11;
+const DYNAMIC_DRAW: u32 = 0x88E8;
+
+/// A GL buffer bound to the uniform blocks, see `UniformBlock`
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug)]
+pub struct UniformBuffer(Reference);
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct UniformBuffer(u32);
+
+#[cfg(target_arch = "wasm32")]
+pub fn create_uniform_buffer(gl: &WebGLRenderingContext) -> UniformBuffer {
+    let buffer = js! {
+        return Module.gl.get(@{gl.reference}).createBuffer();
+    };
+    UniformBuffer(buffer.into_reference().unwrap())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn create_uniform_buffer(_gl: &WebGLRenderingContext) -> UniformBuffer {
+    let mut buffer = 0;
+    unsafe {
+        gl::GenBuffers(1, &mut buffer);
+    }
+    UniformBuffer(buffer)
+}
+
+/// Replace the content of `buffer` and bind it to the uniform blocks of `binding`
+#[cfg(target_arch = "wasm32")]
+pub fn upload_uniform_buffer(
+    gl: &WebGLRenderingContext,
+    buffer: &UniformBuffer,
+    binding: u32,
+    data: &[f32],
+) {
+    let data = TypedArray::<f32>::from(data);
+    js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        ctx.bindBuffer(@{UNIFORM_BUFFER}, @{&buffer.0});
+        ctx.bufferData(@{UNIFORM_BUFFER}, @{data}, @{DYNAMIC_DRAW});
+        ctx.bindBufferBase(@{UNIFORM_BUFFER}, @{binding}, @{&buffer.0});
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn upload_uniform_buffer(
+    _gl: &WebGLRenderingContext,
+    buffer: &UniformBuffer,
+    binding: u32,
+    data: &[f32],
+) {
+    unsafe {
+        gl::BindBuffer(UNIFORM_BUFFER, buffer.0);
+        gl::BufferData(
+            UNIFORM_BUFFER,
+            (data.len() * mem::size_of::<f32>()) as isize,
+            data.as_ptr() as *const _,
+            DYNAMIC_DRAW,
+        );
+        gl::BindBufferBase(UNIFORM_BUFFER, binding, buffer.0);
+    }
+}
+
+/// Read the uniform block `name` of `prog` from the buffer bound to `binding`,
+/// false when the program has no such block
+#[cfg(target_arch = "wasm32")]
+pub fn uniform_block_binding(
+    gl: &WebGLRenderingContext,
+    prog: &WebGLProgram,
+    name: &str,
+    binding: u32,
+) -> bool {
+    let found = js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        var prog = Module.gl.get(@{**prog});
+        var index = ctx.getUniformBlockIndex(prog, @{name});
+        if (index === ctx.INVALID_INDEX) {
+            return false;
+        }
+        ctx.uniformBlockBinding(prog, index, @{binding});
+        return true;
+    };
+    found.try_into().unwrap_or(false)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn uniform_block_binding(
+    _gl: &WebGLRenderingContext,
+    prog: &WebGLProgram,
+    name: &str,
+    binding: u32,
+) -> bool {
+    let name = match CString::new(name) {
+        Ok(name) => name,
+        Err(_) => return false,
+    };
+
+    unsafe {
+        let index = gl::GetUniformBlockIndex(**prog, name.as_ptr());
+        if index == gl::INVALID_INDEX {
+            return false;
+        }
+        gl::UniformBlockBinding(**prog, index, binding);
+    }
+    true
+}
+
+/// The WebGL2 texture targets uni-gl has no binding for
+pub const TEXTURE_2D_ARRAY: u32 = 0x8C1A;
+pub const TEXTURE_3D: u32 = 0x806F;
+
+pub const TEXTURE_MAG_FILTER: u32 = 0x2800;
+pub const TEXTURE_MIN_FILTER: u32 = 0x2801;
+pub const TEXTURE_WRAP_S: u32 = 0x2802;
+pub const TEXTURE_WRAP_T: u32 = 0x2803;
+pub const TEXTURE_WRAP_R: u32 = 0x8072;
+
+/// Bind `tex` to `target`, `TEXTURE_2D_ARRAY` or `TEXTURE_3D`, of the active unit
+#[cfg(target_arch = "wasm32")]
+pub fn bind_texture_3d(gl: &WebGLRenderingContext, target: u32, tex: &WebGLTexture) {
+    js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        ctx.bindTexture(@{target}, Module.gl.get(@{**tex}));
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn bind_texture_3d(_gl: &WebGLRenderingContext, target: u32, tex: &WebGLTexture) {
+    unsafe {
+        gl::BindTexture(target, **tex);
+    }
+}
+
+/// Upload the Rgba8 texels of `size` (width, height, depth), x first then y
+/// then z, as the first level of the texture bound to `target`
+#[cfg(target_arch = "wasm32")]
+pub fn tex_image_3d(
+    gl: &WebGLRenderingContext,
+    target: u32,
+    (w, h, d): (u32, u32, u32),
+    data: &[u8],
+) {
+    let data = TypedArray::<u8>::from(data);
+    js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        // Not allowed with the typed arrays of texImage3D
+        var flip = ctx.getParameter(ctx.UNPACK_FLIP_Y_WEBGL);
+        var premultiply = ctx.getParameter(ctx.UNPACK_PREMULTIPLY_ALPHA_WEBGL);
+        ctx.pixelStorei(ctx.UNPACK_FLIP_Y_WEBGL, false);
+        ctx.pixelStorei(ctx.UNPACK_PREMULTIPLY_ALPHA_WEBGL, false);
+
+        ctx.texImage3D(@{target}, 0, ctx.RGBA8, @{w}, @{h}, @{d}, 0, ctx.RGBA,
+                       ctx.UNSIGNED_BYTE, @{data});
+
+        ctx.pixelStorei(ctx.UNPACK_FLIP_Y_WEBGL, flip);
+        ctx.pixelStorei(ctx.UNPACK_PREMULTIPLY_ALPHA_WEBGL, premultiply);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn tex_image_3d(
+    _gl: &WebGLRenderingContext,
+    target: u32,
+    (w, h, d): (u32, u32, u32),
+    data: &[u8],
+) {
+    unsafe {
+        gl::TexImage3D(
+            target,
+            0,
+            gl::RGBA8 as i32,
+            w as i32,
+            h as i32,
+            d as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            data.as_ptr() as *const _,
+        );
+    }
+}
+
+/// Upload a level of the compressed texels of `internal_format`, one uni-gl
+/// has no `TextureCompression` for, to `target` of the bound texture
+#[cfg(target_arch = "wasm32")]
+pub fn compressed_tex_image_2d(
+    gl: &WebGLRenderingContext,
+    target: u32,
+    level: u32,
+    internal_format: u32,
+    (w, h): (u32, u32),
+    data: &[u8],
+) {
+    let data = TypedArray::<u8>::from(data);
+    js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        ctx.compressedTexImage2D(@{target}, @{level}, @{internal_format}, @{w}, @{h}, 0,
+                                 @{data});
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compressed_tex_image_2d(
+    _gl: &WebGLRenderingContext,
+    target: u32,
+    level: u32,
+    internal_format: u32,
+    (w, h): (u32, u32),
+    data: &[u8],
+) {
+    unsafe {
+        gl::CompressedTexImage2D(
+            target,
+            level as i32,
+            internal_format,
+            w as i32,
+            h as i32,
+            0,
+            data.len() as i32,
+            data.as_ptr() as *const _,
+        );
+    }
+}
+
+/// Upload the Rgba f32 texels of `size` as the first level of `target` of the
+/// bound texture, stored as RGBA16F, which WebGL2 filters linearly
+#[cfg(target_arch = "wasm32")]
+pub fn tex_image_2d_half_float(
+    gl: &WebGLRenderingContext,
+    target: u32,
+    (w, h): (u32, u32),
+    data: &[f32],
+) {
+    let data = TypedArray::<f32>::from(data);
+    js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        ctx.texImage2D(@{target}, 0, ctx.RGBA16F, @{w}, @{h}, 0, ctx.RGBA, ctx.FLOAT, @{data});
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn tex_image_2d_half_float(
+    _gl: &WebGLRenderingContext,
+    target: u32,
+    (w, h): (u32, u32),
+    data: &[f32],
+) {
+    unsafe {
+        gl::TexImage2D(
+            target,
+            0,
+            gl::RGBA16F as i32,
+            w as i32,
+            h as i32,
+            0,
+            gl::RGBA,
+            gl::FLOAT,
+            data.as_ptr() as *const _,
+        );
+    }
+}
+
+/// Set a parameter of the texture bound to `target`
+#[cfg(target_arch = "wasm32")]
+pub fn tex_parameteri_3d(gl: &WebGLRenderingContext, target: u32, pname: u32, value: i32) {
+    js! {
+        Module.gl.get(@{gl.reference}).texParameteri(@{target}, @{pname}, @{value});
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn tex_parameteri_3d(_gl: &WebGLRenderingContext, target: u32, pname: u32, value: i32) {
+    unsafe {
+        gl::TexParameteri(target, pname, value);
+    }
+}
+
+/// A float parameter of the texture bound to `target`
+#[cfg(target_arch = "wasm32")]
+pub fn tex_parameterf(gl: &WebGLRenderingContext, target: u32, pname: u32, value: f32) {
+    js! {
+        Module.gl.get(@{gl.reference}).texParameterf(@{target}, @{pname}, @{value});
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn tex_parameterf(_gl: &WebGLRenderingContext, target: u32, pname: u32, value: f32) {
+    unsafe {
+        gl::TexParameterf(target, pname, value);
+    }
+}
+
+pub const TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY_EXT: u32 = 0x84FF;
+
+/// Enable EXT_texture_filter_anisotropic, and return the most samples a
+/// texture can be filtered with, 1 without the extension
+#[cfg(target_arch = "wasm32")]
+pub fn max_anisotropy(gl: &WebGLRenderingContext) -> f32 {
+    let max = js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        var ext = ctx.getExtension("EXT_texture_filter_anisotropic") ||
+                  ctx.getExtension("WEBKIT_EXT_texture_filter_anisotropic");
+        return ext ? ctx.getParameter(@{MAX_TEXTURE_MAX_ANISOTROPY_EXT}) : 1;
+    };
+    let max: f64 = max.try_into().unwrap_or(1.0);
+    max as f32
+}
+
+/// Core in GL 4.6, an extension every desktop driver has before
+#[cfg(not(target_arch = "wasm32"))]
+pub fn max_anisotropy(_gl: &WebGLRenderingContext) -> f32 {
+    let mut max = 1.0;
+    unsafe {
+        // Only the error of the query itself tells the extension is missing
+        while gl::GetError() != gl::NO_ERROR {}
+        gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut max);
+        if gl::GetError() != gl::NO_ERROR {
+            return 1.0;
+        }
+    }
+    max.max(1.0)
+}
+
+/// Generate the mipmaps of the texture bound to `target` from its first level
+#[cfg(target_arch = "wasm32")]
+pub fn generate_mipmap_3d(gl: &WebGLRenderingContext, target: u32) {
+    js! {
+        Module.gl.get(@{gl.reference}).generateMipmap(@{target});
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn generate_mipmap_3d(_gl: &WebGLRenderingContext, target: u32) {
+    unsafe {
+        gl::GenerateMipmap(target);
+    }
+}
+
+/// The context loss events of the canvas since the last call, true for a
+/// `webglcontextlost`, whose default is prevented so the browser restores the
+/// context, and false for a `webglcontextrestored`. The listeners are added
+/// on the first call.
+#[cfg(target_arch = "wasm32")]
+pub fn take_context_events(gl: &WebGLRenderingContext) -> Vec<bool> {
+    let events = js! {
+        var ctx = Module.gl.get(@{gl.reference});
+        if (!ctx.unrustContextEvents) {
+            ctx.unrustContextEvents = [];
+            ctx.canvas.addEventListener("webglcontextlost", function(e) {
+                e.preventDefault();
+                ctx.unrustContextEvents.push(true);
+            }, false);
+            ctx.canvas.addEventListener("webglcontextrestored", function() {
+                ctx.unrustContextEvents.push(false);
+            }, false);
+        }
+
+        var events = ctx.unrustContextEvents;
+        ctx.unrustContextEvents = [];
+        return events;
+    };
+    events.try_into().unwrap_or_default()
+}
+
+/// The desktop GL contexts are not lost
+#[cfg(not(target_arch = "wasm32"))]
+pub fn take_context_events(_gl: &WebGLRenderingContext) -> Vec<bool> {
+    Vec::new()
+}
@@ -0,0 +1,161 @@
+use engine::render::{RenderTexture, ShaderProgram, SkyboxSource, Texture, TextureAttachment};
+use math::*;
+use std::f32;
+use std::rc::Rc;
+
+/// Roughness levels of the prefiltered specular map, from 0 to 1
+pub const PROBE_SPECULAR_LEVELS: u32 = 5;
+/// Face size of the diffuse irradiance map
+pub const PROBE_IRRADIANCE_SIZE: u32 = 16;
+
+/// The maps of a probe, cube maps stored as rows of 6 faces in the order
+/// +X, -X, +Y, -Y, +Z, -Z, see "unrust/cube_atlas.glsl"
+#[derive(Clone)]
+pub struct ProbeMaps {
+    /// Face size of `capture` and `specular`
+    pub size: u32,
+    /// The captured surroundings
+    pub capture: Rc<RenderTexture>,
+    /// Cosine convolution of `capture`, for the diffuse lighting
+    pub irradiance: Rc<RenderTexture>,
+    /// GGX prefiltered `capture`, one row per roughness level
+    pub specular: Rc<RenderTexture>,
+}
+
+impl ProbeMaps {
+    fn new(size: u32) -> ProbeMaps {
+        ProbeMaps {
+            size,
+            capture: Rc::new(RenderTexture::new_with_depth(
+                size * 6,
+                size,
+                TextureAttachment::Color0,
+            )),
+            irradiance: Rc::new(RenderTexture::new(
+                PROBE_IRRADIANCE_SIZE * 6,
+                PROBE_IRRADIANCE_SIZE,
+                TextureAttachment::Color0,
+            )),
+            specular: Rc::new(RenderTexture::new(
+                size * 6,
+                size * PROBE_SPECULAR_LEVELS,
+                TextureAttachment::Color0,
+            )),
+        }
+    }
+
+    /// Bind the uniforms, the textures go through the engine texture units
+    pub fn bind(&self, prog: &ShaderProgram) {
+        prog.set("uIBLEnabled", true);
+        prog.set(
+            "uIrradianceMapSize",
+            Vector2f::new((PROBE_IRRADIANCE_SIZE * 6) as f32, PROBE_IRRADIANCE_SIZE as f32),
+        );
+        prog.set(
+            "uSpecularMapSize",
+            Vector2f::new(
+                (self.size * 6) as f32,
+                (self.size * PROBE_SPECULAR_LEVELS) as f32,
+            ),
+        );
+        prog.set("uSpecularLevels", PROBE_SPECULAR_LEVELS as f32);
+    }
+}
+
+/// Captures its surroundings for the image based lighting of the PBR
+/// materials, the probe nearest to the camera is used. The capture is
+/// baked on demand, not every frame.
+#[derive(Component)]
+pub struct ReflectionProbe {
+    /// Face size of the captured cube map in pixels
+    pub resolution: u32,
+    /// Distance from the probe where it is preferred over the others
+    pub radius: f32,
+    /// Bit mask of the GameObject layers in the capture
+    pub culling_mask: u32,
+
+    environment: Option<Rc<Texture>>,
+    needs_bake: bool,
+    maps: Option<ProbeMaps>,
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> ReflectionProbe {
+        ReflectionProbe {
+            resolution: 128,
+            radius: 10.0,
+            culling_mask: !0,
+            environment: None,
+            needs_bake: true,
+            maps: None,
+        }
+    }
+}
+
+impl ReflectionProbe {
+    pub fn new() -> ReflectionProbe {
+        Default::default()
+    }
+
+    /// A probe lit by the equirectangular (latitude-longitude) image of `source`,
+    /// e.g. a .hdr file, instead of the scene. The image is rendered into the
+    /// capture on the GPU once it is loaded, then convolved like a captured
+    /// scene. Its radius is infinite so the other probes are preferred in
+    /// their range, and `skybox_source` draws the same image behind the scene.
+    pub fn from_equirect(source: Rc<Texture>, resolution: u32) -> ReflectionProbe {
+        ReflectionProbe {
+            resolution,
+            radius: f32::INFINITY,
+            environment: Some(source),
+            ..Default::default()
+        }
+    }
+
+    /// The equirectangular image of a probe created with `from_equirect`
+    pub fn environment(&self) -> Option<&Rc<Texture>> {
+        self.environment.as_ref()
+    }
+
+    /// A skybox of the capture, filled by the bakes. The maps are recreated
+    /// when the resolution changes, so the skybox should be too.
+    pub fn skybox_source(&mut self) -> SkyboxSource {
+        SkyboxSource::Probe(self.current_maps())
+    }
+
+    /// The maps of the resolution, recreated when it changed
+    fn current_maps(&mut self) -> ProbeMaps {
+        let size = self.resolution.max(1);
+        if self.maps.as_ref().map(|m| m.size) != Some(size) {
+            self.maps = Some(ProbeMaps::new(size));
+        }
+
+        self.maps.clone().unwrap()
+    }
+
+    /// Capture the surroundings again on the next frame
+    pub fn bake(&mut self) {
+        self.needs_bake = true;
+    }
+
+    /// The maps of the last bake
+    pub fn maps(&self) -> Option<&ProbeMaps> {
+        match self.maps {
+            Some(ref maps) if !self.needs_bake => Some(maps),
+            _ => None,
+        }
+    }
+
+    /// The maps to render when a bake was requested, recreated when
+    /// the resolution changed. They are not used until `end_bake`.
+    pub fn begin_bake(&mut self) -> Option<ProbeMaps> {
+        if !self.needs_bake {
+            return None;
+        }
+
+        Some(self.current_maps())
+    }
+
+    pub fn end_bake(&mut self) {
+        self.needs_bake = false;
+    }
+}
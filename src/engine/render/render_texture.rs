@@ -1,4 +1,4 @@
-use engine::render::{Texture, TextureAttachment};
+use engine::render::{Capabilities, Texture, TextureAttachment};
 use std::rc::Rc;
 use std::ops::Deref;
 use engine::render::frame_buffer::FrameBuffer;
@@ -19,16 +19,62 @@ impl RenderTexture {
         RenderTexture(FrameBuffer::new(width, height, attach))
     }
 
-    pub fn bind_frame_buffer(&self, gl: &WebGLRenderingContext) {
-        self.0.prepare(gl);
-        self.0.bind(gl);
+    /// Color texture with a depth texture attached, for rendering whole scenes
+    pub fn new_with_depth(width: u32, height: u32, attach: TextureAttachment) -> RenderTexture {
+        RenderTexture(FrameBuffer::new_with_depth(width, height, attach))
+    }
+
+    /// Several color outputs for multiple render targets, see `MaterialState::draw_buffers`
+    pub fn new_mrt(
+        width: u32,
+        height: u32,
+        attachments: &[TextureAttachment],
+        with_depth: bool,
+    ) -> RenderTexture {
+        RenderTexture(FrameBuffer::new_mrt(
+            width,
+            height,
+            attachments,
+            with_depth,
+        ))
+    }
+
+    pub fn bind_frame_buffer(&self, gl: &WebGLRenderingContext, caps: &Capabilities) {
+        self.0.prepare(gl, caps);
+        self.0.bind(gl, caps);
     }
 
     pub fn unbind_frame_buffer(&self, gl: &WebGLRenderingContext) {
         self.0.unbind(gl);
+        self.0.generate_mipmaps(gl);
+    }
+
+    /// Generate the mipmaps of the color textures after each render,
+    /// to call before the first render
+    pub fn set_mipmaps(&self, enabled: bool) {
+        self.0.texture.set_render_mipmaps(enabled);
+        for tex in self.0.extra_textures.iter() {
+            tex.set_render_mipmaps(enabled);
+        }
     }
 
     pub fn as_texture(&self) -> Rc<Texture> {
         self.0.texture.clone()
     }
+
+    pub fn depth_texture(&self) -> Option<Rc<Texture>> {
+        self.0.depth.clone()
+    }
+
+    /// The color texture at output `location`
+    pub fn color_texture(&self, location: usize) -> Option<Rc<Texture>> {
+        match location {
+            0 => Some(self.0.texture.clone()),
+            n => self.0.extra_textures.get(n - 1).cloned(),
+        }
+    }
+
+    pub fn color_count(&self) -> usize {
+        self.0.color_count()
+    }
 }
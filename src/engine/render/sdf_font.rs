@@ -0,0 +1,63 @@
+use engine::asset::{Asset, AssetSystem, FileFuture, LoadableAsset, Resource};
+use engine::render::Texture;
+use math::*;
+use std::cell::Ref;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A glyph of a `SdfFont`, the metrics are in ems
+#[derive(Copy, Clone, Debug)]
+pub struct SdfGlyph {
+    /// The (top left, bottom right) uv in the atlas
+    pub uv: (Vector2f, Vector2f),
+    /// From the pen position on the baseline to the top left of the quad
+    pub offset: Vector2f,
+    pub size: Vector2f,
+    pub advance: f32,
+}
+
+#[derive(Debug)]
+pub struct SdfFontData {
+    /// The distance field in alpha, 0.5 on the glyph edges
+    pub texture: Rc<Texture>,
+    pub glyphs: HashMap<char, SdfGlyph>,
+    /// Added to the advance between two characters
+    pub kerning: HashMap<(char, char), f32>,
+    pub line_height: f32,
+    /// The distance covered by the field on each side of the edges
+    pub spread: f32,
+}
+
+/// A signed distance field font generated from a TTF file by
+/// `AssetSystem::new_font`, sharp at any size, see `TextMesh`
+pub struct SdfFont {
+    data: Resource<SdfFontData>,
+}
+
+impl Asset for SdfFont {
+    type Resource = Resource<SdfFontData>;
+
+    fn new_from_resource(r: Self::Resource) -> Rc<Self> {
+        Rc::new(SdfFont { data: r })
+    }
+}
+
+impl LoadableAsset for SdfFont {
+    fn load<T>(asys: &T, mut files: Vec<FileFuture>) -> Self::Resource
+    where
+        T: AssetSystem + Clone + 'static,
+    {
+        Self::load_resource::<SdfFontData, T>(asys.clone(), files.remove(0))
+    }
+
+    fn gather<T: AssetSystem>(asys: &T, fname: &str) -> Vec<FileFuture> {
+        vec![asys.new_file(fname)]
+    }
+}
+
+impl SdfFont {
+    /// None while loading
+    pub fn data(&self) -> Option<Ref<SdfFontData>> {
+        self.data.try_borrow().ok()
+    }
+}
@@ -1,10 +1,10 @@
-use uni_glsl::preprocessor;
 use uni_glsl::preprocessor::PreprocessError;
 
 //use uni_glsl::parser;
 // use uni_glsl::TypeQualifier;
 // use uni_glsl::query::*;
 
+use engine::asset::{AssetError, AssetResult};
 use uni_gl;
 use std::collections::HashMap;
 use std::marker::PhantomData;
@@ -35,20 +35,535 @@ impl ShaderKindProvider for ShaderKindFs {
     }
 }
 
+/// The file and line (from 1) a line of the expanded source comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+}
+
 #[derive(Debug)]
-pub struct PreprocessedShaderCode(String);
+pub struct PreprocessedShaderCode {
+    code: String,
+    prefix_lines: usize,
+    lines: Vec<SourceLocation>,
+
+    // kept around to build the keyword variants
+    kind: ShaderKind,
+    filename: String,
+    source: String,
+    external_files: HashMap<String, String>,
+}
+
+/// Files included deeper than this are an include cycle, the files guarded
+/// by an `#ifndef` can include each other
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+fn include_name(line: &str) -> Option<&str> {
+    let rest = line.trim();
+
+    if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+        Some(&rest[1..rest.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// The name and the rest of a `# name rest` directive line
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_left();
+    if !line.starts_with('#') {
+        return None;
+    }
+
+    let line = line[1..].trim_left();
+    let name = identifier(line);
+
+    Some((name, line[name.len()..].trim()))
+}
+
+/// The leading identifier of `s`, e.g. the macro of `#ifdef NAME`
+fn identifier(s: &str) -> &str {
+    let end = s.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&'static str; 13] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "(", ")",
+];
+
+fn tokenize(s: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_left();
+
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+
+        if c.is_digit(10) {
+            let end = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+            let digits = rest[..end].trim_right_matches(|c| c == 'u' || c == 'U');
+            tokens.push(Token::Number(digits.parse().ok()?));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let name = identifier(rest);
+            tokens.push(Token::Ident(name.to_owned()));
+            rest = &rest[name.len()..];
+        } else {
+            let op = OPERATORS.iter().find(|op| rest.starts_with(*op))?;
+            tokens.push(Token::Op(*op));
+            rest = &rest[op.len()..];
+        }
+
+        rest = rest.trim_left();
+    }
+
+    Some(tokens)
+}
+
+/// Evaluates the expression of an `#if` or `#elif`, the macros without
+/// an integer value are 0
+struct Condition<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    defines: &'a HashMap<String, String>,
+    depth: usize,
+}
+
+impl<'a> Condition<'a> {
+    fn eval(expr: &str, defines: &'a HashMap<String, String>, depth: usize) -> Option<i64> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return None;
+        }
+
+        let mut cond = Condition {
+            tokens: tokenize(expr)?,
+            pos: 0,
+            defines,
+            depth,
+        };
+
+        let value = cond.or()?;
+        if cond.pos == cond.tokens.len() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn accept(&mut self, op: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(&Token::Op(o)) if o == op => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Option<i64> {
+        let mut value = self.and()?;
+        while self.accept("||") {
+            let rhs = self.and()?;
+            value = (value != 0 || rhs != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn and(&mut self) -> Option<i64> {
+        let mut value = self.compare()?;
+        while self.accept("&&") {
+            let rhs = self.compare()?;
+            value = (value != 0 && rhs != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn compare(&mut self) -> Option<i64> {
+        let mut value = self.sum()?;
+        loop {
+            value = if self.accept("==") {
+                (value == self.sum()?) as i64
+            } else if self.accept("!=") {
+                (value != self.sum()?) as i64
+            } else if self.accept("<=") {
+                (value <= self.sum()?) as i64
+            } else if self.accept(">=") {
+                (value >= self.sum()?) as i64
+            } else if self.accept("<") {
+                (value < self.sum()?) as i64
+            } else if self.accept(">") {
+                (value > self.sum()?) as i64
+            } else {
+                return Some(value);
+            };
+        }
+    }
+
+    /// None on overflow too, like the other expressions left to the driver
+    fn sum(&mut self) -> Option<i64> {
+        let mut value = self.unary()?;
+        loop {
+            value = if self.accept("+") {
+                value.checked_add(self.unary()?)?
+            } else if self.accept("-") {
+                value.checked_sub(self.unary()?)?
+            } else {
+                return Some(value);
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        if self.accept("!") {
+            Some((self.unary()? == 0) as i64)
+        } else if self.accept("-") {
+            self.unary()?.checked_neg()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Option<i64> {
+        match self.next()? {
+            Token::Number(n) => Some(n),
+            Token::Op("(") => {
+                let value = self.or()?;
+                if self.accept(")") {
+                    Some(value)
+                } else {
+                    None
+                }
+            }
+            Token::Ident(ref name) if name == "defined" => {
+                let paren = self.accept("(");
+                let defined = match self.next()? {
+                    Token::Ident(name) => self.defines.contains_key(&name),
+                    _ => return None,
+                };
+                if paren && !self.accept(")") {
+                    return None;
+                }
+                Some(defined as i64)
+            }
+            Token::Ident(name) => match self.defines.get(&name) {
+                Some(value) if !value.trim().is_empty() => {
+                    Condition::eval(value, self.defines, self.depth + 1)
+                }
+                _ => Some(0),
+            },
+            Token::Op(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Branch {
+    /// The enclosing branch is active
+    parent: bool,
+    active: bool,
+    /// One of the branches of the `#if` was taken already
+    taken: bool,
+    /// The condition can't be evaluated here, the directives are left to the
+    /// driver and every branch is expanded
+    undecided: bool,
+}
+
+/// Expands the `#include` lines and resolves the conditionals, in one pass
+/// which records where each line of the output comes from. Only the code of
+/// the active branches is kept, with the `#define`, `#extension` and the
+/// other directives the driver preprocesses.
+struct IncludeExpander<'a> {
+    external_files: &'a HashMap<String, String>,
+    defines: HashMap<String, String>,
+    branches: Vec<Branch>,
+    stack: Vec<String>,
+    out: String,
+    /// The location of each line of `out`
+    lines: Vec<SourceLocation>,
+}
+
+impl<'a> IncludeExpander<'a> {
+    fn new(
+        external_files: &'a HashMap<String, String>,
+        defines: HashMap<String, String>,
+    ) -> IncludeExpander<'a> {
+        IncludeExpander {
+            external_files,
+            defines,
+            branches: Vec::new(),
+            stack: Vec::new(),
+            out: String::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.branches.last().map_or(true, |b| b.active)
+    }
+
+    fn push_line(&mut self, line: &str, filename: &str, i: usize) {
+        self.out.push_str(line);
+        self.out.push('\n');
+        self.lines.push(SourceLocation {
+            file: filename.to_owned(),
+            line: i + 1,
+        });
+    }
+
+    /// The branch of an `#ifdef`, `#ifndef` or `#if` line
+    fn open_branch(&self, name: &str, rest: &str) -> Branch {
+        let parent = self.active();
+        let value = match name {
+            _ if !parent => Some(false),
+            "ifdef" => Some(self.defines.contains_key(identifier(rest))),
+            "ifndef" => Some(!self.defines.contains_key(identifier(rest))),
+            _ => Condition::eval(rest, &self.defines, 0).map(|v| v != 0),
+        };
+
+        Branch {
+            parent,
+            active: value.unwrap_or(true),
+            taken: value.unwrap_or(false),
+            undecided: value.is_none(),
+        }
+    }
+
+    fn expand(&mut self, filename: &str, source: &str) -> Result<(), PreprocessError> {
+        if self.stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(PreprocessError::ParseError(format!(
+                "include cycle {} -> {}",
+                self.stack.join(" -> "),
+                filename
+            )));
+        }
+
+        self.stack.push(filename.to_owned());
+
+        for (i, line) in source.lines().enumerate() {
+            let active = self.active();
+
+            let (name, rest) = match directive(line) {
+                Some(d) => d,
+                None => {
+                    if active {
+                        self.push_line(line, filename, i);
+                    }
+                    continue;
+                }
+            };
+
+            match name {
+                "include" if active => {
+                    let name = include_name(rest).ok_or_else(|| {
+                        PreprocessError::ParseError(format!(
+                            "{}:{}: malformed #include",
+                            filename,
+                            i + 1
+                        ))
+                    })?;
+
+                    let external_files = self.external_files;
+                    let included = external_files
+                        .get(name)
+                        .ok_or_else(|| PreprocessError::MissingFile(name.to_owned()))?;
+
+                    self.expand(name, included)?;
+                }
+                "define" if active => {
+                    let name = identifier(rest);
+                    let value = rest[name.len()..].trim();
+                    self.defines.insert(name.to_owned(), value.to_owned());
+                    self.push_line(line, filename, i);
+                }
+                "undef" if active => {
+                    self.defines.remove(identifier(rest));
+                    self.push_line(line, filename, i);
+                }
+                "ifdef" | "ifndef" | "if" => {
+                    let b = self.open_branch(name, rest);
+                    if b.undecided {
+                        self.push_line(line, filename, i);
+                    }
+                    self.branches.push(b);
+                }
+                "elif" => {
+                    let b = match self.branches.last().cloned() {
+                        Some(b) => b,
+                        None => continue,
+                    };
+
+                    if b.undecided {
+                        self.push_line(line, filename, i);
+                        continue;
+                    }
+
+                    let value = if b.parent && !b.taken {
+                        Condition::eval(rest, &self.defines, 0).map(|v| v != 0)
+                    } else {
+                        Some(false)
+                    };
+
+                    // The driver resolves this branch and the following ones
+                    if value.is_none() {
+                        self.push_line(&format!("#if {}", rest), filename, i);
+                    }
+
+                    *self.branches.last_mut().unwrap() = Branch {
+                        active: value.unwrap_or(true),
+                        taken: b.taken || value.unwrap_or(false),
+                        undecided: value.is_none(),
+                        ..b
+                    };
+                }
+                "else" => {
+                    let undecided = self.branches.last().map_or(false, |b| b.undecided);
+                    if undecided {
+                        self.push_line(line, filename, i);
+                    } else if let Some(b) = self.branches.last_mut() {
+                        b.active = b.parent && !b.taken;
+                        b.taken = true;
+                    }
+                }
+                "endif" => {
+                    if self.branches.pop().map_or(false, |b| b.undecided) {
+                        self.push_line(line, filename, i);
+                    }
+                }
+                // e.g. #extension, left to the driver
+                _ => {
+                    if active {
+                        self.push_line(line, filename, i);
+                    }
+                }
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// The span and value of the line number of a compile log line
+fn log_line_number(line: &str) -> Option<(usize, usize, usize)> {
+    let digits = |s: &str| s.find(|c: char| !c.is_digit(10)).unwrap_or(s.len());
+
+    for (start, _) in line.match_indices('0') {
+        if line[..start].ends_with(|c: char| c.is_alphanumeric()) {
+            continue;
+        }
+
+        let rest = &line[start + 1..];
+        let (open, close) = if rest.starts_with(':') {
+            (':', ':')
+        } else if rest.starts_with('(') {
+            ('(', ')')
+        } else {
+            continue;
+        };
+
+        let number = &rest[open.len_utf8()..];
+        let len = digits(number);
+        if len == 0 || !number[len..].starts_with(close) {
+            continue;
+        }
+
+        // `0(12)` is replaced whole, the `:` after `0:12` is kept
+        let end = start + 1 + open.len_utf8() + len + if close == ')' { 1 } else { 0 };
+        return number[..len].parse().ok().map(|n| (start, end, n));
+    }
+
+    None
+}
 
 impl PreprocessedShaderCode {
     pub fn as_string(&self) -> &String {
-        &self.0
+        &self.code
+    }
+
+    /// The names of the files pulled in with `#include`
+    pub fn included_files(&self) -> Vec<String> {
+        self.external_files.keys().cloned().collect()
+    }
+
+    /// Map a line (from 1) of the code handed to the driver, as found in
+    /// compile logs, back to its file after the includes were expanded
+    pub fn source_location(&self, line: usize) -> Option<&SourceLocation> {
+        if line <= self.prefix_lines {
+            return None;
+        }
+
+        self.lines.get(line - self.prefix_lines - 1)
+    }
+
+    /// Replace the line numbers of a compile log, `0:12` (ANGLE, Mesa) or
+    /// `0(12)` (NVIDIA), with the file and line they come from
+    pub fn remap_log(&self, log: &str) -> String {
+        let mut out = String::new();
+
+        for line in log.lines() {
+            match log_line_number(line) {
+                Some((start, end, n)) => match self.source_location(n) {
+                    Some(loc) => {
+                        out.push_str(&line[..start]);
+                        out.push_str(&format!("{}:{}", loc.file, loc.line));
+                        out.push_str(&line[end..]);
+                    }
+                    None => out.push_str(line),
+                },
+                None => out.push_str(line),
+            }
+            out.push('\n');
+        }
+
+        out
     }
 
     pub fn new(
         kind: ShaderKind,
+        filename: &str,
         s: &str,
         external_files: &HashMap<String, String>,
     ) -> Result<PreprocessedShaderCode, PreprocessError> {
-        let prefix = match kind {
+        Self::new_with_defines(kind, filename, s, external_files, &[])
+    }
+
+    /// Preprocess the same source again, with each keyword in `defines` defined,
+    /// "NAME=VALUE" defines NAME as VALUE
+    pub fn with_defines(
+        &self,
+        defines: &[String],
+    ) -> Result<PreprocessedShaderCode, PreprocessError> {
+        Self::new_with_defines(
+            self.kind,
+            &self.filename,
+            &self.source,
+            &self.external_files,
+            defines,
+        )
+    }
+
+    fn new_with_defines(
+        kind: ShaderKind,
+        filename: &str,
+        s: &str,
+        external_files: &HashMap<String, String>,
+        defines: &[String],
+    ) -> Result<PreprocessedShaderCode, PreprocessError> {
+        let mut prefix = match kind {
             ShaderKind::Vertex => if !uni_gl::IS_GL_ES {
                 "#version 150\n".to_owned()
             } else {
@@ -75,9 +590,33 @@ impl PreprocessedShaderCode {
             predefs.insert("GL_ES".to_string(), "".to_string());
         }
 
-        let processed = preprocessor::preprocess(&s, &predefs, external_files);
+        for define in defines {
+            match define.find('=') {
+                Some(i) => predefs.insert(define[..i].to_string(), define[i + 1..].to_string()),
+                None => predefs.insert(define.clone(), "".to_string()),
+            };
+        }
+
+        // The driver defines GL_ES itself, and rejects the other GL_ macros
+        let mut names: Vec<_> = predefs.keys().filter(|name| *name != "GL_ES").collect();
+        names.sort();
+        for name in names {
+            prefix += &format!("#define {} {}\n", name, predefs[name]);
+        }
+
+        let mut expander = IncludeExpander::new(external_files, predefs.clone());
+        expander.expand(filename, s)?;
 
-        processed.map(|s| PreprocessedShaderCode(prefix + &s))
+        Ok(PreprocessedShaderCode {
+            prefix_lines: prefix.lines().count(),
+            code: prefix + &expander.out,
+            lines: expander.lines,
+
+            kind: kind,
+            filename: filename.to_owned(),
+            source: s.to_owned(),
+            external_files: external_files.clone(),
+        })
     }
 }
 
@@ -97,7 +636,12 @@ where
     T: ShaderKindProvider,
 {
     pub fn new(filename: &str, s: &str) -> Shader<T> {
-        let code = PreprocessedShaderCode::new(T::kind(), s, &HashMap::new()).unwrap();
+        Self::with_includes(filename, s, &HashMap::new())
+    }
+
+    /// A shader whose `#include` files are given, e.g. embedded in the executable
+    pub fn with_includes(filename: &str, s: &str, files: &HashMap<String, String>) -> Shader<T> {
+        let code = PreprocessedShaderCode::new(T::kind(), filename, s, files).unwrap();
 
         Shader {
             //unit: unit,
@@ -107,6 +651,19 @@ where
         }
     }
 
+    /// This shader preprocessed again with each keyword in `defines` defined
+    pub fn with_defines(&self, defines: &[String]) -> AssetResult<Shader<T>> {
+        let code = self.code
+            .with_defines(defines)
+            .map_err(|e| AssetError::InvalidFormat {
+                path: self.filename.clone(),
+                len: self.code.source.len(),
+                reason: format!("{:?}", e),
+            })?;
+
+        Ok(Shader::from_preprocessed(&self.filename, code))
+    }
+
     pub fn from_preprocessed(filename: &str, code: PreprocessedShaderCode) -> Shader<T> {
         uni_gl::print(&format!("preprocessing {}...\n", filename));
 
@@ -125,3 +682,167 @@ where
     //         .is_some()
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defines(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn preprocess(
+        source: &str,
+        files: &[(&str, &str)],
+    ) -> Result<PreprocessedShaderCode, PreprocessError> {
+        PreprocessedShaderCode::new(ShaderKind::Vertex, "main.glsl", source, &defines(files))
+    }
+
+    /// The lines after the prefix, with the file and line each comes from
+    fn code_lines(code: &PreprocessedShaderCode) -> Vec<(String, String, usize)> {
+        code.as_string()
+            .lines()
+            .enumerate()
+            .skip(code.prefix_lines)
+            .map(|(i, line)| {
+                let loc = code.source_location(i + 1).unwrap();
+                (line.to_string(), loc.file.clone(), loc.line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn condition_eval() {
+        let d = defines(&[("A", "1"), ("B", ""), ("C", "A + 2"), ("R", "R")]);
+
+        assert_eq!(Condition::eval("A && defined(B)", &d, 0), Some(1));
+        assert_eq!(Condition::eval("defined B && !defined D", &d, 0), Some(1));
+        assert_eq!(Condition::eval("C == 3", &d, 0), Some(1));
+        assert_eq!(Condition::eval("(1 + 2) > 3 || -A < 0", &d, 0), Some(1));
+        assert_eq!(Condition::eval("B || D", &d, 0), Some(0));
+        assert_eq!(Condition::eval("100u >= 99", &d, 0), Some(1));
+
+        assert_eq!(Condition::eval("A +", &d, 0), None);
+        assert_eq!(Condition::eval("(A", &d, 0), None);
+        assert_eq!(Condition::eval("F(1)", &d, 0), None);
+        assert_eq!(Condition::eval("A * 2", &d, 0), None);
+        assert_eq!(Condition::eval("R", &d, 0), None);
+
+        // Overflows
+        assert_eq!(Condition::eval("9223372036854775807 + 1", &d, 0), None);
+        assert_eq!(Condition::eval("-9223372036854775807 - 2", &d, 0), None);
+        assert_eq!(Condition::eval("-(-9223372036854775807 - 1)", &d, 0), None);
+    }
+
+    #[test]
+    fn log_line_numbers() {
+        assert_eq!(
+            log_line_number("ERROR: 0:12: 'x' : undeclared identifier"),
+            Some((7, 11, 12))
+        );
+        assert_eq!(
+            log_line_number("0(34) : error C1008: undefined variable"),
+            Some((0, 5, 34))
+        );
+        assert_eq!(log_line_number("main.glsl:10:4 error"), None);
+        assert_eq!(log_line_number("0:x: error"), None);
+        assert_eq!(log_line_number("no line number"), None);
+    }
+
+    #[test]
+    fn include_guard() {
+        let guarded = "#ifndef GUARD\n#define GUARD\nfloat a;\n#include \"a.glsl\"\n#endif\n";
+        let code = preprocess(
+            "#include \"a.glsl\"\n#include \"a.glsl\"\nvoid main() {}\n",
+            &[("a.glsl", guarded)],
+        ).unwrap();
+
+        assert_eq!(
+            code_lines(&code),
+            vec![
+                ("#define GUARD".to_string(), "a.glsl".to_string(), 2),
+                ("float a;".to_string(), "a.glsl".to_string(), 3),
+                ("void main() {}".to_string(), "main.glsl".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn include_cycle() {
+        let r = preprocess(
+            "#include \"a.glsl\"\n",
+            &[("a.glsl", "#include \"b.glsl\"\n"), ("b.glsl", "#include \"a.glsl\"\n")],
+        );
+
+        match r {
+            Err(PreprocessError::ParseError(ref e)) => assert!(e.starts_with("include cycle")),
+            _ => panic!("the cycle is not detected"),
+        }
+    }
+
+    #[test]
+    fn inactive_includes() {
+        let code = preprocess(
+            "#ifdef MISSING\n#include \"missing.glsl\"\n#else\nfloat b;\n#endif\n",
+            &[],
+        ).unwrap();
+        assert_eq!(
+            code_lines(&code),
+            vec![("float b;".to_string(), "main.glsl".to_string(), 4)]
+        );
+
+        match preprocess("#include \"missing.glsl\"\n", &[]) {
+            Err(PreprocessError::MissingFile(ref name)) => assert_eq!(name, "missing.glsl"),
+            _ => panic!("the missing file is not reported"),
+        }
+    }
+
+    #[test]
+    fn undecided_conditions() {
+        let code = preprocess(
+            "#if F(1)\nfloat a;\n#elif 1\nfloat b;\n#endif\n#if 0\nfloat c;\n#elif F(2)\n\
+             float d;\n#else\nfloat e;\n#endif\n",
+            &[],
+        ).unwrap();
+
+        let lines: Vec<_> = code_lines(&code)
+            .into_iter()
+            .map(|(line, _, n)| (line, n))
+            .collect();
+        let expected = [
+            ("#if F(1)", 1),
+            ("float a;", 2),
+            ("#elif 1", 3),
+            ("float b;", 4),
+            ("#endif", 5),
+            ("#if F(2)", 8),
+            ("float d;", 9),
+            ("#else", 10),
+            ("float e;", 11),
+            ("#endif", 12),
+        ];
+        assert_eq!(
+            lines,
+            expected
+                .iter()
+                .map(|&(line, n)| (line.to_string(), n))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn remap_log() {
+        let code = preprocess("#include \"a.glsl\"\nvoid main() {}\n", &[("a.glsl", "float a;\n")])
+            .unwrap();
+        let first = code.prefix_lines + 1;
+
+        let log = format!("ERROR: 0:{}: 'a' : redefinition\n0({}) : error\n", first, first + 1);
+        assert_eq!(
+            code.remap_log(&log),
+            "ERROR: a.glsl:1: 'a' : redefinition\nmain.glsl:2 : error\n"
+        );
+    }
+}
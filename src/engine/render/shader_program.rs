@@ -1,13 +1,19 @@
-use engine::asset::{Asset, AssetResult, AssetSystem, FileFuture, LoadableAsset, Resource};
-use engine::render::shader::{ShaderFs, ShaderVs};
+use engine::asset::{Asset, AssetError, AssetResult, AssetSystem, FileFuture, LoadableAsset,
+                    Resource};
+use engine::render::backend::{RenderBackend, ShaderStage};
+use engine::render::capabilities::Capabilities;
+use engine::render::context_loss::context_generation;
+use engine::render::raw_gl::{link_completion_status, uniform_block_binding};
+use engine::render::shader::{Shader, ShaderFs, ShaderKindProvider, ShaderVs};
+use engine::render::uniform_block::UniformBlock;
 use engine::render::uniforms::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::rc::Rc;
-use uni_gl::{ShaderKind as WebGLShaderKind, WebGLProgram, WebGLRenderingContext};
+use uni_gl::{WebGLProgram, WebGLRenderingContext, WebGLShader};
 
 use std::borrow::Cow;
-
 use uni_app;
 
 pub enum ShaderAttrib {
@@ -16,6 +22,42 @@ pub enum ShaderAttrib {
     Normal = 2,
     Tangent = 3,
     Bitangent = 4,
+    /// A mat4, uses the 4 locations from here
+    InstanceModel = 5,
+}
+
+/// New programs linked per frame, the others wait for the next frames.
+/// The links are then polled with KHR_parallel_shader_compile, see
+/// `link_completed`.
+const MAX_LINKS_PER_FRAME: usize = 4;
+
+/// The current frame and the programs linked during it, see `end_shader_frame`
+thread_local!(static LINKS: Cell<(u64, usize)> = Cell::new((0, 0)));
+
+/// Start the next frame, the programs linked during this one are ready to use
+pub fn end_shader_frame() {
+    LINKS.with(|links| links.set((links.get().0 + 1, 0)));
+}
+
+fn current_frame() -> u64 {
+    LINKS.with(|links| links.get().0)
+}
+
+/// Whether the link of `prog` issued in `frame` completed. Where the context
+/// has KHR_parallel_shader_compile the driver links in the background and
+/// `COMPLETION_STATUS_KHR` is polled, otherwise the programs are used from
+/// the next frame.
+fn link_completed(
+    gl: &WebGLRenderingContext,
+    caps: &Capabilities,
+    prog: &WebGLProgram,
+    frame: u64,
+) -> bool {
+    if caps.parallel_shader_compile {
+        link_completion_status(gl, prog)
+    } else {
+        frame != current_frame()
+    }
 }
 
 impl Asset for ShaderProgram {
@@ -28,8 +70,13 @@ impl Asset for ShaderProgram {
             coord_map: Default::default(),
             uniform_cache: Default::default(),
 
-            vs_shader: vs,
-            fs_shader: fs,
+            source: ShaderProgramSource::Files(RefCell::new(ShaderFiles {
+                vs,
+                fs,
+                reloading: None,
+                generation: 0,
+            })),
+            failed: Cell::new(None),
         })
     }
 }
@@ -56,6 +103,31 @@ impl LoadableAsset for ShaderProgram {
 #[derive(Debug)]
 pub struct ShaderProgramGLState {
     prog: WebGLProgram,
+    generation: usize,
+    /// The `context_generation` `prog` was linked in
+    context: u32,
+    /// The frame the link was issued in, for the new programs waiting for
+    /// the link to complete
+    pending_frame: Option<u64>,
+    /// Whether the program declares each uniform block, bound on first use
+    blocks: HashMap<&'static str, bool>,
+}
+
+#[derive(Debug)]
+struct ShaderFiles {
+    vs: Resource<ShaderVs>,
+    fs: Resource<ShaderFs>,
+    /// The files being reloaded, they replace `vs` and `fs` once loaded
+    reloading: Option<(Resource<ShaderVs>, Resource<ShaderFs>)>,
+    /// Bumped on each reload, programs compiled from older files are rebuilt
+    generation: usize,
+}
+
+#[derive(Debug)]
+enum ShaderProgramSource {
+    Files(RefCell<ShaderFiles>),
+    /// The files of another program, preprocessed with some keywords defined
+    Variant(Rc<ShaderProgram>, Vec<String>),
 }
 
 #[derive(Debug)]
@@ -64,18 +136,47 @@ pub struct ShaderProgram {
 
     coord_map: RefCell<HashMap<String, Option<u32>>>,
 
-    vs_shader: Resource<ShaderVs>,
-    fs_shader: Resource<ShaderFs>,
+    source: ShaderProgramSource,
 
     uniform_cache: UniformCache,
+
+    /// The generation and `context_generation` of the last failed recompile,
+    /// not tried again until one of them changes
+    failed: Cell<Option<(usize, u32)>>,
 }
 
 impl ShaderProgram {
-    pub fn bind(&self, gl: &WebGLRenderingContext) -> AssetResult<()> {
-        self.prepare(gl)?;
+    /// The permutation of `base` compiled with each of `defines` defined,
+    /// the source files are shared with `base`
+    pub fn new_variant(base: &Rc<ShaderProgram>, mut defines: Vec<String>) -> Rc<ShaderProgram> {
+        let base = match base.source {
+            ShaderProgramSource::Variant(ref b, ref d) => {
+                defines.extend(d.iter().cloned());
+                b.clone()
+            }
+            ShaderProgramSource::Files(..) => base.clone(),
+        };
+
+        defines.sort();
+        defines.dedup();
+
+        Rc::new(ShaderProgram {
+            gl_state: RefCell::new(None),
+
+            coord_map: Default::default(),
+            uniform_cache: Default::default(),
+
+            source: ShaderProgramSource::Variant(base, defines),
+            failed: Cell::new(None),
+        })
+    }
+
+    /// The shaders are compiled with the `Capabilities::shader_defines` of `caps`
+    pub fn bind(&self, gl: &WebGLRenderingContext, caps: &Capabilities) -> AssetResult<()> {
+        self.prepare(gl, caps)?;
 
         let gl_state = self.gl_state.borrow();
-        gl.use_program(&gl_state.as_ref().unwrap().prog);
+        gl.bind_program(&gl_state.as_ref().unwrap().prog);
 
         // after use, we should clean up the committed uniform state.
         // https://www.khronos.org/registry/OpenGL-Refpages/gl4/html/glUniform.xhtml
@@ -84,20 +185,182 @@ impl ShaderProgram {
         Ok(())
     }
 
-    fn prepare(&self, gl: &WebGLRenderingContext) -> AssetResult<()> {
-        if self.gl_state.borrow().is_some() {
-            return Ok(());
+    fn prepare(&self, gl: &WebGLRenderingContext, caps: &Capabilities) -> AssetResult<()> {
+        self.poll_reload();
+        let generation = self.generation();
+        let context = context_generation();
+
+        let recompile = match *self.gl_state.borrow_mut() {
+            Some(ref mut state) if state.generation == generation && state.context == context => {
+                if let Some(frame) = state.pending_frame {
+                    if !link_completed(gl, caps, &state.prog, frame) {
+                        return Err(AssetError::NotReady);
+                    }
+
+                    state.pending_frame = None;
+                }
+
+                return Ok(());
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if !recompile {
+            let (frame, links) = LINKS.with(|links| links.get());
+            if links >= MAX_LINKS_PER_FRAME {
+                return Err(AssetError::NotReady);
+            }
+
+            let mut state = self.compile(gl, caps, generation)?;
+            state.pending_frame = Some(frame);
+            *self.gl_state.borrow_mut() = Some(state);
+            LINKS.with(|l| l.set((frame, links + 1)));
+
+            return Err(AssetError::NotReady);
+        }
+
+        if self.failed.get() == Some((generation, context)) {
+            return Err(AssetError::NotReady);
         }
 
-        let vs = self.vs_shader.try_borrow()?;
-        let fs = self.fs_shader.try_borrow()?;
+        // The gl backend panics on compile errors, keep the old program in that case
+        let compiled =
+            panic::catch_unwind(AssertUnwindSafe(|| self.compile(gl, caps, generation)));
+
+        match compiled {
+            Ok(Ok(state)) => {
+                *self.gl_state.borrow_mut() = Some(state);
+                self.coord_map.borrow_mut().clear();
+                self.uniform_cache.invalidate();
+                return Ok(());
+            }
+            Ok(Err(e)) => uni_app::App::print(format!(
+                "Failed to recompile shader program, keeping the old one: {:?}\n",
+                e
+            )),
+            Err(_) => uni_app::App::print(
+                "Failed to compile shader program, keeping the old one\n".to_owned(),
+            ),
+        }
 
-        let state = Some(ShaderProgramGLState::new(gl, &vs, &fs));
-        *self.gl_state.borrow_mut() = state;
+        // do not try again until the files change or the context is restored
+        self.failed.set(Some((generation, context)));
 
+        let mut gl_state = self.gl_state.borrow_mut();
+        let state = gl_state.as_mut().unwrap();
+        if state.context != context {
+            // The old program was lost with its context, nothing is drawn with it
+            return Err(AssetError::NotReady);
+        }
+
+        state.generation = generation;
         Ok(())
     }
 
+    fn compile(
+        &self,
+        gl: &WebGLRenderingContext,
+        caps: &Capabilities,
+        generation: usize,
+    ) -> AssetResult<ShaderProgramGLState> {
+        let mut defines = caps.shader_defines();
+        if let ShaderProgramSource::Variant(_, ref keywords) = self.source {
+            defines.extend(keywords.iter().cloned());
+        }
+
+        let (vs, fs) = match self.source {
+            ShaderProgramSource::Files(..) => self.variant_shaders(&defines)?,
+            ShaderProgramSource::Variant(ref base, _) => base.variant_shaders(&defines)?,
+        };
+
+        Ok(ShaderProgramGLState::new(gl, &vs, &fs, generation))
+    }
+
+    fn variant_shaders(&self, defines: &[String]) -> AssetResult<(ShaderVs, ShaderFs)> {
+        match self.source {
+            ShaderProgramSource::Files(ref files) => {
+                let files = files.borrow();
+                let vs = files.vs.try_borrow()?;
+                let fs = files.fs.try_borrow()?;
+
+                Ok((vs.with_defines(defines)?, fs.with_defines(defines)?))
+            }
+            ShaderProgramSource::Variant(..) => unreachable!(),
+        }
+    }
+
+    fn generation(&self) -> usize {
+        match self.source {
+            ShaderProgramSource::Files(ref files) => files.borrow().generation,
+            ShaderProgramSource::Variant(ref base, _) => base.generation(),
+        }
+    }
+
+    /// Load the program again from `files`, the current files are used
+    /// until the new ones are loaded and compiled
+    pub fn reload(&self, (vs, fs): (Resource<ShaderVs>, Resource<ShaderFs>)) {
+        match self.source {
+            ShaderProgramSource::Files(ref files) => {
+                files.borrow_mut().reloading = Some((vs, fs));
+            }
+            ShaderProgramSource::Variant(ref base, _) => base.reload((vs, fs)),
+        }
+    }
+
+    fn poll_reload(&self) {
+        let files = match self.source {
+            ShaderProgramSource::Files(ref files) => files,
+            ShaderProgramSource::Variant(ref base, _) => return base.poll_reload(),
+        };
+
+        let mut files = files.borrow_mut();
+        let loaded = match files.reloading {
+            None => return,
+            Some((ref vs, ref fs)) => vs.try_borrow()
+                .and_then(|_| fs.try_borrow().map(|_| ())),
+        };
+
+        match loaded {
+            Err(AssetError::NotReady) => {}
+            Err(e) => {
+                uni_app::App::print(format!(
+                    "Failed to reload shader program, keeping the old one: {:?}\n",
+                    e
+                ));
+                files.reloading = None;
+            }
+            Ok(()) => {
+                let (vs, fs) = files.reloading.take().unwrap();
+                files.vs = vs;
+                files.fs = fs;
+                files.generation += 1;
+            }
+        }
+    }
+
+    /// The files included by the shaders of the program, once they are loaded
+    pub fn included_files(&self) -> Vec<String> {
+        match self.source {
+            ShaderProgramSource::Files(ref files) => {
+                let files = files.borrow();
+                let mut names = Vec::new();
+
+                if let Ok(vs) = files.vs.try_borrow() {
+                    names.extend(vs.code.included_files());
+                }
+                if let Ok(fs) = files.fs.try_borrow() {
+                    names.extend(fs.code.included_files());
+                }
+
+                names.sort();
+                names.dedup();
+                names
+            }
+            ShaderProgramSource::Variant(ref base, _) => base.included_files(),
+        }
+    }
+
     pub fn attrib_loc(&self, gl: &WebGLRenderingContext, s: &str) -> Option<u32> {
         let mut m = self.coord_map.borrow_mut();
 
@@ -107,13 +370,35 @@ impl ShaderProgram {
         match m.get(s) {
             Some(opt_coord) => *opt_coord,
             None => {
-                let coord = gl.get_attrib_location(&gl_state.prog, s.into());
+                let coord = gl.attribute_location(&gl_state.prog, s);
                 m.insert(s.into(), coord);
                 coord
             }
         }
     }
 
+    /// Whether the program samples or reads the uniform `s`, false until it is linked
+    pub fn has_uniform(&self, gl: &WebGLRenderingContext, s: &Cow<'static, str>) -> bool {
+        match *self.gl_state.borrow() {
+            Some(ref gl_state) => self.uniform_cache.has_uniform(gl, &gl_state.prog, s),
+            None => false,
+        }
+    }
+
+    /// Read the uniform block of `block` from its buffer, false when the
+    /// linked program doesn't declare it and takes the plain uniforms instead
+    pub fn bind_uniform_block(&self, gl: &WebGLRenderingContext, block: &UniformBlock) -> bool {
+        match *self.gl_state.borrow_mut() {
+            Some(ref mut gl_state) => {
+                let prog = &gl_state.prog;
+                *gl_state.blocks.entry(block.name()).or_insert_with(|| {
+                    uniform_block_binding(gl, prog, block.name(), block.binding())
+                })
+            }
+            None => false,
+        }
+    }
+
     pub fn set<T, S>(&self, s: S, data: T)
     where
         T: Into<UniformAdapter>,
@@ -122,6 +407,12 @@ impl ShaderProgram {
         self.uniform_cache.set(s, data);
     }
 
+    /// Return and reset the number of uniform uploads skipped because
+    /// the program already had the value
+    pub fn take_saved_uniform_calls(&self) -> u32 {
+        self.uniform_cache.take_saved_calls()
+    }
+
     pub fn commit(&self, gl: &WebGLRenderingContext) {
         self.gl_state.borrow().as_ref().map(|gl_state| {
             self.uniform_cache.commit(gl, &gl_state.prog);
@@ -129,43 +420,54 @@ impl ShaderProgram {
     }
 }
 
+impl UniformTarget for ShaderProgram {
+    fn set<T, S>(&self, s: S, data: T)
+    where
+        T: Into<UniformAdapter>,
+        S: Into<Cow<'static, str>>,
+    {
+        self.uniform_cache.set(s, data);
+    }
+}
+
+/// The backend panics with the compile log, whose line numbers are
+/// remapped to the files the lines come from
+fn compile_shader<T: ShaderKindProvider>(
+    gl: &WebGLRenderingContext,
+    stage: ShaderStage,
+    unit: &Shader<T>,
+) -> WebGLShader {
+    uni_app::App::print(format!("Compiling shader file : {}\n", unit.filename));
+
+    let source = unit.code.as_string();
+    let compiled = panic::catch_unwind(AssertUnwindSafe(|| gl.build_shader(stage, &source)));
+    match compiled {
+        Ok(shader) => shader,
+        Err(e) => {
+            let log = match e.downcast_ref::<String>() {
+                Some(s) => s.clone(),
+                None => e.downcast_ref::<&str>().map_or(String::new(), |s| s.to_string()),
+            };
+
+            panic!("{}:\n{}", unit.filename, unit.code.remap_log(&log));
+        }
+    }
+}
+
 impl ShaderProgramGLState {
     pub fn new(
         gl: &WebGLRenderingContext,
         vs_unit: &ShaderVs,
         fs_unit: &ShaderFs,
+        generation: usize,
     ) -> ShaderProgramGLState {
         /*================ Shaders ====================*/
 
-        // Create a vertex shader object
-        let vert_shader = gl.create_shader(WebGLShaderKind::Vertex);
-
-        // Attach vertex shader source code
-        gl.shader_source(&vert_shader, &vs_unit.code.as_string());
-
         // Compile the vertex shader
-        uni_app::App::print(format!("Compiling shader file : {}\n", vs_unit.filename));
-        gl.compile_shader(&vert_shader);
-
-        // Create fragment shader object
-        let frag_shader = gl.create_shader(WebGLShaderKind::Fragment);
-
-        // Attach fragment shader source code
-        gl.shader_source(&frag_shader, &fs_unit.code.as_string());
-
-        // Compile the fragmentt shader
-        uni_app::App::print(format!("Compiling shader file : {}\n", fs_unit.filename));
-        gl.compile_shader(&frag_shader);
-
-        // Create a shader program object to store
-        // the combined shader program
-        let shader_program = gl.create_program();
-
-        // Attach a vertex shader
-        gl.attach_shader(&shader_program, &vert_shader);
+        let vert_shader = compile_shader(gl, ShaderStage::Vertex, vs_unit);
 
-        // Attach a fragment shader
-        gl.attach_shader(&shader_program, &frag_shader);
+        // Compile the fragment shader
+        let frag_shader = compile_shader(gl, ShaderStage::Fragment, fs_unit);
 
         // We bind the position to 0
         // see: https://developer.mozilla.org/en-US/docs/Web/API/WebGL_API/WebGL_best_practices
@@ -176,29 +478,24 @@ impl ShaderProgramGLState {
         // This is because in desktop OpenGL, nothing gets drawn if vertex attrib 0 is not array-enabled.
         // You can use bindAttribLocation() to force a vertex attribute to use location 0,
         // and use enableVertexAttribArray() to make it array-enabled.
-        gl.bind_attrib_location(
-            &shader_program,
-            "aVertexPosition",
-            ShaderAttrib::Position as _,
-        );
-        gl.bind_attrib_location(&shader_program, "aTextureCoord", ShaderAttrib::UV0 as _);
-        gl.bind_attrib_location(&shader_program, "aVertexNormal", ShaderAttrib::Normal as _);
-        gl.bind_attrib_location(
-            &shader_program,
-            "aVertexTangent",
-            ShaderAttrib::Tangent as _,
-        );
-        gl.bind_attrib_location(
-            &shader_program,
-            "aVertexBitangent",
-            ShaderAttrib::Bitangent as _,
-        );
-
-        // Link both the programs
-        gl.link_program(&shader_program);
+        let attributes = [
+            ("aVertexPosition", ShaderAttrib::Position as u32),
+            ("aTextureCoord", ShaderAttrib::UV0 as u32),
+            ("aVertexNormal", ShaderAttrib::Normal as u32),
+            ("aVertexTangent", ShaderAttrib::Tangent as u32),
+            ("aVertexBitangent", ShaderAttrib::Bitangent as u32),
+            ("aInstanceModel", ShaderAttrib::InstanceModel as u32),
+        ];
+
+        // Link both the shaders
+        let shader_program = gl.build_program(&[&vert_shader, &frag_shader], &attributes);
 
         let prog = ShaderProgramGLState {
             prog: shader_program,
+            generation: generation,
+            context: context_generation(),
+            pending_frame: None,
+            blocks: HashMap::new(),
         };
 
         prog
@@ -0,0 +1,70 @@
+use engine::asset::AssetSystem;
+use engine::render::{Material, MeshSurface, ProbeMaps, RenderQueue, Texture};
+use math::*;
+use std::rc::Rc;
+
+pub enum SkyboxSource {
+    /// A cube map texture, e.g. "sky_cubemap.dds" loading the 6 "sky_*.dds" faces
+    CubeMap(Rc<Texture>),
+    /// An equirectangular image, e.g. a .hdr file, converted to a cube map
+    /// once it is loaded
+    Equirectangular(Rc<Texture>),
+    /// The captured surroundings of a probe, e.g. `ReflectionProbe::from_equirect`
+    /// converting an equirectangular image on the GPU
+    Probe(ProbeMaps),
+}
+
+/// Draws a cube map behind the scene, in the `RenderQueue::Skybox` queue of the
+/// cameras. The `uSkybox` texture and the other params can be changed through
+/// its material.
+#[derive(Component)]
+pub struct Skybox {
+    surface: Rc<MeshSurface>,
+}
+
+impl Skybox {
+    pub fn new(db: &AssetSystem, source: SkyboxSource) -> Skybox {
+        let mut material = match source {
+            SkyboxSource::CubeMap(tex) => {
+                let mut material = Material::new(db.new_program("unrust/skybox"));
+                material.set("uSkybox", tex);
+                material.set("uSkyboxHdr", false);
+                material.set("uSkyboxRGBM", false);
+                material
+            }
+            SkyboxSource::Equirectangular(tex) => {
+                let mut material = Material::new(db.new_program("unrust/skybox"));
+                material.set("uSkybox", Texture::new_equirect_cubemap(tex));
+                // The faces are float textures when the context has them
+                material.set("uSkyboxHdr", true);
+                material.set("uSkyboxRGBM", !db.capabilities().float_textures);
+                material
+            }
+            SkyboxSource::Probe(maps) => {
+                let mut material = Material::new(db.new_program("unrust/skybox_atlas"));
+                let size = Vector2f::new((maps.size * 6) as f32, maps.size as f32);
+                material.set("uSkyboxAtlas", maps.capture.as_texture());
+                material.set("uSkyboxAtlasSize", size);
+                material
+            }
+        };
+
+        material.render_queue = RenderQueue::Skybox;
+
+        Skybox {
+            surface: Rc::new(MeshSurface {
+                buffer: db.new_mesh_buffer("skybox"),
+                material: Rc::new(material),
+                properties: None,
+            }),
+        }
+    }
+
+    pub fn material(&self) -> &Rc<Material> {
+        &self.surface.material
+    }
+
+    pub fn surface(&self) -> &Rc<MeshSurface> {
+        &self.surface
+    }
+}
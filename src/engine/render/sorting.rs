@@ -0,0 +1,55 @@
+/// The layer of the objects without a sorting layer, or with an unknown one
+pub const DEFAULT_SORTING_LAYER: &'static str = "Default";
+
+/// The named sorting layers of 2D content, from the back to the front, see
+/// `Engine::sorting_layers`. "Default" is the only one at first, move it to
+/// draw layers behind it.
+#[derive(Clone, Debug)]
+pub struct SortingLayers {
+    pub names: Vec<String>,
+}
+
+impl Default for SortingLayers {
+    fn default() -> SortingLayers {
+        SortingLayers {
+            names: vec![DEFAULT_SORTING_LAYER.to_string()],
+        }
+    }
+}
+
+impl SortingLayers {
+    /// Add the layer `name` in front of the others
+    pub fn add<S: Into<String>>(&mut self, name: S) {
+        let name = name.into();
+        if !self.names.contains(&name) {
+            self.names.push(name);
+        }
+    }
+
+    /// The rank of the layer `name`, the one of "Default" when unknown
+    pub fn index(&self, name: &str) -> i32 {
+        let find = |n: &str| self.names.iter().position(|l| l == n);
+
+        find(name)
+            .or_else(|| find(DEFAULT_SORTING_LAYER))
+            .unwrap_or(0) as i32
+    }
+}
+
+/// Orders the transparent surfaces of its game object by sorting layer, then
+/// by `order_in_layer`, before the camera distance. Without it an object is
+/// in "Default" at order 0.
+#[derive(Component, Clone, Debug)]
+pub struct SortingGroup {
+    pub sorting_layer: String,
+    pub order_in_layer: i32,
+}
+
+impl SortingGroup {
+    pub fn new<S: Into<String>>(sorting_layer: S, order_in_layer: i32) -> SortingGroup {
+        SortingGroup {
+            sorting_layer: sorting_layer.into(),
+            order_in_layer,
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use engine::asset::{Asset, AssetSystem};
+use engine::render::{Blend, CullMode, Material, MaterialPropertyBlock, MeshBuffer, MeshData,
+                     MeshSurface, NineSlice, RenderQueue, Texture, DEFAULT_SORTING_LAYER,
+                     MAX_BATCH_VERTICES};
+use math::*;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// A textured quad in the local XY plane of its game object, for 2D games.
+/// Sprites are not drawn one by one: every frame the visible ones are sorted
+/// and the consecutive sprites sharing a texture and a material are merged
+/// into a single draw, see `SpriteBatcher`.
+#[derive(Component)]
+pub struct SpriteRenderer {
+    pub texture: Rc<Texture>,
+    /// Tangent space normals over the same uvs as `texture`, for the 2D
+    /// lights of the cameras rendering the normal maps, see `Lighting2DSettings`
+    pub normal_map: Option<Rc<Texture>>,
+    /// The (min, max) uv of the sprite in the texture, all of it by default.
+    /// The uvs start at the top left of the image.
+    pub region: (Vector2f, Vector2f),
+    /// Size in world units
+    pub size: Vector2f,
+    /// The point of the sprite at the object position, from (0, 0) at the
+    /// bottom left to (1, 1) at the top right
+    pub pivot: Vector2f,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Multiplies the texture color
+    pub color: Vector4<f32>,
+    /// Sprites are drawn by sorting layer, see `SortingLayers`, then by
+    /// increasing order in the layer, then back to front
+    pub sorting_layer: String,
+    pub order_in_layer: i32,
+    /// Draw the region as 9 patches keeping the size of its border when the
+    /// sprite is scaled
+    pub nine_slice: Option<NineSlice>,
+    /// Pixels of the nine-slice border in a world unit
+    pub pixels_per_unit: f32,
+    /// Replaces the "unrust/sprite" material, which should be in the
+    /// `Sprite` queue and read the texture from `uTexture`
+    pub material: Option<Rc<Material>>,
+}
+
+impl SpriteRenderer {
+    pub fn new(texture: Rc<Texture>) -> SpriteRenderer {
+        SpriteRenderer {
+            texture,
+            normal_map: None,
+            region: (Vector2f::new(0.0, 0.0), Vector2f::new(1.0, 1.0)),
+            size: Vector2f::new(1.0, 1.0),
+            pivot: Vector2f::new(0.5, 0.5),
+            flip_x: false,
+            flip_y: false,
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            sorting_layer: DEFAULT_SORTING_LAYER.to_string(),
+            order_in_layer: 0,
+            nine_slice: None,
+            pixels_per_unit: 100.0,
+            material: None,
+        }
+    }
+
+    /// The world space corners with `transform`, counter clockwise from the
+    /// bottom left
+    pub fn corners(&self, transform: &Matrix4f) -> [Vector3f; 4] {
+        let min = Vector2f::new(-self.pivot.x * self.size.x, -self.pivot.y * self.size.y);
+        let max = min + self.size;
+
+        let corner = |x: f32, y: f32| transform.transform_point(Point3::new(x, y, 0.0)).to_vec();
+
+        [
+            corner(min.x, min.y),
+            corner(max.x, min.y),
+            corner(max.x, max.y),
+            corner(min.x, max.y),
+        ]
+    }
+
+    /// The uvs of the corners, with the flips. The top of the region is at
+    /// the top of the sprite.
+    pub fn uvs(&self) -> [Vector2f; 4] {
+        let (mut min, mut max) = self.region;
+
+        if self.flip_x {
+            ::std::mem::swap(&mut min.x, &mut max.x);
+        }
+        if self.flip_y {
+            ::std::mem::swap(&mut min.y, &mut max.y);
+        }
+
+        [
+            Vector2f::new(min.x, max.y),
+            Vector2f::new(max.x, max.y),
+            Vector2f::new(max.x, min.y),
+            Vector2f::new(min.x, min.y),
+        ]
+    }
+
+    /// The world space quads with `transform`, the 9 patches of a nine-slice
+    /// sprite or the whole sprite
+    pub fn quads(&self, transform: &Matrix4f) -> Vec<SpriteQuad> {
+        let slice = match self.nine_slice {
+            Some(ref slice) => slice.flipped(self.flip_x, self.flip_y),
+            None => return vec![(self.corners(transform), self.uvs())],
+        };
+
+        let min = Vector2f::new(-self.pivot.x * self.size.x, -self.pivot.y * self.size.y);
+        let max = min + self.size;
+        let uvs = self.uvs();
+        let pixel = Vector2f::new(1.0, 1.0) / self.pixels_per_unit;
+
+        let corner = |x: f32, y: f32| transform.transform_point(Point3::new(x, y, 0.0)).to_vec();
+
+        slice
+            .patches(min, max, uvs[0], uvs[2], pixel)
+            .into_iter()
+            .map(|((p0, p1), (t0, t1))| {
+                (
+                    [
+                        corner(p0.x, p0.y),
+                        corner(p1.x, p0.y),
+                        corner(p1.x, p1.y),
+                        corner(p0.x, p1.y),
+                    ],
+                    [
+                        Vector2f::new(t0.x, t0.y),
+                        Vector2f::new(t1.x, t0.y),
+                        Vector2f::new(t1.x, t1.y),
+                        Vector2f::new(t0.x, t1.y),
+                    ],
+                )
+            })
+            .collect()
+    }
+}
+
+/// The corners and their uvs, counter clockwise from the bottom left
+pub type SpriteQuad = ([Vector3f; 4], [Vector2f; 4]);
+
+/// A visible sprite of the frame, in world space
+pub struct SpriteInstance {
+    pub quads: Vec<SpriteQuad>,
+    pub color: Vector4<f32>,
+    /// The sorting layer rank and the order in layer
+    pub sorting: (i32, i32),
+    pub cam_distance: f32,
+    pub texture: Rc<Texture>,
+    pub normal_map: Option<Rc<Texture>>,
+    pub material: Option<Rc<Material>>,
+    pub layer: u32,
+}
+
+impl SpriteInstance {
+    fn can_batch_with(&self, other: &SpriteInstance) -> bool {
+        let same_material = match (&self.material, &other.material) {
+            (&Some(ref a), &Some(ref b)) => Rc::ptr_eq(a, b),
+            (&None, &None) => true,
+            _ => false,
+        };
+
+        let same_normal_map = match (&self.normal_map, &other.normal_map) {
+            (&Some(ref a), &Some(ref b)) => Rc::ptr_eq(a, b),
+            (&None, &None) => true,
+            _ => false,
+        };
+
+        same_material && same_normal_map && Rc::ptr_eq(&self.texture, &other.texture)
+            && self.layer == other.layer
+    }
+}
+
+/// A merged draw of sprites, its textures are bound through `properties`
+pub struct SpriteBatch {
+    pub surface: Rc<MeshSurface>,
+    pub properties: Rc<MaterialPropertyBlock>,
+    pub layer: u32,
+    pub sorting: (i32, i32),
+    pub count: usize,
+}
+
+/// Layout of the sprite vertices: uvs, the color as normal and its alpha as tangent
+const SPRITE_LAYOUT: [bool; 4] = [true, true, true, false];
+
+/// Merges the sprites into streaming buffers on the CPU, the buffers are
+/// pooled and refilled for every camera
+#[derive(Default)]
+pub struct SpriteBatcher {
+    buffers: RefCell<Vec<Rc<MeshBuffer>>>,
+    material: RefCell<Option<Rc<Material>>>,
+}
+
+impl SpriteBatcher {
+    /// The material of the sprites without one, "unrust/sprite" alpha blended
+    pub fn default_material(&self, db: &AssetSystem) -> Rc<Material> {
+        let mut cached = self.material.borrow_mut();
+
+        cached
+            .get_or_insert_with(|| {
+                let mut material = Material::new(db.new_program("unrust/sprite"));
+                material.render_queue = RenderQueue::Sprite;
+                material.states.cull = Some(CullMode::Off);
+                material.states.depth_write = Some(false);
+                material.states.alpha_blending = Some(true);
+                material.states.blend = Some(Blend::default());
+                material.fog = false;
+
+                Rc::new(material)
+            })
+            .clone()
+    }
+
+    /// Sort the sprites and merge the consecutive ones sharing a texture, a
+    /// material and a layer, in drawing order
+    pub fn batch(&self, sprites: &mut Vec<SpriteInstance>, db: &AssetSystem) -> Vec<SpriteBatch> {
+        sprites.sort_by(|a, b| {
+            a.sorting.cmp(&b.sorting).then_with(|| {
+                b.cam_distance
+                    .partial_cmp(&a.cam_distance)
+                    .unwrap_or(Ordering::Equal)
+            })
+        });
+
+        let default_material = self.default_material(db);
+        let max_quads = MAX_BATCH_VERTICES / 4;
+
+        let mut batches = Vec::new();
+        let mut start = 0;
+
+        while start < sprites.len() {
+            let first = &sprites[start];
+            let mut end = start + 1;
+            let mut quads = first.quads.len();
+            while end < sprites.len() && quads + sprites[end].quads.len() <= max_quads
+                && first.can_batch_with(&sprites[end])
+            {
+                quads += sprites[end].quads.len();
+                end += 1;
+            }
+
+            let mut properties = MaterialPropertyBlock::new();
+            properties.set("uTexture", first.texture.clone());
+            properties.set("uHasNormalMap", first.normal_map.is_some());
+            if let Some(ref normal_map) = first.normal_map {
+                properties.set("uNormalMap", normal_map.clone());
+            }
+
+            batches.push(SpriteBatch {
+                surface: Rc::new(MeshSurface {
+                    buffer: self.fill(batches.len(), &sprites[start..end]),
+                    material: first.material.clone().unwrap_or(default_material.clone()),
+                    properties: None,
+                }),
+                properties: Rc::new(properties),
+                layer: first.layer,
+                sorting: first.sorting,
+                count: end - start,
+            });
+
+            start = end;
+        }
+
+        batches
+    }
+
+    /// Refill the pooled buffer `index` with the quads of `sprites`
+    fn fill(&self, index: usize, sprites: &[SpriteInstance]) -> Rc<MeshBuffer> {
+        let mut data = MeshData::with_layout(SPRITE_LAYOUT);
+
+        {
+            let uvs = data.uvs.as_mut().unwrap();
+            let colors = data.normals.as_mut().unwrap();
+            let alphas = data.tangents.as_mut().unwrap();
+
+            for s in sprites.iter() {
+                let c = s.color;
+
+                for &(ref corners, ref quad_uvs) in s.quads.iter() {
+                    let base = (data.vertices.len() / 3) as u16;
+
+                    for k in 0..4 {
+                        let (p, uv) = (corners[k], quad_uvs[k]);
+                        data.vertices.extend_from_slice(&[p.x, p.y, p.z]);
+                        uvs.extend_from_slice(&[uv.x, uv.y]);
+                        colors.extend_from_slice(&[c.x, c.y, c.z]);
+                        alphas.extend_from_slice(&[c.w, 0.0, 0.0]);
+                    }
+
+                    data.indices.extend_from_slice(&[
+                        base,
+                        base + 1,
+                        base + 2,
+                        base,
+                        base + 2,
+                        base + 3,
+                    ]);
+                }
+            }
+        }
+
+        let mut buffers = self.buffers.borrow_mut();
+        while buffers.len() <= index {
+            let buffer = MeshBuffer::new(MeshData::with_layout(SPRITE_LAYOUT));
+            buffer.set_streaming(true);
+            buffers.push(buffer);
+        }
+
+        buffers[index].update_mesh_data(data);
+        buffers[index].clone()
+    }
+}
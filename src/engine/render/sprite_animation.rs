@@ -0,0 +1,135 @@
+use engine::render::{SpriteRenderer, TextureAtlas};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// See `SpriteAnimation::events`
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpriteAnimationEvent {
+    /// The looping clip went back to its first frame
+    Looped(String),
+    /// The clip stopped on its last frame
+    Completed(String),
+}
+
+/// Plays named frame sequences of a `TextureAtlas` with the `SpriteRenderer`
+/// of its game object. The clips are the animations of the atlas file and
+/// those added with `add_clip`. The engine steps it every frame.
+#[derive(Component)]
+pub struct SpriteAnimation {
+    pub atlas: Rc<TextureAtlas>,
+    /// Frames per second
+    pub fps: f32,
+    pub looping: bool,
+
+    clips: HashMap<String, Vec<String>>,
+    clip: Option<String>,
+    /// The frames of `clip`, resolved once the atlas is loaded
+    frames: Vec<String>,
+    frame: usize,
+    time: f32,
+    playing: bool,
+    events: Vec<SpriteAnimationEvent>,
+}
+
+impl SpriteAnimation {
+    pub fn new(atlas: Rc<TextureAtlas>, fps: f32) -> SpriteAnimation {
+        SpriteAnimation {
+            atlas,
+            fps,
+            looping: true,
+            clips: HashMap::new(),
+            clip: None,
+            frames: Vec::new(),
+            frame: 0,
+            time: 0.0,
+            playing: false,
+            events: Vec::new(),
+        }
+    }
+
+    /// A clip of frame names of the atlas, it hides the atlas animation of the
+    /// same name
+    pub fn add_clip<S: Into<String>>(&mut self, name: S, frames: Vec<String>) {
+        self.clips.insert(name.into(), frames);
+    }
+
+    /// Play the clip `name` from its first frame, unless it is already playing
+    pub fn play(&mut self, name: &str) {
+        if self.playing && self.clip.as_ref().map(|c| c.as_str()) == Some(name) {
+            return;
+        }
+
+        self.clip = Some(name.to_string());
+        self.frames.clear();
+        self.frame = 0;
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    /// Stay on the current frame
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn clip(&self) -> Option<&str> {
+        self.clip.as_ref().map(|c| c.as_str())
+    }
+
+    /// The name of the shown frame
+    pub fn frame(&self) -> Option<&str> {
+        self.frames.get(self.frame).map(|f| f.as_str())
+    }
+
+    /// The events of the last update
+    pub fn events(&self) -> &[SpriteAnimationEvent] {
+        &self.events
+    }
+
+    /// Advance by `dt` seconds and show the current frame with `sprite`
+    pub fn update(&mut self, dt: f32, sprite: &mut SpriteRenderer) {
+        self.events.clear();
+
+        let clip = match self.clip {
+            Some(ref clip) => clip.clone(),
+            None => return,
+        };
+
+        if self.frames.is_empty() {
+            self.frames = match self.clips.get(&clip) {
+                Some(frames) => frames.clone(),
+                None => self.atlas.animation(&clip).unwrap_or_default(),
+            };
+
+            // Wait for the atlas
+            if self.frames.is_empty() {
+                return;
+            }
+        }
+
+        if self.playing && self.fps > 0.0 {
+            self.time += dt * self.fps;
+
+            while self.time >= 1.0 {
+                self.time -= 1.0;
+
+                if self.frame + 1 < self.frames.len() {
+                    self.frame += 1;
+                } else if self.looping {
+                    self.frame = 0;
+                    self.events.push(SpriteAnimationEvent::Looped(clip.clone()));
+                } else {
+                    self.time = 0.0;
+                    self.playing = false;
+                    self.events.push(SpriteAnimationEvent::Completed(clip.clone()));
+                    break;
+                }
+            }
+        }
+
+        self.atlas.apply(sprite, &self.frames[self.frame]);
+    }
+}
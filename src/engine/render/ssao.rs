@@ -0,0 +1,137 @@
+use engine::asset::AssetSystem;
+use engine::render::{Material, RenderTexture, ShaderProgram, TextureAttachment};
+use math::*;
+use std::rc::Rc;
+
+/// Should match SSAO_SAMPLES in unrust/ssao_fs.glsl
+pub const SSAO_SAMPLES: usize = 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct SsaoSettings {
+    /// View space radius of the sampled hemisphere
+    pub radius: f32,
+    /// Exponent applied to the occlusion term
+    pub intensity: f32,
+    /// Depth offset to avoid self occlusion
+    pub bias: f32,
+    /// Orient the samples along the prepass normals,
+    /// otherwise sample a sphere using the depth only
+    pub use_normals: bool,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> SsaoSettings {
+        SsaoSettings {
+            radius: 0.5,
+            intensity: 1.0,
+            bias: 0.025,
+            use_normals: true,
+        }
+    }
+}
+
+/// The targets of the depth/normal prepass and of the occlusion passes,
+/// the occlusion is computed at half resolution and sampled by the
+/// ambient terms of the phong shaders.
+pub struct SsaoState {
+    pub normal_rt: Rc<RenderTexture>,
+    pub ao_rt: Rc<RenderTexture>,
+    pub blur_rt: Rc<RenderTexture>,
+
+    pub prepass_material: Rc<Material>,
+    pub ao_material: Rc<Material>,
+    pub blur_material: Rc<Material>,
+
+    /// Set by the engine for the pass the occlusion is built for
+    pub enabled: bool,
+
+    size: (u32, u32),
+    viewport: ((i32, i32), (u32, u32)),
+}
+
+// Points in the unit hemisphere, denser near the center
+fn sample_kernel() -> Vec<Vector3f> {
+    let mut seed: u32 = 0x1234_5678;
+    let mut rand = move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 8) as f32 / (1 << 24) as f32
+    };
+
+    (0..SSAO_SAMPLES)
+        .map(|i| {
+            let v = Vector3f::new(rand() * 2.0 - 1.0, rand() * 2.0 - 1.0, rand()).normalize();
+            let scale = i as f32 / SSAO_SAMPLES as f32;
+            v * rand() * (0.1 + 0.9 * scale * scale)
+        })
+        .collect()
+}
+
+impl SsaoState {
+    pub fn new(db: &AssetSystem, size: (u32, u32)) -> SsaoState {
+        let half = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+
+        let ao_material = Rc::new(Material::new(db.new_program("unrust/ssao")));
+        for (i, k) in sample_kernel().into_iter().enumerate() {
+            ao_material.set(format!("uKernel[{}]", i), k);
+        }
+
+        SsaoState {
+            normal_rt: Rc::new(RenderTexture::new_with_depth(
+                size.0,
+                size.1,
+                TextureAttachment::Color0,
+            )),
+            ao_rt: Rc::new(RenderTexture::new(half.0, half.1, TextureAttachment::Color0)),
+            blur_rt: Rc::new(RenderTexture::new(half.0, half.1, TextureAttachment::Color0)),
+            prepass_material: Rc::new(Material::new(db.new_program("unrust/depth_normal"))),
+            ao_material: ao_material,
+            blur_material: Rc::new(Material::new(db.new_program("unrust/ssao_blur"))),
+            enabled: false,
+            size: size,
+            viewport: ((0, 0), size),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn half_size(&self) -> (u32, u32) {
+        ((self.size.0 / 2).max(1), (self.size.1 / 2).max(1))
+    }
+
+    /// Update the occlusion pass uniforms for a camera
+    pub fn setup(
+        &mut self,
+        settings: &SsaoSettings,
+        proj: &Matrix4f,
+        viewport: ((i32, i32), (u32, u32)),
+    ) {
+        let mat = &self.ao_material;
+
+        mat.set("uDepthTexture", self.normal_rt.depth_texture().unwrap());
+        mat.set("uNormalTexture", self.normal_rt.as_texture());
+        mat.set("uProjection", *proj);
+        mat.set("uInvProjection", proj.invert().unwrap_or(Matrix4f::identity()));
+        mat.set("uRadius", settings.radius);
+        mat.set("uIntensity", settings.intensity);
+        mat.set("uBias", settings.bias);
+        mat.set("uUseNormals", settings.use_normals);
+
+        self.blur_material
+            .set("uSourceTexture", self.ao_rt.as_texture());
+
+        self.viewport = viewport;
+    }
+
+    /// Bind the uniforms, the texture goes through the engine texture units
+    pub fn bind(&self, prog: &ShaderProgram) {
+        let ((x, y), (w, h)) = self.viewport;
+
+        prog.set("uSSAOEnabled", true);
+        prog.set(
+            "uSSAOViewport",
+            Vector4::new(x as f32, y as f32, w as f32, h as f32),
+        );
+    }
+}
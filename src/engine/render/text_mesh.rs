@@ -0,0 +1,286 @@
+use engine::asset::{Asset, AssetSystem};
+use engine::render::{Blend, CullMode, Material, MaterialPropertyBlock, MeshBuffer, MeshData,
+                     MeshSurface, RenderQueue, SdfFont, SdfGlyph, TtfFont};
+use math::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// The font of a `TextMesh`
+#[derive(Clone)]
+pub enum TextFont {
+    /// Sharp at any size, with an outline
+    Sdf(Rc<SdfFont>),
+    /// The glyphs rasterized at a number of pixels per em, blurry when they are
+    /// magnified. The coverage is in alpha, there is no outline.
+    Ttf(Rc<TtfFont>, f32),
+}
+
+impl From<Rc<SdfFont>> for TextFont {
+    fn from(font: Rc<SdfFont>) -> TextFont {
+        TextFont::Sdf(font)
+    }
+}
+
+/// The fields of `TextMesh` changing its mesh
+#[derive(Clone, PartialEq)]
+struct LayoutKey {
+    text: String,
+    size: u32,
+    line_spacing: u32,
+    alignment: TextAlignment,
+    /// Of the atlas of a TTF font, the glyphs are rasterized again when it changes
+    generation: u32,
+}
+
+/// A glyph quad in world units
+struct GlyphQuad {
+    /// The (top left, bottom right) uv in the atlas
+    uv: (Vector2f, Vector2f),
+    /// From the pen position on the baseline to the top left of the quad
+    offset: Vector2f,
+    size: Vector2f,
+    advance: f32,
+}
+
+/// A string drawn in the local XY plane of its game object as glyph quads,
+/// from the baseline of its first line at the origin. The mesh is rebuilt
+/// when the text or its layout change, once the font is loaded.
+#[derive(Component)]
+pub struct TextMesh {
+    pub text: String,
+    pub font: TextFont,
+    /// Height of an em in world units
+    pub size: f32,
+    pub color: Vector4<f32>,
+    pub alignment: TextAlignment,
+    /// Multiplies the line height of the font
+    pub line_spacing: f32,
+    /// Width of the outline from 0 to 1, 1 is the whole distance field spread.
+    /// Only for the SDF fonts.
+    pub outline_width: f32,
+    pub outline_color: Vector4<f32>,
+    /// See `default_material`
+    pub material: Rc<Material>,
+
+    layout: RefCell<Option<(LayoutKey, Rc<MeshBuffer>)>>,
+}
+
+impl TextMesh {
+    pub fn new<S, F>(text: S, font: F, material: Rc<Material>) -> TextMesh
+    where
+        S: Into<String>,
+        F: Into<TextFont>,
+    {
+        TextMesh {
+            text: text.into(),
+            font: font.into(),
+            size: 1.0,
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            alignment: TextAlignment::Left,
+            line_spacing: 1.0,
+            outline_width: 0.0,
+            outline_color: Vector4::new(0.0, 0.0, 0.0, 1.0),
+            material,
+            layout: RefCell::new(None),
+        }
+    }
+
+    /// The alpha blended material of "unrust/sdf_text", in the transparent queue.
+    /// It reads the alpha of the TTF fonts as their coverage.
+    pub fn default_material(db: &AssetSystem) -> Material {
+        let mut material = Material::new(db.new_program("unrust/sdf_text"));
+
+        material.render_queue = RenderQueue::Transparent;
+        material.states.cull = Some(CullMode::Off);
+        material.states.depth_write = Some(false);
+        material.states.alpha_blending = Some(true);
+        material.states.blend = Some(Blend::default());
+
+        material
+    }
+
+    /// The glyph quads, None while the font is loading
+    pub fn surface(&self) -> Option<Rc<MeshSurface>> {
+        let key = LayoutKey {
+            text: self.text.clone(),
+            size: self.size.to_bits(),
+            line_spacing: self.line_spacing.to_bits(),
+            alignment: self.alignment,
+            generation: match self.font {
+                TextFont::Sdf(_) => 0,
+                TextFont::Ttf(ref font, _) => font.generation(),
+            },
+        };
+
+        let mut layout = self.layout.borrow_mut();
+        let cached = match *layout {
+            Some((ref k, ref buffer)) if *k == key => Some(buffer.clone()),
+            _ => None,
+        };
+
+        let buffer = match cached {
+            Some(buffer) => buffer,
+            None => {
+                let data = match self.font {
+                    TextFont::Sdf(ref font) => {
+                        let font = font.data()?;
+                        let scale = |g: &SdfGlyph| GlyphQuad {
+                            uv: g.uv,
+                            offset: g.offset * self.size,
+                            size: g.size * self.size,
+                            advance: g.advance * self.size,
+                        };
+
+                        self.build(
+                            font.line_height * self.size,
+                            |c| font.glyphs.get(&c).or_else(|| font.glyphs.get(&'?')).map(&scale),
+                            |a, b| font.kerning.get(&(a, b)).cloned().unwrap_or(0.0) * self.size,
+                        )
+                    }
+                    TextFont::Ttf(ref font, pixels) => {
+                        let scale = self.size / pixels;
+                        let line_height = font.line_metrics(pixels)?.line_height() * scale;
+
+                        self.build(
+                            line_height,
+                            |c| {
+                                font.glyph(c, pixels).map(|g| GlyphQuad {
+                                    uv: g.uv,
+                                    offset: g.offset * scale,
+                                    size: g.size * scale,
+                                    advance: g.advance * scale,
+                                })
+                            },
+                            |a, b| font.kerning(a, b, pixels) * scale,
+                        )
+                    }
+                };
+
+                let buffer = MeshBuffer::new(data);
+                *layout = Some((key, buffer.clone()));
+                buffer
+            }
+        };
+
+        Some(Rc::new(MeshSurface {
+            buffer,
+            material: self.material.clone(),
+            properties: None,
+        }))
+    }
+
+    /// The font texture, the colors and the outline
+    pub fn properties(&self) -> Option<Rc<MaterialPropertyBlock>> {
+        // The TTF atlas uploads the glyphs rasterized by `surface`
+        let (texture, coverage) = match self.font {
+            TextFont::Sdf(ref font) => (font.data()?.texture.clone(), false),
+            TextFont::Ttf(ref font, _) => {
+                font.data()?;
+                (font.texture(), true)
+            }
+        };
+
+        // The outline of a 0 width has the color of the text, no dark fringe
+        let outline_color = if self.outline_width > 0.0 {
+            self.outline_color
+        } else {
+            self.color
+        };
+
+        let mut properties = MaterialPropertyBlock::new();
+        properties.set("uTexture", texture);
+        properties.set("uCoverageAlpha", coverage);
+        properties.set("uColor", self.color);
+        properties.set("uOutlineColor", outline_color);
+        properties.set("uOutlineWidth", self.outline_width.max(0.0).min(1.0) * 0.5);
+
+        Some(Rc::new(properties))
+    }
+
+    /// The quads of `glyph`, '?' or nothing for the missing characters
+    fn build<G, K>(&self, line_height: f32, glyph: G, kerning: K) -> MeshData
+    where
+        G: Fn(char) -> Option<GlyphQuad>,
+        K: Fn(char, char) -> f32,
+    {
+        let mut data = MeshData::with_layout([true, false, false, false]);
+        let line_height = line_height * self.line_spacing;
+
+        for (i, line) in self.text.lines().enumerate() {
+            let y = -(i as f32) * line_height;
+
+            // The quads of the line from x = 0, aligned once its width is known
+            let start = data.vertices.len();
+            let mut pen = 0.0;
+            let mut prev = None;
+
+            for c in line.chars() {
+                let glyph = match glyph(c) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+
+                if let Some(p) = prev {
+                    pen += kerning(p, c);
+                }
+                prev = Some(c);
+
+                if glyph.size.x > 0.0 {
+                    let min = Vector2f::new(pen, y) + glyph.offset;
+                    let size = glyph.size;
+                    let (uv0, uv1) = glyph.uv;
+                    let base = (data.vertices.len() / 3) as u16;
+
+                    data.vertices.extend_from_slice(&[
+                        min.x,
+                        min.y - size.y,
+                        0.0,
+                        min.x + size.x,
+                        min.y - size.y,
+                        0.0,
+                        min.x + size.x,
+                        min.y,
+                        0.0,
+                        min.x,
+                        min.y,
+                        0.0,
+                    ]);
+                    data.uvs.as_mut().unwrap().extend_from_slice(&[
+                        uv0.x, uv1.y, uv1.x, uv1.y, uv1.x, uv0.y, uv0.x, uv0.y,
+                    ]);
+                    data.indices.extend_from_slice(&[
+                        base,
+                        base + 1,
+                        base + 2,
+                        base,
+                        base + 2,
+                        base + 3,
+                    ]);
+                }
+
+                pen += glyph.advance;
+            }
+
+            let shift = match self.alignment {
+                TextAlignment::Left => 0.0,
+                TextAlignment::Center => -pen * 0.5,
+                TextAlignment::Right => -pen,
+            };
+
+            let mut x = start;
+            while x < data.vertices.len() {
+                data.vertices[x] += shift;
+                x += 3;
+            }
+        }
+
+        data
+    }
+}
@@ -1,59 +1,223 @@
 use uni_gl;
 use uni_gl::*;
 
-use image::{RgbImage, RgbaImage};
-
-use engine::asset::{Asset, AssetResult, AssetSystem, FileFuture, LoadableAsset, Resource, DDS};
+use image::{ImageBuffer, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+
+use engine::asset::{Asset, AssetError, AssetResult, AssetSystem, DDSFormat, FileFuture,
+                    LoadableAsset, Resource, DDS};
+use engine::render::backend::{FrameAttachment, RenderBackend, TextureTarget};
+use engine::render::capabilities::Capabilities;
+use engine::render::context_loss::context_generation;
+use engine::render::gpu_memory::{GpuAllocation, GpuResourceKind};
+use engine::render::raw_gl;
 use std::cell::{Cell, RefCell};
+use std::f32;
+use std::fmt;
 use std::path::Path;
+use std::slice;
 use std::rc::Rc;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TextureFiltering {
     Nearest,
     Linear,
 }
 
-#[derive(Debug, Copy, Clone)]
+/// How the mipmap levels are sampled, for the textures having them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MipmapFiltering {
+    /// Only the first level
+    None,
+    Nearest,
+    Linear,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TextureWrap {
     Repeat,
     ClampToEdge,
     MirroredRepeat,
 }
 
+/// The sampling of a texture, see `Texture::set_sampler`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureSampler {
+    pub min_filter: TextureFiltering,
+    pub mag_filter: TextureFiltering,
+    pub mipmap_filter: MipmapFiltering,
+    pub wrap_u: TextureWrap,
+    pub wrap_v: TextureWrap,
+    /// The third axis of the cube maps
+    pub wrap_w: TextureWrap,
+    /// Samples of EXT_texture_filter_anisotropic, 1 for isotropic filtering.
+    /// Clamped to `Capabilities::max_anisotropy`.
+    pub anisotropy: f32,
+}
+
+impl TextureSampler {
+    /// Clamped, with trilinear filtering for `Linear` and no mipmaps for `Nearest`
+    pub fn new(filtering: TextureFiltering) -> TextureSampler {
+        TextureSampler {
+            min_filter: filtering,
+            mag_filter: filtering,
+            mipmap_filter: match filtering {
+                TextureFiltering::Nearest => MipmapFiltering::None,
+                TextureFiltering::Linear => MipmapFiltering::Linear,
+            },
+            wrap_u: TextureWrap::ClampToEdge,
+            wrap_v: TextureWrap::ClampToEdge,
+            wrap_w: TextureWrap::ClampToEdge,
+            anisotropy: 1.0,
+        }
+    }
+
+    /// The min and mag filters, with the mipmap filter of `new`
+    fn with_filtering(self, filtering: TextureFiltering) -> TextureSampler {
+        let defaults = TextureSampler::new(filtering);
+
+        TextureSampler {
+            min_filter: defaults.min_filter,
+            mag_filter: defaults.mag_filter,
+            mipmap_filter: defaults.mipmap_filter,
+            ..self
+        }
+    }
+}
+
+impl Default for TextureSampler {
+    fn default() -> TextureSampler {
+        TextureSampler::new(TextureFiltering::Linear)
+    }
+}
+
 #[derive(Debug)]
 pub enum TextureImage {
     Rgba(RgbaImage),
     Rgb(RgbImage),
+    /// Linear HDR colors, e.g. of a .hdr file, uploaded as a float texture, or
+    /// RGBM encoded without float textures
+    RgbF32(ImageBuffer<Rgb<f32>, Vec<f32>>),
+    /// A 16 bits grayscale image, e.g. a PNG heightmap, uploaded to the red
+    /// channel of a float texture, or 8 bits without float textures
+    Luma16(ImageBuffer<Luma<u16>, Vec<u16>>),
+    /// A 16 bits color image, uploaded as a float texture, or 8 bits without
+    /// float textures
+    Rgba16(ImageBuffer<Rgba<u16>, Vec<u16>>),
     DXT1(DDS),
     DXT5(DDS),
+    /// The ETC2 and ASTC images of the KTX files, uploaded in the internal
+    /// format of their `DDSFormat`
+    ETC2(DDS),
+    ASTC(DDS),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum TextureAttachment {
     Color0,
+    /// Floating point color for HDR rendering, needs float render targets
+    /// (WebGL2 or the color_buffer_float extensions)
+    Color0Hdr,
+    /// Half float color, HDR at half the memory of `Color0Hdr`
+    Color0HalfFloat,
+    /// A single float channel, e.g. linear depth or circle of confusion
+    Color0R32F,
     Depth,
 }
 
+impl TextureAttachment {
+    pub fn is_color(&self) -> bool {
+        match *self {
+            TextureAttachment::Depth => false,
+            _ => true,
+        }
+    }
+}
+
 #[derive(Debug)]
 enum TextureKind {
     Image(Resource<TextureImage>),
     CubeMap([Resource<TextureImage>; 6]),
+    /// Cube map converted from the equirectangular image of a texture
+    EquirectCubeMap(Rc<Texture>),
+    /// Cube map from the images of 6 textures, see `Texture::new_cubemap`
+    FacesCubeMap(Vec<Rc<Texture>>),
+    /// Cube map cut from the cross layout image of a texture
+    CrossCubeMap(Rc<Texture>),
+    /// The images of the textures stacked from the bottom, see `Texture::new_array`
+    Array(Vec<Rc<Texture>>),
+    /// Rgba8 voxels, x first then y then z, see `Texture::new_3d`
+    Volume {
+        size: (u32, u32, u32),
+        data: Vec<u8>,
+    },
+    /// The images of the textures as the slices of a volume
+    VolumeSlices(Vec<Rc<Texture>>),
     RenderTexture {
         size: (u32, u32),
         attach: TextureAttachment,
+        /// Regenerated by `generate_mipmaps` after rendering
+        mipmaps: Cell<bool>,
+    },
+    Data {
+        size: (u32, u32),
+        data: RefCell<Vec<u8>>,
+        dirty: Cell<bool>,
     },
 }
 
-#[derive(Debug)]
+/// The values of the deprecated `filtering` and `wrap_*` cells of `Texture`
+#[derive(Copy, Clone, PartialEq)]
+struct LegacySampler {
+    filtering: TextureFiltering,
+    wrap_u: TextureWrap,
+    wrap_v: TextureWrap,
+    wrap_w: Option<TextureWrap>,
+}
+
+impl LegacySampler {
+    fn new(sampler: &TextureSampler) -> LegacySampler {
+        LegacySampler {
+            filtering: sampler.min_filter,
+            wrap_u: sampler.wrap_u,
+            wrap_v: sampler.wrap_v,
+            wrap_w: Some(sampler.wrap_w),
+        }
+    }
+}
+
 pub struct Texture {
+    // Kept from before `TextureSampler`, what is set in them is still applied
+    #[deprecated(note = "use `sampler` and `set_filtering` or `set_sampler`")]
     pub filtering: Cell<TextureFiltering>,
+    #[deprecated(note = "use `sampler` and `set_wrap` or `set_sampler`")]
     pub wrap_u: Cell<TextureWrap>,
+    #[deprecated(note = "use `sampler` and `set_wrap` or `set_sampler`")]
     pub wrap_v: Cell<TextureWrap>,
+    #[deprecated(note = "use `sampler` and `set_sampler`")]
     pub wrap_w: Cell<Option<TextureWrap>>,
 
-    gl_state: RefCell<Option<TextureGLState>>,
+    sampler: Cell<TextureSampler>,
+    /// The deprecated cells when `sampler` was last synced with them
+    synced: Cell<LegacySampler>,
+
+    /// Shared with the eviction of the GPU memory budget
+    gl_state: Rc<RefCell<Option<TextureGLState>>>,
     kind: TextureKind,
+    srgb: Cell<bool>,
+    /// Of the image textures
+    gen_mipmaps: Cell<bool>,
+}
+
+impl fmt::Debug for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Texture")
+            .field("sampler", &self.sampler)
+            .field("gl_state", &self.gl_state)
+            .field("kind", &self.kind)
+            .field("srgb", &self.srgb)
+            .field("gen_mipmaps", &self.gen_mipmaps)
+            .finish()
+    }
 }
 
 pub enum TextureAsset {
@@ -72,23 +236,15 @@ impl Asset for Texture {
 
     fn new_from_resource(r: Self::Resource) -> Rc<Self> {
         return match r {
-            TextureAsset::Single(res) => Rc::new(Texture {
-                filtering: Cell::new(TextureFiltering::Linear),
-                wrap_u: Cell::new(TextureWrap::ClampToEdge),
-                wrap_v: Cell::new(TextureWrap::ClampToEdge),
-                wrap_w: Cell::new(None),
-                gl_state: RefCell::new(None),
-                kind: TextureKind::Image(res),
-            }),
-
-            TextureAsset::Cube(res) => Rc::new(Texture {
-                filtering: Cell::new(TextureFiltering::Linear),
-                gl_state: RefCell::new(None),
-                kind: TextureKind::CubeMap(res),
-                wrap_u: Cell::new(TextureWrap::ClampToEdge),
-                wrap_v: Cell::new(TextureWrap::ClampToEdge),
-                wrap_w: Cell::new(Some(TextureWrap::ClampToEdge)),
-            }),
+            TextureAsset::Single(res) => Texture::with_kind(
+                TextureKind::Image(res),
+                Default::default(),
+            ),
+
+            TextureAsset::Cube(res) => Texture::with_kind(
+                TextureKind::CubeMap(res),
+                Default::default(),
+            ),
         };
     }
 }
@@ -146,102 +302,1061 @@ impl LoadableAsset for Texture {
     }
 }
 
-#[derive(Debug)]
 struct TextureGLState {
     tex: WebGLTexture,
     size: (u32, u32),
+    gl_kind: uni_gl::TextureKind,
+    /// `TEXTURE_2D_ARRAY` or `TEXTURE_3D` of the array and 3D textures of a
+    /// WebGL2 context, `gl_kind` is not used then
+    target_3d: Option<u32>,
+    has_mipmap: bool,
+    /// Float textures without linear filtering support
+    force_nearest: bool,
+    /// HDR image uploaded RGBM encoded, without float texture support
+    rgbm: bool,
+    /// The sampler set on `tex`
+    sampler: Cell<TextureSampler>,
+    /// The `context_generation` `tex` was created in
+    context: u32,
+    memory: GpuAllocation,
+    gl: WebGLRenderingContext,
+}
+
+impl fmt::Debug for TextureGLState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TextureGLState")
+            .field("tex", &self.tex)
+            .field("size", &self.size)
+            .field("gl_kind", &self.gl_kind)
+            .field("target_3d", &self.target_3d)
+            .field("has_mipmap", &self.has_mipmap)
+            .field("force_nearest", &self.force_nearest)
+            .field("rgbm", &self.rgbm)
+            .field("sampler", &self.sampler)
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+impl TextureGLState {
+    /// Bind `tex` to the active unit, the 2D targets or the one of `target_3d`
+    fn bind_2d(&self, gl: &WebGLRenderingContext) {
+        match self.target_3d {
+            Some(target) => raw_gl::bind_texture_3d(gl, target, &self.tex),
+            None => gl.bind_texture(&self.tex),
+        }
+    }
+
+    /// The target `tex` is bound to
+    fn target(&self) -> TextureTarget {
+        match self.target_3d {
+            Some(raw_gl::TEXTURE_2D_ARRAY) => TextureTarget::Array,
+            Some(_) => TextureTarget::Volume,
+            None => match self.gl_kind {
+                uni_gl::TextureKind::TextureCubeMap => TextureTarget::CubeMap,
+                _ => TextureTarget::Texture2d,
+            },
+        }
+    }
+}
+
+impl Drop for TextureGLState {
+    fn drop(&mut self) {
+        // The handle went away with the lost context
+        if self.context == context_generation() {
+            self.gl.destroy_texture(&self.tex);
+        }
+    }
 }
 
 impl Texture {
-    pub fn new_render_texture(width: u32, height: u32, attach: TextureAttachment) -> Rc<Self> {
+    #[allow(deprecated)]
+    fn with_kind(kind: TextureKind, sampler: TextureSampler) -> Rc<Self> {
+        let legacy = LegacySampler::new(&sampler);
+
         Rc::new(Texture {
-            filtering: Cell::new(TextureFiltering::Linear),
-            gl_state: RefCell::new(None),
-            wrap_u: Cell::new(TextureWrap::ClampToEdge),
-            wrap_v: Cell::new(TextureWrap::ClampToEdge),
-            wrap_w: Cell::new(None),
-            kind: TextureKind::RenderTexture {
+            filtering: Cell::new(legacy.filtering),
+            wrap_u: Cell::new(legacy.wrap_u),
+            wrap_v: Cell::new(legacy.wrap_v),
+            wrap_w: Cell::new(legacy.wrap_w),
+            sampler: Cell::new(sampler),
+            synced: Cell::new(legacy),
+            gl_state: Default::default(),
+            kind,
+            srgb: Cell::new(true),
+            gen_mipmaps: Cell::new(true),
+        })
+    }
+
+    pub fn new_render_texture(width: u32, height: u32, attach: TextureAttachment) -> Rc<Self> {
+        Texture::with_kind(
+            TextureKind::RenderTexture {
                 size: (width, height),
                 attach: attach,
+                mipmaps: Cell::new(false),
             },
-        })
+            Default::default(),
+        )
+    }
+
+    /// A cube map converted from the equirectangular (latitude-longitude) image
+    /// of `source` once it is loaded. The faces are RGBA16F with
+    /// `Capabilities::float_textures`, RGBM encoded otherwise, see `RGBM_RANGE`.
+    pub fn new_equirect_cubemap(source: Rc<Texture>) -> Rc<Self> {
+        Texture::with_kind(TextureKind::EquirectCubeMap(source), Default::default())
+    }
+
+    /// A cube map from the square images of `faces` once they are loaded, in the
+    /// +X, -X, +Y, -Y, +Z, -Z order of the GL cube map faces
+    pub fn new_cubemap(faces: [Rc<Texture>; 6]) -> Rc<Self> {
+        Texture::with_kind(TextureKind::FacesCubeMap(faces.to_vec()), Default::default())
+    }
+
+    /// A cube map cut from the cross layout image of `source` once it is loaded,
+    /// either a horizontal 4x3 cross or a vertical 3x4 one with the -Z face
+    /// upside down at the bottom
+    pub fn new_cross_cubemap(source: Rc<Texture>) -> Rc<Self> {
+        Texture::with_kind(TextureKind::CrossCubeMap(source), Default::default())
+    }
+
+    /// The same sized images of `layers` in one texture, once they are loaded,
+    /// so a shader can index them with a single texture unit. It is a
+    /// TEXTURE_2D_ARRAY on WebGL2, otherwise the layers are stacked vertically
+    /// in a 2D texture without mipmaps, to keep the layers from bleeding
+    /// together. Both are sampled with `TextureArray` of `unrust/texture_array.glsl`,
+    /// from GLSL 300 es shaders on WebGL2.
+    pub fn new_array(layers: Vec<Rc<Texture>>) -> Rc<Self> {
+        Texture::with_kind(
+            TextureKind::Array(layers),
+            TextureSampler {
+                mipmap_filter: MipmapFiltering::None,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// The number of layers of an array texture
+    pub fn layers(&self) -> Option<usize> {
+        match self.kind {
+            TextureKind::Array(ref layers) => Some(layers.len()),
+            _ => None,
+        }
+    }
+
+    /// A volume of `size` (width, height, depth) from Rgba8 `data`, x first then
+    /// y then z. It is a TEXTURE_3D on WebGL2, otherwise the depth slices are
+    /// laid side by side in a 2D texture like the LUT strips of `ColorGrading`.
+    /// Both are sampled with `Texture3D` of `unrust/texture_3d.glsl`, from
+    /// GLSL 300 es shaders on WebGL2.
+    pub fn new_3d(size: (u32, u32, u32), data: Vec<u8>) -> Rc<Self> {
+        assert_eq!(data.len(), (size.0 * size.1 * size.2 * 4) as usize);
+
+        Texture::new_volume(TextureKind::Volume { size, data })
+    }
+
+    /// A volume of the same sized images of `slices` once they are loaded,
+    /// from the front (z = 0) to the back, see `new_3d`
+    pub fn new_3d_from_slices(slices: Vec<Rc<Texture>>) -> Rc<Self> {
+        Texture::new_volume(TextureKind::VolumeSlices(slices))
+    }
+
+    fn new_volume(kind: TextureKind) -> Rc<Self> {
+        Texture::with_kind(
+            kind,
+            TextureSampler {
+                mipmap_filter: MipmapFiltering::None,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// The number of slices of a 3D texture
+    pub fn depth(&self) -> Option<usize> {
+        match self.kind {
+            TextureKind::Volume { size, .. } => Some(size.2 as usize),
+            TextureKind::VolumeSlices(ref slices) => Some(slices.len()),
+            _ => None,
+        }
+    }
+
+    /// Rgba8 texture filled from the cpu side with `set_data`
+    pub fn new_data_texture(width: u32, height: u32) -> Rc<Self> {
+        Texture::with_kind(
+            TextureKind::Data {
+                size: (width, height),
+                data: RefCell::new(vec![0; (width * height * 4) as usize]),
+                dirty: Cell::new(false),
+            },
+            TextureSampler::new(TextureFiltering::Nearest),
+        )
+    }
+
+    /// Replace the pixels of a data texture, uploaded the next time it is bound
+    pub fn set_data(&self, pixels: Vec<u8>) {
+        if let TextureKind::Data {
+            size,
+            ref data,
+            ref dirty,
+        } = self.kind
+        {
+            assert_eq!(pixels.len(), (size.0 * size.1 * 4) as usize);
+
+            data.replace(pixels);
+            dirty.set(true);
+        }
+    }
+
+    /// Replace the image of a texture loaded from a single image, e.g. when its
+    /// file changed, uploaded the next time it is bound. False for the others.
+    pub fn replace_image(&self, img: TextureImage) -> bool {
+        match self.kind {
+            TextureKind::Image(ref res) => {
+                res.replace(img);
+                self.gl_state.replace(None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Give a color render texture mipmaps, to call before it is first bound.
+    /// WebGL1 only supports them for power of two sizes.
+    pub fn set_render_mipmaps(&self, enabled: bool) {
+        if let TextureKind::RenderTexture {
+            ref attach,
+            ref mipmaps,
+            ..
+        } = self.kind
+        {
+            mipmaps.set(enabled && attach.is_color());
+        }
+    }
+
+    /// Rebuild the mipmaps of a render texture from its first level,
+    /// after rendering into it
+    pub fn generate_mipmaps(&self, gl: &WebGLRenderingContext) {
+        let enabled = match self.kind {
+            TextureKind::RenderTexture { ref mipmaps, .. } => mipmaps.get(),
+            _ => false,
+        };
+
+        if !enabled {
+            return;
+        }
+
+        if let Some(ref state) = *self.gl_state.borrow() {
+            gl.active_texture(0);
+            gl.bind_texture(&state.tex);
+            gl.generate_mipmap();
+        }
     }
 
     pub fn size(&self) -> Option<(u32, u32)> {
         self.gl_state.borrow().as_ref().map(|s| s.size)
     }
 
-    pub fn bind(&self, gl: &WebGLRenderingContext, unit: u32) -> AssetResult<()> {
-        self.prepare(gl, unit)?;
+    /// Whether the colors are RGBM encoded once uploaded, see `RGBM_RANGE`
+    pub fn is_rgbm(&self) -> bool {
+        self.gl_state.borrow().as_ref().map_or(false, |s| s.rgbm)
+    }
+
+    /// Including what was set in the deprecated `filtering` and `wrap_*` cells
+    pub fn sampler(&self) -> TextureSampler {
+        let legacy = self.legacy_sampler();
+        let synced = self.synced.get();
+        if legacy == synced {
+            return self.sampler.get();
+        }
+
+        let mut sampler = self.sampler.get();
+        if legacy.filtering != synced.filtering {
+            sampler = sampler.with_filtering(legacy.filtering);
+        }
+        if legacy.wrap_u != synced.wrap_u {
+            sampler.wrap_u = legacy.wrap_u;
+        }
+        if legacy.wrap_v != synced.wrap_v {
+            sampler.wrap_v = legacy.wrap_v;
+        }
+        match legacy.wrap_w {
+            Some(wrap_w) if legacy.wrap_w != synced.wrap_w => sampler.wrap_w = wrap_w,
+            _ => {}
+        }
+
+        self.sampler.set(sampler);
+        self.synced.set(legacy);
+        sampler
+    }
+
+    /// Set at load time or afterward, applied the next time the texture is bound
+    #[allow(deprecated)]
+    pub fn set_sampler(&self, sampler: TextureSampler) {
+        let legacy = LegacySampler::new(&sampler);
+
+        self.filtering.set(legacy.filtering);
+        self.wrap_u.set(legacy.wrap_u);
+        self.wrap_v.set(legacy.wrap_v);
+        self.wrap_w.set(legacy.wrap_w);
+        self.synced.set(legacy);
+        self.sampler.set(sampler);
+    }
+
+    /// The min and mag filters, with the mipmap filter of `TextureSampler::new`
+    pub fn set_filtering(&self, filtering: TextureFiltering) {
+        self.set_sampler(self.sampler().with_filtering(filtering));
+    }
+
+    pub fn set_wrap(&self, wrap_u: TextureWrap, wrap_v: TextureWrap) {
+        self.set_sampler(TextureSampler {
+            wrap_u,
+            wrap_v,
+            ..self.sampler()
+        });
+    }
+
+    #[allow(deprecated)]
+    fn legacy_sampler(&self) -> LegacySampler {
+        LegacySampler {
+            filtering: self.filtering.get(),
+            wrap_u: self.wrap_u.get(),
+            wrap_v: self.wrap_v.get(),
+            wrap_w: self.wrap_w.get(),
+        }
+    }
+
+    pub fn is_srgb(&self) -> bool {
+        self.srgb.get()
+    }
+
+    /// Whether the texels are sRGB colors, decoded by the linear color space,
+    /// the default. False for data like masks, the materials set the
+    /// `<param>_linear` uniform of each texture param to `!is_srgb()`.
+    pub fn set_srgb(&self, srgb: bool) {
+        self.srgb.set(srgb);
+    }
+
+    pub fn mipmaps(&self) -> bool {
+        self.gen_mipmaps.get()
+    }
+
+    /// Whether mipmaps are generated when an image texture is uploaded, the
+    /// default. The gl texture is created again when it changes.
+    pub fn set_mipmaps(&self, enabled: bool) {
+        if self.gen_mipmaps.replace(enabled) != enabled && !self.is_render_texture() {
+            self.gl_state.replace(None);
+        }
+    }
+
+    /// Apply the sampler when it changed since it was set, `tex` is bound
+    fn update_sampler(
+        &self,
+        gl: &WebGLRenderingContext,
+        caps: &Capabilities,
+        state: &TextureGLState,
+    ) {
+        let sampler = self.sampler();
+        if state.sampler.get() != sampler {
+            apply_sampler(gl, caps, state, &sampler);
+            state.sampler.set(sampler);
+        }
+    }
+
+    pub fn bind(
+        &self,
+        gl: &WebGLRenderingContext,
+        caps: &Capabilities,
+        unit: u32,
+    ) -> AssetResult<()> {
+        self.prepare(gl, caps, unit)?;
 
         let state_option = self.gl_state.borrow();
         let state = state_option.as_ref().unwrap();
 
         gl.active_texture(unit);
         match self.kind {
-            TextureKind::CubeMap(_) => gl.bind_texture_cube(&state.tex),
-            _ => gl.bind_texture(&state.tex),
+            TextureKind::CubeMap(_)
+            | TextureKind::EquirectCubeMap(_)
+            | TextureKind::FacesCubeMap(_)
+            | TextureKind::CrossCubeMap(_) => gl.bind_texture_cube(&state.tex),
+            _ => state.bind_2d(gl),
+        }
+
+        self.update_sampler(gl, caps, state);
+
+        if let TextureKind::Data {
+            size,
+            ref data,
+            ref dirty,
+        } = self.kind
+        {
+            if dirty.get() {
+                upload_data(gl, size, &data.borrow());
+                dirty.set(false);
+            }
         }
 
         Ok(())
     }
 
-    pub fn bind_with_frame_buffer(&self, gl: &WebGLRenderingContext, unit: u32) -> AssetResult<()> {
-        self.prepare(gl, unit)?;
+    pub fn bind_with_frame_buffer(
+        &self,
+        gl: &WebGLRenderingContext,
+        caps: &Capabilities,
+        unit: u32,
+    ) -> AssetResult<()> {
+        self.prepare(gl, caps, unit)?;
 
         let state_option = self.gl_state.borrow();
         let state = state_option.as_ref().unwrap();
 
         gl.active_texture(unit);
         match self.kind {
-            TextureKind::CubeMap(_) => gl.bind_texture_cube(&state.tex),
-            _ => gl.bind_texture(&state.tex),
+            TextureKind::CubeMap(_)
+            | TextureKind::EquirectCubeMap(_)
+            | TextureKind::FacesCubeMap(_)
+            | TextureKind::CrossCubeMap(_) => gl.bind_texture_cube(&state.tex),
+            _ => state.bind_2d(gl),
         }
 
         if let TextureKind::RenderTexture { ref attach, .. } = self.kind {
-            match attach {
-                &TextureAttachment::Color0 => {
-                    bind_to_framebuffer(gl, &state.tex, Buffers::ColorAttachment0);
-                }
-                &TextureAttachment::Depth => {
-                    bind_to_framebuffer(gl, &state.tex, Buffers::DepthAttachment);
-                    gl.draw_buffer(&[ColorBuffer::None]);
-                }
+            if attach.is_color() {
+                gl.attach_texture(&state.tex, FrameAttachment::Color(0));
+            } else {
+                gl.attach_texture(&state.tex, FrameAttachment::Depth);
+                gl.set_color_outputs(&[false]);
             }
         }
 
         Ok(())
     }
 
-    pub fn prepare(&self, gl: &WebGLRenderingContext, unit: u32) -> AssetResult<()> {
-        if self.gl_state.borrow().is_some() {
-            return Ok(());
+    /// Attach as the `index` color output of the bound frame buffer,
+    /// used by multiple render targets
+    pub fn bind_color_with_frame_buffer(
+        &self,
+        gl: &WebGLRenderingContext,
+        caps: &Capabilities,
+        unit: u32,
+        index: usize,
+    ) -> AssetResult<()> {
+        self.prepare(gl, caps, unit)?;
+
+        let state_option = self.gl_state.borrow();
+        let state = state_option.as_ref().unwrap();
+
+        gl.active_texture(unit);
+        gl.bind_texture(&state.tex);
+        gl.attach_texture(&state.tex, FrameAttachment::Color(index));
+
+        Ok(())
+    }
+
+    /// Attach as the depth buffer of the bound frame buffer, next to its color texture
+    pub fn bind_depth_with_frame_buffer(
+        &self,
+        gl: &WebGLRenderingContext,
+        caps: &Capabilities,
+        unit: u32,
+    ) -> AssetResult<()> {
+        self.prepare(gl, caps, unit)?;
+
+        let state_option = self.gl_state.borrow();
+        let state = state_option.as_ref().unwrap();
+
+        gl.active_texture(unit);
+        gl.bind_texture(&state.tex);
+        gl.attach_texture(&state.tex, FrameAttachment::Depth);
+
+        Ok(())
+    }
+
+    /// Create the gl texture if needed, with the formats and targets `caps` has
+    pub fn prepare(
+        &self,
+        gl: &WebGLRenderingContext,
+        caps: &Capabilities,
+        unit: u32,
+    ) -> AssetResult<()> {
+        match *self.gl_state.borrow() {
+            Some(ref state) if state.context == context_generation() => {
+                state.memory.touch();
+                return Ok(());
+            }
+            _ => {}
         }
 
-        let new_state = Some(texture_bind_buffer(
+        let new_state = texture_bind_buffer(
             gl,
-            &self.filtering.get(),
-            self.wrap_u.get(),
-            self.wrap_v.get(),
-            self.wrap_w.get(),
+            caps,
+            &self.sampler(),
             &self.kind,
+            self.gen_mipmaps.get(),
             unit,
-        )?);
+        )?;
+
+        // The others are created again from their images or data, the content
+        // of a render texture would be lost
+        if !self.is_render_texture() {
+            let gl_state = Rc::downgrade(&self.gl_state);
 
-        self.gl_state.replace(new_state);
+            new_state.memory.set_evict(move || {
+                if let Some(gl_state) = gl_state.upgrade() {
+                    if let Ok(mut state) = gl_state.try_borrow_mut() {
+                        state.take();
+                    }
+                }
+            });
+        }
+
+        self.gl_state.replace(Some(new_state));
 
         Ok(())
     }
+
+    /// Free the gl texture now instead of when the texture is dropped, it is
+    /// created again from the image or data when next bound. Not for the
+    /// render textures, whose content would be lost.
+    pub fn release_gpu(&self) {
+        if !self.is_render_texture() {
+            self.gl_state.replace(None);
+        }
+    }
+
+    fn is_render_texture(&self) -> bool {
+        match self.kind {
+            TextureKind::RenderTexture { .. } => true,
+            _ => false,
+        }
+    }
 }
 
-fn bind_to_framebuffer(gl: &WebGLRenderingContext, tex: &WebGLTexture, buffer: Buffers) {
-    gl.framebuffer_texture2d(
-        Buffers::Framebuffer,
-        buffer,
-        TextureBindPoint::Texture2d,
-        tex,
-        0,
-    );
+/// Range of the RGBM encoded HDR images, decoded as `rgb * a * RGBM_RANGE`
+pub const RGBM_RANGE: f32 = 8.0;
+
+const CUBE_MAP_FACES: [TextureBindPoint; 6] = [
+    TextureBindPoint::TextureCubeMapPositiveX,
+    TextureBindPoint::TextureCubeMapNegativeX,
+    TextureBindPoint::TextureCubeMapPositiveY,
+    TextureBindPoint::TextureCubeMapNegativeY,
+    TextureBindPoint::TextureCubeMapPositiveZ,
+    TextureBindPoint::TextureCubeMapNegativeZ,
+];
+
+fn invalid_equirect(reason: &str) -> AssetError {
+    AssetError::InvalidFormat {
+        path: "equirectangular cube map".to_string(),
+        len: 0,
+        reason: reason.to_string(),
+    }
+}
+
+fn invalid_cubemap(reason: &str) -> AssetError {
+    AssetError::InvalidFormat {
+        path: "cube map".to_string(),
+        len: 0,
+        reason: reason.to_string(),
+    }
+}
+
+/// The compressed images the context can't upload fail to load
+fn check_compression(img: &TextureImage, caps: &Capabilities) -> AssetResult<()> {
+    let unsupported = |reason: &str| {
+        Err(AssetError::InvalidFormat {
+            path: "compressed texture".to_string(),
+            len: 0,
+            reason: reason.to_string(),
+        })
+    };
+
+    match *img {
+        TextureImage::DXT1(_) | TextureImage::DXT5(_) if !caps.s3tc => unsupported(
+            "DXT1 and DXT5 need WEBGL_compressed_texture_s3tc, which the context doesn't support",
+        ),
+        TextureImage::ETC2(_) if !caps.etc2 => unsupported(
+            "ETC2 needs WEBGL_compressed_texture_etc, which the context doesn't support",
+        ),
+        TextureImage::ASTC(_) if !caps.astc => unsupported(
+            "ASTC needs WEBGL_compressed_texture_astc, which the context doesn't support",
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Upload the mip chain of an ETC2 or ASTC image to `target` of the bound
+/// texture, uni-gl has no `TextureCompression` for them
+fn upload_compressed_levels(gl: &WebGLRenderingContext, target: TextureBindPoint, dds: &DDS) {
+    let internal_format = match dds.format {
+        DDSFormat::ETC2(f) | DDSFormat::ASTC(f) => f,
+        DDSFormat::DXT1 | DDSFormat::DXT5 => return,
+    };
+
+    for (lvl, img) in dds.images.iter().enumerate() {
+        raw_gl::compressed_tex_image_2d(
+            gl,
+            target as u32,
+            lvl as u32,
+            internal_format,
+            (img.width, img.height),
+            &img.data,
+        );
+    }
+}
+
+fn rgbm(c: [f32; 3]) -> [u8; 4] {
+    let max = c[0].max(c[1]).max(c[2]) / RGBM_RANGE;
+    let m = ((max.min(1.0) * 255.0).ceil() / 255.0).max(1.0 / 255.0);
+    let scale = 255.0 / (m * RGBM_RANGE);
+    let channel = |v: f32| (v * scale).max(0.0).min(255.0).round() as u8;
+
+    [channel(c[0]), channel(c[1]), channel(c[2]), (m * 255.0).round() as u8]
+}
+
+fn encode_rgbm(img: &ImageBuffer<Rgb<f32>, Vec<f32>>) -> Vec<u8> {
+    let mut data = Vec::with_capacity((img.width() * img.height() * 4) as usize);
+    for p in img.pixels() {
+        data.extend_from_slice(&rgbm(p.data));
+    }
+
+    data
+}
+
+fn invalid_array(reason: &str) -> AssetError {
+    AssetError::InvalidFormat {
+        path: "texture array".to_string(),
+        len: 0,
+        reason: reason.to_string(),
+    }
+}
+
+fn invalid_volume(reason: &str) -> AssetError {
+    AssetError::InvalidFormat {
+        path: "3D texture".to_string(),
+        len: 0,
+        reason: reason.to_string(),
+    }
+}
+
+/// The Rgba8 pixels of an uncompressed image, RGBM encoded for the HDR ones
+fn rgba_pixels(img: &TextureImage) -> Option<((u32, u32), Vec<u8>)> {
+    match *img {
+        TextureImage::Rgba(ref img) => Some((img.dimensions(), img.clone().into_raw())),
+        TextureImage::Rgb(ref img) => {
+            let mut data = Vec::with_capacity((img.width() * img.height() * 4) as usize);
+            for p in img.pixels() {
+                data.extend_from_slice(&[p.data[0], p.data[1], p.data[2], 255]);
+            }
+            Some((img.dimensions(), data))
+        }
+        TextureImage::RgbF32(ref img) => Some((img.dimensions(), encode_rgbm(img))),
+        TextureImage::Luma16(ref img) => {
+            let mut data = Vec::with_capacity((img.width() * img.height() * 4) as usize);
+            for p in img.pixels() {
+                let v = (p.data[0] >> 8) as u8;
+                data.extend_from_slice(&[v, v, v, 255]);
+            }
+            Some((img.dimensions(), data))
+        }
+        TextureImage::Rgba16(ref img) => {
+            let data = img.iter().map(|&v| (v >> 8) as u8).collect();
+            Some((img.dimensions(), data))
+        }
+        _ => None,
+    }
+}
+
+/// The size of an uncompressed image
+fn image_size(img: &TextureImage) -> Option<(u32, u32)> {
+    match *img {
+        TextureImage::Rgba(ref img) => Some(img.dimensions()),
+        TextureImage::Rgb(ref img) => Some(img.dimensions()),
+        TextureImage::RgbF32(ref img) => Some(img.dimensions()),
+        TextureImage::Luma16(ref img) => Some(img.dimensions()),
+        TextureImage::Rgba16(ref img) => Some(img.dimensions()),
+        _ => None,
+    }
+}
+
+/// The channels of an HDR or 16 bits image as floats, the 16 bits ones in [0, 1]
+fn float_pixels(img: &TextureImage) -> Option<(PixelFormat, Vec<f32>)> {
+    let normalize = |v: &u16| f32::from(*v) / 65535.0;
+
+    match *img {
+        TextureImage::RgbF32(ref img) => {
+            let mut data = Vec::with_capacity((img.width() * img.height() * 4) as usize);
+            for p in img.pixels() {
+                data.extend_from_slice(&[p.data[0], p.data[1], p.data[2], 1.0]);
+            }
+            Some((PixelFormat::Rgba, data))
+        }
+        TextureImage::Luma16(ref img) => {
+            Some((PixelFormat::Red, img.iter().map(normalize).collect()))
+        }
+        TextureImage::Rgba16(ref img) => {
+            Some((PixelFormat::Rgba, img.iter().map(normalize).collect()))
+        }
+        _ => None,
+    }
+}
+
+/// Upload an HDR or 16 bits image to `target` of the bound texture, as a float
+/// texture to keep its precision when the context has them, as 8 bits otherwise.
+/// Returns whether it is a float texture, they have no mipmaps nor linear filtering.
+fn upload_high_precision(
+    gl: &WebGLRenderingContext,
+    caps: &Capabilities,
+    target: TextureBindPoint,
+    img: &TextureImage,
+) -> bool {
+    let (w, h) = match image_size(img) {
+        Some(size) => size,
+        None => return false,
+    };
+
+    if caps.float_textures {
+        if let Some((format, data)) = float_pixels(img) {
+            let bytes =
+                unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 4) };
+            gl.tex_image2d(target, 0, w as u16, h as u16, format, PixelType::Float, bytes);
+            return true;
+        }
+    }
+
+    if let Some((_, data)) = rgba_pixels(img) {
+        gl.tex_image2d(
+            target,
+            0,
+            w as u16,
+            h as u16,
+            PixelFormat::Rgba,
+            PixelType::UnsignedByte,
+            &data,
+        );
+    }
+
+    false
+}
+
+/// The pixels of the same sized images of `layers`, one after the other
+fn layers_pixels(
+    layers: &[Rc<Texture>],
+    invalid: fn(&str) -> AssetError,
+) -> AssetResult<((u32, u32), Vec<u8>)> {
+    let mut size = None;
+    let mut data = Vec::new();
+
+    for layer in layers.iter() {
+        let img_res = match layer.kind {
+            TextureKind::Image(ref res) => res,
+            _ => return Err(invalid("A layer is not a single image")),
+        };
+
+        let (layer_size, pixels) = match rgba_pixels(&*img_res.try_borrow()?) {
+            Some(pixels) => pixels,
+            None => return Err(invalid("Compressed images are not supported")),
+        };
+
+        if *size.get_or_insert(layer_size) != layer_size {
+            return Err(invalid("The layers are not the same size"));
+        }
+
+        data.extend_from_slice(&pixels);
+    }
+
+    let size = size.ok_or_else(|| invalid("No layers"))?;
+    Ok((size, data))
+}
+
+fn max_texture_size(gl: &WebGLRenderingContext) -> u32 {
+    gl.get_parameter(Parameter::MaxTextureSize).max(0) as u32
+}
+
+/// The layers of an array texture stacked in one image, bottom to top
+fn stack_layers(
+    gl: &WebGLRenderingContext,
+    layers: &[Rc<Texture>],
+) -> AssetResult<((u32, u32), Vec<u8>)> {
+    let ((w, h), data) = layers_pixels(layers, invalid_array)?;
+    let height = h * layers.len() as u32;
+
+    if height > max_texture_size(gl) {
+        return Err(invalid_array("The stacked layers exceed the max texture size"));
+    }
+
+    Ok(((w, height), data))
+}
+
+/// A `TEXTURE_2D_ARRAY` or `TEXTURE_3D` texture of the Rgba8 texels of `size`,
+/// bound to `unit`
+fn upload_3d(
+    gl: &WebGLRenderingContext,
+    unit: u32,
+    target: u32,
+    size: (u32, u32, u32),
+    data: &[u8],
+    mipmaps: bool,
+) -> WebGLTexture {
+    let tex = gl.create_texture();
+    gl.active_texture(unit);
+    raw_gl::bind_texture_3d(gl, target, &tex);
+    raw_gl::tex_image_3d(gl, target, size, data);
+
+    if mipmaps {
+        raw_gl::generate_mipmap_3d(gl, target);
+    }
+
+    tex
+}
+
+/// The slices of a volume laid side by side in one image, front to back
+fn volume_strip(
+    gl: &WebGLRenderingContext,
+    (w, h, d): (u32, u32, u32),
+    data: &[u8],
+) -> AssetResult<((u32, u32), Vec<u8>)> {
+    if w * d > max_texture_size(gl) {
+        return Err(invalid_volume("The slices exceed the max texture size"));
+    }
+
+    let row = (w * 4) as usize;
+    let mut strip = Vec::with_capacity(data.len());
+
+    for y in 0..h as usize {
+        for z in 0..d as usize {
+            let start = (z * h as usize + y) * row;
+            strip.extend_from_slice(&data[start..start + row]);
+        }
+    }
+
+    Ok(((w * d, h), strip))
+}
+
+/// Color of a texel of an uncompressed image, in [0, 1] for 8 and 16 bits images
+fn texel(img: &TextureImage, x: u32, y: u32) -> [f32; 3] {
+    let to_f32 = |p: &[u8]| [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0];
+    let u16_to_f32 = |v: u16| f32::from(v) / 65535.0;
+
+    match *img {
+        TextureImage::Rgba(ref img) => to_f32(&img.get_pixel(x, y).data),
+        TextureImage::Rgb(ref img) => to_f32(&img.get_pixel(x, y).data),
+        TextureImage::RgbF32(ref img) => img.get_pixel(x, y).data,
+        TextureImage::Luma16(ref img) => [u16_to_f32(img.get_pixel(x, y).data[0]); 3],
+        TextureImage::Rgba16(ref img) => {
+            let p = img.get_pixel(x, y).data;
+            [u16_to_f32(p[0]), u16_to_f32(p[1]), u16_to_f32(p[2])]
+        }
+        _ => [0.0; 3],
+    }
+}
+
+/// Bilinear sample of an equirectangular image, wrapping around horizontally
+fn sample_equirect(img: &TextureImage, (w, h): (u32, u32), u: f32, v: f32) -> [f32; 3] {
+    let x = u * w as f32 - 0.5;
+    let y = (v * h as f32 - 0.5).max(0.0);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let wrap_x = |x: f32| ((x as i32 % w as i32 + w as i32) % w as i32) as u32;
+    let clamp_y = |y: f32| (y as u32).min(h - 1);
+
+    let c00 = texel(img, wrap_x(x0), clamp_y(y0));
+    let c10 = texel(img, wrap_x(x0 + 1.0), clamp_y(y0));
+    let c01 = texel(img, wrap_x(x0), clamp_y(y0 + 1.0));
+    let c11 = texel(img, wrap_x(x0 + 1.0), clamp_y(y0 + 1.0));
+
+    let mut c = [0.0; 3];
+    for i in 0..3 {
+        let top = c00[i] + (c10[i] - c00[i]) * fx;
+        let bottom = c01[i] + (c11[i] - c01[i]) * fx;
+        c[i] = top + (bottom - top) * fy;
+    }
+
+    c
+}
+
+/// The face size and the 6 faces of a cross layout image of `size`, in the order
+/// of `CUBE_MAP_FACES`. None when the image is not a 4x3 or 3x4 cross.
+fn cross_to_cube_faces(size: (u32, u32), data: &[u8]) -> Option<(u32, Vec<Vec<u8>>)> {
+    let (w, h) = size;
+
+    // Tile of each face in the cross
+    let (face_size, tiles) = if w * 3 == h * 4 {
+        (w / 4, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)])
+    } else if w * 4 == h * 3 {
+        (w / 3, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)])
+    } else {
+        return None;
+    };
+
+    if face_size == 0 {
+        return None;
+    }
+
+    let faces = tiles
+        .iter()
+        .enumerate()
+        .map(|(face, &(tx, ty))| {
+            let flipped = face == 5 && w < h;
+            let mut pixels = Vec::with_capacity((face_size * face_size * 4) as usize);
+
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let (fx, fy) = if flipped {
+                        (face_size - 1 - x, face_size - 1 - y)
+                    } else {
+                        (x, y)
+                    };
+
+                    let i = (((ty * face_size + fy) * w + tx * face_size + fx) * 4) as usize;
+                    pixels.extend_from_slice(&data[i..i + 4]);
+                }
+            }
+
+            pixels
+        })
+        .collect();
+
+    Some((face_size, faces))
+}
+
+/// A new cube map of the Rgba8 `faces`, in the order of `CUBE_MAP_FACES`
+fn upload_cube_faces(
+    gl: &WebGLRenderingContext,
+    face_size: u32,
+    faces: &[Vec<u8>],
+) -> WebGLTexture {
+    let tex = gl.create_texture();
+    gl.active_texture(0);
+    gl.bind_texture_cube(&tex);
+
+    for (i, face) in faces.iter().enumerate() {
+        gl.tex_image2d(
+            CUBE_MAP_FACES[i],       // target
+            0,                       // level
+            face_size as u16,        // width
+            face_size as u16,        // height
+            PixelFormat::Rgba,       // format
+            PixelType::UnsignedByte, // type
+            face,                    // data
+        );
+    }
+
+    gl.generate_mipmap_cube();
+    tex
+}
+
+/// A new cube map of the Rgba f32 `faces`, in the order of `CUBE_MAP_FACES`,
+/// stored as RGBA16F. Without mipmaps, unlike `upload_cube_faces`.
+fn upload_float_cube_faces(
+    gl: &WebGLRenderingContext,
+    face_size: u32,
+    faces: &[Vec<f32>],
+) -> WebGLTexture {
+    let tex = gl.create_texture();
+    gl.active_texture(0);
+    gl.bind_texture_cube(&tex);
+
+    for (i, face) in faces.iter().enumerate() {
+        raw_gl::tex_image_2d_half_float(gl, CUBE_MAP_FACES[i] as u32, (face_size, face_size), face);
+    }
+
+    tex
+}
+
+/// The Rgba f32 `faces` RGBM encoded as Rgba8, for the contexts without float textures
+fn encode_rgbm_faces(faces: &[Vec<f32>]) -> Vec<Vec<u8>> {
+    faces
+        .iter()
+        .map(|face| {
+            let mut data = Vec::with_capacity(face.len());
+            for c in face.chunks(4) {
+                data.extend_from_slice(&rgbm([c[0], c[1], c[2]]));
+            }
+            data
+        })
+        .collect()
+}
+
+/// The face size and the 6 Rgba f32 faces of an equirectangular image, in the
+/// order of `CUBE_MAP_FACES`. None for compressed images.
+fn equirect_to_cube_faces(img: &TextureImage) -> Option<(u32, Vec<Vec<f32>>)> {
+    let (w, h) = image_size(img)?;
+
+    let size = (w / 4).max(1);
+
+    let faces = (0..6)
+        .map(|face| {
+            let mut data = Vec::with_capacity((size * size * 4) as usize);
+
+            for y in 0..size {
+                for x in 0..size {
+                    let s = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                    let t = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+
+                    // Direction of the texel, following the GL cube map faces
+                    let d = match face {
+                        0 => [1.0, -t, -s],
+                        1 => [-1.0, -t, s],
+                        2 => [s, 1.0, t],
+                        3 => [s, -1.0, -t],
+                        4 => [s, -t, 1.0],
+                        _ => [-s, -t, -1.0],
+                    };
+                    let len = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+
+                    // -Z is at the center of the image, +Y at the top
+                    let u = 0.5 + d[0].atan2(-d[2]) / (2.0 * f32::consts::PI);
+                    let v = (d[1] / len).max(-1.0).min(1.0).acos() / f32::consts::PI;
+
+                    let c = sample_equirect(img, (w, h), u, v);
+                    data.extend_from_slice(&[c[0], c[1], c[2], 1.0]);
+                }
+            }
+
+            data
+        })
+        .collect();
+
+    Some((size, faces))
+}
+
+fn upload_data(gl: &WebGLRenderingContext, size: (u32, u32), data: &[u8]) {
+    gl.upload_texture_rgba(size, data);
+}
+
+/// Color outputs a frame buffer can have, the minimum WebGL2 guarantees
+pub const MAX_COLOR_ATTACHMENTS: usize = 4;
+
+/// The attachment point and draw buffer of a color output
+pub fn color_attachment(index: usize) -> (Buffers, ColorBuffer) {
+    match index {
+        0 => (Buffers::ColorAttachment0, ColorBuffer::ColorAttachment0),
+        1 => (Buffers::ColorAttachment1, ColorBuffer::ColorAttachment1),
+        2 => (Buffers::ColorAttachment2, ColorBuffer::ColorAttachment2),
+        3 => (Buffers::ColorAttachment3, ColorBuffer::ColorAttachment3),
+        _ => panic!("Color attachment {} is out of range", index),
+    }
+}
+
+/// The GPU memory of a texture, as rgba8 texels
+fn estimate_bytes(kind: &TextureKind, size: (u32, u32), has_mipmap: bool) -> usize {
+    let layers = match *kind {
+        TextureKind::CubeMap(_)
+        | TextureKind::EquirectCubeMap(_)
+        | TextureKind::FacesCubeMap(_)
+        | TextureKind::CrossCubeMap(_) => 6,
+        TextureKind::Array(ref layers) | TextureKind::VolumeSlices(ref layers) => layers.len(),
+        TextureKind::Volume { size, .. } => size.2 as usize,
+        _ => 1,
+    };
+
+    let bytes = size.0 as usize * size.1 as usize * 4 * layers;
+
+    // The mip chain adds a third
+    if has_mipmap {
+        bytes + bytes / 3
+    } else {
+        bytes
+    }
 }
 
 // Web do no need to unbind the texture normally,
@@ -260,19 +1375,23 @@ fn bind_to_framebuffer(gl: &WebGLRenderingContext, tex: &WebGLTexture, buffer: B
 
 fn texture_bind_buffer(
     gl: &WebGLRenderingContext,
-    texfilter: &TextureFiltering,
-    wrap_u: TextureWrap,
-    wrap_v: TextureWrap,
-    wrap_w: Option<TextureWrap>,
+    caps: &Capabilities,
+    sampler: &TextureSampler,
     kind: &TextureKind,
+    gen_mipmaps: bool,
     unit: u32,
 ) -> AssetResult<TextureGLState> {
     let mut gl_tex_kind: uni_gl::TextureKind = uni_gl::TextureKind::Texture2d;
+    let mut target_3d = None;
     let mut force_nearest_filtering = false;
+    let mut rgbm = false;
 
     let (tex, size, has_midmap) = match kind {
         &TextureKind::Image(ref img_res) => {
-            let teximg = img_res.try_into()?;
+            // Borrowed, the image uploads again after an eviction or a context loss
+            let teximg = img_res.try_borrow()?;
+            check_compression(&teximg, caps)?;
+
             let tex = gl.create_texture();
             let size: (u32, u32);
             let has_midmap;
@@ -280,8 +1399,8 @@ fn texture_bind_buffer(
             gl.active_texture(unit);
             gl.bind_texture(&tex);
 
-            match teximg {
-                TextureImage::Rgba(img) => {
+            match *teximg {
+                TextureImage::Rgba(ref img) => {
                     size = (img.width(), img.height());
                     gl.tex_image2d(
                         TextureBindPoint::Texture2d, // target
@@ -293,10 +1412,12 @@ fn texture_bind_buffer(
                         &*img,                       // data
                     );
 
-                    gl.generate_mipmap();
-                    has_midmap = true;
+                    if gen_mipmaps {
+                        gl.generate_mipmap();
+                    }
+                    has_midmap = gen_mipmaps;
                 }
-                TextureImage::Rgb(img) => {
+                TextureImage::Rgb(ref img) => {
                     size = (img.width(), img.height());
                     gl.tex_image2d(
                         TextureBindPoint::Texture2d, // target
@@ -308,11 +1429,32 @@ fn texture_bind_buffer(
                         &*img,                       // data
                     );
 
-                    gl.generate_mipmap();
-                    has_midmap = true;
+                    if gen_mipmaps {
+                        gl.generate_mipmap();
+                    }
+                    has_midmap = gen_mipmaps;
+                }
+                ref img @ TextureImage::RgbF32(_)
+                | ref img @ TextureImage::Luma16(_)
+                | ref img @ TextureImage::Rgba16(_) => {
+                    size = image_size(img).unwrap_or((0, 0));
+
+                    if upload_high_precision(gl, caps, TextureBindPoint::Texture2d, img) {
+                        force_nearest_filtering = true;
+                        has_midmap = false;
+                    } else {
+                        rgbm = match *img {
+                            TextureImage::RgbF32(_) => true,
+                            _ => false,
+                        };
+                        if gen_mipmaps {
+                            gl.generate_mipmap();
+                        }
+                        has_midmap = gen_mipmaps;
+                    }
                 }
 
-                TextureImage::DXT1(dds) => {
+                TextureImage::DXT1(ref dds) => {
                     size = (dds.images[0].width, dds.images[0].height);
 
                     let format = if dds.has_alpha {
@@ -335,7 +1477,7 @@ fn texture_bind_buffer(
                     has_midmap = dds.images.len() > 1;
                 }
 
-                TextureImage::DXT5(dds) => {
+                TextureImage::DXT5(ref dds) => {
                     size = (dds.images[0].width, dds.images[0].height);
 
                     for (lvl, img) in dds.images.iter().enumerate() {
@@ -351,6 +1493,12 @@ fn texture_bind_buffer(
 
                     has_midmap = dds.images.len() > 1;
                 }
+
+                TextureImage::ETC2(ref dds) | TextureImage::ASTC(ref dds) => {
+                    size = (dds.images[0].width, dds.images[0].height);
+                    upload_compressed_levels(gl, TextureBindPoint::Texture2d, dds);
+                    has_midmap = dds.images.len() > 1;
+                }
             }
 
             (tex, size, has_midmap)
@@ -376,7 +1524,9 @@ fn texture_bind_buffer(
             }
 
             for res in img_res.iter() {
-                imgs.push(res.try_into()?);
+                let teximg = res.try_borrow()?;
+                check_compression(&teximg, caps)?;
+                imgs.push(teximg);
             }
 
             let tex = gl.create_texture();
@@ -386,8 +1536,8 @@ fn texture_bind_buffer(
             let mut need_gen_mipmap = false;
 
             for (i, teximg) in imgs.iter().enumerate() {
-                match teximg {
-                    &TextureImage::Rgba(ref img) => {
+                match **teximg {
+                    TextureImage::Rgba(ref img) => {
                         size = (img.width(), img.height());
                         gl.tex_image2d(
                             bindpoints[i],           // target
@@ -400,7 +1550,7 @@ fn texture_bind_buffer(
                         );
                         need_gen_mipmap = true;
                     }
-                    &TextureImage::Rgb(ref img) => {
+                    TextureImage::Rgb(ref img) => {
                         size = (img.width(), img.height());
                         gl.tex_image2d(
                             bindpoints[i],           // target
@@ -413,8 +1563,19 @@ fn texture_bind_buffer(
                         );
                         need_gen_mipmap = true;
                     }
+                    ref img @ TextureImage::RgbF32(_)
+                    | ref img @ TextureImage::Luma16(_)
+                    | ref img @ TextureImage::Rgba16(_) => {
+                        size = image_size(img).unwrap_or(size);
+
+                        if upload_high_precision(gl, caps, bindpoints[i], img) {
+                            force_nearest_filtering = true;
+                        } else {
+                            need_gen_mipmap = true;
+                        }
+                    }
 
-                    &TextureImage::DXT1(ref dds) => {
+                    TextureImage::DXT1(ref dds) => {
                         size = (dds.images[0].width, dds.images[0].height);
 
                         for (lvl, img) in dds.images.iter().enumerate() {
@@ -431,7 +1592,7 @@ fn texture_bind_buffer(
                         has_midmap = dds.images.len() > 1;
                     }
 
-                    &TextureImage::DXT5(ref dds) => {
+                    TextureImage::DXT5(ref dds) => {
                         size = (dds.images[0].width, dds.images[0].height);
 
                         for (lvl, img) in dds.images.iter().enumerate() {
@@ -447,10 +1608,17 @@ fn texture_bind_buffer(
 
                         has_midmap = dds.images.len() > 1;
                     }
+
+                    TextureImage::ETC2(ref dds) | TextureImage::ASTC(ref dds) => {
+                        size = (dds.images[0].width, dds.images[0].height);
+                        upload_compressed_levels(gl, bindpoints[i], dds);
+                        has_midmap = dds.images.len() > 1;
+                    }
                 }
             }
 
-            if need_gen_mipmap {
+            // Float faces have no mipmaps
+            if need_gen_mipmap && gen_mipmaps && !force_nearest_filtering {
                 gl.generate_mipmap_cube();
                 has_midmap = true;
             }
@@ -460,9 +1628,145 @@ fn texture_bind_buffer(
             (tex, size, has_midmap)
         }
 
-        &TextureKind::RenderTexture { size, ref attach } => {
+        &TextureKind::EquirectCubeMap(ref source) => {
+            let img_res = match source.kind {
+                TextureKind::Image(ref res) => res,
+                _ => return Err(invalid_equirect("The source is not a single image")),
+            };
+
+            let (face_size, faces) = match equirect_to_cube_faces(&*img_res.try_borrow()?) {
+                Some(faces) => faces,
+                None => return Err(invalid_equirect("Compressed images are not supported")),
+            };
+
+            // Keep the HDR range, the mipmaps of RGBA16F need it to be renderable
+            let (tex, has_midmap) = if caps.float_textures {
+                let tex = upload_float_cube_faces(gl, face_size, &faces);
+                if caps.half_float_render_targets {
+                    gl.generate_mipmap_cube();
+                }
+                (tex, caps.half_float_render_targets)
+            } else {
+                rgbm = true;
+                (upload_cube_faces(gl, face_size, &encode_rgbm_faces(&faces)), true)
+            };
+            gl_tex_kind = uni_gl::TextureKind::TextureCubeMap;
+
+            (tex, (face_size, face_size), has_midmap)
+        }
+
+        &TextureKind::FacesCubeMap(ref faces) => {
+            let ((w, h), data) = layers_pixels(faces, invalid_cubemap)?;
+            if w != h {
+                return Err(invalid_cubemap("The faces are not square"));
+            }
+
+            let faces: Vec<Vec<u8>> = data
+                .chunks((w * h * 4) as usize)
+                .map(|f| f.to_vec())
+                .collect();
+
+            let tex = upload_cube_faces(gl, w, &faces);
+            gl_tex_kind = uni_gl::TextureKind::TextureCubeMap;
+
+            (tex, (w, h), true)
+        }
+
+        &TextureKind::CrossCubeMap(ref source) => {
+            let (size, data) = layers_pixels(&[source.clone()], invalid_cubemap)?;
+
+            let (face_size, faces) = match cross_to_cube_faces(size, &data) {
+                Some(faces) => faces,
+                None => return Err(invalid_cubemap("The image is not a 4x3 or 3x4 cross")),
+            };
+
+            let tex = upload_cube_faces(gl, face_size, &faces);
+            gl_tex_kind = uni_gl::TextureKind::TextureCubeMap;
+
+            (tex, (face_size, face_size), true)
+        }
+
+        &TextureKind::Array(ref layers) if caps.texture_3d => {
+            let ((w, h), data) = layers_pixels(layers, invalid_array)?;
+            let size = (w, h, layers.len() as u32);
+
+            let tex = upload_3d(gl, unit, raw_gl::TEXTURE_2D_ARRAY, size, &data, gen_mipmaps);
+            target_3d = Some(raw_gl::TEXTURE_2D_ARRAY);
+
+            (tex, (w, h), gen_mipmaps)
+        }
+
+        &TextureKind::Array(ref layers) => {
+            let (size, data) = stack_layers(gl, layers)?;
+
+            let tex = gl.create_texture();
+            gl.active_texture(unit);
+            gl.bind_texture(&tex);
+            upload_data(gl, size, &data);
+
+            (tex, size, false)
+        }
+
+        &TextureKind::Volume { size, ref data } if caps.texture_3d => {
+            let tex = upload_3d(gl, unit, raw_gl::TEXTURE_3D, size, data, gen_mipmaps);
+            target_3d = Some(raw_gl::TEXTURE_3D);
+
+            (tex, (size.0, size.1), gen_mipmaps)
+        }
+
+        &TextureKind::Volume { size, ref data } => {
+            let (size, strip) = volume_strip(gl, size, data)?;
+
+            let tex = gl.create_texture();
+            gl.active_texture(unit);
+            gl.bind_texture(&tex);
+            upload_data(gl, size, &strip);
+
+            (tex, size, false)
+        }
+
+        &TextureKind::VolumeSlices(ref slices) if caps.texture_3d => {
+            let ((w, h), data) = layers_pixels(slices, invalid_volume)?;
+            let size = (w, h, slices.len() as u32);
+
+            let tex = upload_3d(gl, unit, raw_gl::TEXTURE_3D, size, &data, gen_mipmaps);
+            target_3d = Some(raw_gl::TEXTURE_3D);
+
+            (tex, (w, h), gen_mipmaps)
+        }
+
+        &TextureKind::VolumeSlices(ref slices) => {
+            let ((w, h), data) = layers_pixels(slices, invalid_volume)?;
+            let (size, strip) = volume_strip(gl, (w, h, slices.len() as u32), &data)?;
+
+            let tex = gl.create_texture();
+            gl.active_texture(unit);
+            gl.bind_texture(&tex);
+            upload_data(gl, size, &strip);
+
+            (tex, size, false)
+        }
+
+        &TextureKind::RenderTexture {
+            size,
+            ref attach,
+            ref mipmaps,
+        } => {
             let (fmt, data_type) = match attach {
                 &TextureAttachment::Color0 => (PixelFormat::Rgba, PixelType::UnsignedByte),
+                &TextureAttachment::Color0Hdr => {
+                    // Linear filtering of float textures is an extension
+                    force_nearest_filtering = true;
+                    (PixelFormat::Rgba, PixelType::Float)
+                }
+                &TextureAttachment::Color0HalfFloat => {
+                    force_nearest_filtering = true;
+                    (PixelFormat::Rgba, PixelType::HalfFloat)
+                }
+                &TextureAttachment::Color0R32F => {
+                    force_nearest_filtering = true;
+                    (PixelFormat::Red, PixelType::Float)
+                }
                 &TextureAttachment::Depth => {
                     force_nearest_filtering = true;
                     (PixelFormat::DepthComponent, PixelType::UnsignedShort)
@@ -482,68 +1786,80 @@ fn texture_bind_buffer(
                 &[],                         // data
             );
 
+            // Allocate the levels, they are filled after each render
+            if mipmaps.get() {
+                gl.generate_mipmap();
+            }
+
+            (tex, size, mipmaps.get())
+        }
+
+        &TextureKind::Data {
+            size,
+            ref data,
+            ref dirty,
+        } => {
+            let tex = gl.create_texture();
+            gl.active_texture(unit);
+            gl.bind_texture(&tex);
+            upload_data(gl, size, &data.borrow());
+            dirty.set(false);
+
             (tex, size, false)
         }
     };
 
-    let mut filtering: (i32, i32) = match texfilter {
-        &TextureFiltering::Nearest => (
-            TextureMinFilter::Nearest as i32,
-            TextureMagFilter::Nearest as i32,
+    let state = TextureGLState {
+        tex,
+        size,
+        gl_kind: gl_tex_kind,
+        target_3d,
+        has_mipmap: has_midmap,
+        force_nearest: force_nearest_filtering,
+        rgbm,
+        sampler: Cell::new(*sampler),
+        context: context_generation(),
+        memory: GpuAllocation::new(
+            GpuResourceKind::Texture,
+            estimate_bytes(kind, size, has_midmap),
         ),
-        _ => {
-            if has_midmap {
-                (
-                    TextureMinFilter::LinearMipmapLinear as i32,
-                    TextureMagFilter::Linear as i32,
-                )
-            } else {
-                (
-                    TextureMinFilter::Linear as i32,
-                    TextureMagFilter::Linear as i32,
-                )
-            }
-        }
+        gl: gl.clone(),
     };
 
-    if force_nearest_filtering {
-        filtering = (
-            TextureMinFilter::Nearest as i32,
-            TextureMagFilter::Nearest as i32,
-        )
-    }
+    apply_sampler(gl, caps, &state, sampler);
 
-    let to_gl_wrap = |w: TextureWrap| match w {
-        TextureWrap::Repeat => uni_gl::TextureWrap::Repeat as i32,
-        TextureWrap::ClampToEdge => uni_gl::TextureWrap::ClampToEdge as i32,
-        TextureWrap::MirroredRepeat => uni_gl::TextureWrap::MirroredRepeat as i32,
-    };
+    //unbind_texture(gl, kind);
 
-    gl.tex_parameteri(gl_tex_kind, TextureParameter::TextureMinFilter, filtering.0);
-    gl.tex_parameteri(gl_tex_kind, TextureParameter::TextureMagFilter, filtering.1);
-
-    gl.tex_parameteri(
-        gl_tex_kind,
-        TextureParameter::TextureWrapS,
-        to_gl_wrap(wrap_u),
-    );
-    gl.tex_parameteri(
-        gl_tex_kind,
-        TextureParameter::TextureWrapT,
-        to_gl_wrap(wrap_v),
-    );
-
-    if let &TextureKind::CubeMap(..) = kind {
-        if let Some(wrap_w) = wrap_w {
-            gl.tex_parameteri(
-                gl_tex_kind,
-                TextureParameter::TextureWrapR,
-                to_gl_wrap(wrap_w),
-            );
-        }
+    Ok(state)
+}
+
+/// Set the parameters of `sampler` on `state.tex`, which is bound
+fn apply_sampler(
+    gl: &WebGLRenderingContext,
+    caps: &Capabilities,
+    state: &TextureGLState,
+    sampler: &TextureSampler,
+) {
+    let mut applied = *sampler;
+
+    if state.force_nearest {
+        applied.min_filter = TextureFiltering::Nearest;
+        applied.mag_filter = TextureFiltering::Nearest;
+    }
+    if !state.has_mipmap || state.force_nearest {
+        applied.mipmap_filter = MipmapFiltering::None;
     }
 
-    //unbind_texture(gl, kind);
+    gl.apply_sampler(state.target(), &applied);
 
-    Ok(TextureGLState { tex, size })
+    // The parameter is an error without EXT_texture_filter_anisotropic
+    if caps.max_anisotropy > 1.0 {
+        let samples = if state.force_nearest {
+            1.0
+        } else {
+            sampler.anisotropy.min(caps.max_anisotropy).max(1.0)
+        };
+
+        gl.set_anisotropy(state.target(), samples);
+    }
 }
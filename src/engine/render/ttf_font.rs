@@ -0,0 +1,254 @@
+use engine::asset::{Asset, AssetSystem, FileFuture, LoadableAsset, Resource};
+use engine::render::Texture;
+use math::*;
+use rusttype::{point, Font, Scale};
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Width and height of the glyph atlas of a `TtfFont`
+pub const TTF_ATLAS_SIZE: u32 = 512;
+/// Empty pixels between the glyphs, so the filtering does not bleed
+const GLYPH_PADDING: u32 = 1;
+
+/// A glyph of a `TtfFont` rasterized at a pixel size, the metrics are in pixels
+#[derive(Copy, Clone, Debug)]
+pub struct TtfGlyph {
+    /// The (top left, bottom right) uv in the atlas
+    pub uv: (Vector2f, Vector2f),
+    /// From the pen position on the baseline to the top left of the quad
+    pub offset: Vector2f,
+    pub size: Vector2f,
+    pub advance: f32,
+}
+
+/// The vertical metrics of a `TtfFont` at a pixel size
+#[derive(Copy, Clone, Debug)]
+pub struct TtfLineMetrics {
+    /// Above the baseline
+    pub ascent: f32,
+    /// Below the baseline, negative
+    pub descent: f32,
+    pub line_gap: f32,
+}
+
+impl TtfLineMetrics {
+    pub fn line_height(&self) -> f32 {
+        self.ascent - self.descent + self.line_gap
+    }
+}
+
+pub struct TtfFontData {
+    pub font: Font<'static>,
+}
+
+impl fmt::Debug for TtfFontData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TtfFontData {{ glyphs: {} }}", self.font.glyph_count())
+    }
+}
+
+/// The glyphs rasterized so far, packed in rows
+struct GlyphAtlas {
+    texture: Rc<Texture>,
+    pixels: Vec<u8>,
+    glyphs: HashMap<(char, u32), TtfGlyph>,
+    cursor: (u32, u32),
+    row_height: u32,
+    dirty: bool,
+    generation: u32,
+}
+
+impl GlyphAtlas {
+    fn new() -> GlyphAtlas {
+        GlyphAtlas {
+            texture: Texture::new_data_texture(TTF_ATLAS_SIZE, TTF_ATLAS_SIZE),
+            pixels: GlyphAtlas::empty_pixels(),
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+            dirty: false,
+            generation: 0,
+        }
+    }
+
+    /// White, the coverage is in alpha
+    fn empty_pixels() -> Vec<u8> {
+        let texels = (TTF_ATLAS_SIZE * TTF_ATLAS_SIZE) as usize;
+        [0xff, 0xff, 0xff, 0].iter().cloned().cycle().take(texels * 4).collect()
+    }
+
+    fn clear(&mut self) {
+        self.pixels = GlyphAtlas::empty_pixels();
+        self.glyphs.clear();
+        self.cursor = (0, 0);
+        self.row_height = 0;
+        self.dirty = true;
+        self.generation += 1;
+    }
+
+    /// The top left of a free `w` x `h` rect, None when the atlas is full
+    fn allocate(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        let (w, h) = (w + GLYPH_PADDING, h + GLYPH_PADDING);
+
+        if self.cursor.0 + w > TTF_ATLAS_SIZE {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+
+        if self.cursor.0 + w > TTF_ATLAS_SIZE || self.cursor.1 + h > TTF_ATLAS_SIZE {
+            return None;
+        }
+
+        let pos = self.cursor;
+        self.cursor.0 += w;
+        self.row_height = self.row_height.max(h);
+
+        Some(pos)
+    }
+}
+
+/// A TTF or OTF font loaded by `AssetSystem::new_ttf_font`, its glyphs are
+/// rasterized on demand at the requested pixel sizes into a shared atlas.
+/// When the atlas is full it is cleared and `generation` changes, the glyphs
+/// returned before are then invalid.
+pub struct TtfFont {
+    data: Resource<TtfFontData>,
+    atlas: RefCell<GlyphAtlas>,
+}
+
+impl Asset for TtfFont {
+    type Resource = Resource<TtfFontData>;
+
+    fn new_from_resource(r: Self::Resource) -> Rc<Self> {
+        Rc::new(TtfFont {
+            data: r,
+            atlas: RefCell::new(GlyphAtlas::new()),
+        })
+    }
+}
+
+impl LoadableAsset for TtfFont {
+    fn load<T>(asys: &T, mut files: Vec<FileFuture>) -> Self::Resource
+    where
+        T: AssetSystem + Clone + 'static,
+    {
+        Self::load_resource::<TtfFontData, T>(asys.clone(), files.remove(0))
+    }
+
+    fn gather<T: AssetSystem>(asys: &T, fname: &str) -> Vec<FileFuture> {
+        vec![asys.new_file(fname)]
+    }
+}
+
+impl TtfFont {
+    /// None while loading
+    pub fn data(&self) -> Option<Ref<TtfFontData>> {
+        self.data.try_borrow().ok()
+    }
+
+    /// None while loading
+    pub fn line_metrics(&self, pixels: f32) -> Option<TtfLineMetrics> {
+        let data = self.data()?;
+        let v_metrics = data.font.v_metrics(Scale::uniform(pixels));
+
+        Some(TtfLineMetrics {
+            ascent: v_metrics.ascent,
+            descent: v_metrics.descent,
+            line_gap: v_metrics.line_gap,
+        })
+    }
+
+    /// Added to the advance between two characters, 0 while loading
+    pub fn kerning(&self, a: char, b: char, pixels: f32) -> f32 {
+        self.data()
+            .map_or(0.0, |data| data.font.pair_kerning(Scale::uniform(pixels), a, b))
+    }
+
+    /// The glyph of `c` at `pixels` rounded to a whole size, rasterized into the
+    /// atlas the first time. None while loading.
+    pub fn glyph(&self, c: char, pixels: f32) -> Option<TtfGlyph> {
+        let size = pixels.round().max(1.0) as u32;
+
+        if let Some(glyph) = self.atlas.borrow().glyphs.get(&(c, size)) {
+            return Some(*glyph);
+        }
+
+        let data = self.data()?;
+        let glyph = data.font
+            .glyph(c)
+            .scaled(Scale::uniform(size as f32))
+            .positioned(point(0.0, 0.0));
+        let advance = glyph.unpositioned().h_metrics().advance_width;
+
+        let empty = TtfGlyph {
+            uv: (Vector2f::zero(), Vector2f::zero()),
+            offset: Vector2f::zero(),
+            size: Vector2f::zero(),
+            advance,
+        };
+
+        let mut atlas = self.atlas.borrow_mut();
+
+        let rasterized = match glyph.pixel_bounding_box() {
+            // Spaces only advance
+            None => empty,
+            Some(bb) => {
+                let (w, h) = (bb.width() as u32, bb.height() as u32);
+
+                let pos = atlas.allocate(w, h).or_else(|| {
+                    atlas.clear();
+                    atlas.allocate(w, h)
+                });
+
+                match pos {
+                    // Larger than the atlas
+                    None => empty,
+                    Some((x, y)) => {
+                        {
+                            let pixels = &mut atlas.pixels;
+                            glyph.draw(|gx, gy, v| {
+                                let i = ((y + gy) * TTF_ATLAS_SIZE + x + gx) * 4 + 3;
+                                pixels[i as usize] = (v * 255.0).round() as u8;
+                            });
+                        }
+                        atlas.dirty = true;
+
+                        let uv = |px: u32, py: u32| {
+                            Vector2f::new(px as f32, py as f32) / TTF_ATLAS_SIZE as f32
+                        };
+
+                        TtfGlyph {
+                            uv: (uv(x, y), uv(x + w, y + h)),
+                            // The bounding box is in pixels down from the baseline
+                            offset: Vector2f::new(bb.min.x as f32, -bb.min.y as f32),
+                            size: Vector2f::new(w as f32, h as f32),
+                            advance,
+                        }
+                    }
+                }
+            }
+        };
+
+        atlas.glyphs.insert((c, size), rasterized);
+        Some(rasterized)
+    }
+
+    /// The atlas, with the glyphs rasterized since the last call
+    pub fn texture(&self) -> Rc<Texture> {
+        let mut atlas = self.atlas.borrow_mut();
+
+        if atlas.dirty {
+            atlas.texture.set_data(atlas.pixels.clone());
+            atlas.dirty = false;
+        }
+
+        atlas.texture.clone()
+    }
+
+    /// Changed each time the atlas is cleared
+    pub fn generation(&self) -> u32 {
+        self.atlas.borrow().generation
+    }
+}
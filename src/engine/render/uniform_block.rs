@@ -0,0 +1,178 @@
+use engine::render::context_loss::context_generation;
+use engine::render::raw_gl::{self, UniformBuffer};
+use engine::render::uniforms::{UniformAdapter, UniformTarget};
+use fnv::FnvHashMap;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use uni_gl::WebGLRenderingContext;
+
+/// The uniform buffer binding of the "Camera" block of unrust/camera_block.glsl
+pub const CAMERA_BLOCK_BINDING: u32 = 0;
+/// The uniform buffer binding of the "Lights" block of unrust/lights_block.glsl
+pub const LIGHTS_BLOCK_BINDING: u32 = 1;
+
+/// std140 byte offsets of the members of the light structs of phong_light.glsl
+const DIRECTIONAL_LIGHT: &'static [(&'static str, usize)] = &[
+    ("direction", 0),
+    ("ambient", 16),
+    ("diffuse", 32),
+    ("specular", 48),
+    ("has_cookie", 60),
+    ("cookie_matrix", 64),
+];
+const DIRECTIONAL_LIGHT_SIZE: usize = 128;
+
+const POINT_LIGHT: &'static [(&'static str, usize)] = &[
+    ("position", 0),
+    ("constant", 12),
+    ("linear", 16),
+    ("quadratic", 20),
+    ("ambient", 32),
+    ("diffuse", 48),
+    ("specular", 64),
+    ("rate", 76),
+];
+const POINT_LIGHT_SIZE: usize = 80;
+
+const SPOT_LIGHT: &'static [(&'static str, usize)] = &[
+    ("position", 0),
+    ("direction", 16),
+    ("cut_off", 28),
+    ("outer_cut_off", 32),
+    ("range", 36),
+    ("ambient", 48),
+    ("diffuse", 64),
+    ("specular", 80),
+    ("rate", 92),
+    ("has_cookie", 96),
+    ("cookie_matrix", 112),
+];
+const SPOT_LIGHT_SIZE: usize = 176;
+
+/// The members of a std140 uniform block, set by name like the uniforms of a
+/// program and uploaded to its uniform buffer when they changed. The programs
+/// declaring the block read it from the buffer, see `ShaderProgram::bind_uniform_block`.
+#[derive(Debug)]
+pub struct UniformBlock {
+    name: &'static str,
+    binding: u32,
+    /// Offset of each member, in 32 bits words
+    offsets: FnvHashMap<Cow<'static, str>, usize>,
+    data: RefCell<Vec<f32>>,
+    changed: Cell<bool>,
+    /// The buffer and the `context_generation` it was created in
+    buffer: RefCell<Option<(UniformBuffer, u32)>>,
+}
+
+impl UniformBlock {
+    /// An empty block of `size` bytes
+    pub fn new(name: &'static str, binding: u32, size: usize) -> UniformBlock {
+        UniformBlock {
+            name,
+            binding,
+            offsets: FnvHashMap::default(),
+            data: RefCell::new(vec![0.0; (size + 15) / 16 * 4]),
+            changed: Cell::new(true),
+            buffer: RefCell::new(None),
+        }
+    }
+
+    /// The "Camera" block of unrust/camera_block.glsl
+    pub fn camera() -> UniformBlock {
+        let mut block = UniformBlock::new("Camera", CAMERA_BLOCK_BINDING, 148);
+        block.add("uVMatrix", 0);
+        block.add("uPMatrix", 64);
+        block.add("uViewPos", 128);
+        block.add("uLinearColor", 140);
+        block.add("uEncodeSrgb", 144);
+        block
+    }
+
+    /// The "Lights" block of unrust/lights_block.glsl, with the
+    /// UNI_POINT_LIGHTS and UNI_SPOT_LIGHTS slots of the shaders
+    pub fn lights(point_lights: usize, spot_lights: usize) -> UniformBlock {
+        let spot_base = DIRECTIONAL_LIGHT_SIZE + point_lights * POINT_LIGHT_SIZE;
+        let size = spot_base + spot_lights * SPOT_LIGHT_SIZE;
+
+        let mut block = UniformBlock::new("Lights", LIGHTS_BLOCK_BINDING, size);
+        block.add_struct("uDirectionalLight", 0, DIRECTIONAL_LIGHT);
+        for i in 0..point_lights {
+            let base = DIRECTIONAL_LIGHT_SIZE + i * POINT_LIGHT_SIZE;
+            block.add_struct(&format!("uPointLights[{}]", i), base, POINT_LIGHT);
+        }
+        for i in 0..spot_lights {
+            let base = spot_base + i * SPOT_LIGHT_SIZE;
+            block.add_struct(&format!("uSpotLights[{}]", i), base, SPOT_LIGHT);
+        }
+        block
+    }
+
+    /// The member `name` at the byte `offset`, a multiple of 4
+    pub fn add<S: Into<Cow<'static, str>>>(&mut self, name: S, offset: usize) {
+        self.offsets.insert(name.into(), offset / 4);
+    }
+
+    /// The members of the struct `prefix` at the byte `base`, by their offset
+    /// in the struct
+    pub fn add_struct(&mut self, prefix: &str, base: usize, layout: &[(&str, usize)]) {
+        for &(member, offset) in layout.iter() {
+            self.add(format!("{}.{}", prefix, member), base + offset);
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn binding(&self) -> u32 {
+        self.binding
+    }
+
+    /// Upload the members to the buffer when they changed since the last
+    /// upload, and bind it to the blocks of `binding`
+    pub fn upload(&self, gl: &WebGLRenderingContext) {
+        let mut buffer = self.buffer.borrow_mut();
+
+        let lost = match *buffer {
+            Some((_, context)) => context != context_generation(),
+            None => true,
+        };
+        if lost {
+            *buffer = Some((raw_gl::create_uniform_buffer(gl), context_generation()));
+        } else if !self.changed.get() {
+            return;
+        }
+
+        self.changed.set(false);
+        let &(ref handle, _) = buffer.as_ref().unwrap();
+        raw_gl::upload_uniform_buffer(gl, handle, self.binding, &self.data.borrow());
+    }
+}
+
+impl UniformTarget for UniformBlock {
+    /// The names which are not members of the block are ignored
+    fn set<T, S>(&self, s: S, data: T)
+    where
+        T: Into<UniformAdapter>,
+        S: Into<Cow<'static, str>>,
+    {
+        let offset = match self.offsets.get(&s.into()) {
+            Some(offset) => *offset,
+            None => return,
+        };
+
+        let words = data.into().std140_words();
+        let mut block = self.data.borrow_mut();
+        let member = &mut block[offset..offset + words.len()];
+
+        // By bits, the ints and bools are not floats
+        if member
+            .iter()
+            .zip(words.iter())
+            .any(|(a, b)| a.to_bits() != b.to_bits())
+        {
+            member.copy_from_slice(&words);
+            self.changed.set(true);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use math::{Matrix4, Vector2, Vector3, Vector4};
 use std::mem::size_of;
 
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc;
 use fnv::FnvHashMap;
 use std::borrow::Cow;
@@ -75,7 +75,34 @@ impl From<(rc::Weak<Texture>, u32)> for UniformAdapter {
     }
 }
 
+/// Where the named uniforms of a struct are written, a program or the
+/// members of a `UniformBlock`
+pub trait UniformTarget {
+    fn set<T, S>(&self, s: S, data: T)
+    where
+        T: Into<UniformAdapter>,
+        S: Into<Cow<'static, str>>;
+}
+
 impl UniformAdapter {
+    /// The std140 words of the value, bools and ints are 32 bits
+    pub fn std140_words(&self) -> Vec<f32> {
+        match *self {
+            UniformAdapter::Matrix4(ref m) => {
+                let m: &[f32; 16] = m.as_ref();
+                m.to_vec()
+            }
+            UniformAdapter::F32(f) => vec![f],
+            UniformAdapter::Bool(b) => vec![f32::from_bits(b as u32)],
+            UniformAdapter::I32(i) => vec![f32::from_bits(i as u32)],
+            UniformAdapter::Vector2(v) => vec![v.x, v.y],
+            UniformAdapter::Vector3(v) => vec![v.x, v.y, v.z],
+            UniformAdapter::Vector4(v) => vec![v.x, v.y, v.z, v.w],
+            // Samplers are not allowed in uniform blocks
+            UniformAdapter::Texture(_) => Vec::new(),
+        }
+    }
+
     fn set(&self, gl: &WebGLRenderingContext, loc: &WebGLUniformLocation) {
         match *self {
             UniformAdapter::Matrix4(m) => {
@@ -112,6 +139,9 @@ pub struct UniformCache {
     pending_entries: RefCell<FnvHashMap<Cow<'static, str>, UniformAdapter>>,
 
     uniform_map: RefCell<FnvHashMap<Cow<'static, str>, Option<Rc<WebGLUniformLocation>>>>,
+
+    /// Number of `set` calls skipped since the value was already uploaded
+    saved_calls: Cell<u32>,
 }
 
 impl UniformCache {
@@ -130,6 +160,7 @@ impl UniformCache {
 
         if let Entry::Occupied(o) = entry {
             if *o.get() == adapter {
+                self.saved_calls.set(self.saved_calls.get() + 1);
                 return;
             }
             let (key, _) = o.remove_entry();
@@ -139,6 +170,23 @@ impl UniformCache {
         }
     }
 
+    /// Return and reset the number of skipped uploads
+    pub fn take_saved_calls(&self) -> u32 {
+        self.saved_calls.replace(0)
+    }
+
+    /// Forget the locations and send every value again on the next commit,
+    /// e.g. when the program is recompiled
+    pub fn invalidate(&self) {
+        let mut pending = self.pending_entries.borrow_mut();
+
+        for (key, adapter) in self.uniform_entries.borrow_mut().drain() {
+            pending.entry(key).or_insert(adapter);
+        }
+
+        self.uniform_map.borrow_mut().clear();
+    }
+
     pub fn commit(&self, gl: &WebGLRenderingContext, prog: &WebGLProgram) {
         {
             let mut pending = self.pending_entries.borrow_mut();
@@ -155,6 +203,16 @@ impl UniformCache {
         }
     }
 
+    /// Whether the linked program declares and uses the uniform
+    pub fn has_uniform(
+        &self,
+        gl: &WebGLRenderingContext,
+        prog: &WebGLProgram,
+        s: &Cow<'static, str>,
+    ) -> bool {
+        self.get_uniform(gl, prog, s).is_some()
+    }
+
     fn get_uniform(
         &self,
         gl: &WebGLRenderingContext,
@@ -0,0 +1,56 @@
+use engine::asset::{Asset, AssetSystem, FileFuture, LoadableAsset, Resource};
+use std::cell::Ref;
+use std::rc::Rc;
+
+/// The decoded samples of an `AudioClip`
+#[derive(Debug)]
+pub struct AudioClipData {
+    /// number of channels. 1:mono, 2: stereo
+    pub channels: usize,
+    /// audio samples per second
+    pub sample_rate: usize,
+    /// interleaved samples between -1.0 and 1.0
+    pub samples: Vec<f32>,
+}
+
+impl AudioClipData {
+    /// The length in seconds
+    pub fn duration(&self) -> f32 {
+        let frames = self.samples.len() / self.channels.max(1);
+        frames as f32 / self.sample_rate.max(1) as f32
+    }
+}
+
+/// A WAV or OGG Vorbis file loaded by `AssetSystem::new_audio_clip`,
+/// decoded once the file is read
+pub struct AudioClip {
+    data: Resource<AudioClipData>,
+}
+
+impl Asset for AudioClip {
+    type Resource = Resource<AudioClipData>;
+
+    fn new_from_resource(r: Self::Resource) -> Rc<Self> {
+        Rc::new(AudioClip { data: r })
+    }
+}
+
+impl LoadableAsset for AudioClip {
+    fn load<T>(asys: &T, mut files: Vec<FileFuture>) -> Self::Resource
+    where
+        T: AssetSystem + Clone + 'static,
+    {
+        Self::load_resource::<AudioClipData, T>(asys.clone(), files.remove(0))
+    }
+
+    fn gather<T: AssetSystem>(asys: &T, fname: &str) -> Vec<FileFuture> {
+        vec![asys.new_file(fname)]
+    }
+}
+
+impl AudioClip {
+    /// None while loading
+    pub fn data(&self) -> Option<Ref<AudioClipData>> {
+        self.data.try_borrow().ok()
+    }
+}
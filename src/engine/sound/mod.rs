@@ -1,4 +1,5 @@
 mod channel;
+mod clip;
 mod generator;
 
 use std::cell::RefCell;
@@ -12,6 +13,8 @@ use uni_snd::SoundDriver;
 
 use self::generator::Generator;
 
+pub use self::clip::{AudioClip, AudioClipData};
+
 const CHANNEL_COUNT: usize = 4;
 
 #[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
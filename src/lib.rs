@@ -10,13 +10,18 @@ extern crate fnv;
 extern crate futures;
 extern crate hound;
 extern crate image;
+extern crate lewton;
 extern crate obj;
+extern crate rusttype;
+extern crate serde;
+extern crate serde_json;
 extern crate typed_arena;
 extern crate uni_app;
 extern crate uni_glsl;
 extern crate uni_pad;
 extern crate uni_snd;
 extern crate uni_gl;
+extern crate xml;
 
 #[macro_use]
 extern crate unrust_derive;
@@ -27,6 +32,15 @@ extern crate bitflags;
 #[cfg(feature = "flame_it")]
 extern crate flame;
 
+// for decoding the images off the main thread on the web
+#[cfg(target_arch = "wasm32")]
+#[macro_use]
+extern crate stdweb;
+
+// for the gl calls uni-gl has no entry point for, see render::raw_gl
+#[cfg(not(target_arch = "wasm32"))]
+extern crate gl;
+
 // This is here so that our procedural macros
 // can work within the crate.
 pub(crate) mod unrust {
@@ -1,11 +1,15 @@
-use engine::{Engine, File, FileFuture, FileIoError, FileSystem};
+use engine::worker;
+use engine::{Engine, File, FileFuture, FileIoError, FileSystem, MemoryFile};
 use uni_app::fs;
 
 use futures::{Async, Future};
 use futures::future;
 use std::collections::BTreeSet;
 use std::cell::RefCell;
+use std::io;
 use std::rc::Rc;
+use std::time::SystemTime;
+use std;
 
 // unrust engine support different file system.
 #[derive(Default)]
@@ -46,6 +50,10 @@ impl FileSystem for AppFileSystem {
 
         abs_filename = abs_filename.replace("\\", "/");
 
+        if cfg!(not(target_arch = "wasm32")) {
+            return self.read_on_worker(filename, abs_filename);
+        }
+
         let f = fs::FileSystem::open(&abs_filename)
             .map_err(|_| FileIoError::NoSuchFile(filename.to_string()));
 
@@ -69,6 +77,42 @@ impl FileSystem for AppFileSystem {
             .map(|s| s.clone())
             .collect()
     }
+
+    fn modified(&self, filename: &str) -> Option<SystemTime> {
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+
+        let abs_filename = ("static/".to_string() + filename).replace("\\", "/");
+
+        std::fs::metadata(&abs_filename)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+}
+
+impl AppFileSystem {
+    /// Read the whole file on a worker thread, not to block the main loop
+    fn read_on_worker(&self, filename: &str, abs_filename: String) -> FileFuture {
+        let name = filename.to_string();
+        let loading_files = self.loading_files.clone();
+        loading_files.borrow_mut().insert(name.clone());
+
+        let data = worker::spawn(move || std::fs::read(&abs_filename));
+
+        Box::new(data.then(move |r| {
+            loading_files.borrow_mut().remove(&name);
+
+            match r {
+                Ok(Ok(data)) => Ok(Box::new(MemoryFile { name, data }) as Box<File>),
+                Ok(Err(ref e)) if e.kind() == io::ErrorKind::NotFound => {
+                    Err(FileIoError::NoSuchFile(name))
+                }
+                Ok(Err(e)) => Err(FileIoError::IoError(e)),
+                Err(e) => Err(FileIoError::Unknown(e)),
+            }
+        }))
+    }
 }
 
 impl File for AppFile {
@@ -262,6 +262,8 @@ impl<'a> World {
 
     #[cfg_attr(feature = "flame_it", flame)]
     fn step(&mut self) {
+        self.engine.poll_context_loss();
+
         for evt in self.events.borrow().iter() {
             match evt {
                 &AppEvent::Resized(size) => self.engine.resize(size),
@@ -280,6 +282,13 @@ impl<'a> World {
 
         self.fps.step();
 
+        let dt = self.delta_time() as f32;
+        self.engine.update_behaviours(dt);
+        self.engine.update_physics_2d(dt);
+        self.engine.update_particles(dt);
+        self.engine.update_sprite_animations(dt);
+        self.engine.debug_draw().step(dt);
+
         if self.shown_stats {
             let loading_files = self.engine().asset_system().loading_files();
 
@@ -297,7 +306,7 @@ impl<'a> World {
             imgui::label(
                 Native(0.0, 0.0) + Pixel(8.0, 8.0),
                 &format!(
-                    "fps: {} dt: {:04.2}[{:04.2}|{:04.2}-{:04.2}]ms\nnobj: {} actors:{} gobjs:{} sf:{} oc:[{}:{}] tc:[{}:{}]\n{}",
+                    "fps: {} dt: {:04.2}[{:04.2}|{:04.2}-{:04.2}]ms\nnobj: {} actors:{} gobjs:{} sf:{} oc:[{}:{}] tc:[{}:{}] cc:[{}:{}] us:{}\n{}",
                     self.fps.fps,
                     self.fps.delta_time() * 1000.0,
                     self.fps.delta_time_stats().dt_avg * 1000.0,
@@ -309,6 +318,8 @@ impl<'a> World {
                     self.engine().stats.surfaces_count, 
                     self.engine().stats.opaque_count,self.engine().stats.total_opaque_count,
                     self.engine().stats.transparent_count, self.engine().stats.total_transparent_count,
+                    self.engine().stats.culled_count, self.engine().stats.occluded_count,
+                    self.engine().stats.uniform_calls_saved,
                     loading_stats
                 ),
             );
@@ -7,7 +7,7 @@ out vec4 FragColor;
 
 varying vec3 vColor;
 varying vec2 vTextureCoord;
-uniform sampler2D uDiffuse;
+uniform sampler2D uScreenTexture;
 
 const float crtBend			= 4.8;
 const float crtOverscan		= 0.1;
@@ -34,6 +34,6 @@ void main(void) {
     	gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
     } else {
         float coef=0.8 + abs(sin(600.0*vTextureCoord.t)) * 0.2;
-        gl_FragColor = texture2D(uDiffuse, crtCoords) * vec4(0.8,1.0*coef,0.7,1.0)  ;
+        gl_FragColor = texture2D(uScreenTexture, crtCoords) * vec4(0.8,1.0*coef,0.7,1.0)  ;
     }
 }
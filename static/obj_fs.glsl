@@ -5,7 +5,9 @@
 #define texture2D texture
 out vec4 FragColor;
 
+#ifndef UNI_POINT_LIGHTS
 #define UNI_POINT_LIGHTS 4
+#endif
 
 #include "unrust/phong_light.glsl"
 #include "unrust/shadow_utils.glsl"
@@ -31,7 +33,7 @@ struct MaterialColor {
 };
 
 
-uniform vec3 uViewPos;
+#include "unrust/camera_block.glsl"
 uniform Material uMaterial;
 
 varying vec3 vFragPos;
@@ -7,7 +7,9 @@
 out vec4 FragColor;
 //#endif
 
+#ifndef UNI_POINT_LIGHTS
 #define UNI_POINT_LIGHTS 4
+#endif
 #include "unrust/phong_light.glsl"
 #include "unrust/shadow_utils.glsl"
 
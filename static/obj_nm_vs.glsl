@@ -5,7 +5,9 @@
 #define varying out
 #define texture2D texture
 
+#ifndef UNI_POINT_LIGHTS
 #define UNI_POINT_LIGHTS 4
+#endif
 
 #include "unrust/default_uniforms.glsl"
 
@@ -14,8 +16,6 @@ attribute vec3 aVertexNormal;
 attribute vec3 aVertexTangent;
 attribute vec2 aTextureCoord;
 
-uniform vec3 uViewPos;
-
 varying vec3 vFragPos;
 
 varying vec2 vTexCoords;
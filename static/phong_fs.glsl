@@ -5,8 +5,20 @@
 out vec4 FragColor;
 #endif
 
+#ifndef UNI_POINT_LIGHTS
 #define UNI_POINT_LIGHTS 4
+#endif
+#ifndef UNI_SPOT_LIGHTS
+#define UNI_SPOT_LIGHTS 4
+#endif
+#ifndef UNI_AREA_LIGHTS
+#define UNI_AREA_LIGHTS 4
+#endif
 #include "unrust/phong_light.glsl"
+#include "unrust/area_light.glsl"
+#include "unrust/light_cookie.glsl"
+#include "unrust/ssao.glsl"
+#include "unrust/normal_map.glsl"
 
 struct Material {
     sampler2D diffuse;
@@ -15,22 +27,37 @@ struct Material {
 
 uniform vec3 uViewPos;
 uniform Material uMaterial;
+uniform float uAlphaCutoff;
 
 varying vec3 vFragPos;
 varying vec2 vTexCoords;       
 varying vec3 vNormal;                       
+varying vec3 vTangent;
+varying vec3 vBitangent;
+
+// vTexCoords after the parallax offset
+vec2 texCoords;
 
 // Lights
 uniform DirectionalLight uDirectionalLight;
 uniform PointLight uPointLights[UNI_POINT_LIGHTS];
+uniform SpotLight uSpotLights[UNI_SPOT_LIGHTS];
 
 vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir);
 vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
 
 void main(void) {
-    vec3 norm = normalize(vNormal);
+    vec3 normal = normalize(vNormal);
     vec3 viewDir = normalize(uViewPos - vFragPos);
 
+    texCoords = ParallaxOcclusion(vTexCoords, viewDir, normal, vTangent, vBitangent);
+
+    // Alpha cutout
+    if (texture2D(uMaterial.diffuse, texCoords).a < uAlphaCutoff)
+        discard;
+
+    vec3 norm = ApplyNormalMap(normal, vTangent, vBitangent, texCoords);
+
     // Directional Light
     vec3 result = CalcDirectionalLight(uDirectionalLight, norm, viewDir);
     
@@ -38,24 +65,36 @@ void main(void) {
     for(int i = 0; i < UNI_POINT_LIGHTS; i++)
         result += CalcPointLight(uPointLights[i], norm, vFragPos, viewDir);
 
+    vec3 diffuseColor = texture2D(uMaterial.diffuse, texCoords).rgb;
+
+    // Spot Lights
+    for(int i = 0; i < UNI_SPOT_LIGHTS; i++)
+        result += CalcSpotLight(uSpotLights[i], norm, vFragPos, viewDir, diffuseColor, diffuseColor * AmbientOcclusion(), uMaterial.shininess, SpotLightCookie(i, uSpotLights[i], vFragPos));
+
+    // Area Lights
+    for(int i = 0; i < UNI_AREA_LIGHTS; i++)
+        result += CalcAreaLight(uAreaLights[i], norm, vFragPos, viewDir, diffuseColor, uMaterial.shininess);
+
     gl_FragColor = vec4(result, 1.0);           
 }
 
 vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir)
 {
     // diffuse
-    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, vTexCoords));
+    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, texCoords)) * AmbientOcclusion();
 
     vec3 lightDir = normalize(-light.direction);  
     float diff = max(dot(normal, lightDir), 0.0);
-    vec3 diffuse = light.diffuse * diff * texture2D(uMaterial.diffuse, vTexCoords).rgb;  
+    vec3 diffuse = light.diffuse * diff * texture2D(uMaterial.diffuse, texCoords).rgb;  
 
     // specular    
     vec3 reflectDir = reflect(-lightDir, normal);  
     float spec = pow(max(dot(viewDir, reflectDir), 0.0), uMaterial.shininess);
     vec3 specular = light.specular * spec; 
 
-    return ambient + diffuse + specular;
+    vec3 cookie = DirectionalLightCookie(light, vFragPos);
+
+    return ambient + (diffuse + specular) * cookie;
 }
 
 vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
@@ -74,8 +113,8 @@ vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
     float attenuation = 1.0 / max(d, 0.001);
     
     // combine results
-    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, vTexCoords));
-    vec3 diffuse = light.diffuse * diff * vec3(texture2D(uMaterial.diffuse, vTexCoords));
+    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, texCoords)) * AmbientOcclusion();
+    vec3 diffuse = light.diffuse * diff * vec3(texture2D(uMaterial.diffuse, texCoords));
     vec3 specular = light.specular * spec;
     
     ambient *= attenuation;
@@ -0,0 +1,116 @@
+// Rectangular area lights, using Linearly Transformed Cosines
+// Reference: Real-Time Polygonal-Light Shading with Linearly Transformed Cosines
+// (Heitz, Dupuy, Hill and Neubelt 2016)
+
+struct AreaLight {
+    vec3 points[4];
+
+    vec3 diffuse;
+    vec3 specular;
+
+    bool two_sided;
+    float rate;
+};
+
+uniform AreaLight uAreaLights[UNI_AREA_LIGHTS];
+
+// Optional LTC fit tables, see Engine::area_light_lut
+uniform bool uLTCEnabled;
+uniform sampler2D uLTC1;
+uniform sampler2D uLTC2;
+
+#define LTC_LUT_SIZE 64.0
+#define LTC_PI 3.14159265
+
+vec3 LTCIntegrateEdge(vec3 v1, vec3 v2)
+{
+    float x = dot(v1, v2);
+    float y = abs(x);
+
+    float a = 0.8543985 + (0.4965155 + 0.0145206 * y) * y;
+    float b = 3.4175940 + (4.1616724 + y) * y;
+    float v = a / b;
+
+    float theta_sintheta = (x > 0.0) ? v : 0.5 * inversesqrt(max(1.0 - x * x, 1e-7)) - v;
+
+    return cross(v1, v2) * theta_sintheta;
+}
+
+// Integrate the light polygon over the clamped cosine distribution
+// transformed by Minv, which is expressed in a frame around N.
+float LTCEvaluate(vec3 N, vec3 V, vec3 P, mat3 Minv, AreaLight light)
+{
+    vec3 T1 = V - N * dot(V, N);
+    if (dot(T1, T1) < 1e-6) {
+        T1 = abs(N.x) < 0.9 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 1.0, 0.0);
+        T1 = T1 - N * dot(T1, N);
+    }
+    T1 = normalize(T1);
+    vec3 T2 = cross(N, T1);
+
+    // world to (T1, T2, N), there is no transpose() in GLSL ES 1.0
+    mat3 frame = mat3(T1.x, T2.x, N.x, T1.y, T2.y, N.y, T1.z, T2.z, N.z);
+    Minv = Minv * frame;
+
+    vec3 L0 = normalize(Minv * (light.points[0] - P));
+    vec3 L1 = normalize(Minv * (light.points[1] - P));
+    vec3 L2 = normalize(Minv * (light.points[2] - P));
+    vec3 L3 = normalize(Minv * (light.points[3] - P));
+
+    vec3 vsum = LTCIntegrateEdge(L0, L1);
+    vsum += LTCIntegrateEdge(L1, L2);
+    vsum += LTCIntegrateEdge(L2, L3);
+    vsum += LTCIntegrateEdge(L3, L0);
+
+    // Clamping the integral approximates clipping against the horizon
+    float sum = light.two_sided ? abs(vsum.z) : max(vsum.z, 0.0);
+
+    return sum / (2.0 * LTC_PI);
+}
+
+vec3 CalcAreaLight(AreaLight light, vec3 normal, vec3 fragPos, vec3 viewDir, vec3 diffuseColor, float shininess)
+{
+    // unused slot
+    if (light.rate <= 0.0) {
+        return vec3(0.0);
+    }
+
+    // phong shininess to GGX alpha
+    float alpha = clamp(sqrt(2.0 / (shininess + 2.0)), 0.01, 1.0);
+
+    // Lambert is the cosine distribution itself
+    float diff = LTCEvaluate(normal, viewDir, fragPos, mat3(1.0), light);
+
+    float spec;
+    if (uLTCEnabled) {
+        float ndotv = clamp(dot(normal, viewDir), 0.0, 1.0);
+        vec2 uv = vec2(sqrt(alpha), sqrt(1.0 - ndotv));
+        uv = uv * (LTC_LUT_SIZE - 1.0) / LTC_LUT_SIZE + 0.5 / LTC_LUT_SIZE;
+
+        vec4 t1 = texture2D(uLTC1, uv);
+        vec4 t2 = texture2D(uLTC2, uv);
+
+        mat3 Minv = mat3(
+            vec3(t1.x, 0.0, t1.y),
+            vec3(0.0, 1.0, 0.0),
+            vec3(t1.z, 0.0, t1.w)
+        );
+
+        spec = LTCEvaluate(normal, viewDir, fragPos, Minv, light) * t2.x;
+    } else {
+        // Without the fit, shrink the cosine lobe around the reflection vector
+        vec3 R = reflect(-viewDir, normal);
+        mat3 Minv = mat3(
+            1.0 / alpha, 0.0, 0.0,
+            0.0, 1.0 / alpha, 0.0,
+            0.0, 0.0, 1.0
+        );
+
+        spec = LTCEvaluate(R, viewDir, fragPos, Minv, light);
+    }
+
+    vec3 diffuse = light.diffuse * diff * diffuseColor;
+    vec3 specular = light.specular * spec;
+
+    return (diffuse + specular) * light.rate;
+}
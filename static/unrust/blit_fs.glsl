@@ -0,0 +1,16 @@
+// Copies uScreenTexture into the viewport, its filtering is kept,
+// e.g. nearest for the pixel perfect cameras.
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+
+void main()
+{
+    gl_FragColor = texture2D(uScreenTexture, vTexCoords);
+}
@@ -0,0 +1,20 @@
+// Adds the blurred bright pass over the screen
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+uniform sampler2D uBloomTexture;
+uniform float uIntensity;
+
+void main()
+{
+    vec4 color = texture2D(uScreenTexture, vTexCoords);
+    vec3 bloom = texture2D(uBloomTexture, vTexCoords).rgb;
+
+    gl_FragColor = vec4(color.rgb + bloom * uIntensity, color.a);
+}
@@ -0,0 +1,33 @@
+// Keeps the pixels brighter than uThreshold, with a soft knee
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+uniform vec2 uScreenSize;
+uniform float uThreshold;
+uniform float uSoftKnee;
+
+void main()
+{
+    // Average 4 texels of the full size source
+    vec2 offset = 0.25 / uScreenSize;
+    vec3 color = texture2D(uScreenTexture, vTexCoords + vec2(-offset.x, -offset.y)).rgb;
+    color += texture2D(uScreenTexture, vTexCoords + vec2(offset.x, -offset.y)).rgb;
+    color += texture2D(uScreenTexture, vTexCoords + vec2(-offset.x, offset.y)).rgb;
+    color += texture2D(uScreenTexture, vTexCoords + vec2(offset.x, offset.y)).rgb;
+    color *= 0.25;
+
+    float brightness = max(color.r, max(color.g, color.b));
+    float knee = uThreshold * uSoftKnee + 0.0001;
+    float soft = clamp(brightness - uThreshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+
+    float contribution = max(soft, brightness - uThreshold) / max(brightness, 0.0001);
+
+    gl_FragColor = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,29 @@
+// The camera uniforms of the standard shaders, see Engine::setup_camera.
+// The GLSL 300 es shaders read them from the "Camera" uniform block when
+// the context has uniform buffers, declared the same in both stages.
+#ifndef UNRUST_CAMERA_BLOCK
+#define UNRUST_CAMERA_BLOCK
+
+#ifdef USE_GLSL_300ES
+#ifdef UNIFORM_BUFFERS
+#define UNRUST_UNIFORM_BLOCKS
+#endif
+#endif
+
+#ifdef UNRUST_UNIFORM_BLOCKS
+layout(std140) uniform Camera {
+    mat4 uVMatrix;
+    mat4 uPMatrix;
+    vec3 uViewPos;
+    bool uLinearColor;
+    bool uEncodeSrgb;
+};
+#else
+uniform mat4 uVMatrix;
+uniform mat4 uPMatrix;
+uniform vec3 uViewPos;
+uniform bool uLinearColor;
+uniform bool uEncodeSrgb;
+#endif
+
+#endif
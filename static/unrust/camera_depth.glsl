@@ -0,0 +1,29 @@
+// Depth of the opaque queue built by the engine for the current camera,
+// see Camera::depth_texture.
+uniform bool uCameraDepthEnabled;
+uniform sampler2D uCameraDepthTexture;
+// x, y, width, height of the viewport the depth matches
+uniform vec4 uCameraDepthViewport;
+// Clip planes of the camera
+uniform vec2 uCameraNearFar;
+
+// Window depth of the opaque scene at the fragment, 1 (far) without depth
+float SceneDepth()
+{
+    if (!uCameraDepthEnabled) {
+        return 1.0;
+    }
+
+    vec2 uv = (gl_FragCoord.xy - uCameraDepthViewport.xy) / uCameraDepthViewport.zw;
+    return texture2D(uCameraDepthTexture, uv).r;
+}
+
+// View distance of a window depth, for perspective projections
+float LinearEyeDepth(float depth)
+{
+    float n = uCameraNearFar.x;
+    float f = uCameraNearFar.y;
+    float z = depth * 2.0 - 1.0;
+
+    return 2.0 * n * f / (f + n - z * (f - n));
+}
@@ -0,0 +1,95 @@
+// Point lights binned into view space clusters (Forward+), GLSL 300 es only.
+// The layout of both tables is described in LightClusters.
+
+#define CLUSTER_X 16
+#define CLUSTER_Y 9
+#define CLUSTER_Z 24
+#define CLUSTER_TEXELS 9
+
+uniform bool uClusteredEnabled;
+uniform sampler2D uClusterLightTexture;
+uniform sampler2D uClusterTexture;
+
+// near, far, then scale and bias from log(depth) to the slice index
+uniform vec4 uClusterDepth;
+// x, y, width, height of the viewport in pixels
+uniform vec4 uClusterViewport;
+uniform int uClusterLayer;
+
+uint ClusterUint(int light, int index)
+{
+    uvec4 b = uvec4(texelFetch(uClusterLightTexture, ivec2(index, light), 0) * 255.0 + 0.5);
+    return b.r | (b.g << 8u) | (b.b << 16u) | (b.a << 24u);
+}
+
+vec4 ClusterVec4(int light, int index)
+{
+    int i = index * 4;
+
+    return vec4(
+        uintBitsToFloat(ClusterUint(light, i)),
+        uintBitsToFloat(ClusterUint(light, i + 1)),
+        uintBitsToFloat(ClusterUint(light, i + 2)),
+        uintBitsToFloat(ClusterUint(light, i + 3)));
+}
+
+vec3 CalcClusteredLights(vec3 normal, vec3 fragPos, vec3 viewDir, vec3 diffuseColor, float shininess)
+{
+    vec3 result = vec3(0.0);
+
+    if (!uClusteredEnabled || uClusterLayer < 0) {
+        return result;
+    }
+
+    // linear view depth from the depth buffer
+    float n = uClusterDepth.x;
+    float f = uClusterDepth.y;
+    float depth = 2.0 * n * f / (f + n - (gl_FragCoord.z * 2.0 - 1.0) * (f - n));
+
+    int slice = clamp(int(log(depth) * uClusterDepth.z + uClusterDepth.w), 0, CLUSTER_Z - 1);
+    vec2 uv = (gl_FragCoord.xy - uClusterViewport.xy) / uClusterViewport.zw;
+    ivec2 tile = clamp(ivec2(uv * vec2(CLUSTER_X, CLUSTER_Y)), ivec2(0), ivec2(CLUSTER_X - 1, CLUSTER_Y - 1));
+
+    ivec2 base = ivec2(tile.x * CLUSTER_TEXELS, slice * CLUSTER_Y + tile.y);
+    int count = int(texelFetch(uClusterTexture, base, 0).r * 255.0 + 0.5);
+    uint layerBit = 1u << uint(uClusterLayer);
+
+    for (int i = 0; i < count; i++) {
+        vec4 indices = texelFetch(uClusterTexture, base + ivec2(1 + i / 4, 0), 0);
+        int light = int(indices[i % 4] * 255.0 + 0.5);
+
+        if ((ClusterUint(light, 16) & layerBit) == 0u) {
+            continue;
+        }
+
+        vec4 posRange = ClusterVec4(light, 0);
+        vec4 ambientConstant = ClusterVec4(light, 1);
+        vec4 diffuseLinear = ClusterVec4(light, 2);
+        vec4 specularQuadratic = ClusterVec4(light, 3);
+
+        float distance = length(posRange.xyz - fragPos);
+        if (distance > posRange.w) {
+            continue;
+        }
+
+        vec3 lightDir = (posRange.xyz - fragPos) / max(distance, 0.0001);
+
+        // diffuse shading
+        float diff = max(dot(normal, lightDir), 0.0);
+        // specular shading
+        vec3 reflectDir = reflect(-lightDir, normal);
+        float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+
+        // attenuation
+        float d = ambientConstant.w + diffuseLinear.w * distance + specularQuadratic.w * (distance * distance);
+        float attenuation = 1.0 / max(d, 0.001);
+
+        vec3 ambient = ambientConstant.rgb * diffuseColor;
+        vec3 diffuse = diffuseLinear.rgb * diff * diffuseColor;
+        vec3 specular = specularQuadratic.rgb * spec;
+
+        result += (ambient + diffuse + specular) * attenuation;
+    }
+
+    return result;
+}
@@ -0,0 +1,49 @@
+// Color grading from 2D strip LUTs, see ColorGrading
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+uniform sampler2D uLut;
+uniform sampler2D uTargetLut;
+uniform bool uHasTargetLut;
+uniform float uLutSize;
+uniform float uBlend;
+
+vec3 SampleStrip(sampler2D lut, vec3 color)
+{
+    float maxIndex = uLutSize - 1.0;
+    float slice = color.b * maxIndex;
+    float slice0 = floor(slice);
+    float slice1 = min(slice0 + 1.0, maxIndex);
+
+    // Texel centers inside a slice
+    vec2 uv = (color.rg * maxIndex + 0.5) / vec2(uLutSize * uLutSize, uLutSize);
+
+    // Green grows downward in the image, which is flipped on load
+    uv.y = 1.0 - uv.y;
+
+    vec3 c0 = texture2D(lut, uv + vec2(slice0 / uLutSize, 0.0)).rgb;
+    vec3 c1 = texture2D(lut, uv + vec2(slice1 / uLutSize, 0.0)).rgb;
+
+    return mix(c0, c1, slice - slice0);
+}
+
+void main()
+{
+    vec4 color = texture2D(uScreenTexture, vTexCoords);
+    vec3 src = clamp(color.rgb, 0.0, 1.0);
+    vec3 graded = SampleStrip(uLut, src);
+
+    if (uHasTargetLut) {
+        graded = mix(graded, SampleStrip(uTargetLut, src), uBlend);
+    } else {
+        graded = mix(src, graded, uBlend);
+    }
+
+    gl_FragColor = vec4(graded, color.a);
+}
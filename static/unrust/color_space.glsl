@@ -0,0 +1,22 @@
+// sRGB transfer of the linear color pipeline, see ColorSpace.
+// uLinearColor decodes the colors to linear before the lighting,
+// uEncodeSrgb encodes the result back, unless it goes to an HDR
+// target encoded by the tonemapping.
+#include "unrust/camera_block.glsl"
+
+// The 2.2 power approximates the sRGB curves
+vec4 DecodeColor(vec4 color)
+{
+    if (uLinearColor) {
+        return vec4(pow(color.rgb, vec3(2.2)), color.a);
+    }
+    return color;
+}
+
+vec4 EncodeColor(vec4 color)
+{
+    if (uEncodeSrgb) {
+        return vec4(pow(max(color.rgb, vec3(0.0)), vec3(1.0 / 2.2)), color.a);
+    }
+    return color;
+}
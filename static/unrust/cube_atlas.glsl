@@ -0,0 +1,61 @@
+// Cube maps stored in 2D atlases, rows of 6 faces in the order +X, -X, +Y, -Y, +Z, -Z,
+// each face rendered with the look_at basis below, see ReflectionProbe.
+
+float CubeAtlasFaceIndex(vec3 d)
+{
+    vec3 ad = abs(d);
+
+    if (ad.x >= ad.y && ad.x >= ad.z) {
+        return d.x > 0.0 ? 0.0 : 1.0;
+    }
+    if (ad.y >= ad.z) {
+        return d.y > 0.0 ? 2.0 : 3.0;
+    }
+    return d.z > 0.0 ? 4.0 : 5.0;
+}
+
+void CubeFaceBasis(float face, out vec3 forward, out vec3 right, out vec3 up)
+{
+    if (face < 0.5) {
+        forward = vec3(1.0, 0.0, 0.0); up = vec3(0.0, -1.0, 0.0);
+    } else if (face < 1.5) {
+        forward = vec3(-1.0, 0.0, 0.0); up = vec3(0.0, -1.0, 0.0);
+    } else if (face < 2.5) {
+        forward = vec3(0.0, 1.0, 0.0); up = vec3(0.0, 0.0, 1.0);
+    } else if (face < 3.5) {
+        forward = vec3(0.0, -1.0, 0.0); up = vec3(0.0, 0.0, -1.0);
+    } else if (face < 4.5) {
+        forward = vec3(0.0, 0.0, 1.0); up = vec3(0.0, -1.0, 0.0);
+    } else {
+        forward = vec3(0.0, 0.0, -1.0); up = vec3(0.0, -1.0, 0.0);
+    }
+
+    right = cross(forward, up);
+}
+
+// The direction through `uv` in [0, 1] of `face`
+vec3 CubeFaceDirection(float face, vec2 uv)
+{
+    vec3 forward, right, up;
+    CubeFaceBasis(face, forward, right, up);
+
+    vec2 p = uv * 2.0 - 1.0;
+    return normalize(forward + p.x * right + p.y * up);
+}
+
+// Atlas uv of direction `d` in the face row `row` of `rows`
+vec2 CubeAtlasUV(vec3 d, float row, float rows, vec2 atlasSize)
+{
+    float face = CubeAtlasFaceIndex(d);
+    vec3 forward, right, up;
+    CubeFaceBasis(face, forward, right, up);
+
+    vec2 uv = vec2(dot(d, right), dot(d, up)) / dot(d, forward) * 0.5 + 0.5;
+
+    // Stay inside the face, its neighbours in the atlas are unrelated
+    vec2 tile = vec2(1.0 / 6.0, 1.0 / rows);
+    vec2 texel = 1.0 / atlasSize;
+    uv = clamp(uv * tile, texel * 0.5, tile - texel * 0.5);
+
+    return vec2(face, row) * tile + uv;
+}
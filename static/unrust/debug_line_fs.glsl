@@ -0,0 +1,12 @@
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+out vec4 FragColor;
+#endif
+
+varying vec3 vColor;
+
+void main()
+{
+    gl_FragColor = vec4(vColor, 1.0);
+}
@@ -0,0 +1,17 @@
+#ifndef GL_ES
+#define attribute in
+#define varying out
+#endif
+
+#include "unrust/default_uniforms.glsl"
+
+// The lines of DebugDraw are in world space, the normal is the color
+attribute vec3 aVertexPosition;
+attribute vec3 aVertexNormal;
+
+varying vec3 vColor;
+
+void main(void) {
+    vColor = aVertexNormal;
+    gl_Position = uPMatrix * uVMatrix * vec4(aVertexPosition, 1.0);
+}
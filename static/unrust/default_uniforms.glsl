@@ -1,4 +1,30 @@
+#include "unrust/camera_block.glsl"
+
+#ifdef INSTANCING
+// The model matrix comes from the instance buffer, see InstancedMesh.
+attribute mat4 aInstanceModel;
+
+// The inverse transpose of the 3x3 part of the model matrix, from its
+// cofactors, for the non uniformly scaled instances
+mat4 InstanceNormalMatrix(mat4 m) {
+    vec3 x = m[0].xyz;
+    vec3 y = m[1].xyz;
+    vec3 z = m[2].xyz;
+    vec3 cx = cross(y, z);
+    float det = dot(x, cx);
+
+    return mat4(
+        vec4(cx / det, 0.0),
+        vec4(cross(z, x) / det, 0.0),
+        vec4(cross(x, y) / det, 0.0),
+        vec4(0.0, 0.0, 0.0, 1.0));
+}
+
+#define uMMatrix aInstanceModel
+#define uNMatrix InstanceNormalMatrix(aInstanceModel)
+#define uMVMatrix (uVMatrix * aInstanceModel)
+#else
 uniform mat4 uMVMatrix;
-uniform mat4 uPMatrix;
 uniform mat4 uNMatrix;
 uniform mat4 uMMatrix;
+#endif
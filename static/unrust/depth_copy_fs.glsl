@@ -0,0 +1,15 @@
+#define USE_GLSL_300ES
+
+// Writes the scene depth into the target before the overlay, the color is
+// kept by the blending of the material
+#define texture2D texture
+out vec4 FragColor;
+
+in vec2 vTexCoords;
+uniform sampler2D uDepthTexture;
+
+void main()
+{
+    gl_FragDepth = texture2D(uDepthTexture, vTexCoords).r;
+    FragColor = vec4(0.0);
+}
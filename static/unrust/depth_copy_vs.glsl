@@ -0,0 +1,6 @@
+#define USE_GLSL_300ES
+
+#define attribute in
+#define varying out
+
+#include "unrust/post_process_vs.glsl"
@@ -0,0 +1,12 @@
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+out vec4 FragColor;
+#endif
+
+varying vec3 vNormal;
+
+void main()
+{
+    gl_FragColor = vec4(normalize(vNormal) * 0.5 + 0.5, 1.0);
+}
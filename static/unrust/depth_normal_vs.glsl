@@ -0,0 +1,17 @@
+#ifndef GL_ES
+#define attribute in
+#define varying out
+#endif
+
+#include "unrust/default_uniforms.glsl"
+
+attribute vec3 aVertexPosition;
+attribute vec3 aVertexNormal;
+
+varying vec3 vNormal;
+
+void main(void) {
+    // View space normal
+    vNormal = mat3(uVMatrix) * mat3(uNMatrix) * aVertexNormal;
+    gl_Position = uPMatrix * uMVMatrix * vec4(aVertexPosition, 1.0);
+}
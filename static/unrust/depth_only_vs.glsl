@@ -0,0 +1,13 @@
+#ifndef GL_ES
+#define attribute in
+#define varying out
+#endif
+
+#include "unrust/default_uniforms.glsl"
+
+attribute vec3 aVertexPosition;
+
+void main(void) {
+    // Same expression as the color shaders, so the Equal depth test passes
+    gl_Position = uPMatrix * uMVMatrix * vec4(aVertexPosition, 1.0);
+}
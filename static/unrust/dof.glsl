@@ -0,0 +1,21 @@
+// Circle of confusion shared by the depth of field passes
+uniform sampler2D uDepthTexture;
+uniform vec2 uCameraNearFar;
+uniform float uFocusDistance;
+uniform float uAperture;
+
+float LinearDepth(vec2 uv)
+{
+    float z = texture2D(uDepthTexture, uv).r * 2.0 - 1.0;
+    float near = uCameraNearFar.x;
+    float far = uCameraNearFar.y;
+
+    return 2.0 * near * far / (far + near - z * (far - near));
+}
+
+// 0 is sharp, 1 is the largest blur
+float CircleOfConfusion(vec2 uv)
+{
+    float depth = LinearDepth(uv);
+    return clamp(uAperture * abs(depth - uFocusDistance) / max(depth, 0.0001), 0.0, 1.0);
+}
@@ -0,0 +1,51 @@
+// Gathers the half resolution image over rings of a disk, each sample
+// only contributes when its own circle of confusion reaches the center
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+#define MAX_RINGS 4
+#define SAMPLES_PER_RING 8
+
+varying vec2 vTexCoords;
+uniform sampler2D uSourceTexture;
+uniform vec2 uScreenSize;
+uniform float uMaxRadius;
+uniform int uRings;
+
+void main()
+{
+    vec4 center = texture2D(uSourceTexture, vTexCoords);
+    vec2 texel = 1.0 / uScreenSize;
+
+    vec3 color = center.rgb;
+    float weight = 1.0;
+
+    for (int ring = 1; ring <= MAX_RINGS; ring++) {
+        if (ring > uRings) {
+            break;
+        }
+
+        float ringRadius = float(ring) / float(uRings);
+
+        for (int i = 0; i < MAX_RINGS * SAMPLES_PER_RING; i++) {
+            if (i >= ring * SAMPLES_PER_RING) {
+                break;
+            }
+
+            float angle = 6.2831853 * float(i) / float(ring * SAMPLES_PER_RING);
+            vec2 offset = vec2(cos(angle), sin(angle)) * ringRadius * uMaxRadius * texel;
+            vec4 s = texture2D(uSourceTexture, vTexCoords + offset);
+
+            // Blurred samples spread over the center, and the center gathers when blurred
+            float w = smoothstep(ringRadius - 0.25, ringRadius, max(s.a, center.a));
+            color += s.rgb * w;
+            weight += w;
+        }
+    }
+
+    gl_FragColor = vec4(color / weight, center.a);
+}
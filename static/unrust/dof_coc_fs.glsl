@@ -0,0 +1,18 @@
+// Half resolution color with the circle of confusion in alpha
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+#include "unrust/dof.glsl"
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+
+void main()
+{
+    vec3 color = texture2D(uScreenTexture, vTexCoords).rgb;
+    gl_FragColor = vec4(color, CircleOfConfusion(vTexCoords));
+}
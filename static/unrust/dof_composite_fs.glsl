@@ -0,0 +1,24 @@
+// Blends the sharp image with the gathered bokeh by the circle of confusion
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+#include "unrust/dof.glsl"
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+uniform sampler2D uBokehTexture;
+
+void main()
+{
+    vec4 sharp = texture2D(uScreenTexture, vTexCoords);
+    vec4 bokeh = texture2D(uBokehTexture, vTexCoords);
+
+    float coc = max(CircleOfConfusion(vTexCoords), bokeh.a);
+    float blend = smoothstep(0.05, 0.3, coc);
+
+    gl_FragColor = vec4(mix(sharp.rgb, bokeh.rgb, blend), sharp.a);
+}
@@ -0,0 +1,22 @@
+// Halves uSourceTexture with a 4 tap bilinear box filter
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uSourceTexture;
+uniform vec2 uScreenSize;
+
+void main()
+{
+    vec2 offset = 0.5 / uScreenSize;
+    vec4 color = texture2D(uSourceTexture, vTexCoords + vec2(-offset.x, -offset.y));
+    color += texture2D(uSourceTexture, vTexCoords + vec2(offset.x, -offset.y));
+    color += texture2D(uSourceTexture, vTexCoords + vec2(-offset.x, offset.y));
+    color += texture2D(uSourceTexture, vTexCoords + vec2(offset.x, offset.y));
+
+    gl_FragColor = color * 0.25;
+}
@@ -0,0 +1 @@
+#include "unrust/post_process_vs.glsl"
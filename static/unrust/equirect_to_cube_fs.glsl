@@ -0,0 +1,34 @@
+// An equirectangular image rendered into a cube atlas, the capture of an environment
+// ReflectionProbe
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uSourceTexture;
+// HDR image encoded by the engine, see RGBM_RANGE
+uniform bool uSourceRGBM;
+
+#include "unrust/cube_atlas.glsl"
+
+const float PI = 3.14159265359;
+
+void main()
+{
+    float face = floor(vTexCoords.x * 6.0);
+    vec2 uv = vec2(fract(vTexCoords.x * 6.0), vTexCoords.y);
+    vec3 d = CubeFaceDirection(face, uv);
+
+    // -Z is at the center of the image, +Y at the top
+    vec2 st = vec2(0.5 + atan(d.x, -d.z) / (2.0 * PI), acos(clamp(d.y, -1.0, 1.0)) / PI);
+
+    vec4 color = texture2D(uSourceTexture, st);
+    if (uSourceRGBM) {
+        color.rgb *= color.a * 8.0;
+    }
+
+    gl_FragColor = vec4(color.rgb, 1.0);
+}
@@ -0,0 +1,27 @@
+// Scene fog of the engine, see Fog.
+uniform bool uFogEnabled;
+// 0: linear, 1: exponential, 2: exponential squared
+uniform int uFogMode;
+uniform vec3 uFogColor;
+// Start and end of the linear fog, then the density
+uniform vec3 uFogParams;
+
+vec3 ApplyFog(vec3 color, float distance)
+{
+    if (!uFogEnabled) {
+        return color;
+    }
+
+    float visibility;
+    if (uFogMode == 0) {
+        float range = max(uFogParams.y - uFogParams.x, 0.0001);
+        visibility = (uFogParams.y - distance) / range;
+    } else if (uFogMode == 1) {
+        visibility = exp(-uFogParams.z * distance);
+    } else {
+        float d = uFogParams.z * distance;
+        visibility = exp(-d * d);
+    }
+
+    return mix(uFogColor, color, clamp(visibility, 0.0, 1.0));
+}
@@ -0,0 +1,65 @@
+// FXAA, after the "FXAA 3.11 console" variant by Timothy Lottes
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+uniform vec2 uScreenSize;
+uniform float uEdgeThreshold;
+uniform float uSubpixel;
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_SPAN_MAX 8.0
+
+float Luma(vec3 color)
+{
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main()
+{
+    vec2 texel = 1.0 / uScreenSize;
+
+    vec4 center = texture2D(uScreenTexture, vTexCoords);
+    float lumaM = Luma(center.rgb);
+    float lumaNW = Luma(texture2D(uScreenTexture, vTexCoords + vec2(-1.0, -1.0) * texel).rgb);
+    float lumaNE = Luma(texture2D(uScreenTexture, vTexCoords + vec2(1.0, -1.0) * texel).rgb);
+    float lumaSW = Luma(texture2D(uScreenTexture, vTexCoords + vec2(-1.0, 1.0) * texel).rgb);
+    float lumaSE = Luma(texture2D(uScreenTexture, vTexCoords + vec2(1.0, 1.0) * texel).rgb);
+
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // Not an edge
+    if (lumaMax - lumaMin < max(0.0312, lumaMax * uEdgeThreshold)) {
+        gl_FragColor = center;
+        return;
+    }
+
+    vec2 dir = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+        ((lumaNW + lumaSW) - (lumaNE + lumaSE)));
+
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * (1.0 - uSubpixel),
+                          FXAA_REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 rgbA = 0.5 * (
+        texture2D(uScreenTexture, vTexCoords + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture2D(uScreenTexture, vTexCoords + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (
+        texture2D(uScreenTexture, vTexCoords - dir * 0.5).rgb +
+        texture2D(uScreenTexture, vTexCoords + dir * 0.5).rgb);
+
+    float lumaB = Luma(rgbB);
+    if (lumaB < lumaMin || lumaB > lumaMax) {
+        gl_FragColor = vec4(rgbA, center.a);
+    } else {
+        gl_FragColor = vec4(rgbB, center.a);
+    }
+}
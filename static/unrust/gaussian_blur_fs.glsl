@@ -0,0 +1,26 @@
+// 9 tap separable gaussian blur of uSourceTexture along uDirection,
+// using linear sampling between texels (5 fetches)
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uSourceTexture;
+uniform vec2 uScreenSize;
+uniform vec2 uDirection;
+
+void main()
+{
+    vec2 texel = uDirection / uScreenSize;
+
+    vec4 color = texture2D(uSourceTexture, vTexCoords) * 0.2270270270;
+    color += texture2D(uSourceTexture, vTexCoords + texel * 1.3846153846) * 0.3162162162;
+    color += texture2D(uSourceTexture, vTexCoords - texel * 1.3846153846) * 0.3162162162;
+    color += texture2D(uSourceTexture, vTexCoords + texel * 3.2307692308) * 0.0702702703;
+    color += texture2D(uSourceTexture, vTexCoords - texel * 3.2307692308) * 0.0702702703;
+
+    gl_FragColor = color;
+}
@@ -0,0 +1,55 @@
+// Image based lighting from the nearest ReflectionProbe, see "unrust/cube_atlas.glsl"
+uniform bool uIBLEnabled;
+uniform sampler2D uIrradianceMap;
+uniform vec2 uIrradianceMapSize;
+// One face row per roughness level
+uniform sampler2D uSpecularMap;
+uniform vec2 uSpecularMapSize;
+uniform float uSpecularLevels;
+
+#include "unrust/cube_atlas.glsl"
+
+// Analytic fit of the split sum BRDF term (Karis, mobile UE4)
+vec3 EnvBRDFApprox(vec3 f0, float roughness, float NdotV)
+{
+    const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+
+    vec4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * NdotV)) * r.x + r.y;
+    vec2 ab = vec2(-1.04, 1.04) * a004 + r.zw;
+
+    return f0 * ab.x + ab.y;
+}
+
+vec3 SampleSpecularMap(vec3 dir, float roughness)
+{
+    float levels = uSpecularLevels;
+    float level = roughness * (levels - 1.0);
+    float l0 = floor(level);
+    float l1 = min(l0 + 1.0, levels - 1.0);
+
+    vec3 c0 = texture2D(uSpecularMap, CubeAtlasUV(dir, l0, levels, uSpecularMapSize)).rgb;
+    vec3 c1 = texture2D(uSpecularMap, CubeAtlasUV(dir, l1, levels, uSpecularMapSize)).rgb;
+
+    return mix(c0, c1, level - l0);
+}
+
+// Diffuse and specular ambient of the surface
+vec3 ImageBasedLighting(vec3 albedo, float metallic, float roughness, vec3 f0, vec3 normal, vec3 viewDir)
+{
+    if (!uIBLEnabled) {
+        return vec3(0.0);
+    }
+
+    float NdotV = max(dot(normal, viewDir), 0.0001);
+    vec3 irradiance = texture2D(uIrradianceMap, CubeAtlasUV(normal, 0.0, 1.0, uIrradianceMapSize)).rgb;
+
+    vec3 specularColor = EnvBRDFApprox(f0, roughness, NdotV);
+    vec3 kd = (vec3(1.0) - specularColor) * (1.0 - metallic);
+
+    vec3 reflected = reflect(-viewDir, normal);
+    vec3 specular = SampleSpecularMap(reflected, roughness) * specularColor;
+
+    return kd * irradiance * albedo + specular;
+}
@@ -0,0 +1,42 @@
+// Cosine convolution of a captured cube atlas, the diffuse lighting of a ReflectionProbe
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uSourceTexture;
+uniform vec2 uSourceSize;
+
+#include "unrust/cube_atlas.glsl"
+
+const float PI = 3.14159265359;
+
+void main()
+{
+    float face = floor(vTexCoords.x * 6.0);
+    vec2 uv = vec2(fract(vTexCoords.x * 6.0), vTexCoords.y);
+    vec3 normal = CubeFaceDirection(face, uv);
+
+    vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, normal));
+    vec3 bitangent = cross(normal, tangent);
+
+    vec3 irradiance = vec3(0.0);
+
+    for (int i = 0; i < 16; i++) {
+        for (int j = 0; j < 8; j++) {
+            float phi = float(i) / 16.0 * 2.0 * PI;
+            float theta = (float(j) + 0.5) / 8.0 * 0.5 * PI;
+
+            vec3 d = sin(theta) * (cos(phi) * tangent + sin(phi) * bitangent) + cos(theta) * normal;
+            vec2 atlasUV = CubeAtlasUV(d, 0.0, 1.0, uSourceSize);
+
+            irradiance += texture2D(uSourceTexture, atlasUV).rgb * cos(theta) * sin(theta);
+        }
+    }
+
+    gl_FragColor = vec4(PI * irradiance / 128.0, 1.0);
+}
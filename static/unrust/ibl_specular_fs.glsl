@@ -0,0 +1,59 @@
+// GGX prefiltering of a captured cube atlas for one roughness level of a ReflectionProbe
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uSourceTexture;
+uniform vec2 uSourceSize;
+uniform float uRoughness;
+
+#include "unrust/cube_atlas.glsl"
+
+const float PI = 3.14159265359;
+const int SAMPLE_COUNT = 32;
+
+vec3 ImportanceSampleGGX(vec2 xi, vec3 normal, vec3 tangent, vec3 bitangent, float roughness)
+{
+    float a = roughness * roughness;
+
+    float phi = 2.0 * PI * xi.x;
+    float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+
+    return sinTheta * (cos(phi) * tangent + sin(phi) * bitangent) + cosTheta * normal;
+}
+
+void main()
+{
+    float face = floor(vTexCoords.x * 6.0);
+    vec2 uv = vec2(fract(vTexCoords.x * 6.0), vTexCoords.y);
+
+    // The view and reflected directions are the normal
+    vec3 normal = CubeFaceDirection(face, uv);
+
+    vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, normal));
+    vec3 bitangent = cross(normal, tangent);
+
+    vec3 color = vec3(0.0);
+    float weight = 0.0;
+
+    for (int i = 0; i < SAMPLE_COUNT; i++) {
+        // Golden ratio sequence, no bit operations in GLSL ES 1.0
+        vec2 xi = vec2(fract(float(i) * 0.618034), (float(i) + 0.5) / float(SAMPLE_COUNT));
+        vec3 h = ImportanceSampleGGX(xi, normal, tangent, bitangent, uRoughness);
+        vec3 l = normalize(2.0 * dot(normal, h) * h - normal);
+
+        float NdotL = dot(normal, l);
+        if (NdotL > 0.0) {
+            color += texture2D(uSourceTexture, CubeAtlasUV(l, 0.0, 1.0, uSourceSize)).rgb * NdotL;
+            weight += NdotL;
+        }
+    }
+
+    gl_FragColor = vec4(color / max(weight, 0.0001), 1.0);
+}
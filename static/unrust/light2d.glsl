@@ -0,0 +1,16 @@
+// Light texture of the Light2D components built by the engine for the
+// current camera, see Lighting2DSettings.
+uniform bool uLight2DEnabled;
+uniform sampler2D uLight2DTexture;
+// x, y, width, height of the viewport the light texture matches
+uniform vec4 uLight2DViewport;
+
+vec3 Light2D()
+{
+    if (!uLight2DEnabled) {
+        return vec3(1.0);
+    }
+
+    vec2 uv = (gl_FragCoord.xy - uLight2DViewport.xy) / uLight2DViewport.zw;
+    return texture2D(uLight2DTexture, uv).rgb;
+}
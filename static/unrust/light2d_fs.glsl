@@ -0,0 +1,57 @@
+// A Light2D added to the light texture, over the whole viewport. The pixels
+// are lit where the camera rays cross the plane of the light.
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+
+uniform sampler2D uNormalTexture;
+uniform bool uNormalMaps;
+uniform mat4 uInvViewProj;
+uniform vec2 uScreenSize;
+
+uniform vec3 uLightPosition;
+uniform vec2 uLightDirection;
+uniform vec3 uLightColor;
+uniform float uLightRadius;
+uniform float uLightFalloff;
+uniform float uLightHeight;
+// Cosines of the outer and inner half angles of the cone
+uniform vec2 uLightCone;
+
+vec3 Unproject(vec2 ndc, float z)
+{
+    vec4 p = uInvViewProj * vec4(ndc, z, 1.0);
+    return p.xyz / p.w;
+}
+
+void main()
+{
+    vec2 ndc = gl_FragCoord.xy / uScreenSize * 2.0 - 1.0;
+    vec3 near = Unproject(ndc, -1.0);
+    vec3 far = Unproject(ndc, 1.0);
+
+    vec3 ray = far - near;
+    float t = abs(ray.z) > 0.00001 ? (uLightPosition.z - near.z) / ray.z : 0.0;
+    vec2 pos = near.xy + ray.xy * t;
+
+    vec2 to_pixel = pos - uLightPosition.xy;
+    float dist = length(to_pixel);
+
+    float attenuation = pow(clamp(1.0 - dist / uLightRadius, 0.0, 1.0), uLightFalloff);
+
+    vec2 dir = dist > 0.0 ? to_pixel / dist : uLightDirection;
+    attenuation *= smoothstep(uLightCone.x, uLightCone.y, dot(dir, uLightDirection));
+
+    if (uNormalMaps) {
+        vec3 normal = normalize(texture2D(uNormalTexture, vTexCoords).xyz * 2.0 - 1.0);
+        vec3 to_light = normalize(vec3(-to_pixel, uLightHeight));
+        attenuation *= max(dot(normal, to_light), 0.0);
+    }
+
+    gl_FragColor = vec4(uLightColor * attenuation, 1.0);
+}
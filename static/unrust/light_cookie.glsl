@@ -0,0 +1,43 @@
+// Projected light textures, needs phong_light.glsl
+
+#define UNI_SPOT_COOKIES 4
+
+uniform sampler2D uDirectionalLightCookie;
+uniform sampler2D uSpotLightCookies[UNI_SPOT_COOKIES];
+
+vec3 DirectionalLightCookie(DirectionalLight light, vec3 fragPos)
+{
+    if (!light.has_cookie) {
+        return vec3(1.0);
+    }
+
+    vec2 uv = (light.cookie_matrix * vec4(fragPos, 1.0)).xy;
+    return texture2D(uDirectionalLightCookie, uv).rgb;
+}
+
+vec3 SpotLightCookie(int index, SpotLight light, vec3 fragPos)
+{
+    if (!light.has_cookie) {
+        return vec3(1.0);
+    }
+
+    vec4 p = light.cookie_matrix * vec4(fragPos, 1.0);
+    if (p.w <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec2 uv = p.xy / p.w * 0.5 + 0.5;
+
+    // Sampler arrays can only be indexed by constant expressions
+    if (index == 0) {
+        return texture2D(uSpotLightCookies[0], uv).rgb;
+    } else if (index == 1) {
+        return texture2D(uSpotLightCookies[1], uv).rgb;
+    } else if (index == 2) {
+        return texture2D(uSpotLightCookies[2], uv).rgb;
+    } else if (index == 3) {
+        return texture2D(uSpotLightCookies[3], uv).rgb;
+    }
+
+    return vec3(1.0);
+}
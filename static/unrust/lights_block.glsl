@@ -0,0 +1,17 @@
+// The lights of the standard fragment shaders, see Engine::setup_light.
+// The GLSL 300 es shaders read them from the "Lights" uniform block when
+// the context has uniform buffers. Needs phong_light.glsl, UNI_POINT_LIGHTS
+// and UNI_SPOT_LIGHTS.
+#include "unrust/camera_block.glsl"
+
+#ifdef UNRUST_UNIFORM_BLOCKS
+layout(std140) uniform Lights {
+    DirectionalLight uDirectionalLight;
+    PointLight uPointLights[UNI_POINT_LIGHTS];
+    SpotLight uSpotLights[UNI_SPOT_LIGHTS];
+};
+#else
+uniform DirectionalLight uDirectionalLight;
+uniform PointLight uPointLights[UNI_POINT_LIGHTS];
+uniform SpotLight uSpotLights[UNI_SPOT_LIGHTS];
+#endif
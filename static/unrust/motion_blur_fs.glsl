@@ -0,0 +1,46 @@
+// Camera motion blur by reprojecting the depth into the last frame
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+#define MAX_SAMPLES 16
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+uniform sampler2D uDepthTexture;
+uniform mat4 uInvViewProjection;
+uniform mat4 uPrevViewProjection;
+uniform float uIntensity;
+uniform int uSamples;
+
+void main()
+{
+    float depth = texture2D(uDepthTexture, vTexCoords).r;
+
+    vec4 ndc = vec4(vTexCoords * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+    vec4 world = uInvViewProjection * ndc;
+    world /= world.w;
+
+    vec4 prev = uPrevViewProjection * world;
+    vec2 prevUV = prev.xy / prev.w * 0.5 + 0.5;
+
+    vec2 velocity = (vTexCoords - prevUV) * uIntensity;
+
+    vec4 color = texture2D(uScreenTexture, vTexCoords);
+    float count = 1.0;
+
+    for (int i = 1; i < MAX_SAMPLES; i++) {
+        if (i >= uSamples) {
+            break;
+        }
+
+        vec2 uv = vTexCoords - velocity * (float(i) / float(uSamples) - 0.5);
+        color += texture2D(uScreenTexture, uv);
+        count += 1.0;
+    }
+
+    gl_FragColor = color / count;
+}
@@ -0,0 +1,79 @@
+// Tangent space normal and parallax occlusion mapping from the uNormalMap and
+// uHeightMap slots of the material, the engine sets uHasNormalMap and
+// uHasHeightMap when the material has them.
+uniform sampler2D uNormalMap;
+uniform bool uHasNormalMap;
+
+uniform sampler2D uHeightMap;
+uniform bool uHasHeightMap;
+// Depth of the surface in texture space, around 0.05
+uniform float uHeightScale;
+// Ray march steps, clamped to MAX_PARALLAX_STEPS
+uniform int uParallaxSteps;
+
+#define MAX_PARALLAX_STEPS 32
+
+bool HasTangentFrame(vec3 tangent)
+{
+    return dot(tangent, tangent) > 0.000001;
+}
+
+// tangent and bitangent are zero when the mesh has none
+mat3 TangentFrame(vec3 normal, vec3 tangent, vec3 bitangent)
+{
+    // Gram-Schmidt, the interpolated tangent is not orthogonal anymore
+    vec3 T = normalize(tangent - normal * dot(normal, tangent));
+    vec3 B = cross(normal, T);
+    if (dot(bitangent, bitangent) > 0.000001 && dot(B, bitangent) < 0.0) {
+        B = -B;
+    }
+
+    return mat3(T, B, normal);
+}
+
+vec3 ApplyNormalMap(vec3 normal, vec3 tangent, vec3 bitangent, vec2 uv)
+{
+    if (!uHasNormalMap || !HasTangentFrame(tangent)) {
+        return normal;
+    }
+
+    vec3 n = texture2D(uNormalMap, uv).xyz * 2.0 - 1.0;
+    return normalize(TangentFrame(normal, tangent, bitangent) * n);
+}
+
+// Offset uv along the view direction until the ray goes below the height map
+vec2 ParallaxOcclusion(vec2 uv, vec3 viewDir, vec3 normal, vec3 tangent, vec3 bitangent)
+{
+    if (!uHasHeightMap || !HasTangentFrame(tangent)) {
+        return uv;
+    }
+
+    mat3 tbn = TangentFrame(normal, tangent, bitangent);
+    vec3 v = vec3(dot(viewDir, tbn[0]), dot(viewDir, tbn[1]), dot(viewDir, tbn[2]));
+
+    int steps = int(clamp(float(uParallaxSteps), 1.0, float(MAX_PARALLAX_STEPS)));
+    float layerDepth = 1.0 / float(steps);
+    vec2 delta = v.xy / max(v.z, 0.05) * uHeightScale * layerDepth;
+
+    vec2 current = uv;
+    float currentDepth = 0.0;
+    float mapDepth = 1.0 - texture2D(uHeightMap, current).r;
+
+    for (int i = 0; i < MAX_PARALLAX_STEPS; i++) {
+        if (i >= steps || currentDepth >= mapDepth) {
+            break;
+        }
+
+        current -= delta;
+        mapDepth = 1.0 - texture2D(uHeightMap, current).r;
+        currentDepth += layerDepth;
+    }
+
+    // Interpolate between the last two layers
+    vec2 previous = current + delta;
+    float after = mapDepth - currentDepth;
+    float before = (1.0 - texture2D(uHeightMap, previous).r) - currentDepth + layerDepth;
+    float weight = after / min(after - before, -0.0001);
+
+    return mix(current, previous, weight);
+}
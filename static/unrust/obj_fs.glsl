@@ -0,0 +1,170 @@
+#define USE_GLSL_300ES
+
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+
+#ifndef UNI_POINT_LIGHTS
+#define UNI_POINT_LIGHTS 4
+#endif
+#ifndef UNI_SPOT_LIGHTS
+#define UNI_SPOT_LIGHTS 4
+#endif
+#ifndef UNI_AREA_LIGHTS
+#define UNI_AREA_LIGHTS 4
+#endif
+
+#include "unrust/phong_light.glsl"
+#include "unrust/area_light.glsl"
+#include "unrust/light_cookie.glsl"
+#include "unrust/ssao.glsl"
+#include "unrust/normal_map.glsl"
+#include "unrust/clustered_light.glsl"
+#include "unrust/shadow_utils.glsl"
+#include "unrust/fog.glsl"
+#include "unrust/color_space.glsl"
+
+// The Ka, Kd, Ks, Ns and d statements of a MTL material, each map is
+// multiplied with its color
+struct Material {
+    vec3 ambient;
+    vec3 diffuse;
+    sampler2D diffuse_tex;
+
+    vec3 specular;
+    sampler2D specular_tex;
+
+    float shininess;
+    float transparent;
+    sampler2D mask_tex;
+};
+
+struct MaterialColor {
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+
+uniform Material uMaterial;
+uniform float uAlphaCutoff;
+
+in vec3 vFragPos;
+in vec2 vTexCoords;
+in vec3 vNormal;
+in vec3 vTangent;
+in vec3 vBitangent;
+
+// Lights
+#include "unrust/lights_block.glsl"
+
+vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir, MaterialColor color);
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir, MaterialColor color, float shadow);
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir, MaterialColor color, vec3 cookie);
+
+void main(void) {
+    vec3 normal = normalize(vNormal);
+    vec3 viewDir = normalize(uViewPos - vFragPos);
+
+    vec2 texCoords = ParallaxOcclusion(vTexCoords, viewDir, normal, vTangent, vBitangent);
+
+    vec4 diffuseTex = DecodeColor(texture2D(uMaterial.diffuse_tex, texCoords));
+    float alpha = diffuseTex.a * texture2D(uMaterial.mask_tex, texCoords).r * uMaterial.transparent;
+
+    // Alpha cutout
+    if (alpha < uAlphaCutoff)
+        discard;
+
+    vec3 norm = ApplyNormalMap(normal, vTangent, vBitangent, texCoords);
+
+    MaterialColor color;
+    color.diffuse = uMaterial.diffuse * diffuseTex.rgb;
+    color.ambient = uMaterial.ambient * color.diffuse * AmbientOcclusion();
+    color.specular = uMaterial.specular * texture2D(uMaterial.specular_tex, texCoords).rgb;
+
+    // Directional Light
+    vec3 result = CalcDirectionalLight(uDirectionalLight, norm, viewDir, color);
+
+    // Point Lights
+    for(int i = 0; i < UNI_POINT_LIGHTS; i++)
+        result += CalcPointLight(uPointLights[i], norm, vFragPos, viewDir, color, PointShadowCalculation(i, vFragPos, norm));
+
+    // Clustered Point Lights
+    result += CalcClusteredLights(norm, vFragPos, viewDir, color.diffuse, uMaterial.shininess);
+
+    // Spot Lights
+    for(int i = 0; i < UNI_SPOT_LIGHTS; i++)
+        result += CalcSpotLight(uSpotLights[i], norm, vFragPos, viewDir, color, SpotLightCookie(i, uSpotLights[i], vFragPos));
+
+    // Area Lights
+    for(int i = 0; i < UNI_AREA_LIGHTS; i++)
+        result += CalcAreaLight(uAreaLights[i], norm, vFragPos, viewDir, color.diffuse, uMaterial.shininess);
+
+    result = ApplyFog(result, length(uViewPos - vFragPos));
+
+    gl_FragColor = EncodeColor(vec4(result, alpha));
+}
+
+float SpecularTerm(vec3 lightDir, vec3 normal, vec3 viewDir)
+{
+    vec3 reflectDir = reflect(-lightDir, normal);
+    return pow(max(dot(viewDir, reflectDir), 0.0), uMaterial.shininess);
+}
+
+vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir, MaterialColor color)
+{
+    vec3 lightDir = normalize(-light.direction);
+    float diff = max(dot(normal, lightDir), 0.0);
+
+    vec3 ambient = light.ambient * color.ambient;
+    vec3 diffuse = light.diffuse * diff * color.diffuse;
+    vec3 specular = light.specular * SpecularTerm(lightDir, normal, viewDir) * color.specular;
+
+    float shadow = ShadowCalculation(vFragPos, normal, normal, lightDir);
+    vec3 cookie = DirectionalLightCookie(light, vFragPos);
+
+    return ambient + (diffuse + specular) * shadow * cookie;
+}
+
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir, MaterialColor color, float shadow)
+{
+    vec3 lightDir = normalize(light.position - fragPos);
+    float diff = max(dot(normal, lightDir), 0.0);
+
+    // attenuation
+    float distance = length(light.position - fragPos);
+    float d = (light.constant + light.linear * distance + light.quadratic * (distance * distance));
+    float attenuation = 1.0 / max(d, 0.001);
+
+    vec3 ambient = light.ambient * color.ambient;
+    vec3 diffuse = light.diffuse * diff * color.diffuse;
+    vec3 specular = light.specular * SpecularTerm(lightDir, normal, viewDir) * color.specular;
+
+    return (ambient + (diffuse + specular) * shadow) * attenuation * light.rate;
+}
+
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir, MaterialColor color, vec3 cookie)
+{
+    // unused slot
+    if (light.rate <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec3 lightDir = normalize(light.position - fragPos);
+    float diff = max(dot(normal, lightDir), 0.0);
+
+    // attenuation, fade out smoothly at range
+    float distance = length(light.position - fragPos);
+    float falloff = clamp(1.0 - pow(distance / max(light.range, 0.001), 4.0), 0.0, 1.0);
+    float attenuation = falloff * falloff;
+
+    // cone with soft edges
+    float theta = dot(lightDir, normalize(-light.direction));
+    float epsilon = max(light.cut_off - light.outer_cut_off, 0.001);
+    float intensity = clamp((theta - light.outer_cut_off) / epsilon, 0.0, 1.0);
+
+    vec3 ambient = light.ambient * color.ambient;
+    vec3 diffuse = light.diffuse * diff * color.diffuse;
+    vec3 specular = light.specular * SpecularTerm(lightDir, normal, viewDir) * color.specular;
+
+    return (ambient + (diffuse + specular) * intensity * cookie) * attenuation * light.rate;
+}
@@ -0,0 +1,18 @@
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+varying vec4 vColor;
+
+uniform sampler2D uTexture;
+
+#include "unrust/color_space.glsl"
+
+void main()
+{
+    gl_FragColor = EncodeColor(DecodeColor(texture2D(uTexture, vTexCoords) * vColor));
+}
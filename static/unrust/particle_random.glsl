@@ -0,0 +1,6 @@
+// Hash of a particle slot in [0, 1), `n` picks one of its values
+float ParticleRandom(float slot, float seed, float n)
+{
+    float x = mod(slot * 12.9898 + seed * 78.233 + n * 37.719, 6283.1853);
+    return fract(sin(x) * 43758.5453);
+}
@@ -0,0 +1,63 @@
+// One step of the GPU particles of a ParticleEmitter, rendered twice into the
+// (position, age) and (velocity, lifetime) state textures
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+#include "unrust/particle_random.glsl"
+
+varying vec2 vTexCoords;
+
+uniform sampler2D uPositions;
+uniform sampler2D uVelocities;
+uniform vec2 uStateSize;
+uniform float uCapacity;
+uniform bool uVelocityPass;
+
+// Dead slots in [uEmitStart, uEmitStart + uEmitCount) modulo uCapacity are emitted
+uniform float uEmitStart;
+uniform float uEmitCount;
+uniform float uSeed;
+uniform float uDeltaTime;
+
+uniform mat4 uEmitterMatrix;
+uniform vec3 uGravity;
+uniform float uSpread;
+uniform vec2 uStartSpeed;
+uniform vec2 uLifetime;
+
+void main()
+{
+    vec4 position = texture2D(uPositions, vTexCoords);
+    vec4 velocity = texture2D(uVelocities, vTexCoords);
+
+    vec2 texel = floor(vTexCoords * uStateSize);
+    float slot = texel.y * uStateSize.x + texel.x;
+
+    bool alive = position.w < velocity.w;
+    float offset = mod(slot - uEmitStart + uCapacity, uCapacity);
+
+    if (!alive && slot < uCapacity && offset < uEmitCount) {
+        // A direction in the cone around +Y
+        float cosTheta = 1.0 - ParticleRandom(slot, uSeed, 0.0) * (1.0 - cos(uSpread));
+        float sinTheta = sqrt(max(1.0 - cosTheta * cosTheta, 0.0));
+        float phi = ParticleRandom(slot, uSeed, 1.0) * 6.2831853;
+        vec3 local = vec3(sinTheta * cos(phi), cosTheta, sinTheta * sin(phi));
+        vec3 direction = normalize((uEmitterMatrix * vec4(local, 0.0)).xyz);
+
+        float speed = mix(uStartSpeed.x, uStartSpeed.y, ParticleRandom(slot, uSeed, 2.0));
+        float lifetime = mix(uLifetime.x, uLifetime.y, ParticleRandom(slot, uSeed, 3.0));
+
+        position = vec4(uEmitterMatrix[3].xyz, 0.0);
+        velocity = vec4(direction * speed, max(lifetime, 1e-3));
+    } else if (alive) {
+        velocity.xyz += uGravity * uDeltaTime;
+        position.xyz += velocity.xyz * uDeltaTime;
+        position.w += uDeltaTime;
+    }
+
+    gl_FragColor = uVelocityPass ? velocity : position;
+}
@@ -0,0 +1,76 @@
+#ifndef GL_ES
+#define attribute in
+#define varying out
+#define texture2D texture
+#endif
+
+#include "unrust/default_uniforms.glsl"
+
+attribute vec3 aVertexPosition;
+attribute vec2 aTextureCoord;
+attribute vec3 aVertexNormal;
+
+#ifdef GPU_PARTICLES
+#include "unrust/particle_random.glsl"
+
+// The normal is the texel of the particle in the state textures and its slot,
+// see ParticleEmitter::begin_gpu_step
+uniform sampler2D uParticlePositions;
+uniform sampler2D uParticleVelocities;
+
+uniform vec2 uStartSize;
+uniform vec2 uStartRotation;
+uniform vec4 uColorStart;
+uniform vec4 uColorEnd;
+uniform float uSizeStart;
+uniform float uSizeEnd;
+#else
+// See ParticleEmitter: the center of the particle in world space, the quad
+// corner, the color and (size, rotation, alpha)
+attribute vec3 aVertexTangent;
+#endif
+
+varying vec2 vTexCoords;
+varying vec4 vColor;
+
+void main(void) {
+#ifdef GPU_PARTICLES
+    vec4 state = texture2D(uParticlePositions, aVertexNormal.xy);
+    vec4 velocity = texture2D(uParticleVelocities, aVertexNormal.xy);
+
+    if (state.w >= velocity.w) {
+        // Dead, outside of the clip volume
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+
+    // The lifetime differs between the particles of a slot
+    float slot = aVertexNormal.z;
+    float t = state.w / velocity.w;
+
+    vec3 center = state.xyz;
+    float size = mix(uStartSize.x, uStartSize.y, ParticleRandom(slot, velocity.w, 4.0))
+        * mix(uSizeStart, uSizeEnd, t);
+    float rotation = mix(uStartRotation.x, uStartRotation.y, ParticleRandom(slot, velocity.w, 5.0));
+    vColor = mix(uColorStart, uColorEnd, t);
+#else
+    vec3 center = aVertexPosition;
+    float size = aVertexTangent.x;
+    float rotation = aVertexTangent.y;
+    vColor = vec4(aVertexNormal, aVertexTangent.z);
+#endif
+
+    // The camera axes in world space, rows of the view rotation
+    vec3 right = vec3(uVMatrix[0][0], uVMatrix[1][0], uVMatrix[2][0]);
+    vec3 up = vec3(uVMatrix[0][1], uVMatrix[1][1], uVMatrix[2][1]);
+
+    float s = sin(rotation);
+    float c = cos(rotation);
+    vec2 corner = (aTextureCoord - 0.5) * size;
+    corner = vec2(corner.x * c - corner.y * s, corner.x * s + corner.y * c);
+
+    vec3 position = center + right * corner.x + up * corner.y;
+
+    vTexCoords = aTextureCoord;
+    gl_Position = uPMatrix * uVMatrix * vec4(position, 1.0);
+}
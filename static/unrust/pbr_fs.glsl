@@ -0,0 +1,195 @@
+#define USE_GLSL_300ES
+
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+
+#ifndef UNI_POINT_LIGHTS
+#define UNI_POINT_LIGHTS 4
+#endif
+#ifndef UNI_SPOT_LIGHTS
+#define UNI_SPOT_LIGHTS 4
+#endif
+
+#include "unrust/phong_light.glsl"
+#include "unrust/light_cookie.glsl"
+#include "unrust/ssao.glsl"
+#include "unrust/shadow_utils.glsl"
+#include "unrust/ibl.glsl"
+#include "unrust/fog.glsl"
+#include "unrust/color_space.glsl"
+
+// Metallic-roughness material, see Material::new_pbr
+struct Material {
+    vec4 albedo;
+    sampler2D albedo_map;
+
+    float metallic;
+    float roughness;
+    sampler2D metallic_roughness_map;
+
+    sampler2D normal_map;
+    float normal_scale;
+
+    sampler2D occlusion_map;
+    float occlusion_strength;
+
+    vec3 emissive;
+    sampler2D emissive_map;
+};
+
+uniform Material uMaterial;
+uniform float uAlphaCutoff;
+
+in vec3 vFragPos;
+in vec2 vTexCoords;
+in vec3 vNormal;
+
+// Lights, the diffuse color is used as the radiance
+#include "unrust/lights_block.glsl"
+
+const float PI = 3.14159265359;
+
+struct Surface {
+    vec3 albedo;
+    float metallic;
+    float roughness;
+    vec3 f0;
+};
+
+float DistributionGGX(float NdotH, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = NdotH * NdotH * (a2 - 1.0) + 1.0;
+
+    return a2 / max(PI * d * d, 0.0001);
+}
+
+float GeometrySchlickGGX(float NdotV, float roughness)
+{
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+vec3 FresnelSchlick(float cosTheta, vec3 f0)
+{
+    return f0 + (1.0 - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// Cook-Torrance for one light of `radiance` coming from `lightDir`
+vec3 CalcRadiance(Surface s, vec3 normal, vec3 viewDir, vec3 lightDir, vec3 radiance)
+{
+    vec3 halfway = normalize(viewDir + lightDir);
+
+    float NdotL = max(dot(normal, lightDir), 0.0);
+    float NdotV = max(dot(normal, viewDir), 0.0001);
+    float NdotH = max(dot(normal, halfway), 0.0);
+
+    float D = DistributionGGX(NdotH, s.roughness);
+    float G = GeometrySchlickGGX(NdotV, s.roughness) * GeometrySchlickGGX(NdotL, s.roughness);
+    vec3 F = FresnelSchlick(max(dot(halfway, viewDir), 0.0), s.f0);
+
+    vec3 specular = D * G * F / max(4.0 * NdotV * NdotL, 0.0001);
+    vec3 kd = (vec3(1.0) - F) * (1.0 - s.metallic);
+
+    return (kd * s.albedo / PI + specular) * radiance * NdotL;
+}
+
+// Normal map perturbation without tangents, from the screen space derivatives
+vec3 PerturbNormal(vec3 normal, vec3 fragPos, vec2 uv)
+{
+    vec3 tangentNormal = texture2D(uMaterial.normal_map, uv).xyz * 2.0 - 1.0;
+    tangentNormal.xy *= uMaterial.normal_scale;
+
+    vec3 dp1 = dFdx(fragPos);
+    vec3 dp2 = dFdy(fragPos);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float invmax = inversesqrt(max(dot(T, T), dot(B, B)));
+    mat3 tbn = mat3(T * invmax, B * invmax, normal);
+
+    return normalize(tbn * tangentNormal);
+}
+
+void main(void) {
+    vec4 albedo = DecodeColor(uMaterial.albedo * texture2D(uMaterial.albedo_map, vTexCoords));
+    if (albedo.a < uAlphaCutoff)
+        discard;
+    vec4 mr = texture2D(uMaterial.metallic_roughness_map, vTexCoords);
+
+    Surface s;
+    s.albedo = albedo.rgb;
+    s.metallic = clamp(uMaterial.metallic * mr.b, 0.0, 1.0);
+    s.roughness = clamp(uMaterial.roughness * mr.g, 0.04, 1.0);
+    s.f0 = mix(vec3(0.04), s.albedo, s.metallic);
+
+    vec3 normal = PerturbNormal(normalize(vNormal), vFragPos, vTexCoords);
+    vec3 viewDir = normalize(uViewPos - vFragPos);
+
+    // Directional Light
+    DirectionalLight dl = uDirectionalLight;
+    vec3 lightDir = normalize(-dl.direction);
+    float shadow = ShadowCalculation(vFragPos, normal, normal, lightDir);
+    vec3 cookie = DirectionalLightCookie(dl, vFragPos);
+    vec3 result = CalcRadiance(s, normal, viewDir, lightDir, dl.diffuse * shadow * cookie);
+
+    vec3 ambient = dl.ambient;
+
+    // Point Lights
+    for (int i = 0; i < UNI_POINT_LIGHTS; i++) {
+        PointLight pl = uPointLights[i];
+
+        float distance = length(pl.position - vFragPos);
+        float d = pl.constant + pl.linear * distance + pl.quadratic * (distance * distance);
+        float attenuation = pl.rate / max(d, 0.001);
+
+        vec3 dir = normalize(pl.position - vFragPos);
+        float pointShadow = PointShadowCalculation(i, vFragPos, normal);
+
+        result += CalcRadiance(s, normal, viewDir, dir, pl.diffuse * attenuation * pointShadow);
+        ambient += pl.ambient * attenuation;
+    }
+
+    // Spot Lights
+    for (int i = 0; i < UNI_SPOT_LIGHTS; i++) {
+        SpotLight sl = uSpotLights[i];
+        if (sl.rate <= 0.0) {
+            continue;
+        }
+
+        vec3 dir = normalize(sl.position - vFragPos);
+
+        float distance = length(sl.position - vFragPos);
+        float falloff = clamp(1.0 - pow(distance / max(sl.range, 0.001), 4.0), 0.0, 1.0);
+        float attenuation = falloff * falloff * sl.rate;
+
+        float theta = dot(dir, normalize(-sl.direction));
+        float epsilon = max(sl.cut_off - sl.outer_cut_off, 0.001);
+        float intensity = clamp((theta - sl.outer_cut_off) / epsilon, 0.0, 1.0);
+
+        vec3 spotCookie = SpotLightCookie(i, sl, vFragPos);
+
+        result += CalcRadiance(s, normal, viewDir, dir, sl.diffuse * attenuation * intensity * spotCookie);
+        ambient += sl.ambient * attenuation;
+    }
+
+    // Ambient and image based lighting, occluded by the map and the screen space occlusion
+    float occlusion = mix(1.0, texture2D(uMaterial.occlusion_map, vTexCoords).r, uMaterial.occlusion_strength);
+    vec3 ibl = ImageBasedLighting(s.albedo, s.metallic, s.roughness, s.f0, normal, viewDir);
+    result += (ambient * s.albedo + ibl) * occlusion * AmbientOcclusion();
+
+    vec4 emissive = vec4(uMaterial.emissive, 1.0) * texture2D(uMaterial.emissive_map, vTexCoords);
+    result += DecodeColor(emissive).rgb;
+    result = ApplyFog(result, length(uViewPos - vFragPos));
+
+    gl_FragColor = EncodeColor(vec4(result, albedo.a));
+}
@@ -4,6 +4,9 @@ struct DirectionalLight {
     vec3 ambient;
     vec3 diffuse;
     vec3 specular;
+
+    bool has_cookie;
+    mat4 cookie_matrix;
 };
 
 struct PointLight {
@@ -18,4 +21,62 @@ struct PointLight {
     vec3 specular;
 
     float rate;
-};
\ No newline at end of file
+};
+
+struct SpotLight {
+    vec3 position;
+    vec3 direction;
+
+    float cut_off;
+    float outer_cut_off;
+    float range;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+
+    float rate;
+
+    bool has_cookie;
+    mat4 cookie_matrix;
+};
+
+// `ambientColor` is the diffuse color with the ambient occlusion, `cookie`
+// the color of the light cookie at the fragment, white without cookie
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir,
+                   vec3 diffuseColor, vec3 ambientColor, float shininess, vec3 cookie)
+{
+    // unused slot
+    if (light.rate <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec3 lightDir = normalize(light.position - fragPos);
+
+    // diffuse shading
+    float diff = max(dot(normal, lightDir), 0.0);
+    // specular shading
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+
+    // attenuation, fade out smoothly at range
+    float distance = length(light.position - fragPos);
+    float falloff = clamp(1.0 - pow(distance / max(light.range, 0.001), 4.0), 0.0, 1.0);
+    float attenuation = falloff * falloff;
+
+    // cone with soft edges
+    float theta = dot(lightDir, normalize(-light.direction));
+    float epsilon = max(light.cut_off - light.outer_cut_off, 0.001);
+    float intensity = clamp((theta - light.outer_cut_off) / epsilon, 0.0, 1.0);
+
+    // combine results
+    vec3 ambient = light.ambient * ambientColor;
+    vec3 diffuse = light.diffuse * diff * diffuseColor;
+    vec3 specular = light.specular * spec;
+
+    ambient *= attenuation;
+    diffuse *= attenuation * intensity * cookie;
+    specular *= attenuation * intensity * cookie;
+
+    return (ambient + diffuse + specular) * light.rate;
+}
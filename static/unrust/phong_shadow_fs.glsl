@@ -4,52 +4,94 @@
 #define texture2D texture
 out vec4 FragColor;
 
+#ifndef UNI_POINT_LIGHTS
 #define UNI_POINT_LIGHTS 4
+#endif
+#ifndef UNI_SPOT_LIGHTS
+#define UNI_SPOT_LIGHTS 4
+#endif
+#ifndef UNI_AREA_LIGHTS
+#define UNI_AREA_LIGHTS 4
+#endif
 
 #include "unrust/phong_light.glsl"
+#include "unrust/area_light.glsl"
+#include "unrust/light_cookie.glsl"
+#include "unrust/ssao.glsl"
+#include "unrust/normal_map.glsl"
+#include "unrust/clustered_light.glsl"
 #include "unrust/shadow_utils.glsl"
+#include "unrust/fog.glsl"
+#include "unrust/color_space.glsl"
 
 struct Material {
     sampler2D diffuse;
     float shininess;
 };
 
-uniform vec3 uViewPos;
 uniform Material uMaterial;
+uniform float uAlphaCutoff;
 
 in vec3 vFragPos;
 in vec2 vTexCoords;       
 in vec3 vNormal;       
+in vec3 vTangent;
+in vec3 vBitangent;
+
+// vTexCoords after the parallax offset
+vec2 texCoords;
 
 // Lights
-uniform DirectionalLight uDirectionalLight;
-uniform PointLight uPointLights[UNI_POINT_LIGHTS];
+#include "unrust/lights_block.glsl"
 
 vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir);
-vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir, float shadow);
 
 void main(void) {
-    vec3 norm = normalize(vNormal);
+    vec3 normal = normalize(vNormal);
     vec3 viewDir = normalize(uViewPos - vFragPos);
 
+    texCoords = ParallaxOcclusion(vTexCoords, viewDir, normal, vTangent, vBitangent);
+
+    // Alpha cutout
+    if (texture2D(uMaterial.diffuse, texCoords).a < uAlphaCutoff)
+        discard;
+
+    vec3 norm = ApplyNormalMap(normal, vTangent, vBitangent, texCoords);
+
     // Directional Light
     vec3 result = CalcDirectionalLight(uDirectionalLight, norm, viewDir);
     
     // Point Lights
     for(int i = 0; i < UNI_POINT_LIGHTS; i++)
-        result += CalcPointLight(uPointLights[i], norm, vFragPos, viewDir);
+        result += CalcPointLight(uPointLights[i], norm, vFragPos, viewDir, PointShadowCalculation(i, vFragPos, norm));
+
+    vec3 diffuseColor = DecodeColor(texture2D(uMaterial.diffuse, texCoords)).rgb;
+
+    // Clustered Point Lights
+    result += CalcClusteredLights(norm, vFragPos, viewDir, diffuseColor, uMaterial.shininess);
+
+    // Spot Lights
+    for(int i = 0; i < UNI_SPOT_LIGHTS; i++)
+        result += CalcSpotLight(uSpotLights[i], norm, vFragPos, viewDir, diffuseColor, diffuseColor * AmbientOcclusion(), uMaterial.shininess, SpotLightCookie(i, uSpotLights[i], vFragPos));
+
+    // Area Lights
+    for(int i = 0; i < UNI_AREA_LIGHTS; i++)
+        result += CalcAreaLight(uAreaLights[i], norm, vFragPos, viewDir, diffuseColor, uMaterial.shininess);
+
+    result = ApplyFog(result, length(uViewPos - vFragPos));
 
-    gl_FragColor = vec4(result, 1.0);           
+    gl_FragColor = EncodeColor(vec4(result, 1.0));           
 }
 
 vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir)
 {
     // diffuse
-    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, vTexCoords));
+    vec3 ambient = light.ambient * vec3(DecodeColor(texture2D(uMaterial.diffuse, texCoords))) * AmbientOcclusion();
 
     vec3 lightDir = normalize(-light.direction);  
     float diff = max(dot(normal, lightDir), 0.0);
-    vec3 diffuse = light.diffuse * diff * texture2D(uMaterial.diffuse, vTexCoords).rgb;  
+    vec3 diffuse = light.diffuse * diff * DecodeColor(texture2D(uMaterial.diffuse, texCoords)).rgb;  
 
     // specular    
     vec3 reflectDir = reflect(-lightDir, normal);  
@@ -57,11 +99,12 @@ vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir)
     vec3 specular = light.specular * spec; 
 
     float shadow = ShadowCalculation(vFragPos, normal, normal, lightDir);
+    vec3 cookie = DirectionalLightCookie(light, vFragPos);
 
-    return ambient + (diffuse + specular) * shadow;
+    return ambient + (diffuse + specular) * shadow * cookie;
 }
 
-vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir, float shadow)
 {
     vec3 lightDir = normalize(light.position - fragPos);
     
@@ -77,13 +120,13 @@ vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir)
     float attenuation = 1.0 / max(d, 0.001);
     
     // combine results
-    vec3 ambient = light.ambient * vec3(texture2D(uMaterial.diffuse, vTexCoords));
-    vec3 diffuse = light.diffuse * diff * vec3(texture2D(uMaterial.diffuse, vTexCoords));
+    vec3 ambient = light.ambient * vec3(DecodeColor(texture2D(uMaterial.diffuse, texCoords))) * AmbientOcclusion();
+    vec3 diffuse = light.diffuse * diff * vec3(DecodeColor(texture2D(uMaterial.diffuse, texCoords)));
     vec3 specular = light.specular * spec;
     
     ambient *= attenuation;
     diffuse *= attenuation;
     specular *= attenuation;
     
-    return (ambient + diffuse + specular) * light.rate;        
+    return (ambient + (diffuse + specular) * shadow) * light.rate;        
 }
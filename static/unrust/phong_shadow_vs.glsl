@@ -7,16 +7,22 @@
 
 attribute vec3 aVertexPosition;
 attribute vec3 aVertexNormal;
+attribute vec3 aVertexTangent;
+attribute vec3 aVertexBitangent;
 attribute vec2 aTextureCoord;
 
 varying vec3 vFragPos;
 varying vec3 vNormal;
+varying vec3 vTangent;
+varying vec3 vBitangent;
 varying vec2 vTexCoords;
 
 void main(void) {
     vFragPos = vec3(uMMatrix * vec4(aVertexPosition, 1.0));            
     
     vNormal = mat3(uNMatrix) * aVertexNormal;
+    vTangent = mat3(uMMatrix) * aVertexTangent;
+    vBitangent = mat3(uMMatrix) * aVertexBitangent;
     vTexCoords = aTextureCoord;
     
     gl_Position = uPMatrix * uMVMatrix * vec4(aVertexPosition, 1.0);
@@ -0,0 +1,10 @@
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+void main()
+{
+}         
\ No newline at end of file
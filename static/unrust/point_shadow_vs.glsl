@@ -0,0 +1,13 @@
+#ifndef GL_ES
+#define attribute in
+#define varying out
+#endif
+
+#include "unrust/default_uniforms.glsl"
+
+attribute vec3 aVertexPosition;
+uniform mat4 uShadowMatrix;            
+
+void main(void) {
+    gl_Position = uShadowMatrix * uMMatrix * vec4(aVertexPosition, 1.0);
+}
\ No newline at end of file
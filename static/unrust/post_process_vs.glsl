@@ -0,0 +1,16 @@
+// Vertex stage of the fullscreen post processing passes,
+// draws the "screen_quad" mesh over the whole target.
+#ifndef GL_ES
+#define attribute in
+#define varying out
+#endif
+
+attribute vec3 aVertexPosition;
+attribute vec2 aTextureCoord;
+
+varying vec2 vTexCoords;
+
+void main(void) {
+    gl_Position = vec4(aVertexPosition.xy, 0.0, 1.0);
+    vTexCoords = aTextureCoord;
+}
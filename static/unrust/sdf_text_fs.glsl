@@ -0,0 +1,42 @@
+// Glyphs of a TextMesh, the alpha of uTexture is the signed distance
+// field of the font: 0.5 on the glyph edges, above inside. With a TtfFont
+// the alpha is the coverage of the glyph instead.
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+
+uniform sampler2D uTexture;
+uniform vec4 uColor;
+uniform vec4 uOutlineColor;
+// In distance units, from 0 to 0.5
+uniform float uOutlineWidth;
+// The alpha is the coverage of a TtfFont glyph, without outline
+uniform bool uCoverageAlpha;
+
+#include "unrust/color_space.glsl"
+
+// Half width of the antialiased edge, without the derivatives of WebGL1
+#define SMOOTHING 0.06
+
+void main()
+{
+    float d = texture2D(uTexture, vTexCoords).a;
+
+    if (uCoverageAlpha) {
+        vec4 color = DecodeColor(uColor);
+        gl_FragColor = EncodeColor(vec4(color.rgb, color.a * d));
+        return;
+    }
+
+    float fill = smoothstep(0.5 - SMOOTHING, 0.5 + SMOOTHING, d);
+    float edge = 0.5 - uOutlineWidth;
+    float outline = smoothstep(edge - SMOOTHING, edge + SMOOTHING, d);
+
+    vec4 color = mix(DecodeColor(uOutlineColor), DecodeColor(uColor), fill);
+    gl_FragColor = EncodeColor(vec4(color.rgb, color.a * outline));
+}
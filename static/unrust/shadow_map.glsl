@@ -7,3 +7,10 @@ struct ShadowMap {
     vec2 viewport_scale;
     float tex_size;
 };
+
+struct ShadowSettings {
+    float bias;
+    float slope_bias;
+    float normal_bias;
+    int pcf_radius;
+};
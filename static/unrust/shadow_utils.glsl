@@ -2,8 +2,12 @@
 
 uniform bool uShadowEnabled;
 uniform ShadowMap uShadowMap[4];
+uniform ShadowSettings uShadowSettings;
 uniform sampler2D uShadowMapTexture;
 
+// Loops need constant bounds, ShadowSettings.pcf_radius is clamped to this
+#define MAX_PCF_RADIUS 3
+
 float ndc_z() {
     return ((2.0 * gl_FragCoord.z - gl_DepthRange.near - gl_DepthRange.far) /
     (gl_DepthRange.far - gl_DepthRange.near));
@@ -18,7 +22,7 @@ vec2 get_shadow_offsets(vec3 N, vec3 L) {
 
 vec3 LightSpacePosition(int index, vec3 worldPos, vec3 worldNormal, vec2 bias_offset)
 {
-    float normal_bias = 0.02;
+    float normal_bias = uShadowSettings.normal_bias;
 
     vec4 posLightSpace = uShadowMap[index].light_matrix * vec4(worldPos + worldNormal * normal_bias * bias_offset.x, 1.0);
     vec3 projCoordsNDC = posLightSpace.xyz / posLightSpace.w;
@@ -41,10 +45,6 @@ float ShadowCalculation(vec3 worldPos, vec3 worldNormal, vec3 normal, vec3 light
         return 1.0;
     }
     
-    float constant_bias = 0.5;
-    float slope_bias = 3.0;
-    float normal_bias = 0.02;
-
     vec2 bias_offset = get_shadow_offsets(normal, lightDir);    
     int index = ShadowIndex();
             
@@ -53,12 +53,17 @@ float ShadowCalculation(vec3 worldPos, vec3 worldNormal, vec3 normal, vec3 light
     float texelSize = uShadowMap[index].tex_size;
 
     float shadow = 0.0;
-    float bias = constant_bias * texelSize * (constant_bias + slope_bias * bias_offset.y);
+    float bias = texelSize * (uShadowSettings.bias + uShadowSettings.slope_bias * bias_offset.y);
+    int radius = uShadowSettings.pcf_radius;
 
-    for(int x = -1; x <= 1; ++x)
+    for(int x = -MAX_PCF_RADIUS; x <= MAX_PCF_RADIUS; ++x)
     {
-        for(int y = -1; y <= 1; ++y)
+        for(int y = -MAX_PCF_RADIUS; y <= MAX_PCF_RADIUS; ++y)
         {
+            if (x < -radius || x > radius || y < -radius || y > radius) {
+                continue;
+            }
+
             vec2 offset = vec2(x, y)* texelSize;
             vec2 boundProj = projCoords.xy + offset;
             vec2 adjBoundProj = uShadowMap[index].viewport_offset + boundProj * uShadowMap[index].viewport_scale;
@@ -73,8 +78,102 @@ float ShadowCalculation(vec3 worldPos, vec3 worldNormal, vec3 normal, vec3 light
         }       
     }
     
-    shadow /= 9.0;
+    float kernel = 2.0 * min(float(radius), float(MAX_PCF_RADIUS)) + 1.0;
+    shadow /= kernel * kernel;
 
     return (1.0 - shadow);
 }
 
+
+
+// Point light shadows
+// Each shadow casting point light owns a row of 6 cube faces in
+// uPointShadowMapTexture, in the order +X, -X, +Y, -Y, +Z, -Z.
+// uPointShadows[i] belongs to uPointLights[i], its tile is the row.
+
+#define UNI_POINT_SHADOWS 4
+
+struct PointShadow {
+    bool enabled;
+    float tile;
+    vec3 position;
+    vec2 depth_range;
+    ShadowSettings settings;
+};
+
+uniform PointShadow uPointShadows[UNI_POINT_SHADOWS];
+uniform sampler2D uPointShadowMapTexture;
+uniform vec2 uPointShadowMapSize;
+
+float PointShadowCalculation(int index, vec3 worldPos, vec3 normal)
+{
+    if (index >= UNI_POINT_SHADOWS || !uPointShadows[index].enabled) {
+        return 1.0;
+    }
+
+    ShadowSettings settings = uPointShadows[index].settings;
+
+    vec3 d = worldPos + normal * settings.normal_bias - uPointShadows[index].position;
+    vec3 ad = abs(d);
+
+    // Pick the cube face and rebuild its look_at basis
+    float face;
+    vec3 forward;
+    vec3 up;
+
+    if (ad.x >= ad.y && ad.x >= ad.z) {
+        face = d.x > 0.0 ? 0.0 : 1.0;
+        forward = vec3(sign(d.x), 0.0, 0.0);
+        up = vec3(0.0, -1.0, 0.0);
+    } else if (ad.y >= ad.z) {
+        face = d.y > 0.0 ? 2.0 : 3.0;
+        forward = vec3(0.0, sign(d.y), 0.0);
+        up = vec3(0.0, 0.0, sign(d.y));
+    } else {
+        face = d.z > 0.0 ? 4.0 : 5.0;
+        forward = vec3(0.0, 0.0, sign(d.z));
+        up = vec3(0.0, -1.0, 0.0);
+    }
+
+    vec3 right = cross(forward, up);
+    up = cross(right, forward);
+
+    float z = dot(d, forward);
+    vec2 uv = vec2(dot(d, right), dot(d, up)) / z * 0.5 + 0.5;
+
+    float n = uPointShadows[index].depth_range.x;
+    float f = uPointShadows[index].depth_range.y;
+
+    vec3 lightDir = normalize(-d);
+    float bias = max(settings.slope_bias * (1.0 - dot(normal, lightDir)), settings.bias) * z;
+    int radius = settings.pcf_radius;
+
+    vec2 tile_scale = vec2(1.0 / 6.0, 1.0 / float(UNI_POINT_SHADOWS));
+    vec2 tile_offset = vec2(face, uPointShadows[index].tile) * tile_scale;
+    vec2 texelSize = 1.0 / uPointShadowMapSize;
+
+    float shadow = 0.0;
+
+    for(int x = -MAX_PCF_RADIUS; x <= MAX_PCF_RADIUS; ++x)
+    {
+        for(int y = -MAX_PCF_RADIUS; y <= MAX_PCF_RADIUS; ++y)
+        {
+            if (x < -radius || x > radius || y < -radius || y > radius) {
+                continue;
+            }
+
+            // Stay inside the face, neighbours in the atlas are unrelated
+            vec2 faceUV = clamp(uv * tile_scale + vec2(x, y) * texelSize, texelSize, tile_scale - texelSize);
+            float pcfDepth = texture2D(uPointShadowMapTexture, tile_offset + faceUV).r;
+
+            // back to linear depth
+            float pcfZ = 2.0 * f * n / ((f + n) - (pcfDepth * 2.0 - 1.0) * (f - n));
+            shadow += float(z - bias > pcfZ);
+        }
+    }
+
+    float kernel = 2.0 * min(float(radius), float(MAX_PCF_RADIUS)) + 1.0;
+    shadow /= kernel * kernel;
+
+    return (1.0 - shadow);
+}
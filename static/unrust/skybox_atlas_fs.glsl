@@ -0,0 +1,21 @@
+// The skybox of a cube atlas, e.g. the capture of a ReflectionProbe
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec3 vTexCoords;
+uniform sampler2D uSkyboxAtlas;
+uniform vec2 uSkyboxAtlasSize;
+
+#include "unrust/cube_atlas.glsl"
+#include "unrust/color_space.glsl"
+
+void main()
+{
+    vec4 color = texture2D(uSkyboxAtlas, CubeAtlasUV(vTexCoords, 0.0, 1.0, uSkyboxAtlasSize));
+
+    gl_FragColor = EncodeColor(vec4(color.rgb, 1.0));
+}
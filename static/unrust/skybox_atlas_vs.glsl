@@ -0,0 +1 @@
+#include "unrust/skybox_vs.glsl"
@@ -7,8 +7,21 @@ out vec4 FragColor;
 
 varying vec3 vTexCoords;
 uniform samplerCube uSkybox;
+// Linear HDR faces of an equirectangular image, float textures or RGBM
+// encoded by the engine without them, see RGBM_RANGE
+uniform bool uSkyboxHdr;
+uniform bool uSkyboxRGBM;
+
+#include "unrust/color_space.glsl"
 
 void main()
 {    
-    gl_FragColor = textureCube(uSkybox, vTexCoords);
+    vec4 color = textureCube(uSkybox, vTexCoords);
+    if (uSkyboxRGBM) {
+        color = vec4(color.rgb * color.a * 8.0, 1.0);
+    } else if (!uSkyboxHdr) {
+        color = DecodeColor(color);
+    }
+
+    gl_FragColor = EncodeColor(color);
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+varying vec4 vColor;
+
+uniform sampler2D uTexture;
+
+#include "unrust/light2d.glsl"
+#include "unrust/color_space.glsl"
+
+void main()
+{
+    vec4 color = DecodeColor(texture2D(uTexture, vTexCoords) * vColor);
+    gl_FragColor = EncodeColor(vec4(color.rgb * Light2D(), color.a));
+}
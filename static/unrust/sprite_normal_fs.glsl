@@ -0,0 +1,29 @@
+// The normals of the sprites for the 2D lights, see Lighting2DSettings.
+// The sprites without a normal map face the camera.
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+varying vec4 vColor;
+
+uniform sampler2D uTexture;
+uniform sampler2D uNormalMap;
+uniform bool uHasNormalMap;
+
+void main()
+{
+    if (texture2D(uTexture, vTexCoords).a * vColor.a < 0.5) {
+        discard;
+    }
+
+    vec3 normal = vec3(0.5, 0.5, 1.0);
+    if (uHasNormalMap) {
+        normal = texture2D(uNormalMap, vTexCoords).xyz;
+    }
+
+    gl_FragColor = vec4(normal, 1.0);
+}
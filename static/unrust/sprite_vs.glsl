@@ -0,0 +1,22 @@
+#ifndef GL_ES
+#define attribute in
+#define varying out
+#endif
+
+#include "unrust/default_uniforms.glsl"
+
+// See SpriteBatcher: the corner in world space, its uv, the tint color and
+// its alpha as the first tangent component
+attribute vec3 aVertexPosition;
+attribute vec2 aTextureCoord;
+attribute vec3 aVertexNormal;
+attribute vec3 aVertexTangent;
+
+varying vec2 vTexCoords;
+varying vec4 vColor;
+
+void main(void) {
+    vTexCoords = aTextureCoord;
+    vColor = vec4(aVertexNormal, aVertexTangent.x);
+    gl_Position = uPMatrix * uVMatrix * vec4(aVertexPosition, 1.0);
+}
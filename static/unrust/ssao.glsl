@@ -0,0 +1,16 @@
+// Ambient occlusion built by the engine for the current camera,
+// see SsaoSettings.
+uniform bool uSSAOEnabled;
+uniform sampler2D uSSAOTexture;
+// x, y, width, height of the viewport the occlusion matches
+uniform vec4 uSSAOViewport;
+
+float AmbientOcclusion()
+{
+    if (!uSSAOEnabled) {
+        return 1.0;
+    }
+
+    vec2 uv = (gl_FragCoord.xy - uSSAOViewport.xy) / uSSAOViewport.zw;
+    return texture2D(uSSAOTexture, uv).r;
+}
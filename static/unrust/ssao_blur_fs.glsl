@@ -0,0 +1,26 @@
+// 4x4 box blur hiding the noise pattern of the occlusion
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uSourceTexture;
+uniform vec2 uScreenSize;
+
+void main()
+{
+    vec2 texel = 1.0 / uScreenSize;
+    float result = 0.0;
+
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            vec2 offset = vec2(float(x) + 0.5, float(y) + 0.5) * texel;
+            result += texture2D(uSourceTexture, vTexCoords + offset).r;
+        }
+    }
+
+    gl_FragColor = vec4(vec3(result / 16.0), 1.0);
+}
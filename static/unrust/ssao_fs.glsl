@@ -0,0 +1,79 @@
+// Occlusion of the depth/normal prepass, 1.0 means not occluded
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+#define SSAO_SAMPLES 16
+
+varying vec2 vTexCoords;
+uniform sampler2D uDepthTexture;
+uniform sampler2D uNormalTexture;
+uniform mat4 uProjection;
+uniform mat4 uInvProjection;
+uniform vec3 uKernel[SSAO_SAMPLES];
+uniform float uRadius;
+uniform float uIntensity;
+uniform float uBias;
+uniform bool uUseNormals;
+
+vec3 ViewPosition(vec2 uv)
+{
+    float depth = texture2D(uDepthTexture, uv).r * 2.0 - 1.0;
+    vec4 pos = uInvProjection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return pos.xyz / pos.w;
+}
+
+float Random(vec2 co)
+{
+    return fract(sin(dot(co, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+void main()
+{
+    if (texture2D(uDepthTexture, vTexCoords).r >= 1.0) {
+        gl_FragColor = vec4(1.0);
+        return;
+    }
+
+    vec3 pos = ViewPosition(vTexCoords);
+
+    float angle = Random(gl_FragCoord.xy) * 6.2831853;
+    vec3 randomVec = vec3(cos(angle), sin(angle), 0.0);
+
+    vec3 normal = normalize(texture2D(uNormalTexture, vTexCoords).xyz * 2.0 - 1.0);
+    vec3 tangent = normalize(randomVec - normal * dot(randomVec, normal));
+    mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+    float occlusion = 0.0;
+
+    for (int i = 0; i < SSAO_SAMPLES; i++) {
+        vec3 dir;
+        if (uUseNormals) {
+            dir = tbn * uKernel[i];
+        } else {
+            // Whole sphere, half of the samples are behind the surface
+            dir = reflect(uKernel[i], randomVec);
+        }
+
+        vec3 samplePos = pos + dir * uRadius;
+
+        vec4 offset = uProjection * vec4(samplePos, 1.0);
+        offset.xy = offset.xy / offset.w * 0.5 + 0.5;
+
+        float sampleDepth = ViewPosition(offset.xy).z;
+        float rangeCheck = smoothstep(0.0, 1.0, uRadius / max(abs(pos.z - sampleDepth), 0.0001));
+
+        occlusion += (sampleDepth >= samplePos.z + uBias ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    if (!uUseNormals) {
+        occlusion = max(occlusion * 2.0 - float(SSAO_SAMPLES), 0.0);
+    }
+
+    float ao = 1.0 - occlusion / float(SSAO_SAMPLES);
+
+    gl_FragColor = vec4(vec3(pow(ao, uIntensity)), 1.0);
+}
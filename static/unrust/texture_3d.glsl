@@ -0,0 +1,43 @@
+// Sampling of the 3D textures of Texture::new_3d, declared as
+// TEXTURE_3D_SAMPLER. They are sampler3D on WebGL2, where TEXTURE_3D is
+// predefined and the shaders are GLSL 300 es. Otherwise the slices of
+// increasing depth are laid side by side in one 2D texture, like the
+// ColorGrading LUTs. `size` is the width, height and depth of the volume.
+
+#ifdef USE_GLSL_300ES
+#ifdef TEXTURE_3D
+#define TEXTURE_3D_NATIVE
+#endif
+#endif
+
+#ifdef TEXTURE_3D_NATIVE
+precision highp sampler3D;
+#define TEXTURE_3D_SAMPLER sampler3D
+
+// Trilinear, uvw is clamped to the volume
+vec4 Texture3D(sampler3D volume, vec3 size, vec3 uvw)
+{
+    return texture(volume, clamp(uvw, 0.5 / size, 1.0 - 0.5 / size));
+}
+#else
+#define TEXTURE_3D_SAMPLER sampler2D
+
+// Trilinear, uvw is clamped to the volume
+vec4 Texture3D(sampler2D volume, vec3 size, vec3 uvw)
+{
+    vec3 p = clamp(uvw, 0.0, 1.0);
+
+    float slice = p.z * size.z - 0.5;
+    float slice0 = clamp(floor(slice), 0.0, size.z - 1.0);
+    float slice1 = min(slice0 + 1.0, size.z - 1.0);
+
+    // Keep the filtering inside a slice
+    float x = clamp(p.x * size.x, 0.5, size.x - 0.5);
+    vec2 uv = vec2(x / (size.x * size.z), p.y);
+
+    vec4 c0 = texture2D(volume, uv + vec2(slice0 / size.z, 0.0));
+    vec4 c1 = texture2D(volume, uv + vec2(slice1 / size.z, 0.0));
+
+    return mix(c0, c1, clamp(slice - slice0, 0.0, 1.0));
+}
+#endif
@@ -0,0 +1,35 @@
+// Sampling of the array textures of Texture::new_array, declared as
+// TEXTURE_ARRAY_SAMPLER. They are sampler2DArray on WebGL2, where TEXTURE_3D
+// is predefined and the shaders are GLSL 300 es. Otherwise the layers are
+// stacked from the bottom of one 2D texture so they take a single unit.
+// `layers` is the number of layers of the texture, see Texture::layers.
+
+#ifdef USE_GLSL_300ES
+#ifdef TEXTURE_3D
+#define TEXTURE_ARRAY_NATIVE
+#endif
+#endif
+
+#ifdef TEXTURE_ARRAY_NATIVE
+precision highp sampler2DArray;
+#define TEXTURE_ARRAY_SAMPLER sampler2DArray
+
+// uv is clamped to the layer, without repeat
+vec4 TextureArray(sampler2DArray array, float layers, vec2 uv, float layer)
+{
+    float index = clamp(floor(layer + 0.5), 0.0, layers - 1.0);
+
+    return texture(array, vec3(uv.x, clamp(uv.y, 0.0, 1.0), index));
+}
+#else
+#define TEXTURE_ARRAY_SAMPLER sampler2D
+
+// uv is clamped to the layer, without repeat
+vec4 TextureArray(sampler2D array, float layers, vec2 uv, float layer)
+{
+    float index = clamp(floor(layer + 0.5), 0.0, layers - 1.0);
+    float v = (index + clamp(uv.y, 0.0, 1.0)) / layers;
+
+    return texture2D(array, vec2(uv.x, v));
+}
+#endif
@@ -0,0 +1,46 @@
+// Maps the HDR camera target to the displayable range,
+// uTonemapping matches the Tonemapping enum of the camera.
+#ifndef GL_ES
+#define varying in
+#define gl_FragColor FragColor
+#define texture2D texture
+out vec4 FragColor;
+#endif
+
+varying vec2 vTexCoords;
+uniform sampler2D uScreenTexture;
+uniform float uExposure;
+uniform int uTonemapping;
+
+#include "unrust/color_space.glsl"
+
+vec3 Reinhard(vec3 color)
+{
+    return color / (color + vec3(1.0));
+}
+
+// ACES filmic curve fit by Krzysztof Narkowicz
+vec3 AcesFilmic(vec3 color)
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+void main()
+{
+    vec4 hdr = texture2D(uScreenTexture, vTexCoords);
+    vec3 color = hdr.rgb * uExposure;
+
+    if (uTonemapping == 1) {
+        color = Reinhard(color);
+    } else if (uTonemapping == 2) {
+        color = AcesFilmic(color);
+    }
+
+    gl_FragColor = EncodeColor(vec4(clamp(color, 0.0, 1.0), hdr.a));
+}